@@ -1,3 +1,4 @@
+use crate::btrfs::transaction;
 use dialoguer::{Confirm, Input, Select, theme::ColorfulTheme};
 use std::path::Path;
 use std::process::Command;
@@ -163,7 +164,11 @@ fn install_specific_kernel(kernel_name: &str) {
         Ok(None) | Err(_) => return,
     };
 
-    if confirm {
+    if !confirm {
+        return;
+    }
+
+    transaction::run(&format!("arch boot: install {kernel_name}"), || {
         // Install kernel and headers
         if kernel_name.contains("tkg")
             || kernel_name.contains("cachy")
@@ -207,7 +212,7 @@ fn install_specific_kernel(kernel_name: &str) {
                 _ => println!("❌ Failed to install kernel"),
             }
         }
-    }
+    });
 }
 
 fn systemd_boot_config() {
@@ -269,15 +274,17 @@ fn show_systemd_boot_config() {
 fn edit_loader_conf() {
     println!("📝 Editing loader.conf");
 
-    if !Path::new("/boot/loader/loader.conf").exists() {
-        println!("❌ loader.conf not found. Creating default configuration...");
-        create_default_loader_conf();
-    }
+    let _ = transaction::run("arch boot: edit loader.conf", || {
+        if !Path::new("/boot/loader/loader.conf").exists() {
+            println!("❌ loader.conf not found. Creating default configuration...");
+            create_default_loader_conf();
+        }
 
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-    let _ = Command::new("sudo")
-        .args(&[&editor, "/boot/loader/loader.conf"])
-        .status();
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
+        Command::new("sudo")
+            .args(&[&editor, "/boot/loader/loader.conf"])
+            .status()
+    });
 }
 
 fn create_default_loader_conf() {
@@ -368,19 +375,21 @@ options root=UUID={} {}
     };
 
     if confirm {
-        let _ = Command::new("sudo")
-            .args(["mkdir", "-p", "/boot/loader/entries"])
-            .status();
-
-        // Write to temp file and move with sudo
-        let temp_file = "/tmp/boot_entry.conf.tmp";
-        if std::fs::write(temp_file, &entry_content).is_ok() {
+        transaction::run(&format!("arch boot: create entry {entry_name}"), || {
             let _ = Command::new("sudo")
-                .args(["mv", temp_file, &entry_filename])
+                .args(["mkdir", "-p", "/boot/loader/entries"])
                 .status();
-        }
 
-        println!("✅ Boot entry created successfully");
+            // Write to temp file and move with sudo
+            let temp_file = "/tmp/boot_entry.conf.tmp";
+            if std::fs::write(temp_file, &entry_content).is_ok() {
+                let _ = Command::new("sudo")
+                    .args(["mv", temp_file, &entry_filename])
+                    .status();
+            }
+
+            println!("✅ Boot entry created successfully");
+        });
     }
 }
 
@@ -413,11 +422,12 @@ pub fn regenerate_boot_entries() {
     println!("============================");
 
     // Check if mkinitcpio presets exist
+    let mut regenerate = false;
     if Path::new("/etc/mkinitcpio.d").exists() {
         println!("🔍 Found mkinitcpio presets:");
         let _ = Command::new("ls").args(&["/etc/mkinitcpio.d/"]).status();
 
-        let regenerate = match Confirm::new()
+        regenerate = match Confirm::new()
             .with_prompt("Regenerate initramfs for all kernels?")
             .default(true)
             .interact_opt()
@@ -425,18 +435,20 @@ pub fn regenerate_boot_entries() {
             Ok(Some(c)) => c,
             Ok(None) | Err(_) => return,
         };
+    }
 
+    transaction::run("arch boot: regenerate entries", || {
         if regenerate {
             println!("🔄 Regenerating initramfs...");
             let _ = Command::new("sudo").args(&["mkinitcpio", "-P"]).status();
         }
-    }
 
-    // Update systemd-boot if installed
-    if Path::new("/boot/EFI/systemd").exists() {
-        println!("🔄 Updating systemd-boot...");
-        let _ = Command::new("sudo").args(&["bootctl", "update"]).status();
-    }
+        // Update systemd-boot if installed
+        if Path::new("/boot/EFI/systemd").exists() {
+            println!("🔄 Updating systemd-boot...");
+            let _ = Command::new("sudo").args(&["bootctl", "update"]).status();
+        }
+    });
 
     println!("✅ Boot entries regenerated");
 }
@@ -597,7 +609,11 @@ fn remove_kernel() {
         Ok(None) | Err(_) => return,
     };
 
-    if confirm {
+    if !confirm {
+        return;
+    }
+
+    transaction::run(&format!("arch boot: remove {kernel_to_remove}"), || {
         let headers_package = format!("{}-headers", kernel_to_remove);
 
         println!("Removing {} and {}...", kernel_to_remove, headers_package);
@@ -626,12 +642,14 @@ fn remove_kernel() {
             }
             Err(e) => println!("Failed to remove kernel: {}", e),
         }
-    }
+    });
 }
 
 fn update_kernels() {
     println!("🔄 Update All Kernels");
-    let _ = Command::new("sudo").args(&["pacman", "-Syu"]).status();
+    let _ = transaction::run("arch boot: update kernels", || {
+        Command::new("sudo").args(&["pacman", "-Syu"]).status()
+    });
 }
 
 fn kernel_config() {
@@ -699,22 +717,26 @@ fn edit_kernel_parameters() {
                 };
 
                 if create {
-                    // Get current parameters from /proc/cmdline and write via temp file
-                    let _ = Command::new("sudo")
-                        .args(["mkdir", "-p", "/etc/kernel"])
-                        .status();
-                    if let Ok(cmdline) = std::fs::read_to_string("/proc/cmdline")
-                        && std::fs::write("/tmp/cmdline.tmp", &cmdline).is_ok()
-                    {
+                    transaction::run("arch boot: create kernel cmdline", || {
+                        // Get current parameters from /proc/cmdline and write via temp file
                         let _ = Command::new("sudo")
-                            .args(["mv", "/tmp/cmdline.tmp", "/etc/kernel/cmdline"])
+                            .args(["mkdir", "-p", "/etc/kernel"])
                             .status();
-                    }
+                        if let Ok(cmdline) = std::fs::read_to_string("/proc/cmdline")
+                            && std::fs::write("/tmp/cmdline.tmp", &cmdline).is_ok()
+                        {
+                            let _ = Command::new("sudo")
+                                .args(["mv", "/tmp/cmdline.tmp", "/etc/kernel/cmdline"])
+                                .status();
+                        }
+                    });
                 }
             }
 
             let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-            let _ = Command::new("sudo").args([&editor, cmdline_path]).status();
+            let _ = transaction::run("arch boot: edit kernel cmdline", || {
+                Command::new("sudo").args([&editor, cmdline_path]).status()
+            });
         }
         1 => {
             // List boot entries and let user select one to edit
@@ -757,7 +779,9 @@ fn edit_kernel_parameters() {
             if choice < entries.len() {
                 let entry_path = format!("/boot/loader/entries/{}", entries[choice]);
                 let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-                let _ = Command::new("sudo").args([&editor, &entry_path]).status();
+                let _ = transaction::run(&format!("arch boot: edit {}", entries[choice]), || {
+                    Command::new("sudo").args([&editor, &entry_path]).status()
+                });
             }
         }
         2 => {
@@ -819,17 +843,22 @@ fn manage_modules() {
 
                 if module_name.is_empty() {
                     let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-                    let _ = Command::new("sudo")
-                        .args([&editor, mkinitcpio_path])
-                        .status();
+                    let _ = transaction::run("arch boot: edit mkinitcpio.conf", || {
+                        Command::new("sudo")
+                            .args([&editor, mkinitcpio_path])
+                            .status()
+                    });
                 } else {
                     println!("Adding module '{}' to MODULES array...", module_name);
                     // Use sed directly without shell wrapper
                     let sed_pattern =
                         format!("s/^MODULES=(\\(.*\\))/MODULES=(\\1 {})'/", module_name);
-                    let _ = Command::new("sudo")
-                        .args(["sed", "-i", &sed_pattern, mkinitcpio_path])
-                        .status();
+                    let _ =
+                        transaction::run(&format!("arch boot: add module {module_name}"), || {
+                            Command::new("sudo")
+                                .args(["sed", "-i", &sed_pattern, mkinitcpio_path])
+                                .status()
+                        });
 
                     let regenerate = match Confirm::new()
                         .with_prompt("Regenerate initramfs now?")
@@ -841,7 +870,9 @@ fn manage_modules() {
                     };
 
                     if regenerate {
-                        let _ = Command::new("sudo").args(["mkinitcpio", "-P"]).status();
+                        let _ = transaction::run("arch boot: regenerate initramfs", || {
+                            Command::new("sudo").args(["mkinitcpio", "-P"]).status()
+                        });
                     }
                 }
             } else {
@@ -863,9 +894,12 @@ fn manage_modules() {
             // Write to temp file and move with sudo
             let temp_file = "/tmp/module_load.conf.tmp";
             if std::fs::write(temp_file, &format!("{}\n", module_name)).is_ok() {
-                let status = Command::new("sudo")
-                    .args(["mv", temp_file, &conf_file])
-                    .status();
+                let status =
+                    transaction::run(&format!("arch boot: load {module_name} at boot"), || {
+                        Command::new("sudo")
+                            .args(["mv", temp_file, &conf_file])
+                            .status()
+                    });
                 match status {
                     Ok(s) if s.success() => {
                         println!("Module '{}' will be loaded at boot.", module_name);
@@ -891,9 +925,12 @@ fn manage_modules() {
             // Write to temp file and move with sudo
             let temp_file = "/tmp/blacklist.conf.tmp";
             if std::fs::write(temp_file, &format!("blacklist {}\n", module_name)).is_ok() {
-                let status = Command::new("sudo")
-                    .args(["mv", temp_file, &conf_file])
-                    .status();
+                let status =
+                    transaction::run(&format!("arch boot: blacklist {module_name}"), || {
+                        Command::new("sudo")
+                            .args(["mv", temp_file, &conf_file])
+                            .status()
+                    });
                 match status {
                     Ok(s) if s.success() => {
                         println!("Module '{}' has been blacklisted.", module_name);
@@ -957,9 +994,11 @@ fn configure_mkinitcpio() {
     match choice {
         0 => {
             let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-            let _ = Command::new("sudo")
-                .args([&editor, mkinitcpio_path])
-                .status();
+            let _ = transaction::run("arch boot: edit mkinitcpio.conf", || {
+                Command::new("sudo")
+                    .args([&editor, mkinitcpio_path])
+                    .status()
+            });
 
             let regenerate = match Confirm::new()
                 .with_prompt("Regenerate initramfs after editing?")
@@ -972,12 +1011,16 @@ fn configure_mkinitcpio() {
 
             if regenerate {
                 println!("Regenerating initramfs...");
-                let _ = Command::new("sudo").args(["mkinitcpio", "-P"]).status();
+                let _ = transaction::run("arch boot: regenerate initramfs", || {
+                    Command::new("sudo").args(["mkinitcpio", "-P"]).status()
+                });
             }
         }
         1 => {
             println!("Regenerating initramfs for all kernels...");
-            let _ = Command::new("sudo").args(["mkinitcpio", "-P"]).status();
+            let _ = transaction::run("arch boot: regenerate initramfs", || {
+                Command::new("sudo").args(["mkinitcpio", "-P"]).status()
+            });
         }
         2 => {
             // List available presets
@@ -1010,9 +1053,11 @@ fn configure_mkinitcpio() {
                 if choice < presets.len() {
                     let preset = presets[choice].trim_end_matches(".preset");
                     println!("Regenerating initramfs for {}...", preset);
-                    let _ = Command::new("sudo")
-                        .args(["mkinitcpio", "-p", preset])
-                        .status();
+                    let _ = transaction::run(&format!("arch boot: regenerate {preset}"), || {
+                        Command::new("sudo")
+                            .args(["mkinitcpio", "-p", preset])
+                            .status()
+                    });
                 }
             }
         }
@@ -1102,7 +1147,9 @@ fn manage_boot_entries() {
         }
         1 => {
             let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-            let _ = Command::new("sudo").args([&editor, &entry_path]).status();
+            let _ = transaction::run(&format!("arch boot: edit {selected_entry}"), || {
+                Command::new("sudo").args([&editor, &entry_path]).status()
+            });
             println!("Boot entry updated.");
         }
         2 => {
@@ -1119,7 +1166,10 @@ fn manage_boot_entries() {
             };
 
             if confirm {
-                let status = Command::new("sudo").args(["rm", &entry_path]).status();
+                let status =
+                    transaction::run(&format!("arch boot: delete {selected_entry}"), || {
+                        Command::new("sudo").args(["rm", &entry_path]).status()
+                    });
                 match status {
                     Ok(s) if s.success() => {
                         println!("Boot entry deleted successfully.");
@@ -1161,7 +1211,9 @@ fn setup_systemd_boot() {
         };
 
         if update {
-            let _ = Command::new("sudo").args(["bootctl", "update"]).status();
+            let _ = transaction::run("arch boot: update systemd-boot", || {
+                Command::new("sudo").args(["bootctl", "update"]).status()
+            });
             println!("Systemd-boot updated.");
         }
         return;
@@ -1226,9 +1278,16 @@ fn setup_systemd_boot() {
         return;
     }
 
+    transaction::run("arch boot: install systemd-boot", || {
+        install_systemd_boot(&esp_path)
+    });
+}
+
+/// Install systemd-boot to `esp_path` and create entries for the running kernel.
+fn install_systemd_boot(esp_path: &str) {
     println!("\nInstalling systemd-boot...");
     let install_status = Command::new("sudo")
-        .args(["bootctl", "--esp-path", &esp_path, "install"])
+        .args(["bootctl", "--esp-path", esp_path, "install"])
         .status();
 
     match install_status {
@@ -1331,5 +1390,7 @@ options root=UUID={} rw
 
 fn update_systemd_boot() {
     println!("🔄 Update Systemd-boot");
-    let _ = Command::new("sudo").args(&["bootctl", "update"]).status();
+    let _ = transaction::run("arch boot: update systemd-boot", || {
+        Command::new("sudo").args(&["bootctl", "update"]).status()
+    });
 }
@@ -1,3 +1,4 @@
+use crate::btrfs::transaction;
 use dialoguer::{Input, MultiSelect, Select, theme::ColorfulTheme};
 use std::fs;
 use std::path::Path;
@@ -177,39 +178,41 @@ fn fix_common_pkgbuild_issues() {
         return;
     }
 
-    // Create backup first
-    let backup_path = format!("{}.backup", pkgbuild_path);
-    if let Err(e) = fs::copy(&pkgbuild_path, &backup_path) {
-        println!("❌ Failed to create backup: {}", e);
-        return;
-    }
-    println!("📁 Backup created: {}", backup_path);
-
-    if let Ok(mut content) = fs::read_to_string(&pkgbuild_path) {
-        for &fix in &selected_fixes {
-            match fix {
-                0 => content = fix_quoting_issues(content),
-                1 => content = add_missing_fields(content),
-                2 => content = update_to_standards(content),
-                3 => content = clean_formatting(content),
-                4 => content = fix_security_issues(content),
-                5 => {
-                    content = fix_quoting_issues(content);
-                    content = add_missing_fields(content);
-                    content = update_to_standards(content);
-                    content = clean_formatting(content);
-                    content = fix_security_issues(content);
+    transaction::run("arch pkgfix: fix common issues", || {
+        // Create backup first
+        let backup_path = format!("{}.backup", pkgbuild_path);
+        if let Err(e) = fs::copy(&pkgbuild_path, &backup_path) {
+            println!("❌ Failed to create backup: {}", e);
+            return;
+        }
+        println!("📁 Backup created: {}", backup_path);
+
+        if let Ok(mut content) = fs::read_to_string(&pkgbuild_path) {
+            for &fix in &selected_fixes {
+                match fix {
+                    0 => content = fix_quoting_issues(content),
+                    1 => content = add_missing_fields(content),
+                    2 => content = update_to_standards(content),
+                    3 => content = clean_formatting(content),
+                    4 => content = fix_security_issues(content),
+                    5 => {
+                        content = fix_quoting_issues(content);
+                        content = add_missing_fields(content);
+                        content = update_to_standards(content);
+                        content = clean_formatting(content);
+                        content = fix_security_issues(content);
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
-        }
 
-        if let Err(e) = fs::write(&pkgbuild_path, content) {
-            println!("❌ Failed to write fixes: {}", e);
-        } else {
-            println!("✅ PKGBUILD fixes applied successfully");
+            if let Err(e) = fs::write(&pkgbuild_path, content) {
+                println!("❌ Failed to write fixes: {}", e);
+            } else {
+                println!("✅ PKGBUILD fixes applied successfully");
+            }
         }
-    }
+    });
 }
 
 fn fix_quoting_issues(content: String) -> String {
@@ -450,47 +453,49 @@ fn clean_build_environment() {
         return;
     }
 
-    for &option in &selected {
-        match option {
-            0 => {
-                println!("🗑️  Cleaning makepkg cache...");
-                let _ = Command::new("rm")
-                    .args(["-rf", "~/.cache/makepkg"])
-                    .status();
-            }
-            1 => {
-                println!("📁 Removing build directories...");
-                let _ = Command::new("rm").args(["-rf", "./src", "./pkg"]).status();
-            }
-            2 => {
-                println!("🧹 Cleaning source cache...");
-                let _ = Command::new("rm")
-                    .args(["-rf", "~/.cache/yay/sources", "~/.cache/paru/sources"])
-                    .status();
-            }
-            3 => {
-                println!("🔄 Resetting build flags...");
-                println!("  💡 Unset MAKEFLAGS, CFLAGS, CXXFLAGS if customized");
-            }
-            4 => {
-                println!("📦 Cleaning package cache...");
-                let _ = Command::new("sudo")
-                    .args(["pacman", "-Sc", "--noconfirm"])
-                    .status();
-            }
-            5 => {
-                println!("🌀 Full environment reset...");
-                // Comprehensive cleanup
-                let _ = Command::new("rm")
-                    .args(["-rf", "./src", "./pkg", "~/.cache/makepkg"])
-                    .status();
-                let _ = Command::new("sudo")
-                    .args(["rm", "-rf", "/tmp/makepkg-*", "/tmp/yay-*", "/tmp/paru-*"])
-                    .status();
+    transaction::run("arch pkgfix: clean build environment", || {
+        for &option in &selected {
+            match option {
+                0 => {
+                    println!("🗑️  Cleaning makepkg cache...");
+                    let _ = Command::new("rm")
+                        .args(["-rf", "~/.cache/makepkg"])
+                        .status();
+                }
+                1 => {
+                    println!("📁 Removing build directories...");
+                    let _ = Command::new("rm").args(["-rf", "./src", "./pkg"]).status();
+                }
+                2 => {
+                    println!("🧹 Cleaning source cache...");
+                    let _ = Command::new("rm")
+                        .args(["-rf", "~/.cache/yay/sources", "~/.cache/paru/sources"])
+                        .status();
+                }
+                3 => {
+                    println!("🔄 Resetting build flags...");
+                    println!("  💡 Unset MAKEFLAGS, CFLAGS, CXXFLAGS if customized");
+                }
+                4 => {
+                    println!("📦 Cleaning package cache...");
+                    let _ = Command::new("sudo")
+                        .args(["pacman", "-Sc", "--noconfirm"])
+                        .status();
+                }
+                5 => {
+                    println!("🌀 Full environment reset...");
+                    // Comprehensive cleanup
+                    let _ = Command::new("rm")
+                        .args(["-rf", "./src", "./pkg", "~/.cache/makepkg"])
+                        .status();
+                    let _ = Command::new("sudo")
+                        .args(["rm", "-rf", "/tmp/makepkg-*", "/tmp/yay-*", "/tmp/paru-*"])
+                        .status();
+                }
+                _ => {}
             }
-            _ => {}
         }
-    }
+    });
 
     println!("✅ Build environment cleanup completed");
 }
@@ -611,45 +616,47 @@ fn update_pkgbuild_standards() {
 
     println!("🔄 Applying standards updates...");
 
-    for &update in &selected {
-        match update {
-            0 => {
-                println!("📋 Generating .SRCINFO...");
-                let _ = Command::new("makepkg")
-                    .args(["--printsrcinfo"])
-                    .current_dir(Path::new(&pkgbuild_path).parent().unwrap_or(Path::new(".")))
-                    .output()
-                    .and_then(|output| fs::write(".SRCINFO", output.stdout));
-                println!("  ✅ .SRCINFO generated");
-            }
-            1 => {
-                println!("🔢 Updating arch field standards...");
-                // Implementation for arch field updates
-                println!("  ✅ Arch standards updated");
-            }
-            2 => {
-                println!("🔐 Migrating to sha256 checksums...");
-                // Implementation for checksum migration
-                println!("  ✅ Checksums updated");
-            }
-            3 => {
-                println!("📝 Adding optional fields...");
-                // Implementation for optional fields
-                println!("  ✅ Optional fields added");
-            }
-            4 => {
-                println!("🏷️  Updating metadata format...");
-                // Implementation for metadata updates
-                println!("  ✅ Metadata format updated");
-            }
-            5 => {
-                println!("🔄 Applying all updates...");
-                // Apply all updates
-                println!("  ✅ All standards updates applied");
+    transaction::run("arch pkgfix: update standards", || {
+        for &update in &selected {
+            match update {
+                0 => {
+                    println!("📋 Generating .SRCINFO...");
+                    let _ = Command::new("makepkg")
+                        .args(["--printsrcinfo"])
+                        .current_dir(Path::new(&pkgbuild_path).parent().unwrap_or(Path::new(".")))
+                        .output()
+                        .and_then(|output| fs::write(".SRCINFO", output.stdout));
+                    println!("  ✅ .SRCINFO generated");
+                }
+                1 => {
+                    println!("🔢 Updating arch field standards...");
+                    // Implementation for arch field updates
+                    println!("  ✅ Arch standards updated");
+                }
+                2 => {
+                    println!("🔐 Migrating to sha256 checksums...");
+                    // Implementation for checksum migration
+                    println!("  ✅ Checksums updated");
+                }
+                3 => {
+                    println!("📝 Adding optional fields...");
+                    // Implementation for optional fields
+                    println!("  ✅ Optional fields added");
+                }
+                4 => {
+                    println!("🏷️  Updating metadata format...");
+                    // Implementation for metadata updates
+                    println!("  ✅ Metadata format updated");
+                }
+                5 => {
+                    println!("🔄 Applying all updates...");
+                    // Apply all updates
+                    println!("  ✅ All standards updates applied");
+                }
+                _ => {}
             }
-            _ => {}
         }
-    }
+    });
 
     println!("✅ PKGBUILD standards update completed");
 }
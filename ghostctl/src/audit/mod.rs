@@ -26,6 +26,7 @@ use clap::{Arg, ArgAction, ArgMatches, Command};
 use config::AuditConfig;
 use reqwest::blocking::Client;
 use scan::{Finding, Severity};
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
 use std::io::{IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
@@ -170,7 +171,7 @@ fn cve(cfg: &AuditConfig) -> Result<()> {
                 true
             } else {
                 // Still vulnerable only if the installed version is below the fix.
-                version_lt(inst_ver, &fixed).unwrap_or(true)
+                version_lt(inst_ver, &fixed)
             };
             if !vulnerable {
                 continue;
@@ -602,7 +603,7 @@ fn install_hook_name(pkgbuild: &str, pkg: &str) -> Option<String> {
     None
 }
 
-fn http_client(timeout_secs: u64) -> Result<Client> {
+pub(crate) fn http_client(timeout_secs: u64) -> Result<Client> {
     Client::builder()
         .timeout(Duration::from_secs(timeout_secs))
         .user_agent("ghostctl")
@@ -676,15 +677,114 @@ fn parse_pacman_q(text: &str) -> BTreeMap<String, String> {
     map
 }
 
-/// True if version `a` is strictly older than `b`, using pacman's `vercmp`.
-/// Returns None if `vercmp` is unavailable or output can't be parsed.
-fn version_lt(a: &str, b: &str) -> Option<bool> {
-    let out = ProcCommand::new("vercmp").args([a, b]).output().ok()?;
-    if !out.status.success() {
-        return None;
+/// True if version `a` is strictly older than `b` in pacman's ordering.
+pub(crate) fn version_lt(a: &str, b: &str) -> bool {
+    vercmp(a, b) == Ordering::Less
+}
+
+/// Compare two `[epoch:]version[-release]` strings the way pacman's
+/// `vercmp` (libalpm `alpm_pkg_vercmp`) does, without needing the binary.
+/// The release is only compared when both sides have one.
+pub(crate) fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch_a, ver_a, rel_a) = split_evr(a);
+    let (epoch_b, ver_b, rel_b) = split_evr(b);
+    rpmvercmp(epoch_a, epoch_b)
+        .then_with(|| rpmvercmp(ver_a, ver_b))
+        .then_with(|| match (rel_a, rel_b) {
+            (Some(x), Some(y)) => rpmvercmp(x, y),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Split `[epoch:]version[-release]`; a missing epoch is `"0"`.
+fn split_evr(evr: &str) -> (&str, &str, Option<&str>) {
+    let digits = evr.bytes().take_while(u8::is_ascii_digit).count();
+    let (epoch, rest) = match evr[digits..].strip_prefix(':') {
+        Some(rest) if digits > 0 => (&evr[..digits], rest),
+        Some(rest) => ("0", rest),
+        None => ("0", evr),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// The segment-wise comparison behind `vercmp`: runs of digits compare
+/// numerically, runs of letters lexically, a numeric segment beats an
+/// alphabetic one, and a trailing letter segment sorts before the end.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (sep_a, sep_b) = (i, j);
+        while i < a.len() && !a[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < b.len() && !b[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i == a.len() || j == b.len() {
+            break;
+        }
+        // More separators wins, e.g. "1..0" > "1.0".
+        if i - sep_a != j - sep_b {
+            return (i - sep_a).cmp(&(j - sep_b));
+        }
+
+        let numeric = a[i].is_ascii_digit();
+        let in_segment = |c: &u8| {
+            if numeric {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let end_a = i + a[i..].iter().take_while(|c| in_segment(c)).count();
+        let end_b = j + b[j..].iter().take_while(|c| in_segment(c)).count();
+        if end_b == j {
+            // Segment types differ: numeric is newer than alphabetic.
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let (mut seg_a, mut seg_b) = (&a[i..end_a], &b[j..end_b]);
+        if numeric {
+            while seg_a.len() > 1 && seg_a[0] == b'0' {
+                seg_a = &seg_a[1..];
+            }
+            while seg_b.len() > 1 && seg_b[0] == b'0' {
+                seg_b = &seg_b[1..];
+            }
+            let by_len = seg_a.len().cmp(&seg_b.len());
+            if by_len != Ordering::Equal {
+                return by_len;
+            }
+        }
+        let by_text = seg_a.cmp(seg_b);
+        if by_text != Ordering::Equal {
+            return by_text;
+        }
+        i = end_a;
+        j = end_b;
+    }
+
+    match (a.get(i), b.get(j)) {
+        (None, None) => Ordering::Equal,
+        // "1.0" < "1.0.1" and "1.0a" < "1.0": whatever is left over decides.
+        (None, Some(c)) if !c.is_ascii_alphabetic() => Ordering::Less,
+        (Some(c), _) if c.is_ascii_alphabetic() => Ordering::Less,
+        _ => Ordering::Greater,
     }
-    let n: i32 = String::from_utf8_lossy(&out.stdout).trim().parse().ok()?;
-    Some(n < 0)
 }
 
 #[cfg(test)]
@@ -699,4 +799,30 @@ mod tests {
         assert_eq!(map.get("firefox").map(String::as_str), Some("126.0-1"));
         assert_eq!(map.len(), 2);
     }
+
+    #[test]
+    fn test_vercmp_matches_pacman() {
+        let cases = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "1.0.1", Ordering::Less),
+            ("1.0a", "1.0", Ordering::Less),
+            ("1.0", "1.0.a", Ordering::Less),
+            ("1.01", "1.1", Ordering::Equal),
+            ("1.0.10", "1.0.9", Ordering::Greater),
+            ("1.0alpha", "1.0beta", Ordering::Less),
+            ("1.0-1", "1.0-2", Ordering::Less),
+            ("1.0", "1.0-5", Ordering::Equal),
+            ("1:1.0-1", "2.0-1", Ordering::Greater),
+            ("0:2.0", "2.0", Ordering::Equal),
+            ("6.9.1.arch1-1", "6.9.2.arch1-1", Ordering::Less),
+            ("3.2.0-1", "3.2.1-1", Ordering::Less),
+            ("1.0.1", "1.0a", Ordering::Greater),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(vercmp(a, b), expected, "vercmp {a} {b}");
+            assert_eq!(vercmp(b, a), expected.reverse(), "vercmp {b} {a}");
+        }
+        assert!(version_lt("3.2.0-1", "3.2.1-1"));
+        assert!(!version_lt("3.2.1-1", "3.2.1-1"));
+    }
 }
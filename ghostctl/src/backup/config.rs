@@ -0,0 +1,305 @@
+use crate::support::SecretSource;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// One declarative restic backup job (`[[backup.jobs]]` in config.toml).
+///
+/// Jobs are run with `ghostctl backup run <job>` and need no prompts, so the
+/// systemd units written by `ghostctl backup timers` can call them directly.
+/// Each snapshot is tagged `ghostctl:<name>` so several jobs can share one
+/// repository without their retention touching each other's snapshots.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BackupJob {
+    /// Job name, used on the CLI, in unit names and in the snapshot tag.
+    pub name: String,
+
+    /// Paths to back up.
+    pub sources: Vec<String>,
+
+    /// restic `--exclude` patterns.
+    #[serde(default)]
+    pub excludes: Vec<String>,
+
+    /// restic repository: a local path, `sftp:user@host:/path`,
+    /// `s3:host/bucket/path` or `rest:https://host:8000/repo`.
+    pub repository: String,
+
+    /// Where the repository password comes from; `$RESTIC_PASSWORD` by
+    /// default.
+    #[serde(default = "default_password")]
+    pub password: SecretSource,
+
+    /// Shell file with backend credentials (`AWS_ACCESS_KEY_ID=...`, ...),
+    /// sourced before every restic call.
+    #[serde(default)]
+    pub env_file: Option<String>,
+
+    /// Shell commands run before the backup (database dumps,
+    /// `docker compose pause`, ...). A failing hook skips the backup.
+    #[serde(default)]
+    pub pre: Vec<String>,
+
+    /// Shell commands run after the backup, even when it failed.
+    #[serde(default)]
+    pub post: Vec<String>,
+
+    /// Snapshots to keep; falls back to the `[backup]` retention counts.
+    #[serde(default)]
+    pub retention: Option<JobRetention>,
+
+    /// systemd `OnCalendar=` expression, e.g. `daily` or `*-*-* 02:00:00`.
+    #[serde(default)]
+    pub schedule: Option<String>,
+
+    /// Extra tags added to every snapshot.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Restore drill settings (`[backup.jobs.drill]`).
+    #[serde(default)]
+    pub drill: DrillConfig,
+}
+
+/// What `ghostctl backup drill` restores and how it checks the result.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DrillConfig {
+    /// Files that are always restored; a canary missing from the snapshot
+    /// fails the drill.
+    #[serde(default)]
+    pub canary_files: Vec<String>,
+
+    /// Number of random files restored in addition to the canaries.
+    #[serde(default = "default_drill_sample")]
+    pub sample: usize,
+
+    /// `sha256sum`-format manifest checked instead of the live source for
+    /// the files it lists.
+    #[serde(default)]
+    pub manifest: Option<String>,
+}
+
+fn default_drill_sample() -> usize {
+    20
+}
+
+impl Default for DrillConfig {
+    fn default() -> Self {
+        Self {
+            canary_files: Vec::new(),
+            sample: default_drill_sample(),
+            manifest: None,
+        }
+    }
+}
+
+pub fn default_password() -> SecretSource {
+    SecretSource::Env("RESTIC_PASSWORD".to_string())
+}
+
+/// `restic forget` keep counts; zero means "not used".
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct JobRetention {
+    #[serde(default)]
+    pub keep_last: u32,
+    #[serde(default)]
+    pub keep_hourly: u32,
+    #[serde(default)]
+    pub keep_daily: u32,
+    #[serde(default)]
+    pub keep_weekly: u32,
+    #[serde(default)]
+    pub keep_monthly: u32,
+    #[serde(default)]
+    pub keep_yearly: u32,
+}
+
+impl JobRetention {
+    /// `restic forget` flags for the non-zero counts.
+    pub fn forget_args(&self) -> Vec<String> {
+        [
+            ("--keep-last", self.keep_last),
+            ("--keep-hourly", self.keep_hourly),
+            ("--keep-daily", self.keep_daily),
+            ("--keep-weekly", self.keep_weekly),
+            ("--keep-monthly", self.keep_monthly),
+            ("--keep-yearly", self.keep_yearly),
+        ]
+        .into_iter()
+        .filter(|(_, n)| *n > 0)
+        .flat_map(|(flag, n)| [flag.to_string(), n.to_string()])
+        .collect()
+    }
+}
+
+impl BackupJob {
+    /// Tag identifying this job's snapshots.
+    pub fn tag(&self) -> String {
+        format!("ghostctl:{}", self.name)
+    }
+
+    /// Job retention, or the global `[backup]` counts when unset.
+    pub fn effective_retention(&self, global: &crate::config::BackupConfig) -> JobRetention {
+        self.retention.clone().unwrap_or(JobRetention {
+            keep_daily: global.retention_daily,
+            keep_weekly: global.retention_weekly,
+            keep_monthly: global.retention_monthly,
+            ..JobRetention::default()
+        })
+    }
+}
+
+/// Job names end up in systemd unit file names, paths and `ExecStart=`
+/// lines, so only `[A-Za-z0-9_-]` is accepted.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        bail!("invalid backup job name '{name}': use letters, digits, '_' and '-' only");
+    }
+    Ok(())
+}
+
+/// Names must also be unique: two jobs with one name would share a tag, and
+/// so each other's retention, and overwrite each other's timer units.
+pub fn validate_jobs(jobs: &[BackupJob]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for job in jobs {
+        validate_name(&job.name)?;
+        if !seen.insert(job.name.as_str()) {
+            bail!("backup job '{}' is defined more than once", job.name);
+        }
+    }
+    Ok(())
+}
+
+/// Jobs configured in `[[backup.jobs]]`, refusing invalid names.
+pub fn load_jobs() -> Result<Vec<BackupJob>> {
+    let jobs = crate::config::GhostConfig::load().backup.jobs;
+    validate_jobs(&jobs)?;
+    Ok(jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Wrapper {
+        jobs: Vec<BackupJob>,
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        let parsed: Wrapper = toml::from_str(
+            r#"
+            [[jobs]]
+            name = "home"
+            sources = ["/home"]
+            repository = "sftp:backup@nas:/srv/restic"
+            password = { file = "/root/.restic-pw" }
+            pre = ["docker compose -f /srv/app/compose.yml pause"]
+            post = ["docker compose -f /srv/app/compose.yml unpause"]
+            schedule = "daily"
+
+            [jobs.retention]
+            keep_daily = 14
+
+            [[jobs]]
+            name = "etc"
+            sources = ["/etc"]
+            repository = "rest:https://rest.lan:8000/etc"
+
+            [jobs.drill]
+            canary_files = ["/etc/hostname"]
+            sample = 5
+            "#,
+        )
+        .unwrap();
+
+        let home = &parsed.jobs[0];
+        assert_eq!(home.password, SecretSource::File("/root/.restic-pw".into()));
+        assert_eq!(home.tag(), "ghostctl:home");
+        assert_eq!(home.schedule.as_deref(), Some("daily"));
+        assert_eq!(
+            home.retention.as_ref().unwrap().forget_args(),
+            vec!["--keep-daily", "14"]
+        );
+
+        assert_eq!(home.drill.sample, 20);
+
+        let etc = &parsed.jobs[1];
+        assert_eq!(etc.password, default_password());
+        assert_eq!(etc.drill.canary_files, vec!["/etc/hostname"]);
+        assert_eq!(etc.drill.sample, 5);
+        assert!(etc.pre.is_empty() && etc.retention.is_none());
+    }
+
+    #[test]
+    fn test_job_names_are_unit_safe() {
+        for name in ["home", "db_01", "nas-etc"] {
+            assert!(validate_name(name).is_ok(), "{name}");
+        }
+        for name in ["", "a b", "../etc", "x;reboot", "db.daily", "nàs"] {
+            assert!(validate_name(name).is_err(), "{name}");
+        }
+
+        let parsed: Wrapper = toml::from_str(
+            r#"
+            [[jobs]]
+            name = "home"
+            sources = ["/home"]
+            repository = "/mnt/a"
+
+            [[jobs]]
+            name = "etc"
+            sources = ["/etc"]
+            repository = "/mnt/a"
+
+            [[jobs]]
+            name = "home"
+            sources = ["/srv"]
+            repository = "/mnt/b"
+            "#,
+        )
+        .unwrap();
+        assert!(validate_jobs(&parsed.jobs[..2]).is_ok());
+        let err = validate_jobs(&parsed.jobs).unwrap_err().to_string();
+        assert!(
+            err.contains("'home'") && err.contains("more than once"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_retention_falls_back_to_global() {
+        let global = crate::config::GhostConfig::default().backup;
+        let job = BackupJob {
+            name: "x".into(),
+            sources: vec!["/etc".into()],
+            excludes: vec![],
+            repository: "/mnt/restic".into(),
+            password: default_password(),
+            env_file: None,
+            pre: vec![],
+            post: vec![],
+            retention: None,
+            schedule: None,
+            tags: vec![],
+            drill: DrillConfig::default(),
+        };
+        assert_eq!(
+            job.effective_retention(&global).forget_args(),
+            vec![
+                "--keep-daily",
+                "7",
+                "--keep-weekly",
+                "4",
+                "--keep-monthly",
+                "12"
+            ]
+        );
+    }
+}
@@ -0,0 +1,631 @@
+//! Restore drills: prove that a job's latest snapshot actually restores.
+//!
+//! A drill restores the job's canary files plus a random sample from the
+//! latest `ghostctl:<job>` snapshot into a scratch directory, hashes every
+//! restored file and compares it with the recorded manifest or, failing
+//! that, the live source (files modified since the snapshot are reported but
+//! not counted as failures). Restore throughput is measured and the full
+//! report is written as JSON to the support log directory.
+
+use super::config::BackupJob;
+use super::jobs::Restic;
+use crate::command::CommandRunner;
+use crate::sign::hash::{DigestAlgorithm, file_digest};
+use crate::tui;
+use crate::utils::bytes_to_hex;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Latest snapshot of a job, from `restic snapshots --json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotRef {
+    pub id: String,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct RawSnapshot {
+    id: String,
+    time: String,
+}
+
+/// A regular file in a snapshot, from `restic ls --json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotFile {
+    pub path: String,
+    pub size: u64,
+    pub mtime: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    Canary,
+    Sample,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    /// Restored hash equals the manifest or live source hash.
+    Match,
+    /// Restored content differs from an unchanged source or the manifest.
+    Mismatch,
+    /// The file was selected but is not in the restore target.
+    NotRestored,
+    /// A canary file is not part of the snapshot.
+    MissingFromSnapshot,
+    /// Live file was modified after the snapshot; nothing to compare.
+    ChangedSinceSnapshot,
+    /// Live file no longer exists and no manifest entry covers it.
+    MissingOnSource,
+}
+
+impl CheckStatus {
+    pub fn is_failure(self) -> bool {
+        matches!(
+            self,
+            CheckStatus::Mismatch | CheckStatus::NotRestored | CheckStatus::MissingFromSnapshot
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCheck {
+    pub path: String,
+    pub selection: Selection,
+    pub size: u64,
+    pub status: CheckStatus,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrillReport {
+    pub job: String,
+    pub repository: String,
+    pub snapshot_id: String,
+    pub snapshot_time: String,
+    pub started_at: String,
+    /// Sampling seed; the same seed against the same snapshot picks the
+    /// same files.
+    pub seed: u64,
+    pub restored_bytes: u64,
+    pub restore_secs: f64,
+    pub throughput_mib_s: f64,
+    pub passed: bool,
+    pub files: Vec<FileCheck>,
+}
+
+/// Newest snapshot in `restic snapshots --json` output.
+pub fn parse_latest_snapshot(output: &str) -> Result<Option<SnapshotRef>> {
+    let snapshots: Vec<RawSnapshot> =
+        serde_json::from_str(output.trim()).context("invalid restic snapshots output")?;
+    let mut parsed = Vec::with_capacity(snapshots.len());
+    for raw in snapshots {
+        let time = DateTime::parse_from_rfc3339(&raw.time)
+            .with_context(|| format!("invalid time on snapshot {}", raw.id))?
+            .with_timezone(&Utc);
+        parsed.push(SnapshotRef { id: raw.id, time });
+    }
+    Ok(parsed.into_iter().max_by_key(|s| s.time))
+}
+
+/// Regular files in `restic ls --json` output.
+pub fn parse_ls(output: &str) -> Vec<SnapshotFile> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line.trim()).ok()?;
+            if value.get("type")?.as_str()? != "file" {
+                return None;
+            }
+            Some(SnapshotFile {
+                path: value.get("path")?.as_str()?.to_string(),
+                size: value.get("size").and_then(|v| v.as_u64()).unwrap_or(0),
+                mtime: value
+                    .get("mtime")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                    .map(|t| t.with_timezone(&Utc)),
+            })
+        })
+        .collect()
+}
+
+/// Entries of a `sha256sum`-format manifest, keyed by path.
+pub fn parse_manifest(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (hash, path) = line.trim().split_once(char::is_whitespace)?;
+            let path = path.trim_start().trim_start_matches('*');
+            (hash.len() == 64 && !path.is_empty())
+                .then(|| (path.to_string(), hash.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Canaries plus up to `sample` other files picked with a seeded xorshift,
+/// so a drill can be reproduced from the seed in its report.
+pub fn select_files<'a>(
+    files: &'a [SnapshotFile],
+    canaries: &[String],
+    sample: usize,
+    seed: u64,
+) -> (Vec<(&'a SnapshotFile, Selection)>, Vec<String>) {
+    let canary_set: HashSet<&str> = canaries.iter().map(String::as_str).collect();
+    let mut selected: Vec<(&SnapshotFile, Selection)> = files
+        .iter()
+        .filter(|f| canary_set.contains(f.path.as_str()))
+        .map(|f| (f, Selection::Canary))
+        .collect();
+    let missing = canaries
+        .iter()
+        .filter(|c| !files.iter().any(|f| &f.path == *c))
+        .cloned()
+        .collect();
+
+    let mut pool: Vec<&SnapshotFile> = files
+        .iter()
+        .filter(|f| !canary_set.contains(f.path.as_str()))
+        .collect();
+    let mut state = seed | 1;
+    for _ in 0..sample.min(pool.len()) {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let picked = pool.swap_remove((state % pool.len() as u64) as usize);
+        selected.push((picked, Selection::Sample));
+    }
+    (selected, missing)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    Ok(bytes_to_hex(file_digest(path, DigestAlgorithm::Sha256)?))
+}
+
+fn live_mtime(path: &Path) -> Option<DateTime<Utc>> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(DateTime::<Utc>::from)
+}
+
+/// Compare one restored file with the manifest or the live source.
+pub fn check_file(
+    file: &SnapshotFile,
+    selection: Selection,
+    target: &Path,
+    manifest: &BTreeMap<String, String>,
+) -> FileCheck {
+    let restored = target.join(file.path.trim_start_matches('/'));
+    let mut check = FileCheck {
+        path: file.path.clone(),
+        selection,
+        size: file.size,
+        status: CheckStatus::NotRestored,
+        expected: None,
+        actual: None,
+    };
+    let Ok(actual) = sha256_file(&restored) else {
+        return check;
+    };
+    check.actual = Some(actual);
+
+    let live = Path::new(&file.path);
+    check.expected = match manifest.get(&file.path) {
+        Some(hash) => Some(hash.clone()),
+        None if !live.exists() => {
+            check.status = CheckStatus::MissingOnSource;
+            return check;
+        }
+        None => {
+            let unchanged = match (live_mtime(live), file.mtime) {
+                (Some(live), Some(snap)) => live.timestamp() == snap.timestamp(),
+                _ => true,
+            };
+            if !unchanged {
+                check.status = CheckStatus::ChangedSinceSnapshot;
+                return check;
+            }
+            sha256_file(live).ok()
+        }
+    };
+    check.status = if check.expected == check.actual {
+        CheckStatus::Match
+    } else {
+        CheckStatus::Mismatch
+    };
+    check
+}
+
+/// Restore and verify a sample of the latest snapshot into `target`.
+pub fn run_drill(
+    runner: &dyn CommandRunner,
+    job: &BackupJob,
+    target: &Path,
+    seed: u64,
+) -> Result<DrillReport> {
+    let restic = Restic::new(job)?;
+    let started_at = Utc::now();
+    let tag = job.tag();
+
+    let snapshots = restic.run(
+        runner,
+        &["snapshots", "--json", "--tag", &tag, "--latest", "1"],
+    )?;
+    if !snapshots.success {
+        bail!("restic snapshots failed: {}", snapshots.stderr.trim());
+    }
+    let Some(snapshot) = parse_latest_snapshot(&snapshots.stdout)? else {
+        bail!("job '{}' has no snapshots tagged {tag}", job.name);
+    };
+
+    let listing = restic.run(runner, &["ls", "--json", &snapshot.id])?;
+    if !listing.success {
+        bail!("restic ls failed: {}", listing.stderr.trim());
+    }
+    let files = parse_ls(&listing.stdout);
+    let (selected, missing) = select_files(&files, &job.drill.canary_files, job.drill.sample, seed);
+    if selected.is_empty() && missing.is_empty() {
+        bail!("snapshot {} contains no files to restore", snapshot.id);
+    }
+
+    // Without any `--include`, restic would restore the whole snapshot; when
+    // only missing canaries are left there is nothing to restore.
+    let restore_secs = if selected.is_empty() {
+        0.0
+    } else {
+        let target_str = target.to_string_lossy().to_string();
+        let mut args = vec![
+            "restore".to_string(),
+            snapshot.id.clone(),
+            "--target".to_string(),
+            target_str,
+        ];
+        for (file, _) in &selected {
+            args.extend(["--include".to_string(), file.path.clone()]);
+        }
+        let restore_started = Instant::now();
+        let restore = restic.run(runner, &args)?;
+        if !restore.success {
+            bail!("restic restore failed: {}", restore.stderr.trim());
+        }
+        restore_started.elapsed().as_secs_f64()
+    };
+
+    let manifest = match &job.drill.manifest {
+        Some(path) => parse_manifest(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read drill manifest {path}"))?,
+        ),
+        None => BTreeMap::new(),
+    };
+
+    let mut checks: Vec<FileCheck> = selected
+        .iter()
+        .map(|(file, selection)| check_file(file, *selection, target, &manifest))
+        .collect();
+    checks.extend(missing.into_iter().map(|path| FileCheck {
+        path,
+        selection: Selection::Canary,
+        size: 0,
+        status: CheckStatus::MissingFromSnapshot,
+        expected: None,
+        actual: None,
+    }));
+
+    let restored_bytes: u64 = checks
+        .iter()
+        .filter(|c| c.actual.is_some())
+        .map(|c| c.size)
+        .sum();
+    let throughput_mib_s = if restore_secs > 0.0 {
+        restored_bytes as f64 / (1024.0 * 1024.0) / restore_secs
+    } else {
+        0.0
+    };
+
+    Ok(DrillReport {
+        job: job.name.clone(),
+        repository: job.repository.clone(),
+        snapshot_id: snapshot.id,
+        snapshot_time: snapshot.time.to_rfc3339(),
+        started_at: started_at.to_rfc3339(),
+        seed,
+        restored_bytes,
+        restore_secs,
+        throughput_mib_s,
+        passed: !checks.iter().any(|c| c.status.is_failure()),
+        files: checks,
+    })
+}
+
+fn write_report(report: &DrillReport, at: DateTime<Utc>) -> Result<PathBuf> {
+    let dir = crate::support::log_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let path = dir.join(format!(
+        "backup-drill-{}-{}.json",
+        report.job,
+        at.format("%Y%m%dT%H%M%S")
+    ));
+    std::fs::write(&path, serde_json::to_string_pretty(report)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// `ghostctl backup drill <job>`.
+pub fn run(name: &str) -> Result<()> {
+    let jobs = super::config::load_jobs()?;
+    let Some(job) = jobs.iter().find(|j| j.name == name) else {
+        bail!("no backup job '{name}'");
+    };
+
+    if crate::utils::is_dry_run() {
+        println!(
+            "[DRY RUN] Would restore {} canary file(s) and {} sampled file(s) from the latest '{}' snapshot",
+            job.drill.canary_files.len(),
+            job.drill.sample,
+            job.name
+        );
+        return Ok(());
+    }
+
+    tui::header(&format!("Restore drill: {}", job.name));
+    let scratch = tempfile::Builder::new()
+        .prefix("ghostctl-drill-")
+        .tempdir()
+        .context("failed to create restore directory")?;
+    let seed = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    let report = run_drill(crate::command::runner().as_ref(), job, scratch.path(), seed)?;
+
+    println!(
+        "Snapshot {} ({})",
+        &report.snapshot_id[..report.snapshot_id.len().min(8)],
+        report.snapshot_time
+    );
+    for check in &report.files {
+        let line = format!("{:?} {:?}: {}", check.selection, check.status, check.path);
+        if check.status.is_failure() {
+            tui::error(&line);
+        } else if check.status != CheckStatus::Match {
+            tui::warn(&line);
+        }
+    }
+    let matched = report
+        .files
+        .iter()
+        .filter(|c| c.status == CheckStatus::Match)
+        .count();
+    println!(
+        "{matched}/{} file(s) verified, {:.1} MiB restored in {:.1}s ({:.1} MiB/s)",
+        report.files.len(),
+        report.restored_bytes as f64 / (1024.0 * 1024.0),
+        report.restore_secs,
+        report.throughput_mib_s
+    );
+
+    let path = write_report(&report, Utc::now())?;
+    tui::info(&format!("Report: {}", path.display()));
+    if !report.passed {
+        bail!("restore drill failed for job '{}'", job.name);
+    }
+    tui::success("Restore drill passed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    fn file(path: &str) -> SnapshotFile {
+        SnapshotFile {
+            path: path.into(),
+            size: 4,
+            mtime: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_latest_snapshot_and_ls() {
+        let snapshots = r#"[
+            {"id":"aaa","time":"2026-01-01T02:00:00.5+01:00","paths":["/srv"]},
+            {"id":"bbb","time":"2026-01-02T02:00:00Z","paths":["/srv"]}
+        ]"#;
+        assert_eq!(parse_latest_snapshot(snapshots).unwrap().unwrap().id, "bbb");
+        assert!(parse_latest_snapshot("[]").unwrap().is_none());
+
+        let ls = r#"{"time":"2026-01-02T02:00:00Z","struct_type":"snapshot","id":"bbb"}
+{"name":"srv","type":"dir","path":"/srv","struct_type":"node"}
+{"name":"a.txt","type":"file","path":"/srv/a.txt","size":12,"mtime":"2026-01-01T10:00:00.123+00:00","struct_type":"node"}"#;
+        let files = parse_ls(ls);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "/srv/a.txt");
+        assert_eq!(files[0].size, 12);
+        assert!(files[0].mtime.is_some());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let hash = "a".repeat(64);
+        let manifest = parse_manifest(&format!("{hash}  /srv/a.txt\n{hash} */srv/b.bin\nbogus\n"));
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest["/srv/b.bin"], hash);
+    }
+
+    #[test]
+    fn test_select_files_is_seeded_and_reports_missing_canaries() {
+        let files: Vec<SnapshotFile> = (0..10).map(|i| file(&format!("/srv/{i}"))).collect();
+        let canaries = vec!["/srv/3".to_string(), "/srv/gone".to_string()];
+
+        let (selected, missing) = select_files(&files, &canaries, 4, 42);
+        assert_eq!(missing, vec!["/srv/gone"]);
+        assert_eq!(selected.len(), 5);
+        assert_eq!(selected[0].0.path, "/srv/3");
+        assert_eq!(selected[0].1, Selection::Canary);
+        assert!(
+            selected[1..]
+                .iter()
+                .all(|(f, s)| *s == Selection::Sample && f.path != "/srv/3")
+        );
+
+        let (again, _) = select_files(&files, &canaries, 4, 42);
+        let paths = |v: &[(&SnapshotFile, Selection)]| {
+            v.iter().map(|(f, _)| f.path.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(paths(&selected), paths(&again));
+
+        let (all, _) = select_files(&files, &[], 50, 7);
+        assert_eq!(all.len(), 10);
+    }
+
+    #[test]
+    fn test_run_drill_compares_with_live_source() {
+        let source = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let good = source.path().join("good.txt");
+        let bad = source.path().join("bad.txt");
+        std::fs::write(&good, "same").unwrap();
+        std::fs::write(&bad, "live").unwrap();
+        let good = good.to_string_lossy().to_string();
+        let bad = bad.to_string_lossy().to_string();
+
+        // Simulate what `restic restore --target` would have written.
+        for (path, content) in [(&good, "same"), (&bad, "rotten")] {
+            let restored = target.path().join(path.trim_start_matches('/'));
+            std::fs::create_dir_all(restored.parent().unwrap()).unwrap();
+            std::fs::write(restored, content).unwrap();
+        }
+
+        let job: BackupJob = toml::from_str(&format!(
+            r#"
+            name = "srv"
+            sources = ["{src}"]
+            repository = "/mnt/restic"
+            password = {{ file = "/root/pw" }}
+            [drill]
+            canary_files = ["{good}", "/srv/missing"]
+            sample = 5
+            "#,
+            src = source.path().display(),
+        ))
+        .unwrap();
+
+        let restic = Restic::new(&job).unwrap();
+        let mock = MockRunner::new();
+        mock.mock_shell(
+            &restic.command_line(&[
+                "snapshots",
+                "--json",
+                "--tag",
+                "ghostctl:srv",
+                "--latest",
+                "1",
+            ]),
+            CommandResult::ok(r#"[{"id":"cafe1234","time":"2026-01-02T02:00:00Z"}]"#),
+        );
+        mock.mock_shell(
+            &restic.command_line(&["ls", "--json", "cafe1234"]),
+            CommandResult::ok(format!(
+                "{{\"type\":\"file\",\"path\":\"{good}\",\"size\":4}}\n\
+                 {{\"type\":\"file\",\"path\":\"{bad}\",\"size\":4}}\n"
+            )),
+        );
+
+        let report = run_drill(&mock, &job, target.path(), 1).unwrap();
+
+        assert!(mock.was_called("restore cafe1234 --target"));
+        let status = |p: &str| report.files.iter().find(|c| c.path == p).unwrap().status;
+        assert_eq!(status(&good), CheckStatus::Match);
+        assert_eq!(status(&bad), CheckStatus::Mismatch);
+        assert_eq!(status("/srv/missing"), CheckStatus::MissingFromSnapshot);
+        assert_eq!(report.restored_bytes, 8);
+        assert!(!report.passed);
+    }
+
+    #[test]
+    fn test_run_drill_with_only_missing_canaries_restores_nothing() {
+        let target = tempfile::tempdir().unwrap();
+        let job: BackupJob = toml::from_str(
+            r#"
+            name = "srv"
+            sources = ["/srv"]
+            repository = "/mnt/restic"
+            password = { file = "/root/pw" }
+            [drill]
+            canary_files = ["/srv/gone"]
+            sample = 0
+            "#,
+        )
+        .unwrap();
+
+        let restic = Restic::new(&job).unwrap();
+        let mock = MockRunner::new();
+        mock.mock_shell(
+            &restic.command_line(&[
+                "snapshots",
+                "--json",
+                "--tag",
+                "ghostctl:srv",
+                "--latest",
+                "1",
+            ]),
+            CommandResult::ok(r#"[{"id":"cafe1234","time":"2026-01-02T02:00:00Z"}]"#),
+        );
+        mock.mock_shell(
+            &restic.command_line(&["ls", "--json", "cafe1234"]),
+            CommandResult::ok("{\"type\":\"file\",\"path\":\"/srv/a.txt\",\"size\":4}\n"),
+        );
+
+        let report = run_drill(&mock, &job, target.path(), 1).unwrap();
+
+        assert!(!mock.was_called("restore cafe1234"));
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].path, "/srv/gone");
+        assert_eq!(report.files[0].status, CheckStatus::MissingFromSnapshot);
+        assert_eq!(report.restored_bytes, 0);
+        assert!(!report.passed);
+    }
+
+    #[test]
+    fn test_check_file_prefers_manifest_and_skips_changed_files() {
+        let target = tempfile::tempdir().unwrap();
+        let restored = target.path().join("srv/x");
+        std::fs::create_dir_all(restored.parent().unwrap()).unwrap();
+        std::fs::write(&restored, "data").unwrap();
+        let hash = sha256_file(&restored).unwrap();
+
+        let mut manifest = BTreeMap::new();
+        manifest.insert("/srv/x".to_string(), hash);
+        let check = check_file(&file("/srv/x"), Selection::Sample, target.path(), &manifest);
+        assert_eq!(check.status, CheckStatus::Match);
+
+        let check = check_file(
+            &file("/srv/x"),
+            Selection::Sample,
+            target.path(),
+            &BTreeMap::new(),
+        );
+        assert_eq!(check.status, CheckStatus::MissingOnSource);
+        assert!(!check.status.is_failure());
+
+        let live = tempfile::NamedTempFile::new().unwrap();
+        let live_path = live.path().to_string_lossy().to_string();
+        let restored = target.path().join(live_path.trim_start_matches('/'));
+        std::fs::create_dir_all(restored.parent().unwrap()).unwrap();
+        std::fs::write(&restored, "old").unwrap();
+        let changed = SnapshotFile {
+            path: live_path,
+            size: 3,
+            mtime: Some(Utc::now() - chrono::Duration::days(2)),
+        };
+        let check = check_file(&changed, Selection::Sample, target.path(), &BTreeMap::new());
+        assert_eq!(check.status, CheckStatus::ChangedSinceSnapshot);
+    }
+}
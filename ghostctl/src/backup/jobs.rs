@@ -0,0 +1,759 @@
+//! Declarative restic backup jobs.
+//!
+//! A job run is: pre hooks, `restic backup --json` tagged with the job tag,
+//! `restic forget --prune` scoped to that tag, then post hooks (always, so a
+//! `docker compose pause` hook is matched by its `unpause`). Nothing prompts,
+//! which lets the generated systemd timers call `ghostctl backup run <job>`.
+//! The outcome of every run is kept in the ghostctl state directory for
+//! `ghostctl backup status`.
+
+use super::config::BackupJob;
+use crate::command::{CommandResult, CommandRunner};
+use crate::config::BackupConfig;
+use crate::support::SecretSource;
+use crate::tui;
+use crate::utils::shell_quote;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Last recorded outcome of a job.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobState {
+    pub last_run: String,
+    #[serde(default)]
+    pub last_success: Option<String>,
+    #[serde(default)]
+    pub last_snapshot: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub duration_secs: u64,
+    #[serde(default)]
+    pub data_added: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JobsState {
+    #[serde(default)]
+    pub jobs: BTreeMap<String, JobState>,
+}
+
+impl JobsState {
+    fn path() -> PathBuf {
+        crate::support::state_dir().join("backup-jobs.json")
+    }
+
+    pub fn load() -> Self {
+        Self::load_from(&Self::path())
+    }
+
+    fn load_from(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Store `entry` as the record of job `name`, leaving the other jobs
+    /// alone.
+    pub fn record(name: &str, entry: &JobState) -> Result<()> {
+        Self::record_at(&Self::path(), name, entry)
+    }
+
+    /// Timers of different jobs can finish together, so the file is re-read
+    /// under an exclusive lock and replaced by a rename; a plain
+    /// load-modify-save would drop whichever run saved first.
+    fn record_at(path: &Path, name: &str, entry: &JobState) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let lock_path = path.with_extension("lock");
+        let lock = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open {}", lock_path.display()))?;
+        lock.lock()
+            .with_context(|| format!("failed to lock {}", lock_path.display()))?;
+
+        let mut state = Self::load_from(path);
+        state.jobs.insert(name.to_string(), entry.clone());
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&state)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Summary of a successful `restic backup`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BackupSummary {
+    pub snapshot_id: Option<String>,
+    pub files_new: u64,
+    pub files_changed: u64,
+    pub data_added: u64,
+}
+
+/// Pull the `summary` message out of `restic backup --json` output.
+pub fn parse_backup_summary(output: &str) -> Option<BackupSummary> {
+    output.lines().rev().find_map(|line| {
+        let value: serde_json::Value = serde_json::from_str(line.trim()).ok()?;
+        if value.get("message_type")?.as_str()? != "summary" {
+            return None;
+        }
+        let count = |key: &str| value.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+        Some(BackupSummary {
+            snapshot_id: value
+                .get("snapshot_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            files_new: count("files_new"),
+            files_changed: count("files_changed"),
+            data_added: count("data_added"),
+        })
+    })
+}
+
+/// restic invocations for one job, with the password and backend
+/// environment wired in without putting secrets on the command line.
+pub struct Restic<'a> {
+    job: &'a BackupJob,
+    password_args: Vec<String>,
+    // Holds a resolved password for `--password-file`; removed on drop.
+    _secret: Option<tempfile::NamedTempFile>,
+}
+
+impl<'a> Restic<'a> {
+    pub fn new(job: &'a BackupJob) -> Result<Self> {
+        let secret = match &job.password {
+            // restic reads files and runs commands itself.
+            SecretSource::File(path) => {
+                return Ok(Self::with_args(job, ["--password-file", path]));
+            }
+            SecretSource::Command(cmd) => {
+                return Ok(Self::with_args(job, ["--password-command", cmd]));
+            }
+            // Read by restic after `env_file` is sourced, so the variable may
+            // come from there (as it must under a systemd timer).
+            SecretSource::Env(var) => {
+                let cmd = format!("printenv {var}");
+                return Ok(Self::with_args(job, ["--password-command", &cmd]));
+            }
+            other => other.resolve(&format!("job '{}' password", job.name))?,
+        };
+
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(secret.as_bytes())?;
+        file.flush()?;
+        let path = file.path().to_string_lossy().to_string();
+        Ok(Self {
+            job,
+            password_args: vec!["--password-file".into(), path],
+            _secret: Some(file),
+        })
+    }
+
+    fn with_args<const N: usize>(job: &'a BackupJob, args: [&str; N]) -> Self {
+        Self {
+            job,
+            password_args: args.iter().map(|a| a.to_string()).collect(),
+            _secret: None,
+        }
+    }
+
+    /// Shell line running `restic <args>` against the job repository.
+    pub fn command_line<S: AsRef<str>>(&self, args: &[S]) -> String {
+        let mut parts = vec![
+            "restic".to_string(),
+            "-r".to_string(),
+            shell_quote(&self.job.repository),
+        ];
+        parts.extend(self.password_args.iter().map(|a| shell_quote(a)));
+        parts.extend(args.iter().map(|a| shell_quote(a.as_ref())));
+        let restic = parts.join(" ");
+        match &self.job.env_file {
+            Some(env) => format!("set -a && . {} && set +a && {restic}", shell_quote(env)),
+            None => restic,
+        }
+    }
+
+    pub fn run<S: AsRef<str>>(
+        &self,
+        runner: &dyn CommandRunner,
+        args: &[S],
+    ) -> Result<CommandResult> {
+        runner
+            .run_shell(&self.command_line(args))
+            .context("failed to run restic")
+    }
+}
+
+/// `restic backup` arguments for a job.
+pub fn backup_args(job: &BackupJob) -> Vec<String> {
+    let mut args = vec!["backup".to_string(), "--json".to_string()];
+    for tag in std::iter::once(job.tag()).chain(job.tags.iter().cloned()) {
+        args.extend(["--tag".to_string(), tag]);
+    }
+    for exclude in &job.excludes {
+        args.extend(["--exclude".to_string(), exclude.clone()]);
+    }
+    args.extend(job.sources.iter().cloned());
+    args
+}
+
+/// `restic forget` arguments for a job, scoped to its tag.
+pub fn forget_args(job: &BackupJob, global: &BackupConfig) -> Vec<String> {
+    let mut args = vec![
+        "forget".to_string(),
+        "--prune".to_string(),
+        "--tag".to_string(),
+        job.tag(),
+    ];
+    args.extend(job.effective_retention(global).forget_args());
+    args
+}
+
+fn run_hooks(runner: &dyn CommandRunner, kind: &str, hooks: &[String]) -> Result<()> {
+    for hook in hooks {
+        tui::info(&format!("🪝 {kind} hook: {hook}"));
+        let result = runner
+            .run_shell(hook)
+            .with_context(|| format!("failed to run {kind} hook"))?;
+        if !result.success {
+            bail!(
+                "{kind} hook `{hook}` failed ({}): {}",
+                result.exit_code.unwrap_or(-1),
+                result.stderr.trim()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Run one job and record the outcome in `state`.
+pub fn run_job(
+    runner: &dyn CommandRunner,
+    job: &BackupJob,
+    global: &BackupConfig,
+    state: &mut JobsState,
+) -> Result<BackupSummary> {
+    if job.sources.is_empty() {
+        bail!("job '{}' has no sources", job.name);
+    }
+    let restic = Restic::new(job)?;
+
+    if crate::utils::is_dry_run() {
+        for hook in &job.pre {
+            println!("[DRY RUN] pre hook: {hook}");
+        }
+        println!("[DRY RUN] {}", restic.command_line(&backup_args(job)));
+        println!(
+            "[DRY RUN] {}",
+            restic.command_line(&forget_args(job, global))
+        );
+        for hook in &job.post {
+            println!("[DRY RUN] post hook: {hook}");
+        }
+        return Ok(BackupSummary::default());
+    }
+
+    let started = Instant::now();
+    let now = chrono::Local::now().to_rfc3339();
+
+    let outcome = run_hooks(runner, "pre", &job.pre).and_then(|()| {
+        let result = restic.run(runner, &backup_args(job))?;
+        if !result.success {
+            bail!("restic backup failed: {}", result.stderr.trim());
+        }
+        let summary = parse_backup_summary(&result.stdout).unwrap_or_default();
+
+        let forget = restic.run(runner, &forget_args(job, global))?;
+        if !forget.success {
+            bail!("restic forget failed: {}", forget.stderr.trim());
+        }
+        Ok(summary)
+    });
+    let post = run_hooks(runner, "post", &job.post);
+    let outcome = outcome.and_then(|summary| post.map(|()| summary));
+
+    let entry = state.jobs.entry(job.name.clone()).or_default();
+    entry.last_run = now.clone();
+    entry.duration_secs = started.elapsed().as_secs();
+    match &outcome {
+        Ok(summary) => {
+            entry.last_success = Some(now);
+            entry.last_snapshot = summary.snapshot_id.clone();
+            entry.last_error = None;
+            entry.data_added = summary.data_added;
+        }
+        Err(e) => entry.last_error = Some(format!("{e:#}")),
+    }
+    outcome
+}
+
+fn find_job<'a>(jobs: &'a [BackupJob], name: &str) -> Result<&'a BackupJob> {
+    jobs.iter().find(|j| j.name == name).with_context(|| {
+        let known: Vec<&str> = jobs.iter().map(|j| j.name.as_str()).collect();
+        format!(
+            "no backup job '{name}' (configured: {})",
+            if known.is_empty() {
+                "none".to_string()
+            } else {
+                known.join(", ")
+            }
+        )
+    })
+}
+
+/// `ghostctl backup run [job]`: one job, or every job in order.
+pub fn run(name: Option<&str>) -> Result<()> {
+    let config = crate::config::GhostConfig::load();
+    let jobs = &config.backup.jobs;
+    super::config::validate_jobs(jobs)?;
+    if jobs.is_empty() {
+        bail!("no [[backup.jobs]] configured in config.toml");
+    }
+    let selected: Vec<&BackupJob> = match name {
+        Some(name) => vec![find_job(jobs, name)?],
+        None => jobs.iter().collect(),
+    };
+
+    let runner = crate::command::runner();
+    let mut failed = Vec::new();
+    for job in selected {
+        tui::header(&format!("Backup job: {}", job.name));
+        let mut state = JobsState::load();
+        match run_job(runner.as_ref(), job, &config.backup, &mut state) {
+            Ok(summary) => tui::success(&format!(
+                "{}: snapshot {} ({} new, {} changed files, {} added)",
+                job.name,
+                summary.snapshot_id.as_deref().unwrap_or("-"),
+                summary.files_new,
+                summary.files_changed,
+                format_bytes(summary.data_added)
+            )),
+            Err(e) => {
+                tui::error(&format!("{}: {e:#}", job.name));
+                failed.push(job.name.clone());
+            }
+        }
+        if !crate::utils::is_dry_run()
+            && let Some(entry) = state.jobs.get(&job.name)
+        {
+            JobsState::record(&job.name, entry)?;
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("backup failed for: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// `ghostctl backup init <job>`: create the job repository.
+pub fn init(name: &str) -> Result<()> {
+    let jobs = super::config::load_jobs()?;
+    let job = find_job(&jobs, name)?;
+    let restic = Restic::new(job)?;
+    if crate::utils::is_dry_run() {
+        println!("[DRY RUN] {}", restic.command_line(&["init"]));
+        return Ok(());
+    }
+    let result = restic.run(crate::command::runner().as_ref(), &["init"])?;
+    if !result.success {
+        bail!("restic init failed: {}", result.stderr.trim());
+    }
+    tui::success(&format!("Initialized repository {}", job.repository));
+    Ok(())
+}
+
+/// `ghostctl backup status`: last success per job.
+pub fn print_status(json: bool) -> Result<()> {
+    let jobs = super::config::load_jobs()?;
+    let state = JobsState::load();
+
+    if json {
+        let report: BTreeMap<&str, Option<&JobState>> = jobs
+            .iter()
+            .map(|j| (j.name.as_str(), state.jobs.get(&j.name)))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    tui::header("Backup Jobs");
+    if jobs.is_empty() {
+        println!("No [[backup.jobs]] configured.");
+        return Ok(());
+    }
+    println!(
+        "{:<14} {:<26} {:<10} {:<18} RESULT",
+        "JOB", "LAST SUCCESS", "SNAPSHOT", "SCHEDULE"
+    );
+    for job in &jobs {
+        let entry = state.jobs.get(&job.name);
+        let success = entry
+            .and_then(|e| e.last_success.as_deref())
+            .unwrap_or("never");
+        let snapshot = entry
+            .and_then(|e| e.last_snapshot.as_deref())
+            .map(|s| s.chars().take(8).collect::<String>())
+            .unwrap_or_else(|| "-".into());
+        let result = match entry {
+            None => "not run".to_string(),
+            Some(e) => match &e.last_error {
+                Some(err) => format!("failed at {}: {err}", e.last_run),
+                None => "ok".to_string(),
+            },
+        };
+        println!(
+            "{:<14} {:<26} {:<10} {:<18} {}",
+            job.name,
+            success,
+            snapshot,
+            job.schedule.as_deref().unwrap_or("manual"),
+            result
+        );
+    }
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// systemd service and timer for a scheduled job.
+pub fn timer_units(job: &BackupJob, exe: &str, schedule: &str) -> (String, String) {
+    let service = format!(
+        "[Unit]\n\
+         Description=ghostctl backup job {name}\n\
+         After=network-online.target\n\
+         Wants=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={exe} --headless backup run {name}\n\
+         Nice=10\n\
+         IOSchedulingClass=idle\n",
+        name = job.name,
+    );
+    let timer = format!(
+        "[Unit]\n\
+         Description=ghostctl backup timer for {name}\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={schedule}\n\
+         Persistent=true\n\
+         RandomizedDelaySec=10min\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        name = job.name,
+    );
+    (service, timer)
+}
+
+/// A timer runs without the invoking shell's environment, so a password
+/// taken from an environment variable has to come from `env_file`.
+pub fn check_schedulable(job: &BackupJob) -> Result<()> {
+    if let SecretSource::Env(var) = &job.password
+        && job.env_file.is_none()
+    {
+        bail!(
+            "job '{}' reads its password from ${var}, which a timer does not have; \
+             set it in an `env_file` or use a `file`/`command` password",
+            job.name
+        );
+    }
+    Ok(())
+}
+
+pub fn unit_name(job: &BackupJob) -> String {
+    format!("ghostctl-backup-{}", job.name)
+}
+
+/// `ghostctl backup timers`: write and enable a timer per scheduled job.
+pub fn install_timers(system: bool) -> Result<()> {
+    let jobs = super::config::load_jobs()?;
+    let scheduled: Vec<(&BackupJob, &str)> = jobs
+        .iter()
+        .filter_map(|j| j.schedule.as_deref().map(|s| (j, s)))
+        .collect();
+    if scheduled.is_empty() {
+        bail!("no backup job has a `schedule` set");
+    }
+    for (job, _) in &scheduled {
+        check_schedulable(job)?;
+    }
+
+    let dir = if system {
+        PathBuf::from("/etc/systemd/system")
+    } else {
+        dirs::config_dir()
+            .context("could not determine config directory")?
+            .join("systemd/user")
+    };
+    let exe = std::env::current_exe()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "/usr/bin/ghostctl".to_string());
+    let scope: &[&str] = if system { &[] } else { &["--user"] };
+
+    let runner = crate::command::runner();
+    let dry_run = crate::utils::is_dry_run();
+    if !dry_run {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    for (job, schedule) in &scheduled {
+        let (service, timer) = timer_units(job, &exe, schedule);
+        let name = unit_name(job);
+        if dry_run {
+            println!(
+                "[DRY RUN] Would write {}/{name}.service:\n{service}",
+                dir.display()
+            );
+            println!(
+                "[DRY RUN] Would write {}/{name}.timer:\n{timer}",
+                dir.display()
+            );
+            continue;
+        }
+        for (ext, content) in [("service", &service), ("timer", &timer)] {
+            let path = dir.join(format!("{name}.{ext}"));
+            std::fs::write(&path, content)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        tui::success(&format!("{name}.timer ({schedule})"));
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let mut reload = scope.to_vec();
+    reload.push("daemon-reload");
+    runner.run("systemctl", &reload)?;
+    for (job, _) in &scheduled {
+        let timer = format!("{}.timer", unit_name(job));
+        let mut enable = scope.to_vec();
+        enable.extend(["enable", "--now", timer.as_str()]);
+        let result = runner.run("systemctl", &enable)?;
+        if !result.success {
+            tui::warn(&format!(
+                "Could not enable {timer}: {}",
+                result.stderr.trim()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::config::JobRetention;
+    use crate::command::MockRunner;
+
+    fn job() -> BackupJob {
+        BackupJob {
+            name: "db".into(),
+            sources: vec!["/var/backups/pg".into(), "/etc".into()],
+            excludes: vec!["*.tmp".into()],
+            repository: "sftp:backup@nas:/srv/restic".into(),
+            password: SecretSource::File("/root/.restic-pw".into()),
+            env_file: None,
+            pre: vec!["pg_dumpall > /var/backups/pg/all.sql".into()],
+            post: vec!["rm -f /var/backups/pg/all.sql".into()],
+            retention: Some(JobRetention {
+                keep_daily: 7,
+                ..JobRetention::default()
+            }),
+            schedule: Some("daily".into()),
+            tags: vec![],
+            drill: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_backup_summary() {
+        let output = r#"{"message_type":"status","percent_done":0.5}
+{"message_type":"summary","files_new":3,"files_changed":1,"data_added":2048,"snapshot_id":"4f1a2b3c9d"}"#;
+        let summary = parse_backup_summary(output).unwrap();
+        assert_eq!(summary.snapshot_id.as_deref(), Some("4f1a2b3c9d"));
+        assert_eq!(summary.files_new, 3);
+        assert_eq!(summary.data_added, 2048);
+        assert!(parse_backup_summary("not json").is_none());
+    }
+
+    #[test]
+    fn test_command_line_quotes_and_sources_env_file() {
+        let mut job = job();
+        job.env_file = Some("/etc/ghostctl/s3 creds.env".into());
+        let restic = Restic::new(&job).unwrap();
+        assert_eq!(
+            restic.command_line(&["snapshots", "--tag", "ghostctl:db"]),
+            "set -a && . '/etc/ghostctl/s3 creds.env' && set +a && \
+             restic -r sftp:backup@nas:/srv/restic --password-file /root/.restic-pw \
+             snapshots --tag ghostctl:db"
+        );
+    }
+
+    #[test]
+    fn test_backup_and_forget_args() {
+        let global = crate::config::GhostConfig::default().backup;
+        assert_eq!(
+            backup_args(&job()),
+            vec![
+                "backup",
+                "--json",
+                "--tag",
+                "ghostctl:db",
+                "--exclude",
+                "*.tmp",
+                "/var/backups/pg",
+                "/etc"
+            ]
+        );
+        assert_eq!(
+            forget_args(&job(), &global),
+            vec![
+                "forget",
+                "--prune",
+                "--tag",
+                "ghostctl:db",
+                "--keep-daily",
+                "7"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_job_records_success() {
+        let job = job();
+        let global = crate::config::GhostConfig::default().backup;
+        let mock = MockRunner::new();
+        mock.mock_shell(
+            &Restic::new(&job).unwrap().command_line(&backup_args(&job)),
+            CommandResult::ok(
+                r#"{"message_type":"summary","data_added":10,"snapshot_id":"abc123"}"#,
+            ),
+        );
+        let mut state = JobsState::default();
+
+        let summary = run_job(&mock, &job, &global, &mut state).unwrap();
+
+        assert_eq!(summary.snapshot_id.as_deref(), Some("abc123"));
+        let history = mock.get_history();
+        assert_eq!(history.len(), 4);
+        assert!(history[0].contains("pg_dumpall"));
+        assert!(history[1].contains("backup --json"));
+        assert!(history[2].contains("forget --prune --tag ghostctl:db"));
+        assert!(history[3].contains("rm -f"));
+        let entry = &state.jobs["db"];
+        assert!(entry.last_success.is_some());
+        assert_eq!(entry.last_snapshot.as_deref(), Some("abc123"));
+        assert_eq!(entry.last_error, None);
+    }
+
+    #[test]
+    fn test_failed_pre_hook_skips_backup_but_runs_post() {
+        let job = job();
+        let global = crate::config::GhostConfig::default().backup;
+        let mock = MockRunner::new();
+        mock.mock_shell(
+            "pg_dumpall > /var/backups/pg/all.sql",
+            CommandResult::err("connection refused", 2),
+        );
+        let mut state = JobsState::default();
+        state.jobs.insert(
+            "db".into(),
+            JobState {
+                last_success: Some("yesterday".into()),
+                ..JobState::default()
+            },
+        );
+
+        let err = run_job(&mock, &job, &global, &mut state).unwrap_err();
+
+        assert!(format!("{err:#}").contains("connection refused"));
+        assert!(!mock.was_called("restic"));
+        assert!(mock.was_called("rm -f /var/backups/pg/all.sql"));
+        let entry = &state.jobs["db"];
+        assert_eq!(entry.last_success.as_deref(), Some("yesterday"));
+        assert!(entry.last_error.is_some());
+    }
+
+    #[test]
+    fn test_concurrent_records_keep_every_job() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("backup-jobs.json");
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    let entry = JobState {
+                        last_run: format!("run {i}"),
+                        ..JobState::default()
+                    };
+                    JobsState::record_at(path, &format!("job{i}"), &entry).unwrap();
+                });
+            }
+        });
+
+        let state = JobsState::load_from(&path);
+        assert_eq!(state.jobs.len(), 8);
+        assert_eq!(state.jobs["job3"].last_run, "run 3");
+    }
+
+    #[test]
+    fn test_timer_units() {
+        let (service, timer) = timer_units(&job(), "/usr/bin/ghostctl", "daily");
+        assert!(service.contains("ExecStart=/usr/bin/ghostctl --headless backup run db\n"));
+        assert!(timer.contains("OnCalendar=daily\n"));
+        assert!(timer.contains("Persistent=true"));
+        assert_eq!(unit_name(&job()), "ghostctl-backup-db");
+    }
+
+    #[test]
+    fn test_env_password_is_read_after_env_file_is_sourced() {
+        let mut job = job();
+        job.password = crate::backup::config::default_password();
+        assert!(check_schedulable(&job).is_err());
+
+        job.env_file = Some("/etc/ghostctl/db.env".into());
+        check_schedulable(&job).unwrap();
+        let (service, _) = timer_units(&job, "/usr/bin/ghostctl", "daily");
+        assert!(service.contains("ExecStart=/usr/bin/ghostctl --headless backup run db\n"));
+        // ghostctl itself never needs $RESTIC_PASSWORD; restic reads it once
+        // the env file has been sourced.
+        let restic = Restic::new(&job).unwrap();
+        assert_eq!(
+            restic.command_line(&["snapshots"]),
+            "set -a && . /etc/ghostctl/db.env && set +a && \
+             restic -r sftp:backup@nas:/srv/restic \
+             --password-command 'printenv RESTIC_PASSWORD' snapshots"
+        );
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+    }
+}
@@ -1,4 +1,7 @@
 pub mod cleanup;
+pub mod config;
+pub mod drill;
+pub mod jobs;
 pub mod schedule;
 pub mod setup;
 pub mod verify;
@@ -0,0 +1,152 @@
+use super::retention::RetentionPolicy;
+use serde::{Deserialize, Serialize};
+
+/// Btrfs configuration stored in config.toml under [btrfs].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BtrfsConfig {
+    /// Directory for snapshots created with `ghostctl btrfs create`.
+    #[serde(default = "default_manual_snapshot_dir")]
+    pub snapshot_dir: String,
+
+    /// Retention policy applied by `ghostctl btrfs cleanup`.
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+
+    /// `btrfs send`/`receive` replication targets (`[[btrfs.replicate]]`).
+    #[serde(default)]
+    pub replicate: Vec<ReplicationTarget>,
+}
+
+fn default_manual_snapshot_dir() -> String {
+    "/@snapshots".to_string()
+}
+
+impl Default for BtrfsConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_dir: default_manual_snapshot_dir(),
+            retention: RetentionPolicy::default(),
+            replicate: Vec::new(),
+        }
+    }
+}
+
+/// One replication job: snapshot `subvolume` into `snapshot_dir` and ship the
+/// read-only snapshots to `target`, incrementally where a common parent exists.
+///
+/// `target` is either a local path (an external disk or NFS-mounted btrfs
+/// volume) or `ssh://[user@]host[:port]/path`. Remote receives run as
+/// `sudo -n btrfs receive` unless the ssh user is root.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReplicationTarget {
+    /// Job name, used on the CLI and as the default snapshot prefix.
+    pub name: String,
+
+    /// Subvolume to snapshot before each run.
+    #[serde(default = "default_subvolume")]
+    pub subvolume: String,
+
+    /// Directory (on the same filesystem) that holds the source snapshots.
+    #[serde(default = "default_snapshot_dir")]
+    pub snapshot_dir: String,
+
+    /// Receive location: local path or `ssh://[user@]host[:port]/path`.
+    pub target: String,
+
+    /// Snapshot name prefix (defaults to `name`).
+    #[serde(default)]
+    pub prefix: Option<String>,
+
+    /// Private key for ssh targets (the send pipeline runs as root).
+    #[serde(default)]
+    pub ssh_key: Option<String>,
+
+    /// Snapshots kept on the source after a successful send.
+    #[serde(default = "default_source_keep")]
+    pub source_keep: usize,
+
+    /// Snapshots kept on the target.
+    #[serde(default = "default_target_keep")]
+    pub target_keep: usize,
+}
+
+fn default_subvolume() -> String {
+    "/".to_string()
+}
+
+fn default_snapshot_dir() -> String {
+    "/.snapshots/ghostctl".to_string()
+}
+
+fn default_source_keep() -> usize {
+    3
+}
+
+fn default_target_keep() -> usize {
+    14
+}
+
+impl ReplicationTarget {
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl BtrfsConfig {
+    pub fn load() -> Self {
+        crate::config::GhostConfig::load().btrfs.unwrap_or_default()
+    }
+
+    pub fn find_target(&self, name: &str) -> Option<&ReplicationTarget> {
+        self.replicate.iter().find(|t| t.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replication_target_defaults() {
+        let cfg: BtrfsConfig = toml::from_str(
+            r#"
+            [[replicate]]
+            name = "nas"
+            target = "ssh://backup@nas.lan/tank/ghost"
+            "#,
+        )
+        .unwrap();
+        let target = cfg.find_target("nas").unwrap();
+        assert_eq!(target.subvolume, "/");
+        assert_eq!(target.snapshot_dir, "/.snapshots/ghostctl");
+        assert_eq!(target.prefix(), "nas");
+        assert_eq!(target.source_keep, 3);
+        assert_eq!(target.target_keep, 14);
+    }
+
+    #[test]
+    fn test_empty_config() {
+        let cfg: BtrfsConfig = toml::from_str("").unwrap();
+        assert_eq!(cfg.snapshot_dir, "/@snapshots");
+        assert_eq!(cfg.retention, RetentionPolicy::default());
+        assert!(cfg.replicate.is_empty());
+        assert!(cfg.find_target("nas").is_none());
+    }
+
+    #[test]
+    fn test_retention_overrides() {
+        let cfg: BtrfsConfig = toml::from_str(
+            r#"
+            [retention]
+            hourly = 24
+            daily = 14
+            min_free_percent = 15.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.retention.hourly, 24);
+        assert_eq!(cfg.retention.daily, 14);
+        assert_eq!(cfg.retention.weekly, 4);
+        assert_eq!(cfg.retention.min_free_percent, Some(15.0));
+    }
+}
@@ -0,0 +1,633 @@
+//! Typed snapshot inventory.
+//!
+//! Builds one `Snapshot` list from `btrfs subvolume list` (UUIDs, creation
+//! time, read-only flag) and, for snapper-managed locations, enriches each
+//! entry with the metadata from `snapper --jsonout list`. Everything goes
+//! through `CommandRunner` so inventory and retention logic can be exercised
+//! with `MockRunner`.
+
+use super::config::BtrfsConfig;
+use crate::command::CommandRunner;
+use anyhow::{Context, Result, bail};
+use chrono::NaiveDateTime;
+use std::collections::{BTreeMap, HashSet};
+
+const BTRFS_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Name prefixes of the snapshots `btrfs::transaction` keeps in the plain
+/// snapshot directory: the pre/post pairs and the writable rollback roots.
+const TRANSACTION_PREFIXES: [&str; 2] = ["ghostctl-txn-", "ghostctl-rollback-"];
+
+/// Snapper userdata key `btrfs::transaction` tags its pre/post pairs with.
+pub const TRANSACTION_USERDATA: &str = "ghostctl_txn";
+
+/// Snapper metadata for a snapshot managed by a snapper config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapperInfo {
+    pub config: String,
+    pub number: u32,
+    /// `single`, `pre` or `post`.
+    pub kind: String,
+    pub pre_number: Option<u32>,
+    pub description: String,
+    /// Snapper cleanup algorithm (`number`, `timeline`, or empty).
+    pub cleanup: String,
+    pub userdata: BTreeMap<String, String>,
+    pub default: bool,
+    pub active: bool,
+}
+
+/// One read-only or writable btrfs snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub id: u64,
+    /// Absolute path of the snapshot subvolume.
+    pub path: String,
+    /// Short name (directory name, or `<config>#<number>` for snapper).
+    pub name: String,
+    pub uuid: Option<String>,
+    pub parent_uuid: Option<String>,
+    pub received_uuid: Option<String>,
+    pub ctime: Option<NaiveDateTime>,
+    pub readonly: bool,
+    pub snapper: Option<SnapperInfo>,
+}
+
+impl Snapshot {
+    /// Location group used for retention (snapper config or snapshot dir).
+    pub fn group(&self) -> String {
+        match &self.snapper {
+            Some(info) => format!("snapper:{}", info.config),
+            None => self
+                .path
+                .rsplit_once('/')
+                .map(|(dir, _)| if dir.is_empty() { "/" } else { dir })
+                .unwrap_or("/")
+                .to_string(),
+        }
+    }
+
+    /// Snapshots that must never be deleted automatically: snapper's
+    /// "current" pseudo-snapshot, the default/active snapshot, anything
+    /// marked `important=yes`, and ghostctl's own transaction snapshots.
+    pub fn is_protected(&self) -> bool {
+        match &self.snapper {
+            Some(info) => {
+                info.number == 0
+                    || info.default
+                    || info.active
+                    || info.userdata.get("important").map(String::as_str) == Some("yes")
+                    || info.userdata.contains_key(TRANSACTION_USERDATA)
+            }
+            None => self.is_transaction(),
+        }
+    }
+
+    /// A snapper snapshot with a cleanup algorithm (`number`, `timeline`,
+    /// `empty-pre-post`), i.e. one snapper itself is allowed to delete.
+    pub fn has_cleanup_algorithm(&self) -> bool {
+        self.snapper
+            .as_ref()
+            .is_some_and(|info| !info.cleanup.is_empty())
+    }
+
+    /// A plain snapshot written by `ghostctl` transactions. These belong to
+    /// the transaction journal (`ghostctl rollback`), not to retention.
+    pub fn is_transaction(&self) -> bool {
+        self.snapper.is_none()
+            && TRANSACTION_PREFIXES
+                .iter()
+                .any(|prefix| self.name.starts_with(prefix))
+    }
+}
+
+/// One line of `btrfs subvolume list -s -u -q -R`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubvolumeEntry {
+    pub id: u64,
+    pub otime: Option<NaiveDateTime>,
+    pub parent_uuid: Option<String>,
+    pub received_uuid: Option<String>,
+    pub uuid: Option<String>,
+    /// Path relative to the filesystem top level.
+    pub path: String,
+}
+
+fn uuid_value(value: &str) -> Option<String> {
+    (!value.is_empty() && value != "-").then(|| value.to_string())
+}
+
+pub fn parse_subvolume_list(output: &str) -> Vec<SubvolumeEntry> {
+    let mut entries = Vec::new();
+    for line in output.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.first() != Some(&"ID") {
+            continue;
+        }
+        let mut entry = SubvolumeEntry::default();
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i] {
+                "ID" => {
+                    entry.id = tokens.get(i + 1).and_then(|v| v.parse().ok()).unwrap_or(0);
+                    i += 2;
+                }
+                "otime" => {
+                    if let (Some(date), Some(time)) = (tokens.get(i + 1), tokens.get(i + 2)) {
+                        entry.otime = NaiveDateTime::parse_from_str(
+                            &format!("{date} {time}"),
+                            BTRFS_TIME_FORMAT,
+                        )
+                        .ok();
+                    }
+                    i += 3;
+                }
+                "parent_uuid" => {
+                    entry.parent_uuid = tokens.get(i + 1).and_then(|v| uuid_value(v));
+                    i += 2;
+                }
+                "received_uuid" => {
+                    entry.received_uuid = tokens.get(i + 1).and_then(|v| uuid_value(v));
+                    i += 2;
+                }
+                "uuid" => {
+                    entry.uuid = tokens.get(i + 1).and_then(|v| uuid_value(v));
+                    i += 2;
+                }
+                "path" => {
+                    entry.path = tokens[i + 1..].join(" ");
+                    break;
+                }
+                _ => i += 1,
+            }
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+/// Parse `snapper --jsonout -c <config> list` into (info, date) pairs.
+pub fn parse_snapper_json(
+    config: &str,
+    output: &str,
+) -> Result<Vec<(SnapperInfo, Option<NaiveDateTime>)>> {
+    let value: serde_json::Value =
+        serde_json::from_str(output).context("failed to parse snapper JSON output")?;
+    let Some(items) = value.get(config).and_then(|v| v.as_array()) else {
+        bail!("snapper output has no '{config}' entry");
+    };
+
+    let mut snapshots = Vec::new();
+    for item in items {
+        let Some(number) = item.get("number").and_then(|n| n.as_u64()) else {
+            continue;
+        };
+        let text = |key: &str| {
+            item.get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let flag = |key: &str| item.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        let userdata = item
+            .get("userdata")
+            .and_then(|v| v.as_object())
+            .map(|map| {
+                map.iter()
+                    .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let kind = match text("type") {
+            kind if kind.is_empty() => "single".to_string(),
+            kind => kind,
+        };
+        let date = NaiveDateTime::parse_from_str(&text("date"), BTRFS_TIME_FORMAT).ok();
+
+        snapshots.push((
+            SnapperInfo {
+                config: config.to_string(),
+                number: number as u32,
+                kind,
+                pre_number: item
+                    .get("pre-number")
+                    .and_then(|n| n.as_u64())
+                    .map(|n| n as u32),
+                description: text("description"),
+                cleanup: text("cleanup"),
+                userdata,
+                default: flag("default"),
+                active: flag("active"),
+            },
+            date,
+        ));
+    }
+    Ok(snapshots)
+}
+
+/// Parse `snapper --jsonout list-configs` into (config, subvolume) pairs.
+pub fn parse_snapper_configs(output: &str) -> Vec<(String, String)> {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(output) else {
+        return Vec::new();
+    };
+    value
+        .get("configs")
+        .and_then(|v| v.as_array())
+        .map(|configs| {
+            configs
+                .iter()
+                .filter_map(|c| {
+                    Some((
+                        c.get("config")?.as_str()?.to_string(),
+                        c.get("subvolume")?.as_str()?.to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn run_checked(runner: &dyn CommandRunner, cmd: &str, args: &[&str]) -> Result<String> {
+    let result = runner
+        .run_sudo(cmd, args)
+        .with_context(|| format!("failed to run {cmd}"))?;
+    if !result.success {
+        bail!("{cmd} {} failed: {}", args.join(" "), result.stderr.trim());
+    }
+    Ok(result.stdout)
+}
+
+/// Snapshot and read-only listings for the subvolumes below `dir`.
+fn list_below(
+    runner: &dyn CommandRunner,
+    dir: &str,
+) -> Result<(Vec<SubvolumeEntry>, HashSet<u64>)> {
+    let entries = parse_subvolume_list(&run_checked(
+        runner,
+        "btrfs",
+        &["subvolume", "list", "-o", "-s", "-u", "-q", "-R", dir],
+    )?);
+    let readonly = parse_subvolume_list(&run_checked(
+        runner,
+        "btrfs",
+        &["subvolume", "list", "-o", "-r", dir],
+    )?)
+    .into_iter()
+    .map(|e| e.id)
+    .collect();
+    Ok((entries, readonly))
+}
+
+/// Snapshots stored directly in a plain snapshot directory.
+pub fn scan_dir(runner: &dyn CommandRunner, dir: &str) -> Result<Vec<Snapshot>> {
+    let dir = dir.trim_end_matches('/');
+    let listing = run_checked(runner, "ls", &["-1", dir])?;
+    let names: HashSet<&str> = listing.lines().map(str::trim).collect();
+    let (entries, readonly) = list_below(runner, dir)?;
+
+    let mut snapshots: Vec<Snapshot> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let name = entry.path.rsplit('/').next()?.to_string();
+            if !names.contains(name.as_str()) {
+                return None;
+            }
+            Some(Snapshot {
+                id: entry.id,
+                path: format!("{dir}/{name}"),
+                name,
+                readonly: readonly.contains(&entry.id),
+                uuid: entry.uuid,
+                parent_uuid: entry.parent_uuid,
+                received_uuid: entry.received_uuid,
+                ctime: entry.otime,
+                snapper: None,
+            })
+        })
+        .collect();
+    snapshots.sort_by(|a, b| a.ctime.cmp(&b.ctime).then(a.name.cmp(&b.name)));
+    Ok(snapshots)
+}
+
+/// Snapshots of one snapper config, joined with their btrfs metadata.
+pub fn scan_snapper(
+    runner: &dyn CommandRunner,
+    config: &str,
+    subvolume: &str,
+) -> Result<Vec<Snapshot>> {
+    let json = run_checked(runner, "snapper", &["--jsonout", "-c", config, "list"])?;
+    let infos = parse_snapper_json(config, &json)?;
+    let snapshot_dir = format!("{}/.snapshots", subvolume.trim_end_matches('/'));
+    let (entries, readonly) = list_below(runner, &snapshot_dir)?;
+
+    let mut snapshots = Vec::new();
+    for (info, date) in infos {
+        // Number 0 is snapper's pseudo-snapshot for the live subvolume.
+        if info.number == 0 {
+            continue;
+        }
+        let suffix = format!("{}/snapshot", info.number);
+        let entry = entries
+            .iter()
+            .find(|e| e.path == suffix || e.path.ends_with(&format!("/{suffix}")));
+        snapshots.push(Snapshot {
+            id: entry.map(|e| e.id).unwrap_or(0),
+            path: format!("{snapshot_dir}/{suffix}"),
+            name: format!("{config}#{}", info.number),
+            uuid: entry.and_then(|e| e.uuid.clone()),
+            parent_uuid: entry.and_then(|e| e.parent_uuid.clone()),
+            received_uuid: entry.and_then(|e| e.received_uuid.clone()),
+            ctime: date.or(entry.and_then(|e| e.otime)),
+            readonly: entry.is_some_and(|e| readonly.contains(&e.id)),
+            snapper: Some(info),
+        });
+    }
+    Ok(snapshots)
+}
+
+/// Snapper configs known to this host.
+pub fn snapper_configs(runner: &dyn CommandRunner) -> Vec<(String, String)> {
+    if !runner.command_exists("snapper") {
+        return Vec::new();
+    }
+    runner
+        .run_sudo("snapper", &["--jsonout", "list-configs"])
+        .ok()
+        .filter(|r| r.success)
+        .map(|r| parse_snapper_configs(&r.stdout))
+        .unwrap_or_default()
+}
+
+/// Full inventory: every snapper config plus the configured snapshot dir.
+/// Locations that cannot be read are reported as warnings and skipped.
+pub fn inventory(runner: &dyn CommandRunner, cfg: &BtrfsConfig) -> Vec<Snapshot> {
+    let mut all = Vec::new();
+    for (config, subvolume) in snapper_configs(runner) {
+        match scan_snapper(runner, &config, &subvolume) {
+            Ok(snapshots) => all.extend(snapshots),
+            Err(e) => log::warn!("skipping snapper config '{config}': {e:#}"),
+        }
+    }
+    if runner.file_exists(&cfg.snapshot_dir) {
+        match scan_dir(runner, &cfg.snapshot_dir) {
+            Ok(snapshots) => all.extend(snapshots),
+            Err(e) => log::warn!("skipping {}: {e:#}", cfg.snapshot_dir),
+        }
+    }
+    all
+}
+
+/// Print an inventory as a table.
+pub fn print_inventory(snapshots: &[Snapshot]) {
+    if snapshots.is_empty() {
+        println!("No snapshots found.");
+        return;
+    }
+    println!(
+        "{:<22} {:<19} {:<3} {:<36} DESCRIPTION",
+        "NAME", "CREATED", "RO", "UUID"
+    );
+    for snap in snapshots {
+        let created = snap
+            .ctime
+            .map(|t| t.format(BTRFS_TIME_FORMAT).to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let description = snap
+            .snapper
+            .as_ref()
+            .map(|s| format!("[{}] {}", s.kind, s.description))
+            .unwrap_or_else(|| snap.path.clone());
+        println!(
+            "{:<22} {:<19} {:<3} {:<36} {}",
+            snap.name,
+            created,
+            if snap.readonly { "yes" } else { "no" },
+            snap.uuid.as_deref().unwrap_or("-"),
+            description
+        );
+    }
+}
+
+/// Delete snapshots: snapper-managed ones through snapper (so its metadata
+/// stays consistent), everything else with `btrfs subvolume delete`.
+pub fn delete_snapshots(runner: &dyn CommandRunner, snapshots: &[&Snapshot]) -> Result<()> {
+    let mut by_config: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    let mut plain = Vec::new();
+    for snap in snapshots {
+        match &snap.snapper {
+            Some(info) => by_config
+                .entry(info.config.as_str())
+                .or_default()
+                .push(info.number.to_string()),
+            None => plain.push(snap.path.as_str()),
+        }
+    }
+
+    for (config, numbers) in by_config {
+        let mut args = vec!["-c", config, "delete", "--sync"];
+        args.extend(numbers.iter().map(String::as_str));
+        run_checked(runner, "snapper", &args)?;
+    }
+    if !plain.is_empty() {
+        let mut args = vec!["subvolume", "delete"];
+        args.extend(plain.iter().copied());
+        run_checked(runner, "btrfs", &args)?;
+    }
+    Ok(())
+}
+
+/// Wait until btrfs has freed the space of deleted plain snapshots.
+/// `btrfs subvolume delete` returns before the cleaner reclaims anything;
+/// snapper deletions already wait through `--sync`.
+pub fn sync_deleted(runner: &dyn CommandRunner, snapshots: &[&Snapshot]) -> Result<()> {
+    let dirs: HashSet<String> = snapshots
+        .iter()
+        .filter(|s| s.snapper.is_none())
+        .map(|s| s.group())
+        .collect();
+    for dir in dirs {
+        run_checked(runner, "btrfs", &["subvolume", "sync", &dir])?;
+    }
+    Ok(())
+}
+
+/// Free space on the filesystem holding `path`, as a percentage.
+pub fn free_percent(runner: &dyn CommandRunner, path: &str) -> Option<f64> {
+    let result = runner
+        .run("df", &["--output=size,avail", "-B1", path])
+        .ok()?;
+    if !result.success {
+        return None;
+    }
+    parse_df_free_percent(&result.stdout)
+}
+
+pub fn parse_df_free_percent(output: &str) -> Option<f64> {
+    let line = output.lines().nth(1)?;
+    let mut fields = line.split_whitespace().map(|f| f.parse::<f64>().ok());
+    let size = fields.next()??;
+    let avail = fields.next()??;
+    (size > 0.0).then(|| avail / size * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    const LIST: &str = "\
+ID 260 gen 40 cgen 39 top level 258 otime 2024-06-01 10:00:00 parent_uuid aaaa received_uuid - uuid 1111 path @/.snapshots/1/snapshot
+ID 261 gen 41 cgen 41 top level 258 otime 2024-06-02 10:00:00 parent_uuid aaaa received_uuid - uuid 2222 path @/.snapshots/2/snapshot
+ID 262 gen 42 cgen 42 top level 258 otime 2024-06-03 10:00:00 parent_uuid aaaa received_uuid - uuid 3333 path @/.snapshots/3/snapshot
+";
+
+    const SNAPPER: &str = r#"{
+  "root": [
+    {"subvolume": "/", "number": 0, "default": false, "active": false, "date": "", "cleanup": "", "description": "current", "userdata": null},
+    {"subvolume": "/", "number": 1, "default": true, "active": true, "date": "2024-06-01 10:00:01", "type": "single", "cleanup": "number", "description": "first root filesystem", "userdata": null},
+    {"subvolume": "/", "number": 2, "default": false, "active": false, "date": "2024-06-02 10:00:01", "type": "pre", "cleanup": "number", "description": "pacman -Syu", "userdata": {"important": "no"}},
+    {"subvolume": "/", "number": 3, "default": false, "active": false, "date": "2024-06-03 10:00:01", "type": "pre", "cleanup": "number", "description": "ghostctl: pacman -Syu", "userdata": {"ghostctl_txn": "20240603-100000"}}
+  ]
+}"#;
+
+    #[test]
+    fn test_parse_subvolume_list() {
+        let entries = parse_subvolume_list(LIST);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].id, 260);
+        assert_eq!(entries[0].uuid.as_deref(), Some("1111"));
+        assert_eq!(entries[0].parent_uuid.as_deref(), Some("aaaa"));
+        assert_eq!(entries[0].received_uuid, None);
+        assert_eq!(entries[1].path, "@/.snapshots/2/snapshot");
+        assert_eq!(
+            entries[1].otime.unwrap().format("%Y-%m-%d").to_string(),
+            "2024-06-02"
+        );
+    }
+
+    #[test]
+    fn test_parse_snapper_json() {
+        let infos = parse_snapper_json("root", SNAPPER).unwrap();
+        assert_eq!(infos.len(), 4);
+        assert_eq!(infos[0].0.kind, "single");
+        assert!(infos[1].0.default);
+        assert_eq!(infos[2].0.kind, "pre");
+        assert_eq!(infos[2].0.userdata.get("important").unwrap(), "no");
+        assert!(parse_snapper_json("home", SNAPPER).is_err());
+    }
+
+    #[test]
+    fn test_parse_snapper_configs() {
+        let configs = parse_snapper_configs(
+            r#"{"configs": [{"config": "root", "subvolume": "/"}, {"config": "home", "subvolume": "/home"}]}"#,
+        );
+        assert_eq!(
+            configs,
+            vec![
+                ("root".to_string(), "/".to_string()),
+                ("home".to_string(), "/home".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_snapper_joins_metadata() {
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "snapper",
+            &["--jsonout", "-c", "root", "list"],
+            CommandResult::ok(SNAPPER),
+        );
+        mock.mock_command(
+            "btrfs",
+            &[
+                "subvolume",
+                "list",
+                "-o",
+                "-s",
+                "-u",
+                "-q",
+                "-R",
+                "/.snapshots",
+            ],
+            CommandResult::ok(LIST),
+        );
+        mock.mock_command(
+            "btrfs",
+            &["subvolume", "list", "-o", "-r", "/.snapshots"],
+            CommandResult::ok("ID 260 gen 40 top level 258 path @/.snapshots/1/snapshot\n"),
+        );
+
+        let snapshots = scan_snapper(&mock, "root", "/").unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0].name, "root#1");
+        assert_eq!(snapshots[0].path, "/.snapshots/1/snapshot");
+        assert_eq!(snapshots[0].uuid.as_deref(), Some("1111"));
+        assert!(snapshots[0].readonly);
+        assert!(snapshots[0].is_protected());
+        assert!(!snapshots[1].readonly);
+        assert!(!snapshots[1].is_protected());
+        assert_eq!(snapshots[1].group(), "snapper:root");
+        assert!(snapshots[2].is_protected(), "ghostctl transaction pair");
+        assert!(!snapshots[2].is_transaction());
+    }
+
+    #[test]
+    fn test_scan_dir_only_includes_listed_entries() {
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "ls",
+            &["-1", "/@snapshots"],
+            CommandResult::ok("pre-upgrade\n"),
+        );
+        mock.mock_command(
+            "btrfs",
+            &["subvolume", "list", "-o", "-s", "-u", "-q", "-R", "/@snapshots"],
+            CommandResult::ok(
+                "ID 300 gen 5 cgen 5 top level 5 otime 2024-06-03 09:00:00 parent_uuid p received_uuid - uuid u1 path @snapshots/pre-upgrade\n\
+                 ID 301 gen 6 cgen 6 top level 5 otime 2024-06-03 09:30:00 parent_uuid p received_uuid - uuid u2 path @home\n",
+            ),
+        );
+        mock.mock_command(
+            "btrfs",
+            &["subvolume", "list", "-o", "-r", "/@snapshots"],
+            CommandResult::ok(""),
+        );
+
+        let snapshots = scan_dir(&mock, "/@snapshots/").unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].path, "/@snapshots/pre-upgrade");
+        assert_eq!(snapshots[0].group(), "/@snapshots");
+        assert!(!snapshots[0].readonly);
+    }
+
+    #[test]
+    fn test_delete_routes_snapper_and_plain() {
+        let mock = MockRunner::as_root();
+        let snapper = Snapshot {
+            name: "root#5".to_string(),
+            snapper: Some(SnapperInfo {
+                config: "root".to_string(),
+                number: 5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let plain = Snapshot {
+            name: "old".to_string(),
+            path: "/@snapshots/old".to_string(),
+            ..Default::default()
+        };
+
+        delete_snapshots(&mock, &[&snapper, &plain]).unwrap();
+        assert!(mock.was_called("snapper -c root delete --sync 5"));
+        assert!(mock.was_called("btrfs subvolume delete /@snapshots/old"));
+    }
+
+    #[test]
+    fn test_parse_df_free_percent() {
+        let output = "      1B-blocks         Avail\n 1000 250\n";
+        assert_eq!(parse_df_free_percent(output), Some(25.0));
+        assert_eq!(parse_df_free_percent("header only\n"), None);
+    }
+}
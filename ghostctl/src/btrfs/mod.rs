@@ -1,5 +1,10 @@
+pub mod config;
+pub mod inventory;
 pub mod recovery;
+pub mod replicate;
+pub mod retention;
 pub mod snapshot;
+pub mod transaction;
 
 use crate::tui;
 use crate::utils::{is_headless, sudo_run};
@@ -26,13 +31,24 @@ pub fn handle_btrfs_action(action: crate::BtrfsAction) {
         crate::BtrfsAction::Usage { mountpoint } => show_filesystem_usage(&mountpoint),
         crate::BtrfsAction::Quota { mountpoint } => show_quota_info(&mountpoint),
         crate::BtrfsAction::EmergencyCleanup => snapshot::emergency_cleanup_all_snapshots(),
-        crate::BtrfsAction::CleanupByAge { days } => {
-            snapshot::cleanup_snapshots_by_age(&days);
+        crate::BtrfsAction::CleanupByAge {
+            days,
+            include_manual,
+        } => {
+            snapshot::cleanup_snapshots_by_age(&days, include_manual);
         }
         crate::BtrfsAction::CleanupByRange { range } => {
             snapshot::cleanup_snapshots_by_range(&range);
         }
+        crate::BtrfsAction::CleanupPolicy { plan_only } => snapshot::cleanup_with_policy(plan_only),
         crate::BtrfsAction::DiskSpace => snapshot::check_disk_space(),
+        crate::BtrfsAction::Replicate { target } => {
+            if let Err(e) = replicate::run(target.as_deref()) {
+                tui::error(&format!("Replication failed: {e:#}"));
+                std::process::exit(1);
+            }
+        }
+        crate::BtrfsAction::ReplicateStatus => replicate::print_status(),
     }
 }
 
@@ -387,18 +403,12 @@ pub fn list_snapshots() {
     println!("📋 Listing All Snapshots");
     println!("========================");
 
-    // Try snapper first
-    let output = Command::new("sudo").args(&["snapper", "list"]).output();
-
-    match output {
-        Ok(out) if out.status.success() => {
-            println!("{}", String::from_utf8_lossy(&out.stdout));
-        }
-        _ => {
-            // Fall back to btrfs subvolume list
-            println!("📸 Listing Btrfs subvolumes:");
-            snapshot::list_snapshots();
-        }
+    let cfg = config::BtrfsConfig::load();
+    let snapshots = inventory::inventory(crate::command::runner().as_ref(), &cfg);
+    if snapshots.is_empty() {
+        println!("No snapper or {} snapshots found.", cfg.snapshot_dir);
+    } else {
+        inventory::print_inventory(&snapshots);
     }
 }
 
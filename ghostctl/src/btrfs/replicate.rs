@@ -0,0 +1,814 @@
+//! Native `btrfs send`/`receive` replication.
+//!
+//! btrbk-style workflow: each run takes a read-only snapshot of the configured
+//! subvolume, sends it to the target incrementally (`-p`) against the newest
+//! snapshot both sides still share (the target copy's received UUID must be
+//! the source copy's UUID; a matching name alone is not enough), verifies
+//! that the received UUID on the target matches the source UUID, and then
+//! applies independent retention on the source and the target. The last verified snapshot per target is kept in
+//! the ghostctl state directory so the next run prefers it as the parent.
+
+use super::config::{BtrfsConfig, ReplicationTarget};
+use super::inventory::{SubvolumeEntry, parse_subvolume_list};
+use crate::command::CommandRunner;
+use crate::utils::shell_quote;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// Where snapshots are received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Local {
+        path: String,
+    },
+    Ssh {
+        user: Option<String>,
+        host: String,
+        port: Option<u16>,
+        path: String,
+    },
+}
+
+impl Endpoint {
+    /// Parse a local absolute path or `ssh://[user@]host[:port]/path`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        if let Some(rest) = spec.strip_prefix("ssh://") {
+            let (authority, path) = rest
+                .split_once('/')
+                .with_context(|| format!("ssh target '{spec}' is missing a path"))?;
+            let (user, host_port) = match authority.rsplit_once('@') {
+                Some((user, host_port)) => (Some(user.to_string()), host_port),
+                None => (None, authority),
+            };
+            let (host, port) = match host_port.rsplit_once(':') {
+                Some((host, port)) => {
+                    let port = port
+                        .parse::<u16>()
+                        .with_context(|| format!("invalid ssh port in '{spec}'"))?;
+                    (host.to_string(), Some(port))
+                }
+                None => (host_port.to_string(), None),
+            };
+            if host.is_empty() {
+                bail!("ssh target '{spec}' is missing a host");
+            }
+            Ok(Endpoint::Ssh {
+                user,
+                host,
+                port,
+                path: normalize_path(&format!("/{path}")),
+            })
+        } else if spec.starts_with('/') {
+            Ok(Endpoint::Local {
+                path: normalize_path(spec),
+            })
+        } else {
+            bail!("target '{spec}' must be an absolute path or ssh://[user@]host[:port]/path")
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Endpoint::Local { path } | Endpoint::Ssh { path, .. } => path,
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        matches!(self, Endpoint::Ssh { .. })
+    }
+
+    /// Wrap a command so it runs on the endpoint. The result is meant for
+    /// `run_sudo_shell`, so remote commands are sent over ssh as root's client.
+    pub fn wrap(&self, command: &str, ssh_key: Option<&str>) -> String {
+        match self {
+            Endpoint::Local { .. } => command.to_string(),
+            Endpoint::Ssh {
+                user, host, port, ..
+            } => {
+                let mut ssh = String::from("ssh -o BatchMode=yes");
+                if let Some(port) = port {
+                    ssh.push_str(&format!(" -p {port}"));
+                }
+                if let Some(key) = ssh_key {
+                    ssh.push_str(&format!(" -i {}", shell_quote(key)));
+                }
+                let destination = match user {
+                    Some(user) => format!("{user}@{host}"),
+                    None => host.clone(),
+                };
+                let remote = if user.as_deref() == Some("root") {
+                    command.to_string()
+                } else {
+                    format!("sudo -n {command}")
+                };
+                format!(
+                    "{ssh} {} {}",
+                    shell_quote(&destination),
+                    shell_quote(&remote)
+                )
+            }
+        }
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// Fields of `btrfs subvolume show` that replication and rollback care about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubvolumeShow {
+    pub id: Option<u64>,
+    pub uuid: Option<String>,
+    pub parent_uuid: Option<String>,
+    pub received_uuid: Option<String>,
+    pub readonly: bool,
+}
+
+pub fn parse_subvolume_show(output: &str) -> SubvolumeShow {
+    let mut info = SubvolumeShow::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let uuid = (!value.is_empty() && value != "-").then(|| value.to_string());
+        match key.trim() {
+            "Subvolume ID" => info.id = value.parse().ok(),
+            "UUID" => info.uuid = uuid,
+            "Parent UUID" => info.parent_uuid = uuid,
+            "Received UUID" => info.received_uuid = uuid,
+            "Flags" => info.readonly = value.split_whitespace().any(|f| f == "readonly"),
+            _ => {}
+        }
+    }
+    info
+}
+
+/// Name for a new snapshot, e.g. `nas.20240601T120000`.
+pub fn snapshot_name(prefix: &str, now: chrono::DateTime<chrono::Local>) -> String {
+    format!("{prefix}.{}", now.format(TIMESTAMP_FORMAT))
+}
+
+/// Entries of a directory listing that are snapshots with `prefix`, oldest first.
+pub fn filter_snapshots(listing: &str, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = listing
+        .lines()
+        .map(str::trim)
+        .filter(|name| {
+            name.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('.'))
+                .is_some_and(|ts| {
+                    chrono::NaiveDateTime::parse_from_str(ts, TIMESTAMP_FORMAT).is_ok()
+                })
+        })
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names
+}
+
+/// `name -> column` for the subvolumes in a `btrfs subvolume list -u -R`
+/// listing whose last path component is one of `names`.
+pub fn uuids_by_name(
+    listing: &str,
+    names: &[String],
+    column: impl Fn(&SubvolumeEntry) -> Option<&String>,
+) -> BTreeMap<String, String> {
+    parse_subvolume_list(listing)
+        .iter()
+        .filter_map(|entry| {
+            let name = entry.path.rsplit('/').next()?;
+            let uuid = column(entry)?;
+            names
+                .iter()
+                .any(|n| n == name)
+                .then(|| (name.to_string(), uuid.clone()))
+        })
+        .collect()
+}
+
+/// Pick the incremental parent among the snapshots whose target copy was
+/// received from the source copy (`source` maps names to UUIDs, `target`
+/// to received UUIDs): the recorded last-sent snapshot when it still
+/// qualifies, otherwise the newest one. A same-named snapshot that came from
+/// elsewhere is no parent; `None` means a full send.
+pub fn select_parent(
+    source: &BTreeMap<String, String>,
+    target: &BTreeMap<String, String>,
+    recorded: Option<&str>,
+) -> Option<String> {
+    let shared = |name: &str| {
+        source
+            .get(name)
+            .is_some_and(|uuid| target.get(name) == Some(uuid))
+    };
+    if let Some(recorded) = recorded
+        && shared(recorded)
+    {
+        return Some(recorded.to_string());
+    }
+    source.keys().rev().find(|name| shared(name)).cloned()
+}
+
+/// Snapshots to delete so that only the newest `keep` (at least one) remain.
+/// Names in `protect` are never returned.
+pub fn retention_victims(names: &[String], keep: usize, protect: &[&str]) -> Vec<String> {
+    let mut sorted: Vec<&String> = names.iter().collect();
+    sorted.sort();
+    sorted.dedup();
+    let keep = keep.max(1);
+    let excess = sorted.len().saturating_sub(keep);
+    sorted
+        .into_iter()
+        .take(excess)
+        .filter(|name| !protect.contains(&name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Last verified send per target.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplicationState {
+    #[serde(default)]
+    pub targets: BTreeMap<String, TargetState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TargetState {
+    pub last_snapshot: String,
+    pub uuid: String,
+    pub sent_at: String,
+}
+
+impl ReplicationState {
+    pub fn path() -> PathBuf {
+        crate::support::state_dir().join("btrfs-replicate.json")
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Outcome of one replication run.
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    pub snapshot: String,
+    pub parent: Option<String>,
+    pub pruned_source: Vec<String>,
+    pub pruned_target: Vec<String>,
+}
+
+/// Run a read-only command, failing with its stderr.
+fn query(runner: &dyn CommandRunner, command: &str) -> Result<String> {
+    let result = runner
+        .run_sudo_shell(command)
+        .with_context(|| format!("failed to run `{command}`"))?;
+    if !result.success {
+        bail!("`{command}` failed: {}", result.stderr.trim());
+    }
+    Ok(result.stdout)
+}
+
+/// Run a mutating command, or print it in dry-run mode.
+fn mutate(runner: &dyn CommandRunner, command: &str, dry_run: bool) -> Result<()> {
+    if dry_run {
+        println!("[DRY RUN] Would execute: {command}");
+        return Ok(());
+    }
+    query(runner, command).map(|_| ())
+}
+
+/// Snapshot, send, verify and prune for one target.
+pub fn replicate_target(
+    runner: &dyn CommandRunner,
+    target: &ReplicationTarget,
+    state: &mut ReplicationState,
+    snapshot: &str,
+    dry_run: bool,
+) -> Result<RunReport> {
+    let endpoint = Endpoint::parse(&target.target)?;
+    let key = target.ssh_key.as_deref();
+    let prefix = target.prefix();
+    let source_dir = normalize_path(&target.snapshot_dir);
+    let source_path = join(&source_dir, snapshot);
+    let target_dir = endpoint.path().to_string();
+
+    mutate(
+        runner,
+        &format!("mkdir -p {}", shell_quote(&source_dir)),
+        dry_run,
+    )?;
+    mutate(
+        runner,
+        &format!(
+            "btrfs subvolume snapshot -r {} {}",
+            shell_quote(&target.subvolume),
+            shell_quote(&source_path)
+        ),
+        dry_run,
+    )?;
+
+    mutate(
+        runner,
+        &endpoint.wrap(&format!("mkdir -p {}", shell_quote(&target_dir)), key),
+        dry_run,
+    )?;
+
+    let source_snapshots = filter_snapshots(
+        &query(runner, &format!("ls -1 {}", shell_quote(&source_dir)))?,
+        prefix,
+    );
+    // In dry-run mode the target directory may not exist yet.
+    let target_listing = endpoint.wrap(&format!("ls -1 {}", shell_quote(&target_dir)), key);
+    let target_snapshots = match query(runner, &target_listing) {
+        Ok(listing) => filter_snapshots(&listing, prefix),
+        Err(e) if dry_run => {
+            log::debug!("target listing unavailable in dry-run: {e:#}");
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    let target_received = if target_snapshots.is_empty() {
+        BTreeMap::new()
+    } else {
+        let listing = query(
+            runner,
+            &endpoint.wrap(
+                &format!("btrfs subvolume list -o -u -R {}", shell_quote(&target_dir)),
+                key,
+            ),
+        )?;
+        uuids_by_name(&listing, &target_snapshots, |e| e.received_uuid.as_ref())
+    };
+
+    let recorded = state
+        .targets
+        .get(&target.name)
+        .map(|s| s.last_snapshot.as_str());
+    let candidates: Vec<String> = source_snapshots
+        .iter()
+        .filter(|s| s.as_str() != snapshot && target_snapshots.contains(s))
+        .cloned()
+        .collect();
+    let source_uuids = if candidates.is_empty() {
+        BTreeMap::new()
+    } else {
+        let listing = query(
+            runner,
+            &format!("btrfs subvolume list -o -u -R {}", shell_quote(&source_dir)),
+        )?;
+        uuids_by_name(&listing, &candidates, |e| e.uuid.as_ref())
+    };
+    let parent = select_parent(&source_uuids, &target_received, recorded);
+
+    let receive = endpoint.wrap(&format!("btrfs receive {}", shell_quote(&target_dir)), key);
+    let send = match &parent {
+        Some(parent) => format!(
+            "btrfs send -p {} {} | {receive}",
+            shell_quote(&join(&source_dir, parent)),
+            shell_quote(&source_path)
+        ),
+        None => format!("btrfs send {} | {receive}", shell_quote(&source_path)),
+    };
+    mutate(runner, &send, dry_run)?;
+
+    if !dry_run {
+        let source_info = parse_subvolume_show(&query(
+            runner,
+            &format!("btrfs subvolume show {}", shell_quote(&source_path)),
+        )?);
+        let received_info = parse_subvolume_show(&query(
+            runner,
+            &endpoint.wrap(
+                &format!(
+                    "btrfs subvolume show {}",
+                    shell_quote(&join(&target_dir, snapshot))
+                ),
+                key,
+            ),
+        )?);
+        let Some(uuid) = source_info.uuid else {
+            bail!("could not read UUID of {source_path}");
+        };
+        if received_info.received_uuid.as_deref() != Some(uuid.as_str()) {
+            bail!(
+                "received UUID mismatch for {snapshot}: source {uuid}, target {}",
+                received_info.received_uuid.as_deref().unwrap_or("-")
+            );
+        }
+        state.targets.insert(
+            target.name.clone(),
+            TargetState {
+                last_snapshot: snapshot.to_string(),
+                uuid,
+                sent_at: chrono::Local::now().to_rfc3339(),
+            },
+        );
+    }
+
+    let mut all_source = source_snapshots.clone();
+    all_source.push(snapshot.to_string());
+    let pruned_source = retention_victims(&all_source, target.source_keep, &[snapshot]);
+    if !pruned_source.is_empty() {
+        let paths: Vec<String> = pruned_source
+            .iter()
+            .map(|name| shell_quote(&join(&source_dir, name)))
+            .collect();
+        mutate(
+            runner,
+            &format!("btrfs subvolume delete {}", paths.join(" ")),
+            dry_run,
+        )?;
+    }
+
+    let mut all_target = target_snapshots.clone();
+    all_target.push(snapshot.to_string());
+    let pruned_target = retention_victims(&all_target, target.target_keep, &[snapshot]);
+    if !pruned_target.is_empty() {
+        let paths: Vec<String> = pruned_target
+            .iter()
+            .map(|name| shell_quote(&join(&target_dir, name)))
+            .collect();
+        mutate(
+            runner,
+            &endpoint.wrap(&format!("btrfs subvolume delete {}", paths.join(" ")), key),
+            dry_run,
+        )?;
+    }
+
+    Ok(RunReport {
+        snapshot: snapshot.to_string(),
+        parent,
+        pruned_source,
+        pruned_target,
+    })
+}
+
+/// `ghostctl btrfs replicate [target]`: run one or all configured targets.
+pub fn run(target_name: Option<&str>) -> Result<()> {
+    let cfg = BtrfsConfig::load();
+    if cfg.replicate.is_empty() {
+        bail!(
+            "no replication targets configured - add a [[btrfs.replicate]] entry to {}",
+            crate::config::GhostConfig::config_path().display()
+        );
+    }
+
+    let targets: Vec<&ReplicationTarget> = match target_name {
+        Some(name) => vec![
+            cfg.find_target(name)
+                .with_context(|| format!("unknown replication target '{name}'"))?,
+        ],
+        None => cfg.replicate.iter().collect(),
+    };
+
+    let runner = crate::command::runner();
+    let dry_run = crate::utils::is_dry_run();
+    let mut state = ReplicationState::load();
+    let mut failures = 0usize;
+
+    for target in targets {
+        crate::tui::subheader(&format!(
+            "Replicating '{}' -> {}",
+            target.name, target.target
+        ));
+        let snapshot = snapshot_name(target.prefix(), chrono::Local::now());
+        match replicate_target(runner.as_ref(), target, &mut state, &snapshot, dry_run) {
+            Ok(report) => {
+                match &report.parent {
+                    Some(parent) => {
+                        println!("  sent:   {} (incremental from {parent})", report.snapshot)
+                    }
+                    None => println!("  sent:   {} (full)", report.snapshot),
+                }
+                if !dry_run {
+                    println!("  verify: received UUID matches source");
+                    state.save()?;
+                }
+                for name in &report.pruned_source {
+                    println!("  pruned source: {name}");
+                }
+                for name in &report.pruned_target {
+                    println!("  pruned target: {name}");
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                crate::tui::error(&format!("{}: {e:#}", target.name));
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{failures} replication target(s) failed");
+    }
+    Ok(())
+}
+
+/// `ghostctl btrfs replicate --status`: configured targets and their last send.
+pub fn print_status() {
+    let cfg = BtrfsConfig::load();
+    let state = ReplicationState::load();
+
+    crate::tui::header("Btrfs Replication Targets");
+    if cfg.replicate.is_empty() {
+        println!("No [[btrfs.replicate]] targets configured.");
+        return;
+    }
+    for target in &cfg.replicate {
+        println!("{}", target.name);
+        println!("  source: {} -> {}", target.subvolume, target.snapshot_dir);
+        println!("  target: {}", target.target);
+        println!(
+            "  keep:   {} source / {} target",
+            target.source_keep, target.target_keep
+        );
+        match state.targets.get(&target.name) {
+            Some(last) => println!(
+                "  last:   {} at {} (uuid {})",
+                last.last_snapshot, last.sent_at, last.uuid
+            ),
+            None => println!("  last:   never sent"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    fn names(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn local_target() -> ReplicationTarget {
+        ReplicationTarget {
+            name: "usb".to_string(),
+            subvolume: "/".to_string(),
+            snapshot_dir: "/.snapshots/ghostctl".to_string(),
+            target: "/mnt/backup".to_string(),
+            prefix: None,
+            ssh_key: None,
+            source_keep: 2,
+            target_keep: 3,
+        }
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            Endpoint::parse("/mnt/backup/").unwrap(),
+            Endpoint::Local {
+                path: "/mnt/backup".to_string()
+            }
+        );
+        assert_eq!(
+            Endpoint::parse("ssh://backup@nas.lan:2222/tank/ghost").unwrap(),
+            Endpoint::Ssh {
+                user: Some("backup".to_string()),
+                host: "nas.lan".to_string(),
+                port: Some(2222),
+                path: "/tank/ghost".to_string(),
+            }
+        );
+        assert!(Endpoint::parse("relative/path").is_err());
+        assert!(Endpoint::parse("ssh://nas.lan").is_err());
+    }
+
+    #[test]
+    fn test_wrap_remote_uses_sudo_for_non_root() {
+        let endpoint = Endpoint::parse("ssh://backup@nas/tank").unwrap();
+        assert_eq!(
+            endpoint.wrap("btrfs receive /tank", Some("/root/.ssh/nas")),
+            "ssh -o BatchMode=yes -i /root/.ssh/nas backup@nas 'sudo -n btrfs receive /tank'"
+        );
+        let root = Endpoint::parse("ssh://root@nas/tank").unwrap();
+        assert!(
+            root.wrap("ls -1 /tank", None)
+                .ends_with("root@nas 'ls -1 /tank'")
+        );
+    }
+
+    #[test]
+    fn test_parse_subvolume_show() {
+        let output = "/mnt/backup/usb.20240601T120000\n\
+            \tName: \t\t\tusb.20240601T120000\n\
+            \tUUID: \t\t\t8d3c0b1e-aaaa\n\
+            \tSubvolume ID: \t\t271\n\
+            \tParent UUID: \t\t-\n\
+            \tReceived UUID: \t\t2f1e-bbbb\n\
+            \tFlags: \t\t\treadonly\n";
+        let info = parse_subvolume_show(output);
+        assert_eq!(info.id, Some(271));
+        assert_eq!(info.uuid.as_deref(), Some("8d3c0b1e-aaaa"));
+        assert_eq!(info.parent_uuid, None);
+        assert_eq!(info.received_uuid.as_deref(), Some("2f1e-bbbb"));
+        assert!(info.readonly);
+    }
+
+    #[test]
+    fn test_filter_snapshots_ignores_foreign_entries() {
+        let listing = "usb.20240602T000000\nusb.20240601T000000\nusb.tmp\nother.20240601T000000\n";
+        assert_eq!(
+            filter_snapshots(listing, "usb"),
+            names(&["usb.20240601T000000", "usb.20240602T000000"])
+        );
+    }
+
+    fn uuids(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, uuid)| (name.to_string(), uuid.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_select_parent_prefers_recorded() {
+        let source = uuids(&[("a.1", "u1"), ("a.2", "u2"), ("a.3", "u3")]);
+        let target = uuids(&[("a.1", "u1"), ("a.2", "u2"), ("a.3", "u3")]);
+        assert_eq!(
+            select_parent(&source, &target, Some("a.2")),
+            Some("a.2".to_string())
+        );
+        assert_eq!(
+            select_parent(&source, &target, Some("a.0")),
+            Some("a.3".to_string())
+        );
+        assert_eq!(select_parent(&source, &uuids(&[("b.1", "u1")]), None), None);
+    }
+
+    #[test]
+    fn test_select_parent_requires_matching_received_uuid() {
+        let source = uuids(&[("a.1", "u1"), ("a.2", "u2"), ("a.3", "u3")]);
+        // a.3 on the target has the right name but came from another source.
+        let target = uuids(&[("a.1", "u1"), ("a.2", "u2"), ("a.3", "elsewhere")]);
+        assert_eq!(
+            select_parent(&source, &target, Some("a.3")),
+            Some("a.2".to_string())
+        );
+        assert_eq!(
+            select_parent(&source, &uuids(&[("a.3", "elsewhere")]), None),
+            None
+        );
+
+        let listing = "\
+ID 300 gen 5 top level 5 received_uuid - uuid u1 path @snapshots/a.1
+ID 301 gen 6 top level 5 received_uuid r2 uuid x2 path backup/a.2
+ID 302 gen 7 top level 5 received_uuid r9 uuid x9 path backup/other
+";
+        let wanted = names(&["a.1", "a.2"]);
+        assert_eq!(
+            uuids_by_name(listing, &wanted, |e| e.uuid.as_ref()),
+            uuids(&[("a.1", "u1"), ("a.2", "x2")])
+        );
+        assert_eq!(
+            uuids_by_name(listing, &wanted, |e| e.received_uuid.as_ref()),
+            uuids(&[("a.2", "r2")])
+        );
+    }
+
+    #[test]
+    fn test_retention_victims() {
+        let all = names(&["a.1", "a.2", "a.3", "a.4"]);
+        assert_eq!(retention_victims(&all, 2, &[]), names(&["a.1", "a.2"]));
+        assert_eq!(retention_victims(&all, 2, &["a.1"]), names(&["a.2"]));
+        assert_eq!(retention_victims(&all, 0, &[]).len(), 3);
+        assert!(retention_victims(&all, 10, &[]).is_empty());
+    }
+
+    fn mock_for_incremental(received_uuid: &str) -> MockRunner {
+        let mock = MockRunner::as_root();
+        mock.mock_shell(
+            "ls -1 /.snapshots/ghostctl",
+            CommandResult::ok("usb.20240601T000000\nusb.20240602T000000\nusb.20240603T000000\n"),
+        );
+        mock.mock_shell(
+            "ls -1 /mnt/backup",
+            CommandResult::ok("usb.20240601T000000\nusb.20240602T000000\n"),
+        );
+        mock.mock_shell(
+            "btrfs subvolume list -o -u -R /.snapshots/ghostctl",
+            CommandResult::ok(
+                "ID 260 gen 40 top level 5 received_uuid - uuid s1 path @/.snapshots/ghostctl/usb.20240601T000000\n\
+                 ID 261 gen 41 top level 5 received_uuid - uuid s2 path @/.snapshots/ghostctl/usb.20240602T000000\n",
+            ),
+        );
+        mock.mock_shell(
+            "btrfs subvolume list -o -u -R /mnt/backup",
+            CommandResult::ok(
+                "ID 400 gen 9 top level 5 received_uuid s1 uuid t1 path backup/usb.20240601T000000\n\
+                 ID 401 gen 9 top level 5 received_uuid s2 uuid t2 path backup/usb.20240602T000000\n",
+            ),
+        );
+        mock.mock_shell(
+            "btrfs subvolume show /.snapshots/ghostctl/usb.20240603T000000",
+            CommandResult::ok("\tUUID: \t\tsource-uuid\n\tFlags: \t\treadonly\n"),
+        );
+        mock.mock_shell(
+            "btrfs subvolume show /mnt/backup/usb.20240603T000000",
+            CommandResult::ok(format!(
+                "\tUUID: \t\tother\n\tReceived UUID: \t{received_uuid}\n"
+            )),
+        );
+        mock
+    }
+
+    #[test]
+    fn test_replicate_incremental_and_prune() {
+        let mock = mock_for_incremental("source-uuid");
+        let mut state = ReplicationState::default();
+        state.targets.insert(
+            "usb".to_string(),
+            TargetState {
+                last_snapshot: "usb.20240602T000000".to_string(),
+                uuid: "old".to_string(),
+                sent_at: String::new(),
+            },
+        );
+
+        let report = replicate_target(
+            &mock,
+            &local_target(),
+            &mut state,
+            "usb.20240603T000000",
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(report.parent.as_deref(), Some("usb.20240602T000000"));
+        assert!(mock.was_called(
+            "btrfs send -p /.snapshots/ghostctl/usb.20240602T000000 /.snapshots/ghostctl/usb.20240603T000000 | btrfs receive /mnt/backup"
+        ));
+        assert_eq!(report.pruned_source, names(&["usb.20240601T000000"]));
+        assert!(report.pruned_target.is_empty());
+        assert_eq!(state.targets["usb"].last_snapshot, "usb.20240603T000000");
+        assert_eq!(state.targets["usb"].uuid, "source-uuid");
+    }
+
+    #[test]
+    fn test_replicate_rejects_uuid_mismatch() {
+        let mock = mock_for_incremental("something-else");
+        let mut state = ReplicationState::default();
+
+        let err = replicate_target(
+            &mock,
+            &local_target(),
+            &mut state,
+            "usb.20240603T000000",
+            false,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("received UUID mismatch"));
+        assert!(state.targets.is_empty());
+        assert!(!mock.was_called("btrfs subvolume delete"));
+    }
+
+    #[test]
+    fn test_replicate_dry_run_does_not_mutate() {
+        let mock = mock_for_incremental("source-uuid");
+        let mut state = ReplicationState::default();
+
+        replicate_target(
+            &mock,
+            &local_target(),
+            &mut state,
+            "usb.20240603T000000",
+            true,
+        )
+        .unwrap();
+
+        assert!(!mock.was_called("btrfs send"));
+        assert!(!mock.was_called("btrfs subvolume snapshot"));
+        assert!(state.targets.is_empty());
+    }
+}
@@ -22,7 +22,13 @@ pub fn restore_snapshot() {
             return;
         }
     };
-    let source = format!("/@snapshots/{}", snapshot);
+    let source = format!(
+        "{}/{}",
+        super::config::BtrfsConfig::load()
+            .snapshot_dir
+            .trim_end_matches('/'),
+        snapshot
+    );
     println!("Restoring snapshot '{}' to '{}'...", snapshot, target);
     let status = std::process::Command::new("sudo")
         .args(["btrfs", "subvolume", "snapshot", &source, &target])
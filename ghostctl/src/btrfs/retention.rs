@@ -0,0 +1,696 @@
+//! Declarative snapshot retention.
+//!
+//! A `RetentionPolicy` (`[btrfs.retention]`) keeps the newest `latest`
+//! snapshots plus the first snapshot of each of the most recent N hours,
+//! days, weeks, months and years, per location (snapper config or snapshot
+//! directory). `plan` turns an inventory into keep/delete decisions with the
+//! reasons behind them; `apply` executes the deletions and, when
+//! `min_free_percent` is set, keeps removing the oldest unprotected snapshots
+//! until the filesystem holding each of them has enough free space.
+
+use super::inventory::{self, Snapshot};
+use crate::command::CommandRunner;
+use anyhow::Result;
+use chrono::{Datelike, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Always keep this many of the newest snapshots.
+    #[serde(default = "default_latest")]
+    pub latest: usize,
+    #[serde(default)]
+    pub hourly: usize,
+    #[serde(default = "default_daily")]
+    pub daily: usize,
+    #[serde(default = "default_weekly")]
+    pub weekly: usize,
+    #[serde(default = "default_monthly")]
+    pub monthly: usize,
+    #[serde(default)]
+    pub yearly: usize,
+    /// Delete additional old snapshots while free space is below this.
+    #[serde(default)]
+    pub min_free_percent: Option<f64>,
+}
+
+fn default_latest() -> usize {
+    3
+}
+
+fn default_daily() -> usize {
+    7
+}
+
+fn default_weekly() -> usize {
+    4
+}
+
+fn default_monthly() -> usize {
+    6
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            latest: default_latest(),
+            hourly: 0,
+            daily: default_daily(),
+            weekly: default_weekly(),
+            monthly: default_monthly(),
+            yearly: 0,
+            min_free_percent: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Keep(Vec<&'static str>),
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanEntry {
+    pub snapshot: Snapshot,
+    pub decision: Decision,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPlan {
+    pub entries: Vec<PlanEntry>,
+}
+
+impl RetentionPlan {
+    pub fn deletions(&self) -> Vec<&Snapshot> {
+        self.entries
+            .iter()
+            .filter(|e| e.decision == Decision::Delete)
+            .map(|e| &e.snapshot)
+            .collect()
+    }
+
+    /// Kept snapshots that may still go if free space stays below the
+    /// minimum, oldest first. The newest snapshot of each group always stays,
+    /// and the pre half of a kept pre/post pair is only offered right after
+    /// its post, so a pair is never left with just its post.
+    pub fn low_space_candidates(&self) -> Vec<&Snapshot> {
+        let mut newest: BTreeMap<String, &Snapshot> = BTreeMap::new();
+        for entry in &self.entries {
+            let snap = &entry.snapshot;
+            if snap.ctime.is_some() && !snap.is_transaction() {
+                newest
+                    .entry(snap.group())
+                    .and_modify(|n| {
+                        if snap.ctime > n.ctime {
+                            *n = snap;
+                        }
+                    })
+                    .or_insert(snap);
+            }
+        }
+
+        let mut candidates: Vec<&Snapshot> = self
+            .entries
+            .iter()
+            .filter(|e| !newest.values().any(|n| std::ptr::eq(*n, &e.snapshot)))
+            .filter(|e| match &e.decision {
+                Decision::Keep(reasons) => {
+                    !e.snapshot.is_protected() && !reasons.contains(&"no creation time")
+                }
+                Decision::Delete => false,
+            })
+            .map(|e| &e.snapshot)
+            .collect();
+        candidates.sort_by_key(|s| s.ctime);
+
+        let mut ordered = Vec::new();
+        for &snap in &candidates {
+            if self.kept_post_of(snap).is_some() {
+                continue;
+            }
+            ordered.push(snap);
+            if let Some(info) = &snap.snapper
+                && let Some(pre_number) = info.pre_number
+                && let Some(&pre) = candidates.iter().find(|c| {
+                    c.snapper
+                        .as_ref()
+                        .is_some_and(|p| p.config == info.config && p.number == pre_number)
+                })
+            {
+                ordered.push(pre);
+            }
+        }
+        ordered
+    }
+
+    /// The kept post snapshot whose pre is `snap`, if any.
+    pub fn kept_post_of(&self, snap: &Snapshot) -> Option<&Snapshot> {
+        let info = snap.snapper.as_ref()?;
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.decision, Decision::Keep(_)))
+            .map(|e| &e.snapshot)
+            .find(|post| {
+                post.snapper
+                    .as_ref()
+                    .is_some_and(|p| p.config == info.config && p.pre_number == Some(info.number))
+            })
+    }
+}
+
+type PeriodKey = fn(&NaiveDateTime) -> String;
+
+fn buckets(policy: &RetentionPolicy) -> [(&'static str, usize, PeriodKey); 5] {
+    [
+        ("hourly", policy.hourly, |t| {
+            t.format("%Y-%m-%d %H").to_string()
+        }),
+        ("daily", policy.daily, |t| t.format("%Y-%m-%d").to_string()),
+        ("weekly", policy.weekly, |t| {
+            let week = t.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }),
+        ("monthly", policy.monthly, |t| t.format("%Y-%m").to_string()),
+        ("yearly", policy.yearly, |t| t.format("%Y").to_string()),
+    ]
+}
+
+/// Decide which snapshots to keep. Groups are evaluated independently.
+pub fn plan(snapshots: &[Snapshot], policy: &RetentionPolicy) -> RetentionPlan {
+    let mut groups: BTreeMap<String, Vec<&Snapshot>> = BTreeMap::new();
+    for snap in snapshots {
+        groups.entry(snap.group()).or_default().push(snap);
+    }
+
+    let mut entries = Vec::new();
+    for (_, mut group) in groups {
+        // Newest first; snapshots without a creation time sort last.
+        group.sort_by(|a, b| b.ctime.cmp(&a.ctime).then(b.name.cmp(&a.name)));
+        let mut reasons: Vec<Vec<&'static str>> = vec![Vec::new(); group.len()];
+
+        for (i, snap) in group.iter().enumerate() {
+            if snap.is_protected() {
+                reasons[i].push("protected");
+            }
+            if snap.ctime.is_none() {
+                reasons[i].push("no creation time");
+            }
+            if snap.is_transaction() {
+                reasons[i].push("ghostctl transaction");
+            }
+        }
+
+        // Transaction snapshots belong to `ghostctl rollback` and take no
+        // slot from the policy.
+        let dated: Vec<usize> = (0..group.len())
+            .filter(|&i| group[i].ctime.is_some() && !group[i].is_transaction())
+            .collect();
+        for &i in dated.iter().take(policy.latest) {
+            reasons[i].push("latest");
+        }
+
+        for (label, count, key) in buckets(policy) {
+            if count == 0 {
+                continue;
+            }
+            // Walking newest to oldest, the last index seen for a period is
+            // its first (oldest) snapshot.
+            let mut periods: Vec<(String, usize)> = Vec::new();
+            for &i in &dated {
+                let Some(ctime) = group[i].ctime.as_ref() else {
+                    continue;
+                };
+                let period = key(ctime);
+                match periods.last_mut() {
+                    Some((last, idx)) if *last == period => *idx = i,
+                    _ => periods.push((period, i)),
+                }
+            }
+            for (_, i) in periods.into_iter().take(count) {
+                reasons[i].push(label);
+            }
+        }
+
+        // Keep the pre half of every kept snapper pre/post pair.
+        let kept_pres: HashSet<(String, u32)> = group
+            .iter()
+            .zip(&reasons)
+            .filter(|(_, r)| !r.is_empty())
+            .filter_map(|(s, _)| {
+                let info = s.snapper.as_ref()?;
+                Some((info.config.clone(), info.pre_number?))
+            })
+            .collect();
+        for (i, snap) in group.iter().enumerate() {
+            if let Some(info) = &snap.snapper
+                && reasons[i].is_empty()
+                && kept_pres.contains(&(info.config.clone(), info.number))
+            {
+                reasons[i].push("pre/post pair");
+            }
+        }
+
+        for (snap, reasons) in group.into_iter().zip(reasons) {
+            let decision = if reasons.is_empty() {
+                Decision::Delete
+            } else {
+                Decision::Keep(reasons)
+            };
+            entries.push(PlanEntry {
+                snapshot: snap.clone(),
+                decision,
+            });
+        }
+    }
+
+    RetentionPlan { entries }
+}
+
+pub fn print_plan(plan: &RetentionPlan) {
+    let mut current_group = String::new();
+    for entry in &plan.entries {
+        let group = entry.snapshot.group();
+        if group != current_group {
+            println!("\n{group}");
+            current_group = group;
+        }
+        let created = entry
+            .snapshot
+            .ctime
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        match &entry.decision {
+            Decision::Keep(reasons) => println!(
+                "  keep    {:<24} {created:<16} {}",
+                entry.snapshot.name,
+                reasons.join(", ")
+            ),
+            Decision::Delete => {
+                println!("  DELETE  {:<24} {created:<16}", entry.snapshot.name)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub deleted: Vec<String>,
+    pub low_space_deleted: Vec<String>,
+}
+
+/// Free space per snapshot group, measured on the filesystem holding the
+/// group's snapshots (`df` on a snapshot path).
+pub fn free_space_by_group(
+    runner: &dyn CommandRunner,
+    plan: &RetentionPlan,
+) -> BTreeMap<String, f64> {
+    let mut free = BTreeMap::new();
+    for entry in &plan.entries {
+        let group = entry.snapshot.group();
+        if free.contains_key(&group) {
+            continue;
+        }
+        if let Some(percent) = inventory::free_percent(runner, &entry.snapshot.path) {
+            free.insert(group, percent);
+        }
+    }
+    free
+}
+
+/// Execute a plan, then enforce `min_free_percent` by deleting the oldest
+/// unprotected snapshots one at a time. Each candidate is only deleted while
+/// the filesystem that holds it is below the minimum.
+pub fn apply(
+    runner: &dyn CommandRunner,
+    plan: &RetentionPlan,
+    policy: &RetentionPolicy,
+) -> Result<ApplyReport> {
+    let mut report = ApplyReport::default();
+    let deletions = plan.deletions();
+    if !deletions.is_empty() {
+        inventory::delete_snapshots(runner, &deletions)?;
+        report.deleted = deletions.iter().map(|s| s.name.clone()).collect();
+    }
+
+    if let Some(min_free) = policy.min_free_percent {
+        for snap in plan.low_space_candidates() {
+            // A pre only goes together with its post.
+            if let Some(post) = plan.kept_post_of(snap)
+                && !report.low_space_deleted.contains(&post.name)
+            {
+                continue;
+            }
+            if inventory::free_percent(runner, &snap.path).is_some_and(|free| free < min_free) {
+                inventory::delete_snapshots(runner, &[snap])?;
+                inventory::sync_deleted(runner, &[snap])?;
+                report.low_space_deleted.push(snap.name.clone());
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btrfs::inventory::SnapperInfo;
+    use crate::command::{CommandResult, MockRunner};
+
+    fn at(ts: &str) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M").ok()
+    }
+
+    fn plain(name: &str, ts: &str) -> Snapshot {
+        Snapshot {
+            name: name.to_string(),
+            path: format!("/@snapshots/{name}"),
+            ctime: at(ts),
+            readonly: true,
+            ..Default::default()
+        }
+    }
+
+    fn snapper(number: u32, ts: &str, kind: &str, pre: Option<u32>) -> Snapshot {
+        Snapshot {
+            name: format!("root#{number}"),
+            path: format!("/.snapshots/{number}/snapshot"),
+            ctime: at(ts),
+            snapper: Some(SnapperInfo {
+                config: "root".to_string(),
+                number,
+                kind: kind.to_string(),
+                pre_number: pre,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn only(latest: usize) -> RetentionPolicy {
+        RetentionPolicy {
+            latest,
+            hourly: 0,
+            daily: 0,
+            weekly: 0,
+            monthly: 0,
+            yearly: 0,
+            min_free_percent: None,
+        }
+    }
+
+    fn deleted_names(plan: &RetentionPlan) -> Vec<String> {
+        let mut names: Vec<String> = plan.deletions().iter().map(|s| s.name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_latest_keeps_newest() {
+        let snaps = vec![
+            plain("a", "2024-06-01 10:00"),
+            plain("b", "2024-06-02 10:00"),
+            plain("c", "2024-06-03 10:00"),
+        ];
+        let plan = plan(&snaps, &only(2));
+        assert_eq!(deleted_names(&plan), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_daily_keeps_first_snapshot_of_each_day() {
+        let snaps = vec![
+            plain("d1-early", "2024-06-01 01:00"),
+            plain("d1-late", "2024-06-01 23:00"),
+            plain("d2-early", "2024-06-02 01:00"),
+            plain("d2-late", "2024-06-02 23:00"),
+            plain("d3", "2024-06-03 12:00"),
+        ];
+        let policy = RetentionPolicy {
+            daily: 2,
+            ..only(0)
+        };
+        let plan = plan(&snaps, &policy);
+        assert_eq!(
+            deleted_names(&plan),
+            vec![
+                "d1-early".to_string(),
+                "d1-late".to_string(),
+                "d2-late".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weekly_monthly_yearly_buckets() {
+        let snaps = vec![
+            plain("2023", "2023-12-30 00:00"),
+            plain("may", "2024-05-15 00:00"),
+            plain("jun-w1", "2024-06-03 00:00"),
+            plain("jun-w2", "2024-06-10 00:00"),
+        ];
+        let policy = RetentionPolicy {
+            weekly: 1,
+            monthly: 2,
+            yearly: 2,
+            ..only(0)
+        };
+        let plan = plan(&snaps, &policy);
+        // weekly -> jun-w2, monthly -> jun-w1 + may, yearly -> may + 2023.
+        assert!(plan.deletions().is_empty());
+
+        let policy = RetentionPolicy {
+            weekly: 1,
+            ..only(0)
+        };
+        assert_eq!(
+            deleted_names(&super::plan(&snaps, &policy)),
+            vec!["2023".to_string(), "jun-w1".to_string(), "may".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_protected_and_undated_are_kept() {
+        let mut current = snapper(1, "2024-01-01 00:00", "single", None);
+        if let Some(info) = current.snapper.as_mut() {
+            info.default = true;
+        }
+        let undated = Snapshot {
+            ctime: None,
+            ..plain("mystery", "")
+        };
+        let snaps = vec![current, undated, plain("old", "2024-01-01 00:00")];
+        let plan = plan(&snaps, &only(0));
+        assert_eq!(deleted_names(&plan), vec!["old".to_string()]);
+        assert!(plan.low_space_candidates().is_empty());
+    }
+
+    #[test]
+    fn test_transaction_snapshots_are_never_planned_away() {
+        let mock = MockRunner::as_root();
+        for name in ["old", "ghostctl-txn-1-pre", "ghostctl-rollback-1"] {
+            mock.mock_command(
+                "df",
+                &["--output=size,avail", "-B1", &format!("/@snapshots/{name}")],
+                CommandResult::ok("1B-blocks Avail\n100 5\n"),
+            );
+        }
+        // An unpaired pre (the post was never taken) and the writable root a
+        // rollback made the default subvolume, both older than everything.
+        let snaps = vec![
+            plain("ghostctl-txn-1-pre", "2024-05-01 10:00"),
+            Snapshot {
+                readonly: false,
+                ..plain("ghostctl-rollback-1", "2024-05-02 10:00")
+            },
+            plain("old", "2024-06-01 10:00"),
+            plain("new", "2024-06-02 10:00"),
+        ];
+        let policy = RetentionPolicy {
+            min_free_percent: Some(10.0),
+            ..only(1)
+        };
+        let plan = plan(&snaps, &policy);
+        assert_eq!(deleted_names(&plan), vec!["old".to_string()]);
+        assert!(plan.low_space_candidates().is_empty());
+
+        let report = apply(&mock, &plan, &policy).unwrap();
+        assert!(report.low_space_deleted.is_empty());
+        assert!(!mock.was_called("btrfs subvolume delete /@snapshots/ghostctl-txn-1-pre"));
+        assert!(!mock.was_called("btrfs subvolume delete /@snapshots/ghostctl-rollback-1"));
+    }
+
+    #[test]
+    fn test_groups_are_independent_and_pairs_kept() {
+        let snaps = vec![
+            snapper(10, "2024-06-01 10:00", "pre", None),
+            snapper(11, "2024-06-01 10:05", "post", Some(10)),
+            snapper(12, "2024-05-01 10:00", "single", None),
+            plain("manual", "2024-01-01 00:00"),
+        ];
+        let plan = plan(&snaps, &only(1));
+        // Latest in root keeps #11, which pulls in its pre #10; the plain dir
+        // keeps its own latest.
+        assert_eq!(deleted_names(&plan), vec!["root#12".to_string()]);
+    }
+
+    #[test]
+    fn test_apply_deletes_and_enforces_free_space() {
+        let mock = MockRunner::as_root();
+        for name in ["a", "b", "c"] {
+            mock.mock_command(
+                "df",
+                &["--output=size,avail", "-B1", &format!("/@snapshots/{name}")],
+                CommandResult::ok("1B-blocks Avail\n100 5\n"),
+            );
+        }
+        let snaps = vec![
+            plain("a", "2024-06-01 10:00"),
+            plain("b", "2024-06-02 10:00"),
+            plain("c", "2024-06-03 10:00"),
+        ];
+        let policy = RetentionPolicy {
+            min_free_percent: Some(10.0),
+            ..only(2)
+        };
+        let plan = plan(&snaps, &policy);
+
+        let report = apply(&mock, &plan, &policy).unwrap();
+        assert_eq!(report.deleted, vec!["a".to_string()]);
+        // Free space never recovers in the mock, so everything but the
+        // newest snapshot goes.
+        assert_eq!(report.low_space_deleted, vec!["b".to_string()]);
+        assert!(mock.was_called("btrfs subvolume delete /@snapshots/a"));
+    }
+
+    #[test]
+    fn test_low_space_stops_once_the_cleaner_frees_enough() {
+        let mock = MockRunner::as_root();
+        // One shared filesystem: full when `a` is measured, and freed by the
+        // time `b` is measured, after `a` was deleted and synced.
+        let df = |name: &str, avail: u32| {
+            mock.mock_command(
+                "df",
+                &["--output=size,avail", "-B1", &format!("/@snapshots/{name}")],
+                CommandResult::ok(format!("1B-blocks Avail\n100 {avail}\n")),
+            );
+        };
+        df("a", 5);
+        df("b", 50);
+        df("c", 50);
+        let snaps = vec![
+            plain("a", "2024-06-01 10:00"),
+            plain("b", "2024-06-02 10:00"),
+            plain("c", "2024-06-03 10:00"),
+            plain("d", "2024-06-04 10:00"),
+        ];
+        let policy = RetentionPolicy {
+            min_free_percent: Some(10.0),
+            ..only(4)
+        };
+        let plan = plan(&snaps, &policy);
+        let report = apply(&mock, &plan, &policy).unwrap();
+
+        assert_eq!(report.low_space_deleted, vec!["a".to_string()]);
+        let history = mock.get_history();
+        let position = |cmd: &str| history.iter().position(|h| h == cmd).unwrap();
+        assert!(
+            position("btrfs subvolume delete /@snapshots/a")
+                < position("btrfs subvolume sync /@snapshots")
+        );
+        assert!(
+            position("btrfs subvolume sync /@snapshots")
+                < position("df --output=size,avail -B1 /@snapshots/b")
+        );
+        assert!(!mock.was_called("btrfs subvolume delete /@snapshots/b"));
+    }
+
+    #[test]
+    fn test_low_space_never_deletes_a_pre_before_its_post() {
+        let mock = MockRunner::as_root();
+        for number in 1..=4 {
+            mock.mock_command(
+                "df",
+                &[
+                    "--output=size,avail",
+                    "-B1",
+                    &format!("/.snapshots/{number}/snapshot"),
+                ],
+                CommandResult::ok("1B-blocks Avail\n100 5\n"),
+            );
+        }
+        let policy = RetentionPolicy {
+            min_free_percent: Some(10.0),
+            ..only(2)
+        };
+
+        // The pre is the oldest snapshot; its post is kept as one of the two
+        // latest but is not the newest.
+        let snaps = vec![
+            snapper(1, "2024-06-01 10:00", "pre", None),
+            snapper(3, "2024-06-02 10:00", "single", None),
+            snapper(2, "2024-06-03 10:00", "post", Some(1)),
+            snapper(4, "2024-06-04 10:00", "single", None),
+        ];
+        let plan = plan(&snaps, &policy);
+        assert_eq!(deleted_names(&plan), vec!["root#3".to_string()]);
+        let order: Vec<&str> = plan
+            .low_space_candidates()
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(order, vec!["root#2", "root#1"]);
+        let report = apply(&mock, &plan, &policy).unwrap();
+        assert_eq!(
+            report.low_space_deleted,
+            vec!["root#2".to_string(), "root#1".to_string()]
+        );
+
+        // When the post is the newest snapshot it stays, and so does its pre.
+        let snaps = vec![
+            snapper(1, "2024-06-01 10:00", "pre", None),
+            snapper(3, "2024-06-02 10:00", "single", None),
+            snapper(2, "2024-06-03 10:00", "post", Some(1)),
+        ];
+        let plan = super::plan(&snaps, &only(1));
+        assert!(plan.low_space_candidates().is_empty());
+    }
+
+    #[test]
+    fn test_free_space_is_measured_where_each_snapshot_lives() {
+        let mock = MockRunner::as_root();
+        // `/` is nearly full, the data filesystem is not.
+        mock.mock_command(
+            "df",
+            &["--output=size,avail", "-B1", "/.snapshots/1/snapshot"],
+            CommandResult::ok("1B-blocks Avail\n100 5\n"),
+        );
+        mock.mock_command(
+            "df",
+            &["--output=size,avail", "-B1", "/data/.snapshots/old"],
+            CommandResult::ok("1B-blocks Avail\n100 60\n"),
+        );
+        let data = |name: &str, ts: &str| Snapshot {
+            path: format!("/data/.snapshots/{name}"),
+            ..plain(name, ts)
+        };
+        let snaps = vec![
+            snapper(1, "2024-06-01 10:00", "single", None),
+            snapper(2, "2024-06-02 10:00", "single", None),
+            data("old", "2024-06-01 09:00"),
+            data("new", "2024-06-02 09:00"),
+        ];
+        let policy = RetentionPolicy {
+            min_free_percent: Some(10.0),
+            ..only(2)
+        };
+        let plan = plan(&snaps, &policy);
+        let free = free_space_by_group(&mock, &plan);
+        assert_eq!(free.get("snapper:root"), Some(&5.0));
+        assert_eq!(free.get("/data/.snapshots"), Some(&60.0));
+
+        let report = apply(&mock, &plan, &policy).unwrap();
+        assert_eq!(report.low_space_deleted, vec!["root#1".to_string()]);
+        assert!(!mock.was_called("btrfs subvolume delete /data/.snapshots/old"));
+    }
+}
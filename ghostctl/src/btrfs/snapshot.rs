@@ -1,6 +1,19 @@
+use super::config::BtrfsConfig;
+use super::inventory::{self, Snapshot};
+use super::retention;
+use crate::tui;
+
+fn snapshot_path(name: &str) -> String {
+    format!(
+        "{}/{}",
+        BtrfsConfig::load().snapshot_dir.trim_end_matches('/'),
+        name
+    )
+}
+
 pub fn create_snapshot(subvolume: &str, name: &str) {
     println!("Creating snapshot: {}", name);
-    let target = format!("/@snapshots/{}", name);
+    let target = snapshot_path(name);
     let status = std::process::Command::new("sudo")
         .args(["btrfs", "subvolume", "snapshot", subvolume, &target])
         .status();
@@ -11,19 +24,17 @@ pub fn create_snapshot(subvolume: &str, name: &str) {
 }
 
 pub fn list_snapshots() {
-    println!("Listing Btrfs snapshots:");
-    let output = std::process::Command::new("sudo")
-        .args(["btrfs", "subvolume", "list", "/@snapshots"])
-        .output();
-    match output {
-        Ok(out) => println!("{}", String::from_utf8_lossy(&out.stdout)),
-        Err(_) => println!("Failed to list snapshots."),
+    let cfg = BtrfsConfig::load();
+    println!("Listing Btrfs snapshots in {}:", cfg.snapshot_dir);
+    match inventory::scan_dir(crate::command::runner().as_ref(), &cfg.snapshot_dir) {
+        Ok(snapshots) => inventory::print_inventory(&snapshots),
+        Err(e) => println!("Failed to list snapshots: {e:#}"),
     }
 }
 
 pub fn delete_snapshot(name: &str) {
     use dialoguer::Confirm;
-    let target = format!("/@snapshots/{}", name);
+    let target = snapshot_path(name);
     let confirmed = match Confirm::new()
         .with_prompt(format!("Delete snapshot '{}'?", name))
         .default(false)
@@ -57,7 +68,7 @@ pub fn restore_snapshot(name: &str, target: &str) {
         _ => false,
     };
     if confirmed {
-        let source = format!("/@snapshots/{}", name);
+        let source = snapshot_path(name);
         let status = std::process::Command::new("sudo")
             .args(["btrfs", "subvolume", "snapshot", &source, target])
             .status();
@@ -291,43 +302,189 @@ pub fn bulk_cleanup_snapshots() {
     }
 }
 
-pub fn cleanup_snapshots_by_age(days: &str) {
-    println!("🗓️  Deleting snapshots older than {} days...", days);
+/// Unprotected snapshots created before `cutoff`. Only snapshots a snapper
+/// cleanup algorithm already manages qualify, unless `include_manual` also
+/// lets in the hand-made ones (snapper snapshots without an algorithm and
+/// the plain snapshots in `btrfs.snapshot_dir`).
+fn age_victims(
+    snapshots: &[Snapshot],
+    cutoff: chrono::NaiveDateTime,
+    include_manual: bool,
+) -> Vec<&Snapshot> {
+    snapshots
+        .iter()
+        .filter(|s| {
+            !s.is_protected()
+                && (include_manual || s.has_cleanup_algorithm())
+                && s.ctime.is_some_and(|t| t < cutoff)
+        })
+        .collect()
+}
 
-    let status = std::process::Command::new("sudo")
-        .args(["snapper", "-c", "root", "delete", "--sync"])
-        .arg(format!("--older-than={}", days))
-        .status();
+pub fn cleanup_snapshots_by_age(days: &str, include_manual: bool) {
+    let Ok(days) = days.trim().parse::<i64>() else {
+        println!("❌ Invalid number of days: {}", days);
+        return;
+    };
+    println!("🗓️  Deleting snapshots older than {} days...", days);
 
-    match status {
-        Ok(s) if s.success() => println!("✅ Age-based cleanup completed"),
-        _ => println!("❌ Age-based cleanup failed"),
+    let runner = crate::command::runner();
+    let cutoff = chrono::Local::now().naive_local() - chrono::Duration::days(days);
+    let snapshots = inventory::inventory(runner.as_ref(), &BtrfsConfig::load());
+    let victims = age_victims(&snapshots, cutoff, include_manual);
+    if !include_manual {
+        let manual = age_victims(&snapshots, cutoff, true).len() - victims.len();
+        if manual > 0 {
+            println!(
+                "ℹ️  Skipping {} manual snapshot(s); pass --include-manual to delete them too",
+                manual
+            );
+        }
     }
+
+    confirm_and_delete(runner.as_ref(), &victims, "Age-based cleanup");
 }
 
 pub fn cleanup_snapshots_by_range(range: &str) {
     println!("🔢 Deleting snapshot range {}...", range);
 
-    let confirmed = match dialoguer::Confirm::new()
-        .with_prompt(format!("Delete snapshots {}?", range))
-        .default(false)
-        .interact_opt()
-    {
-        Ok(Some(c)) => c,
-        _ => false,
+    let Some((start, end)) = parse_range(range) else {
+        println!("❌ Invalid range '{}' (expected e.g. 1-100)", range);
+        return;
     };
-    if !confirmed {
-        println!("❌ Range cleanup aborted");
+
+    let runner = crate::command::runner();
+    let snapshots = match inventory::scan_snapper(runner.as_ref(), "root", "/") {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            println!("❌ Failed to read snapper snapshots: {e:#}");
+            return;
+        }
+    };
+    let victims: Vec<&Snapshot> = snapshots
+        .iter()
+        .filter(|s| {
+            !s.is_protected()
+                && s.snapper
+                    .as_ref()
+                    .is_some_and(|i| (start..=end).contains(&i.number))
+        })
+        .collect();
+
+    confirm_and_delete(runner.as_ref(), &victims, "Range cleanup");
+}
+
+/// Parse an inclusive snapper number range such as `1-100`.
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let (start, end) = range.trim().split_once('-')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end).then_some((start, end))
+}
+
+fn confirm_and_delete(
+    runner: &dyn crate::command::CommandRunner,
+    victims: &[&Snapshot],
+    label: &str,
+) {
+    if victims.is_empty() {
+        println!("✅ No snapshots matched");
         return;
     }
 
-    let status = std::process::Command::new("sudo")
-        .args(["snapper", "-c", "root", "delete", range])
-        .status();
+    println!("The following snapshots will be deleted:");
+    for snap in victims {
+        let created = snap
+            .ctime
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!("  {:<24} {}", snap.name, created);
+    }
 
-    match status {
-        Ok(s) if s.success() => println!("✅ Range cleanup completed"),
-        _ => println!("❌ Range cleanup failed"),
+    if crate::utils::is_dry_run() {
+        println!("[DRY RUN] Would delete {} snapshot(s)", victims.len());
+        return;
+    }
+    if !tui::is_auto_yes()
+        && !tui::confirm(&format!("Delete {} snapshot(s)?", victims.len()), false)
+    {
+        println!("❌ {} aborted", label);
+        return;
+    }
+
+    match inventory::delete_snapshots(runner, victims) {
+        Ok(()) => println!("✅ {} completed", label),
+        Err(e) => println!("❌ {} failed: {e:#}", label),
+    }
+}
+
+/// `ghostctl btrfs cleanup`: show the retention plan, then apply it.
+pub fn cleanup_with_policy(plan_only: bool) {
+    let cfg = BtrfsConfig::load();
+    let runner = crate::command::runner();
+
+    tui::header("Snapshot Retention Plan");
+    let policy = &cfg.retention;
+    println!(
+        "Policy: latest={} hourly={} daily={} weekly={} monthly={} yearly={}",
+        policy.latest, policy.hourly, policy.daily, policy.weekly, policy.monthly, policy.yearly
+    );
+
+    let snapshots = inventory::inventory(runner.as_ref(), &cfg);
+    if snapshots.is_empty() {
+        println!("No snapshots found.");
+        return;
+    }
+    let plan = retention::plan(&snapshots, policy);
+    retention::print_plan(&plan);
+
+    let free = retention::free_space_by_group(runner.as_ref(), &plan);
+    if !free.is_empty() {
+        println!();
+    }
+    let mut low_space = false;
+    for (group, free) in &free {
+        match policy.min_free_percent {
+            Some(min) => {
+                println!("Free space for {group}: {free:.1}% (minimum {min:.1}%)");
+                low_space |= *free < min;
+            }
+            None => println!("Free space for {group}: {free:.1}%"),
+        }
+    }
+    if low_space {
+        println!("⚠️  Below minimum: oldest kept snapshots will also be removed until it is met");
+    }
+
+    let deletions = plan.deletions();
+    println!(
+        "\n{} of {} snapshot(s) would be deleted",
+        deletions.len(),
+        plan.entries.len()
+    );
+    if plan_only || (deletions.is_empty() && !low_space) {
+        return;
+    }
+    if crate::utils::is_dry_run() {
+        println!("[DRY RUN] No snapshots deleted");
+        return;
+    }
+    if !tui::is_auto_yes() && !tui::confirm("Apply this plan?", false) {
+        println!("❌ Cleanup aborted");
+        return;
+    }
+
+    match retention::apply(runner.as_ref(), &plan, policy) {
+        Ok(report) => {
+            println!("✅ Deleted {} snapshot(s)", report.deleted.len());
+            if !report.low_space_deleted.is_empty() {
+                println!(
+                    "🧹 Removed {} more for free space: {}",
+                    report.low_space_deleted.len(),
+                    report.low_space_deleted.join(", ")
+                );
+            }
+        }
+        Err(e) => println!("❌ Cleanup failed: {e:#}"),
     }
 }
 
@@ -341,8 +498,16 @@ fn cleanup_by_age_interactive() {
         Ok(d) => d,
         Err(_) => return,
     };
+    let include_manual = match dialoguer::Confirm::new()
+        .with_prompt("Include manual snapshots (no snapper cleanup algorithm)?")
+        .default(false)
+        .interact_opt()
+    {
+        Ok(Some(c)) => c,
+        _ => false,
+    };
 
-    cleanup_snapshots_by_age(&days);
+    cleanup_snapshots_by_age(&days, include_manual);
 }
 
 fn cleanup_by_range_interactive() {
@@ -472,3 +637,59 @@ pub fn check_disk_space() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_age_cleanup_skips_manual_snapshots_unless_asked() {
+        let at = |day: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 6, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+        };
+        let snapper = |name: &str, cleanup: &str| Snapshot {
+            name: name.to_string(),
+            ctime: at(1),
+            snapper: Some(inventory::SnapperInfo {
+                config: "root".to_string(),
+                number: 5,
+                cleanup: cleanup.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let snapshots = vec![
+            snapper("root#5", "number"),
+            snapper("root#6", ""),
+            Snapshot {
+                name: "before-upgrade".to_string(),
+                ctime: at(1),
+                ..Default::default()
+            },
+            Snapshot {
+                name: "recent".to_string(),
+                ctime: at(20),
+                ..Default::default()
+            },
+        ];
+        let cutoff = at(10).unwrap();
+        let names = |include_manual| {
+            age_victims(&snapshots, cutoff, include_manual)
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(false), ["root#5"]);
+        assert_eq!(names(true), ["root#5", "root#6", "before-upgrade"]);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("1-100"), Some((1, 100)));
+        assert_eq!(parse_range(" 5 - 7 "), Some((5, 7)));
+        assert_eq!(parse_range("10-1"), None);
+        assert_eq!(parse_range("abc"), None);
+    }
+}
@@ -0,0 +1,832 @@
+//! Pre/post snapshots around ghostctl's own system-mutating operations.
+//!
+//! Risky operations run inside [`run`]: when `/` is btrfs, a labeled
+//! pre-snapshot is taken first (through snapper when a config manages `/`,
+//! otherwise as a read-only snapshot in `btrfs.snapshot_dir`), the operation
+//! runs, and a post-snapshot closes the transaction. Every transaction is
+//! journaled with the exact ghostctl command line so `ghostctl rollback <id>`
+//! can undo it later. Callers open the transaction after any confirmation
+//! prompts, around the change itself, so the pre/post pair brackets only
+//! what ghostctl wrote.
+
+use super::config::BtrfsConfig;
+use super::inventory;
+use super::replicate::parse_subvolume_show;
+use crate::command::CommandRunner;
+use crate::tui;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use clap::{Arg, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Journal entries kept before the oldest are dropped.
+const MAX_JOURNAL_ENTRIES: usize = 200;
+
+/// Set while a transaction is open so nested wrapped calls do not snapshot again.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Holds [`ACTIVE`] for the wrapped operation and clears it on drop, so an
+/// operation that panics does not leave every later transaction skipped.
+struct ActiveGuard;
+
+impl ActiveGuard {
+    fn enter() -> Self {
+        ACTIVE.store(true, Ordering::SeqCst);
+        ActiveGuard
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Where the pre/post snapshots of a transaction live.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum Backend {
+    Snapper {
+        config: String,
+        pre: u32,
+        post: Option<u32>,
+    },
+    Btrfs {
+        pre: String,
+        post: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Running,
+    Succeeded,
+    Failed,
+    RolledBack,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Outcome::Running => "running",
+            Outcome::Succeeded => "succeeded",
+            Outcome::Failed => "failed",
+            Outcome::RolledBack => "rolled back",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: String,
+    pub label: String,
+    pub command: String,
+    pub started_at: String,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    pub outcome: Outcome,
+    #[serde(flatten)]
+    pub backend: Backend,
+}
+
+impl Transaction {
+    fn description(&self) -> String {
+        format!("ghostctl: {}", self.label)
+    }
+}
+
+/// Transaction journal stored in the ghostctl state directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+}
+
+impl Journal {
+    fn path() -> PathBuf {
+        crate::support::state_dir().join("transactions.json")
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Insert or replace a transaction, trimming the oldest entries.
+    pub fn record(&mut self, txn: &Transaction) {
+        match self.transactions.iter_mut().find(|t| t.id == txn.id) {
+            Some(existing) => *existing = txn.clone(),
+            None => self.transactions.push(txn.clone()),
+        }
+        if self.transactions.len() > MAX_JOURNAL_ENTRIES {
+            let excess = self.transactions.len() - MAX_JOURNAL_ENTRIES;
+            self.transactions.drain(..excess);
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Transaction> {
+        self.transactions.iter().find(|t| t.id == id)
+    }
+}
+
+/// Whether a wrapped operation succeeded, for the journal.
+pub trait TxnResult {
+    fn succeeded(&self) -> bool;
+}
+
+impl TxnResult for () {
+    fn succeeded(&self) -> bool {
+        true
+    }
+}
+
+impl TxnResult for bool {
+    fn succeeded(&self) -> bool {
+        *self
+    }
+}
+
+impl<T, E> TxnResult for std::result::Result<T, E> {
+    fn succeeded(&self) -> bool {
+        self.is_ok()
+    }
+}
+
+/// Run `f` inside a transaction labeled `label`.
+///
+/// Snapshots are skipped in dry-run mode, when `/` is not btrfs, and for
+/// calls nested inside another transaction. A failed pre-snapshot is
+/// reported but does not block the operation.
+pub fn run<T: TxnResult>(label: &str, f: impl FnOnce() -> T) -> T {
+    if crate::utils::is_dry_run() || ACTIVE.load(Ordering::SeqCst) {
+        return f();
+    }
+
+    let runner = crate::command::runner();
+    let cfg = BtrfsConfig::load();
+    let mut journal = Journal::load();
+    let started = begin(
+        runner.as_ref(),
+        &cfg,
+        &journal,
+        label,
+        &command_line(),
+        Local::now(),
+    );
+    let mut txn = match started {
+        Ok(Some(txn)) => txn,
+        Ok(None) => return f(),
+        Err(e) => {
+            tui::warn(&format!(
+                "Pre-snapshot failed, continuing without a rollback point: {e:#}"
+            ));
+            return f();
+        }
+    };
+    tui::info(&format!("📸 Transaction {}: pre-snapshot taken", txn.id));
+    journal.record(&txn);
+    if let Err(e) = journal.save() {
+        tui::warn(&format!("Could not write transaction journal: {e:#}"));
+    }
+
+    let result = {
+        let _active = ActiveGuard::enter();
+        f()
+    };
+
+    if let Err(e) = finish(
+        runner.as_ref(),
+        &cfg,
+        &mut txn,
+        result.succeeded(),
+        Local::now(),
+    ) {
+        tui::warn(&format!("Post-snapshot failed: {e:#}"));
+    }
+    journal.record(&txn);
+    if let Err(e) = journal.save() {
+        tui::warn(&format!("Could not write transaction journal: {e:#}"));
+    }
+    tui::info(&format!("↩️  Undo with: ghostctl rollback {}", txn.id));
+    result
+}
+
+/// The ghostctl invocation being recorded, shell-quoted.
+fn command_line() -> String {
+    std::env::args()
+        .map(|arg| crate::utils::shell_quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn run_checked(runner: &dyn CommandRunner, cmd: &str, args: &[&str]) -> Result<String> {
+    let result = runner
+        .run_sudo(cmd, args)
+        .with_context(|| format!("failed to run {cmd}"))?;
+    if !result.success {
+        bail!("{cmd} {} failed: {}", args.join(" "), result.stderr.trim());
+    }
+    Ok(result.stdout)
+}
+
+fn root_is_btrfs(runner: &dyn CommandRunner) -> bool {
+    runner
+        .run("findmnt", &["-n", "-o", "FSTYPE", "/"])
+        .map(|r| r.success && r.stdout.trim() == "btrfs")
+        .unwrap_or(false)
+}
+
+/// Snapper config managing `/`, if any.
+fn root_snapper_config(runner: &dyn CommandRunner) -> Option<String> {
+    inventory::snapper_configs(runner)
+        .into_iter()
+        .find(|(_, subvolume)| subvolume == "/")
+        .map(|(config, _)| config)
+}
+
+fn snapper_create(
+    runner: &dyn CommandRunner,
+    config: &str,
+    txn: &Transaction,
+    pre_number: Option<u32>,
+) -> Result<u32> {
+    let description = txn.description();
+    let userdata = format!("{}={}", inventory::TRANSACTION_USERDATA, txn.id);
+    let pre = pre_number.map(|n| n.to_string());
+    let mut args = vec!["-c", config, "create"];
+    match &pre {
+        Some(pre) => args.extend(["--type", "post", "--pre-number", pre.as_str()]),
+        None => args.extend(["--type", "pre"]),
+    }
+    args.extend([
+        "--print-number",
+        "--cleanup-algorithm",
+        "number",
+        "--description",
+        &description,
+        "--userdata",
+        &userdata,
+    ]);
+    let out = run_checked(runner, "snapper", &args)?;
+    out.trim()
+        .parse()
+        .with_context(|| format!("unexpected snapper output: {}", out.trim()))
+}
+
+fn btrfs_snapshot_path(cfg: &BtrfsConfig, id: &str, phase: &str) -> String {
+    format!(
+        "{}/ghostctl-txn-{id}-{phase}",
+        cfg.snapshot_dir.trim_end_matches('/')
+    )
+}
+
+/// Timestamp id, suffixed `-2`, `-3`, ... while the journal or the
+/// snapshot directory already has it.
+fn unique_id(
+    runner: &dyn CommandRunner,
+    cfg: &BtrfsConfig,
+    journal: &Journal,
+    now: DateTime<Local>,
+) -> String {
+    let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+    let taken = |id: &str| {
+        journal.find(id).is_some() || runner.file_exists(&btrfs_snapshot_path(cfg, id, "pre"))
+    };
+    let mut id = stamp.clone();
+    let mut n = 2;
+    while taken(&id) {
+        id = format!("{stamp}-{n}");
+        n += 1;
+    }
+    id
+}
+
+/// Take the pre-snapshot. Returns `None` when `/` is not btrfs.
+pub fn begin(
+    runner: &dyn CommandRunner,
+    cfg: &BtrfsConfig,
+    journal: &Journal,
+    label: &str,
+    command: &str,
+    now: DateTime<Local>,
+) -> Result<Option<Transaction>> {
+    if !root_is_btrfs(runner) {
+        return Ok(None);
+    }
+
+    let id = unique_id(runner, cfg, journal, now);
+    let mut txn = Transaction {
+        id: id.clone(),
+        label: label.to_string(),
+        command: command.to_string(),
+        started_at: now.to_rfc3339(),
+        finished_at: None,
+        outcome: Outcome::Running,
+        backend: Backend::Btrfs {
+            pre: btrfs_snapshot_path(cfg, &id, "pre"),
+            post: None,
+        },
+    };
+
+    if let Some(config) = root_snapper_config(runner) {
+        let pre = snapper_create(runner, &config, &txn, None)?;
+        txn.backend = Backend::Snapper {
+            config,
+            pre,
+            post: None,
+        };
+    } else if let Backend::Btrfs { pre, .. } = &txn.backend {
+        run_checked(runner, "btrfs", &["subvolume", "snapshot", "-r", "/", pre])?;
+    }
+    Ok(Some(txn))
+}
+
+/// Take the post-snapshot and record the outcome.
+pub fn finish(
+    runner: &dyn CommandRunner,
+    cfg: &BtrfsConfig,
+    txn: &mut Transaction,
+    succeeded: bool,
+    now: DateTime<Local>,
+) -> Result<()> {
+    txn.finished_at = Some(now.to_rfc3339());
+    txn.outcome = if succeeded {
+        Outcome::Succeeded
+    } else {
+        Outcome::Failed
+    };
+
+    let snapshot = txn.clone();
+    match &mut txn.backend {
+        Backend::Snapper { config, pre, post } => {
+            *post = Some(snapper_create(runner, config, &snapshot, Some(*pre))?);
+        }
+        Backend::Btrfs { post, .. } => {
+            let path = btrfs_snapshot_path(cfg, &snapshot.id, "post");
+            run_checked(
+                runner,
+                "btrfs",
+                &["subvolume", "snapshot", "-r", "/", &path],
+            )?;
+            *post = Some(path);
+        }
+    }
+    Ok(())
+}
+
+/// Where `/` is mounted by explicit `subvol=`/`subvolid=`, which makes the
+/// default subvolume irrelevant at boot.
+fn root_subvolume_pins(runner: &dyn CommandRunner) -> Vec<String> {
+    let is_pin = |opt: &str| opt.starts_with("subvol=") || opt.starts_with("subvolid=");
+    let mut pins = Vec::new();
+    if let Ok(fstab) = runner.read_file("/etc/fstab") {
+        for line in fstab.lines().map(str::trim) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if line.starts_with('#') || fields.len() < 4 || fields[1] != "/" {
+                continue;
+            }
+            pins.extend(
+                fields[3]
+                    .split(',')
+                    .filter(|o| is_pin(o))
+                    .map(|o| format!("/etc/fstab: {o}")),
+            );
+        }
+    }
+    if let Ok(cmdline) = runner.read_file("/proc/cmdline") {
+        for flags in cmdline
+            .split_whitespace()
+            .filter_map(|arg| arg.strip_prefix("rootflags="))
+        {
+            pins.extend(
+                flags
+                    .split(',')
+                    .filter(|o| is_pin(o))
+                    .map(|o| format!("kernel command line: rootflags={o}")),
+            );
+        }
+    }
+    pins
+}
+
+/// Undo a transaction.
+///
+/// Snapper transactions are reverted in place with `snapper undochange`
+/// (against the live system when no post-snapshot exists). Plain btrfs
+/// transactions get a writable copy of the pre-snapshot that is made the
+/// default subvolume, which takes effect on the next boot. That is refused
+/// when fstab or the bootloader pin the root subvolume, since the new
+/// default would never be mounted.
+pub fn rollback(
+    runner: &dyn CommandRunner,
+    cfg: &BtrfsConfig,
+    txn: &Transaction,
+) -> Result<String> {
+    match &txn.backend {
+        Backend::Snapper { config, pre, post } => {
+            let range = format!("{pre}..{}", post.unwrap_or(0));
+            run_checked(runner, "snapper", &["-c", config, "undochange", &range])?;
+            Ok(format!(
+                "Reverted changes {range} with snapper config '{config}'"
+            ))
+        }
+        Backend::Btrfs { pre, .. } => {
+            let pins = root_subvolume_pins(runner);
+            if !pins.is_empty() {
+                bail!(
+                    "/ is mounted by an explicit subvolume ({}), so changing the default \
+                     subvolume would have no effect; boot {pre} from the bootloader or point \
+                     those entries at a writable copy of it instead",
+                    pins.join("; ")
+                );
+            }
+            let target = format!(
+                "{}/ghostctl-rollback-{}",
+                cfg.snapshot_dir.trim_end_matches('/'),
+                txn.id
+            );
+            run_checked(runner, "btrfs", &["subvolume", "snapshot", pre, &target])?;
+            let show = parse_subvolume_show(&run_checked(
+                runner,
+                "btrfs",
+                &["subvolume", "show", &target],
+            )?);
+            let Some(subvol_id) = show.id else {
+                bail!("could not determine the subvolume id of {target}");
+            };
+            run_checked(
+                runner,
+                "btrfs",
+                &["subvolume", "set-default", &subvol_id.to_string(), "/"],
+            )?;
+            Ok(format!(
+                "{target} (id {subvol_id}) is now the default subvolume; reboot to complete the rollback"
+            ))
+        }
+    }
+}
+
+pub fn command() -> Command {
+    Command::new("rollback")
+        .about("Undo a ghostctl transaction using its pre-snapshot")
+        .long_about(
+            "Risky ghostctl operations (driver installs, boot changes, VFIO setup, \
+             package fixes, PVE upgrades) take a pre- and post-snapshot when / is \
+             btrfs. Without an id, lists recorded transactions.",
+        )
+        .arg(Arg::new("id").help("Transaction id (see `ghostctl rollback`)"))
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let mut journal = Journal::load();
+    let Some(id) = matches.get_one::<String>("id") else {
+        print_journal(&journal);
+        return Ok(());
+    };
+    let Some(txn) = journal.find(id).cloned() else {
+        bail!("no transaction '{id}' (run `ghostctl rollback` to list them)");
+    };
+
+    tui::header(&format!("Rollback {}", txn.id));
+    println!("Operation: {}", txn.label);
+    println!("Command:   {}", txn.command);
+    println!("Outcome:   {}", txn.outcome);
+    match &txn.backend {
+        Backend::Snapper { config, pre, post } => println!(
+            "Snapshots: snapper {config} #{pre}..{}",
+            post.map(|p| format!("#{p}"))
+                .unwrap_or_else(|| "live".into())
+        ),
+        Backend::Btrfs { pre, .. } => println!("Snapshot:  {pre}"),
+    }
+
+    if txn.outcome == Outcome::RolledBack {
+        tui::warn("This transaction was already rolled back");
+    }
+    if crate::utils::is_dry_run() {
+        println!("[DRY RUN] Would roll back transaction {}", txn.id);
+        return Ok(());
+    }
+    if !tui::confirm_dangerous(&format!("Roll back '{}'?", txn.label)) {
+        println!("Rollback aborted.");
+        return Ok(());
+    }
+
+    let message = rollback(
+        crate::command::runner().as_ref(),
+        &BtrfsConfig::load(),
+        &txn,
+    )?;
+    let mut updated = txn;
+    updated.outcome = Outcome::RolledBack;
+    journal.record(&updated);
+    journal.save()?;
+    tui::success(&message);
+    Ok(())
+}
+
+fn print_journal(journal: &Journal) {
+    if journal.transactions.is_empty() {
+        println!("No transactions recorded.");
+        return;
+    }
+    println!("{:<16} {:<12} {:<28} COMMAND", "ID", "OUTCOME", "OPERATION");
+    for txn in journal.transactions.iter().rev() {
+        println!(
+            "{:<16} {:<12} {:<28} {}",
+            txn.id,
+            txn.outcome.to_string(),
+            txn.label,
+            txn.command
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 3, 1, 12, 30, 0).unwrap()
+    }
+
+    fn btrfs_root() -> MockRunner {
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "findmnt",
+            &["-n", "-o", "FSTYPE", "/"],
+            CommandResult::ok("btrfs\n"),
+        );
+        mock
+    }
+
+    #[test]
+    fn test_not_btrfs_skips_transaction() {
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "findmnt",
+            &["-n", "-o", "FSTYPE", "/"],
+            CommandResult::ok("ext4\n"),
+        );
+        let txn = begin(
+            &mock,
+            &BtrfsConfig::default(),
+            &Journal::default(),
+            "sysctl set",
+            "ghostctl",
+            now(),
+        )
+        .unwrap();
+        assert!(txn.is_none());
+        assert!(!mock.was_called("btrfs"));
+    }
+
+    #[test]
+    fn test_snapper_pre_post_and_undochange() {
+        let mock = btrfs_root();
+        mock.mock_command(
+            "snapper",
+            &["--jsonout", "list-configs"],
+            CommandResult::ok(r#"{"configs":[{"config":"root","subvolume":"/"}]}"#),
+        );
+        mock.mock_command(
+            "snapper",
+            &[
+                "-c",
+                "root",
+                "create",
+                "--type",
+                "pre",
+                "--print-number",
+                "--cleanup-algorithm",
+                "number",
+                "--description",
+                "ghostctl: nvidia install",
+                "--userdata",
+                "ghostctl_txn=20260301-123000",
+            ],
+            CommandResult::ok("41\n"),
+        );
+        mock.mock_command(
+            "snapper",
+            &[
+                "-c",
+                "root",
+                "create",
+                "--type",
+                "post",
+                "--pre-number",
+                "41",
+                "--print-number",
+                "--cleanup-algorithm",
+                "number",
+                "--description",
+                "ghostctl: nvidia install",
+                "--userdata",
+                "ghostctl_txn=20260301-123000",
+            ],
+            CommandResult::ok("42\n"),
+        );
+        let cfg = BtrfsConfig::default();
+
+        let mut txn = begin(
+            &mock,
+            &cfg,
+            &Journal::default(),
+            "nvidia install",
+            "ghostctl nvidia install",
+            now(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(txn.id, "20260301-123000");
+        finish(&mock, &cfg, &mut txn, true, now()).unwrap();
+        assert_eq!(txn.outcome, Outcome::Succeeded);
+        assert_eq!(
+            txn.backend,
+            Backend::Snapper {
+                config: "root".into(),
+                pre: 41,
+                post: Some(42)
+            }
+        );
+
+        rollback(&mock, &cfg, &txn).unwrap();
+        assert!(mock.was_called("snapper -c root undochange 41..42"));
+    }
+
+    #[test]
+    fn test_plain_btrfs_snapshots_and_set_default() {
+        let mock = btrfs_root();
+        mock.mock_command(
+            "btrfs",
+            &[
+                "subvolume",
+                "show",
+                "/@snapshots/ghostctl-rollback-20260301-123000",
+            ],
+            CommandResult::ok(
+                "\tName: \tghostctl-rollback-20260301-123000\n\tSubvolume ID: \t512\n",
+            ),
+        );
+        let cfg = BtrfsConfig::default();
+
+        let mut txn = begin(
+            &mock,
+            &cfg,
+            &Journal::default(),
+            "vfio bind",
+            "ghostctl vfio bind",
+            now(),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(mock.was_called(
+            "btrfs subvolume snapshot -r / /@snapshots/ghostctl-txn-20260301-123000-pre"
+        ));
+        finish(&mock, &cfg, &mut txn, false, now()).unwrap();
+        assert_eq!(txn.outcome, Outcome::Failed);
+        assert!(mock.was_called(
+            "btrfs subvolume snapshot -r / /@snapshots/ghostctl-txn-20260301-123000-post"
+        ));
+
+        let message = rollback(&mock, &cfg, &txn).unwrap();
+        assert!(mock.was_called(
+            "btrfs subvolume snapshot /@snapshots/ghostctl-txn-20260301-123000-pre /@snapshots/ghostctl-rollback-20260301-123000"
+        ));
+        assert!(mock.was_called("btrfs subvolume set-default 512 /"));
+        assert!(message.contains("reboot"));
+    }
+
+    #[test]
+    fn test_same_second_transactions_get_distinct_ids() {
+        let mock = btrfs_root();
+        let cfg = BtrfsConfig::default();
+        let mut journal = Journal::default();
+        for expected in ["20260301-123000", "20260301-123000-2", "20260301-123000-3"] {
+            let txn = begin(&mock, &cfg, &journal, "sysctl set", "ghostctl", now())
+                .unwrap()
+                .unwrap();
+            assert_eq!(txn.id, expected);
+            journal.record(&txn);
+        }
+        assert!(mock.was_called(
+            "btrfs subvolume snapshot -r / /@snapshots/ghostctl-txn-20260301-123000-2-pre"
+        ));
+    }
+
+    #[test]
+    fn test_pinned_root_subvolume_refuses_set_default() {
+        let mock = btrfs_root();
+        mock.mock_file(
+            "/etc/fstab",
+            "# <fs> <dir> <type> <options>\n\
+             UUID=abcd / btrfs rw,noatime,compress=zstd,subvol=/@ 0 0\n\
+             UUID=abcd /home btrfs rw,subvol=/@home 0 0\n",
+        );
+        mock.mock_file(
+            "/proc/cmdline",
+            "BOOT_IMAGE=/vmlinuz root=UUID=abcd rw rootflags=subvol=@\n",
+        );
+        let txn = Transaction {
+            id: "x".into(),
+            label: "vfio bind".into(),
+            command: "ghostctl vfio bind".into(),
+            started_at: String::new(),
+            finished_at: None,
+            outcome: Outcome::Succeeded,
+            backend: Backend::Btrfs {
+                pre: "/@snapshots/ghostctl-txn-x-pre".into(),
+                post: None,
+            },
+        };
+        let err = rollback(&mock, &BtrfsConfig::default(), &txn)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("/etc/fstab: subvol=/@"), "{err}");
+        assert!(err.contains("rootflags=subvol=@"), "{err}");
+        assert!(!err.contains("@home"), "{err}");
+        assert!(!mock.was_called("btrfs"));
+    }
+
+    #[test]
+    fn test_snapper_rollback_without_post_uses_live_system() {
+        let mock = MockRunner::as_root();
+        let txn = Transaction {
+            id: "x".into(),
+            label: "arch boot".into(),
+            command: "ghostctl arch boot".into(),
+            started_at: String::new(),
+            finished_at: None,
+            outcome: Outcome::Running,
+            backend: Backend::Snapper {
+                config: "root".into(),
+                pre: 7,
+                post: None,
+            },
+        };
+        rollback(&mock, &BtrfsConfig::default(), &txn).unwrap();
+        assert!(mock.was_called("snapper -c root undochange 7..0"));
+    }
+
+    #[test]
+    fn test_journal_round_trip_and_trim() {
+        let mut journal = Journal::default();
+        let mut txn = Transaction {
+            id: "0".into(),
+            label: "sysctl set".into(),
+            command: "ghostctl sysctl set".into(),
+            started_at: String::new(),
+            finished_at: None,
+            outcome: Outcome::Running,
+            backend: Backend::Btrfs {
+                pre: "/@snapshots/pre".into(),
+                post: None,
+            },
+        };
+        for i in 0..=MAX_JOURNAL_ENTRIES {
+            txn.id = i.to_string();
+            journal.record(&txn);
+        }
+        txn.outcome = Outcome::Succeeded;
+        journal.record(&txn);
+
+        assert_eq!(journal.transactions.len(), MAX_JOURNAL_ENTRIES);
+        assert!(journal.find("0").is_none());
+        assert_eq!(journal.find("200").unwrap().outcome, Outcome::Succeeded);
+
+        let json = serde_json::to_string(&journal).unwrap();
+        assert!(json.contains(r#""backend":"btrfs""#));
+        let parsed: Journal = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.transactions, journal.transactions);
+    }
+
+    #[test]
+    fn test_active_flag_is_cleared_after_a_panic() {
+        let panicked = std::panic::catch_unwind(|| {
+            let _active = ActiveGuard::enter();
+            assert!(ACTIVE.load(Ordering::SeqCst));
+            panic!("operation failed");
+        });
+        assert!(panicked.is_err());
+        assert!(!ACTIVE.load(Ordering::SeqCst));
+    }
+}
@@ -95,38 +95,50 @@ pub fn build_cli() -> Command {
                 .about("Proxmox VE management")
                 .subcommand(Command::new("menu").about("PVE management menu"))
                 .subcommand(Command::new("status").about("Show PVE status"))
-                .subcommand(
+                .subcommand(crate::proxmox::remote::clusters_command())
+                .subcommand(crate::proxmox::remote::fingerprint_command())
+                .subcommand(crate::proxmox::guests::plan_command())
+                .subcommand(crate::proxmox::guests::apply_command())
+                .subcommand(crate::proxmox::cloud_images::command())
+                .subcommand(crate::proxmox::script_registry::command())
+                .subcommand(crate::proxmox::rolling_upgrade::command())
+                .subcommand(crate::proxmox::firewall::command())
+                .subcommand(crate::proxmox::remote::target_args(
                     Command::new("vm")
                         .about("Virtual machine management")
-                        .subcommand(Command::new("list").about("List VMs"))
+                        .subcommand(crate::proxmox::remote::list_args(
+                            Command::new("list").about("List VMs"),
+                        ))
                         .subcommand(Command::new("create").about("Create VM"))
-                        .subcommand(
+                        .subcommand(crate::proxmox::remote::action_args(
                             Command::new("start")
                                 .about("Start VM")
                                 .arg(Arg::new("id").required(true).help("VM ID")),
-                        )
-                        .subcommand(
+                        ))
+                        .subcommand(crate::proxmox::remote::action_args(
                             Command::new("stop")
                                 .about("Stop VM")
                                 .arg(Arg::new("id").required(true).help("VM ID")),
-                        ),
-                )
-                .subcommand(
+                        )),
+                ))
+                .subcommand(crate::proxmox::remote::target_args(
                     Command::new("ct")
                         .about("Container management")
-                        .subcommand(Command::new("list").about("List containers"))
+                        .subcommand(crate::proxmox::remote::list_args(
+                            Command::new("list").about("List containers"),
+                        ))
                         .subcommand(Command::new("create").about("Create container"))
-                        .subcommand(
+                        .subcommand(crate::proxmox::remote::action_args(
                             Command::new("start")
                                 .about("Start container")
                                 .arg(Arg::new("id").required(true).help("Container ID")),
-                        )
-                        .subcommand(
+                        ))
+                        .subcommand(crate::proxmox::remote::action_args(
                             Command::new("stop")
                                 .about("Stop container")
                                 .arg(Arg::new("id").required(true).help("Container ID")),
-                        ),
-                ),
+                        )),
+                )),
         )
         .subcommand(
             Command::new("docker")
@@ -134,7 +146,10 @@ pub fn build_cli() -> Command {
                 .subcommand(Command::new("menu").about("Docker menu"))
                 .subcommand(Command::new("install").about("Install Docker"))
                 .subcommand(Command::new("status").about("Show Docker service status"))
-                .subcommand(Command::new("homelab").about("Homelab stacks")),
+                .subcommand(Command::new("homelab").about("Homelab stacks"))
+                .subcommand(crate::docker::stack::command())
+                .subcommand(crate::docker::updates::command())
+                .subcommand(crate::docker::vulnscan::command()),
         )
         .subcommand(
             Command::new("scripts")
@@ -279,7 +294,19 @@ pub fn build_cli() -> Command {
                 )
                 .subcommand(
                     Command::new("cleanup")
-                        .about("Emergency cleanup snapshots")
+                        .about("Apply the snapshot retention policy")
+                        .arg(
+                            Arg::new("plan")
+                                .long("plan")
+                                .action(clap::ArgAction::SetTrue)
+                                .help("Show the retention plan without deleting anything"),
+                        )
+                        .arg(
+                            Arg::new("disk-space")
+                                .long("disk-space")
+                                .action(clap::ArgAction::SetTrue)
+                                .help("Show disk space and snapshot usage"),
+                        )
                         .arg(
                             Arg::new("emergency")
                                 .long("emergency")
@@ -292,12 +319,33 @@ pub fn build_cli() -> Command {
                                 .value_name("DAYS")
                                 .help("Remove snapshots older than X days"),
                         )
+                        .arg(
+                            Arg::new("include-manual")
+                                .long("include-manual")
+                                .requires("days")
+                                .action(clap::ArgAction::SetTrue)
+                                .help("With --days, also remove manual snapshots"),
+                        )
                         .arg(
                             Arg::new("range")
                                 .long("range")
                                 .value_name("RANGE")
                                 .help("Remove snapshot range (e.g., 1-100)"),
                         ),
+                )
+                .subcommand(
+                    Command::new("replicate")
+                        .about("Send snapshots to [[btrfs.replicate]] targets (btrfs send/receive)")
+                        .arg(
+                            Arg::new("target")
+                                .help("Replication target name (default: all configured targets)"),
+                        )
+                        .arg(
+                            Arg::new("status")
+                                .long("status")
+                                .action(ArgAction::SetTrue)
+                                .help("Show configured targets and their last successful send"),
+                        ),
                 ),
         )
         .subcommand(
@@ -483,7 +531,42 @@ pub fn build_cli() -> Command {
                 .subcommand(Command::new("setup").about("Setup backup system"))
                 .subcommand(Command::new("schedule").about("Schedule backups"))
                 .subcommand(Command::new("verify").about("Verify backups"))
-                .subcommand(Command::new("cleanup").about("Cleanup old backups")),
+                .subcommand(Command::new("cleanup").about("Cleanup old backups"))
+                .subcommand(
+                    Command::new("run")
+                        .about("Run a [[backup.jobs]] job (all jobs when omitted)")
+                        .arg(Arg::new("job").help("Job name")),
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("Show the last successful run of each backup job")
+                        .arg(
+                            Arg::new("json")
+                                .long("json")
+                                .action(ArgAction::SetTrue)
+                                .help("Output as JSON"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("init")
+                        .about("Initialize the repository of a backup job")
+                        .arg(Arg::new("job").required(true).help("Job name")),
+                )
+                .subcommand(
+                    Command::new("timers")
+                        .about("Install systemd timers for scheduled backup jobs")
+                        .arg(
+                            Arg::new("system")
+                                .long("system")
+                                .action(ArgAction::SetTrue)
+                                .help("Install system units in /etc/systemd/system"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("drill")
+                        .about("Restore a sample of the latest snapshot and verify its hashes")
+                        .arg(Arg::new("job").required(true).help("Job name")),
+                ),
         )
         .subcommand(
             Command::new("restore")
@@ -556,7 +639,8 @@ pub fn build_cli() -> Command {
                             .help("Domain name to lookup"),
                     ),
                 )
-                .subcommand(Command::new("mesh").about("Mesh networking"))
+                .subcommand(network::mesh::command())
+                .subcommand(crate::networking::diagnose::command())
                 .subcommand(
                     Command::new("scan")
                         .about("Scan network ports")
@@ -642,7 +726,8 @@ pub fn build_cli() -> Command {
                             .help("Domain name to lookup"),
                     ),
                 )
-                .subcommand(Command::new("mesh").about("Mesh networking"))
+                .subcommand(network::mesh::command())
+                .subcommand(crate::networking::diagnose::command())
                 .subcommand(
                     Command::new("scan")
                         .about("Network port scanning")
@@ -844,7 +929,38 @@ pub fn build_cli() -> Command {
                         .long("quiet")
                         .action(clap::ArgAction::SetTrue)
                         .help("Minimal output"),
-                ),
+                )
+                .arg(
+                    Arg::new("os")
+                        .long("os")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Fingerprint the OS of hosts that answered"),
+                )
+                .arg(
+                    Arg::new("no-save")
+                        .long("no-save")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Do not record the results in the scan database"),
+                )
+                .arg(
+                    Arg::new("alert")
+                        .long("alert")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("no-save")
+                        .help("Raise changes since the previous scan as Alertmanager alerts"),
+                )
+                .arg(
+                    Arg::new("db")
+                        .long("db")
+                        .value_name("PATH")
+                        .help("Scan database (default: ~/.local/state/ghostctl/scans/inventory.jsonl)"),
+                )
+                .subcommand_negates_reqs(true)
+                .args_conflicts_with_subcommands(true)
+                .subcommands(crate::networking::inventory::subcommands())
+                .subcommand(crate::networking::nmap::command())
+                .subcommand(crate::networking::tls::command())
+                .subcommand(crate::networking::probes::command()),
         )
         .subcommand(
             Command::new("completion")
@@ -940,6 +1056,9 @@ pub fn build_cli() -> Command {
         .subcommand(gitlab::command())
         .subcommand(audit::command())
         .subcommand(unifi::command())
+        .subcommand(crate::proxmox::backup_server::command())
+        .subcommand(btrfs::transaction::command())
+        .subcommand(crate::networking::policy::command())
 }
 
 pub fn handle_cli_args(matches: &ArgMatches) {
@@ -1071,12 +1190,30 @@ pub fn handle_cli_args(matches: &ArgMatches) {
                 std::process::exit(1);
             }
         }
+        Some(("pbs", matches)) => {
+            if let Err(e) = crate::proxmox::backup_server::handle(matches) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
         Some(("unifi", matches)) => {
             if let Err(e) = unifi::handle(matches) {
                 eprintln!("Error: {e:#}");
                 std::process::exit(1);
             }
         }
+        Some(("rollback", matches)) => {
+            if let Err(e) = btrfs::transaction::handle(matches) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("firewall", matches)) => {
+            if let Err(e) = crate::networking::policy::handle(matches) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
         Some(("menu", _)) | None => crate::menu::show(),
         Some((cmd, _)) => {
             eprintln!("Unknown command: {}", cmd);
@@ -1294,13 +1431,112 @@ fn handle_pve_commands(matches: &ArgMatches) {
     match matches.subcommand() {
         Some(("menu", _)) => crate::pve::pve_management_menu(),
         Some(("status", _)) => crate::pve::show_pve_status(),
-        Some(("vm", vm_matches)) => handle_vm_commands(vm_matches),
-        Some(("ct", ct_matches)) => handle_ct_commands(ct_matches),
+        Some(("clusters", m)) => {
+            if let Err(e) = crate::proxmox::remote::handle_clusters(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("fingerprint", m)) => {
+            if let Err(e) = crate::proxmox::remote::handle_fingerprint(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("plan", m)) => {
+            if let Err(e) = crate::proxmox::guests::handle_plan(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("apply", m)) => {
+            if let Err(e) = crate::proxmox::guests::handle_apply(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("template", m)) => {
+            if let Err(e) = crate::proxmox::cloud_images::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("scripts", m)) => {
+            if let Err(e) = crate::proxmox::script_registry::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("upgrade", m)) => {
+            if let Err(e) = crate::proxmox::rolling_upgrade::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("firewall", m)) => {
+            if let Err(e) = crate::proxmox::firewall::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("vm", vm_matches)) => {
+            if let Some(cluster) = crate::proxmox::remote::remote_cluster(vm_matches) {
+                handle_remote_guest_commands(
+                    vm_matches,
+                    &cluster,
+                    crate::proxmox::api::GuestKind::Qemu,
+                );
+            } else {
+                handle_vm_commands(vm_matches);
+            }
+        }
+        Some(("ct", ct_matches)) => {
+            if let Some(cluster) = crate::proxmox::remote::remote_cluster(ct_matches) {
+                handle_remote_guest_commands(
+                    ct_matches,
+                    &cluster,
+                    crate::proxmox::api::GuestKind::Lxc,
+                );
+            } else {
+                handle_ct_commands(ct_matches);
+            }
+        }
         None => crate::pve::pve_management_menu(),
         _ => unreachable!(),
     }
 }
 
+/// `pve vm`/`pve ct` against a cluster's API instead of local `qm`/`pct`.
+fn handle_remote_guest_commands(
+    matches: &ArgMatches,
+    cluster: &str,
+    kind: crate::proxmox::api::GuestKind,
+) {
+    use crate::proxmox::remote;
+    let node = matches.get_one::<String>("node").map(String::as_str);
+    let result = match matches.subcommand() {
+        Some(("list", m)) => remote::list(cluster, node, kind, m.get_flag("json")),
+        Some((action @ ("start" | "stop"), m)) => remote::action(
+            cluster,
+            node,
+            kind,
+            m.get_one::<String>("id")
+                .map(String::as_str)
+                .unwrap_or_default(),
+            action,
+            !m.get_flag("no-wait"),
+        ),
+        Some(("create", _)) => Err(anyhow::anyhow!(
+            "creating guests over the API is not supported yet; run on a node or drop --cluster"
+        )),
+        _ => remote::list(cluster, node, kind, false),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e:#}");
+        std::process::exit(1);
+    }
+}
+
 fn handle_vm_commands(matches: &ArgMatches) {
     match matches.subcommand() {
         Some(("list", _)) => crate::pve::list_vms(),
@@ -1345,6 +1581,24 @@ fn handle_docker_commands(matches: &ArgMatches) {
         Some(("install", _)) => install_docker(),
         Some(("status", _)) => show_docker_status(),
         Some(("homelab", _)) => docker_homelab_menu(),
+        Some(("stack", m)) => {
+            if let Err(e) = crate::docker::stack::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("updates", m)) => {
+            if let Err(e) = crate::docker::updates::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("scan", m)) => {
+            if let Err(e) = crate::docker::vulnscan::handle(m) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
         None => crate::docker::devops::docker_management(),
         _ => unreachable!(),
     }
@@ -1521,7 +1775,18 @@ fn handle_network_commands(matches: &ArgMatches) {
                 println!("❌ Please provide a domain name. Usage: ghostctl network dns <domain>");
             }
         }
-        Some(("mesh", _)) => network::mesh::status(),
+        Some(("diagnose", sub_matches)) => {
+            if let Err(e) = crate::networking::diagnose::handle(sub_matches) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("mesh", sub_matches)) => {
+            if let Err(e) = network::mesh::handle(sub_matches) {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
         Some(("scan", sub_matches)) => {
             // Redirect to new scan command handler
             handle_scan_command(sub_matches);
@@ -1741,14 +2006,29 @@ fn handle_btrfs_commands(matches: &ArgMatches) {
             if sub_matches.get_flag("emergency") {
                 btrfs::handle_btrfs_action(crate::BtrfsAction::EmergencyCleanup);
             } else if let Some(days) = sub_matches.get_one::<String>("days") {
-                btrfs::handle_btrfs_action(crate::BtrfsAction::CleanupByAge { days: days.clone() });
+                btrfs::handle_btrfs_action(crate::BtrfsAction::CleanupByAge {
+                    days: days.clone(),
+                    include_manual: sub_matches.get_flag("include-manual"),
+                });
             } else if let Some(range) = sub_matches.get_one::<String>("range") {
                 btrfs::handle_btrfs_action(crate::BtrfsAction::CleanupByRange {
                     range: range.clone(),
                 });
-            } else {
-                // Show disk space and cleanup menu
+            } else if sub_matches.get_flag("disk-space") {
                 btrfs::handle_btrfs_action(crate::BtrfsAction::DiskSpace);
+            } else {
+                btrfs::handle_btrfs_action(crate::BtrfsAction::CleanupPolicy {
+                    plan_only: sub_matches.get_flag("plan"),
+                });
+            }
+        }
+        Some(("replicate", sub_matches)) => {
+            if sub_matches.get_flag("status") {
+                btrfs::handle_btrfs_action(crate::BtrfsAction::ReplicateStatus);
+            } else {
+                btrfs::handle_btrfs_action(crate::BtrfsAction::Replicate {
+                    target: sub_matches.get_one::<String>("target").cloned(),
+                });
             }
         }
         None => btrfs::btrfs_menu(),
@@ -1956,8 +2236,19 @@ fn handle_vfio_commands(matches: &ArgMatches) {
         }
         Some(("bind", sub_matches)) => {
             if let Some(device) = sub_matches.get_one::<String>("device") {
-                match vfio::bind_device(device) {
-                    Ok(_) => println!("Device {} bound to vfio-pci", device),
+                let previous_driver = vfio::get_current_driver(device).ok().flatten();
+                match btrfs::transaction::run(&format!("vfio bind {device}"), || {
+                    vfio::bind_device(device)
+                }) {
+                    Ok(_) => {
+                        println!("Device {} bound to vfio-pci", device);
+                        if let Some(driver) = previous_driver.filter(|d| d != "vfio-pci") {
+                            println!(
+                                "Previous driver was {}; run 'ghostctl vfio unbind {}' to hand the device back to it.",
+                                driver, device
+                            );
+                        }
+                    }
                     Err(e) => {
                         eprintln!("Error binding device: {}", e);
                         eprintln!("Hint: Make sure you're running as root (sudo)");
@@ -1967,8 +2258,19 @@ fn handle_vfio_commands(matches: &ArgMatches) {
         }
         Some(("unbind", sub_matches)) => {
             if let Some(device) = sub_matches.get_one::<String>("device") {
-                match vfio::unbind_device(device) {
-                    Ok(_) => println!("Device {} unbound from vfio-pci", device),
+                let previous_driver = vfio::get_current_driver(device).ok().flatten();
+                match btrfs::transaction::run(&format!("vfio unbind {device}"), || {
+                    vfio::unbind_device(device)
+                }) {
+                    Ok(_) => {
+                        if previous_driver.as_deref() == Some("vfio-pci") {
+                            println!("Device {} unbound from vfio-pci", device);
+                            println!(
+                                "Previous driver was vfio-pci; run 'ghostctl vfio bind {}' to bind it again.",
+                                device
+                            );
+                        }
+                    }
                     Err(e) => {
                         eprintln!("Error unbinding device: {}", e);
                         eprintln!("Hint: Make sure you're running as root (sudo)");
@@ -2203,6 +2505,19 @@ fn handle_backup_commands(matches: &ArgMatches) {
         Some(("schedule", _)) => backup::schedule::setup_schedule(),
         Some(("verify", _)) => backup::verify::verify_backups(),
         Some(("cleanup", _)) => backup::cleanup::cleanup_old_backups(),
+        Some((cmd @ ("run" | "status" | "init" | "timers" | "drill"), m)) => {
+            let result = match cmd {
+                "run" => backup::jobs::run(m.get_one::<String>("job").map(String::as_str)),
+                "status" => backup::jobs::print_status(m.get_flag("json")),
+                "init" => backup::jobs::init(m.get_one::<String>("job").expect("job is required")),
+                "drill" => backup::drill::run(m.get_one::<String>("job").expect("job is required")),
+                _ => backup::jobs::install_timers(m.get_flag("system")),
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
         _ => backup::backup_menu(),
     }
 }
@@ -2802,6 +3117,19 @@ fn handle_netcat_commands(matches: &ArgMatches) {
 }
 
 fn handle_scan_command(matches: &ArgMatches) {
+    if let Some((name, sub)) = matches.subcommand() {
+        let result = match name {
+            "import" => crate::networking::nmap::handle(sub),
+            "tls" => crate::networking::tls::handle(sub),
+            "probes" => crate::networking::probes::handle(sub),
+            _ => crate::networking::inventory::handle(name, sub),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
     let Some(target) = matches.get_one::<String>("target") else {
         eprintln!("❌ Missing required argument: target");
         std::process::exit(1);
@@ -2812,7 +3140,7 @@ fn handle_scan_command(matches: &ArgMatches) {
         .and_then(|s| s.parse().ok());
     let full_scan = matches.get_flag("full");
     let service_detection = matches.get_flag("service");
-    let _json_output = matches.get_flag("json");
+    let json_output = matches.get_flag("json");
     let quiet = matches.get_flag("quiet");
 
     if !quiet {
@@ -2847,11 +3175,65 @@ fn handle_scan_command(matches: &ArgMatches) {
         vec![target.to_string()]
     };
 
-    // Launch the scanner
-    if let Err(e) =
-        crate::network::scan::scan_cli(targets, port_spec.map(|s| s.to_string()), threads)
+    // Launch the scanner; cron jobs and --json skip the TUI
+    let ports = port_spec.unwrap_or("1-1000").to_string();
+    let scan = if json_output || quiet || crate::utils::is_headless() {
+        crate::network::scan::scan_headless(targets.clone(), Some(ports.clone()), threads)
+    } else {
+        crate::network::scan::scan_cli(targets.clone(), Some(ports.clone()), threads)
+    };
+    let results = match scan {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("❌ Scan failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if json_output {
+        let report: Vec<_> = results
+            .iter()
+            .filter(|r| r.status == crate::networking::scanner::PortStatus::Open)
+            .map(crate::networking::export::ExportScanResult::from)
+            .collect();
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{json}"),
+            Err(e) => eprintln!("❌ {e}"),
+        }
+    }
+    if matches.get_flag("no-save") {
+        return;
+    }
+
+    use crate::networking::inventory::{self, ScanRecord};
+    let mut record = ScanRecord::from_results(&results, &targets, &ports, chrono::Utc::now());
+    if matches.get_flag("os") {
+        let hosts: Vec<String> = record.hosts.keys().cloned().collect();
+        for (host, guess) in crate::network::scan::os_guesses(&hosts) {
+            if let Some(h) = record.hosts.get_mut(&host) {
+                h.os = Some(guess);
+            }
+        }
+    }
+    let db = inventory::database(matches);
+    let (id, changes) = match inventory::save(&db, record) {
+        Ok(saved) => saved,
+        Err(e) => {
+            eprintln!("❌ Could not save the scan: {e:#}");
+            std::process::exit(1);
+        }
+    };
+    if !json_output {
+        println!("💾 Saved as scan #{id} ({})", db.path.display());
+        if id > 1 {
+            println!("Changes since the previous scan:");
+            inventory::print_changes(&changes);
+        }
+    }
+    if matches.get_flag("alert")
+        && let Err(e) =
+            crate::monitor::push_alerts(&inventory::alerts(&changes), inventory::ALERT_HOURS)
     {
-        eprintln!("❌ Scan failed: {}", e);
+        eprintln!("❌ Could not raise alerts: {e:#}");
         std::process::exit(1);
     }
 }
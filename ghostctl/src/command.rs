@@ -62,6 +62,10 @@ pub trait CommandRunner: Send + Sync {
     /// Execute a command and capture output
     fn run(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult>;
 
+    /// Execute a command with `input` on its stdin, for data that must not
+    /// show up in the process list
+    fn run_with_stdin(&self, cmd: &str, args: &[&str], input: &str) -> io::Result<CommandResult>;
+
     /// Execute a command with sudo (or directly if root)
     fn run_sudo(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult>;
 
@@ -94,6 +98,9 @@ pub trait CommandRunner: Send + Sync {
 
     /// Check if a file exists
     fn file_exists(&self, path: &str) -> bool;
+
+    /// Remove a file or directory tree (with sudo fallback if needed)
+    fn remove_path(&self, path: &str) -> io::Result<()>;
 }
 
 /// System command runner - executes real commands
@@ -146,6 +153,29 @@ impl CommandRunner for SystemRunner {
         Ok(CommandResult::from_output(&output))
     }
 
+    fn run_with_stdin(&self, cmd: &str, args: &[&str], input: &str) -> io::Result<CommandResult> {
+        if self.dry_run {
+            return Ok(CommandResult::ok(format!(
+                "[DRY RUN] Would execute: {} {}",
+                cmd,
+                args.join(" ")
+            )));
+        }
+
+        let mut child = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            use std::io::Write;
+            stdin.write_all(input.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        Ok(CommandResult::from_output(&output))
+    }
+
     fn run_sudo(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult> {
         if self.dry_run {
             return Ok(CommandResult::ok(format!(
@@ -317,6 +347,36 @@ impl CommandRunner for SystemRunner {
     fn file_exists(&self, path: &str) -> bool {
         std::path::Path::new(path).exists()
     }
+
+    fn remove_path(&self, path: &str) -> io::Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        // Try normal removal first
+        let target = std::path::Path::new(path);
+        let removed = if target.is_dir() {
+            std::fs::remove_dir_all(target)
+        } else {
+            std::fs::remove_file(target)
+        };
+        match removed {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(_) => {}
+        }
+
+        // Fall back to sudo
+        let result = self.run_sudo("rm", &["-rf", path])?;
+        if result.success {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                result.stderr,
+            ))
+        }
+    }
 }
 
 /// Mock command runner for testing
@@ -326,6 +386,8 @@ pub struct MockRunner {
     responses: Arc<Mutex<HashMap<String, CommandResult>>>,
     /// Record of all commands executed
     history: Arc<Mutex<Vec<String>>>,
+    /// Record of everything written to a command's stdin
+    inputs: Arc<Mutex<Vec<String>>>,
     /// Default response for unmatched commands
     default_response: Arc<Mutex<Option<CommandResult>>>,
     /// Mock file system
@@ -406,6 +468,15 @@ impl MockRunner {
             .unwrap_or(false)
     }
 
+    /// Check if some command was given `text` on its stdin
+    pub fn was_fed(&self, text: &str) -> bool {
+        self.inputs
+            .lock()
+            .ok()
+            .map(|i| i.iter().any(|input| input.contains(text)))
+            .unwrap_or(false)
+    }
+
     /// Get the number of times a command was called
     pub fn call_count(&self, cmd: &str) -> usize {
         self.history
@@ -452,6 +523,13 @@ impl CommandRunner for MockRunner {
         self.record_and_respond(&key)
     }
 
+    fn run_with_stdin(&self, cmd: &str, args: &[&str], input: &str) -> io::Result<CommandResult> {
+        if let Ok(mut inputs) = self.inputs.lock() {
+            inputs.push(input.to_string());
+        }
+        self.run(cmd, args)
+    }
+
     fn run_sudo(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult> {
         let key = if self.is_root {
             format!("{} {}", cmd, args.join(" "))
@@ -530,6 +608,15 @@ impl CommandRunner for MockRunner {
             .map(|f| f.contains_key(path))
             .unwrap_or(false)
     }
+
+    fn remove_path(&self, path: &str) -> io::Result<()> {
+        let Ok(mut files) = self.files.lock() else {
+            return Err(io::Error::other("Failed to acquire lock"));
+        };
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        files.retain(|name, _| name != path && !name.starts_with(&prefix));
+        Ok(())
+    }
 }
 
 /// Global runner instance - can be swapped for testing
@@ -583,6 +670,12 @@ mod tests {
 
         let content = mock.read_file("/etc/test").unwrap();
         assert_eq!(content, "content");
+
+        mock.mock_file("/etc/dir/a", "a");
+        mock.mock_file("/etc/directory", "b");
+        mock.remove_path("/etc/dir").unwrap();
+        assert!(!mock.file_exists("/etc/dir/a"));
+        assert!(mock.file_exists("/etc/directory"));
     }
 
     #[test]
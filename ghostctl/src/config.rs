@@ -38,6 +38,15 @@ pub struct GhostConfig {
 
     #[serde(default)]
     pub unifi: Option<crate::unifi::config::UnifiConfig>,
+
+    #[serde(default)]
+    pub btrfs: Option<crate::btrfs::config::BtrfsConfig>,
+
+    #[serde(default)]
+    pub mesh: Option<crate::network::mesh::config::MeshConfig>,
+
+    #[serde(default)]
+    pub pve: Option<crate::proxmox::config::PveConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -55,6 +64,9 @@ pub struct BackupConfig {
     pub retention_daily: u32,
     pub retention_weekly: u32,
     pub retention_monthly: u32,
+    /// Declarative restic jobs (`[[backup.jobs]]`).
+    #[serde(default)]
+    pub jobs: Vec<crate::backup::config::BackupJob>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -190,6 +202,7 @@ impl Default for GhostConfig {
                 retention_daily: 7,
                 retention_weekly: 4,
                 retention_monthly: 12,
+                jobs: Vec::new(),
             },
             scripts: ScriptsConfig {
                 local_scripts_dir: "~/.config/ghostctl/scripts".to_string(),
@@ -221,6 +234,9 @@ impl Default for GhostConfig {
             openshell: None,   // Use OpenShell defaults when not specified
             gitlab: None,      // Use GitLab defaults when not specified
             unifi: None,       // Use UniFi defaults when not specified
+            btrfs: None,       // No replication targets by default
+            mesh: None,        // No mesh profiles by default
+            pve: None,         // No remote PVE clusters by default
         }
     }
 }
@@ -323,6 +339,15 @@ impl GhostConfig {
             config.backup.retention_weekly,
             config.backup.retention_monthly
         );
+        println!("  Jobs: {}", config.backup.jobs.len());
+        for job in &config.backup.jobs {
+            println!(
+                "    - {} -> {} ({})",
+                job.name,
+                job.repository,
+                job.schedule.as_deref().unwrap_or("manual")
+            );
+        }
         println!();
 
         println!("📜 Scripts:");
@@ -400,6 +425,20 @@ impl GhostConfig {
         println!("  Binary: {}", openshell.bin);
         println!("  Gateway URL: {}", openshell.gateway_url);
         println!("  Timeout: {}s", openshell.timeout_secs);
+        println!();
+
+        let btrfs = config.btrfs.clone().unwrap_or_default();
+        println!("🗂️  Btrfs:");
+        println!("  Snapshot Dir: {}", btrfs.snapshot_dir);
+        let r = &btrfs.retention;
+        println!(
+            "  Retention: latest={} hourly={} daily={} weekly={} monthly={} yearly={}",
+            r.latest, r.hourly, r.daily, r.weekly, r.monthly, r.yearly
+        );
+        println!("  Replication Targets: {}", btrfs.replicate.len());
+        for target in &btrfs.replicate {
+            println!("    - {} -> {}", target.name, target.target);
+        }
     }
 
     pub fn reset() -> Result<(), Box<dyn std::error::Error>> {
@@ -183,26 +183,27 @@ fn browse_local_stacks() {
     }
 }
 
-fn find_compose_stacks() -> Vec<std::path::PathBuf> {
+/// Stack directories under `./docker`, `/opt/docker`, `~/docker` and `./`.
+pub fn find_compose_stacks() -> Vec<std::path::PathBuf> {
     let mut stacks = Vec::new();
 
-    let search_dirs = ["./docker", "/opt/docker", "~/docker", "./"];
+    let mut search_dirs = vec![
+        std::path::PathBuf::from("./docker"),
+        std::path::PathBuf::from("/opt/docker"),
+    ];
+    if let Some(home) = dirs::home_dir() {
+        search_dirs.push(home.join("docker"));
+    }
+    search_dirs.push(std::path::PathBuf::from("./"));
 
-    for dir in &search_dirs {
-        let path = std::path::Path::new(dir);
+    for path in &search_dirs {
         if path.exists()
             && let Ok(entries) = fs::read_dir(path)
         {
             for entry in entries.flatten() {
                 let entry_path = entry.path();
-                if entry_path.is_dir() {
-                    // Check if directory contains docker-compose.yml
-                    let compose_file = entry_path.join("docker-compose.yml");
-                    let compose_file_alt = entry_path.join("docker-compose.yaml");
-
-                    if compose_file.exists() || compose_file_alt.exists() {
-                        stacks.push(entry_path);
-                    }
+                if entry_path.is_dir() && is_valid_compose_directory(&entry_path) {
+                    stacks.push(entry_path);
                 }
             }
         }
@@ -589,18 +590,19 @@ fn update_all_stacks() {
 
 /// Check if a path contains a valid docker-compose file
 pub fn is_valid_compose_directory(path: &std::path::Path) -> bool {
-    path.join("docker-compose.yml").exists() || path.join("docker-compose.yaml").exists()
+    get_compose_filename(path).is_some()
 }
 
 /// Get the compose file name in a directory
 pub fn get_compose_filename(path: &std::path::Path) -> Option<&'static str> {
-    if path.join("docker-compose.yml").exists() {
-        Some("docker-compose.yml")
-    } else if path.join("docker-compose.yaml").exists() {
-        Some("docker-compose.yaml")
-    } else {
-        None
-    }
+    [
+        "docker-compose.yml",
+        "docker-compose.yaml",
+        "compose.yaml",
+        "compose.yml",
+    ]
+    .into_iter()
+    .find(|name| path.join(name).exists())
 }
 
 /// Parse a basic compose file and extract service names
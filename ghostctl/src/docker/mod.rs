@@ -3,7 +3,11 @@ pub mod compose;
 pub mod container;
 pub mod devops;
 pub mod registry;
+pub mod rpmdb;
 pub mod security;
+pub mod stack;
+pub mod updates;
+pub mod vulnscan;
 
 use crate::tui;
 use crate::utils::is_headless;
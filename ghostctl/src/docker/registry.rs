@@ -1856,6 +1856,69 @@ pub fn parse_docker_auths(config: &serde_json::Value) -> Vec<String> {
     registries
 }
 
+/// Normalize a config.json `auths` key or a registry host for comparison.
+/// All Docker Hub spellings map to `docker.io`.
+fn normalize_registry_key(key: &str) -> String {
+    let host = key
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split('/')
+        .next()
+        .unwrap_or("");
+    match host {
+        "" | "index.docker.io" | "registry-1.docker.io" | "docker.io" => "docker.io".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Username and password stored for `registry` in a Docker config.json,
+/// either inline (`auths.<registry>.auth`) or via a credential helper
+/// (`credHelpers.<registry>` / `credsStore`).
+pub fn docker_credentials(config: &serde_json::Value, registry: &str) -> Option<(String, String)> {
+    use base64::Engine;
+
+    let wanted = normalize_registry_key(registry);
+    let key = parse_docker_auths(config)
+        .into_iter()
+        .find(|k| normalize_registry_key(k) == wanted);
+
+    if let Some(auth) = key
+        .as_ref()
+        .and_then(|k| config["auths"][k.as_str()]["auth"].as_str())
+        && let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(auth)
+        && let Some((user, pass)) = String::from_utf8_lossy(&decoded).split_once(':')
+    {
+        return Some((user.to_string(), pass.to_string()));
+    }
+
+    let helper = config["credHelpers"]
+        .as_object()
+        .and_then(|helpers| {
+            helpers
+                .iter()
+                .find(|(k, _)| normalize_registry_key(k) == wanted)
+                .and_then(|(_, v)| v.as_str())
+        })
+        .or_else(|| config["credsStore"].as_str())?;
+    let server = key.unwrap_or_else(|| match wanted.as_str() {
+        "docker.io" => "https://index.docker.io/v1/".to_string(),
+        other => other.to_string(),
+    });
+    let output = crate::command::runner()
+        .run_shell(&format!(
+            "printf %s {} | docker-credential-{} get",
+            crate::utils::shell_quote(&server),
+            crate::utils::shell_quote(helper)
+        ))
+        .ok()
+        .filter(|r| r.success)?;
+    let secret: serde_json::Value = serde_json::from_str(&output.stdout).ok()?;
+    Some((
+        secret["Username"].as_str()?.to_string(),
+        secret["Secret"].as_str()?.to_string(),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1999,6 +2062,26 @@ mod tests {
         assert!(registries.is_empty());
     }
 
+    #[test]
+    fn test_docker_credentials_inline_auth() {
+        // "user:secret"
+        let config = serde_json::json!({
+            "auths": {
+                "https://index.docker.io/v1/": {"auth": "dXNlcjpzZWNyZXQ="},
+                "registry.lan:5000": {"auth": "Ym90OnRva2VuOndpdGg6Y29sb25z"}
+            }
+        });
+        assert_eq!(
+            docker_credentials(&config, "registry-1.docker.io"),
+            Some(("user".to_string(), "secret".to_string()))
+        );
+        assert_eq!(
+            docker_credentials(&config, "registry.lan:5000"),
+            Some(("bot".to_string(), "token:with:colons".to_string()))
+        );
+        assert_eq!(docker_credentials(&config, "ghcr.io"), None);
+    }
+
     #[test]
     fn test_parse_docker_auths_no_auths_key() {
         let config = serde_json::json!({
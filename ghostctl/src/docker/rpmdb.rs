@@ -0,0 +1,545 @@
+//! Read installed packages from an rpm `rpmdb.sqlite` without sqlite or rpm.
+//!
+//! rpm >= 4.16 (Fedora 33+, RHEL/Rocky/Alma 9) keeps one header blob per
+//! package in the `Packages` table. That table is a plain SQLite table
+//! b-tree, so a small read-only walker over the file format is enough: find
+//! the table's root page in `sqlite_master`, visit every leaf cell (following
+//! overflow chains for large blobs) and decode the rpm header in each blob.
+//! The older BerkeleyDB `Packages` format is not supported.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+
+const RPMTAG_NAME: u32 = 1000;
+const RPMTAG_VERSION: u32 = 1001;
+const RPMTAG_RELEASE: u32 = 1002;
+const RPMTAG_EPOCH: u32 = 1003;
+const RPMTAG_ARCH: u32 = 1022;
+const RPMTAG_SOURCERPM: u32 = 1044;
+
+const RPM_INT32_TYPE: u32 = 4;
+const RPM_STRING_TYPE: u32 = 6;
+
+/// One installed rpm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpmPackage {
+    pub name: String,
+    pub epoch: Option<u32>,
+    pub version: String,
+    pub release: String,
+    pub arch: String,
+    pub source_rpm: Option<String>,
+}
+
+impl RpmPackage {
+    /// `[epoch:]version-release`, the form OSV rpm ecosystems use.
+    pub fn evr(&self) -> String {
+        match self.epoch {
+            Some(epoch) if epoch > 0 => format!("{epoch}:{}-{}", self.version, self.release),
+            _ => format!("{}-{}", self.version, self.release),
+        }
+    }
+}
+
+/// Packages recorded in an `rpmdb.sqlite` image.
+pub fn read_packages(db: &[u8]) -> Result<Vec<RpmPackage>> {
+    let file = SqliteFile::open(db)?;
+    let root = file
+        .table_root("Packages")?
+        .context("rpmdb.sqlite has no Packages table")?;
+    let mut packages = Vec::new();
+    for row in file.table_rows(root)? {
+        // (hnum INTEGER PRIMARY KEY, blob BLOB): hnum is the rowid alias.
+        if let Some(Value::Blob(blob)) = row.get(1)
+            && let Some(package) = parse_header(blob)
+        {
+            packages.push(package);
+        }
+    }
+    Ok(packages)
+}
+
+/// Decode the fields we need from an rpm header blob (index + data store,
+/// without the on-disk lead).
+pub fn parse_header(blob: &[u8]) -> Option<RpmPackage> {
+    let index_len = be_u32(blob, 0)? as usize;
+    let data_len = be_u32(blob, 4)? as usize;
+    let data_start = 8 + index_len.checked_mul(16)?;
+    let data = blob.get(data_start..data_start.checked_add(data_len)?)?;
+
+    let mut name = None;
+    let mut version = None;
+    let mut release = None;
+    let mut arch = String::new();
+    let mut epoch = None;
+    let mut source_rpm = None;
+    for i in 0..index_len {
+        let entry = 8 + i * 16;
+        let tag = be_u32(blob, entry)?;
+        let kind = be_u32(blob, entry + 4)?;
+        let offset = be_u32(blob, entry + 8)? as usize;
+        let string = || {
+            let bytes = data.get(offset..)?;
+            let end = bytes.iter().position(|&b| b == 0)?;
+            Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+        };
+        match (tag, kind) {
+            (RPMTAG_NAME, RPM_STRING_TYPE) => name = string(),
+            (RPMTAG_VERSION, RPM_STRING_TYPE) => version = string(),
+            (RPMTAG_RELEASE, RPM_STRING_TYPE) => release = string(),
+            (RPMTAG_ARCH, RPM_STRING_TYPE) => arch = string().unwrap_or_default(),
+            (RPMTAG_SOURCERPM, RPM_STRING_TYPE) => source_rpm = string(),
+            (RPMTAG_EPOCH, RPM_INT32_TYPE) => epoch = be_u32(data, offset),
+            _ => {}
+        }
+    }
+    Some(RpmPackage {
+        name: name?,
+        epoch,
+        version: version?,
+        release: release?,
+        arch,
+        source_rpm,
+    })
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+struct SqliteFile<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    usable: usize,
+}
+
+/// SQLite varint: up to 9 bytes, 7 bits each except a full 9th byte.
+fn varint(bytes: &[u8], at: usize) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *bytes.get(at + i)?;
+        if i == 8 {
+            return Some(((value << 8) | byte as u64, 9));
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+impl<'a> SqliteFile<'a> {
+    fn open(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(b"SQLite format 3\0") || data.len() < 100 {
+            bail!("not an SQLite database");
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            n => n as usize,
+        };
+        if page_size < 512 || !page_size.is_power_of_two() {
+            bail!("invalid SQLite page size {page_size}");
+        }
+        let usable = page_size - data[20] as usize;
+        Ok(Self {
+            data,
+            page_size,
+            usable,
+        })
+    }
+
+    fn page(&self, number: u32) -> Result<&'a [u8]> {
+        let start = (number as usize)
+            .checked_sub(1)
+            .context("page 0 does not exist")?
+            * self.page_size;
+        self.data
+            .get(start..start + self.page_size)
+            .with_context(|| format!("page {number} is past the end of the file"))
+    }
+
+    fn table_root(&self, name: &str) -> Result<Option<u32>> {
+        for row in self.table_rows(1)? {
+            if let (Some(Value::Text(kind)), Some(Value::Text(table)), Some(Value::Int(root))) =
+                (row.first(), row.get(1), row.get(3))
+                && kind == "table"
+                && table == name
+            {
+                return Ok(Some(*root as u32));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Every record of the table b-tree rooted at `root`, in rowid order.
+    fn table_rows(&self, root: u32) -> Result<Vec<Vec<Value>>> {
+        let mut rows = Vec::new();
+        let mut stack = vec![root];
+        let mut visited = 0usize;
+        while let Some(number) = stack.pop() {
+            visited += 1;
+            if visited > self.data.len() / self.page_size + 1 {
+                bail!("b-tree loop detected");
+            }
+            let page = self.page(number)?;
+            // Page 1 starts with the 100-byte file header.
+            let header = if number == 1 { 100 } else { 0 };
+            let kind = page[header];
+            let cells = u16::from_be_bytes([page[header + 3], page[header + 4]]) as usize;
+            let pointers = header + if kind == 0x05 { 12 } else { 8 };
+            let cell_offset = |i: usize| -> Result<usize> {
+                let at = pointers + i * 2;
+                let bytes = page.get(at..at + 2).context("truncated cell pointer")?;
+                Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+            };
+
+            match kind {
+                0x05 => {
+                    // Interior: children left to right, pushed in reverse.
+                    let right = be_u32(page, header + 8).context("truncated page")?;
+                    stack.push(right);
+                    for i in (0..cells).rev() {
+                        let child = be_u32(page, cell_offset(i)?).context("truncated cell")?;
+                        stack.push(child);
+                    }
+                }
+                0x0d => {
+                    for i in 0..cells {
+                        let payload = self.leaf_payload(page, cell_offset(i)?)?;
+                        rows.push(decode_record(&payload)?);
+                    }
+                }
+                other => bail!("unexpected b-tree page type {other:#x} on page {number}"),
+            }
+        }
+        Ok(rows)
+    }
+
+    fn leaf_payload(&self, page: &[u8], offset: usize) -> Result<Vec<u8>> {
+        let (size, n) = varint(page, offset).context("truncated cell")?;
+        let (_rowid, m) = varint(page, offset + n).context("truncated cell")?;
+        // A payload cannot be larger than the file holding it; anything
+        // bigger is corruption, not a reason to allocate.
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|&size| size <= self.data.len())
+            .with_context(|| format!("cell payload of {size} bytes exceeds the file"))?;
+        let start = offset + n + m;
+
+        let max_local = self.usable - 35;
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (self.usable - 12) * 32 / 255 - 23;
+            let k = min_local + (size - min_local) % (self.usable - 4);
+            if k <= max_local { k } else { min_local }
+        };
+        let mut payload = page
+            .get(start..start + local)
+            .context("truncated payload")?
+            .to_vec();
+
+        let mut next = if local < size {
+            be_u32(page, start + local).context("truncated overflow pointer")?
+        } else {
+            0
+        };
+        let mut seen = HashSet::new();
+        while payload.len() < size {
+            if next == 0 {
+                bail!("overflow chain ends early");
+            }
+            if !seen.insert(next) {
+                bail!("overflow chain loops back to page {next}");
+            }
+            let overflow = self.page(next)?;
+            next = be_u32(overflow, 0).context("truncated overflow page")?;
+            let take = (size - payload.len()).min(self.usable - 4);
+            payload.extend_from_slice(&overflow[4..4 + take]);
+        }
+        Ok(payload)
+    }
+}
+
+fn decode_record(payload: &[u8]) -> Result<Vec<Value>> {
+    let (header_len, n) = varint(payload, 0).context("truncated record")?;
+    let header_len = header_len as usize;
+    let mut types = Vec::new();
+    let mut at = n;
+    while at < header_len {
+        let (serial, n) = varint(payload, at).context("truncated record header")?;
+        types.push(serial);
+        at += n;
+    }
+
+    let mut body = header_len;
+    let mut values = Vec::with_capacity(types.len());
+    for serial in types {
+        let end = |len: usize| body.checked_add(len).context("value length overflows");
+        let int = |len: usize| -> Result<i64> {
+            let bytes = payload.get(body..end(len)?).context("truncated value")?;
+            let mut v: i64 = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+            for &b in bytes {
+                v = (v << 8) | b as i64;
+            }
+            Ok(v)
+        };
+        let (value, len) = match serial {
+            0 => (Value::Null, 0),
+            1 => (Value::Int(int(1)?), 1),
+            2 => (Value::Int(int(2)?), 2),
+            3 => (Value::Int(int(3)?), 3),
+            4 => (Value::Int(int(4)?), 4),
+            5 => (Value::Int(int(6)?), 6),
+            6 => (Value::Int(int(8)?), 8),
+            7 => (Value::Float(f64::from_bits(int(8)? as u64)), 8),
+            8 => (Value::Int(0), 0),
+            9 => (Value::Int(1), 0),
+            n if n >= 12 => {
+                let len = usize::try_from((n - 12) / 2).context("value length overflows")?;
+                let bytes = payload
+                    .get(body..end(len)?)
+                    .context("truncated value")?
+                    .to_vec();
+                if n % 2 == 0 {
+                    (Value::Blob(bytes), len)
+                } else {
+                    (
+                        Value::Text(String::from_utf8_lossy(&bytes).into_owned()),
+                        len,
+                    )
+                }
+            }
+            other => bail!("reserved serial type {other}"),
+        };
+        values.push(value);
+        body += len;
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+        // Values here stay below 2^56, so the 9-byte form is never needed.
+        let mut groups = vec![(v & 0x7f) as u8];
+        v >>= 7;
+        while v > 0 {
+            groups.push((v & 0x7f) as u8 | 0x80);
+            v >>= 7;
+        }
+        out.extend(groups.iter().rev());
+    }
+
+    fn record(values: &[Value]) -> Vec<u8> {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        for value in values {
+            match value {
+                Value::Null => put_varint(&mut header, 0),
+                Value::Int(i) => {
+                    put_varint(&mut header, 1);
+                    body.push(*i as u8);
+                }
+                Value::Text(s) => {
+                    put_varint(&mut header, 13 + 2 * s.len() as u64);
+                    body.extend(s.as_bytes());
+                }
+                Value::Blob(b) => {
+                    put_varint(&mut header, 12 + 2 * b.len() as u64);
+                    body.extend(b);
+                }
+                Value::Float(_) => unreachable!(),
+            }
+        }
+        let mut out = Vec::new();
+        put_varint(&mut out, header.len() as u64 + 1);
+        out.extend(header);
+        out.extend(body);
+        out
+    }
+
+    /// One-leaf-per-table SQLite file with 512-byte pages; payloads larger
+    /// than a page spill into overflow pages appended at the end.
+    fn sqlite_file(tables: &[(&str, Vec<Vec<Value>>)]) -> Vec<u8> {
+        const PAGE: usize = 512;
+        let usable = PAGE;
+        let mut pages: Vec<Vec<u8>> = vec![vec![0; PAGE]];
+        let mut master_rows = Vec::new();
+        let mut leaves = Vec::new();
+        for (i, (name, rows)) in tables.iter().enumerate() {
+            master_rows.push(vec![
+                Value::Text("table".into()),
+                Value::Text(name.to_string()),
+                Value::Text(name.to_string()),
+                Value::Int(i as i64 + 2),
+                Value::Text(String::new()),
+            ]);
+            leaves.push(rows.clone());
+            pages.push(vec![0; PAGE]);
+        }
+
+        let write_leaf = |pages: &mut Vec<Vec<u8>>, index: usize, rows: &[Vec<Value>]| {
+            let header = if index == 0 { 100 } else { 0 };
+            let mut content_end = PAGE;
+            let mut pointers = Vec::new();
+            for (rowid, row) in rows.iter().enumerate() {
+                let payload = record(row);
+                let max_local = usable - 35;
+                let local = if payload.len() <= max_local {
+                    payload.len()
+                } else {
+                    let min_local = (usable - 12) * 32 / 255 - 23;
+                    let k = min_local + (payload.len() - min_local) % (usable - 4);
+                    if k <= max_local { k } else { min_local }
+                };
+                let mut cell = Vec::new();
+                put_varint(&mut cell, payload.len() as u64);
+                put_varint(&mut cell, rowid as u64 + 1);
+                cell.extend(&payload[..local]);
+                if local < payload.len() {
+                    let chunks: Vec<&[u8]> = payload[local..].chunks(usable - 4).collect();
+                    let first = pages.len() as u32 + 1;
+                    cell.extend(first.to_be_bytes());
+                    for (c, chunk) in chunks.iter().enumerate() {
+                        let mut overflow = vec![0; PAGE];
+                        let next = if c + 1 < chunks.len() {
+                            first + c as u32 + 1
+                        } else {
+                            0
+                        };
+                        overflow[..4].copy_from_slice(&next.to_be_bytes());
+                        overflow[4..4 + chunk.len()].copy_from_slice(chunk);
+                        pages.push(overflow);
+                    }
+                }
+                content_end -= cell.len();
+                pages[index][content_end..content_end + cell.len()].copy_from_slice(&cell);
+                pointers.push(content_end as u16);
+            }
+            let page = &mut pages[index];
+            page[header] = 0x0d;
+            page[header + 3..header + 5].copy_from_slice(&(rows.len() as u16).to_be_bytes());
+            page[header + 5..header + 7].copy_from_slice(&(content_end as u16).to_be_bytes());
+            for (i, p) in pointers.iter().enumerate() {
+                let at = header + 8 + i * 2;
+                page[at..at + 2].copy_from_slice(&p.to_be_bytes());
+            }
+        };
+        write_leaf(&mut pages, 0, &master_rows);
+        for (i, rows) in leaves.iter().enumerate() {
+            write_leaf(&mut pages, i + 1, rows);
+        }
+
+        pages[0][..16].copy_from_slice(b"SQLite format 3\0");
+        pages[0][16..18].copy_from_slice(&(PAGE as u16).to_be_bytes());
+        pages.concat()
+    }
+
+    fn rpm_header(fields: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut index = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        for (tag, kind, value) in fields {
+            index.extend(tag.to_be_bytes());
+            index.extend(kind.to_be_bytes());
+            index.extend((data.len() as u32).to_be_bytes());
+            index.extend(1u32.to_be_bytes());
+            data.extend(value);
+        }
+        let mut out = Vec::new();
+        out.extend((fields.len() as u32).to_be_bytes());
+        out.extend((data.len() as u32).to_be_bytes());
+        out.extend(index);
+        out.extend(data);
+        out
+    }
+
+    fn cstr(s: &str) -> Vec<u8> {
+        let mut v = s.as_bytes().to_vec();
+        v.push(0);
+        v
+    }
+
+    #[test]
+    fn test_read_packages_from_sqlite() {
+        let openssl = rpm_header(&[
+            (RPMTAG_NAME, RPM_STRING_TYPE, cstr("openssl-libs")),
+            (RPMTAG_VERSION, RPM_STRING_TYPE, cstr("3.0.7")),
+            (RPMTAG_RELEASE, RPM_STRING_TYPE, cstr("27.el9")),
+            (RPMTAG_EPOCH, RPM_INT32_TYPE, 1u32.to_be_bytes().to_vec()),
+            (RPMTAG_ARCH, RPM_STRING_TYPE, cstr("x86_64")),
+            (
+                RPMTAG_SOURCERPM,
+                RPM_STRING_TYPE,
+                cstr("openssl-3.0.7-27.el9.src.rpm"),
+            ),
+            // Padding pushes the blob across two overflow pages.
+            (5000, 7, vec![0xaa; 1200]),
+        ]);
+        let bash = rpm_header(&[
+            (RPMTAG_NAME, RPM_STRING_TYPE, cstr("bash")),
+            (RPMTAG_VERSION, RPM_STRING_TYPE, cstr("5.1.8")),
+            (RPMTAG_RELEASE, RPM_STRING_TYPE, cstr("9.el9")),
+        ]);
+        let db = sqlite_file(&[
+            (
+                "Name",
+                vec![vec![Value::Text("bash".into()), Value::Int(2)]],
+            ),
+            (
+                "Packages",
+                vec![
+                    vec![Value::Null, Value::Blob(openssl)],
+                    vec![Value::Null, Value::Blob(bash)],
+                ],
+            ),
+        ]);
+
+        let packages = read_packages(&db).unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "openssl-libs");
+        assert_eq!(packages[0].evr(), "1:3.0.7-27.el9");
+        assert_eq!(
+            packages[0].source_rpm.as_deref(),
+            Some("openssl-3.0.7-27.el9.src.rpm")
+        );
+        assert_eq!(packages[1].evr(), "5.1.8-9.el9");
+    }
+
+    #[test]
+    fn test_rejects_corrupt_overflow_and_lengths() {
+        let blob = rpm_header(&[(5000, 7, vec![0xaa; 1200])]);
+        let mut db = sqlite_file(&[("Packages", vec![vec![Value::Null, Value::Blob(blob)]])]);
+        // Pages: 1 sqlite_master, 2 the Packages leaf, 3.. the overflow chain.
+        // Point the first overflow page back at itself.
+        db[2 * 512..2 * 512 + 4].copy_from_slice(&3u32.to_be_bytes());
+        let err = read_packages(&db).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("loops back to page 3"),
+            "{err:#}"
+        );
+
+        // A blob length far beyond the record is an error, not a panic.
+        let mut record = vec![0x0a];
+        record.extend([0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]);
+        assert!(decode_record(&record).is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_sqlite() {
+        assert!(read_packages(b"Berkeley DB").is_err());
+        assert_eq!(varint(&[0x81, 0x00], 0), Some((128, 2)));
+    }
+}
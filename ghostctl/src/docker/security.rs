@@ -48,19 +48,24 @@ fn vulnerability_scanning() {
         .unwrap_or(false);
 
     if !trivy_check {
-        let install = match Confirm::new()
-            .with_prompt("Trivy not found. Install it?")
-            .default(true)
+        let choice = match Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Trivy not found")
+            .items(&[
+                "🔍 Use the built-in scanner",
+                "📥 Install Trivy",
+                "⬅️  Back",
+            ])
+            .default(0)
             .interact_opt()
         {
-            Ok(Some(i)) => i,
+            Ok(Some(c)) => c,
             Ok(None) | Err(_) => return,
         };
 
-        if install {
-            install_trivy();
-        } else {
-            return;
+        match choice {
+            0 => return builtin_image_scan(),
+            1 => install_trivy(),
+            _ => return,
         }
     }
 
@@ -88,6 +93,21 @@ fn vulnerability_scanning() {
     }
 }
 
+fn builtin_image_scan() {
+    let image: String = match Input::new()
+        .with_prompt("Enter image name (e.g., nginx:latest)")
+        .interact_text()
+    {
+        Ok(i) => i,
+        Err(_) => return,
+    };
+
+    match crate::docker::vulnscan::scan_image(crate::command::runner().as_ref(), &image) {
+        Ok(report) => crate::docker::vulnscan::print_report(&report),
+        Err(e) => println!("❌ Scan failed: {e:#}"),
+    }
+}
+
 fn scan_docker_image() {
     let image: String = match Input::new()
         .with_prompt("Enter image name (e.g., nginx:latest)")
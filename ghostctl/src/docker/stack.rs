@@ -0,0 +1,1142 @@
+//! Compose stacks as desired state: `ghostctl docker stack plan|apply|rollback`.
+//!
+//! The desired side is the normalized model printed by
+//! `docker compose config --format json`, so interpolation, `.env` files and
+//! relative paths are resolved exactly as compose would. The running side is
+//! `docker inspect` of the project's containers. `apply` records the image
+//! each service was running before it brings the stack up, and `rollback`
+//! pins those images again through a compose override file.
+
+use crate::command::CommandRunner;
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Deployments kept per project.
+const MAX_HISTORY: usize = 20;
+
+/// `docker compose config --format json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposeModel {
+    pub name: String,
+    #[serde(default)]
+    pub services: BTreeMap<String, ComposeService>,
+    #[serde(default)]
+    pub volumes: BTreeMap<String, ComposeVolume>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposeService {
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub environment: BTreeMap<String, Option<String>>,
+    #[serde(default)]
+    pub ports: Vec<ComposePort>,
+    #[serde(default)]
+    pub volumes: Vec<ComposeMount>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposePort {
+    pub target: u16,
+    #[serde(default)]
+    pub published: Option<serde_json::Value>,
+    #[serde(default)]
+    pub host_ip: Option<String>,
+    #[serde(default)]
+    pub protocol: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposeMount {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    pub target: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposeVolume {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl ComposeModel {
+    /// Image a service runs; build-only services get compose's default name.
+    pub fn image(&self, service: &str) -> String {
+        self.services
+            .get(service)
+            .and_then(|s| s.image.clone())
+            .unwrap_or_else(|| format!("{}-{service}", self.name))
+    }
+
+    fn ports(&self, service: &ComposeService) -> BTreeSet<String> {
+        service
+            .ports
+            .iter()
+            .map(|p| {
+                let published = match &p.published {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(serde_json::Value::Number(n)) => n.to_string(),
+                    _ => String::new(),
+                };
+                port_key(
+                    p.host_ip.as_deref().unwrap_or(""),
+                    &published,
+                    p.target,
+                    p.protocol.as_deref().unwrap_or("tcp"),
+                )
+            })
+            .collect()
+    }
+
+    fn mounts(&self, service: &ComposeService) -> BTreeSet<String> {
+        service
+            .volumes
+            .iter()
+            .filter(|m| m.kind == "bind" || m.kind == "volume")
+            .map(|m| {
+                let source = match (m.kind.as_str(), &m.source) {
+                    ("volume", Some(name)) => self
+                        .volumes
+                        .get(name)
+                        .and_then(|v| v.name.clone())
+                        .unwrap_or_else(|| format!("{}_{name}", self.name)),
+                    ("volume", None) => "<anonymous>".to_string(),
+                    (_, source) => source.clone().unwrap_or_default(),
+                };
+                mount_key(&source, &m.target, m.read_only)
+            })
+            .collect()
+    }
+}
+
+fn port_key(host_ip: &str, published: &str, target: u16, protocol: &str) -> String {
+    let host_ip = if host_ip == "0.0.0.0" { "" } else { host_ip };
+    match (host_ip.is_empty(), published.is_empty()) {
+        (_, true) => format!("{target}/{protocol}"),
+        (true, false) => format!("{published}:{target}/{protocol}"),
+        (false, false) => format!("{host_ip}:{published}:{target}/{protocol}"),
+    }
+}
+
+fn mount_key(source: &str, target: &str, read_only: bool) -> String {
+    format!("{source}:{target}{}", if read_only { ":ro" } else { "" })
+}
+
+/// The parts of `docker inspect <container>` the diff looks at.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Container {
+    pub name: String,
+    /// Image ID (`sha256:...`).
+    pub image: String,
+    pub config: ContainerConfig,
+    #[serde(default)]
+    pub host_config: HostConfig,
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    /// Image reference the container was created from.
+    pub image: String,
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+    #[serde(default)]
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HostConfig {
+    #[serde(default)]
+    pub port_bindings: Option<BTreeMap<String, Option<Vec<PortBinding>>>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PortBinding {
+    #[serde(default)]
+    pub host_ip: String,
+    #[serde(default)]
+    pub host_port: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Mount {
+    #[serde(rename = "Type")]
+    pub kind: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub source: String,
+    pub destination: String,
+    #[serde(rename = "RW", default = "default_rw")]
+    pub rw: bool,
+}
+
+fn default_rw() -> bool {
+    true
+}
+
+/// The parts of `docker image inspect` the diff looks at.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageInfo {
+    pub id: String,
+    #[serde(default)]
+    pub repo_digests: Vec<String>,
+    #[serde(default)]
+    pub config: Option<ImageConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+}
+
+impl Container {
+    pub fn service(&self) -> Option<&str> {
+        self.config
+            .labels
+            .as_ref()?
+            .get("com.docker.compose.service")
+            .map(String::as_str)
+    }
+
+    fn ports(&self) -> BTreeSet<String> {
+        let mut keys = BTreeSet::new();
+        for (spec, bindings) in self.host_config.port_bindings.iter().flatten() {
+            let (target, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+            let Ok(target) = target.parse() else {
+                continue;
+            };
+            for binding in bindings.iter().flatten() {
+                keys.insert(port_key(
+                    &binding.host_ip,
+                    &binding.host_port,
+                    target,
+                    protocol,
+                ));
+            }
+        }
+        keys
+    }
+
+    fn mounts(&self) -> BTreeSet<String> {
+        self.mounts
+            .iter()
+            .filter(|m| m.kind == "bind" || m.kind == "volume")
+            .map(|m| {
+                let source = match (&m.name, m.kind.as_str()) {
+                    // Anonymous volumes are named by a random 64-hex id.
+                    (Some(name), "volume")
+                        if name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()) =>
+                    {
+                        "<anonymous>".to_string()
+                    }
+                    (Some(name), "volume") => name.clone(),
+                    _ => m.source.clone(),
+                };
+                mount_key(&source, &m.destination, !m.rw)
+            })
+            .collect()
+    }
+
+    /// Environment set on the container, minus what the image already sets.
+    fn env(&self, image: Option<&ImageInfo>) -> BTreeMap<String, String> {
+        let inherited: BTreeSet<&String> = image
+            .and_then(|i| i.config.as_ref())
+            .and_then(|c| c.env.as_ref())
+            .map(|env| env.iter().collect())
+            .unwrap_or_default();
+        self.config
+            .env
+            .iter()
+            .flatten()
+            .filter(|entry| !inherited.contains(entry))
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Update,
+    Remove,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub field: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServicePlan {
+    pub service: String,
+    pub action: Action,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StackPlan {
+    pub project: String,
+    pub services: Vec<ServicePlan>,
+}
+
+impl StackPlan {
+    pub fn has_changes(&self) -> bool {
+        self.services.iter().any(|s| s.action != Action::Unchanged)
+    }
+}
+
+fn short_id(id: &str) -> &str {
+    let id = id.trim_start_matches("sha256:");
+    &id[..id.len().min(12)]
+}
+
+fn describe_set_change(from: &BTreeSet<String>, to: &BTreeSet<String>) -> Option<(String, String)> {
+    (from != to).then(|| {
+        let join = |s: &BTreeSet<String>| {
+            if s.is_empty() {
+                "-".to_string()
+            } else {
+                s.iter().cloned().collect::<Vec<_>>().join(", ")
+            }
+        };
+        (join(from), join(to))
+    })
+}
+
+/// `+KEY`/`~KEY`/`-KEY` for the environment compose would set versus the
+/// container's. A bare `KEY` entry takes its value from the environment
+/// compose runs in (`lookup`); when that is unset too, compose leaves the
+/// variable out and the key is not compared at all.
+fn env_changes(
+    desired: &BTreeMap<String, Option<String>>,
+    live: &BTreeMap<String, String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (key, value) in desired {
+        let Some(value) = value.clone().or_else(|| lookup(key)) else {
+            continue;
+        };
+        match live.get(key) {
+            None => changes.push(format!("+{key}")),
+            Some(live) if *live != value => changes.push(format!("~{key}")),
+            _ => {}
+        }
+    }
+    for key in live.keys() {
+        if !desired.contains_key(key) {
+            changes.push(format!("-{key}"));
+        }
+    }
+    changes
+}
+
+/// Diff the compose model against the project's containers.
+///
+/// `images` maps image references and IDs to `docker image inspect` results;
+/// a desired image missing from it has not been pulled yet.
+pub fn diff(
+    model: &ComposeModel,
+    containers: &[Container],
+    images: &BTreeMap<String, ImageInfo>,
+) -> StackPlan {
+    let mut running: BTreeMap<&str, &Container> = BTreeMap::new();
+    for container in containers {
+        if let Some(service) = container.service() {
+            running.entry(service).or_insert(container);
+        }
+    }
+
+    let mut services = Vec::new();
+    for (name, desired) in &model.services {
+        let Some(container) = running.remove(name.as_str()) else {
+            services.push(ServicePlan {
+                service: name.clone(),
+                action: Action::Create,
+                changes: vec![Change {
+                    field: "image",
+                    from: "-".into(),
+                    to: model.image(name),
+                }],
+            });
+            continue;
+        };
+
+        let mut changes = Vec::new();
+        let image = model.image(name);
+        let desired_id = images.get(&image).map(|i| i.id.as_str());
+        if image != container.config.image || desired_id != Some(container.image.as_str()) {
+            changes.push(Change {
+                field: "image",
+                from: format!(
+                    "{} ({})",
+                    container.config.image,
+                    short_id(&container.image)
+                ),
+                to: match desired_id {
+                    Some(id) => format!("{image} ({})", short_id(id)),
+                    None => format!("{image} (not pulled)"),
+                },
+            });
+        }
+
+        let live_env = container.env(images.get(&container.image));
+        let env_changes = env_changes(&desired.environment, &live_env, |key| {
+            std::env::var(key).ok()
+        });
+        if !env_changes.is_empty() {
+            // Values are left out on purpose: environments carry secrets.
+            changes.push(Change {
+                field: "env",
+                from: String::new(),
+                to: env_changes.join(", "),
+            });
+        }
+
+        if let Some((from, to)) = describe_set_change(&container.ports(), &model.ports(desired)) {
+            changes.push(Change {
+                field: "ports",
+                from,
+                to,
+            });
+        }
+        if let Some((from, to)) = describe_set_change(&container.mounts(), &model.mounts(desired)) {
+            changes.push(Change {
+                field: "volumes",
+                from,
+                to,
+            });
+        }
+
+        services.push(ServicePlan {
+            service: name.clone(),
+            action: if changes.is_empty() {
+                Action::Unchanged
+            } else {
+                Action::Update
+            },
+            changes,
+        });
+    }
+
+    for (name, container) in running {
+        services.push(ServicePlan {
+            service: name.to_string(),
+            action: Action::Remove,
+            changes: vec![Change {
+                field: "container",
+                from: container.name.trim_start_matches('/').to_string(),
+                to: "-".into(),
+            }],
+        });
+    }
+
+    StackPlan {
+        project: model.name.clone(),
+        services,
+    }
+}
+
+pub fn print_plan(plan: &StackPlan) {
+    tui::header(&format!("Stack plan: {}", plan.project));
+    for service in &plan.services {
+        let marker = match service.action {
+            Action::Create => "+",
+            Action::Update => "~",
+            Action::Remove => "-",
+            Action::Unchanged => " ",
+        };
+        println!("{marker} {}", service.service);
+        for change in &service.changes {
+            if change.from.is_empty() {
+                println!("    {}: {}", change.field, change.to);
+            } else {
+                println!("    {}: {} -> {}", change.field, change.from, change.to);
+            }
+        }
+    }
+    let count = |a: Action| plan.services.iter().filter(|s| s.action == a).count();
+    println!(
+        "\n{} to create, {} to update, {} to remove, {} unchanged",
+        count(Action::Create),
+        count(Action::Update),
+        count(Action::Remove),
+        count(Action::Unchanged)
+    );
+}
+
+/// Image a service ran before an `apply`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PinnedImage {
+    /// Reference from the compose file at the time, e.g. `nginx:1.25`.
+    pub image: String,
+    /// Local image ID.
+    pub id: String,
+    /// Registry digest (`nginx@sha256:...`) when the image was pulled.
+    #[serde(default)]
+    pub digest: Option<String>,
+}
+
+impl PinnedImage {
+    /// Reference that brings back exactly this image.
+    pub fn pin(&self) -> &str {
+        self.digest.as_deref().unwrap_or(&self.id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deployment {
+    pub applied_at: String,
+    pub dir: String,
+    pub services: BTreeMap<String, PinnedImage>,
+}
+
+/// Pre-apply images per compose project, newest last.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StackHistory {
+    #[serde(default)]
+    pub projects: BTreeMap<String, Vec<Deployment>>,
+}
+
+impl StackHistory {
+    fn path() -> PathBuf {
+        crate::support::state_dir().join("docker-stacks.json")
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn record(&mut self, project: &str, deployment: Deployment) {
+        let history = self.projects.entry(project.to_string()).or_default();
+        history.push(deployment);
+        if history.len() > MAX_HISTORY {
+            history.drain(..history.len() - MAX_HISTORY);
+        }
+    }
+}
+
+/// Images the running containers use, keyed by service.
+pub fn pin_running(
+    containers: &[Container],
+    images: &BTreeMap<String, ImageInfo>,
+) -> BTreeMap<String, PinnedImage> {
+    containers
+        .iter()
+        .filter_map(|c| {
+            let service = c.service()?;
+            let reference = crate::docker::registry::parse_image_reference(
+                c.config.image.split('@').next().unwrap_or_default(),
+            );
+            let name = match &reference.registry {
+                Some(registry) => format!("{registry}/{}", reference.repository),
+                None => reference.repository.clone(),
+            };
+            // Prefer the digest of the repository the service references;
+            // the same image may also be known under a mirror's name.
+            let digest = images.get(&c.image).and_then(|info| {
+                info.repo_digests
+                    .iter()
+                    .find(|d| d.split('@').next() == Some(name.as_str()))
+                    .or(info.repo_digests.first())
+                    .cloned()
+            });
+            Some((
+                service.to_string(),
+                PinnedImage {
+                    image: c.config.image.clone(),
+                    id: c.image.clone(),
+                    digest,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Compose override pinning every service to its recorded image.
+pub fn rollback_override(services: &BTreeMap<String, PinnedImage>) -> String {
+    let mut out = String::from("# Written by `ghostctl docker stack rollback`.\nservices:\n");
+    for (service, pinned) in services {
+        out.push_str(&format!("  {service}:\n    image: \"{}\"\n", pinned.pin()));
+    }
+    out
+}
+
+/// A compose stack directory.
+pub struct Stack {
+    pub dir: PathBuf,
+    pub file: PathBuf,
+}
+
+impl Stack {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("{} does not exist", dir.display()))?;
+        let Some(file) = super::compose::get_compose_filename(&dir) else {
+            bail!("no compose file in {}", dir.display());
+        };
+        Ok(Self {
+            file: dir.join(file),
+            dir,
+        })
+    }
+
+    fn file_arg(&self) -> String {
+        self.file.to_string_lossy().to_string()
+    }
+
+    fn compose(&self, runner: &dyn CommandRunner, extra: &[&str]) -> Result<String> {
+        let file = self.file_arg();
+        let mut args = vec!["compose", "-f", &file];
+        args.extend_from_slice(extra);
+        let result = runner
+            .run("docker", &args)
+            .context("failed to run docker compose")?;
+        if !result.success {
+            bail!(
+                "docker compose {} failed: {}",
+                extra.join(" "),
+                result.stderr.trim()
+            );
+        }
+        Ok(result.stdout)
+    }
+
+    pub fn model(&self, runner: &dyn CommandRunner) -> Result<ComposeModel> {
+        let json = self.compose(runner, &["config", "--format", "json"])?;
+        serde_json::from_str(&json).context("unexpected `docker compose config` output")
+    }
+}
+
+/// Containers of a compose project, including stopped ones.
+pub fn project_containers(runner: &dyn CommandRunner, project: &str) -> Result<Vec<Container>> {
+    let filter = format!("label=com.docker.compose.project={project}");
+    let ids = runner
+        .run("docker", &["ps", "-aq", "--filter", &filter])
+        .context("failed to run docker ps")?;
+    if !ids.success {
+        bail!("docker ps failed: {}", ids.stderr.trim());
+    }
+    let ids: Vec<&str> = ids.stdout.split_whitespace().collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = vec!["inspect"];
+    args.extend(ids);
+    let inspect = runner
+        .run("docker", &args)
+        .context("failed to run docker inspect")?;
+    if !inspect.success {
+        bail!("docker inspect failed: {}", inspect.stderr.trim());
+    }
+    serde_json::from_str(&inspect.stdout).context("unexpected `docker inspect` output")
+}
+
+/// `docker image inspect` for the desired references and running image IDs.
+/// Images that are not present locally are left out.
+pub fn inspect_images(
+    runner: &dyn CommandRunner,
+    model: &ComposeModel,
+    containers: &[Container],
+) -> BTreeMap<String, ImageInfo> {
+    let refs: BTreeSet<String> = model
+        .services
+        .keys()
+        .map(|s| model.image(s))
+        .chain(containers.iter().map(|c| c.image.clone()))
+        .collect();
+    refs.into_iter()
+        .filter_map(|r| {
+            let out = runner.run("docker", &["image", "inspect", &r]).ok()?;
+            if !out.success {
+                return None;
+            }
+            let info: Vec<ImageInfo> = serde_json::from_str(&out.stdout).ok()?;
+            Some((r, info.into_iter().next()?))
+        })
+        .collect()
+}
+
+struct Snapshot {
+    model: ComposeModel,
+    containers: Vec<Container>,
+    images: BTreeMap<String, ImageInfo>,
+}
+
+fn snapshot(runner: &dyn CommandRunner, stack: &Stack) -> Result<Snapshot> {
+    let model = stack.model(runner)?;
+    let containers = project_containers(runner, &model.name)?;
+    let images = inspect_images(runner, &model, &containers);
+    Ok(Snapshot {
+        model,
+        containers,
+        images,
+    })
+}
+
+pub fn plan(runner: &dyn CommandRunner, dir: &Path, pull: bool) -> Result<StackPlan> {
+    let stack = Stack::open(dir)?;
+    if pull {
+        stack.compose(runner, &["pull", "--quiet"])?;
+    }
+    let snap = snapshot(runner, &stack)?;
+    Ok(diff(&snap.model, &snap.containers, &snap.images))
+}
+
+/// Pull, diff, record the running images and bring the stack up.
+pub fn apply(runner: &dyn CommandRunner, dir: &Path, history: &mut StackHistory) -> Result<bool> {
+    let stack = Stack::open(dir)?;
+    if is_dry_run() {
+        let snap = snapshot(runner, &stack)?;
+        print_plan(&diff(&snap.model, &snap.containers, &snap.images));
+        println!(
+            "[DRY RUN] Would run: docker compose -f {} pull && up -d --remove-orphans",
+            stack.file.display()
+        );
+        return Ok(false);
+    }
+
+    stack.compose(runner, &["pull", "--quiet"])?;
+    let snap = snapshot(runner, &stack)?;
+    let plan = diff(&snap.model, &snap.containers, &snap.images);
+    print_plan(&plan);
+    if !plan.has_changes() {
+        tui::success("Stack is up to date");
+        return Ok(false);
+    }
+    if !tui::confirm("Apply these changes?", true) {
+        return Ok(false);
+    }
+
+    let pinned = pin_running(&snap.containers, &snap.images);
+    if !pinned.is_empty() {
+        history.record(
+            &plan.project,
+            Deployment {
+                applied_at: chrono::Utc::now().to_rfc3339(),
+                dir: stack.dir.to_string_lossy().to_string(),
+                services: pinned,
+            },
+        );
+        history.save()?;
+    }
+
+    // A pin left behind by an earlier rollback would mask the new images.
+    let _ = std::fs::remove_file(override_path(&plan.project));
+    stack
+        .compose(runner, &["up", "-d", "--remove-orphans"])
+        .with_context(|| {
+            format!(
+                "apply failed; `ghostctl docker stack rollback {}` restores the previous images",
+                stack.dir.display()
+            )
+        })?;
+    Ok(true)
+}
+
+fn override_path(project: &str) -> PathBuf {
+    crate::support::state_dir()
+        .join("stacks")
+        .join(format!("{project}.rollback.yml"))
+}
+
+/// Bring the stack back to the images recorded by the last `apply`.
+pub fn rollback(
+    runner: &dyn CommandRunner,
+    dir: &Path,
+    history: &mut StackHistory,
+) -> Result<Option<PathBuf>> {
+    let stack = Stack::open(dir)?;
+    let model = stack.model(runner)?;
+    let Some(deployment) = history
+        .projects
+        .get(&model.name)
+        .and_then(|h| h.last())
+        .cloned()
+    else {
+        bail!("no recorded deployment for stack '{}'", model.name);
+    };
+
+    tui::header(&format!(
+        "Rollback {} to images from {}",
+        model.name, deployment.applied_at
+    ));
+    for (service, pinned) in &deployment.services {
+        println!("  {service}: {} -> {}", model.image(service), pinned.pin());
+    }
+    if is_dry_run() {
+        println!("[DRY RUN] Would pin these images and run docker compose up -d");
+        return Ok(None);
+    }
+    if !tui::confirm("Roll back?", true) {
+        return Ok(None);
+    }
+
+    let path = override_path(&model.name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, rollback_override(&deployment.services))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    let override_file = path.to_string_lossy().to_string();
+    stack.compose(runner, &["-f", &override_file, "up", "-d"])?;
+
+    if let Some(entries) = history.projects.get_mut(&model.name) {
+        entries.pop();
+    }
+    history.save()?;
+    Ok(Some(path))
+}
+
+pub fn command() -> Command {
+    let dir = || {
+        Arg::new("dir")
+            .required(true)
+            .help("Directory containing the compose file")
+    };
+    Command::new("stack")
+        .about("Plan, apply and roll back compose stacks")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("plan")
+                .about("Diff a compose file against the running containers")
+                .arg(dir())
+                .arg(
+                    Arg::new("pull")
+                        .long("pull")
+                        .action(ArgAction::SetTrue)
+                        .help("Pull images first so digest changes show up"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Record the running images, then pull and bring the stack up")
+                .arg(dir()),
+        )
+        .subcommand(
+            Command::new("rollback")
+                .about("Restore the images recorded by the last apply")
+                .arg(dir()),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let runner = crate::command::runner();
+    let dir = |m: &ArgMatches| PathBuf::from(m.get_one::<String>("dir").expect("dir is required"));
+    match matches.subcommand() {
+        Some(("plan", m)) => {
+            let plan = plan(runner.as_ref(), &dir(m), m.get_flag("pull"))?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else {
+                print_plan(&plan);
+            }
+        }
+        Some(("apply", m)) => {
+            let mut history = StackHistory::load();
+            if apply(runner.as_ref(), &dir(m), &mut history)? {
+                tui::success("Stack applied");
+            }
+        }
+        Some(("rollback", m)) => {
+            let mut history = StackHistory::load();
+            if let Some(path) = rollback(runner.as_ref(), &dir(m), &mut history)? {
+                tui::success("Stack rolled back");
+                tui::info(&format!(
+                    "Images are pinned by {}; the next `stack apply` removes the pin",
+                    path.display()
+                ));
+            }
+        }
+        _ => unreachable!("subcommand_required"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    const MODEL: &str = r#"{
+        "name": "app",
+        "services": {
+            "web": {
+                "image": "nginx:1.27",
+                "environment": {"TZ": "UTC", "MODE": "prod"},
+                "ports": [{"mode": "ingress", "target": 80, "published": "8080", "protocol": "tcp"}],
+                "volumes": [
+                    {"type": "bind", "source": "/srv/app/html", "target": "/usr/share/nginx/html", "read_only": true},
+                    {"type": "volume", "source": "cache", "target": "/var/cache/nginx"}
+                ]
+            },
+            "worker": {"build": {"context": "."}},
+            "db": {"image": "postgres:16"}
+        },
+        "volumes": {"cache": {"name": "app_cache"}}
+    }"#;
+
+    const CONTAINERS: &str = r#"[
+        {
+            "Name": "/app-web-1",
+            "Image": "sha256:1111111111111111aaaa",
+            "Config": {
+                "Image": "nginx:1.25",
+                "Env": ["TZ=UTC", "MODE=dev", "OLD=1", "PATH=/usr/bin", "NGINX_VERSION=1.25"],
+                "Labels": {"com.docker.compose.project": "app", "com.docker.compose.service": "web"}
+            },
+            "HostConfig": {"PortBindings": {"80/tcp": [{"HostIp": "", "HostPort": "8080"}]}},
+            "Mounts": [
+                {"Type": "bind", "Source": "/srv/app/html", "Destination": "/usr/share/nginx/html", "RW": false},
+                {"Type": "volume", "Name": "app_cache", "Source": "/var/lib/docker/volumes/app_cache/_data", "Destination": "/var/cache/nginx", "RW": true}
+            ]
+        },
+        {
+            "Name": "/app-db-1",
+            "Image": "sha256:2222222222222222bbbb",
+            "Config": {
+                "Image": "postgres:16",
+                "Env": null,
+                "Labels": {"com.docker.compose.service": "db"}
+            },
+            "HostConfig": {"PortBindings": {}},
+            "Mounts": []
+        },
+        {
+            "Name": "/app-cron-1",
+            "Image": "sha256:3333",
+            "Config": {"Image": "alpine", "Labels": {"com.docker.compose.service": "cron"}}
+        }
+    ]"#;
+
+    fn images() -> BTreeMap<String, ImageInfo> {
+        let mut images = BTreeMap::new();
+        images.insert(
+            "sha256:1111111111111111aaaa".to_string(),
+            ImageInfo {
+                id: "sha256:1111111111111111aaaa".into(),
+                repo_digests: vec!["nginx@sha256:old".into()],
+                config: Some(ImageConfig {
+                    env: Some(vec!["PATH=/usr/bin".into(), "NGINX_VERSION=1.25".into()]),
+                }),
+            },
+        );
+        images.insert(
+            "postgres:16".to_string(),
+            ImageInfo {
+                id: "sha256:2222222222222222bbbb".into(),
+                ..Default::default()
+            },
+        );
+        images
+    }
+
+    #[test]
+    fn test_diff_detects_image_env_and_orphans() {
+        let model: ComposeModel = serde_json::from_str(MODEL).unwrap();
+        let containers: Vec<Container> = serde_json::from_str(CONTAINERS).unwrap();
+        let plan = diff(&model, &containers, &images());
+        let by_name = |n: &str| plan.services.iter().find(|s| s.service == n).unwrap();
+
+        let web = by_name("web");
+        assert_eq!(web.action, Action::Update);
+        let fields: Vec<&str> = web.changes.iter().map(|c| c.field).collect();
+        assert_eq!(fields, vec!["image", "env"]);
+        assert_eq!(web.changes[0].to, "nginx:1.27 (not pulled)");
+        assert_eq!(web.changes[1].to, "~MODE, -OLD");
+
+        assert_eq!(by_name("db").action, Action::Unchanged);
+        assert_eq!(by_name("worker").action, Action::Create);
+        assert_eq!(by_name("worker").changes[0].to, "app-worker");
+        assert_eq!(by_name("cron").action, Action::Remove);
+        assert!(plan.has_changes());
+    }
+
+    #[test]
+    fn test_env_changes_resolve_bare_keys() {
+        let desired: BTreeMap<String, Option<String>> = [
+            ("TZ", Some("UTC")),
+            ("TOKEN", None),
+            ("PROXY", None),
+            ("UNSET", None),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.map(str::to_string)))
+        .collect();
+        let live: BTreeMap<String, String> = [("TZ", "UTC"), ("TOKEN", "old"), ("STALE", "1")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let lookup = |key: &str| match key {
+            "TOKEN" => Some("new".to_string()),
+            "PROXY" => Some("http://proxy:3128".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            env_changes(&desired, &live, lookup),
+            vec!["+PROXY", "~TOKEN", "-STALE"]
+        );
+    }
+
+    #[test]
+    fn test_diff_reports_port_and_volume_changes() {
+        let mut model: ComposeModel = serde_json::from_str(MODEL).unwrap();
+        let web = model.services.get_mut("web").unwrap();
+        web.image = Some("nginx:1.25".into());
+        web.environment.insert("MODE".into(), Some("dev".into()));
+        web.environment.insert("OLD".into(), None);
+        web.ports[0].host_ip = Some("127.0.0.1".into());
+        web.volumes[0].read_only = false;
+        let containers: Vec<Container> = serde_json::from_str(CONTAINERS).unwrap();
+        let mut images = images();
+        let running = images["sha256:1111111111111111aaaa"].clone();
+        images.insert("nginx:1.25".into(), running);
+
+        let plan = diff(&model, &containers, &images);
+        let web = plan.services.iter().find(|s| s.service == "web").unwrap();
+        assert_eq!(
+            web.changes,
+            vec![
+                Change {
+                    field: "ports",
+                    from: "8080:80/tcp".into(),
+                    to: "127.0.0.1:8080:80/tcp".into(),
+                },
+                Change {
+                    field: "volumes",
+                    from: "/srv/app/html:/usr/share/nginx/html:ro, app_cache:/var/cache/nginx"
+                        .into(),
+                    to: "/srv/app/html:/usr/share/nginx/html, app_cache:/var/cache/nginx".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pin_running_prefers_matching_repo_digest() {
+        let containers: Vec<Container> = serde_json::from_str(CONTAINERS).unwrap();
+        let mut images = images();
+        images
+            .get_mut("sha256:1111111111111111aaaa")
+            .unwrap()
+            .repo_digests = vec![
+            "registry.lan/mirror/other@sha256:zzz".into(),
+            "nginx@sha256:old".into(),
+        ];
+        let pinned = pin_running(&containers, &images);
+        assert_eq!(pinned["web"].pin(), "nginx@sha256:old");
+        assert_eq!(pinned["db"].pin(), "sha256:2222222222222222bbbb");
+        assert_eq!(pinned["cron"].digest, None);
+
+        let yaml = rollback_override(&pinned);
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            parsed["services"]["web"]["image"].as_str(),
+            Some("nginx@sha256:old")
+        );
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = StackHistory::default();
+        for i in 0..(MAX_HISTORY + 5) {
+            history.record(
+                "app",
+                Deployment {
+                    applied_at: i.to_string(),
+                    dir: "/srv/app".into(),
+                    services: BTreeMap::new(),
+                },
+            );
+        }
+        let entries = &history.projects["app"];
+        assert_eq!(entries.len(), MAX_HISTORY);
+        assert_eq!(entries[0].applied_at, "5");
+    }
+
+    #[test]
+    fn test_plan_reads_compose_and_docker() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("compose.yaml"), "services: {}\n").unwrap();
+        let file = dir
+            .path()
+            .canonicalize()
+            .unwrap()
+            .join("compose.yaml")
+            .to_string_lossy()
+            .to_string();
+
+        let mock = MockRunner::new();
+        mock.mock_command(
+            "docker",
+            &["compose", "-f", &file, "config", "--format", "json"],
+            CommandResult::ok(MODEL),
+        );
+        mock.mock_command(
+            "docker",
+            &[
+                "ps",
+                "-aq",
+                "--filter",
+                "label=com.docker.compose.project=app",
+            ],
+            CommandResult::ok("c1\nc2\nc3\n"),
+        );
+        mock.mock_command(
+            "docker",
+            &["inspect", "c1", "c2", "c3"],
+            CommandResult::ok(CONTAINERS),
+        );
+        mock.set_default(CommandResult::err("No such image", 1));
+
+        let plan = plan(&mock, dir.path(), false).unwrap();
+        assert_eq!(plan.project, "app");
+        assert_eq!(plan.services.len(), 4);
+        assert!(mock.was_called("image inspect nginx:1.27"));
+        assert!(!mock.was_called("pull"));
+    }
+}
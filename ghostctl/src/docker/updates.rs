@@ -0,0 +1,771 @@
+//! Image update detection for compose stacks (`ghostctl docker updates`).
+//!
+//! Every service image in every stack is resolved against its registry's v2
+//! API: the manifest digest behind the tag is compared with the digest the
+//! local image was pulled at, and the tag list is searched for semver-newer
+//! tags of the same shape (`1.25` -> `1.27`, `v2.1.0-alpine` ->
+//! `v2.3.1-alpine`). Registries that require auth get a bearer token using
+//! the credentials from the Docker config.json.
+
+use super::registry::{ImageReference, docker_credentials, parse_image_reference};
+use super::stack::{ImageInfo, Stack, StackHistory};
+use crate::command::CommandRunner;
+use crate::tui;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, WWW_AUTHENTICATE};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Manifest types a tag may resolve to; multi-arch indexes first so the
+/// digest matches what `docker pull` records in `RepoDigests`.
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// Upper bound on `tags/list` pages followed per repository.
+const MAX_TAG_PAGES: usize = 20;
+
+/// Where a reference lives on the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// `https://registry-1.docker.io`, `http://localhost:5000`, ...
+    pub base: String,
+    /// Repository path, `library/` added for official Hub images.
+    pub repository: String,
+}
+
+impl Endpoint {
+    pub fn for_reference(reference: &ImageReference) -> Self {
+        let host = match reference.registry.as_deref() {
+            None | Some("docker.io") | Some("index.docker.io") => "registry-1.docker.io",
+            Some(host) => host,
+        };
+        let repository = if host == "registry-1.docker.io" && !reference.repository.contains('/') {
+            format!("library/{}", reference.repository)
+        } else {
+            reference.repository.clone()
+        };
+        // Docker itself talks plain HTTP to loopback registries.
+        let loopback = ["localhost", "127.", "[::1]"]
+            .iter()
+            .any(|p| host.starts_with(p));
+        Self {
+            base: format!("{}://{host}", if loopback { "http" } else { "https" }),
+            repository,
+        }
+    }
+
+    fn host(&self) -> &str {
+        self.base.split("://").nth(1).unwrap_or(&self.base)
+    }
+}
+
+/// A parsed `WWW-Authenticate` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Challenge {
+    Basic,
+    Bearer {
+        realm: String,
+        service: Option<String>,
+        scope: Option<String>,
+    },
+}
+
+pub fn parse_challenge(header: &str) -> Option<Challenge> {
+    let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if scheme.eq_ignore_ascii_case("basic") {
+        return Some(Challenge::Basic);
+    }
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    // key="value" pairs; values may contain commas (scope lists).
+    let mut values = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => match after.find(',') {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, ""),
+            },
+        };
+        values.insert(key, value.to_string());
+        rest = remaining;
+    }
+    Some(Challenge::Bearer {
+        realm: values.remove("realm")?,
+        service: values.remove("service"),
+        scope: values.remove("scope"),
+    })
+}
+
+/// Minimal registry v2 client: manifest digests and tag lists.
+pub struct RegistryClient {
+    http: Client,
+    docker_config: serde_json::Value,
+    /// `Authorization` header values per host and repository.
+    auth: HashMap<(String, String), String>,
+}
+
+impl RegistryClient {
+    pub fn new(docker_config: serde_json::Value) -> Result<Self> {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent("ghostctl")
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            http,
+            docker_config,
+            auth: HashMap::new(),
+        })
+    }
+
+    /// Client using `$DOCKER_CONFIG/config.json` or `~/.docker/config.json`.
+    pub fn from_docker_config() -> Result<Self> {
+        let path = std::env::var_os("DOCKER_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|h| h.join(".docker")))
+            .map(|dir| dir.join("config.json"));
+        let config = path
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or(serde_json::Value::Null);
+        Self::new(config)
+    }
+
+    fn authorize(&mut self, endpoint: &Endpoint, challenge: &Challenge) -> Result<String> {
+        let credentials = docker_credentials(&self.docker_config, endpoint.host());
+        let header = match challenge {
+            Challenge::Basic => {
+                let Some((user, pass)) = credentials else {
+                    bail!(
+                        "{} requires credentials; run `docker login`",
+                        endpoint.host()
+                    );
+                };
+                use base64::Engine;
+                format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD.encode(format!("{user}:{pass}"))
+                )
+            }
+            Challenge::Bearer {
+                realm,
+                service,
+                scope,
+            } => {
+                let scope = scope
+                    .clone()
+                    .unwrap_or_else(|| format!("repository:{}:pull", endpoint.repository));
+                let mut query = vec![("scope", scope)];
+                if let Some(service) = service {
+                    query.push(("service", service.clone()));
+                }
+                let url = reqwest::Url::parse_with_params(realm, &query)
+                    .with_context(|| format!("invalid token realm {realm}"))?;
+                let mut request = self.http.get(url);
+                if let Some((user, pass)) = credentials {
+                    request = request.basic_auth(user, Some(pass));
+                }
+                let response = request
+                    .send()
+                    .with_context(|| format!("token request to {realm} failed"))?;
+                if !response.status().is_success() {
+                    bail!("token request to {realm} returned {}", response.status());
+                }
+                let body: serde_json::Value = response.json().context("invalid token response")?;
+                let token = body["token"]
+                    .as_str()
+                    .or_else(|| body["access_token"].as_str())
+                    .context("token response has no token")?;
+                format!("Bearer {token}")
+            }
+        };
+        self.auth.insert(
+            (endpoint.host().to_string(), endpoint.repository.clone()),
+            header.clone(),
+        );
+        Ok(header)
+    }
+
+    /// Send a request, answering one auth challenge if the registry asks.
+    fn send(&mut self, endpoint: &Endpoint, head: bool, url: &str) -> Result<Response> {
+        let key = (endpoint.host().to_string(), endpoint.repository.clone());
+        let http = self.http.clone();
+        let build = |auth: Option<&String>| {
+            let mut request = if head { http.head(url) } else { http.get(url) };
+            request = request.header(ACCEPT, MANIFEST_TYPES);
+            if let Some(auth) = auth {
+                request = request.header(AUTHORIZATION, auth);
+            }
+            request
+        };
+
+        let response = build(self.auth.get(&key))
+            .send()
+            .with_context(|| format!("request to {url} failed"))?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_challenge)
+            .with_context(|| format!("{url} returned 401 without a usable challenge"))?;
+        let auth = self.authorize(endpoint, &challenge)?;
+        build(Some(&auth))
+            .send()
+            .with_context(|| format!("request to {url} failed"))
+    }
+
+    /// Digest the registry currently serves for the reference's tag.
+    pub fn manifest_digest(&mut self, reference: &ImageReference) -> Result<String> {
+        let endpoint = Endpoint::for_reference(reference);
+        let url = format!(
+            "{}/v2/{}/manifests/{}",
+            endpoint.base,
+            endpoint.repository,
+            reference.tag_or_latest()
+        );
+        let response = self.send(&endpoint, true, &url)?;
+        if !response.status().is_success() {
+            bail!("{url} returned {}", response.status());
+        }
+        if let Some(digest) = digest_header(response.headers()) {
+            return Ok(digest);
+        }
+
+        // Some registries omit the header on HEAD; hash the manifest instead.
+        let response = self.send(&endpoint, false, &url)?;
+        if let Some(digest) = digest_header(response.headers()) {
+            return Ok(digest);
+        }
+        let body = response.bytes().context("failed to read manifest")?;
+        use sha2::Digest;
+        Ok(format!(
+            "sha256:{}",
+            crate::utils::bytes_to_hex(sha2::Sha256::digest(&body))
+        ))
+    }
+
+    /// All tags of the reference's repository.
+    pub fn tags(&mut self, reference: &ImageReference) -> Result<Vec<String>> {
+        let endpoint = Endpoint::for_reference(reference);
+        let mut url = format!(
+            "{}/v2/{}/tags/list?n=1000",
+            endpoint.base, endpoint.repository
+        );
+        let mut tags = Vec::new();
+        for _ in 0..MAX_TAG_PAGES {
+            let response = self.send(&endpoint, false, &url)?;
+            if !response.status().is_success() {
+                bail!("{url} returned {}", response.status());
+            }
+            let next = response
+                .headers()
+                .get("link")
+                .and_then(|v| v.to_str().ok())
+                .and_then(next_link);
+            let body: serde_json::Value = response.json().context("invalid tag list")?;
+            tags.extend(
+                body["tags"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|t| t.as_str().map(String::from)),
+            );
+            match next {
+                Some(path) if path.starts_with('/') => url = format!("{}{path}", endpoint.base),
+                Some(absolute) => url = absolute,
+                None => break,
+            }
+        }
+        Ok(tags)
+    }
+}
+
+fn digest_header(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("docker-content-digest")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+/// Target of a `Link: <...>; rel="next"` header.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|part| {
+        let (target, params) = part.split_once(';')?;
+        params.contains("rel=\"next\"").then(|| {
+            target
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+/// A tag read as a version: optional `v`, dotted numbers, optional suffix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagVersion {
+    pub v_prefix: bool,
+    pub numbers: Vec<u64>,
+    pub suffix: String,
+}
+
+impl TagVersion {
+    pub fn parse(tag: &str) -> Option<Self> {
+        let (v_prefix, rest) = match tag.strip_prefix('v') {
+            Some(rest) => (true, rest),
+            None => (false, tag),
+        };
+        let (version, suffix) = match rest.split_once('-') {
+            Some((version, suffix)) => (version, format!("-{suffix}")),
+            None => (rest, String::new()),
+        };
+        let numbers = version
+            .split('.')
+            .map(|n| n.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        (!numbers.is_empty() && numbers.len() <= 4).then_some(Self {
+            v_prefix,
+            numbers,
+            suffix,
+        })
+    }
+
+    /// Same prefix, precision and variant suffix, so it is a drop-in tag.
+    fn same_shape(&self, other: &Self) -> bool {
+        self.v_prefix == other.v_prefix
+            && self.numbers.len() == other.numbers.len()
+            && self.suffix == other.suffix
+    }
+}
+
+/// Tags of the same shape as `current` with a higher version, newest first.
+pub fn newer_tags(current: &str, tags: &[String]) -> Vec<String> {
+    let Some(current) = TagVersion::parse(current) else {
+        return Vec::new();
+    };
+    let mut newer: Vec<(Vec<u64>, &String)> = tags
+        .iter()
+        .filter_map(|tag| {
+            let version = TagVersion::parse(tag)?;
+            (version.same_shape(&current) && version.numbers > current.numbers)
+                .then_some((version.numbers, tag))
+        })
+        .collect();
+    newer.sort_by(|a, b| b.0.cmp(&a.0));
+    newer.into_iter().map(|(_, tag)| tag.clone()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    /// Local digest matches the registry.
+    UpToDate,
+    /// The tag now points at a different digest.
+    Outdated,
+    /// Present locally without a registry digest for this repository (built
+    /// or loaded here), so there is nothing to compare with.
+    Local,
+    /// The image is not present locally.
+    NotPulled,
+    /// The reference pins a digest; nothing can move.
+    Pinned,
+    /// Registry lookup failed.
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageCheck {
+    pub image: String,
+    pub status: UpdateStatus,
+    pub local_digest: Option<String>,
+    pub remote_digest: Option<String>,
+    pub newer_tags: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceUpdate {
+    pub stack: String,
+    pub dir: String,
+    pub service: String,
+    #[serde(flatten)]
+    pub check: ImageCheck,
+}
+
+/// Digest recorded in `RepoDigests` for the reference's repository.
+pub fn local_digest(reference: &ImageReference, info: &ImageInfo) -> Option<String> {
+    let name = match &reference.registry {
+        Some(registry) => format!("{registry}/{}", reference.repository),
+        None => reference.repository.clone(),
+    };
+    info.repo_digests.iter().find_map(|entry| {
+        let (repo, digest) = entry.split_once('@')?;
+        let repo = repo.trim_start_matches("docker.io/");
+        (repo == name || repo == format!("library/{name}")).then(|| digest.to_string())
+    })
+}
+
+fn inspect_local(runner: &dyn CommandRunner, image: &str) -> Option<ImageInfo> {
+    let out = runner.run("docker", &["image", "inspect", image]).ok()?;
+    if !out.success {
+        return None;
+    }
+    let infos: Vec<ImageInfo> = serde_json::from_str(&out.stdout).ok()?;
+    infos.into_iter().next()
+}
+
+pub fn check_image(
+    client: &mut RegistryClient,
+    runner: &dyn CommandRunner,
+    image: &str,
+) -> ImageCheck {
+    let mut check = ImageCheck {
+        image: image.to_string(),
+        status: UpdateStatus::Error,
+        local_digest: None,
+        remote_digest: None,
+        newer_tags: Vec::new(),
+        error: None,
+    };
+    if image.contains('@') {
+        check.status = UpdateStatus::Pinned;
+        return check;
+    }
+    let reference = parse_image_reference(image);
+    let local = inspect_local(runner, image);
+    check.local_digest = local.as_ref().and_then(|i| local_digest(&reference, i));
+
+    match client.manifest_digest(&reference) {
+        Ok(remote) => {
+            check.status = match (&local, &check.local_digest) {
+                (None, _) => UpdateStatus::NotPulled,
+                (Some(_), Some(digest)) if *digest == remote => UpdateStatus::UpToDate,
+                (Some(_), Some(_)) => UpdateStatus::Outdated,
+                (Some(_), None) => UpdateStatus::Local,
+            };
+            check.remote_digest = Some(remote);
+        }
+        Err(e) => {
+            check.error = Some(format!("{e:#}"));
+            return check;
+        }
+    }
+
+    if TagVersion::parse(reference.tag_or_latest()).is_some() {
+        match client.tags(&reference) {
+            Ok(tags) => check.newer_tags = newer_tags(reference.tag_or_latest(), &tags),
+            Err(e) => check.error = Some(format!("tag list: {e:#}")),
+        }
+    }
+    check
+}
+
+/// Check every service image of every stack, querying each image once.
+pub fn scan(
+    client: &mut RegistryClient,
+    runner: &dyn CommandRunner,
+    dirs: &[PathBuf],
+) -> Result<Vec<ServiceUpdate>> {
+    let mut cache: BTreeMap<String, ImageCheck> = BTreeMap::new();
+    let mut updates = Vec::new();
+    for dir in dirs {
+        let stack = Stack::open(dir)?;
+        let model = stack.model(runner)?;
+        for (service, definition) in &model.services {
+            // Build-only services have no upstream to compare with.
+            let Some(image) = &definition.image else {
+                continue;
+            };
+            let check = cache
+                .entry(image.clone())
+                .or_insert_with(|| check_image(client, runner, image))
+                .clone();
+            updates.push(ServiceUpdate {
+                stack: model.name.clone(),
+                dir: stack.dir.to_string_lossy().to_string(),
+                service: service.clone(),
+                check,
+            });
+        }
+    }
+    Ok(updates)
+}
+
+pub fn print_report(updates: &[ServiceUpdate]) {
+    tui::header("Image updates");
+    println!(
+        "{:<16} {:<16} {:<40} {:<13} NEWER TAGS",
+        "STACK", "SERVICE", "IMAGE", "STATUS"
+    );
+    for u in updates {
+        let status = match u.check.status {
+            UpdateStatus::UpToDate => "up-to-date",
+            UpdateStatus::Outdated => "outdated",
+            UpdateStatus::Local => "local/unknown",
+            UpdateStatus::NotPulled => "not pulled",
+            UpdateStatus::Pinned => "pinned",
+            UpdateStatus::Error => "error",
+        };
+        let newer = u
+            .check
+            .newer_tags
+            .iter()
+            .take(3)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{:<16} {:<16} {:<40} {:<13} {}",
+            u.stack, u.service, u.check.image, status, newer
+        );
+        if let Some(error) = &u.check.error {
+            tui::warn(&format!("  {}: {error}", u.check.image));
+        }
+    }
+    let outdated = updates
+        .iter()
+        .filter(|u| u.check.status == UpdateStatus::Outdated)
+        .count();
+    let with_newer = updates
+        .iter()
+        .filter(|u| !u.check.newer_tags.is_empty())
+        .count();
+    println!("\n{outdated} outdated service(s), {with_newer} with newer tags available");
+}
+
+pub fn command() -> Command {
+    Command::new("updates")
+        .about("Check compose stack images against their registries")
+        .arg(
+            Arg::new("dirs")
+                .num_args(0..)
+                .help("Stack directories (default: discovered compose stacks)"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+        .arg(
+            Arg::new("apply")
+                .long("apply")
+                .action(ArgAction::SetTrue)
+                .help("Pull and recreate stacks with outdated images (recorded for rollback)"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let dirs: Vec<PathBuf> = match matches.get_many::<String>("dirs") {
+        Some(dirs) => dirs.map(PathBuf::from).collect(),
+        None => super::compose::find_compose_stacks(),
+    };
+    if dirs.is_empty() {
+        bail!("no compose stacks found; pass stack directories explicitly");
+    }
+
+    let runner = crate::command::runner();
+    let mut client = RegistryClient::from_docker_config()?;
+    let updates = scan(&mut client, runner.as_ref(), &dirs)?;
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&updates)?);
+    } else {
+        print_report(&updates);
+    }
+
+    if matches.get_flag("apply") {
+        let mut stale: Vec<&str> = updates
+            .iter()
+            .filter(|u| u.check.status == UpdateStatus::Outdated)
+            .map(|u| u.dir.as_str())
+            .collect();
+        stale.dedup();
+        let mut history = StackHistory::load();
+        for dir in stale {
+            super::stack::apply(runner.as_ref(), std::path::Path::new(dir), &mut history)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+    use crate::test_server::{Reply, Request, serve_http};
+    use std::sync::{Arc, OnceLock};
+
+    #[test]
+    fn test_endpoint_for_reference() {
+        let hub = Endpoint::for_reference(&parse_image_reference("nginx:1.25"));
+        assert_eq!(hub.base, "https://registry-1.docker.io");
+        assert_eq!(hub.repository, "library/nginx");
+
+        let ghcr = Endpoint::for_reference(&parse_image_reference("ghcr.io/home/app:v1"));
+        assert_eq!(ghcr.base, "https://ghcr.io");
+        assert_eq!(ghcr.repository, "home/app");
+
+        let local = Endpoint::for_reference(&parse_image_reference("localhost:5000/tools/x"));
+        assert_eq!(local.base, "http://localhost:5000");
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        assert_eq!(
+            parse_challenge(
+                r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull,push""#
+            ),
+            Some(Challenge::Bearer {
+                realm: "https://auth.docker.io/token".into(),
+                service: Some("registry.docker.io".into()),
+                scope: Some("repository:library/nginx:pull,push".into()),
+            })
+        );
+        assert_eq!(
+            parse_challenge(r#"Basic realm="Registry""#),
+            Some(Challenge::Basic)
+        );
+        assert_eq!(parse_challenge("Negotiate"), None);
+    }
+
+    #[test]
+    fn test_newer_tags_keep_shape() {
+        let tags: Vec<String> = [
+            "1.24",
+            "1.25",
+            "1.26",
+            "1.27",
+            "1.27.1",
+            "1.27-alpine",
+            "2.0",
+            "latest",
+            "v1.30",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(newer_tags("1.25", &tags), vec!["2.0", "1.27", "1.26"]);
+        assert_eq!(newer_tags("1.25-alpine", &tags), vec!["1.27-alpine"]);
+        assert!(newer_tags("latest", &tags).is_empty());
+        assert_eq!(TagVersion::parse("v2.1.0-rc1").unwrap().suffix, "-rc1");
+    }
+
+    #[test]
+    fn test_local_digest_matches_repository() {
+        let info = ImageInfo {
+            id: "sha256:abc".into(),
+            repo_digests: vec![
+                "mirror.lan/library/nginx@sha256:mirror".into(),
+                "nginx@sha256:hub".into(),
+            ],
+            config: None,
+        };
+        assert_eq!(
+            local_digest(&parse_image_reference("nginx:1.25"), &info),
+            Some("sha256:hub".to_string())
+        );
+        assert_eq!(
+            local_digest(&parse_image_reference("ghcr.io/x/nginx"), &info),
+            None
+        );
+    }
+
+    /// A tiny registry:2 stand-in requiring a bearer token.
+    fn fake_registry() -> String {
+        let realm = Arc::new(OnceLock::<String>::new());
+        let token_realm = Arc::clone(&realm);
+        let (base, _) = serve_http(move |req: &Request| {
+            let path = req.path.as_str();
+            if path.starts_with("/token") {
+                // "bot:pw"
+                if req.authorization == "Basic Ym90OnB3"
+                    && path.contains("scope=repository%3Ahome%2Fapp%3Apull")
+                {
+                    Reply::new(200).body(r#"{"token":"t0ken"}"#)
+                } else {
+                    Reply::new(403)
+                }
+            } else if req.authorization != "Bearer t0ken" {
+                Reply::new(401).header(
+                    "WWW-Authenticate",
+                    &format!(
+                        "Bearer realm=\"{}\",service=\"test\",scope=\"repository:home/app:pull\"",
+                        token_realm.get().unwrap()
+                    ),
+                )
+            } else if path.starts_with("/v2/home/app/manifests/1.2") {
+                Reply::new(200).header("Docker-Content-Digest", "sha256:remote")
+            } else if path.starts_with("/v2/home/app/tags/list") {
+                Reply::new(200)
+                    .body(r#"{"name":"home/app","tags":["1.1","1.2","1.3","1.10","latest"]}"#)
+            } else {
+                Reply::new(404)
+            }
+        });
+        realm.set(format!("{base}/token")).unwrap();
+        base.trim_start_matches("http://").to_string()
+    }
+
+    #[test]
+    fn test_check_image_against_token_registry() {
+        let addr = fake_registry();
+        let image = format!("{addr}/home/app:1.2");
+        let config = serde_json::json!({
+            "auths": { addr.as_str(): { "auth": "Ym90OnB3" } }
+        });
+        let mut client = RegistryClient::new(config).unwrap();
+
+        let mock = MockRunner::new();
+        mock.mock_command(
+            "docker",
+            &["image", "inspect", &image],
+            CommandResult::ok(format!(
+                r#"[{{"Id":"sha256:local","RepoDigests":["{addr}/home/app@sha256:old"]}}]"#
+            )),
+        );
+
+        let check = check_image(&mut client, &mock, &image);
+        assert_eq!(check.error, None);
+        assert_eq!(check.status, UpdateStatus::Outdated);
+        assert_eq!(check.local_digest.as_deref(), Some("sha256:old"));
+        assert_eq!(check.remote_digest.as_deref(), Some("sha256:remote"));
+        assert_eq!(check.newer_tags, vec!["1.10", "1.3"]);
+
+        // Built locally: no registry digest to compare, so not "outdated".
+        let local = format!("{addr}/home/app:1.2-local");
+        mock.mock_command(
+            "docker",
+            &["image", "inspect", &local],
+            CommandResult::ok(r#"[{"Id":"sha256:built","RepoDigests":[]}]"#),
+        );
+        let check = check_image(&mut client, &mock, &local);
+        assert_eq!(check.status, UpdateStatus::Local);
+        assert_eq!(check.local_digest, None);
+
+        let pinned = check_image(&mut client, &mock, "nginx@sha256:abc");
+        assert_eq!(pinned.status, UpdateStatus::Pinned);
+    }
+}
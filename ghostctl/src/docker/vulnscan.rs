@@ -0,0 +1,754 @@
+//! Built-in image vulnerability scanner (`ghostctl docker scan <image>`).
+//!
+//! Works where trivy cannot be installed: the image is exported with
+//! `docker save`, its layers are replayed in order (honouring whiteouts) to
+//! recover the final package databases and lockfiles, and the resulting
+//! package set is matched against OSV — the same client `audit deps` uses.
+//! Supported inventories:
+//!
+//!   * dpkg `status` / distroless `status.d` → `Debian:<n>`, `Ubuntu:<v>`
+//!   * apk `installed`                       → `Alpine:v<n.n>`
+//!   * rpm `rpmdb.sqlite`                    → `Rocky Linux:<n>`, `AlmaLinux:<n>`
+//!   * pacman local db                       → Arch Security Tracker
+//!   * `Cargo.lock`, npm/yarn/pnpm/bun locks → crates.io / npm
+
+use super::rpmdb;
+use super::security::{SecurityScore, calculate_security_score};
+use crate::audit::config::AuditConfig;
+use crate::audit::lockfile::{self, NodePackageManager, Package};
+use crate::audit::vuln::{self, VulnFinding, VulnSeverity};
+use crate::audit::{osv, tracker};
+use crate::command::CommandRunner;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Files larger than this are skipped (a corrupt or hostile image should
+/// not exhaust memory).
+const MAX_FILE_BYTES: u64 = 128 * 1024 * 1024;
+
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lock",
+];
+
+fn is_interesting(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    matches!(
+        path,
+        "etc/os-release"
+            | "usr/lib/os-release"
+            | "var/lib/dpkg/status"
+            | "lib/apk/db/installed"
+            | "var/lib/rpm/rpmdb.sqlite"
+            | "usr/lib/sysimage/rpm/rpmdb.sqlite"
+    ) || path.starts_with("var/lib/dpkg/status.d/")
+        || (path.starts_with("var/lib/pacman/local/") && file_name == "desc")
+        || (LOCKFILES.contains(&file_name) && !path.contains("node_modules/"))
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// Apply one layer tar on top of `files`.
+fn apply_layer<R: Read>(files: &mut BTreeMap<String, Vec<u8>>, layer: R) -> Result<()> {
+    let mut archive = tar::Archive::new(layer);
+    for entry in archive.entries().context("invalid layer tar")? {
+        let mut entry = entry.context("invalid layer entry")?;
+        let path = normalize(&entry.path()?.to_string_lossy());
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (format!("{dir}/"), name),
+            None => (String::new(), path.as_str()),
+        };
+
+        if name == ".wh..wh..opq" {
+            files.retain(|p, _| !p.starts_with(&dir));
+            continue;
+        }
+        if let Some(removed) = name.strip_prefix(".wh.") {
+            let target = format!("{dir}{removed}");
+            let prefix = format!("{target}/");
+            files.retain(|p, _| *p != target && !p.starts_with(&prefix));
+            continue;
+        }
+
+        if !entry.header().entry_type().is_file() || !is_interesting(&path) {
+            continue;
+        }
+        if entry.size() > MAX_FILE_BYTES {
+            continue;
+        }
+        let mut content = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut content)?;
+        files.insert(path, content);
+    }
+    Ok(())
+}
+
+/// The package databases and lockfiles of the image's final filesystem.
+pub fn collect_files(archive: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    // First pass: where each member of the `docker save` tar lives.
+    let mut members: HashMap<String, (u64, u64)> = HashMap::new();
+    let mut manifest = None;
+    {
+        let file =
+            File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+        let mut outer = tar::Archive::new(BufReader::new(file));
+        for entry in outer.entries().context("invalid image archive")? {
+            let mut entry = entry?;
+            let path = normalize(&entry.path()?.to_string_lossy());
+            if path == "manifest.json" {
+                let mut text = String::new();
+                entry.read_to_string(&mut text)?;
+                manifest = Some(text);
+            } else {
+                members.insert(path, (entry.raw_file_position(), entry.size()));
+            }
+        }
+    }
+    let manifest: serde_json::Value =
+        serde_json::from_str(&manifest.context("image archive has no manifest.json")?)
+            .context("invalid manifest.json")?;
+    let layers: Vec<String> = manifest[0]["Layers"]
+        .as_array()
+        .context("manifest.json lists no layers")?
+        .iter()
+        .filter_map(|l| l.as_str().map(normalize))
+        .collect();
+
+    let mut files = BTreeMap::new();
+    for layer in layers {
+        let &(offset, size) = members
+            .get(&layer)
+            .with_context(|| format!("layer {layer} missing from archive"))?;
+        let mut file = File::open(archive)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(file.take(size));
+        // OCI layouts may keep layers gzip-compressed.
+        let mut magic = [0u8; 2];
+        reader.read_exact(&mut magic).ok();
+        let chained = std::io::Cursor::new(magic).chain(reader);
+        if magic == [0x1f, 0x8b] {
+            apply_layer(&mut files, flate2::read::GzDecoder::new(chained))?;
+        } else {
+            apply_layer(&mut files, chained)?;
+        }
+    }
+    Ok(files)
+}
+
+/// The `os-release` fields the ecosystem mapping needs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OsRelease {
+    pub id: String,
+    pub version_id: String,
+    pub pretty_name: String,
+}
+
+pub fn parse_os_release(text: &str) -> OsRelease {
+    let mut os = OsRelease::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value
+            .trim()
+            .trim_matches('"')
+            .trim_matches('\'')
+            .to_string();
+        match key.trim() {
+            "ID" => os.id = value,
+            "VERSION_ID" => os.version_id = value,
+            "PRETTY_NAME" => os.pretty_name = value,
+            _ => {}
+        }
+    }
+    os
+}
+
+/// OSV ecosystem for the distribution's OS packages.
+pub fn os_ecosystem(os: &OsRelease) -> Option<String> {
+    let mut parts = os.version_id.split('.');
+    let major = parts.next().filter(|m| !m.is_empty())?;
+    let minor = parts.next();
+    match os.id.as_str() {
+        "debian" => Some(format!("Debian:{major}")),
+        "ubuntu" => {
+            let lts = minor == Some("04") && major.parse::<u32>().is_ok_and(|y| y % 2 == 0);
+            Some(if lts {
+                format!("Ubuntu:{}:LTS", os.version_id)
+            } else {
+                format!("Ubuntu:{}", os.version_id)
+            })
+        }
+        "alpine" => Some(format!("Alpine:v{major}.{}", minor?)),
+        "rocky" => Some(format!("Rocky Linux:{major}")),
+        "almalinux" => Some(format!("AlmaLinux:{major}")),
+        _ => None,
+    }
+}
+
+/// An installed OS package; `source` is what distro advisories key on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OsPackage {
+    pub name: String,
+    pub version: String,
+    pub source: Option<(String, String)>,
+}
+
+fn paragraphs(text: &str) -> impl Iterator<Item = &str> {
+    text.split("\n\n").filter(|p| !p.trim().is_empty())
+}
+
+/// dpkg `status` (or one distroless `status.d` file).
+pub fn parse_dpkg_status(text: &str) -> Vec<OsPackage> {
+    paragraphs(text)
+        .filter_map(|para| {
+            let mut fields = HashMap::new();
+            for line in para.lines() {
+                if let Some((k, v)) = line.split_once(": ")
+                    && !line.starts_with(' ')
+                {
+                    fields.insert(k, v.trim());
+                }
+            }
+            // distroless status.d entries carry no Status field.
+            if fields
+                .get("Status")
+                .is_some_and(|s| !s.ends_with(" installed"))
+            {
+                return None;
+            }
+            let name = fields.get("Package")?.to_string();
+            let version = fields.get("Version")?.to_string();
+            let source = fields.get("Source").map(|s| match s.split_once(" (") {
+                Some((src, ver)) => (src.to_string(), ver.trim_end_matches(')').to_string()),
+                None => (s.to_string(), version.clone()),
+            });
+            Some(OsPackage {
+                name,
+                version,
+                source,
+            })
+        })
+        .collect()
+}
+
+/// apk `lib/apk/db/installed`.
+pub fn parse_apk_installed(text: &str) -> Vec<OsPackage> {
+    paragraphs(text)
+        .filter_map(|para| {
+            let (mut name, mut version, mut origin) = (None, None, None);
+            for line in para.lines() {
+                match line.split_once(':') {
+                    Some(("P", v)) => name = Some(v.to_string()),
+                    Some(("V", v)) => version = Some(v.to_string()),
+                    Some(("o", v)) => origin = Some(v.to_string()),
+                    _ => {}
+                }
+            }
+            let version = version?;
+            Some(OsPackage {
+                name: name?,
+                source: origin.map(|o| (o, version.clone())),
+                version,
+            })
+        })
+        .collect()
+}
+
+/// pacman `local/<pkg>/desc`.
+pub fn parse_pacman_desc(text: &str) -> Option<OsPackage> {
+    let mut lines = text.lines();
+    let (mut name, mut version) = (None, None);
+    while let Some(line) = lines.next() {
+        match line {
+            "%NAME%" => name = lines.next().map(String::from),
+            "%VERSION%" => version = lines.next().map(String::from),
+            _ => {}
+        }
+    }
+    Some(OsPackage {
+        name: name?,
+        version: version?,
+        source: None,
+    })
+}
+
+/// What was found in the image, grouped by where it came from.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Inventory {
+    pub os: Option<OsRelease>,
+    pub os_ecosystem: Option<String>,
+    /// `(label, ecosystem, package count)` per package source.
+    pub sources: Vec<(String, String, usize)>,
+    #[serde(skip)]
+    pub packages: Vec<Package>,
+    /// pacman packages, matched against the Arch Security Tracker.
+    #[serde(skip)]
+    pub arch_packages: BTreeMap<String, String>,
+    pub notes: Vec<String>,
+}
+
+impl Inventory {
+    fn add_os_packages(&mut self, label: &str, packages: Vec<OsPackage>) {
+        let Some(ecosystem) = self.os_ecosystem.clone() else {
+            self.notes.push(format!(
+                "{} {label} package(s) not matched: no OSV ecosystem for {}",
+                packages.len(),
+                self.os
+                    .as_ref()
+                    .map(|o| o.pretty_name.as_str())
+                    .unwrap_or("this distribution")
+            ));
+            return;
+        };
+        self.sources
+            .push((label.to_string(), ecosystem.clone(), packages.len()));
+        self.packages.extend(packages.into_iter().map(|p| {
+            let (name, version) = p.source.unwrap_or((p.name, p.version));
+            Package {
+                ecosystem: ecosystem.clone(),
+                name,
+                version,
+            }
+        }));
+    }
+}
+
+pub fn inventory(files: &BTreeMap<String, Vec<u8>>) -> Inventory {
+    let text = |path: &str| {
+        files
+            .get(path)
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+    };
+    let mut inv = Inventory::default();
+    if let Some(os) = text("etc/os-release").or_else(|| text("usr/lib/os-release")) {
+        let os = parse_os_release(&os);
+        inv.os_ecosystem = os_ecosystem(&os);
+        inv.os = Some(os);
+    }
+
+    let mut dpkg = text("var/lib/dpkg/status")
+        .map(|s| parse_dpkg_status(&s))
+        .unwrap_or_default();
+    for (path, content) in files.range("var/lib/dpkg/status.d/".to_string()..) {
+        if !path.starts_with("var/lib/dpkg/status.d/") {
+            break;
+        }
+        // distroless ships `<pkg>` plus `<pkg>.md5sums`.
+        if !path.ends_with(".md5sums") {
+            dpkg.extend(parse_dpkg_status(&String::from_utf8_lossy(content)));
+        }
+    }
+    if !dpkg.is_empty() {
+        inv.add_os_packages("dpkg", dpkg);
+    }
+
+    if let Some(apk) = text("lib/apk/db/installed") {
+        inv.add_os_packages("apk", parse_apk_installed(&apk));
+    }
+
+    for path in [
+        "var/lib/rpm/rpmdb.sqlite",
+        "usr/lib/sysimage/rpm/rpmdb.sqlite",
+    ] {
+        let Some(db) = files.get(path) else {
+            continue;
+        };
+        match rpmdb::read_packages(db) {
+            Ok(rpms) => inv.add_os_packages(
+                "rpm",
+                rpms.into_iter()
+                    .map(|p| OsPackage {
+                        version: p.evr(),
+                        name: p.name,
+                        source: None,
+                    })
+                    .collect(),
+            ),
+            Err(e) => inv.notes.push(format!("{path}: {e:#}")),
+        }
+        break;
+    }
+
+    for (path, content) in files {
+        if path.starts_with("var/lib/pacman/local/")
+            && let Some(p) = parse_pacman_desc(&String::from_utf8_lossy(content))
+        {
+            inv.arch_packages.insert(p.name, p.version);
+        }
+    }
+    if !inv.arch_packages.is_empty() {
+        inv.sources.push((
+            "pacman".into(),
+            "Arch Linux".into(),
+            inv.arch_packages.len(),
+        ));
+    }
+
+    for (path, content) in files {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        if !LOCKFILES.contains(&file_name) {
+            continue;
+        }
+        let body = String::from_utf8_lossy(content);
+        let parsed = match file_name {
+            "Cargo.lock" => lockfile::parse_cargo_lock(&body),
+            "bun.lock" => lockfile::parse_node_lockfile(NodePackageManager::Bun, &body),
+            "pnpm-lock.yaml" => lockfile::parse_node_lockfile(NodePackageManager::Pnpm, &body),
+            "yarn.lock" => lockfile::parse_node_lockfile(NodePackageManager::Yarn, &body),
+            _ => lockfile::parse_node_lockfile(NodePackageManager::Npm, &body),
+        };
+        match parsed {
+            Ok(packages) => {
+                let ecosystem = packages
+                    .first()
+                    .map(|p| p.ecosystem.clone())
+                    .unwrap_or_default();
+                inv.sources
+                    .push((format!("/{path}"), ecosystem, packages.len()));
+                inv.packages.extend(packages);
+            }
+            Err(e) => inv.notes.push(format!("/{path}: {e:#}")),
+        }
+    }
+
+    inv.packages.sort_by(|a, b| {
+        (&a.ecosystem, &a.name, &a.version).cmp(&(&b.ecosystem, &b.name, &b.version))
+    });
+    inv.packages.dedup();
+    inv
+}
+
+/// Arch Security Tracker advisories affecting the image's pacman packages.
+pub fn match_arch(
+    packages: &BTreeMap<String, String>,
+    entries: &[tracker::AvgEntry],
+    version_lt: impl Fn(&str, &str) -> bool,
+) -> Vec<VulnFinding> {
+    let mut findings = Vec::new();
+    for entry in entries {
+        if entry.status.eq_ignore_ascii_case("Not affected") {
+            continue;
+        }
+        for name in &entry.packages {
+            let Some(installed) = packages.get(name) else {
+                continue;
+            };
+            let fixed = entry.fixed.clone().unwrap_or_default();
+            let vulnerable =
+                entry.is_unfixed() || fixed.is_empty() || version_lt(installed, &fixed);
+            if !vulnerable {
+                continue;
+            }
+            findings.push(VulnFinding {
+                ecosystem: "Arch Linux".into(),
+                package: name.clone(),
+                version: installed.clone(),
+                id: entry.name.clone(),
+                aliases: entry.issues.clone(),
+                severity: VulnSeverity::from_text(&entry.severity).unwrap_or(VulnSeverity::Unknown),
+                summary: format!("{} issue(s)", entry.issues.len()),
+                fixed: if fixed.is_empty() {
+                    vec![]
+                } else {
+                    vec![fixed]
+                },
+                url: format!("https://security.archlinux.org/{}", entry.name),
+            });
+        }
+    }
+    findings
+}
+
+/// Score an image: one check per severity band that has no findings.
+pub fn score(findings: &[VulnFinding]) -> SecurityScore {
+    let (critical, high, medium, low, _) = vuln::severity_counts(findings);
+    calculate_security_score(&[critical == 0, high == 0, medium == 0, low == 0])
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanReport {
+    pub image: String,
+    #[serde(flatten)]
+    pub inventory: Inventory,
+    pub findings: Vec<VulnFinding>,
+    pub score_percent: f32,
+    pub grade: String,
+}
+
+/// Export, inventory and match one image.
+pub fn scan_image(runner: &dyn CommandRunner, image: &str) -> Result<ScanReport> {
+    crate::docker::validate_image_name(image).map_err(|e| anyhow::anyhow!(e))?;
+    let dir = tempfile::Builder::new()
+        .prefix("ghostctl-scan-")
+        .tempdir()
+        .context("failed to create scratch directory")?;
+    let archive = dir.path().join("image.tar");
+    let archive_arg = archive.to_string_lossy().to_string();
+    let save = runner
+        .run("docker", &["save", "-o", &archive_arg, image])
+        .context("failed to run docker save")?;
+    if !save.success {
+        bail!("docker save {image} failed: {}", save.stderr.trim());
+    }
+
+    let files = collect_files(&archive)?;
+    let inventory = inventory(&files);
+    let cfg = AuditConfig::load();
+    let client = crate::audit::http_client(cfg.timeout_secs)?;
+
+    let mut findings = osv::audit_packages(&client, &inventory.packages)?;
+    if !inventory.arch_packages.is_empty() {
+        let body = client
+            .get(&cfg.tracker_url)
+            .send()
+            .with_context(|| format!("request failed: {}", cfg.tracker_url))?
+            .text()
+            .context("failed to read Arch Security Tracker response")?;
+        let entries = tracker::parse_tracker(&body)?;
+        findings.extend(match_arch(
+            &inventory.arch_packages,
+            &entries,
+            crate::audit::version_lt,
+        ));
+    }
+    vuln::sort_findings(&mut findings);
+
+    let score = score(&findings);
+    Ok(ScanReport {
+        image: image.to_string(),
+        inventory,
+        findings,
+        score_percent: score.percentage,
+        grade: score.grade.to_string(),
+    })
+}
+
+pub fn print_report(report: &ScanReport) {
+    crate::tui::header(&format!("Image scan: {}", report.image));
+    if let Some(os) = &report.inventory.os {
+        println!("OS: {}", os.pretty_name);
+    }
+    for (label, ecosystem, count) in &report.inventory.sources {
+        println!("  {label:<40} {count:>5} package(s)  [{ecosystem}]");
+    }
+    for note in &report.inventory.notes {
+        crate::tui::warn(note);
+    }
+    println!();
+    vuln::print_report(&report.findings);
+    println!(
+        "\nSecurity score: {:.0}% ({})",
+        report.score_percent, report.grade
+    );
+}
+
+pub fn command() -> Command {
+    Command::new("scan")
+        .about("Scan an image for vulnerable packages without trivy")
+        .arg(Arg::new("image").required(true).help("Image reference"))
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let image = matches
+        .get_one::<String>("image")
+        .expect("image is required");
+    let json = matches.get_flag("json");
+    let report = scan_image(crate::command::runner().as_ref(), image)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    // Same CI contract as `audit deps`: High/Critical fails non-interactive runs.
+    let interactive = std::io::IsTerminal::is_terminal(&std::io::stdout()) && !json;
+    if vuln::has_high_or_critical(&report.findings) && !interactive {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn image_archive(layers: Vec<Vec<u8>>) -> tempfile::NamedTempFile {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut names = Vec::new();
+        for (i, data) in layers.iter().enumerate() {
+            let name = format!("blobs/sha256/layer{i}");
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, &name, data.as_slice())
+                .unwrap();
+            names.push(name);
+        }
+        let manifest =
+            serde_json::json!([{ "Config": "config.json", "Layers": names }]).to_string();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "manifest.json", manifest.as_bytes())
+            .unwrap();
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), builder.into_inner().unwrap()).unwrap();
+        file
+    }
+
+    const DPKG: &str = "Package: libssl3\nStatus: install ok installed\nSource: openssl (3.0.11-1~deb12u2)\nVersion: 3.0.11-1~deb12u2\n\nPackage: zlib1g\nStatus: install ok installed\nSource: zlib\nVersion: 1:1.2.13.dfsg-1\n\nPackage: gone\nStatus: deinstall ok config-files\nVersion: 1.0\n";
+
+    const CARGO_LOCK: &str = "version = 3\n\n[[package]]\nname = \"smallvec\"\nversion = \"1.6.0\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n";
+
+    #[test]
+    fn test_layers_replay_with_whiteouts() {
+        let base = layer(&[
+            (
+                "etc/os-release",
+                "PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"\nID=debian\nVERSION_ID=\"12\"\n",
+            ),
+            ("var/lib/dpkg/status", DPKG),
+            ("srv/old/Cargo.lock", CARGO_LOCK),
+            ("srv/app/node_modules/x/package-lock.json", "{}"),
+            ("usr/bin/ignored", "binary"),
+        ]);
+        let top = layer(&[("srv/.wh.old", ""), ("app/Cargo.lock", CARGO_LOCK)]);
+        let archive = image_archive(vec![base, top]);
+
+        let files = collect_files(archive.path()).unwrap();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            vec!["app/Cargo.lock", "etc/os-release", "var/lib/dpkg/status"]
+        );
+
+        let inv = inventory(&files);
+        assert_eq!(inv.os_ecosystem.as_deref(), Some("Debian:12"));
+        assert_eq!(inv.packages.len(), 3);
+        assert!(inv.packages.contains(&Package {
+            ecosystem: "Debian:12".into(),
+            name: "openssl".into(),
+            version: "3.0.11-1~deb12u2".into(),
+        }));
+        assert!(inv.packages.iter().any(|p| p.name == "zlib"));
+        assert!(inv.packages.iter().any(|p| p.ecosystem == "crates.io"));
+    }
+
+    #[test]
+    fn test_opaque_whiteout_clears_directory() {
+        let base = layer(&[("app/Cargo.lock", CARGO_LOCK)]);
+        let top = layer(&[("app/.wh..wh..opq", "")]);
+        let archive = image_archive(vec![base, top]);
+        assert!(collect_files(archive.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_os_ecosystems() {
+        let os = |id: &str, v: &str| OsRelease {
+            id: id.into(),
+            version_id: v.into(),
+            pretty_name: String::new(),
+        };
+        assert_eq!(
+            os_ecosystem(&os("ubuntu", "22.04")).as_deref(),
+            Some("Ubuntu:22.04:LTS")
+        );
+        assert_eq!(
+            os_ecosystem(&os("ubuntu", "23.10")).as_deref(),
+            Some("Ubuntu:23.10")
+        );
+        assert_eq!(
+            os_ecosystem(&os("alpine", "3.19.1")).as_deref(),
+            Some("Alpine:v3.19")
+        );
+        assert_eq!(
+            os_ecosystem(&os("rocky", "9.3")).as_deref(),
+            Some("Rocky Linux:9")
+        );
+        assert_eq!(os_ecosystem(&os("arch", "")), None);
+    }
+
+    #[test]
+    fn test_apk_and_pacman_parsers() {
+        let apk = "C:Q1abc=\nP:libcrypto3\nV:3.1.4-r5\no:openssl\n\nP:musl\nV:1.2.4-r2\n\n";
+        let pkgs = parse_apk_installed(apk);
+        assert_eq!(
+            pkgs[0].source,
+            Some(("openssl".to_string(), "3.1.4-r5".to_string()))
+        );
+        assert_eq!(pkgs[1].name, "musl");
+
+        let desc = "%NAME%\nopenssl\n\n%VERSION%\n3.2.1-1\n\n%DESC%\nTLS\n";
+        let pkg = parse_pacman_desc(desc).unwrap();
+        assert_eq!(
+            (pkg.name.as_str(), pkg.version.as_str()),
+            ("openssl", "3.2.1-1")
+        );
+    }
+
+    #[test]
+    fn test_unsupported_os_is_noted_and_arch_matched() {
+        let mut files = BTreeMap::new();
+        files.insert(
+            "etc/os-release".to_string(),
+            b"ID=arch\nPRETTY_NAME=\"Arch Linux\"\n".to_vec(),
+        );
+        files.insert(
+            "var/lib/pacman/local/openssl-3.2.0-1/desc".to_string(),
+            b"%NAME%\nopenssl\n\n%VERSION%\n3.2.0-1\n".to_vec(),
+        );
+        let inv = inventory(&files);
+        assert_eq!(inv.arch_packages.len(), 1);
+
+        let entries = tracker::parse_tracker(
+            r#"[{"name":"AVG-1","packages":["openssl"],"status":"Fixed","severity":"High","fixed":"3.2.1-1","issues":["CVE-2024-1"]},
+                {"name":"AVG-2","packages":["openssl"],"status":"Fixed","severity":"Low","fixed":"3.1.0-1","issues":[]}]"#,
+        )
+        .unwrap();
+        let findings = match_arch(&inv.arch_packages, &entries, crate::audit::version_lt);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].id, "AVG-1");
+        assert_eq!(findings[0].severity, VulnSeverity::High);
+
+        let s = score(&findings);
+        assert_eq!(s.passed, 3);
+        assert_eq!(s.total, 4);
+    }
+}
@@ -54,6 +54,8 @@ mod support;
 mod sysctl;
 mod systemd;
 mod terminal;
+#[cfg(test)]
+mod test_server;
 mod tools;
 pub mod tui;
 mod uefi;
@@ -81,9 +83,12 @@ pub enum BtrfsAction {
     Usage { mountpoint: String },
     Quota { mountpoint: String },
     EmergencyCleanup,
-    CleanupByAge { days: String },
+    CleanupByAge { days: String, include_manual: bool },
     CleanupByRange { range: String },
+    CleanupPolicy { plan_only: bool },
     DiskSpace,
+    Replicate { target: Option<String> },
+    ReplicateStatus,
 }
 
 #[derive(Debug)]
@@ -100,6 +100,7 @@ fn network_mesh_menu() {
     let options = [
         "🔗 Mesh Up",
         "📡 Advertise Subnet",
+        "🔀 Switch Profile",
         "📊 Status",
         "🔽 Mesh Down",
     ];
@@ -114,8 +115,9 @@ fn network_mesh_menu() {
                     network::mesh::advertise(&subnet);
                 }
             }
-            2 => network::mesh::status(),
-            3 => network::mesh::down(),
+            2 => network::mesh::switch_menu(),
+            3 => network::mesh::status(),
+            4 => network::mesh::down(),
             _ => {}
         }
     }
@@ -91,6 +91,22 @@ impl MonitorClient {
         Ok(())
     }
 
+    /// POST a JSON body (used to raise Alertmanager alerts).
+    pub fn post_json(&self, url: &str, body: &serde_json::Value) -> Result<()> {
+        let resp = self
+            .client
+            .post(url)
+            .json(body)
+            .send()
+            .with_context(|| format!("request failed: {url}"))?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().unwrap_or_default();
+            bail!("HTTP {} from {}: {}", status.as_u16(), url, body.trim());
+        }
+        Ok(())
+    }
+
     /// Lightweight liveness probe: true if the URL returns any 2xx response.
     pub fn is_up(&self, url: &str) -> bool {
         self.client
@@ -245,6 +245,24 @@ fn alerts(cfg: &MonitorConfig, mc: &MonitorClient) -> Result<()> {
     Ok(())
 }
 
+/// Raise `alerts` in the configured Alertmanager for `hours` hours.
+pub fn push_alerts(alerts: &[parse::RaisedAlert], hours: i64) -> Result<()> {
+    if alerts.is_empty() {
+        return Ok(());
+    }
+    let cfg = MonitorConfig::load();
+    let mc = MonitorClient::new(cfg.timeout_secs)?;
+    let url = format!(
+        "{}/api/v2/alerts",
+        MonitorConfig::base(&cfg.alertmanager_url)
+    );
+    let now = chrono::Utc::now();
+    mc.post_json(
+        &url,
+        &parse::alerts_body(alerts, now, now + chrono::Duration::hours(hours)),
+    )
+}
+
 fn logs(cfg: &MonitorConfig, mc: &MonitorClient, query: &str, limit: &str) -> Result<()> {
     let url = format!(
         "{}/loki/api/v1/query_range",
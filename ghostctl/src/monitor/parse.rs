@@ -1,4 +1,5 @@
-//! Pure response-parsing functions for the monitoring APIs.
+//! Pure response-parsing functions for the monitoring APIs, plus the body
+//! ghostctl posts when it raises an Alertmanager alert itself.
 //!
 //! Kept separate from the HTTP client so they can be unit-tested against
 //! captured JSON fixtures without any network access.
@@ -153,6 +154,49 @@ pub fn parse_alerts(json: &str) -> Result<Vec<Alert>> {
         .collect())
 }
 
+/// An alert ghostctl raises itself, e.g. `scan --alert` on inventory changes.
+#[derive(Debug, Clone)]
+pub struct RaisedAlert {
+    pub name: String,
+    pub severity: String,
+    /// The `instance` label: the host the alert is about.
+    pub instance: String,
+    pub summary: String,
+    pub description: String,
+}
+
+/// POST body for Alertmanager `/api/v2/alerts`. The alerts stay firing until
+/// `ends_at` unless they are raised again.
+pub fn alerts_body(
+    alerts: &[RaisedAlert],
+    starts_at: chrono::DateTime<chrono::Utc>,
+    ends_at: chrono::DateTime<chrono::Utc>,
+) -> serde_json::Value {
+    let time =
+        |t: chrono::DateTime<chrono::Utc>| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    serde_json::Value::Array(
+        alerts
+            .iter()
+            .map(|a| {
+                serde_json::json!({
+                    "labels": {
+                        "alertname": a.name,
+                        "severity": a.severity,
+                        "instance": a.instance,
+                        "source": "ghostctl",
+                    },
+                    "annotations": {
+                        "summary": a.summary,
+                        "description": a.description,
+                    },
+                    "startsAt": time(starts_at),
+                    "endsAt": time(ends_at),
+                })
+            })
+            .collect(),
+    )
+}
+
 // ---- Loki /loki/api/v1/query_range ----
 
 #[derive(Debug, Clone)]
@@ -228,6 +272,29 @@ pub fn parse_datasource_health(json: &str) -> Result<(String, String)> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_alerts_body_round_trips_through_parse_alerts() {
+        let start = chrono::DateTime::parse_from_rfc3339("2026-10-18T09:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let body = alerts_body(
+            &[RaisedAlert {
+                name: "GhostctlScanChange".to_string(),
+                severity: "warning".to_string(),
+                instance: "10.0.0.5".to_string(),
+                summary: "2 changes on 10.0.0.5".to_string(),
+                description: "+ 3306/tcp mysql".to_string(),
+            }],
+            start,
+            start + chrono::Duration::hours(24),
+        );
+        assert_eq!(body[0]["labels"]["instance"], "10.0.0.5");
+        assert_eq!(body[0]["endsAt"], "2026-10-19T09:00:00Z");
+        let alerts = parse_alerts(&body.to_string()).unwrap();
+        assert_eq!(alerts[0].name, "GhostctlScanChange");
+        assert_eq!(alerts[0].summary, "2 changes on 10.0.0.5");
+    }
+
     #[test]
     fn test_parse_targets() {
         let json = r#"{
@@ -0,0 +1,276 @@
+use crate::support::SecretSource;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Tailscale/Headscale mesh configuration stored in config.toml under [mesh].
+///
+/// Each `[[mesh.profiles]]` entry is one tailnet this machine can join:
+///
+/// ```toml
+/// [mesh]
+/// default_profile = "lab"
+///
+/// [[mesh.profiles]]
+/// name = "lab"
+/// login_server = "https://hs.example.net"
+/// auth_key = { credential = "headscale-lab" }
+/// tags = ["tag:server"]
+/// advertise_routes = ["10.0.0.0/24"]
+/// headscale = { api_key = { env = "HEADSCALE_API_KEY" } }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MeshConfig {
+    /// Profile used by `mesh up` before any `mesh switch`.
+    #[serde(default)]
+    pub default_profile: Option<String>,
+
+    #[serde(default)]
+    pub profiles: Vec<MeshProfile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MeshProfile {
+    pub name: String,
+
+    /// Control server URL; unset for Tailscale's own coordination server.
+    #[serde(default)]
+    pub login_server: Option<String>,
+
+    /// Pre-auth key for unattended logins. Without one, `tailscale up`
+    /// prints a login URL.
+    #[serde(default)]
+    pub auth_key: Option<SecretSource>,
+
+    /// ACL tags to advertise (`tag:server`).
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Peer (name or IP) to route internet traffic through.
+    #[serde(default)]
+    pub exit_node: Option<String>,
+
+    /// Keep LAN access while using an exit node.
+    #[serde(default)]
+    pub exit_node_allow_lan_access: bool,
+
+    /// Offer this machine as an exit node.
+    #[serde(default)]
+    pub advertise_exit_node: bool,
+
+    /// Subnets to advertise to the tailnet.
+    #[serde(default)]
+    pub advertise_routes: Vec<String>,
+
+    #[serde(default = "default_true")]
+    pub accept_routes: bool,
+
+    /// Use the tailnet's DNS settings (MagicDNS). Off by default so the
+    /// local resolver stays in charge.
+    #[serde(default)]
+    pub accept_dns: bool,
+
+    /// Run the Tailscale SSH server.
+    #[serde(default = "default_true")]
+    pub ssh: bool,
+
+    /// Local user allowed to run `tailscale` without sudo.
+    #[serde(default)]
+    pub operator: Option<String>,
+
+    /// Name to register instead of the system hostname.
+    #[serde(default)]
+    pub hostname: Option<String>,
+
+    /// Headscale REST API for `mesh headscale` (needs `login_server` or `url`).
+    #[serde(default)]
+    pub headscale: Option<HeadscaleApi>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HeadscaleApi {
+    /// API base URL; defaults to the profile's `login_server`.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// API key from `headscale apikeys create`.
+    pub api_key: SecretSource,
+
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+
+    /// HTTP request timeout in seconds.
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout() -> u64 {
+    15
+}
+
+impl MeshConfig {
+    pub fn load() -> Self {
+        crate::config::GhostConfig::load().mesh.unwrap_or_default()
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&MeshProfile> {
+        self.profiles
+            .iter()
+            .find(|p| p.name == name)
+            .with_context(|| {
+                let known: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+                if known.is_empty() {
+                    format!("no mesh profile '{name}': add [[mesh.profiles]] to config.toml")
+                } else {
+                    format!("no mesh profile '{name}' (have: {})", known.join(", "))
+                }
+            })
+    }
+
+    /// Problems that would make `tailscale up` fail or misbehave.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (i, profile) in self.profiles.iter().enumerate() {
+            let name = &profile.name;
+            if name.trim().is_empty() {
+                problems.push(format!("profile #{} has no name", i + 1));
+            }
+            if self.profiles[..i].iter().any(|p| p.name == *name) {
+                problems.push(format!("profile '{name}' is defined twice"));
+            }
+            if let Some(url) = &profile.login_server
+                && !(url.starts_with("https://") || url.starts_with("http://"))
+            {
+                problems.push(format!(
+                    "profile '{name}': login_server must be an http(s) URL"
+                ));
+            }
+            for tag in profile.tags.iter().filter(|t| !t.starts_with("tag:")) {
+                problems.push(format!(
+                    "profile '{name}': tag '{tag}' must start with 'tag:'"
+                ));
+            }
+            for route in &profile.advertise_routes {
+                if route.parse::<ipnet::IpNet>().is_err() {
+                    problems.push(format!("profile '{name}': '{route}' is not a CIDR route"));
+                }
+            }
+            if profile.exit_node.is_some() && profile.advertise_exit_node {
+                problems.push(format!(
+                    "profile '{name}': cannot use an exit node and advertise one"
+                ));
+            }
+            if let Some(api) = &profile.headscale
+                && api.url.is_none()
+                && profile.login_server.is_none()
+            {
+                problems.push(format!(
+                    "profile '{name}': headscale needs a url or the profile's login_server"
+                ));
+            }
+        }
+        if let Some(default) = &self.default_profile
+            && !self.profiles.iter().any(|p| p.name == *default)
+        {
+            problems.push(format!("default_profile '{default}' is not defined"));
+        }
+        problems
+    }
+}
+
+impl MeshProfile {
+    /// Headscale API base URL without a trailing slash.
+    pub fn headscale_url(&self) -> Option<String> {
+        let api = self.headscale.as_ref()?;
+        api.url
+            .as_ref()
+            .or(self.login_server.as_ref())
+            .map(|u| u.trim_end_matches('/').to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+default_profile = "lab"
+
+[[profiles]]
+name = "lab"
+login_server = "https://hs.lab.example/"
+auth_key = { env = "LAB_TS_KEY" }
+tags = ["tag:server", "tag:pve"]
+advertise_routes = ["10.0.0.0/24"]
+operator = "ops"
+headscale = { api_key = { credential = "headscale-lab" } }
+
+[[profiles]]
+name = "travel"
+exit_node = "100.64.0.7"
+exit_node_allow_lan_access = true
+accept_dns = true
+ssh = false
+"#;
+
+    #[test]
+    fn profiles_parse_with_defaults() {
+        let cfg: MeshConfig = toml::from_str(CONFIG).unwrap();
+        assert!(cfg.validate().is_empty(), "{:?}", cfg.validate());
+        let lab = cfg.profile("lab").unwrap();
+        assert_eq!(lab.auth_key, Some(SecretSource::Env("LAB_TS_KEY".into())));
+        assert!(lab.accept_routes && lab.ssh && !lab.accept_dns);
+        assert_eq!(
+            lab.headscale_url().as_deref(),
+            Some("https://hs.lab.example")
+        );
+        assert!(lab.headscale.as_ref().unwrap().verify_tls);
+        let travel = cfg.profile("travel").unwrap();
+        assert_eq!(travel.login_server, None);
+        assert!(!travel.ssh);
+        assert!(
+            cfg.profile("work")
+                .unwrap_err()
+                .to_string()
+                .contains("have: lab, travel")
+        );
+    }
+
+    #[test]
+    fn validation_catches_bad_profiles() {
+        let cfg: MeshConfig = toml::from_str(
+            r#"
+default_profile = "nope"
+[[profiles]]
+name = "a"
+login_server = "hs.example"
+tags = ["server"]
+advertise_routes = ["10.0.0.0/33"]
+exit_node = "x"
+advertise_exit_node = true
+[[profiles]]
+name = "a"
+headscale = { api_key = { env = "K" } }
+"#,
+        )
+        .unwrap();
+        let problems = cfg.validate();
+        for expected in [
+            "login_server must be an http(s) URL",
+            "tag 'server' must start with 'tag:'",
+            "'10.0.0.0/33' is not a CIDR route",
+            "cannot use an exit node and advertise one",
+            "profile 'a' is defined twice",
+            "headscale needs a url",
+            "default_profile 'nope' is not defined",
+        ] {
+            assert!(
+                problems.iter().any(|p| p.contains(expected)),
+                "missing '{expected}' in {problems:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Blocking client for the Headscale REST API (`/api/v1`, Headscale 0.26+).
+//!
+//! Auth is a bearer API key from `headscale apikeys create`. Covers what the
+//! team does by hand on the control server: listing nodes, approving the
+//! routes nodes advertise, and managing pre-auth keys. Headscale encodes ids
+//! as strings; they are kept as strings here.
+
+use super::config::MeshProfile;
+use anyhow::{Context, Result, bail};
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub given_name: String,
+    #[serde(default)]
+    pub user: Option<User>,
+    #[serde(default)]
+    pub ip_addresses: Vec<String>,
+    #[serde(default)]
+    pub online: bool,
+    #[serde(default)]
+    pub last_seen: Option<String>,
+    #[serde(default)]
+    pub valid_tags: Vec<String>,
+    #[serde(default)]
+    pub forced_tags: Vec<String>,
+    /// Routes the node offers.
+    #[serde(default)]
+    pub available_routes: Vec<String>,
+    /// Routes an admin approved.
+    #[serde(default)]
+    pub approved_routes: Vec<String>,
+    /// Approved routes the node is currently serving.
+    #[serde(default)]
+    pub subnet_routes: Vec<String>,
+}
+
+impl Node {
+    pub fn display_name(&self) -> &str {
+        if self.given_name.is_empty() {
+            &self.name
+        } else {
+            &self.given_name
+        }
+    }
+
+    /// Routes offered but not yet approved.
+    pub fn pending_routes(&self) -> Vec<&str> {
+        self.available_routes
+            .iter()
+            .filter(|r| !self.approved_routes.contains(r))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreAuthKey {
+    pub id: String,
+    pub key: String,
+    #[serde(default)]
+    pub user: Option<User>,
+    #[serde(default)]
+    pub reusable: bool,
+    #[serde(default)]
+    pub ephemeral: bool,
+    #[serde(default)]
+    pub used: bool,
+    #[serde(default)]
+    pub expiration: Option<String>,
+    #[serde(default)]
+    pub acl_tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Nodes {
+    #[serde(default)]
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct OneNode {
+    node: Node,
+}
+
+#[derive(Deserialize)]
+struct Users {
+    #[serde(default)]
+    users: Vec<User>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Keys {
+    #[serde(default)]
+    pre_auth_keys: Vec<PreAuthKey>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OneKey {
+    pre_auth_key: PreAuthKey,
+}
+
+/// Options for a new pre-auth key.
+#[derive(Debug, Clone, Default)]
+pub struct NewKey {
+    pub reusable: bool,
+    pub ephemeral: bool,
+    /// RFC 3339 expiry.
+    pub expiration: String,
+    pub tags: Vec<String>,
+}
+
+pub struct HeadscaleClient {
+    client: Client,
+    base: String,
+}
+
+impl HeadscaleClient {
+    pub fn new(profile: &MeshProfile) -> Result<Self> {
+        let api = profile.headscale.as_ref().with_context(|| {
+            format!(
+                "mesh profile '{}' has no headscale API configured",
+                profile.name
+            )
+        })?;
+        let base = profile.headscale_url().with_context(|| {
+            format!(
+                "mesh profile '{}': set headscale.url or login_server",
+                profile.name
+            )
+        })?;
+        let key = api.api_key.resolve("headscale API key")?;
+        Self::with_key(&base, &key, api.verify_tls, api.timeout_secs)
+    }
+
+    pub fn with_key(base: &str, key: &str, verify_tls: bool, timeout_secs: u64) -> Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let mut auth = reqwest::header::HeaderValue::from_str(&format!("Bearer {key}"))
+            .context("API key contains invalid header characters")?;
+        auth.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, auth);
+        let client = Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .user_agent("ghostctl")
+            .default_headers(headers)
+            .danger_accept_invalid_certs(!verify_tls)
+            .build()
+            .context("failed to build Headscale HTTP client")?;
+        Ok(Self {
+            client,
+            base: base.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api/v1{path}", self.base)
+    }
+
+    fn send<T: DeserializeOwned>(&self, request: reqwest::blocking::RequestBuilder) -> Result<T> {
+        let resp = request.send().context("Headscale request failed")?;
+        let status = resp.status();
+        let url = resp.url().path().to_string();
+        let body = resp.text().unwrap_or_default();
+        if status.as_u16() == 401 {
+            bail!(
+                "HTTP 401 from {url}: API key rejected (create one with `headscale apikeys create`)"
+            );
+        }
+        if !status.is_success() {
+            // Headscale errors are gRPC-gateway JSON: {"code":..,"message":..}.
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| v["message"].as_str().map(str::to_string))
+                .unwrap_or_else(|| body.trim().to_string());
+            bail!("HTTP {} from {url}: {message}", status.as_u16());
+        }
+        serde_json::from_str(&body).with_context(|| format!("unexpected response from {url}"))
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(self.client.get(self.url(path)))
+    }
+
+    fn post<T: DeserializeOwned>(&self, path: &str, body: &serde_json::Value) -> Result<T> {
+        self.send(self.client.post(self.url(path)).json(body))
+    }
+
+    pub fn nodes(&self) -> Result<Vec<Node>> {
+        let mut nodes = self.get::<Nodes>("/node")?.nodes;
+        nodes.sort_by(|a, b| a.display_name().cmp(b.display_name()));
+        Ok(nodes)
+    }
+
+    /// Find a node by id, name or Tailscale IP.
+    pub fn node(&self, wanted: &str) -> Result<Node> {
+        let nodes = self.nodes()?;
+        nodes
+            .into_iter()
+            .find(|n| {
+                n.id == wanted
+                    || n.name == wanted
+                    || n.given_name == wanted
+                    || n.ip_addresses.iter().any(|ip| ip == wanted)
+            })
+            .with_context(|| format!("no Headscale node '{wanted}'"))
+    }
+
+    /// Approve `routes` on `node` in addition to those already approved.
+    /// Headscale replaces the approved set, so the union is sent.
+    pub fn approve_routes(&self, node: &Node, routes: &[String]) -> Result<Node> {
+        for route in routes {
+            if !node.available_routes.contains(route) {
+                bail!(
+                    "{} does not advertise {route} (advertised: {})",
+                    node.display_name(),
+                    if node.available_routes.is_empty() {
+                        "none".to_string()
+                    } else {
+                        node.available_routes.join(", ")
+                    }
+                );
+            }
+        }
+        let mut approved = node.approved_routes.clone();
+        for route in routes {
+            if !approved.contains(route) {
+                approved.push(route.clone());
+            }
+        }
+        let path = format!("/node/{}/approve_routes", node.id);
+        Ok(self
+            .post::<OneNode>(&path, &json!({ "routes": approved }))?
+            .node)
+    }
+
+    pub fn users(&self) -> Result<Vec<User>> {
+        Ok(self.get::<Users>("/user")?.users)
+    }
+
+    /// Resolve a user name (or id) to its id.
+    pub fn user_id(&self, wanted: &str) -> Result<String> {
+        self.users()?
+            .into_iter()
+            .find(|u| u.name == wanted || u.id == wanted)
+            .map(|u| u.id)
+            .with_context(|| format!("no Headscale user '{wanted}'"))
+    }
+
+    pub fn preauth_keys(&self, user_id: &str) -> Result<Vec<PreAuthKey>> {
+        Ok(self
+            .get::<Keys>(&format!("/preauthkey?user={user_id}"))?
+            .pre_auth_keys)
+    }
+
+    pub fn create_preauth_key(&self, user_id: &str, new: &NewKey) -> Result<PreAuthKey> {
+        let body = json!({
+            "user": user_id,
+            "reusable": new.reusable,
+            "ephemeral": new.ephemeral,
+            "expiration": new.expiration,
+            "aclTags": new.tags,
+        });
+        Ok(self.post::<OneKey>("/preauthkey", &body)?.pre_auth_key)
+    }
+
+    pub fn expire_preauth_key(&self, user_id: &str, key: &str) -> Result<()> {
+        self.post::<serde_json::Value>(
+            "/preauthkey/expire",
+            &json!({ "user": user_id, "key": key }),
+        )?;
+        Ok(())
+    }
+}
+
+/// `24h`, `7d`, `90m` or `30s` as an RFC 3339 time that far from `now`.
+pub fn expiry(spec: &str, now: chrono::DateTime<chrono::Utc>) -> Result<String> {
+    let spec = spec.trim();
+    let split = spec.len().saturating_sub(1);
+    let (count, unit) = spec.split_at(split);
+    let count: i64 = count
+        .parse()
+        .with_context(|| format!("invalid duration '{spec}' (use e.g. 90m, 24h, 7d)"))?;
+    let delta = match unit {
+        "s" => chrono::Duration::seconds(count),
+        "m" => chrono::Duration::minutes(count),
+        "h" => chrono::Duration::hours(count),
+        "d" => chrono::Duration::days(count),
+        _ => bail!("invalid duration '{spec}' (use e.g. 90m, 24h, 7d)"),
+    };
+    Ok((now + delta).to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Request, serve_http};
+    use std::sync::{Arc, Mutex};
+
+    const NODES: &str = r#"{"nodes":[
+      {"id":"2","name":"pve1","givenName":"pve1","user":{"id":"1","name":"ops"},
+       "ipAddresses":["100.64.0.2"],"online":true,"lastSeen":"2026-10-19T08:00:00Z",
+       "validTags":["tag:server"],"forcedTags":[],
+       "availableRoutes":["10.0.0.0/24","0.0.0.0/0","::/0"],
+       "approvedRoutes":["10.0.0.0/24"],"subnetRoutes":["10.0.0.0/24"]},
+      {"id":"1","name":"ws1-x7","givenName":"ws1","user":{"id":"1","name":"ops"},
+       "ipAddresses":["100.64.0.1"],"online":false}
+    ]}"#;
+
+    /// A Headscale stand-in answering from a fixed table.
+    fn fake_headscale() -> (String, Arc<Mutex<Vec<Request>>>) {
+        serve_http(|req: &Request| {
+            if !req.authorization.eq_ignore_ascii_case("bearer hskey") {
+                return (401, "Unauthorized".to_string());
+            }
+            match (req.method.as_str(), req.path.as_str()) {
+                ("GET", "/api/v1/node") => (200, NODES.to_string()),
+                ("POST", "/api/v1/node/2/approve_routes") => (
+                    200,
+                    r#"{"node":{"id":"2","name":"pve1","approvedRoutes":["10.0.0.0/24","0.0.0.0/0"]}}"#
+                        .to_string(),
+                ),
+                ("GET", "/api/v1/user") => (
+                    200,
+                    r#"{"users":[{"id":"1","name":"ops"},{"id":"3","name":"ci"}]}"#.to_string(),
+                ),
+                ("GET", "/api/v1/preauthkey?user=3") => (
+                    200,
+                    r#"{"preAuthKeys":[{"id":"9","key":"abc","reusable":true,"used":false,"expiration":"2026-10-20T00:00:00Z","aclTags":["tag:ci"]}]}"#
+                        .to_string(),
+                ),
+                ("POST", "/api/v1/preauthkey") => (
+                    200,
+                    r#"{"preAuthKey":{"id":"10","key":"def","ephemeral":true}}"#.to_string(),
+                ),
+                ("POST", "/api/v1/preauthkey/expire") => (200, "{}".to_string()),
+                _ => (404, r#"{"code":5,"message":"not found"}"#.to_string()),
+            }
+        })
+    }
+
+    #[test]
+    fn nodes_and_route_approval() {
+        let (base, seen) = fake_headscale();
+        let client = HeadscaleClient::with_key(&base, "hskey", true, 5).unwrap();
+        let nodes = client.nodes().unwrap();
+        assert_eq!(
+            nodes.iter().map(Node::display_name).collect::<Vec<_>>(),
+            vec!["pve1", "ws1"]
+        );
+        let pve = client.node("100.64.0.2").unwrap();
+        assert_eq!(pve.pending_routes(), vec!["0.0.0.0/0", "::/0"]);
+        assert_eq!(pve.user.as_ref().unwrap().name, "ops");
+
+        let err = client
+            .approve_routes(&pve, &["192.168.9.0/24".to_string()])
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("does not advertise 192.168.9.0/24")
+        );
+        let updated = client
+            .approve_routes(&pve, &["0.0.0.0/0".to_string()])
+            .unwrap();
+        assert_eq!(updated.approved_routes, vec!["10.0.0.0/24", "0.0.0.0/0"]);
+        let log = seen.lock().unwrap();
+        assert!(
+            log.iter().any(|r| r.method == "POST"
+                && r.path == "/api/v1/node/2/approve_routes"
+                && r.body == r#"{"routes":["10.0.0.0/24","0.0.0.0/0"]}"#),
+            "{log:?}"
+        );
+    }
+
+    #[test]
+    fn preauth_keys_by_user_name() {
+        let (base, seen) = fake_headscale();
+        let client = HeadscaleClient::with_key(&base, "hskey", true, 5).unwrap();
+        let user = client.user_id("ci").unwrap();
+        assert_eq!(user, "3");
+        let keys = client.preauth_keys(&user).unwrap();
+        assert_eq!(keys[0].acl_tags, vec!["tag:ci"]);
+        assert!(keys[0].reusable);
+
+        let key = client
+            .create_preauth_key(
+                &user,
+                &NewKey {
+                    ephemeral: true,
+                    expiration: "2026-10-20T00:00:00Z".into(),
+                    tags: vec!["tag:ci".into()],
+                    ..NewKey::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(key.key, "def");
+        client.expire_preauth_key(&user, "abc").unwrap();
+
+        let log = seen.lock().unwrap();
+        let create = log
+            .iter()
+            .find(|r| r.method == "POST" && r.path == "/api/v1/preauthkey")
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&create.body).unwrap();
+        assert_eq!(body["user"], "3");
+        assert_eq!(body["ephemeral"], true);
+        assert_eq!(body["aclTags"][0], "tag:ci");
+        assert!(
+            log.iter().any(|r| r.path == "/api/v1/preauthkey/expire"
+                && r.body == r#"{"key":"abc","user":"3"}"#)
+        );
+    }
+
+    #[test]
+    fn api_errors_are_reported() {
+        let (base, _) = fake_headscale();
+        let bad = HeadscaleClient::with_key(&base, "wrong", true, 5).unwrap();
+        assert!(
+            bad.nodes()
+                .unwrap_err()
+                .to_string()
+                .contains("API key rejected")
+        );
+        let client = HeadscaleClient::with_key(&base, "hskey", true, 5).unwrap();
+        assert!(client.user_id("nobody").is_err());
+        assert!(
+            client
+                .get::<serde_json::Value>("/apikey")
+                .unwrap_err()
+                .to_string()
+                .contains("HTTP 404 from /api/v1/apikey: not found")
+        );
+    }
+
+    #[test]
+    fn expiry_durations() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-10-19T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert_eq!(expiry("24h", now).unwrap(), "2026-10-20T00:00:00Z");
+        assert_eq!(expiry("7d", now).unwrap(), "2026-10-26T00:00:00Z");
+        assert_eq!(expiry("90m", now).unwrap(), "2026-10-19T01:30:00Z");
+        assert!(expiry("1w", now).is_err());
+        assert!(expiry("h", now).is_err());
+    }
+}
@@ -0,0 +1,871 @@
+//! Tailscale/Headscale mesh networking driven by `[[mesh.profiles]]`.
+//!
+//! A profile carries everything `tailscale up` needs for one tailnet, so
+//! `mesh switch` can move this machine between control servers without
+//! retyping flags. The last profile brought up is remembered in the state
+//! directory and shown by `mesh status`.
+
+pub mod config;
+pub mod headscale;
+pub mod status;
+
+use crate::command::CommandRunner;
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::{MeshConfig, MeshProfile};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Extra knobs for one `tailscale up` run.
+#[derive(Debug, Clone, Default)]
+pub struct UpOptions {
+    /// Pass `--force-reauth`, needed when changing control servers.
+    pub reauth: bool,
+    /// File holding the pre-auth key, passed as `--auth-key=file:PATH`.
+    pub key_file: Option<PathBuf>,
+    /// Routes to advertise on top of the profile's.
+    pub extra_routes: Vec<String>,
+}
+
+/// The `tailscale up` arguments for `profile`. `--reset` makes every run
+/// fully describe the node, so flags from a previous profile never linger.
+pub fn up_args(profile: &MeshProfile, options: &UpOptions) -> Vec<String> {
+    let mut args = vec!["up".to_string(), "--reset".to_string()];
+    if let Some(server) = &profile.login_server {
+        args.push(format!("--login-server={server}"));
+    }
+    if let Some(path) = &options.key_file {
+        args.push(format!("--auth-key=file:{}", path.display()));
+    }
+    if !profile.tags.is_empty() {
+        args.push(format!("--advertise-tags={}", profile.tags.join(",")));
+    }
+    if let Some(exit) = &profile.exit_node {
+        args.push(format!("--exit-node={exit}"));
+        if profile.exit_node_allow_lan_access {
+            args.push("--exit-node-allow-lan-access".to_string());
+        }
+    }
+    if profile.advertise_exit_node {
+        args.push("--advertise-exit-node".to_string());
+    }
+    let mut routes = profile.advertise_routes.clone();
+    for route in &options.extra_routes {
+        if !routes.contains(route) {
+            routes.push(route.clone());
+        }
+    }
+    if !routes.is_empty() {
+        args.push(format!("--advertise-routes={}", routes.join(",")));
+    }
+    if profile.accept_routes {
+        args.push("--accept-routes".to_string());
+    }
+    args.push(format!("--accept-dns={}", profile.accept_dns));
+    if profile.ssh {
+        args.push("--ssh".to_string());
+    }
+    if let Some(operator) = &profile.operator {
+        args.push(format!("--operator={operator}"));
+    }
+    if let Some(hostname) = &profile.hostname {
+        args.push(format!("--hostname={hostname}"));
+    }
+    if options.reauth {
+        args.push("--force-reauth".to_string());
+    }
+    args
+}
+
+fn active_file() -> PathBuf {
+    crate::support::state_dir().join("mesh").join("active")
+}
+
+/// Name of the profile last brought up.
+pub fn active_profile() -> Option<String> {
+    read_active(&active_file())
+}
+
+fn read_active(path: &Path) -> Option<String> {
+    let name = std::fs::read_to_string(path).ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn write_active(path: &Path, name: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("cannot create {}", parent.display()))?;
+    }
+    std::fs::write(path, format!("{name}\n"))
+        .with_context(|| format!("cannot write {}", path.display()))
+}
+
+/// The profile to use when none is named: the active one, then
+/// `default_profile`, then the only one defined.
+pub fn pick_profile<'a>(
+    cfg: &'a MeshConfig,
+    wanted: Option<&str>,
+    active: Option<&str>,
+) -> Result<&'a MeshProfile> {
+    if let Some(name) = wanted {
+        return cfg.profile(name);
+    }
+    if let Some(name) = active
+        && let Ok(profile) = cfg.profile(name)
+    {
+        return Ok(profile);
+    }
+    if let Some(name) = &cfg.default_profile {
+        return cfg.profile(name);
+    }
+    match cfg.profiles.as_slice() {
+        [only] => Ok(only),
+        [] => bail!("no mesh profiles configured: add [[mesh.profiles]] to config.toml"),
+        _ => bail!("several mesh profiles configured: pass --profile or set mesh.default_profile"),
+    }
+}
+
+/// Bring the mesh up with `profile`, writing the auth key (if any) to a
+/// private temp file so it never shows up in the process list.
+pub fn bring_up(
+    runner: &dyn CommandRunner,
+    profile: &MeshProfile,
+    mut options: UpOptions,
+    state: &Path,
+) -> Result<()> {
+    let mut key_file = None;
+    if let Some(source) = &profile.auth_key {
+        let key = source.resolve(&format!("mesh profile '{}' auth key", profile.name))?;
+        let mut file = tempfile::NamedTempFile::new().context("cannot create auth key file")?;
+        file.write_all(key.as_bytes())?;
+        options.key_file = Some(file.path().to_path_buf());
+        key_file = Some(file);
+    }
+    let args = up_args(profile, &options);
+    if is_dry_run() {
+        let shown = args.join(" ");
+        tui::info(&format!("[DRY RUN] Would run: sudo tailscale {shown}"));
+        return Ok(());
+    }
+    let arg_refs: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = runner
+        .run_sudo_interactive("tailscale", &arg_refs)
+        .context("failed to run tailscale up")?;
+    drop(key_file);
+    if !result.success {
+        bail!(
+            "tailscale up failed for profile '{}'{}",
+            profile.name,
+            result
+                .exit_code
+                .map(|c| format!(" (exit {c})"))
+                .unwrap_or_default()
+        );
+    }
+    write_active(state, &profile.name)?;
+    Ok(())
+}
+
+/// Move to `target`, forcing re-authentication when the control server
+/// changes (tailscale refuses a new `--login-server` otherwise).
+pub fn switch_to(
+    runner: &dyn CommandRunner,
+    cfg: &MeshConfig,
+    target: &str,
+    state: &Path,
+) -> Result<()> {
+    let profile = cfg.profile(target)?;
+    let previous = read_active(state).and_then(|name| cfg.profile(&name).ok());
+    let reauth = previous.is_some_and(|p| p.login_server != profile.login_server);
+    bring_up(
+        runner,
+        profile,
+        UpOptions {
+            reauth,
+            ..UpOptions::default()
+        },
+        state,
+    )
+}
+
+fn load_checked() -> Result<MeshConfig> {
+    let cfg = MeshConfig::load();
+    let problems = cfg.validate();
+    if !problems.is_empty() {
+        bail!("invalid [mesh] config:\n  {}", problems.join("\n  "));
+    }
+    Ok(cfg)
+}
+
+/// Current mesh state from `tailscale status --json`.
+pub fn current_status(runner: &dyn CommandRunner, ping: bool) -> Result<status::MeshStatus> {
+    let result = runner
+        .run("tailscale", &["status", "--json"])
+        .context("failed to run tailscale status (is tailscale installed?)")?;
+    // `tailscale status` exits 1 when stopped but still prints JSON.
+    if result.stdout.trim().is_empty() {
+        bail!("tailscale status failed: {}", result.stderr.trim());
+    }
+    let mut current = status::parse(&result.stdout)?;
+    current.profile = active_profile();
+    if ping && current.backend_state == "Running" {
+        status::measure_latency(&mut current);
+    }
+    Ok(current)
+}
+
+fn report(result: Result<()>) {
+    if let Err(e) = result {
+        tui::error(&format!("{e:#}"));
+    }
+}
+
+/// Bring up the active or default profile.
+pub fn up() {
+    report(up_profile(None, Vec::new()));
+}
+
+/// Bring up the active or default profile with an extra advertised subnet.
+pub fn advertise(subnet: &str) {
+    report(up_profile(None, vec![subnet.to_string()]));
+}
+
+fn up_profile(wanted: Option<&str>, extra_routes: Vec<String>) -> Result<()> {
+    let cfg = load_checked()?;
+    let active = active_profile();
+    let profile = pick_profile(&cfg, wanted, active.as_deref())?;
+    for route in &extra_routes {
+        route
+            .parse::<ipnet::IpNet>()
+            .with_context(|| format!("'{route}' is not a CIDR subnet"))?;
+    }
+    // Going back up on a different control server needs a fresh login.
+    let reauth = active
+        .and_then(|name| cfg.profile(&name).ok())
+        .is_some_and(|p| p.login_server != profile.login_server);
+    let runner = crate::command::runner();
+    bring_up(
+        runner.as_ref(),
+        profile,
+        UpOptions {
+            reauth,
+            extra_routes,
+            ..UpOptions::default()
+        },
+        &active_file(),
+    )?;
+    if !is_dry_run() {
+        tui::success(&format!("Mesh up with profile '{}'", profile.name));
+    }
+    Ok(())
+}
+
+pub fn status() {
+    let runner = crate::command::runner();
+    match current_status(runner.as_ref(), true) {
+        Ok(current) => status::print(&current),
+        Err(e) => tui::error(&format!("{e:#}")),
+    }
+}
+
+pub fn down() {
+    if is_dry_run() {
+        tui::info("[DRY RUN] Would run: sudo tailscale down");
+        return;
+    }
+    match crate::command::runner().run_sudo("tailscale", &["down"]) {
+        Ok(r) if r.success => tui::success("Mesh down"),
+        Ok(r) => tui::error(&format!("tailscale down failed: {}", r.stderr.trim())),
+        Err(e) => tui::error(&format!("failed to run tailscale down: {e}")),
+    }
+}
+
+/// Menu entry: pick a profile and switch to it.
+pub fn switch_menu() {
+    let cfg = MeshConfig::load();
+    if cfg.profiles.is_empty() {
+        tui::warn("No mesh profiles configured: add [[mesh.profiles]] to config.toml");
+        return;
+    }
+    let active = active_profile();
+    let names: Vec<String> = cfg
+        .profiles
+        .iter()
+        .map(|p| {
+            if active.as_deref() == Some(p.name.as_str()) {
+                format!("{} (active)", p.name)
+            } else {
+                p.name.clone()
+            }
+        })
+        .collect();
+    let items: Vec<&str> = names.iter().map(String::as_str).collect();
+    if let Some(choice) = tui::select_with_back("Switch mesh profile", &items, 0) {
+        report(switch(&cfg.profiles[choice].name));
+    }
+}
+
+fn switch(name: &str) -> Result<()> {
+    let cfg = load_checked()?;
+    let runner = crate::command::runner();
+    switch_to(runner.as_ref(), &cfg, name, &active_file())?;
+    if !is_dry_run() {
+        tui::success(&format!("Switched mesh to profile '{name}'"));
+    }
+    Ok(())
+}
+
+pub fn command() -> Command {
+    let json = || {
+        Arg::new("json")
+            .long("json")
+            .action(ArgAction::SetTrue)
+            .help("Output as JSON")
+    };
+    let user = || {
+        Arg::new("user")
+            .long("user")
+            .value_name("USER")
+            .help("Headscale user name or id")
+    };
+    Command::new("mesh")
+        .about("Mesh networking (Tailscale/Headscale profiles)")
+        .subcommand(
+            Command::new("up")
+                .about("Bring the mesh up with a profile")
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .value_name("NAME")
+                        .help("Profile to use (default: active, then mesh.default_profile)"),
+                ),
+        )
+        .subcommand(
+            Command::new("switch")
+                .about("Move this machine to another mesh profile")
+                .arg(Arg::new("profile").required(true).value_name("PROFILE")),
+        )
+        .subcommand(Command::new("down").about("Disconnect from the mesh"))
+        .subcommand(
+            Command::new("status")
+                .about("Show peers, latency and DERP region")
+                .arg(json())
+                .arg(
+                    Arg::new("no-ping")
+                        .long("no-ping")
+                        .action(ArgAction::SetTrue)
+                        .help("Skip measuring peer latency"),
+                ),
+        )
+        .subcommand(
+            Command::new("profiles")
+                .about("List configured mesh profiles")
+                .arg(json()),
+        )
+        .subcommand(
+            Command::new("advertise")
+                .about("Bring the mesh up advertising an extra subnet")
+                .arg(Arg::new("subnet").required(true).value_name("CIDR")),
+        )
+        .subcommand(
+            Command::new("headscale")
+                .about("Administer a Headscale control server")
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .global(true)
+                        .value_name("NAME")
+                        .help("Profile whose headscale API to use"),
+                )
+                .subcommand_required(true)
+                .subcommand(Command::new("nodes").about("List nodes").arg(json()))
+                .subcommand(
+                    Command::new("routes")
+                        .about("List advertised routes, or approve them")
+                        .arg(json())
+                        .subcommand(
+                            Command::new("approve")
+                                .about("Approve routes a node advertises")
+                                .arg(Arg::new("node").required(true).value_name("NODE"))
+                                .arg(
+                                    Arg::new("routes")
+                                        .num_args(0..)
+                                        .value_name("ROUTE")
+                                        .help("Routes to approve"),
+                                )
+                                .arg(
+                                    Arg::new("all")
+                                        .long("all")
+                                        .action(ArgAction::SetTrue)
+                                        .conflicts_with("routes")
+                                        .help("Approve every pending route of the node"),
+                                ),
+                        ),
+                )
+                .subcommand(
+                    Command::new("keys")
+                        .about("List or manage pre-auth keys")
+                        .arg(user())
+                        .arg(json())
+                        .subcommand(
+                            Command::new("create")
+                                .about("Create a pre-auth key")
+                                .arg(user().required(true))
+                                .arg(
+                                    Arg::new("reusable")
+                                        .long("reusable")
+                                        .action(ArgAction::SetTrue),
+                                )
+                                .arg(
+                                    Arg::new("ephemeral")
+                                        .long("ephemeral")
+                                        .action(ArgAction::SetTrue),
+                                )
+                                .arg(
+                                    Arg::new("expiration")
+                                        .long("expiration")
+                                        .value_name("DURATION")
+                                        .default_value("1h")
+                                        .help("Lifetime, e.g. 90m, 24h, 7d"),
+                                )
+                                .arg(
+                                    Arg::new("tag")
+                                        .long("tag")
+                                        .value_name("TAG")
+                                        .action(ArgAction::Append)
+                                        .help("ACL tag for nodes using the key (repeatable)"),
+                                )
+                                .arg(json()),
+                        )
+                        .subcommand(
+                            Command::new("expire")
+                                .about("Expire a pre-auth key")
+                                .arg(user().required(true))
+                                .arg(Arg::new("key").required(true).value_name("KEY")),
+                        ),
+                ),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("up", m)) => up_profile(
+            m.get_one::<String>("profile").map(String::as_str),
+            Vec::new(),
+        ),
+        Some(("switch", m)) => switch(
+            m.get_one::<String>("profile")
+                .map(String::as_str)
+                .unwrap_or_default(),
+        ),
+        Some(("down", _)) => {
+            down();
+            Ok(())
+        }
+        Some(("profiles", m)) => list_profiles(m.get_flag("json")),
+        Some(("advertise", m)) => up_profile(
+            None,
+            m.get_one::<String>("subnet").cloned().into_iter().collect(),
+        ),
+        Some(("headscale", m)) => handle_headscale(m),
+        Some(("status", m)) => {
+            let runner = crate::command::runner();
+            let current = current_status(runner.as_ref(), !m.get_flag("no-ping"))?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&current)?);
+            } else {
+                status::print(&current);
+            }
+            Ok(())
+        }
+        _ => {
+            status();
+            Ok(())
+        }
+    }
+}
+
+fn list_profiles(json: bool) -> Result<()> {
+    let cfg = MeshConfig::load();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&cfg.profiles)?);
+        return Ok(());
+    }
+    for problem in cfg.validate() {
+        tui::warn(&problem);
+    }
+    if cfg.profiles.is_empty() {
+        println!("No mesh profiles configured.");
+        return Ok(());
+    }
+    let active = active_profile();
+    for profile in &cfg.profiles {
+        let marker = if active.as_deref() == Some(profile.name.as_str()) {
+            "*"
+        } else if cfg.default_profile.as_deref() == Some(profile.name.as_str()) {
+            "d"
+        } else {
+            " "
+        };
+        let mut details = vec![
+            profile
+                .login_server
+                .clone()
+                .unwrap_or_else(|| "tailscale.com".to_string()),
+        ];
+        if !profile.tags.is_empty() {
+            details.push(profile.tags.join(","));
+        }
+        if let Some(exit) = &profile.exit_node {
+            details.push(format!("exit {exit}"));
+        }
+        if !profile.advertise_routes.is_empty() {
+            details.push(format!("routes {}", profile.advertise_routes.join(",")));
+        }
+        if profile.headscale.is_some() {
+            details.push("headscale api".to_string());
+        }
+        println!("{marker} {:<16} {}", profile.name, details.join("  "));
+    }
+    Ok(())
+}
+
+fn handle_headscale(matches: &ArgMatches) -> Result<()> {
+    let cfg = load_checked()?;
+    let active = active_profile();
+    let profile = pick_profile(
+        &cfg,
+        matches.get_one::<String>("profile").map(String::as_str),
+        active.as_deref(),
+    )?;
+    let client = headscale::HeadscaleClient::new(profile)?;
+    match matches.subcommand() {
+        Some(("nodes", m)) => {
+            let nodes = client.nodes()?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&nodes)?);
+                return Ok(());
+            }
+            println!(
+                "{:<4} {:<20} {:<10} {:<16} {:<8} TAGS",
+                "ID", "NAME", "USER", "IP", "STATE"
+            );
+            for node in &nodes {
+                let tags: Vec<&str> = node
+                    .valid_tags
+                    .iter()
+                    .chain(&node.forced_tags)
+                    .map(String::as_str)
+                    .collect();
+                println!(
+                    "{:<4} {:<20} {:<10} {:<16} {:<8} {}",
+                    node.id,
+                    node.display_name(),
+                    node.user.as_ref().map(|u| u.name.as_str()).unwrap_or("-"),
+                    node.ip_addresses.first().map(String::as_str).unwrap_or("-"),
+                    if node.online { "online" } else { "offline" },
+                    tags.join(",")
+                );
+            }
+            Ok(())
+        }
+        Some(("routes", m)) => {
+            if let Some(("approve", a)) = m.subcommand() {
+                let wanted = a
+                    .get_one::<String>("node")
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let node = client.node(wanted)?;
+                let routes: Vec<String> = if a.get_flag("all") {
+                    node.pending_routes()
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect()
+                } else {
+                    a.get_many::<String>("routes")
+                        .map(|v| v.cloned().collect())
+                        .unwrap_or_default()
+                };
+                if routes.is_empty() {
+                    tui::info(&format!("Nothing to approve on {}", node.display_name()));
+                    return Ok(());
+                }
+                if is_dry_run() {
+                    tui::info(&format!(
+                        "[DRY RUN] Would approve {} on {}",
+                        routes.join(", "),
+                        node.display_name()
+                    ));
+                    return Ok(());
+                }
+                let updated = client.approve_routes(&node, &routes)?;
+                tui::success(&format!(
+                    "{} approved routes: {}",
+                    updated.display_name(),
+                    updated.approved_routes.join(", ")
+                ));
+                return Ok(());
+            }
+            let nodes = client.nodes()?;
+            if m.get_flag("json") {
+                let routes: Vec<_> = nodes
+                    .iter()
+                    .filter(|n| !n.available_routes.is_empty())
+                    .map(|n| {
+                        serde_json::json!({
+                            "node": n.display_name(),
+                            "id": n.id,
+                            "available": n.available_routes,
+                            "approved": n.approved_routes,
+                            "serving": n.subnet_routes,
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&routes)?);
+                return Ok(());
+            }
+            println!("{:<20} {:<20} STATE", "NODE", "ROUTE");
+            for node in &nodes {
+                for route in &node.available_routes {
+                    let state = if node.subnet_routes.contains(route) {
+                        "serving"
+                    } else if node.approved_routes.contains(route) {
+                        "approved"
+                    } else {
+                        "pending"
+                    };
+                    println!("{:<20} {:<20} {state}", node.display_name(), route);
+                }
+            }
+            Ok(())
+        }
+        Some(("keys", m)) => match m.subcommand() {
+            Some(("create", c)) => {
+                let user = client.user_id(c.get_one::<String>("user").unwrap())?;
+                let new = headscale::NewKey {
+                    reusable: c.get_flag("reusable"),
+                    ephemeral: c.get_flag("ephemeral"),
+                    expiration: headscale::expiry(
+                        c.get_one::<String>("expiration").unwrap(),
+                        chrono::Utc::now(),
+                    )?,
+                    tags: c
+                        .get_many::<String>("tag")
+                        .map(|v| v.cloned().collect())
+                        .unwrap_or_default(),
+                };
+                if is_dry_run() {
+                    tui::info(&format!(
+                        "[DRY RUN] Would create a pre-auth key for user {user} expiring {}",
+                        new.expiration
+                    ));
+                    return Ok(());
+                }
+                let key = client.create_preauth_key(&user, &new)?;
+                if c.get_flag("json") {
+                    println!("{}", serde_json::to_string_pretty(&key)?);
+                } else {
+                    println!("{}", key.key);
+                }
+                Ok(())
+            }
+            Some(("expire", e)) => {
+                let user = client.user_id(e.get_one::<String>("user").unwrap())?;
+                let key = e.get_one::<String>("key").unwrap();
+                if is_dry_run() {
+                    tui::info(&format!("[DRY RUN] Would expire pre-auth key {key}"));
+                    return Ok(());
+                }
+                client.expire_preauth_key(&user, key)?;
+                tui::success("Pre-auth key expired");
+                Ok(())
+            }
+            _ => {
+                let users = match m.get_one::<String>("user") {
+                    Some(name) => vec![client.user_id(name)?],
+                    None => client.users()?.into_iter().map(|u| u.id).collect(),
+                };
+                let mut keys = Vec::new();
+                for user in &users {
+                    keys.extend(client.preauth_keys(user)?);
+                }
+                if m.get_flag("json") {
+                    println!("{}", serde_json::to_string_pretty(&keys)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:<4} {:<10} {:<9} {:<9} {:<5} {:<21} TAGS",
+                    "ID", "USER", "REUSABLE", "EPHEMERAL", "USED", "EXPIRES"
+                );
+                for key in &keys {
+                    println!(
+                        "{:<4} {:<10} {:<9} {:<9} {:<5} {:<21} {}",
+                        key.id,
+                        key.user.as_ref().map(|u| u.name.as_str()).unwrap_or("-"),
+                        key.reusable,
+                        key.ephemeral,
+                        key.used,
+                        key.expiration.as_deref().unwrap_or("-"),
+                        key.acl_tags.join(",")
+                    );
+                }
+                Ok(())
+            }
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    fn config() -> MeshConfig {
+        toml::from_str(
+            r#"
+[[profiles]]
+name = "lab"
+login_server = "https://hs.lab.example"
+tags = ["tag:server"]
+advertise_routes = ["10.0.0.0/24"]
+operator = "ops"
+
+[[profiles]]
+name = "lab-exit"
+login_server = "https://hs.lab.example"
+exit_node = "100.64.0.7"
+exit_node_allow_lan_access = true
+
+[[profiles]]
+name = "work"
+accept_routes = false
+accept_dns = true
+ssh = false
+hostname = "ws-ops"
+"#,
+        )
+        .unwrap()
+    }
+
+    fn ok() -> CommandResult {
+        CommandResult {
+            success: true,
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_code: Some(0),
+        }
+    }
+
+    #[test]
+    fn up_args_follow_the_profile() {
+        let cfg = config();
+        let lab = cfg.profile("lab").unwrap();
+        assert_eq!(
+            up_args(
+                lab,
+                &UpOptions {
+                    extra_routes: vec!["10.0.0.0/24".into(), "10.0.5.0/24".into()],
+                    key_file: Some(PathBuf::from("/tmp/k")),
+                    ..UpOptions::default()
+                }
+            ),
+            vec![
+                "up",
+                "--reset",
+                "--login-server=https://hs.lab.example",
+                "--auth-key=file:/tmp/k",
+                "--advertise-tags=tag:server",
+                "--advertise-routes=10.0.0.0/24,10.0.5.0/24",
+                "--accept-routes",
+                "--accept-dns=false",
+                "--ssh",
+                "--operator=ops",
+            ]
+        );
+        let work = cfg.profile("work").unwrap();
+        assert_eq!(
+            up_args(
+                work,
+                &UpOptions {
+                    reauth: true,
+                    ..UpOptions::default()
+                }
+            ),
+            vec![
+                "up",
+                "--reset",
+                "--accept-dns=true",
+                "--hostname=ws-ops",
+                "--force-reauth"
+            ]
+        );
+        let exit = up_args(cfg.profile("lab-exit").unwrap(), &UpOptions::default());
+        assert!(exit.contains(&"--exit-node=100.64.0.7".to_string()));
+        assert!(exit.contains(&"--exit-node-allow-lan-access".to_string()));
+    }
+
+    #[test]
+    fn profile_selection_order() {
+        let mut cfg = config();
+        assert!(pick_profile(&cfg, None, None).is_err());
+        assert_eq!(pick_profile(&cfg, None, Some("work")).unwrap().name, "work");
+        cfg.default_profile = Some("lab".into());
+        assert_eq!(pick_profile(&cfg, None, Some("gone")).unwrap().name, "lab");
+        assert_eq!(
+            pick_profile(&cfg, Some("lab-exit"), Some("work"))
+                .unwrap()
+                .name,
+            "lab-exit"
+        );
+        cfg.profiles.truncate(1);
+        cfg.default_profile = None;
+        assert_eq!(pick_profile(&cfg, None, None).unwrap().name, "lab");
+    }
+
+    #[test]
+    fn switch_reauths_only_across_control_servers() {
+        let cfg = config();
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("mesh").join("active");
+        let runner = MockRunner::new();
+        runner.set_default(ok());
+
+        switch_to(&runner, &cfg, "lab", &state).unwrap();
+        assert_eq!(read_active(&state).as_deref(), Some("lab"));
+        switch_to(&runner, &cfg, "lab-exit", &state).unwrap();
+        switch_to(&runner, &cfg, "work", &state).unwrap();
+        assert_eq!(read_active(&state).as_deref(), Some("work"));
+
+        let reauths: Vec<bool> = runner
+            .get_history()
+            .iter()
+            .map(|call| call.contains("--force-reauth"))
+            .collect();
+        assert_eq!(reauths, vec![false, false, true]);
+        assert!(switch_to(&runner, &cfg, "nope", &state).is_err());
+    }
+
+    #[test]
+    fn failed_up_keeps_previous_profile() {
+        let cfg = config();
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("active");
+        write_active(&state, "lab").unwrap();
+        let runner = MockRunner::new();
+        runner.set_default(CommandResult {
+            success: false,
+            exit_code: Some(1),
+            ..ok()
+        });
+        let err = switch_to(&runner, &cfg, "work", &state).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("tailscale up failed for profile 'work'")
+        );
+        assert_eq!(read_active(&state).as_deref(), Some("lab"));
+    }
+}
@@ -0,0 +1,368 @@
+//! `tailscale status --json`, reduced to what `mesh status` shows: this
+//! node, its DERP region and exit node, and each peer with its connection
+//! path and (from `tailscale ping`) latency.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawStatus {
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    backend_state: String,
+    #[serde(rename = "Self")]
+    self_node: Option<RawPeer>,
+    #[serde(default)]
+    peer: Option<HashMap<String, RawPeer>>,
+    #[serde(default)]
+    current_tailnet: Option<RawTailnet>,
+    #[serde(default)]
+    health: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawTailnet {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawPeer {
+    #[serde(default)]
+    host_name: String,
+    #[serde(default, rename = "DNSName")]
+    dns_name: String,
+    #[serde(default, rename = "OS")]
+    os: String,
+    #[serde(default, rename = "TailscaleIPs")]
+    tailscale_ips: Option<Vec<String>>,
+    #[serde(default)]
+    online: bool,
+    #[serde(default)]
+    relay: String,
+    #[serde(default)]
+    cur_addr: String,
+    #[serde(default)]
+    exit_node: bool,
+    #[serde(default)]
+    exit_node_option: bool,
+    #[serde(default)]
+    primary_routes: Option<Vec<String>>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    last_seen: Option<String>,
+    #[serde(default)]
+    rx_bytes: u64,
+    #[serde(default)]
+    tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Peer {
+    pub name: String,
+    /// MagicDNS name without the trailing dot.
+    pub dns_name: String,
+    pub ips: Vec<String>,
+    pub os: String,
+    pub online: bool,
+    /// Home DERP region code, e.g. `fra`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derp_region: Option<String>,
+    /// Endpoint of a direct (peer-to-peer) connection, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direct: Option<String>,
+    /// Round trip from `tailscale ping`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// How the ping went: `direct 192.0.2.4:41641` or `DERP(fra)`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub exit_node: bool,
+    pub exit_node_option: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MeshStatus {
+    /// ghostctl profile last brought up, if any.
+    pub profile: Option<String>,
+    pub backend_state: String,
+    pub version: String,
+    pub tailnet: Option<String>,
+    #[serde(rename = "self")]
+    pub self_node: Option<Peer>,
+    /// DERP region this node is homed on.
+    pub derp_region: Option<String>,
+    /// Peer currently used as exit node.
+    pub exit_node: Option<String>,
+    pub peers: Vec<Peer>,
+    pub health: Vec<String>,
+}
+
+impl From<RawPeer> for Peer {
+    fn from(raw: RawPeer) -> Self {
+        let non_empty = |s: String| (!s.is_empty()).then_some(s);
+        Peer {
+            name: raw.host_name,
+            dns_name: raw.dns_name.trim_end_matches('.').to_string(),
+            ips: raw.tailscale_ips.unwrap_or_default(),
+            os: raw.os,
+            online: raw.online,
+            derp_region: non_empty(raw.relay),
+            direct: non_empty(raw.cur_addr),
+            latency_ms: None,
+            path: None,
+            exit_node: raw.exit_node,
+            exit_node_option: raw.exit_node_option,
+            routes: raw.primary_routes.unwrap_or_default(),
+            tags: raw.tags.unwrap_or_default(),
+            // Peers that never connected report the zero time.
+            last_seen: raw.last_seen.filter(|t| !t.starts_with("0001-")),
+            rx_bytes: raw.rx_bytes,
+            tx_bytes: raw.tx_bytes,
+        }
+    }
+}
+
+/// Parse `tailscale status --json`. Peers are sorted online first, then by name.
+pub fn parse(json: &str) -> Result<MeshStatus> {
+    let raw: RawStatus =
+        serde_json::from_str(json).context("unexpected `tailscale status --json` output")?;
+    let mut peers: Vec<Peer> = raw
+        .peer
+        .unwrap_or_default()
+        .into_values()
+        .map(Peer::from)
+        .collect();
+    peers.sort_by(|a, b| b.online.cmp(&a.online).then_with(|| a.name.cmp(&b.name)));
+    let self_node = raw.self_node.map(Peer::from);
+    Ok(MeshStatus {
+        profile: None,
+        backend_state: raw.backend_state,
+        version: raw.version,
+        tailnet: raw
+            .current_tailnet
+            .map(|t| t.name)
+            .filter(|n| !n.is_empty()),
+        derp_region: self_node.as_ref().and_then(|s| s.derp_region.clone()),
+        exit_node: peers.iter().find(|p| p.exit_node).map(|p| p.name.clone()),
+        self_node,
+        peers,
+        health: raw.health.unwrap_or_default(),
+    })
+}
+
+/// Latency and path from `tailscale ping` output, e.g.
+/// `pong from pve1 (100.64.0.2) via 192.0.2.4:41641 in 3ms` or
+/// `pong from pve1 (100.64.0.2) via DERP(fra) in 41ms`.
+pub fn parse_ping(output: &str) -> Option<(f64, String)> {
+    let line = output.lines().rev().find(|l| l.starts_with("pong from"))?;
+    let (_, via) = line.split_once(" via ")?;
+    let (path, time) = via.rsplit_once(" in ")?;
+    let time = time.trim();
+    let ms = match time.strip_suffix("ms") {
+        Some(ms) => ms.parse::<f64>().ok()?,
+        None => time.strip_suffix('s')?.parse::<f64>().ok()? * 1000.0,
+    };
+    let path = if path.starts_with("DERP(") {
+        path.to_string()
+    } else {
+        format!("direct {path}")
+    };
+    Some((ms, path))
+}
+
+/// Ping every online peer once, in parallel.
+pub fn measure_latency(status: &mut MeshStatus) {
+    let runner = crate::command::runner();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = status
+            .peers
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.online && !p.ips.is_empty())
+            .map(|(i, peer)| {
+                let runner = runner.clone();
+                let ip = peer.ips[0].clone();
+                scope.spawn(move || {
+                    let result = runner
+                        .run("tailscale", &["ping", "--c", "1", "--timeout", "3s", &ip])
+                        .ok()?;
+                    parse_ping(&result.stdout).map(|found| (i, found))
+                })
+            })
+            .collect();
+        for handle in handles {
+            if let Ok(Some((i, (ms, path)))) = handle.join() {
+                status.peers[i].latency_ms = Some(ms);
+                status.peers[i].path = Some(path);
+            }
+        }
+    });
+}
+
+pub fn print(status: &MeshStatus) {
+    let profile = status.profile.as_deref().unwrap_or("-");
+    println!(
+        "Mesh: {} (profile {profile}, tailscale {})",
+        status.backend_state, status.version
+    );
+    if let Some(tailnet) = &status.tailnet {
+        println!("Tailnet: {tailnet}");
+    }
+    if let Some(me) = &status.self_node {
+        println!(
+            "This node: {} {} (DERP {})",
+            me.name,
+            me.ips.join(", "),
+            status.derp_region.as_deref().unwrap_or("-")
+        );
+    }
+    if let Some(exit) = &status.exit_node {
+        println!("Exit node: {exit}");
+    }
+    for warning in &status.health {
+        crate::tui::warn(warning);
+    }
+    if status.peers.is_empty() {
+        println!("No peers.");
+        return;
+    }
+    println!();
+    println!(
+        "{:<22} {:<16} {:<8} {:<6} {:>8}  PATH",
+        "PEER", "IP", "OS", "DERP", "LATENCY"
+    );
+    for peer in &status.peers {
+        let latency = match peer.latency_ms {
+            Some(ms) => format!("{ms:.0}ms"),
+            None if peer.online => "-".to_string(),
+            None => "offline".to_string(),
+        };
+        let mut path = peer
+            .path
+            .clone()
+            .or_else(|| peer.direct.as_ref().map(|d| format!("direct {d}")))
+            .unwrap_or_default();
+        if peer.exit_node {
+            path.push_str(" [exit node]");
+        } else if peer.exit_node_option {
+            path.push_str(" [exit option]");
+        }
+        if !peer.routes.is_empty() {
+            path.push_str(&format!(" routes {}", peer.routes.join(",")));
+        }
+        println!(
+            "{:<22} {:<16} {:<8} {:<6} {:>8}  {}",
+            peer.name,
+            peer.ips.first().map(String::as_str).unwrap_or("-"),
+            peer.os,
+            peer.derp_region.as_deref().unwrap_or("-"),
+            latency,
+            path.trim()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = r#"{
+  "Version": "1.76.6-t1234",
+  "BackendState": "Running",
+  "Self": {
+    "ID": "n1", "HostName": "ws1", "DNSName": "ws1.lab.ts.net.", "OS": "linux",
+    "TailscaleIPs": ["100.64.0.1", "fd7a:115c:a1e0::1"],
+    "Relay": "fra", "CurAddr": "", "Online": true, "ExitNode": false
+  },
+  "CurrentTailnet": {"Name": "lab.example", "MagicDNSSuffix": "lab.ts.net", "MagicDNSEnabled": true},
+  "Peer": {
+    "nodekey:aa": {
+      "HostName": "pve1", "DNSName": "pve1.lab.ts.net.", "OS": "linux",
+      "TailscaleIPs": ["100.64.0.2"], "Relay": "fra", "CurAddr": "192.0.2.4:41641",
+      "Online": true, "ExitNode": true, "ExitNodeOption": true,
+      "PrimaryRoutes": ["10.0.0.0/24"], "Tags": ["tag:server"],
+      "LastSeen": "2026-10-19T08:00:00Z", "RxBytes": 1200, "TxBytes": 3400
+    },
+    "nodekey:bb": {
+      "HostName": "laptop", "DNSName": "laptop.lab.ts.net.", "OS": "macOS",
+      "TailscaleIPs": ["100.64.0.3"], "Relay": "", "CurAddr": "",
+      "Online": false, "LastSeen": "0001-01-01T00:00:00Z"
+    },
+    "nodekey:cc": {
+      "HostName": "nas", "DNSName": "nas.lab.ts.net.", "OS": "linux",
+      "TailscaleIPs": ["100.64.0.4"], "Relay": "ams", "CurAddr": "", "Online": true
+    }
+  },
+  "Health": ["Tailscale could not connect to the 'nyc' relay server."]
+}"#;
+
+    #[test]
+    fn status_json_becomes_peers() {
+        let status = parse(STATUS).unwrap();
+        assert_eq!(status.backend_state, "Running");
+        assert_eq!(status.tailnet.as_deref(), Some("lab.example"));
+        assert_eq!(status.derp_region.as_deref(), Some("fra"));
+        assert_eq!(status.exit_node.as_deref(), Some("pve1"));
+        assert_eq!(
+            status
+                .peers
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["nas", "pve1", "laptop"]
+        );
+        let pve = &status.peers[1];
+        assert_eq!(pve.dns_name, "pve1.lab.ts.net");
+        assert_eq!(pve.direct.as_deref(), Some("192.0.2.4:41641"));
+        assert_eq!(pve.routes, vec!["10.0.0.0/24"]);
+        let laptop = &status.peers[2];
+        assert_eq!(laptop.derp_region, None);
+        assert_eq!(laptop.last_seen, None);
+        assert_eq!(status.health.len(), 1);
+
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["self"]["name"], "ws1");
+        assert_eq!(json["peers"][0]["derp_region"], "ams");
+    }
+
+    #[test]
+    fn logged_out_status_parses() {
+        let status =
+            parse(r#"{"Version":"1.76.6","BackendState":"NeedsLogin","Self":null,"Peer":null}"#)
+                .unwrap();
+        assert_eq!(status.backend_state, "NeedsLogin");
+        assert!(status.peers.is_empty() && status.self_node.is_none());
+    }
+
+    #[test]
+    fn ping_output_gives_latency_and_path() {
+        assert_eq!(
+            parse_ping("pong from pve1 (100.64.0.2) via 192.0.2.4:41641 in 3ms\n"),
+            Some((3.0, "direct 192.0.2.4:41641".to_string()))
+        );
+        assert_eq!(
+            parse_ping("pong from nas (100.64.0.4) via DERP(ams) in 41ms"),
+            Some((41.0, "DERP(ams)".to_string()))
+        );
+        assert_eq!(
+            parse_ping("pong from nas (100.64.0.4) via DERP(ams) in 1.2s"),
+            Some((1200.0, "DERP(ams)".to_string()))
+        );
+        assert_eq!(parse_ping("ping \"100.64.0.9\" timed out"), None);
+    }
+}
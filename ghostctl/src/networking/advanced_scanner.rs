@@ -4,7 +4,7 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::net::IpAddr;
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
@@ -150,21 +150,38 @@ pub struct ServiceInfo {
     pub ssl_info: Option<SslInfo>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// TLS details filled in by [`super::tls::inspect`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SslInfo {
+    /// Negotiated protocol, e.g. `TLSv1.3`.
     pub version: String,
     pub cipher: String,
     pub certificate: Option<CertificateInfo>,
+    /// Every protocol version the server accepted, oldest first.
+    #[serde(default)]
+    pub supported_versions: Vec<String>,
+    /// Weak cipher suites (NULL, export, anonymous, RC4, DES, 3DES) accepted.
+    #[serde(default)]
+    pub weak_ciphers: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CertificateInfo {
     pub subject: String,
     pub issuer: String,
-    pub validity_start: SystemTime,
-    pub validity_end: SystemTime,
+    /// RFC 3339.
+    pub validity_start: String,
+    /// RFC 3339.
+    pub validity_end: String,
     pub serial_number: String,
     pub signature_algorithm: String,
+    #[serde(default)]
+    pub subject_alt_names: Vec<String>,
+    /// Key type and size, e.g. `RSA 2048`.
+    #[serde(default)]
+    pub public_key: String,
+    #[serde(default)]
+    pub self_signed: bool,
 }
 
 /// OS fingerprinting results
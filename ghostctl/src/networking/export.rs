@@ -1,13 +1,15 @@
 //! Export functionality for scan results
 //!
-//! Supports JSON, CSV, and Nmap XML-compatible formats
+//! Supports JSON, CSV, and Nmap XML-compatible formats. Results are first
+//! grouped into a [`ScanReport`], which is also what `scan import` builds
+//! from nmap XML, so imported scans render through the same writers.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 
+use super::advanced_scanner::SslInfo;
 use super::scanner::{PortStatus, ScanResult};
 use super::services;
 
@@ -42,9 +44,14 @@ impl std::str::FromStr for ExportFormat {
 pub struct ExportScanResult {
     pub target: String,
     pub port: u16,
+    #[serde(default = "default_protocol")]
+    pub protocol: String,
     pub status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub service: Option<String>,
+    /// Product name, when the scanner reports it apart from the version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -52,31 +59,59 @@ pub struct ExportScanResult {
     pub response_time_ms: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tls: Option<bool>,
+    /// Certificate, protocol versions and weak ciphers from TLS inspection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl: Option<SslInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scripts: Vec<ScriptOutput>,
+}
+
+/// Output of an nmap NSE script.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptOutput {
+    pub id: String,
+    pub output: String,
+}
+
+/// An OS fingerprint match, best first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsMatch {
+    pub name: String,
+    pub accuracy: u8,
+}
+
+fn default_protocol() -> String {
+    "tcp".to_string()
 }
 
 impl From<&ScanResult> for ExportScanResult {
     fn from(r: &ScanResult) -> Self {
-        let version = r
-            .banner
-            .as_ref()
-            .and_then(|b| services::extract_version(r.port, b));
+        let version = r.version.clone().or_else(|| {
+            r.banner
+                .as_ref()
+                .and_then(|b| services::extract_version(r.port, b))
+        });
 
         ExportScanResult {
             target: r.target.clone(),
             port: r.port,
+            protocol: default_protocol(),
             status: format!("{:?}", r.status),
             service: r
                 .service
                 .clone()
                 .or_else(|| services::get_service_name(r.port).map(|s| s.to_string())),
+            product: None,
             version,
             banner: r.banner.clone(),
             response_time_ms: r.response_time.as_millis() as u64,
-            tls: if services::is_tls_port(r.port) {
+            tls: if r.tls.is_some() || services::is_tls_port(r.port) {
                 Some(true)
             } else {
                 None
             },
+            ssl: r.tls.clone(),
+            scripts: Vec::new(),
         }
     }
 }
@@ -105,6 +140,11 @@ pub struct HostReport {
     pub hostname: Option<String>,
     pub status: String,
     pub ports: Vec<ExportScanResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<OsMatch>,
+    /// Host-level script output.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scripts: Vec<ScriptOutput>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,34 +162,49 @@ pub fn export_results(
     results: &[ScanResult],
     path: &Path,
     format: ExportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    export_report(&build_report(results), path, format)
+}
+
+/// Export a report to a file
+pub fn export_report(
+    report: &ScanReport,
+    path: &Path,
+    format: ExportFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let file = File::create(path)?;
     let mut writer = BufWriter::new(file);
+    write_report(report, &mut writer, format)?;
+    writer.flush()?;
+    Ok(())
+}
 
+/// Render a report in `format`
+pub fn write_report<W: Write>(
+    report: &ScanReport,
+    writer: &mut W,
+    format: ExportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
     match format {
-        ExportFormat::Json => export_json(results, &mut writer, false)?,
-        ExportFormat::JsonPretty => export_json(results, &mut writer, true)?,
-        ExportFormat::Csv => export_csv(results, &mut writer)?,
-        ExportFormat::NmapXml => export_nmap_xml(results, &mut writer)?,
-        ExportFormat::Markdown => export_markdown(results, &mut writer)?,
+        ExportFormat::Json => export_json(report, writer, false)?,
+        ExportFormat::JsonPretty => export_json(report, writer, true)?,
+        ExportFormat::Csv => export_csv(report, writer)?,
+        ExportFormat::NmapXml => export_nmap_xml(report, writer)?,
+        ExportFormat::Markdown => export_markdown(report, writer)?,
     }
-
-    writer.flush()?;
     Ok(())
 }
 
 /// Export results as JSON
 fn export_json<W: Write>(
-    results: &[ScanResult],
+    report: &ScanReport,
     writer: &mut W,
     pretty: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let report = build_report(results);
-
     if pretty {
-        serde_json::to_writer_pretty(writer, &report)?;
+        serde_json::to_writer_pretty(writer, report)?;
     } else {
-        serde_json::to_writer(writer, &report)?;
+        serde_json::to_writer(writer, report)?;
     }
 
     Ok(())
@@ -157,7 +212,7 @@ fn export_json<W: Write>(
 
 /// Export results as CSV
 fn export_csv<W: Write>(
-    results: &[ScanResult],
+    report: &ScanReport,
     writer: &mut W,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Header
@@ -166,17 +221,16 @@ fn export_csv<W: Write>(
         "target,port,status,service,version,banner,response_time_ms,tls"
     )?;
 
-    for result in results {
-        let export = ExportScanResult::from(result);
+    for export in report.hosts.iter().flat_map(|h| &h.ports) {
         writeln!(
             writer,
             "{},{},{},{},{},{},{},{}",
             escape_csv(&export.target),
             export.port,
             escape_csv(&export.status),
-            escape_csv(&export.service.unwrap_or_default()),
-            escape_csv(&export.version.unwrap_or_default()),
-            escape_csv(&export.banner.unwrap_or_default()),
+            escape_csv(export.service.as_deref().unwrap_or_default()),
+            escape_csv(export.version.as_deref().unwrap_or_default()),
+            escape_csv(export.banner.as_deref().unwrap_or_default()),
             export.response_time_ms,
             export.tls.map(|t| t.to_string()).unwrap_or_default(),
         )?;
@@ -187,17 +241,16 @@ fn export_csv<W: Write>(
 
 /// Export results as Nmap-compatible XML
 fn export_nmap_xml<W: Write>(
-    results: &[ScanResult],
+    report: &ScanReport,
     writer: &mut W,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let report = build_report(results);
-
     writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
     writeln!(writer, r#"<!DOCTYPE nmaprun>"#)?;
     writeln!(
         writer,
-        r#"<nmaprun scanner="{}" args="" start="{}" version="{}">"#,
+        r#"<nmaprun scanner="{}" args="{}" start="{}" version="{}">"#,
         xml_escape(&report.scan_info.scanner),
+        xml_escape(report.scan_info.args.as_deref().unwrap_or_default()),
         xml_escape(&report.scan_info.start_time),
         xml_escape(&report.scan_info.version)
     )?;
@@ -214,8 +267,13 @@ fn export_nmap_xml<W: Write>(
         )?;
         writeln!(
             writer,
-            r#"<address addr="{}" addrtype="ipv4" />"#,
-            xml_escape(&host.address)
+            r#"<address addr="{}" addrtype="{}" />"#,
+            xml_escape(&host.address),
+            if host.address.contains(':') {
+                "ipv6"
+            } else {
+                "ipv4"
+            }
         )?;
 
         if let Some(ref hostname) = host.hostname {
@@ -233,20 +291,46 @@ fn export_nmap_xml<W: Write>(
                 "Closed" => "closed",
                 _ => "filtered",
             };
-            write!(writer, r#"<port protocol="tcp" portid="{}">"#, port.port)?;
+            write!(
+                writer,
+                r#"<port protocol="{}" portid="{}">"#,
+                xml_escape(&port.protocol),
+                port.port
+            )?;
             write!(writer, r#"<state state="{}" />"#, state)?;
 
             if let Some(ref service) = port.service {
                 write!(writer, r#"<service name="{}""#, xml_escape(service))?;
+                if let Some(ref product) = port.product {
+                    write!(writer, r#" product="{}""#, xml_escape(product))?;
+                }
                 if let Some(ref version) = port.version {
                     write!(writer, r#" version="{}""#, xml_escape(version))?;
                 }
                 write!(writer, " />")?;
             }
+            write_scripts(writer, &port.scripts)?;
 
             writeln!(writer, "</port>")?;
         }
         writeln!(writer, "</ports>")?;
+        if !host.os.is_empty() {
+            write!(writer, "<os>")?;
+            for os in &host.os {
+                write!(
+                    writer,
+                    r#"<osmatch name="{}" accuracy="{}" />"#,
+                    xml_escape(&os.name),
+                    os.accuracy
+                )?;
+            }
+            writeln!(writer, "</os>")?;
+        }
+        if !host.scripts.is_empty() {
+            write!(writer, "<hostscript>")?;
+            write_scripts(writer, &host.scripts)?;
+            writeln!(writer, "</hostscript>")?;
+        }
         writeln!(writer, "</host>")?;
     }
 
@@ -270,13 +354,23 @@ fn export_nmap_xml<W: Write>(
     Ok(())
 }
 
+fn write_scripts<W: Write>(writer: &mut W, scripts: &[ScriptOutput]) -> std::io::Result<()> {
+    for script in scripts {
+        write!(
+            writer,
+            r#"<script id="{}" output="{}" />"#,
+            xml_escape(&script.id),
+            xml_escape(&script.output)
+        )?;
+    }
+    Ok(())
+}
+
 /// Export results as Markdown
 fn export_markdown<W: Write>(
-    results: &[ScanResult],
+    report: &ScanReport,
     writer: &mut W,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let report = build_report(results);
-
     writeln!(writer, "# Scan Report")?;
     writeln!(writer)?;
     writeln!(writer, "## Summary")?;
@@ -302,6 +396,10 @@ fn export_markdown<W: Write>(
     for host in &report.hosts {
         writeln!(writer, "## Host: {}", host.address)?;
         writeln!(writer)?;
+        if let Some(os) = host.os.first() {
+            writeln!(writer, "OS: {} ({}%)", os.name, os.accuracy)?;
+            writeln!(writer)?;
+        }
 
         if host.ports.is_empty() {
             writeln!(writer, "No open ports found.")?;
@@ -316,7 +414,11 @@ fn export_markdown<W: Write>(
                     port.port,
                     port.status,
                     port.service.as_deref().unwrap_or("-"),
-                    port.version.as_deref().unwrap_or("-"),
+                    match (&port.product, &port.version) {
+                        (Some(product), Some(version)) => format!("{product} {version}"),
+                        (Some(v), None) | (None, Some(v)) => v.clone(),
+                        (None, None) => "-".to_string(),
+                    },
                     port.response_time_ms
                 )?;
             }
@@ -328,16 +430,19 @@ fn export_markdown<W: Write>(
 }
 
 /// Build a full report from scan results
-fn build_report(results: &[ScanResult]) -> ScanReport {
+pub fn build_report(results: &[ScanResult]) -> ScanReport {
     let now = chrono::Utc::now();
 
-    // Group by host
-    let mut hosts_map: HashMap<String, Vec<&ScanResult>> = HashMap::new();
+    // Group by host, in scan order
+    let mut hosts_map: Vec<(String, Vec<&ScanResult>)> = Vec::new();
     for result in results {
-        hosts_map
-            .entry(result.target.clone())
-            .or_default()
-            .push(result);
+        match hosts_map
+            .iter_mut()
+            .find(|(addr, _)| *addr == result.target)
+        {
+            Some((_, ports)) => ports.push(result),
+            None => hosts_map.push((result.target.clone(), vec![result])),
+        }
     }
 
     let hosts: Vec<HostReport> = hosts_map
@@ -353,6 +458,8 @@ fn build_report(results: &[ScanResult]) -> ScanReport {
                     "down".to_string()
                 },
                 ports: ports.iter().map(|p| ExportScanResult::from(*p)).collect(),
+                os: Vec::new(),
+                scripts: Vec::new(),
             }
         })
         .collect();
@@ -421,6 +528,8 @@ mod tests {
             status: PortStatus::Open,
             service: Some("ssh".to_string()),
             banner: Some("SSH-2.0-OpenSSH_8.9".to_string()),
+            version: None,
+            tls: None,
             response_time: Duration::from_millis(5),
         }
     }
@@ -429,7 +538,7 @@ mod tests {
     fn test_export_csv() {
         let results = vec![mock_result()];
         let mut output = Vec::new();
-        export_csv(&results, &mut output).unwrap();
+        export_csv(&build_report(&results), &mut output).unwrap();
 
         let csv = String::from_utf8(output).unwrap();
         assert!(csv.contains("192.168.1.1"));
@@ -442,7 +551,7 @@ mod tests {
     fn test_export_json() {
         let results = vec![mock_result()];
         let mut output = Vec::new();
-        export_json(&results, &mut output, true).unwrap();
+        export_json(&build_report(&results), &mut output, true).unwrap();
 
         let json = String::from_utf8(output).unwrap();
         assert!(json.contains("192.168.1.1"));
@@ -1,3 +1,4 @@
+use crate::networking::guard::{self, Backend};
 use crate::networking::safe_commands;
 use crate::security::validation::{
     ValidatedCidr, ValidatedInterface, ValidatedIpAddress, ValidatedPort, ValidatedPortRange,
@@ -136,330 +137,336 @@ fn ufw_management() {
         .interact_opt()
     {
         match choice {
-            0 => {
-                let enable = match Confirm::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Enable UFW?")
-                    .default(true)
-                    .interact_opt()
-                {
-                    Ok(Some(e)) => e,
-                    Ok(None) | Err(_) => continue,
-                };
+            0 | 1 | 2 | 4 | 5 | 6 => guard::interactive(Backend::Ufw, || ufw_action(choice)),
+            8 => break,
+            _ => ufw_action(choice),
+        }
+    }
+}
 
-                if enable {
-                    println!("🔧 Enabling UFW...");
-                    let status = Command::new("sudo").args(&["ufw", "enable"]).status();
+fn ufw_action(choice: usize) {
+    match choice {
+        0 => {
+            let enable = match Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enable UFW?")
+                .default(true)
+                .interact_opt()
+            {
+                Ok(Some(e)) => e,
+                Ok(None) | Err(_) => return,
+            };
 
-                    match status {
-                        Ok(s) if s.success() => println!("✅ UFW enabled"),
-                        _ => println!("❌ Failed to enable UFW"),
-                    }
-                } else {
-                    println!("🔧 Disabling UFW...");
-                    let status = Command::new("sudo").args(&["ufw", "disable"]).status();
+            if enable {
+                println!("🔧 Enabling UFW...");
+                let status = Command::new("sudo").args(&["ufw", "enable"]).status();
 
-                    match status {
-                        Ok(s) if s.success() => println!("✅ UFW disabled"),
-                        _ => println!("❌ Failed to disable UFW"),
-                    }
+                match status {
+                    Ok(s) if s.success() => println!("✅ UFW enabled"),
+                    _ => println!("❌ Failed to enable UFW"),
+                }
+            } else {
+                println!("🔧 Disabling UFW...");
+                let status = Command::new("sudo").args(&["ufw", "disable"]).status();
+
+                match status {
+                    Ok(s) if s.success() => println!("✅ UFW disabled"),
+                    _ => println!("❌ Failed to disable UFW"),
                 }
             }
-            1 => {
-                let rule_type = match Select::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Select rule type")
-                    .items(&[
-                        "Allow port",
-                        "Deny port",
-                        "Allow from IP",
-                        "Deny from IP",
-                        "Allow service",
-                    ])
-                    .default(0)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
+        }
+        1 => {
+            let rule_type = match Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select rule type")
+                .items(&[
+                    "Allow port",
+                    "Deny port",
+                    "Allow from IP",
+                    "Deny from IP",
+                    "Allow service",
+                ])
+                .default(0)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
-                match rule_type {
-                    0 | 1 => {
-                        let port_input: String = match Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt("Enter port number or range (e.g., 80, 8000:8080)")
-                            .interact_text()
-                        {
-                            Ok(i) => i,
-                            Err(_) => continue,
-                        };
+            match rule_type {
+                0 | 1 => {
+                    let port_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter port number or range (e.g., 80, 8000:8080)")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        // Validate port/port range
-                        let validated_port = match ValidatedPortRange::from_input(&port_input) {
-                            Ok(p) => p.to_string(),
-                            Err(e) => {
-                                println!("❌ Invalid port: {}", e);
-                                continue;
-                            }
-                        };
+                    // Validate port/port range
+                    let validated_port = match ValidatedPortRange::from_input(&port_input) {
+                        Ok(p) => p.to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid port: {}", e);
+                            return;
+                        }
+                    };
 
-                        let protocol = match Select::with_theme(&ColorfulTheme::default())
-                            .with_prompt("Select protocol")
-                            .items(&["tcp", "udp", "both"])
-                            .default(0)
-                            .interact_opt()
-                        {
-                            Ok(Some(c)) => c,
-                            Ok(None) | Err(_) => continue,
-                        };
+                    let protocol = match Select::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Select protocol")
+                        .items(&["tcp", "udp", "both"])
+                        .default(0)
+                        .interact_opt()
+                    {
+                        Ok(Some(c)) => c,
+                        Ok(None) | Err(_) => return,
+                    };
 
-                        let action = if rule_type == 0 { "allow" } else { "deny" };
+                    let action = if rule_type == 0 { "allow" } else { "deny" };
 
-                        // Build port arg with protocol suffix
-                        let port_arg = match protocol {
-                            0 => format!("{}/tcp", validated_port),
-                            1 => format!("{}/udp", validated_port),
-                            _ => validated_port.clone(),
-                        };
+                    // Build port arg with protocol suffix
+                    let port_arg = match protocol {
+                        0 => format!("{}/tcp", validated_port),
+                        1 => format!("{}/udp", validated_port),
+                        _ => validated_port.clone(),
+                    };
 
-                        println!("🔧 Executing: sudo ufw {} {}", action, port_arg);
+                    println!("🔧 Executing: sudo ufw {} {}", action, port_arg);
 
-                        let status = Command::new("sudo")
-                            .args(["ufw", action, &port_arg])
-                            .status();
+                    let status = Command::new("sudo")
+                        .args(["ufw", action, &port_arg])
+                        .status();
 
-                        match status {
-                            Ok(s) if s.success() => println!("✅ Rule added"),
-                            _ => println!("❌ Failed to add rule"),
-                        }
+                    match status {
+                        Ok(s) if s.success() => println!("✅ Rule added"),
+                        _ => println!("❌ Failed to add rule"),
                     }
-                    2 | 3 => {
-                        let ip_input: String = match Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt(
-                                "Enter IP address or subnet (e.g., 192.168.1.100, 192.168.1.0/24)",
-                            )
-                            .interact_text()
-                        {
-                            Ok(i) => i,
-                            Err(_) => continue,
-                        };
+                }
+                2 | 3 => {
+                    let ip_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt(
+                            "Enter IP address or subnet (e.g., 192.168.1.100, 192.168.1.0/24)",
+                        )
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        // Validate IP or CIDR
-                        let validated_ip = if ip_input.contains('/') {
-                            match ValidatedCidr::from_input(&ip_input) {
-                                Ok(c) => c.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid CIDR: {}", e);
-                                    continue;
-                                }
+                    // Validate IP or CIDR
+                    let validated_ip = if ip_input.contains('/') {
+                        match ValidatedCidr::from_input(&ip_input) {
+                            Ok(c) => c.value().to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid CIDR: {}", e);
+                                return;
                             }
-                        } else {
-                            match ValidatedIpAddress::from_input(&ip_input) {
-                                Ok(ip) => ip.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid IP address: {}", e);
-                                    continue;
-                                }
+                        }
+                    } else {
+                        match ValidatedIpAddress::from_input(&ip_input) {
+                            Ok(ip) => ip.value().to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid IP address: {}", e);
+                                return;
                             }
-                        };
+                        }
+                    };
 
-                        let action = if rule_type == 2 { "allow" } else { "deny" };
+                    let action = if rule_type == 2 { "allow" } else { "deny" };
 
-                        let port_input: String = match Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt("Enter port (optional, press Enter to skip)")
-                            .allow_empty(true)
-                            .interact_text()
-                        {
-                            Ok(i) => i,
-                            Err(_) => continue,
-                        };
+                    let port_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter port (optional, press Enter to skip)")
+                        .allow_empty(true)
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        let status = if port_input.is_empty() {
-                            println!("🔧 Executing: sudo ufw {} from {}", action, validated_ip);
-                            Command::new("sudo")
-                                .args(["ufw", action, "from", &validated_ip])
-                                .status()
-                        } else {
-                            // Validate port
-                            let validated_port = match ValidatedPort::from_input(&port_input) {
-                                Ok(p) => p.to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid port: {}", e);
-                                    continue;
-                                }
-                            };
-                            println!(
-                                "🔧 Executing: sudo ufw {} from {} to any port {}",
-                                action, validated_ip, validated_port
-                            );
-                            Command::new("sudo")
-                                .args([
-                                    "ufw",
-                                    action,
-                                    "from",
-                                    &validated_ip,
-                                    "to",
-                                    "any",
-                                    "port",
-                                    &validated_port,
-                                ])
-                                .status()
+                    let status = if port_input.is_empty() {
+                        println!("🔧 Executing: sudo ufw {} from {}", action, validated_ip);
+                        Command::new("sudo")
+                            .args(["ufw", action, "from", &validated_ip])
+                            .status()
+                    } else {
+                        // Validate port
+                        let validated_port = match ValidatedPort::from_input(&port_input) {
+                            Ok(p) => p.to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid port: {}", e);
+                                return;
+                            }
                         };
+                        println!(
+                            "🔧 Executing: sudo ufw {} from {} to any port {}",
+                            action, validated_ip, validated_port
+                        );
+                        Command::new("sudo")
+                            .args([
+                                "ufw",
+                                action,
+                                "from",
+                                &validated_ip,
+                                "to",
+                                "any",
+                                "port",
+                                &validated_port,
+                            ])
+                            .status()
+                    };
 
-                        match status {
-                            Ok(s) if s.success() => println!("✅ Rule added"),
-                            _ => println!("❌ Failed to add rule"),
-                        }
-                    }
-                    4 => {
-                        let service_input: String =
-                            match Input::with_theme(&ColorfulTheme::default())
-                                .with_prompt("Enter service name (e.g., ssh, http, https)")
-                                .interact_text()
-                            {
-                                Ok(i) => i,
-                                Err(_) => continue,
-                            };
-
-                        // Validate service name
-                        let validated_service =
-                            match ValidatedServiceName::from_input(&service_input) {
-                                Ok(s) => s.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid service name: {}", e);
-                                    continue;
-                                }
-                            };
-
-                        println!("🔧 Executing: sudo ufw allow {}", validated_service);
-
-                        let status = Command::new("sudo")
-                            .args(["ufw", "allow", &validated_service])
-                            .status();
-
-                        match status {
-                            Ok(s) if s.success() => println!("✅ Service allowed"),
-                            _ => println!("❌ Failed to allow service"),
-                        }
+                    match status {
+                        Ok(s) if s.success() => println!("✅ Rule added"),
+                        _ => println!("❌ Failed to add rule"),
                     }
-                    _ => {}
                 }
-            }
-            2 => {
-                println!("📋 Current UFW rules:");
-                Command::new("sudo")
-                    .args(&["ufw", "status", "numbered"])
-                    .status()
-                    .ok();
+                4 => {
+                    let service_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter service name (e.g., ssh, http, https)")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                let rule_num: String = match Input::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Enter rule number to delete (or 'cancel')")
-                    .interact_text()
-                {
-                    Ok(i) => i,
-                    Err(_) => continue,
-                };
+                    // Validate service name
+                    let validated_service = match ValidatedServiceName::from_input(&service_input) {
+                        Ok(s) => s.value().to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid service name: {}", e);
+                            return;
+                        }
+                    };
 
-                if rule_num != "cancel" {
-                    // Validate rule number (must be numeric)
-                    if !rule_num.chars().all(|c| c.is_ascii_digit()) {
-                        println!("❌ Invalid rule number: must be numeric");
-                        continue;
-                    }
+                    println!("🔧 Executing: sudo ufw allow {}", validated_service);
 
                     let status = Command::new("sudo")
-                        .args(["ufw", "delete", &rule_num])
+                        .args(["ufw", "allow", &validated_service])
                         .status();
 
                     match status {
-                        Ok(s) if s.success() => println!("✅ Rule deleted"),
-                        _ => println!("❌ Failed to delete rule"),
+                        Ok(s) if s.success() => println!("✅ Service allowed"),
+                        _ => println!("❌ Failed to allow service"),
                     }
                 }
+                _ => {}
             }
-            3 => {
-                println!("📋 UFW Rules:");
-                Command::new("sudo")
-                    .args(&["ufw", "status", "verbose"])
-                    .status()
-                    .ok();
-            }
-            4 => {
-                let confirm = match Confirm::with_theme(&ColorfulTheme::default())
-                    .with_prompt("⚠️ This will reset all UFW rules. Continue?")
-                    .default(false)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
+        }
+        2 => {
+            println!("📋 Current UFW rules:");
+            Command::new("sudo")
+                .args(&["ufw", "status", "numbered"])
+                .status()
+                .ok();
 
-                if confirm {
-                    Command::new("sudo")
-                        .args(&["ufw", "--force", "reset"])
-                        .status()
-                        .ok();
-                    println!("✅ UFW reset completed");
+            let rule_num: String = match Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter rule number to delete (or 'cancel')")
+                .interact_text()
+            {
+                Ok(i) => i,
+                Err(_) => return,
+            };
+
+            if rule_num != "cancel" {
+                // Validate rule number (must be numeric)
+                if !rule_num.chars().all(|c| c.is_ascii_digit()) {
+                    println!("❌ Invalid rule number: must be numeric");
+                    return;
                 }
-            }
-            5 => {
-                println!("📋 Available applications:");
-                Command::new("sudo")
-                    .args(&["ufw", "app", "list"])
-                    .status()
-                    .ok();
 
-                let app: String = match Input::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Enter application name to allow")
-                    .interact_text()
-                {
-                    Ok(i) => i,
-                    Err(_) => continue,
-                };
+                let status = Command::new("sudo")
+                    .args(["ufw", "delete", &rule_num])
+                    .status();
 
-                // Validate application name (alphanumeric with spaces allowed for app names)
-                if !app
-                    .chars()
-                    .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
-                {
-                    println!("❌ Invalid application name");
-                    continue;
+                match status {
+                    Ok(s) if s.success() => println!("✅ Rule deleted"),
+                    _ => println!("❌ Failed to delete rule"),
                 }
+            }
+        }
+        3 => {
+            println!("📋 UFW Rules:");
+            Command::new("sudo")
+                .args(&["ufw", "status", "verbose"])
+                .status()
+                .ok();
+        }
+        4 => {
+            let confirm = match Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("⚠️ This will reset all UFW rules. Continue?")
+                .default(false)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
+            if confirm {
                 Command::new("sudo")
-                    .args(["ufw", "allow", &app])
+                    .args(&["ufw", "--force", "reset"])
                     .status()
                     .ok();
+                println!("✅ UFW reset completed");
             }
-            6 => {
-                let app: String = match Input::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Enter application name to deny")
-                    .interact_text()
-                {
-                    Ok(i) => i,
-                    Err(_) => continue,
-                };
+        }
+        5 => {
+            println!("📋 Available applications:");
+            Command::new("sudo")
+                .args(&["ufw", "app", "list"])
+                .status()
+                .ok();
 
-                // Validate application name
-                if !app
-                    .chars()
-                    .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
-                {
-                    println!("❌ Invalid application name");
-                    continue;
-                }
+            let app: String = match Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter application name to allow")
+                .interact_text()
+            {
+                Ok(i) => i,
+                Err(_) => return,
+            };
 
-                Command::new("sudo")
-                    .args(["ufw", "deny", &app])
-                    .status()
-                    .ok();
+            // Validate application name (alphanumeric with spaces allowed for app names)
+            if !app
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+            {
+                println!("❌ Invalid application name");
+                return;
             }
-            7 => {
-                println!("📊 UFW Status:");
-                Command::new("sudo")
-                    .args(&["ufw", "status", "verbose"])
-                    .status()
-                    .ok();
+
+            Command::new("sudo")
+                .args(["ufw", "allow", &app])
+                .status()
+                .ok();
+        }
+        6 => {
+            let app: String = match Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter application name to deny")
+                .interact_text()
+            {
+                Ok(i) => i,
+                Err(_) => return,
+            };
+
+            // Validate application name
+            if !app
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+            {
+                println!("❌ Invalid application name");
+                return;
             }
-            _ => break,
+
+            Command::new("sudo")
+                .args(["ufw", "deny", &app])
+                .status()
+                .ok();
+        }
+        7 => {
+            println!("📊 UFW Status:");
+            Command::new("sudo")
+                .args(&["ufw", "status", "verbose"])
+                .status()
+                .ok();
         }
+        _ => {}
     }
 }
 
@@ -483,415 +490,418 @@ fn firewalld_management() {
         .interact_opt()
     {
         match choice {
-            0 => {
-                let action = match Select::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Select action")
-                    .items(&["Start", "Stop", "Restart", "Enable", "Disable"])
-                    .default(0)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
+            0 | 1 | 3 | 4 | 5 | 6 => {
+                guard::interactive(Backend::Firewalld, || firewalld_action(choice))
+            }
+            8 => break,
+            _ => firewalld_action(choice),
+        }
+    }
+}
 
-                let systemctl_action = match action {
-                    0 => "start",
-                    1 => "stop",
-                    2 => "restart",
-                    3 => "enable",
-                    4 => "disable",
-                    _ => "",
-                };
+fn firewalld_action(choice: usize) {
+    match choice {
+        0 => {
+            let action = match Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select action")
+                .items(&["Start", "Stop", "Restart", "Enable", "Disable"])
+                .default(0)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
-                if !systemctl_action.is_empty() {
-                    let status = Command::new("sudo")
-                        .args(["systemctl", systemctl_action, "firewalld"])
-                        .status();
+            let systemctl_action = match action {
+                0 => "start",
+                1 => "stop",
+                2 => "restart",
+                3 => "enable",
+                4 => "disable",
+                _ => "",
+            };
 
-                    match status {
-                        Ok(s) if s.success() => println!("✅ Action completed"),
-                        _ => println!("❌ Action failed"),
-                    }
+            if !systemctl_action.is_empty() {
+                let status = Command::new("sudo")
+                    .args(["systemctl", systemctl_action, "firewalld"])
+                    .status();
+
+                match status {
+                    Ok(s) if s.success() => println!("✅ Action completed"),
+                    _ => println!("❌ Action failed"),
                 }
             }
-            1 => {
-                println!("🔄 Reloading firewalld configuration...");
-                Command::new("sudo")
-                    .args(&["firewall-cmd", "--reload"])
-                    .status()
-                    .ok();
-                println!("✅ Configuration reloaded");
-            }
-            2 => {
-                println!("📋 Firewalld Zones:");
-                Command::new("sudo")
-                    .args(&["firewall-cmd", "--list-all-zones"])
-                    .status()
-                    .ok();
-            }
-            3 => {
-                let add_type = match Select::with_theme(&ColorfulTheme::default())
-                    .with_prompt("What to add?")
-                    .items(&["Port", "Service", "Source IP"])
-                    .default(0)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
+        }
+        1 => {
+            println!("🔄 Reloading firewalld configuration...");
+            Command::new("sudo")
+                .args(&["firewall-cmd", "--reload"])
+                .status()
+                .ok();
+            println!("✅ Configuration reloaded");
+        }
+        2 => {
+            println!("📋 Firewalld Zones:");
+            Command::new("sudo")
+                .args(&["firewall-cmd", "--list-all-zones"])
+                .status()
+                .ok();
+        }
+        3 => {
+            let add_type = match Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("What to add?")
+                .items(&["Port", "Service", "Source IP"])
+                .default(0)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
-                let permanent = match Confirm::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Make permanent?")
-                    .default(true)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
+            let permanent = match Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Make permanent?")
+                .default(true)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
-                let perm_flag = if permanent { "--permanent" } else { "" };
+            let perm_flag = if permanent { "--permanent" } else { "" };
 
-                match add_type {
-                    0 => {
-                        use crate::security::validation::ValidatedZone;
+            match add_type {
+                0 => {
+                    use crate::security::validation::ValidatedZone;
 
-                        let port_input: String = match Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt("Enter port/protocol (e.g., 8080/tcp, 53/udp)")
-                            .interact_text()
-                        {
-                            Ok(i) => i,
-                            Err(_) => continue,
-                        };
+                    let port_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter port/protocol (e.g., 8080/tcp, 53/udp)")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        // Parse and validate port/protocol format
-                        let parts: Vec<&str> = port_input.split('/').collect();
-                        if parts.len() != 2 {
-                            println!("❌ Invalid format. Use: port/protocol (e.g., 8080/tcp)");
-                            continue;
+                    // Parse and validate port/protocol format
+                    let parts: Vec<&str> = port_input.split('/').collect();
+                    if parts.len() != 2 {
+                        println!("❌ Invalid format. Use: port/protocol (e.g., 8080/tcp)");
+                        return;
+                    }
+
+                    let validated_port = match ValidatedPortRange::from_input(parts[0]) {
+                        Ok(p) => p.to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid port: {}", e);
+                            return;
                         }
+                    };
 
-                        let validated_port = match ValidatedPortRange::from_input(parts[0]) {
-                            Ok(p) => p.to_string(),
-                            Err(e) => {
-                                println!("❌ Invalid port: {}", e);
-                                continue;
-                            }
-                        };
+                    let validated_protocol = match ValidatedProtocol::from_input(parts[1]) {
+                        Ok(p) => p.as_str().to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid protocol: {}", e);
+                            return;
+                        }
+                    };
+
+                    let zone_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter zone (or press Enter for default)")
+                        .allow_empty(true)
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
+
+                    let port_arg = format!("--add-port={}/{}", validated_port, validated_protocol);
+
+                    let mut args: Vec<&str> = vec!["firewall-cmd"];
+                    if permanent {
+                        args.push("--permanent");
+                    }
 
-                        let validated_protocol = match ValidatedProtocol::from_input(parts[1]) {
-                            Ok(p) => p.as_str().to_string(),
+                    let zone_arg;
+                    if !zone_input.is_empty() {
+                        let validated_zone = match ValidatedZone::from_input(&zone_input) {
+                            Ok(z) => z,
                             Err(e) => {
-                                println!("❌ Invalid protocol: {}", e);
-                                continue;
+                                println!("❌ Invalid zone: {}", e);
+                                return;
                             }
                         };
+                        zone_arg = format!("--zone={}", validated_zone);
+                        args.push(&zone_arg);
+                    }
+                    args.push(&port_arg);
 
-                        let zone_input: String = match Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt("Enter zone (or press Enter for default)")
-                            .allow_empty(true)
-                            .interact_text()
-                        {
-                            Ok(i) => i,
-                            Err(_) => continue,
-                        };
-
-                        let port_arg =
-                            format!("--add-port={}/{}", validated_port, validated_protocol);
+                    println!("🔧 Executing: sudo {}", args.join(" "));
+                    Command::new("sudo").args(&args).status().ok();
+                }
+                1 => {
+                    let service_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter service name (e.g., http, https, ssh)")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        let mut args: Vec<&str> = vec!["firewall-cmd"];
-                        if permanent {
-                            args.push("--permanent");
+                    let validated_service = match ValidatedServiceName::from_input(&service_input) {
+                        Ok(s) => s.value().to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid service name: {}", e);
+                            return;
                         }
+                    };
 
-                        let zone_arg;
-                        if !zone_input.is_empty() {
-                            let validated_zone = match ValidatedZone::from_input(&zone_input) {
-                                Ok(z) => z,
-                                Err(e) => {
-                                    println!("❌ Invalid zone: {}", e);
-                                    continue;
-                                }
-                            };
-                            zone_arg = format!("--zone={}", validated_zone);
-                            args.push(&zone_arg);
-                        }
-                        args.push(&port_arg);
+                    let service_arg = format!("--add-service={}", validated_service);
 
-                        println!("🔧 Executing: sudo {}", args.join(" "));
-                        Command::new("sudo").args(&args).status().ok();
+                    let mut args: Vec<&str> = vec!["firewall-cmd"];
+                    if permanent {
+                        args.push("--permanent");
                     }
-                    1 => {
-                        let service_input: String =
-                            match Input::with_theme(&ColorfulTheme::default())
-                                .with_prompt("Enter service name (e.g., http, https, ssh)")
-                                .interact_text()
-                            {
-                                Ok(i) => i,
-                                Err(_) => continue,
-                            };
-
-                        let validated_service =
-                            match ValidatedServiceName::from_input(&service_input) {
-                                Ok(s) => s.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid service name: {}", e);
-                                    continue;
-                                }
-                            };
+                    args.push(&service_arg);
 
-                        let service_arg = format!("--add-service={}", validated_service);
-
-                        let mut args: Vec<&str> = vec!["firewall-cmd"];
-                        if permanent {
-                            args.push("--permanent");
-                        }
-                        args.push(&service_arg);
+                    Command::new("sudo").args(&args).status().ok();
+                }
+                2 => {
+                    let source_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter source IP or subnet")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        Command::new("sudo").args(&args).status().ok();
-                    }
-                    2 => {
-                        let source_input: String =
-                            match Input::with_theme(&ColorfulTheme::default())
-                                .with_prompt("Enter source IP or subnet")
-                                .interact_text()
-                            {
-                                Ok(i) => i,
-                                Err(_) => continue,
-                            };
-
-                        // Validate IP or CIDR
-                        let validated_source = if source_input.contains('/') {
-                            match ValidatedCidr::from_input(&source_input) {
-                                Ok(c) => c.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid CIDR: {}", e);
-                                    continue;
-                                }
+                    // Validate IP or CIDR
+                    let validated_source = if source_input.contains('/') {
+                        match ValidatedCidr::from_input(&source_input) {
+                            Ok(c) => c.value().to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid CIDR: {}", e);
+                                return;
                             }
-                        } else {
-                            match ValidatedIpAddress::from_input(&source_input) {
-                                Ok(ip) => ip.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid IP address: {}", e);
-                                    continue;
-                                }
+                        }
+                    } else {
+                        match ValidatedIpAddress::from_input(&source_input) {
+                            Ok(ip) => ip.value().to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid IP address: {}", e);
+                                return;
                             }
-                        };
-
-                        let source_arg = format!("--add-source={}", validated_source);
-
-                        let mut args: Vec<&str> = vec!["firewall-cmd"];
-                        if permanent {
-                            args.push("--permanent");
                         }
-                        args.push(&source_arg);
+                    };
 
-                        Command::new("sudo").args(&args).status().ok();
+                    let source_arg = format!("--add-source={}", validated_source);
+
+                    let mut args: Vec<&str> = vec!["firewall-cmd"];
+                    if permanent {
+                        args.push("--permanent");
                     }
-                    _ => {}
-                }
+                    args.push(&source_arg);
 
-                if permanent {
-                    println!("🔄 Reloading to apply permanent changes...");
-                    Command::new("sudo")
-                        .args(&["firewall-cmd", "--reload"])
-                        .status()
-                        .ok();
+                    Command::new("sudo").args(&args).status().ok();
                 }
+                _ => {}
             }
-            4 => {
-                let remove_type = match Select::with_theme(&ColorfulTheme::default())
-                    .with_prompt("What to remove?")
-                    .items(&["Port", "Service", "Source IP"])
-                    .default(0)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
-
-                let permanent = match Confirm::with_theme(&ColorfulTheme::default())
-                    .with_prompt("Remove permanently?")
-                    .default(true)
-                    .interact_opt()
-                {
-                    Ok(Some(c)) => c,
-                    Ok(None) | Err(_) => continue,
-                };
 
-                match remove_type {
-                    0 => {
-                        println!("📋 Current ports:");
-                        Command::new("sudo")
-                            .args(&["firewall-cmd", "--list-ports"])
-                            .status()
-                            .ok();
+            if permanent {
+                println!("🔄 Reloading to apply permanent changes...");
+                Command::new("sudo")
+                    .args(&["firewall-cmd", "--reload"])
+                    .status()
+                    .ok();
+            }
+        }
+        4 => {
+            let remove_type = match Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("What to remove?")
+                .items(&["Port", "Service", "Source IP"])
+                .default(0)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
-                        let port_input: String = match Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt("Enter port/protocol to remove")
-                            .interact_text()
-                        {
-                            Ok(i) => i,
-                            Err(_) => continue,
-                        };
+            let permanent = match Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Remove permanently?")
+                .default(true)
+                .interact_opt()
+            {
+                Ok(Some(c)) => c,
+                Ok(None) | Err(_) => return,
+            };
 
-                        // Parse and validate port/protocol format
-                        let parts: Vec<&str> = port_input.split('/').collect();
-                        if parts.len() != 2 {
-                            println!("❌ Invalid format. Use: port/protocol (e.g., 8080/tcp)");
-                            continue;
-                        }
+            match remove_type {
+                0 => {
+                    println!("📋 Current ports:");
+                    Command::new("sudo")
+                        .args(&["firewall-cmd", "--list-ports"])
+                        .status()
+                        .ok();
 
-                        let validated_port = match ValidatedPortRange::from_input(parts[0]) {
-                            Ok(p) => p.to_string(),
-                            Err(e) => {
-                                println!("❌ Invalid port: {}", e);
-                                continue;
-                            }
-                        };
+                    let port_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter port/protocol to remove")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        let validated_protocol = match ValidatedProtocol::from_input(parts[1]) {
-                            Ok(p) => p.as_str().to_string(),
-                            Err(e) => {
-                                println!("❌ Invalid protocol: {}", e);
-                                continue;
-                            }
-                        };
+                    // Parse and validate port/protocol format
+                    let parts: Vec<&str> = port_input.split('/').collect();
+                    if parts.len() != 2 {
+                        println!("❌ Invalid format. Use: port/protocol (e.g., 8080/tcp)");
+                        return;
+                    }
 
-                        let port_arg =
-                            format!("--remove-port={}/{}", validated_port, validated_protocol);
+                    let validated_port = match ValidatedPortRange::from_input(parts[0]) {
+                        Ok(p) => p.to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid port: {}", e);
+                            return;
+                        }
+                    };
 
-                        let mut args: Vec<&str> = vec!["firewall-cmd"];
-                        if permanent {
-                            args.push("--permanent");
+                    let validated_protocol = match ValidatedProtocol::from_input(parts[1]) {
+                        Ok(p) => p.as_str().to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid protocol: {}", e);
+                            return;
                         }
-                        args.push(&port_arg);
+                    };
+
+                    let port_arg =
+                        format!("--remove-port={}/{}", validated_port, validated_protocol);
 
-                        Command::new("sudo").args(&args).status().ok();
+                    let mut args: Vec<&str> = vec!["firewall-cmd"];
+                    if permanent {
+                        args.push("--permanent");
                     }
-                    1 => {
-                        println!("📋 Current services:");
-                        Command::new("sudo")
-                            .args(&["firewall-cmd", "--list-services"])
-                            .status()
-                            .ok();
+                    args.push(&port_arg);
 
-                        let service_input: String =
-                            match Input::with_theme(&ColorfulTheme::default())
-                                .with_prompt("Enter service to remove")
-                                .interact_text()
-                            {
-                                Ok(i) => i,
-                                Err(_) => continue,
-                            };
-
-                        let validated_service =
-                            match ValidatedServiceName::from_input(&service_input) {
-                                Ok(s) => s.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid service name: {}", e);
-                                    continue;
-                                }
-                            };
+                    Command::new("sudo").args(&args).status().ok();
+                }
+                1 => {
+                    println!("📋 Current services:");
+                    Command::new("sudo")
+                        .args(&["firewall-cmd", "--list-services"])
+                        .status()
+                        .ok();
 
-                        let service_arg = format!("--remove-service={}", validated_service);
+                    let service_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter service to remove")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        let mut args: Vec<&str> = vec!["firewall-cmd"];
-                        if permanent {
-                            args.push("--permanent");
+                    let validated_service = match ValidatedServiceName::from_input(&service_input) {
+                        Ok(s) => s.value().to_string(),
+                        Err(e) => {
+                            println!("❌ Invalid service name: {}", e);
+                            return;
                         }
-                        args.push(&service_arg);
+                    };
 
-                        Command::new("sudo").args(&args).status().ok();
+                    let service_arg = format!("--remove-service={}", validated_service);
+
+                    let mut args: Vec<&str> = vec!["firewall-cmd"];
+                    if permanent {
+                        args.push("--permanent");
                     }
-                    2 => {
-                        println!("📋 Current sources:");
-                        Command::new("sudo")
-                            .args(&["firewall-cmd", "--list-sources"])
-                            .status()
-                            .ok();
+                    args.push(&service_arg);
 
-                        let source_input: String =
-                            match Input::with_theme(&ColorfulTheme::default())
-                                .with_prompt("Enter source to remove")
-                                .interact_text()
-                            {
-                                Ok(i) => i,
-                                Err(_) => continue,
-                            };
-
-                        // Validate IP or CIDR
-                        let validated_source = if source_input.contains('/') {
-                            match ValidatedCidr::from_input(&source_input) {
-                                Ok(c) => c.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid CIDR: {}", e);
-                                    continue;
-                                }
-                            }
-                        } else {
-                            match ValidatedIpAddress::from_input(&source_input) {
-                                Ok(ip) => ip.value().to_string(),
-                                Err(e) => {
-                                    println!("❌ Invalid IP address: {}", e);
-                                    continue;
-                                }
-                            }
-                        };
+                    Command::new("sudo").args(&args).status().ok();
+                }
+                2 => {
+                    println!("📋 Current sources:");
+                    Command::new("sudo")
+                        .args(&["firewall-cmd", "--list-sources"])
+                        .status()
+                        .ok();
 
-                        let source_arg = format!("--remove-source={}", validated_source);
+                    let source_input: String = match Input::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Enter source to remove")
+                        .interact_text()
+                    {
+                        Ok(i) => i,
+                        Err(_) => return,
+                    };
 
-                        let mut args: Vec<&str> = vec!["firewall-cmd"];
-                        if permanent {
-                            args.push("--permanent");
+                    // Validate IP or CIDR
+                    let validated_source = if source_input.contains('/') {
+                        match ValidatedCidr::from_input(&source_input) {
+                            Ok(c) => c.value().to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid CIDR: {}", e);
+                                return;
+                            }
+                        }
+                    } else {
+                        match ValidatedIpAddress::from_input(&source_input) {
+                            Ok(ip) => ip.value().to_string(),
+                            Err(e) => {
+                                println!("❌ Invalid IP address: {}", e);
+                                return;
+                            }
                         }
-                        args.push(&source_arg);
+                    };
 
-                        Command::new("sudo").args(&args).status().ok();
+                    let source_arg = format!("--remove-source={}", validated_source);
+
+                    let mut args: Vec<&str> = vec!["firewall-cmd"];
+                    if permanent {
+                        args.push("--permanent");
                     }
-                    _ => {}
-                }
+                    args.push(&source_arg);
 
-                if permanent {
-                    Command::new("sudo")
-                        .args(&["firewall-cmd", "--reload"])
-                        .status()
-                        .ok();
+                    Command::new("sudo").args(&args).status().ok();
                 }
+                _ => {}
             }
-            5 => {
-                zone_management();
-            }
-            6 => {
-                rich_rules_management();
-            }
-            7 => {
-                println!("📊 Firewalld Status:");
-                Command::new("sudo")
-                    .args(&["firewall-cmd", "--state"])
-                    .status()
-                    .ok();
 
-                println!("\n🌐 Default Zone:");
+            if permanent {
                 Command::new("sudo")
-                    .args(&["firewall-cmd", "--get-default-zone"])
+                    .args(&["firewall-cmd", "--reload"])
                     .status()
                     .ok();
+            }
+        }
+        5 => {
+            zone_management();
+        }
+        6 => {
+            rich_rules_management();
+        }
+        7 => {
+            println!("📊 Firewalld Status:");
+            Command::new("sudo")
+                .args(&["firewall-cmd", "--state"])
+                .status()
+                .ok();
 
-                println!("\n📋 Active Zones:");
-                Command::new("sudo")
-                    .args(&["firewall-cmd", "--get-active-zones"])
-                    .status()
-                    .ok();
+            println!("\n🌐 Default Zone:");
+            Command::new("sudo")
+                .args(&["firewall-cmd", "--get-default-zone"])
+                .status()
+                .ok();
 
-                println!("\n🔧 Current Configuration:");
-                Command::new("sudo")
-                    .args(&["firewall-cmd", "--list-all"])
-                    .status()
-                    .ok();
-            }
-            _ => break,
+            println!("\n📋 Active Zones:");
+            Command::new("sudo")
+                .args(&["firewall-cmd", "--get-active-zones"])
+                .status()
+                .ok();
+
+            println!("\n🔧 Current Configuration:");
+            Command::new("sudo")
+                .args(&["firewall-cmd", "--list-all"])
+                .status()
+                .ok();
         }
+        _ => {}
     }
 }
 
@@ -0,0 +1,727 @@
+//! Confirm-or-revert for firewall changes.
+//!
+//! Before a change the current firewall is saved (`nft list ruleset`, or a
+//! copy of `/etc/ufw` / `/etc/firewalld`) next to a generated `restore.sh`.
+//! After the change a transient systemd timer is armed to run that script
+//! when the countdown ends, so the revert still happens if the SSH session
+//! carrying the change dies. The change only sticks once the operator runs
+//! `ghostctl firewall confirm` from a *new* connection - proof the new rules
+//! still let them in. Without systemd a detached `setsid sh -c 'sleep ..'`
+//! takes the timer's place; either way the script exits early once the
+//! `confirmed` marker exists.
+//!
+//! Root runs that script, so it and everything it reads live in
+//! [`STATE_DIR`], created 0700 root and only written through sudo. The
+//! pending change is recorded there too, so `confirm` finds it whichever
+//! user or sudo context it runs under.
+
+use crate::command::CommandRunner;
+use crate::tui;
+use crate::utils::shell_quote;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 90;
+
+/// Root-owned home of the snapshots, restore scripts and `pending.json`.
+pub const STATE_DIR: &str = "/var/lib/ghostctl/firewall";
+
+/// How long the change itself may take (menus included) before the timer
+/// armed ahead of it reverts.
+const CHANGE_WINDOW: Duration = Duration::from_secs(600);
+
+/// The systemd timer fires this much after the in-process deadline.
+const TIMER_SLACK: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Nftables,
+    Ufw,
+    Firewalld,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Nftables => "nftables",
+            Backend::Ufw => "ufw",
+            Backend::Firewalld => "firewalld",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuardOptions {
+    pub timeout: Duration,
+    /// Arm the revert and return instead of waiting for the confirmation.
+    pub detach: bool,
+}
+
+impl Default for GuardOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            detach: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Nothing changed, so nothing was armed.
+    Unchanged,
+    Confirmed,
+    Reverted,
+    /// Armed and detached; the revert fires unless confirmed in time.
+    Pending,
+}
+
+/// The saved pre-change firewall.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub backend: Backend,
+    pub id: String,
+    pub dir: PathBuf,
+    fingerprint: String,
+}
+
+/// An armed revert (`<base>/pending.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pending {
+    pub id: String,
+    pub backend: Backend,
+    pub dir: PathBuf,
+    pub armed_at: String,
+    pub deadline: String,
+    /// systemd unit name, or `None` when the `setsid` fallback was used.
+    pub unit: Option<String>,
+}
+
+impl Pending {
+    fn script(&self) -> PathBuf {
+        self.dir.join("restore.sh")
+    }
+}
+
+pub struct Guard<'a> {
+    runner: &'a dyn CommandRunner,
+    base: PathBuf,
+}
+
+impl<'a> Guard<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self::with_base(runner, PathBuf::from(STATE_DIR))
+    }
+
+    pub fn with_base(runner: &'a dyn CommandRunner, base: PathBuf) -> Self {
+        Self { runner, base }
+    }
+
+    fn pending_path(&self) -> PathBuf {
+        self.base.join("pending.json")
+    }
+
+    pub fn pending(&self) -> Option<Pending> {
+        self.read(&self.pending_path())
+            .and_then(|content| serde_json::from_str(&content).ok())
+    }
+
+    /// Create `dir` (and the base) as root with mode 0700. `install -d` also
+    /// resets the owner and mode of a directory that already exists.
+    fn private_dir(&self, dir: &Path) -> Result<()> {
+        let dir = dir.to_string_lossy();
+        let out = self.runner.run_sudo(
+            "install",
+            &["-d", "-m", "0700", "-o", "root", "-g", "root", &dir],
+        )?;
+        if !out.success {
+            bail!("failed to create {dir}: {}", out.stderr.trim());
+        }
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        self.runner.read_file(&path.to_string_lossy()).ok()
+    }
+
+    fn write(&self, path: &Path, content: &str) -> Result<()> {
+        self.runner
+            .write_file(&path.to_string_lossy(), content)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The state directory is not readable without root, so an unprivileged
+    /// miss is double-checked through sudo.
+    fn exists(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        self.runner.file_exists(&path)
+            || (!self.runner.is_root()
+                && self
+                    .runner
+                    .run_sudo("test", &["-e", &path])
+                    .map(|r| r.success)
+                    .unwrap_or(false))
+    }
+
+    fn remove(&self, path: &Path) {
+        let _ = self.runner.remove_path(&path.to_string_lossy());
+    }
+
+    /// What the backend currently enforces; compared to detect a no-op.
+    fn fingerprint(&self, backend: Backend) -> Result<String> {
+        let (cmd, args): (&str, &[&str]) = match backend {
+            Backend::Nftables => ("nft", &["list", "ruleset"]),
+            Backend::Ufw => ("ufw", &["status", "verbose"]),
+            Backend::Firewalld => ("firewall-cmd", &["--list-all-zones"]),
+        };
+        let out = self
+            .runner
+            .run_sudo(cmd, args)
+            .with_context(|| format!("failed to run {cmd}"))?;
+        match backend {
+            Backend::Firewalld => {
+                // firewall-cmd fails while firewalld is stopped; that is a state too.
+                let runtime = if out.success {
+                    out.stdout
+                } else {
+                    "not running".to_string()
+                };
+                // Permanent-only edits do not touch the runtime zones until a
+                // reload, but the snapshot still has to cover them.
+                let permanent = self
+                    .runner
+                    .run_sudo("firewall-cmd", &["--permanent", "--list-all-zones"])
+                    .map(|r| if r.success { r.stdout } else { String::new() })
+                    .unwrap_or_default();
+                Ok(format!("{runtime}\n--- permanent ---\n{permanent}"))
+            }
+            _ if !out.success => bail!("{cmd} {} failed: {}", args.join(" "), out.stderr.trim()),
+            _ => Ok(out.stdout),
+        }
+    }
+
+    pub fn snapshot(&self, backend: Backend) -> Result<Snapshot> {
+        if let Some(pending) = self.pending() {
+            bail!(
+                "a {} change from {} is still waiting for `ghostctl firewall confirm` \
+                 (or `ghostctl firewall revert`)",
+                pending.backend.name(),
+                pending.armed_at
+            );
+        }
+        let id = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let dir = self.base.join("rollback").join(&id);
+        self.private_dir(&self.base)?;
+        self.private_dir(&dir)?;
+        let fingerprint = self.fingerprint(backend)?;
+
+        let was_active = match backend {
+            Backend::Nftables => {
+                self.write(
+                    &dir.join("ruleset.nft"),
+                    &format!("flush ruleset\n{fingerprint}"),
+                )?;
+                true
+            }
+            Backend::Ufw => {
+                self.copy_config("/etc/ufw", &dir.join("ufw"))?;
+                fingerprint.contains("Status: active")
+            }
+            Backend::Firewalld => {
+                self.copy_config("/etc/firewalld", &dir.join("firewalld"))?;
+                fingerprint != "not running"
+            }
+        };
+        self.write(
+            &dir.join("restore.sh"),
+            &restore_script(backend, &dir, was_active),
+        )?;
+        Ok(Snapshot {
+            backend,
+            id,
+            dir,
+            fingerprint,
+        })
+    }
+
+    fn copy_config(&self, from: &str, to: &Path) -> Result<()> {
+        let to = to.to_string_lossy();
+        let out = self.runner.run_sudo("cp", &["-a", from, &to])?;
+        if !out.success {
+            bail!("failed to save {from}: {}", out.stderr.trim());
+        }
+        Ok(())
+    }
+
+    pub fn changed(&self, snapshot: &Snapshot) -> Result<bool> {
+        Ok(self.fingerprint(snapshot.backend)? != snapshot.fingerprint)
+    }
+
+    /// Arm the revert timer for a change about to be applied.
+    pub fn arm(&self, snapshot: &Snapshot, timeout: Duration) -> Result<Pending> {
+        self.arm_unit(
+            snapshot,
+            timeout,
+            format!("ghostctl-firewall-revert-{}", snapshot.id),
+        )
+    }
+
+    fn arm_unit(&self, snapshot: &Snapshot, timeout: Duration, unit: String) -> Result<Pending> {
+        let secs = timeout.as_secs().max(10);
+        let script = snapshot.dir.join("restore.sh");
+        let script = script.to_string_lossy();
+        let on_active = format!("--on-active={secs}s");
+        let description = format!("Revert unconfirmed {} change", snapshot.backend.name());
+
+        let systemd = self.runner.command_exists("systemd-run")
+            && self
+                .runner
+                .run_sudo(
+                    "systemd-run",
+                    &[
+                        "--unit",
+                        &unit,
+                        "--description",
+                        &description,
+                        &on_active,
+                        "--timer-property=AccuracySec=1s",
+                        "/bin/sh",
+                        &script,
+                    ],
+                )
+                .map(|r| r.success)
+                .unwrap_or(false);
+        if !systemd {
+            let job = format!("sleep {secs}; sh {}", shell_quote(&script));
+            let out = self.runner.run_sudo_shell(&format!(
+                "setsid sh -c {} >/dev/null 2>&1 </dev/null &",
+                shell_quote(&job)
+            ))?;
+            if !out.success {
+                bail!("could not arm the revert timer: {}", out.stderr.trim());
+            }
+        }
+
+        let now = chrono::Local::now();
+        let pending = Pending {
+            id: snapshot.id.clone(),
+            backend: snapshot.backend,
+            dir: snapshot.dir.clone(),
+            armed_at: now.to_rfc3339(),
+            deadline: (now + chrono::Duration::seconds(secs as i64)).to_rfc3339(),
+            unit: systemd.then_some(unit),
+        };
+        self.write(
+            &self.pending_path(),
+            &serde_json::to_string_pretty(&pending)?,
+        )?;
+        Ok(pending)
+    }
+
+    /// Block until the change is confirmed from another connection, or revert
+    /// it when `timeout` runs out. The timer is armed [`TIMER_SLACK`] later
+    /// than this deadline, so the in-process revert disarms it instead of
+    /// racing it.
+    pub fn wait(&self, pending: &Pending, timeout: Duration, poll: Duration) -> Result<Outcome> {
+        let start = Instant::now();
+        let mut last_notice = None;
+        loop {
+            if self.exists(&pending.dir.join("confirmed")) {
+                return Ok(Outcome::Confirmed);
+            }
+            if self.exists(&pending.dir.join("reverted")) {
+                self.remove(&self.pending_path());
+                return Ok(Outcome::Reverted);
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                self.revert_pending(pending)?;
+                return Ok(Outcome::Reverted);
+            }
+            let left = (timeout - elapsed).as_secs();
+            let bucket = left / 15;
+            if last_notice != Some(bucket) {
+                last_notice = Some(bucket);
+                println!(
+                    "  Reverting in {left}s unless confirmed - run `ghostctl firewall confirm` from a NEW connection"
+                );
+            }
+            std::thread::sleep(poll);
+        }
+    }
+
+    /// Snapshot, arm the revert, run `change`, and guard the result if it
+    /// changed anything.
+    pub fn protect<F>(&self, backend: Backend, options: &GuardOptions, change: F) -> Result<Outcome>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let snapshot = self.snapshot(backend)?;
+        let armed = self.arm(&snapshot, CHANGE_WINDOW)?;
+        self.settle(snapshot, armed, options, change())
+    }
+
+    /// Finish a change made while `armed` was counting down: drop everything
+    /// when nothing changed, otherwise restart the countdown for the
+    /// confirmation and wait for it.
+    fn settle(
+        &self,
+        snapshot: Snapshot,
+        armed: Pending,
+        options: &GuardOptions,
+        applied: Result<()>,
+    ) -> Result<Outcome> {
+        if self.exists(&snapshot.dir.join("reverted")) {
+            self.remove(&self.pending_path());
+            applied?;
+            bail!(
+                "the change took longer than {}s and the guard reverted it",
+                CHANGE_WINDOW.as_secs()
+            );
+        }
+        if !self.changed(&snapshot)? {
+            self.disarm(&armed);
+            self.remove(&self.pending_path());
+            self.remove(&snapshot.dir);
+            applied?;
+            return Ok(Outcome::Unchanged);
+        }
+        if let Err(e) = applied {
+            // A half-applied change is reverted straight away.
+            self.revert_pending(&armed)?;
+            return Err(e.context("change failed part-way and was reverted"));
+        }
+        // The confirmation countdown starts now that the change is in place.
+        self.disarm(&armed);
+        let pending = self.arm_unit(
+            &snapshot,
+            options.timeout + TIMER_SLACK,
+            format!("ghostctl-firewall-revert-{}-confirm", snapshot.id),
+        )?;
+        if options.detach {
+            return Ok(Outcome::Pending);
+        }
+        self.wait(&pending, options.timeout, Duration::from_secs(1))
+    }
+
+    /// Keep the applied change: mark it confirmed and disarm the timer.
+    pub fn confirm(&self) -> Result<Pending> {
+        let Some(pending) = self.pending() else {
+            bail!("no firewall change is waiting for confirmation");
+        };
+        self.write(
+            &pending.dir.join("confirmed"),
+            &chrono::Local::now().to_rfc3339(),
+        )?;
+        self.disarm(&pending);
+        self.runner
+            .remove_path(&self.pending_path().to_string_lossy())
+            .context("failed to remove the pending change")?;
+        Ok(pending)
+    }
+
+    /// Restore the saved firewall now.
+    pub fn revert(&self) -> Result<Pending> {
+        let Some(pending) = self.pending() else {
+            bail!("no firewall change is waiting for confirmation");
+        };
+        self.revert_pending(&pending)?;
+        Ok(pending)
+    }
+
+    fn revert_pending(&self, pending: &Pending) -> Result<()> {
+        let script = pending.script().to_string_lossy().to_string();
+        self.disarm(pending);
+        let out = self.runner.run_sudo("sh", &[&script])?;
+        if !out.success {
+            bail!(
+                "restore script {script} failed (the timer was disarmed, run it by hand): {}",
+                out.stderr.trim()
+            );
+        }
+        self.remove(&self.pending_path());
+        Ok(())
+    }
+
+    fn disarm(&self, pending: &Pending) {
+        if let Some(unit) = &pending.unit {
+            let timer = format!("{unit}.timer");
+            let _ = self.runner.run_sudo("systemctl", &["stop", &timer]);
+        }
+    }
+}
+
+/// The script systemd (as root) runs when the countdown ends.
+pub fn restore_script(backend: Backend, dir: &Path, was_active: bool) -> String {
+    let q = |name: &str| shell_quote(&dir.join(name).to_string_lossy());
+    let mut script = format!(
+        "#!/bin/sh\n# ghostctl: restore the {} configuration saved before an unconfirmed change\n\
+         [ -d {} ] || exit 0\n[ -e {} ] && exit 0\n[ -e {} ] && exit 0\nset -e\n",
+        backend.name(),
+        shell_quote(&dir.to_string_lossy()),
+        q("confirmed"),
+        q("reverted")
+    );
+    match backend {
+        Backend::Nftables => {
+            script.push_str(&format!("nft -f {}\n", q("ruleset.nft")));
+        }
+        Backend::Ufw => {
+            script.push_str(&format!("rm -rf /etc/ufw\ncp -a {} /etc/ufw\n", q("ufw")));
+            if was_active {
+                script.push_str(
+                    "if ufw status | grep -q 'Status: active'; then ufw reload; else ufw --force enable; fi\n",
+                );
+            } else {
+                script.push_str("ufw --force disable\n");
+            }
+        }
+        Backend::Firewalld => {
+            script.push_str(&format!(
+                "rm -rf /etc/firewalld\ncp -a {} /etc/firewalld\n",
+                q("firewalld")
+            ));
+            if was_active {
+                script.push_str("systemctl start firewalld\nfirewall-cmd --reload\n");
+            } else {
+                script.push_str("systemctl stop firewalld\n");
+            }
+        }
+    }
+    script.push_str(&format!("touch {}\n", q("reverted")));
+    script
+}
+
+/// Menu entry point: guard `change` with the default options and report the
+/// outcome. Falls back to an explicit prompt when no snapshot can be taken.
+pub fn interactive<F: FnOnce()>(backend: Backend, change: F) {
+    let runner = crate::command::runner();
+    let guard = Guard::new(runner.as_ref());
+    let snapshot = match guard.snapshot(backend) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            tui::warn(&format!("Rollback protection unavailable: {e:#}"));
+            if tui::confirm("Continue without automatic rollback?", false) {
+                change();
+            }
+            return;
+        }
+    };
+    let armed = match guard.arm(&snapshot, CHANGE_WINDOW) {
+        Ok(armed) => armed,
+        Err(e) => {
+            guard.remove(&snapshot.dir);
+            tui::error(&format!("Rollback guard failed: {e:#}"));
+            return;
+        }
+    };
+    change();
+    match guard.settle(snapshot, armed, &GuardOptions::default(), Ok(())) {
+        Ok(outcome) => report(backend, outcome),
+        Err(e) => tui::error(&format!("Rollback guard failed: {e:#}")),
+    }
+}
+
+pub fn report(backend: Backend, outcome: Outcome) {
+    match outcome {
+        Outcome::Unchanged => {}
+        Outcome::Confirmed => tui::success(&format!("{} change confirmed", backend.name())),
+        Outcome::Reverted => tui::warn(&format!(
+            "{} change was not confirmed and has been reverted",
+            backend.name()
+        )),
+        Outcome::Pending => tui::info(&format!(
+            "{} change armed: confirm with `ghostctl firewall confirm` from a new connection",
+            backend.name()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    const BASE: &str = "/state/firewall";
+
+    fn guard_in(mock: &MockRunner) -> Guard<'_> {
+        Guard::with_base(mock, PathBuf::from(BASE))
+    }
+
+    #[test]
+    fn restore_scripts_exit_once_confirmed() {
+        let dir = Path::new("/state/firewall/rollback/1");
+        for backend in [Backend::Nftables, Backend::Ufw, Backend::Firewalld] {
+            let script = restore_script(backend, dir, true);
+            assert!(
+                script.contains("[ -e /state/firewall/rollback/1/confirmed ] && exit 0"),
+                "{script}"
+            );
+            assert!(script.ends_with("touch /state/firewall/rollback/1/reverted\n"));
+        }
+        assert!(
+            restore_script(Backend::Nftables, dir, true)
+                .contains("nft -f /state/firewall/rollback/1/ruleset.nft")
+        );
+        assert!(restore_script(Backend::Ufw, dir, false).contains("ufw --force disable"));
+        assert!(restore_script(Backend::Firewalld, dir, true).contains("firewall-cmd --reload"));
+        let script = restore_script(Backend::Nftables, dir, true);
+        assert!(script.contains("[ -d /state/firewall/rollback/1 ] || exit 0"));
+        assert!(script.contains("[ -e /state/firewall/rollback/1/reverted ] && exit 0"));
+    }
+
+    #[test]
+    fn snapshot_saves_a_flushing_ruleset() {
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "nft",
+            &["list", "ruleset"],
+            CommandResult::ok("table inet ghostctl {\n}\n"),
+        );
+        let guard = guard_in(&mock);
+        let snapshot = guard.snapshot(Backend::Nftables).unwrap();
+        let saved = mock
+            .read_file(&snapshot.dir.join("ruleset.nft").to_string_lossy())
+            .unwrap();
+        assert_eq!(saved, "flush ruleset\ntable inet ghostctl {\n}\n");
+        assert!(mock.file_exists(&snapshot.dir.join("restore.sh").to_string_lossy()));
+        assert!(!guard.changed(&snapshot).unwrap());
+        assert!(mock.was_called(&format!("install -d -m 0700 -o root -g root {BASE}")));
+        assert!(mock.was_called(&format!(
+            "install -d -m 0700 -o root -g root {}",
+            snapshot.dir.display()
+        )));
+    }
+
+    #[test]
+    fn unchanged_ruleset_is_disarmed() {
+        let mock = MockRunner::as_root();
+        mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("same"));
+        mock.mock_command("systemd-run", &[], CommandResult::ok(""));
+        let guard = guard_in(&mock);
+        let outcome = guard
+            .protect(Backend::Nftables, &GuardOptions::default(), || Ok(()))
+            .unwrap();
+        assert_eq!(outcome, Outcome::Unchanged);
+        assert!(
+            mock.was_called("--on-active=600s"),
+            "armed before the change"
+        );
+        assert!(mock.was_called("systemctl stop ghostctl-firewall-revert-"));
+        assert!(guard.pending().is_none());
+        let armed = mock
+            .get_history()
+            .into_iter()
+            .find_map(|c| {
+                c.strip_prefix("systemd-run --unit ghostctl-firewall-revert-")
+                    .map(|rest| rest.split(' ').next().unwrap().to_string())
+            })
+            .unwrap();
+        let script = format!("{BASE}/rollback/{armed}/restore.sh");
+        assert!(!mock.file_exists(&script), "snapshot dropped");
+    }
+
+    #[test]
+    fn detached_change_restarts_the_countdown() {
+        let mock = MockRunner::as_root();
+        mock.mock_command("systemd-run", &[], CommandResult::ok(""));
+        mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("before"));
+        let guard = guard_in(&mock);
+        let options = GuardOptions {
+            timeout: Duration::from_secs(60),
+            detach: true,
+        };
+        let outcome = guard
+            .protect(Backend::Nftables, &options, || {
+                assert!(mock.was_called("--on-active=600s"), "armed before applying");
+                mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("after"));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(outcome, Outcome::Pending);
+        let pending = guard.pending().unwrap();
+        let unit = pending.unit.unwrap();
+        assert!(unit.ends_with("-confirm"));
+        assert!(mock.was_called("--on-active=65s"));
+        assert!(mock.was_called(&format!(
+            "systemctl stop {}.timer",
+            unit.trim_end_matches("-confirm")
+        )));
+    }
+
+    #[test]
+    fn arm_prefers_systemd_and_confirm_disarms() {
+        let mock = MockRunner::as_root();
+        mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("before"));
+        mock.mock_command("systemd-run", &[], CommandResult::ok(""));
+        let guard = guard_in(&mock);
+        let snapshot = guard.snapshot(Backend::Nftables).unwrap();
+        let pending = guard.arm(&snapshot, Duration::from_secs(60)).unwrap();
+
+        let unit = pending.unit.clone().unwrap();
+        assert!(mock.was_called(&format!("systemd-run --unit {unit}")));
+        assert!(mock.was_called("--on-active=60s"));
+        assert!(
+            guard.snapshot(Backend::Nftables).is_err(),
+            "one change at a time"
+        );
+
+        guard.confirm().unwrap();
+        assert!(mock.file_exists(&pending.dir.join("confirmed").to_string_lossy()));
+        assert!(mock.was_called(&format!("systemctl stop {unit}.timer")));
+        assert!(guard.pending().is_none());
+    }
+
+    #[test]
+    fn arm_falls_back_to_a_detached_sleep() {
+        let mock = MockRunner::as_root();
+        mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("before"));
+        let guard = guard_in(&mock);
+        let snapshot = guard.snapshot(Backend::Nftables).unwrap();
+        let pending = guard.arm(&snapshot, Duration::from_secs(30)).unwrap();
+        assert!(pending.unit.is_none());
+        assert!(mock.was_called("bash -c setsid sh -c 'sleep 30; sh "));
+    }
+
+    #[test]
+    fn wait_reverts_when_nobody_confirms() {
+        let mock = MockRunner::as_root();
+        mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("before"));
+        mock.mock_command("systemd-run", &[], CommandResult::ok(""));
+        let guard = guard_in(&mock);
+        let snapshot = guard.snapshot(Backend::Nftables).unwrap();
+        let pending = guard.arm(&snapshot, Duration::from_secs(60)).unwrap();
+
+        let outcome = guard
+            .wait(
+                &pending,
+                Duration::from_millis(30),
+                Duration::from_millis(5),
+            )
+            .unwrap();
+        assert_eq!(outcome, Outcome::Reverted);
+        let script = format!("sh {}", pending.dir.join("restore.sh").display());
+        assert!(mock.get_history().contains(&script));
+        assert!(guard.pending().is_none());
+    }
+
+    #[test]
+    fn wait_returns_once_confirmed_elsewhere() {
+        let mock = MockRunner::as_root();
+        mock.mock_command("nft", &["list", "ruleset"], CommandResult::ok("before"));
+        let guard = guard_in(&mock);
+        let snapshot = guard.snapshot(Backend::Nftables).unwrap();
+        let pending = guard.arm(&snapshot, Duration::from_secs(60)).unwrap();
+        mock.write_file(&pending.dir.join("confirmed").to_string_lossy(), "")
+            .unwrap();
+
+        let outcome = guard
+            .wait(&pending, Duration::from_secs(5), Duration::from_millis(5))
+            .unwrap();
+        assert_eq!(outcome, Outcome::Confirmed);
+        assert!(!mock.get_history().iter().any(|c| c.starts_with("sh ")));
+    }
+}
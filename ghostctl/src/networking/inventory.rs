@@ -0,0 +1,843 @@
+//! Scan inventory: `ghostctl scan list|diff|history`.
+//!
+//! Every saved scan is appended to a JSON-lines database as one
+//! [`ScanRecord`]: the open ports found on each host, keyed by host and
+//! `port/proto`, with the service, banner and version seen, plus the OS guess
+//! when one was made. A record also remembers which targets and ports it
+//! covered, so a port missing from a later scan is only reported as closed
+//! when that scan actually probed it - a weekly `1-1000` sweep never "closes"
+//! a port found by a one-off full scan.
+//!
+//! [`diff`] compares two records: hosts that came up or went away, ports that
+//! opened or closed, version or banner changes on the same port, and OS
+//! fingerprint drift. `scan --alert` raises the changes in Alertmanager via
+//! [`crate::monitor::push_alerts`].
+
+use super::scanner::{PortStatus, ScanResult};
+use super::services::extract_version;
+use crate::monitor::parse::RaisedAlert;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Alertmanager alert name for inventory changes.
+pub const ALERT_NAME: &str = "GhostctlScanChange";
+
+/// How long a raised change alert keeps firing: until the next weekly scan.
+pub const ALERT_HOURS: i64 = 7 * 24;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanRecord {
+    pub id: u64,
+    pub at: String,
+    /// `ghostctl` for the built-in scanner.
+    pub source: String,
+    pub targets: Vec<String>,
+    /// Ports probed per protocol, in scanner syntax (`1-1000`, `22,80,443`).
+    pub ports: BTreeMap<String, String>,
+    pub hosts: BTreeMap<String, HostRecord>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// Open ports keyed `port/proto`, e.g. `22/tcp`.
+    #[serde(default)]
+    pub ports: BTreeMap<String, PortRecord>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PortRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl ScanRecord {
+    /// A record of TCP scanner results. Hosts that answered on any port
+    /// (open or refused) are kept, even without open ports.
+    pub fn from_results(
+        results: &[ScanResult],
+        targets: &[String],
+        ports: &str,
+        at: DateTime<Utc>,
+    ) -> Self {
+        let mut hosts: BTreeMap<String, HostRecord> = BTreeMap::new();
+        for result in results {
+            match result.status {
+                PortStatus::Open => {
+                    let banner = result.banner.clone().filter(|b| !b.trim().is_empty());
+                    hosts
+                        .entry(result.target.clone())
+                        .or_default()
+                        .ports
+                        .insert(
+                            format!("{}/tcp", result.port),
+                            PortRecord {
+                                service: result.service.clone(),
+                                version: result.version.clone().or_else(|| {
+                                    banner
+                                        .as_deref()
+                                        .and_then(|b| extract_version(result.port, b))
+                                }),
+                                banner,
+                            },
+                        );
+                }
+                PortStatus::Closed => {
+                    hosts.entry(result.target.clone()).or_default();
+                }
+                _ => {}
+            }
+        }
+        ScanRecord {
+            id: 0,
+            at: at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            source: "ghostctl".to_string(),
+            targets: targets.to_vec(),
+            ports: BTreeMap::from([("tcp".to_string(), ports.to_string())]),
+            hosts,
+        }
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.at)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    /// Whether this scan looked at `host` at all.
+    pub fn covers_host(&self, host: &str) -> bool {
+        if self.hosts.contains_key(host) {
+            return true;
+        }
+        let ip = host.parse::<IpAddr>().ok();
+        self.targets.iter().any(|target| {
+            target == host
+                || target
+                    .parse::<IpNet>()
+                    .ok()
+                    .zip(ip)
+                    .is_some_and(|(net, ip)| net.contains(&ip))
+        })
+    }
+
+    /// Whether this scan probed `key` (`port/proto`).
+    pub fn covers_port(&self, key: &str) -> bool {
+        let Some((port, proto)) = key.split_once('/') else {
+            return false;
+        };
+        let (Ok(port), Some(spec)) = (port.parse::<u16>(), self.ports.get(proto)) else {
+            return false;
+        };
+        spec.split(',')
+            .any(|part| match part.trim().split_once('-') {
+                Some((lo, hi)) => match (lo.parse::<u16>(), hi.parse::<u16>()) {
+                    (Ok(lo), Ok(hi)) => (lo..=hi).contains(&port),
+                    _ => false,
+                },
+                None => part.trim().parse() == Ok(port),
+            })
+    }
+
+    fn targets_overlap(&self, other: &ScanRecord) -> bool {
+        self.targets.iter().any(|t| other.targets.contains(t))
+            || self.hosts.keys().any(|h| other.hosts.contains_key(h))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    HostUp {
+        host: String,
+        ports: Vec<String>,
+    },
+    HostDown {
+        host: String,
+    },
+    PortOpened {
+        host: String,
+        port: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        service: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+    },
+    PortClosed {
+        host: String,
+        port: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        service: Option<String>,
+    },
+    VersionChanged {
+        host: String,
+        port: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    BannerChanged {
+        host: String,
+        port: String,
+        from: String,
+        to: String,
+    },
+    OsChanged {
+        host: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl Change {
+    pub fn host(&self) -> &str {
+        match self {
+            Change::HostUp { host, .. }
+            | Change::HostDown { host }
+            | Change::PortOpened { host, .. }
+            | Change::PortClosed { host, .. }
+            | Change::VersionChanged { host, .. }
+            | Change::BannerChanged { host, .. }
+            | Change::OsChanged { host, .. } => host,
+        }
+    }
+
+    /// Newly exposed services warrant a look; the rest is informational.
+    pub fn severity(&self) -> &'static str {
+        match self {
+            Change::HostUp { .. } | Change::PortOpened { .. } => "warning",
+            _ => "info",
+        }
+    }
+
+    /// One line, without the host.
+    pub fn describe(&self) -> String {
+        let named = |port: &str, service: &Option<String>| match service {
+            Some(service) => format!("{port} {service}"),
+            None => port.to_string(),
+        };
+        let or_none = |v: &Option<String>| v.clone().unwrap_or_else(|| "unknown".to_string());
+        match self {
+            Change::HostUp { ports, .. } if ports.is_empty() => "host up".to_string(),
+            Change::HostUp { ports, .. } => format!("host up, open: {}", ports.join(", ")),
+            Change::HostDown { .. } => "host down".to_string(),
+            Change::PortOpened {
+                port,
+                service,
+                version,
+                ..
+            } => match version {
+                Some(version) => format!("+ {} ({version})", named(port, service)),
+                None => format!("+ {}", named(port, service)),
+            },
+            Change::PortClosed { port, service, .. } => format!("- {}", named(port, service)),
+            Change::VersionChanged { port, from, to, .. } => {
+                format!("~ {port} version {} -> {}", or_none(from), or_none(to))
+            }
+            Change::BannerChanged { port, from, to, .. } => {
+                format!("~ {port} banner \"{from}\" -> \"{to}\"")
+            }
+            Change::OsChanged { from, to, .. } => format!("~ os {from} -> {to}"),
+        }
+    }
+}
+
+/// What changed from `old` to `new`, by host. Only what `new` probed can be
+/// reported as gone, and only what `old` probed as newly opened.
+pub fn diff(old: &ScanRecord, new: &ScanRecord) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (host, now) in &new.hosts {
+        let Some(before) = old.hosts.get(host) else {
+            if old.covers_host(host) {
+                changes.push(Change::HostUp {
+                    host: host.clone(),
+                    ports: now.ports.keys().cloned().collect(),
+                });
+            }
+            continue;
+        };
+        host_changes(host, before, now, old, new, &mut changes);
+    }
+    for host in old.hosts.keys() {
+        if !new.hosts.contains_key(host) && new.covers_host(host) {
+            changes.push(Change::HostDown { host: host.clone() });
+        }
+    }
+    changes
+}
+
+fn host_changes(
+    host: &str,
+    before: &HostRecord,
+    now: &HostRecord,
+    old: &ScanRecord,
+    new: &ScanRecord,
+    changes: &mut Vec<Change>,
+) {
+    for (port, seen) in &now.ports {
+        match before.ports.get(port) {
+            None if old.covers_port(port) => changes.push(Change::PortOpened {
+                host: host.to_string(),
+                port: port.clone(),
+                service: seen.service.clone(),
+                version: seen.version.clone(),
+            }),
+            None => {}
+            Some(was) if was.version != seen.version && seen.version.is_some() => {
+                changes.push(Change::VersionChanged {
+                    host: host.to_string(),
+                    port: port.clone(),
+                    from: was.version.clone(),
+                    to: seen.version.clone(),
+                })
+            }
+            Some(was) => {
+                if let (Some(from), Some(to)) = (&was.banner, &seen.banner)
+                    && from != to
+                {
+                    changes.push(Change::BannerChanged {
+                        host: host.to_string(),
+                        port: port.clone(),
+                        from: from.clone(),
+                        to: to.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for (port, was) in &before.ports {
+        if !now.ports.contains_key(port) && new.covers_port(port) {
+            changes.push(Change::PortClosed {
+                host: host.to_string(),
+                port: port.clone(),
+                service: was.service.clone(),
+            });
+        }
+    }
+    if let (Some(from), Some(to)) = (&before.os, &now.os)
+        && from != to
+    {
+        changes.push(Change::OsChanged {
+            host: host.to_string(),
+            from: from.clone(),
+            to: to.clone(),
+        });
+    }
+}
+
+/// The scan `record` should be compared with: the latest earlier one that
+/// looked at any of the same targets, else simply the one before it.
+pub fn previous<'a>(records: &'a [ScanRecord], record: &ScanRecord) -> Option<&'a ScanRecord> {
+    let earlier: Vec<&ScanRecord> = records.iter().take_while(|r| r.id != record.id).collect();
+    earlier
+        .iter()
+        .rev()
+        .find(|r| r.targets_overlap(record))
+        .or_else(|| earlier.last())
+        .copied()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub at: String,
+    /// Whether the host answered in this scan.
+    pub up: bool,
+    /// Open ports when the host was first seen.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub first_seen: Vec<String>,
+    pub changes: Vec<Change>,
+}
+
+/// Every scan that covered `host`, with what changed for it each time.
+pub fn history(records: &[ScanRecord], host: &str) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    let mut last: Option<&ScanRecord> = None;
+    for record in records.iter().filter(|r| r.covers_host(host)) {
+        let up = record.hosts.contains_key(host);
+        let (first_seen, changes) = match last {
+            Some(prev) if prev.hosts.contains_key(host) || !up => (
+                Vec::new(),
+                diff(prev, record)
+                    .into_iter()
+                    .filter(|c| c.host() == host)
+                    .collect(),
+            ),
+            _ => (
+                record
+                    .hosts
+                    .get(host)
+                    .map(|h| h.ports.keys().cloned().collect())
+                    .unwrap_or_default(),
+                Vec::new(),
+            ),
+        };
+        entries.push(HistoryEntry {
+            id: record.id,
+            at: record.at.clone(),
+            up,
+            first_seen,
+            changes,
+        });
+        last = Some(record);
+    }
+    entries
+}
+
+/// One alert per host with the changes as its description.
+pub fn alerts(changes: &[Change]) -> Vec<RaisedAlert> {
+    let mut by_host: BTreeMap<&str, Vec<&Change>> = BTreeMap::new();
+    for change in changes {
+        by_host.entry(change.host()).or_default().push(change);
+    }
+    by_host
+        .into_iter()
+        .map(|(host, changes)| RaisedAlert {
+            name: ALERT_NAME.to_string(),
+            severity: if changes.iter().any(|c| c.severity() == "warning") {
+                "warning"
+            } else {
+                "info"
+            }
+            .to_string(),
+            instance: host.to_string(),
+            summary: format!(
+                "{} change{} on {host} since the last scan",
+                changes.len(),
+                if changes.len() == 1 { "" } else { "s" }
+            ),
+            description: changes
+                .iter()
+                .map(|c| c.describe())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+        .collect()
+}
+
+/// The JSON-lines scan database.
+pub struct ScanDb {
+    pub path: PathBuf,
+}
+
+impl ScanDb {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn default_path() -> PathBuf {
+        crate::support::state_dir()
+            .join("scans")
+            .join("inventory.jsonl")
+    }
+
+    /// All records, oldest scan first; unreadable lines are skipped.
+    pub fn load(&self) -> Result<Vec<ScanRecord>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.path.display()));
+            }
+        };
+        let mut records: Vec<ScanRecord> = content
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        // Imported reports can predate scans saved earlier.
+        records.sort_by_key(|r| (r.time(), r.id));
+        Ok(records)
+    }
+
+    /// Store `record` under the next free id, which is returned.
+    pub fn append(&self, record: &mut ScanRecord) -> Result<u64> {
+        record.id = self.load()?.iter().map(|r| r.id).max().unwrap_or(0) + 1;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(record.id)
+    }
+}
+
+/// Save `record` and return its id with what changed since the previous scan
+/// of the same targets (nothing for a first scan).
+pub fn save(db: &ScanDb, mut record: ScanRecord) -> Result<(u64, Vec<Change>)> {
+    let id = db.append(&mut record)?;
+    let records = db.load()?;
+    let changes = previous(&records, &record)
+        .map(|prev| diff(prev, &record))
+        .unwrap_or_default();
+    Ok((id, changes))
+}
+
+pub fn subcommands() -> Vec<Command> {
+    let db = Arg::new("db")
+        .long("db")
+        .value_name("PATH")
+        .help("Scan database (default: ~/.local/state/ghostctl/scans/inventory.jsonl)");
+    let json = Arg::new("json")
+        .long("json")
+        .action(ArgAction::SetTrue)
+        .help("Output as JSON");
+    vec![
+        Command::new("list")
+            .about("List saved scans")
+            .arg(db.clone())
+            .arg(json.clone()),
+        Command::new("diff")
+            .about("Show what changed between two saved scans")
+            .arg(
+                Arg::new("from")
+                    .value_name("FROM")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("Older scan id (default: the previous scan of the same targets)"),
+            )
+            .arg(
+                Arg::new("to")
+                    .value_name("TO")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("Newer scan id (default: the latest scan)"),
+            )
+            .arg(
+                Arg::new("host")
+                    .long("host")
+                    .value_name("HOST")
+                    .help("Only show changes for HOST"),
+            )
+            .arg(
+                Arg::new("alert")
+                    .long("alert")
+                    .action(ArgAction::SetTrue)
+                    .help("Raise the changes as Alertmanager alerts (see `monitor`)"),
+            )
+            .arg(db.clone())
+            .arg(json.clone()),
+        Command::new("history")
+            .about("Show how one host changed across saved scans")
+            .arg(
+                Arg::new("host")
+                    .required(true)
+                    .value_name("HOST")
+                    .help("Host as it was scanned"),
+            )
+            .arg(db)
+            .arg(json),
+    ]
+}
+
+pub fn database(matches: &ArgMatches) -> ScanDb {
+    ScanDb::new(
+        matches
+            .get_one::<String>("db")
+            .map(PathBuf::from)
+            .unwrap_or_else(ScanDb::default_path),
+    )
+}
+
+pub fn handle(name: &str, matches: &ArgMatches) -> Result<()> {
+    let db = database(matches);
+    let records = db.load()?;
+    let json = matches.get_flag("json");
+    match name {
+        "list" => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(&records)?);
+            } else if records.is_empty() {
+                println!("No saved scans in {}", db.path.display());
+            } else {
+                println!(
+                    "{:<5} {:<26} {:<9} {:>5}  TARGETS",
+                    "ID", "WHEN", "SOURCE", "HOSTS"
+                );
+                for r in &records {
+                    println!(
+                        "{:<5} {:<26} {:<9} {:>5}  {}",
+                        r.id,
+                        r.at,
+                        r.source,
+                        r.hosts.len(),
+                        r.targets.join(", ")
+                    );
+                }
+            }
+            Ok(())
+        }
+        "diff" => {
+            let find = |id: u64| {
+                records
+                    .iter()
+                    .find(|r| r.id == id)
+                    .with_context(|| format!("no saved scan with id {id}"))
+            };
+            let to = match matches.get_one::<u64>("to") {
+                Some(id) => find(*id)?,
+                None => records.last().context("no saved scans yet")?,
+            };
+            let from = match matches.get_one::<u64>("from") {
+                Some(id) => find(*id)?,
+                None => {
+                    previous(&records, to).context("only one scan saved; nothing to compare")?
+                }
+            };
+            let position = |r: &ScanRecord| records.iter().position(|x| x.id == r.id);
+            if position(from) >= position(to) {
+                bail!("scan {} is not older than scan {}", from.id, to.id);
+            }
+            let mut changes = diff(from, to);
+            if let Some(host) = matches.get_one::<String>("host") {
+                changes.retain(|c| c.host() == host);
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else {
+                println!(
+                    "Scan {} ({}) -> scan {} ({})",
+                    from.id, from.at, to.id, to.at
+                );
+                print_changes(&changes);
+            }
+            if matches.get_flag("alert") {
+                crate::monitor::push_alerts(&alerts(&changes), ALERT_HOURS)?;
+            }
+            Ok(())
+        }
+        "history" => {
+            let host = matches
+                .get_one::<String>("host")
+                .map(String::as_str)
+                .unwrap_or_default();
+            let entries = history(&records, host);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+                return Ok(());
+            }
+            if entries.is_empty() {
+                println!("No saved scan covered {host}");
+                return Ok(());
+            }
+            println!("History of {host}");
+            for entry in &entries {
+                let head = format!("#{:<4} {}", entry.id, entry.at);
+                if !entry.first_seen.is_empty() {
+                    println!("{head}  first seen: {}", entry.first_seen.join(", "));
+                } else if !entry.changes.is_empty() {
+                    for (i, change) in entry.changes.iter().enumerate() {
+                        let head = if i == 0 { head.as_str() } else { "" };
+                        println!("{head:<32}  {}", change.describe());
+                    }
+                } else if entry.up {
+                    println!("{head}  no changes");
+                } else {
+                    println!("{head}  down");
+                }
+            }
+            Ok(())
+        }
+        _ => unreachable!("scan subcommands are list, diff and history"),
+    }
+}
+
+pub fn print_changes(changes: &[Change]) {
+    if changes.is_empty() {
+        println!("No changes.");
+        return;
+    }
+    let mut host = "";
+    for change in changes {
+        if change.host() != host {
+            host = change.host();
+            println!("{host}");
+        }
+        println!("  {}", change.describe());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn result(target: &str, port: u16, status: PortStatus, banner: Option<&str>) -> ScanResult {
+        ScanResult {
+            target: target.to_string(),
+            port,
+            status,
+            service: super::super::services::get_service_name(port).map(str::to_string),
+            banner: banner.map(str::to_string),
+            version: None,
+            tls: None,
+            response_time: Duration::from_millis(3),
+        }
+    }
+
+    fn at(day: u32) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2026-10-{day:02}T03:00:00Z"))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn week_one() -> ScanRecord {
+        let mut record = ScanRecord::from_results(
+            &[
+                result(
+                    "10.0.0.5",
+                    22,
+                    PortStatus::Open,
+                    Some("SSH-2.0-OpenSSH_9.2p1"),
+                ),
+                result("10.0.0.5", 80, PortStatus::Open, None),
+                result("10.0.0.5", 443, PortStatus::Closed, None),
+                result("10.0.0.6", 22, PortStatus::Closed, None),
+                result("10.0.0.7", 22, PortStatus::Filtered, None),
+            ],
+            &[
+                "10.0.0.5".to_string(),
+                "10.0.0.6".to_string(),
+                "10.0.0.7".to_string(),
+            ],
+            "1-1000",
+            at(4),
+        );
+        record.id = 1;
+        record.hosts.get_mut("10.0.0.5").unwrap().os = Some("Linux 5.x (90%)".to_string());
+        record
+    }
+
+    #[test]
+    fn records_keep_open_ports_and_answering_hosts() {
+        let record = week_one();
+        assert_eq!(
+            record.hosts.keys().collect::<Vec<_>>(),
+            vec!["10.0.0.5", "10.0.0.6"],
+            "a host with only filtered ports is not up"
+        );
+        let ssh = &record.hosts["10.0.0.5"].ports["22/tcp"];
+        assert_eq!(ssh.service.as_deref(), Some("ssh"));
+        assert!(ssh.version.as_deref().unwrap().contains("9.2"));
+        assert!(record.covers_port("999/tcp"));
+        assert!(!record.covers_port("8080/tcp"));
+        assert!(!record.covers_port("53/udp"));
+    }
+
+    #[test]
+    fn diff_reports_ports_versions_hosts_and_os() {
+        let old = week_one();
+        let mut new = ScanRecord::from_results(
+            &[
+                result(
+                    "10.0.0.5",
+                    22,
+                    PortStatus::Open,
+                    Some("SSH-2.0-OpenSSH_9.6p1"),
+                ),
+                result("10.0.0.5", 443, PortStatus::Open, None),
+                result("10.0.0.5", 8080, PortStatus::Open, None),
+                result("10.0.0.7", 3306, PortStatus::Open, None),
+            ],
+            &old.targets,
+            "1-10000",
+            at(11),
+        );
+        new.id = 2;
+        new.hosts.get_mut("10.0.0.5").unwrap().os = Some("Linux 6.x (90%)".to_string());
+        let changes = diff(&old, &new);
+        let lines: Vec<String> = changes
+            .iter()
+            .map(|c| format!("{} {}", c.host(), c.describe()))
+            .collect();
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("10.0.0.5 ~ 22/tcp version"))
+        );
+        assert!(lines.contains(&"10.0.0.5 + 443/tcp https".to_string()));
+        assert!(lines.contains(&"10.0.0.5 - 80/tcp http".to_string()));
+        assert!(lines.contains(&"10.0.0.5 ~ os Linux 5.x (90%) -> Linux 6.x (90%)".to_string()));
+        assert!(lines.contains(&"10.0.0.6 host down".to_string()));
+        assert!(lines.contains(&"10.0.0.7 host up, open: 3306/tcp".to_string()));
+        // 8080 was outside the first scan's range: new to us, but not "opened".
+        assert!(!lines.iter().any(|l| l.contains("8080")));
+
+        let alerts = alerts(&changes);
+        assert_eq!(alerts.len(), 3);
+        assert_eq!(alerts[0].instance, "10.0.0.5");
+        assert_eq!(alerts[0].severity, "warning");
+        assert_eq!(alerts[1].severity, "info");
+    }
+
+    #[test]
+    fn narrower_rescans_do_not_close_ports() {
+        let old = week_one();
+        let mut new = ScanRecord::from_results(
+            &[result(
+                "10.0.0.5",
+                22,
+                PortStatus::Open,
+                Some("SSH-2.0-OpenSSH_9.2p1"),
+            )],
+            &["10.0.0.5".to_string()],
+            "22",
+            at(11),
+        );
+        new.id = 2;
+        assert_eq!(diff(&old, &new), Vec::new());
+    }
+
+    #[test]
+    fn database_assigns_ids_and_history_follows_one_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ScanDb::new(dir.path().join("scans").join("inventory.jsonl"));
+        let mut first = week_one();
+        assert_eq!(db.append(&mut first).unwrap(), 1);
+        let mut other = ScanRecord::from_results(
+            &[result("192.168.9.9", 53, PortStatus::Open, None)],
+            &["192.168.9.0/24".to_string()],
+            "53",
+            at(6),
+        );
+        db.append(&mut other).unwrap();
+        let mut second = ScanRecord::from_results(
+            &[result(
+                "10.0.0.5",
+                22,
+                PortStatus::Open,
+                Some("SSH-2.0-OpenSSH_9.2p1"),
+            )],
+            &first.targets,
+            "1-1000",
+            at(11),
+        );
+        assert_eq!(db.append(&mut second).unwrap(), 3);
+
+        let records = db.load().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            previous(&records, &records[2]).unwrap().id,
+            1,
+            "same targets"
+        );
+        assert!(records[1].covers_host("192.168.9.20"));
+
+        let history = history(&records, "10.0.0.5");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].first_seen, vec!["22/tcp", "80/tcp"]);
+        assert_eq!(history[1].changes.len(), 1);
+        assert_eq!(history[1].changes[0].describe(), "- 80/tcp http");
+    }
+}
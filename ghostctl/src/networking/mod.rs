@@ -1,19 +1,27 @@
 pub mod advanced_firewall;
 pub mod advanced_scanner;
 pub mod bridge_management;
+pub mod diagnose;
 pub mod enterprise_networking;
 pub mod errors;
 pub mod export;
 pub mod fingerprint;
 pub mod firewall;
+pub mod guard;
 pub mod hw_offload;
+pub mod inventory;
 pub mod libvirt_advanced;
 pub mod nftables_enterprise;
+pub mod nmap;
+pub mod policy;
+pub mod probes;
 pub mod safe_commands;
 pub mod scanner;
 pub mod services;
+pub mod tls;
 pub mod troubleshoot;
 pub mod virtualization;
+pub mod xml;
 
 // Note: safe_commands provides secure command execution helpers
 // Import specific functions as needed in firewall modules
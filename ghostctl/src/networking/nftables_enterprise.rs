@@ -316,6 +316,8 @@ pub enum RejectType {
     IcmpProtoUnreach,
     IcmpNetUnreach,
     IcmpAdminProhibited,
+    IcmpHostProhibited,
+    IcmpNetProhibited,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1541,20 +1543,22 @@ fn atomic_rule_deployment() {
     };
 
     if confirm {
-        // Flush and apply atomically
-        let result = Command::new("nft").args(["-f", &file_path]).output();
+        // Flush and apply atomically; reverted unless confirmed from a new session
+        crate::networking::guard::interactive(crate::networking::guard::Backend::Nftables, || {
+            let result = Command::new("nft").args(["-f", &file_path]).output();
 
-        match result {
-            Ok(r) if r.status.success() => {
-                println!("✅ Configuration applied atomically");
-            }
-            Ok(r) => {
-                eprintln!("❌ Failed: {}", String::from_utf8_lossy(&r.stderr));
-            }
-            Err(e) => {
-                eprintln!("❌ Error: {}", e);
+            match result {
+                Ok(r) if r.status.success() => {
+                    println!("✅ Configuration applied atomically");
+                }
+                Ok(r) => {
+                    eprintln!("❌ Failed: {}", String::from_utf8_lossy(&r.stderr));
+                }
+                Err(e) => {
+                    eprintln!("❌ Error: {}", e);
+                }
             }
-        }
+        });
     }
 }
 
@@ -2025,6 +2029,26 @@ fn rule_performance_analysis() {
             } else {
                 println!("  💡 Consider enabling flow offloading for better performance");
             }
+
+            // Counters above are since the last reload; the sampled history
+            // shows which rules actually carry traffic over time.
+            let samples = crate::networking::policy::hits::HitLog::locate()
+                .load()
+                .unwrap_or_default();
+            if samples.is_empty() {
+                println!(
+                    "\n💡 Record counters over time with 'ghostctl firewall hits timer' to find dead and hot rules"
+                );
+            } else {
+                println!();
+                crate::networking::policy::hits::print_report(
+                    &crate::networking::policy::hits::report(
+                        &samples,
+                        chrono::Utc::now(),
+                        &crate::networking::policy::hits::ReportOptions { days: 30, top: 5 },
+                    ),
+                );
+            }
         }
         _ => {
             eprintln!("❌ Failed to analyze ruleset");
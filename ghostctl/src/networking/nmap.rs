@@ -0,0 +1,559 @@
+//! nmap XML reports: `ghostctl scan import`.
+//!
+//! An `-oX` report becomes the same [`ScanReport`] the exporters write, so
+//! it can be re-rendered as JSON, CSV, XML or Markdown, and a
+//! [`ScanRecord`] with source `nmap` for the scan inventory. Hosts, ports
+//! (with the `extraports` counts), service product/version, OS matches and
+//! port and host script output are read; traceroute, timing and the raw
+//! fingerprints are ignored.
+
+use super::export::{
+    ExportScanResult, HostReport, OsMatch, ScanInfo, ScanReport, ScanSummary, ScriptOutput,
+};
+use super::inventory::{self, HostRecord, PortRecord, ScanRecord};
+use super::xml::{self, Element};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// One parsed nmap run.
+#[derive(Debug, Clone)]
+pub struct NmapRun {
+    pub report: ScanReport,
+    /// When the scan started, from `nmaprun start`.
+    pub started: Option<DateTime<Utc>>,
+    /// Ports probed per protocol, from `scaninfo services`.
+    pub scanned: BTreeMap<String, String>,
+}
+
+/// Parse an nmap `-oX` document.
+pub fn parse(input: &str) -> Result<NmapRun> {
+    let root = xml::parse(input)?;
+    if root.name != "nmaprun" {
+        bail!("not an nmap XML report (root element <{}>)", root.name);
+    }
+    let started = root
+        .attr("start")
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|s| DateTime::from_timestamp(s, 0));
+    let finished = root.child("runstats").and_then(|r| r.child("finished"));
+    let timestamp = |t: Option<DateTime<Utc>>, text: Option<&str>| {
+        t.map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .or(text.map(str::to_string))
+            .unwrap_or_default()
+    };
+    let ended = finished
+        .and_then(|f| f.attr("time"))
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|s| DateTime::from_timestamp(s, 0));
+
+    let mut scanned: BTreeMap<String, String> = BTreeMap::new();
+    for info in root.children_named("scaninfo") {
+        if let (Some(proto), Some(services)) = (info.attr("protocol"), info.attr("services")) {
+            scanned
+                .entry(proto.to_string())
+                .and_modify(|s| {
+                    s.push(',');
+                    s.push_str(services);
+                })
+                .or_insert_with(|| services.to_string());
+        }
+    }
+
+    let mut summary = ScanSummary {
+        total_hosts: 0,
+        hosts_up: 0,
+        total_ports_scanned: 0,
+        open_ports: 0,
+        closed_ports: 0,
+        filtered_ports: 0,
+    };
+    let mut hosts = Vec::new();
+    for host in root.children_named("host") {
+        let Some(report) = host_report(host, &mut summary) else {
+            continue;
+        };
+        summary.total_hosts += 1;
+        if report.status == "up" {
+            summary.hosts_up += 1;
+        }
+        hosts.push(report);
+    }
+
+    Ok(NmapRun {
+        report: ScanReport {
+            scan_info: ScanInfo {
+                start_time: timestamp(started, root.attr("startstr")),
+                end_time: timestamp(ended, finished.and_then(|f| f.attr("timestr"))),
+                duration_secs: finished
+                    .and_then(|f| f.attr("elapsed"))
+                    .and_then(|e| e.parse().ok())
+                    .unwrap_or(0.0),
+                scanner: root.attr("scanner").unwrap_or("nmap").to_string(),
+                version: root.attr("version").unwrap_or_default().to_string(),
+                args: root.attr("args").map(str::to_string),
+            },
+            hosts,
+            summary,
+        },
+        started,
+        scanned,
+    })
+}
+
+fn host_report(host: &Element, summary: &mut ScanSummary) -> Option<HostReport> {
+    let address = host
+        .children_named("address")
+        .find(|a| matches!(a.attr("addrtype"), Some("ipv4" | "ipv6") | None))
+        .and_then(|a| a.attr("addr"))?
+        .to_string();
+    let hostnames = host.child("hostnames");
+    let hostname = hostnames
+        .and_then(|h| {
+            h.children_named("hostname")
+                .find(|n| n.attr("type") == Some("user"))
+                .or_else(|| h.child("hostname"))
+        })
+        .and_then(|n| n.attr("name"))
+        .map(str::to_string);
+    let status = host
+        .child("status")
+        .and_then(|s| s.attr("state"))
+        .unwrap_or("unknown")
+        .to_string();
+
+    let mut ports = Vec::new();
+    if let Some(list) = host.child("ports") {
+        for extra in list.children_named("extraports") {
+            let count: usize = extra
+                .attr("count")
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(0);
+            summary.total_ports_scanned += count;
+            match extra.attr("state").map(port_status) {
+                Some("Closed") => summary.closed_ports += count,
+                Some("Filtered") => summary.filtered_ports += count,
+                _ => {}
+            }
+        }
+        for port in list.children_named("port") {
+            let Some(number) = port.attr("portid").and_then(|p| p.parse::<u16>().ok()) else {
+                continue;
+            };
+            let state = port_status(
+                port.child("state")
+                    .and_then(|s| s.attr("state"))
+                    .unwrap_or("unknown"),
+            );
+            summary.total_ports_scanned += 1;
+            match state {
+                "Open" => summary.open_ports += 1,
+                "Closed" => summary.closed_ports += 1,
+                "Filtered" => summary.filtered_ports += 1,
+                _ => {}
+            }
+            let service = port.child("service");
+            let attr = |name: &str| {
+                service
+                    .and_then(|s| s.attr(name))
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+            };
+            let scripts = scripts(port);
+            let banner = scripts
+                .iter()
+                .find(|s| s.id == "banner")
+                .map(|s| s.output.clone())
+                .or_else(|| service_line(service?));
+            ports.push(ExportScanResult {
+                target: address.clone(),
+                port: number,
+                protocol: port.attr("protocol").unwrap_or("tcp").to_string(),
+                status: state.to_string(),
+                service: attr("name"),
+                product: attr("product"),
+                version: attr("version"),
+                banner,
+                response_time_ms: 0,
+                tls: (service.and_then(|s| s.attr("tunnel")) == Some("ssl")).then_some(true),
+                ssl: None,
+                scripts,
+            });
+        }
+    }
+
+    let mut os: Vec<OsMatch> = host
+        .child("os")
+        .map(|o| {
+            o.children_named("osmatch")
+                .filter_map(|m| {
+                    Some(OsMatch {
+                        name: m.attr("name")?.to_string(),
+                        accuracy: m.attr("accuracy").and_then(|a| a.parse().ok()).unwrap_or(0),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    os.sort_by_key(|m| std::cmp::Reverse(m.accuracy));
+
+    Some(HostReport {
+        address,
+        hostname,
+        status,
+        ports,
+        os,
+        scripts: host.child("hostscript").map(scripts).unwrap_or_default(),
+    })
+}
+
+/// nmap port state as the scanner's `PortStatus` name.
+fn port_status(state: &str) -> &'static str {
+    match state {
+        "open" => "Open",
+        "closed" => "Closed",
+        "filtered" | "open|filtered" | "closed|filtered" => "Filtered",
+        _ => "Unknown",
+    }
+}
+
+fn scripts(parent: &Element) -> Vec<ScriptOutput> {
+    parent
+        .children_named("script")
+        .filter_map(|s| {
+            Some(ScriptOutput {
+                id: s.attr("id")?.to_string(),
+                output: s.attr("output").unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `product version (extrainfo)`, nmap's one-line service description.
+fn service_line(service: &Element) -> Option<String> {
+    let mut line: Vec<&str> = ["product", "version"]
+        .iter()
+        .filter_map(|a| service.attr(a))
+        .filter(|v| !v.is_empty())
+        .collect();
+    let extra = service.attr("extrainfo").map(|e| format!("({e})"));
+    if let Some(extra) = &extra {
+        line.push(extra);
+    }
+    (!line.is_empty()).then(|| line.join(" "))
+}
+
+/// The inventory record for an nmap run. Hosts that nmap reported down are
+/// kept as targets so a later scan can report them as having come up.
+pub fn record(run: &NmapRun, fallback_time: DateTime<Utc>) -> ScanRecord {
+    let mut hosts = BTreeMap::new();
+    for host in run.report.hosts.iter().filter(|h| h.status == "up") {
+        let ports = host
+            .ports
+            .iter()
+            .filter(|p| p.status == "Open")
+            .map(|p| {
+                (
+                    format!("{}/{}", p.port, p.protocol),
+                    PortRecord {
+                        service: p.service.clone(),
+                        banner: p.banner.clone(),
+                        version: p.version.clone(),
+                    },
+                )
+            })
+            .collect();
+        hosts.insert(
+            host.address.clone(),
+            HostRecord {
+                os: host.os.first().map(|o| o.name.clone()),
+                ports,
+            },
+        );
+    }
+    ScanRecord {
+        id: 0,
+        at: run
+            .started
+            .unwrap_or(fallback_time)
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        source: "nmap".to_string(),
+        targets: run.report.hosts.iter().map(|h| h.address.clone()).collect(),
+        ports: run.scanned.clone(),
+        hosts,
+    }
+}
+
+pub fn command() -> Command {
+    Command::new("import")
+        .about("Import an nmap XML report into the scan inventory")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_name("FILE.xml")
+                .help("nmap report written with -oX"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(["json", "json-pretty", "csv", "xml", "markdown"])
+                .help("Render the imported report (to stdout unless --output is given)"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("PATH")
+                .requires("format")
+                .help("Write the rendered report to PATH"),
+        )
+        .arg(
+            Arg::new("no-save")
+                .long("no-save")
+                .action(ArgAction::SetTrue)
+                .help("Only render the report; do not add it to the scan database"),
+        )
+        .arg(
+            Arg::new("db")
+                .long("db")
+                .value_name("PATH")
+                .help("Scan database (default: ~/.local/state/ghostctl/scans/inventory.jsonl)"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let file = matches
+        .get_one::<String>("file")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let input = std::fs::read_to_string(file).with_context(|| format!("cannot read {file}"))?;
+    let run = parse(&input).with_context(|| format!("invalid nmap report {file}"))?;
+
+    if let Some(format) = matches.get_one::<String>("format") {
+        let format: super::export::ExportFormat =
+            format.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+        let rendered = match matches.get_one::<String>("output") {
+            Some(path) => super::export::export_report(&run.report, Path::new(path), format),
+            None => {
+                let mut out = std::io::stdout().lock();
+                super::export::write_report(&run.report, &mut out, format)
+            }
+        };
+        rendered.map_err(|e| anyhow::anyhow!("cannot render report: {e}"))?;
+    }
+    if matches.get_flag("no-save") {
+        return Ok(());
+    }
+
+    let db = inventory::database(matches);
+    let record = record(&run, Utc::now());
+    if db
+        .load()?
+        .iter()
+        .any(|r| r.source == record.source && r.at == record.at && r.hosts == record.hosts)
+    {
+        crate::tui::info(&format!("{file} is already in {}", db.path.display()));
+        return Ok(());
+    }
+    let hosts = record.hosts.len();
+    let (id, changes) = inventory::save(&db, record)?;
+    // Keep stdout clean when the report itself went there.
+    if matches.contains_id("format") && !matches.contains_id("output") {
+        return Ok(());
+    }
+    crate::tui::success(&format!(
+        "Imported {file} as scan {id} ({hosts} host(s) up)"
+    ));
+    inventory::print_changes(&changes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::inventory::ScanDb;
+
+    const REPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE nmaprun>
+<?xml-stylesheet href="file:///usr/bin/../share/nmap/nmap.xsl" type="text/xsl"?>
+<nmaprun scanner="nmap" args="nmap -sV -O -oX lab.xml 10.0.0.5-7" start="1760842800" startstr="Sun Oct 19 03:00:00 2025" version="7.94" xmloutputversion="1.05">
+<scaninfo type="syn" protocol="tcp" numservices="1000" services="1,3-4,6-7,9,13,17,19-26,80,443,8000-8100"/>
+<verbose level="0"/>
+<host starttime="1760842801" endtime="1760842830"><status state="up" reason="echo-reply" reason_ttl="63"/>
+<address addr="10.0.0.5" addrtype="ipv4"/>
+<address addr="52:54:00:12:34:56" addrtype="mac" vendor="QEMU virtual NIC"/>
+<hostnames><hostname name="pve1.lab" type="PTR"/></hostnames>
+<ports><extraports state="closed" count="997">
+<extrareasons reason="reset" count="997" proto="tcp" ports="1,3-4"/>
+</extraports>
+<port protocol="tcp" portid="22"><state state="open" reason="syn-ack" reason_ttl="63"/><service name="ssh" product="OpenSSH" version="9.2p1 Debian 2+deb12u3" extrainfo="protocol 2.0" ostype="Linux" method="probed" conf="10"><cpe>cpe:/a:openbsd:openssh:9.2p1</cpe></service><script id="ssh-hostkey" output="&#xa;  256 aa:bb (ECDSA)&#xa;  256 cc:dd (ED25519)"/></port>
+<port protocol="tcp" portid="80"><state state="open" reason="syn-ack" reason_ttl="63"/><service name="http" product="nginx" version="1.22.1" method="probed" conf="10"/><script id="http-title" output="Lab &amp; Co"/></port>
+<port protocol="tcp" portid="8006"><state state="filtered" reason="no-response" reason_ttl="0"/><service name="wpl-analytics" method="table" conf="3"/></port>
+</ports>
+<os><portused state="open" proto="tcp" portid="22"/>
+<osmatch name="Linux 4.15 - 5.8" accuracy="94" line="67340"><osclass type="general purpose" vendor="Linux" osfamily="Linux" osgen="4.X" accuracy="94"/></osmatch>
+<osmatch name="Linux 5.0 - 5.14" accuracy="96" line="68000"/>
+</os>
+<hostscript><script id="smb-os-discovery" output="OS: Samba"/></hostscript>
+<times srtt="386" rttvar="155" to="100000"/>
+</host>
+<host><status state="down" reason="no-response" reason_ttl="0"/>
+<address addr="10.0.0.6" addrtype="ipv4"/>
+</host>
+<runstats><finished time="1760842830" timestr="Sun Oct 19 03:00:30 2025" summary="Nmap done" elapsed="30.12" exit="success"/><hosts up="1" down="1" total="2"/>
+</runstats>
+</nmaprun>
+"#;
+
+    #[test]
+    fn report_is_read_into_hosts_ports_os_and_scripts() {
+        let run = parse(REPORT).unwrap();
+        let report = &run.report;
+        assert_eq!(report.scan_info.scanner, "nmap");
+        assert_eq!(report.scan_info.version, "7.94");
+        assert_eq!(report.scan_info.duration_secs, 30.12);
+        assert_eq!(
+            run.scanned["tcp"],
+            "1,3-4,6-7,9,13,17,19-26,80,443,8000-8100"
+        );
+        assert_eq!(report.hosts.len(), 2);
+        assert_eq!(report.summary.hosts_up, 1);
+        assert_eq!(report.summary.open_ports, 2);
+        assert_eq!(report.summary.closed_ports, 997);
+        assert_eq!(report.summary.filtered_ports, 1);
+        assert_eq!(report.summary.total_ports_scanned, 1000);
+
+        let host = &report.hosts[0];
+        assert_eq!(host.address, "10.0.0.5");
+        assert_eq!(host.hostname.as_deref(), Some("pve1.lab"));
+        assert_eq!(host.os[0].name, "Linux 5.0 - 5.14");
+        assert_eq!(host.os[0].accuracy, 96);
+        assert_eq!(host.scripts[0].id, "smb-os-discovery");
+
+        let ssh = &host.ports[0];
+        assert_eq!(ssh.service.as_deref(), Some("ssh"));
+        assert_eq!(ssh.product.as_deref(), Some("OpenSSH"));
+        assert_eq!(ssh.version.as_deref(), Some("9.2p1 Debian 2+deb12u3"));
+        assert_eq!(
+            ssh.banner.as_deref(),
+            Some("OpenSSH 9.2p1 Debian 2+deb12u3 (protocol 2.0)")
+        );
+        assert_eq!(
+            ssh.scripts[0].output,
+            "256 aa:bb (ECDSA)\n  256 cc:dd (ED25519)"
+        );
+        assert_eq!(host.ports[1].scripts[0].output, "Lab & Co");
+        assert_eq!(host.ports[2].status, "Filtered");
+        assert_eq!(report.hosts[1].status, "down");
+    }
+
+    #[test]
+    fn exported_xml_reads_back() {
+        let run = parse(REPORT).unwrap();
+        let mut xml = Vec::new();
+        super::super::export::write_report(
+            &run.report,
+            &mut xml,
+            super::super::export::ExportFormat::NmapXml,
+        )
+        .unwrap();
+        let again = parse(&String::from_utf8(xml).unwrap()).unwrap();
+        let key = |r: &NmapRun| {
+            r.report.hosts[0]
+                .ports
+                .iter()
+                .map(|p| {
+                    (
+                        p.port,
+                        p.protocol.clone(),
+                        p.status.clone(),
+                        p.service.clone(),
+                        p.product.clone(),
+                        p.version.clone(),
+                        p.scripts.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(key(&again), key(&run));
+        assert_eq!(again.report.hosts[0].os, run.report.hosts[0].os);
+        assert_eq!(again.report.hosts[0].scripts, run.report.hosts[0].scripts);
+        assert_eq!(again.report.hosts[0].hostname, run.report.hosts[0].hostname);
+
+        let mut md = Vec::new();
+        super::super::export::write_report(
+            &run.report,
+            &mut md,
+            super::super::export::ExportFormat::Markdown,
+        )
+        .unwrap();
+        let md = String::from_utf8(md).unwrap();
+        assert!(md.contains("| 22 | Open | ssh | OpenSSH 9.2p1 Debian 2+deb12u3 | 0ms |"));
+        assert!(md.contains("OS: Linux 5.0 - 5.14 (96%)"));
+    }
+
+    #[test]
+    fn imports_merge_into_the_inventory_in_time_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ScanDb::new(dir.path().join("inventory.jsonl"));
+        // A ghostctl scan from this year...
+        let mut recent = record(&parse(REPORT).unwrap(), Utc::now());
+        recent.source = "ghostctl".to_string();
+        recent.at = "2026-10-12T03:00:00Z".to_string();
+        recent.ports = BTreeMap::from([("tcp".to_string(), "1-1000".to_string())]);
+        recent
+            .hosts
+            .get_mut("10.0.0.5")
+            .unwrap()
+            .ports
+            .remove("80/tcp");
+        inventory::save(&db, recent).unwrap();
+
+        // ...then last year's nmap report, imported afterwards.
+        let run = parse(REPORT).unwrap();
+        let (id, changes) = inventory::save(&db, record(&run, Utc::now())).unwrap();
+        assert_eq!(id, 2);
+        assert!(
+            changes.is_empty(),
+            "nothing precedes a 2025 scan: {changes:?}"
+        );
+
+        let records = db.load().unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![2, 1],
+            "records are ordered by scan time"
+        );
+        let nmap = &records[0];
+        assert_eq!(nmap.source, "nmap");
+        assert_eq!(nmap.at, "2025-10-19T03:00:00Z");
+        assert!(nmap.covers_port("8050/tcp"));
+        assert_eq!(
+            nmap.hosts["10.0.0.5"].os.as_deref(),
+            Some("Linux 5.0 - 5.14")
+        );
+        assert!(!nmap.hosts.contains_key("10.0.0.6"));
+
+        // The ghostctl scan now diffs against the imported one.
+        let changes = inventory::diff(&records[0], &records[1]);
+        assert_eq!(
+            changes
+                .iter()
+                .map(inventory::Change::describe)
+                .collect::<Vec<_>>(),
+            vec!["- 80/tcp http"]
+        );
+        let history = inventory::history(&records, "10.0.0.6");
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|entry| !entry.up));
+    }
+
+    #[test]
+    fn other_documents_are_rejected() {
+        let err = parse("<zone><service name=\"ssh\"/></zone>").unwrap_err();
+        assert!(err.to_string().contains("not an nmap XML report"));
+    }
+}
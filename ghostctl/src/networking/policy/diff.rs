@@ -0,0 +1,301 @@
+//! Semantic diff between the compiled policy and the live table.
+//!
+//! Chains and sets are compared by name, set elements as sets, and rules as
+//! ordered lists of normalised rule text (longest common subsequence), so a
+//! moved rule shows as one removal and one addition and rule handles or
+//! counter values never produce noise.
+
+use super::live::{Ruleset, Table};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub family: String,
+    pub table: String,
+    /// Whether the table is currently loaded.
+    pub exists: bool,
+    pub changes: Vec<Change>,
+    /// Other tables hooking the same filter hooks; their verdicts still apply.
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    CreateTable,
+    AddChain {
+        chain: String,
+        definition: Option<String>,
+    },
+    DeleteChain {
+        chain: String,
+    },
+    ChangeChain {
+        chain: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    AddSet {
+        set: String,
+        definition: String,
+    },
+    DeleteSet {
+        set: String,
+    },
+    ChangeSet {
+        set: String,
+        from: String,
+        to: String,
+    },
+    AddElements {
+        set: String,
+        elements: Vec<String>,
+    },
+    RemoveElements {
+        set: String,
+        elements: Vec<String>,
+    },
+    AddRule {
+        chain: String,
+        rule: String,
+    },
+    DeleteRule {
+        chain: String,
+        handle: Option<u64>,
+        rule: String,
+    },
+}
+
+impl Plan {
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+}
+
+pub fn plan(desired: &Table, live: &Ruleset) -> Plan {
+    let current = live.table(&desired.family, &desired.name);
+    let empty = Table::default();
+    let mut changes = Vec::new();
+    if current.is_none() {
+        changes.push(Change::CreateTable);
+    }
+    let current = current.unwrap_or(&empty);
+
+    for set in &desired.sets {
+        match current.sets.iter().find(|s| s.name == set.name) {
+            None => {
+                changes.push(Change::AddSet {
+                    set: set.name.clone(),
+                    definition: set.definition.clone(),
+                });
+                if !set.elements.is_empty() {
+                    changes.push(Change::AddElements {
+                        set: set.name.clone(),
+                        elements: set.elements.clone(),
+                    });
+                }
+            }
+            Some(live_set) => {
+                if live_set.definition != set.definition {
+                    changes.push(Change::ChangeSet {
+                        set: set.name.clone(),
+                        from: live_set.definition.clone(),
+                        to: set.definition.clone(),
+                    });
+                }
+                let added: Vec<String> = set
+                    .elements
+                    .iter()
+                    .filter(|e| !live_set.elements.contains(e))
+                    .cloned()
+                    .collect();
+                let removed: Vec<String> = live_set
+                    .elements
+                    .iter()
+                    .filter(|e| !set.elements.contains(e))
+                    .cloned()
+                    .collect();
+                if !added.is_empty() {
+                    changes.push(Change::AddElements {
+                        set: set.name.clone(),
+                        elements: added,
+                    });
+                }
+                if !removed.is_empty() {
+                    changes.push(Change::RemoveElements {
+                        set: set.name.clone(),
+                        elements: removed,
+                    });
+                }
+            }
+        }
+    }
+    for set in &current.sets {
+        if !desired.sets.iter().any(|s| s.name == set.name) {
+            changes.push(Change::DeleteSet {
+                set: set.name.clone(),
+            });
+        }
+    }
+
+    for chain in &desired.chains {
+        let desired_rules: Vec<&str> = chain.rules.iter().map(|r| r.text.as_str()).collect();
+        match current.chains.iter().find(|c| c.name == chain.name) {
+            None => {
+                changes.push(Change::AddChain {
+                    chain: chain.name.clone(),
+                    definition: chain.definition.clone(),
+                });
+                for rule in desired_rules {
+                    changes.push(Change::AddRule {
+                        chain: chain.name.clone(),
+                        rule: rule.to_string(),
+                    });
+                }
+            }
+            Some(live_chain) => {
+                if live_chain.definition != chain.definition {
+                    changes.push(Change::ChangeChain {
+                        chain: chain.name.clone(),
+                        from: live_chain.definition.clone(),
+                        to: chain.definition.clone(),
+                    });
+                }
+                let live_rules: Vec<&str> =
+                    live_chain.rules.iter().map(|r| r.text.as_str()).collect();
+                for step in lcs_diff(&live_rules, &desired_rules) {
+                    match step {
+                        Step::Keep => {}
+                        Step::Remove(i) => changes.push(Change::DeleteRule {
+                            chain: chain.name.clone(),
+                            handle: live_chain.rules[i].handle,
+                            rule: live_chain.rules[i].text.clone(),
+                        }),
+                        Step::Add(j) => changes.push(Change::AddRule {
+                            chain: chain.name.clone(),
+                            rule: desired_rules[j].to_string(),
+                        }),
+                    }
+                }
+            }
+        }
+    }
+    for chain in &current.chains {
+        if !desired.chains.iter().any(|c| c.name == chain.name) {
+            changes.push(Change::DeleteChain {
+                chain: chain.name.clone(),
+            });
+        }
+    }
+
+    let mut notes = Vec::new();
+    for table in &live.tables {
+        if table.family == desired.family && table.name == desired.name {
+            continue;
+        }
+        for chain in &table.chains {
+            let Some(def) = &chain.definition else {
+                continue;
+            };
+            let filters_same_hook = def.starts_with("type filter")
+                && ["input", "forward", "output"]
+                    .iter()
+                    .any(|hook| def.contains(&format!(" hook {hook} ")));
+            if filters_same_hook {
+                notes.push(format!(
+                    "table {} {} chain {} also filters: {def}",
+                    table.family, table.name, chain.name
+                ));
+            }
+        }
+    }
+
+    Plan {
+        family: desired.family.clone(),
+        table: desired.name.clone(),
+        exists: live.table(&desired.family, &desired.name).is_some(),
+        changes,
+        notes,
+    }
+}
+
+enum Step {
+    Keep,
+    Remove(usize),
+    Add(usize),
+}
+
+/// Edit script turning `old` into `new`, removals before additions at each
+/// divergence.
+fn lcs_diff(old: &[&str], new: &[&str]) -> Vec<Step> {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut steps = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            steps.push(Step::Keep);
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            steps.push(Step::Remove(i));
+            i += 1;
+        } else {
+            steps.push(Step::Add(j));
+            j += 1;
+        }
+    }
+    steps
+}
+
+pub fn print_plan(plan: &Plan) {
+    println!("table {} {}", plan.family, plan.table);
+    if !plan.has_changes() {
+        println!("  (no changes)");
+    }
+    for change in &plan.changes {
+        match change {
+            Change::CreateTable => println!("  + table (not loaded yet)"),
+            Change::AddChain { chain, definition } => match definition {
+                Some(def) => println!("  + chain {chain} {{ {def} }}"),
+                None => println!("  + chain {chain}"),
+            },
+            Change::DeleteChain { chain } => println!("  - chain {chain}"),
+            Change::ChangeChain { chain, from, to } => println!(
+                "  ~ chain {chain}: {} -> {}",
+                from.as_deref().unwrap_or("(regular)"),
+                to.as_deref().unwrap_or("(regular)")
+            ),
+            Change::AddSet { set, definition } => println!("  + set {set} {{ {definition} }}"),
+            Change::DeleteSet { set } => println!("  - set {set}"),
+            Change::ChangeSet { set, from, to } => println!("  ~ set {set}: {from} -> {to}"),
+            Change::AddElements { set, elements } => {
+                println!("  + set {set} elements: {}", elements.join(", "))
+            }
+            Change::RemoveElements { set, elements } => {
+                println!("  - set {set} elements: {}", elements.join(", "))
+            }
+            Change::AddRule { chain, rule } => println!("  + {chain}: {rule}"),
+            Change::DeleteRule {
+                chain,
+                handle,
+                rule,
+            } => match handle {
+                Some(h) => println!("  - {chain}: {rule}  [handle {h}]"),
+                None => println!("  - {chain}: {rule}"),
+            },
+        }
+    }
+    for note in &plan.notes {
+        println!("  note: {note}");
+    }
+}
@@ -0,0 +1,875 @@
+//! Rule hit analytics: `ghostctl firewall hits`.
+//!
+//! `sample` reads the per-rule counters from `nft -j list ruleset` and
+//! appends them, keyed by table, chain and rule handle, to a JSON-lines time
+//! series. `report` turns the series into hit counts per rule over a window:
+//! rules that saw no traffic for N days, the hottest rules, and reorder
+//! suggestions that move hot rules ahead of colder ones. Only rules that are
+//! plain matches ending in the same `accept` or `drop` are ever suggested for
+//! reordering, since swapping those cannot change any packet's verdict.
+//! `export` (or `sample --textfile`) writes the counters in the Prometheus
+//! textfile-collector format for node_exporter.
+
+use super::live::rule_text;
+use crate::command::CommandRunner;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Where the sampling timer (running as root) keeps its series.
+pub const SYSTEM_LOG: &str = "/var/lib/ghostctl/firewall-hits.jsonl";
+pub const TIMER_UNIT: &str = "ghostctl-firewall-hits";
+
+/// One counted rule at sampling time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleCounter {
+    pub family: String,
+    pub table: String,
+    pub chain: String,
+    pub handle: u64,
+    pub text: String,
+    pub packets: u64,
+    pub bytes: u64,
+    /// `accept` or `drop` when the rule is only matches, counters and that
+    /// verdict - the rules that can be reordered among themselves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plain_verdict: Option<String>,
+    /// Index among all rules of the chain, counted or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub at: String,
+    pub rules: Vec<RuleCounter>,
+    /// Rules without a `counter` statement, which cannot be measured.
+    #[serde(default)]
+    pub uncounted: usize,
+}
+
+impl Sample {
+    /// Counters from `nft -j list ruleset` output, in chain order.
+    pub fn parse(json: &str, at: DateTime<Utc>) -> Result<Self> {
+        let root: Value = serde_json::from_str(json).context("invalid nft JSON")?;
+        let items = root
+            .get("nftables")
+            .and_then(Value::as_array)
+            .context("nft JSON has no 'nftables' array")?;
+        let mut sample = Sample {
+            at: at.to_rfc3339(),
+            rules: Vec::new(),
+            uncounted: 0,
+        };
+        let mut positions: HashMap<(String, String, String), usize> = HashMap::new();
+        for rule in items.iter().filter_map(|i| i.get("rule")) {
+            let field = |v: &Value, k: &str| {
+                v.get(k)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let chain = (
+                field(rule, "family"),
+                field(rule, "table"),
+                field(rule, "chain"),
+            );
+            let next = positions.entry(chain).or_insert(0);
+            let position = *next;
+            *next += 1;
+            let exprs = rule
+                .get("expr")
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let counter = exprs
+                .iter()
+                .filter_map(|e| e.get("counter"))
+                .find(|c| c.is_object());
+            let (Some(counter), Some(handle)) =
+                (counter, rule.get("handle").and_then(Value::as_u64))
+            else {
+                sample.uncounted += 1;
+                continue;
+            };
+            sample.rules.push(RuleCounter {
+                family: field(rule, "family"),
+                table: field(rule, "table"),
+                chain: field(rule, "chain"),
+                handle,
+                text: rule_text(rule),
+                packets: counter.get("packets").and_then(Value::as_u64).unwrap_or(0),
+                bytes: counter.get("bytes").and_then(Value::as_u64).unwrap_or(0),
+                plain_verdict: plain_verdict(exprs),
+                position: Some(position),
+            });
+        }
+        Ok(sample)
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.at)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+fn plain_verdict(exprs: &[Value]) -> Option<String> {
+    let (last, rest) = exprs.split_last()?;
+    let verdict = ["accept", "drop"]
+        .into_iter()
+        .find(|v| last.get(*v).is_some())?;
+    rest.iter()
+        .all(|e| e.get("match").is_some() || e.get("counter").is_some())
+        .then(|| verdict.to_string())
+}
+
+pub fn sample(runner: &dyn CommandRunner) -> Result<Sample> {
+    let out = runner
+        .run_sudo("nft", &["-j", "list", "ruleset"])
+        .context("failed to run nft")?;
+    if !out.success {
+        bail!("nft -j list ruleset failed: {}", out.stderr.trim());
+    }
+    Sample::parse(&out.stdout, Utc::now())
+}
+
+/// The append-only series, one [`Sample`] per line.
+pub struct HitLog {
+    pub path: PathBuf,
+}
+
+impl HitLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn default_path() -> PathBuf {
+        crate::support::state_dir()
+            .join("firewall")
+            .join("hits.jsonl")
+    }
+
+    /// The user's own series, or the timer's when only that one exists.
+    pub fn locate() -> Self {
+        let own = Self::default_path();
+        if !own.exists() && Path::new(SYSTEM_LOG).exists() {
+            return Self::new(SYSTEM_LOG);
+        }
+        Self::new(own)
+    }
+
+    pub fn append(&self, sample: &Sample) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(sample)?)?;
+        Ok(())
+    }
+
+    /// All samples, oldest first; unreadable lines are skipped.
+    pub fn load(&self) -> Result<Vec<Sample>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.path.display()));
+            }
+        };
+        let mut samples: Vec<Sample> = content
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        samples.sort_by_key(|s| s.time());
+        Ok(samples)
+    }
+
+    /// Drop samples older than `keep`, returning how many were removed.
+    pub fn prune(&self, keep: Duration, now: DateTime<Utc>) -> Result<usize> {
+        let samples = self.load()?;
+        let cutoff = now - keep;
+        let kept: Vec<&Sample> = samples
+            .iter()
+            .filter(|s| s.time().is_some_and(|t| t >= cutoff))
+            .collect();
+        let removed = samples.len() - kept.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+        let mut out = String::new();
+        for sample in kept {
+            writeln!(out, "{}", serde_json::to_string(sample)?)?;
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        std::fs::write(&tmp, out)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(removed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleStats {
+    pub family: String,
+    pub table: String,
+    pub chain: String,
+    pub handle: u64,
+    pub text: String,
+    /// Packets and bytes counted inside the window.
+    pub packets: u64,
+    pub bytes: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+    #[serde(skip)]
+    plain_verdict: Option<String>,
+    #[serde(skip)]
+    position: Option<usize>,
+    #[serde(skip)]
+    span: Duration,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub family: String,
+    pub table: String,
+    pub chain: String,
+    pub handle: u64,
+    pub text: String,
+    pub packets: u64,
+    /// Handle of the first rule the hot rule should move above.
+    pub before_handle: u64,
+    /// Colder rules every matching packet currently walks past.
+    pub passes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub samples: usize,
+    pub days: i64,
+    pub rules: usize,
+    pub zero_hit: Vec<RuleStats>,
+    pub hottest: Vec<RuleStats>,
+    pub suggestions: Vec<Suggestion>,
+    pub uncounted: usize,
+    pub notes: Vec<String>,
+}
+
+pub struct ReportOptions {
+    /// Window length; also how long a rule must be watched to count as dead.
+    pub days: i64,
+    pub top: usize,
+}
+
+/// Hit counts over the last `days` before `now`.
+///
+/// The last sample before the window is used as the baseline so the whole
+/// window is covered. A counter that goes down (the table was reloaded)
+/// restarts from zero, so its new value is the hits since the reload.
+pub fn report(samples: &[Sample], now: DateTime<Utc>, options: &ReportOptions) -> Report {
+    let window = Duration::days(options.days);
+    let start = now - window;
+    let dated: Vec<(DateTime<Utc>, &Sample)> = samples
+        .iter()
+        .filter_map(|s| s.time().map(|t| (t, s)))
+        .filter(|(t, _)| *t <= now)
+        .collect();
+    let first = dated.iter().rposition(|(t, _)| *t <= start).unwrap_or(0);
+    let used = &dated[first.min(dated.len())..];
+
+    // Key includes the text: a handle reused for a different rule is a new rule.
+    type Key = (String, String, String, u64, String);
+    struct Track {
+        stats: RuleStats,
+        last: (u64, u64),
+        first_at: DateTime<Utc>,
+    }
+    let mut tracks: HashMap<Key, Track> = HashMap::new();
+    for (at, sample) in used {
+        for r in &sample.rules {
+            let key = (
+                r.family.clone(),
+                r.table.clone(),
+                r.chain.clone(),
+                r.handle,
+                r.text.clone(),
+            );
+            match tracks.get_mut(&key) {
+                Some(track) => {
+                    let delta =
+                        |now: u64, before: u64| if now >= before { now - before } else { now };
+                    track.stats.packets += delta(r.packets, track.last.0);
+                    track.stats.bytes += delta(r.bytes, track.last.1);
+                    track.last = (r.packets, r.bytes);
+                    track.stats.last_seen = sample.at.clone();
+                    track.stats.span = *at - track.first_at;
+                    track.stats.plain_verdict = r.plain_verdict.clone();
+                    track.stats.position = r.position;
+                }
+                None => {
+                    tracks.insert(
+                        key,
+                        Track {
+                            stats: RuleStats {
+                                family: r.family.clone(),
+                                table: r.table.clone(),
+                                chain: r.chain.clone(),
+                                handle: r.handle,
+                                text: r.text.clone(),
+                                packets: 0,
+                                bytes: 0,
+                                first_seen: sample.at.clone(),
+                                last_seen: sample.at.clone(),
+                                plain_verdict: r.plain_verdict.clone(),
+                                position: r.position,
+                                span: Duration::zero(),
+                            },
+                            last: (r.packets, r.bytes),
+                            first_at: *at,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    let mut report = Report {
+        from: used.first().map(|(_, s)| s.at.clone()),
+        to: used.last().map(|(_, s)| s.at.clone()),
+        samples: used.len(),
+        days: options.days,
+        rules: 0,
+        zero_hit: Vec::new(),
+        hottest: Vec::new(),
+        suggestions: Vec::new(),
+        uncounted: used.last().map(|(_, s)| s.uncounted).unwrap_or(0),
+        notes: Vec::new(),
+    };
+    let Some((last_at, latest)) = used.last() else {
+        report.notes.push(
+            "no samples yet; run `ghostctl firewall hits sample` (or install the timer)"
+                .to_string(),
+        );
+        return report;
+    };
+    if used.len() < 2 {
+        report
+            .notes
+            .push("only one sample; hit counts need at least two".to_string());
+    }
+    let covered = *last_at - used[0].0;
+    if covered < window {
+        report.notes.push(format!(
+            "history covers {:.1} of {} days; rules are only reported as unused once watched for the full window",
+            covered.num_minutes() as f64 / 1440.0,
+            options.days
+        ));
+    }
+
+    // Current rules in chain order, with their window stats.
+    let current: Vec<&RuleStats> = latest
+        .rules
+        .iter()
+        .filter_map(|r| {
+            tracks
+                .get(&(
+                    r.family.clone(),
+                    r.table.clone(),
+                    r.chain.clone(),
+                    r.handle,
+                    r.text.clone(),
+                ))
+                .map(|t| &t.stats)
+        })
+        .collect();
+    report.rules = current.len();
+    report.zero_hit = current
+        .iter()
+        .filter(|s| s.packets == 0 && s.span >= window)
+        .map(|s| (*s).clone())
+        .collect();
+    let mut hottest: Vec<&RuleStats> = current.iter().copied().filter(|s| s.packets > 0).collect();
+    hottest.sort_by_key(|s| std::cmp::Reverse(s.packets));
+    report.hottest = hottest
+        .iter()
+        .take(options.top)
+        .map(|s| (*s).clone())
+        .collect();
+
+    for (i, hot) in current.iter().enumerate() {
+        let Some(verdict) = &hot.plain_verdict else {
+            continue;
+        };
+        if hot.packets == 0 {
+            continue;
+        }
+        let mut target = i;
+        while target > 0 {
+            let (prev, next) = (current[target - 1], current[target]);
+            let same_chain =
+                (&prev.family, &prev.table, &prev.chain) == (&hot.family, &hot.table, &hot.chain);
+            // An uncounted rule in between (or a sample without positions)
+            // may be any verdict, so the rule cannot be moved past it.
+            let adjacent = matches!(
+                (prev.position, next.position),
+                (Some(p), Some(n)) if p + 1 == n
+            );
+            // At least twice as hot as every rule it jumps over.
+            if !same_chain
+                || !adjacent
+                || prev.plain_verdict.as_ref() != Some(verdict)
+                || prev.packets * 2 > hot.packets
+            {
+                break;
+            }
+            target -= 1;
+        }
+        if target < i {
+            report.suggestions.push(Suggestion {
+                family: hot.family.clone(),
+                table: hot.table.clone(),
+                chain: hot.chain.clone(),
+                handle: hot.handle,
+                text: hot.text.clone(),
+                packets: hot.packets,
+                before_handle: current[target].handle,
+                passes: i - target,
+            });
+        }
+    }
+    report
+        .suggestions
+        .sort_by_key(|s| std::cmp::Reverse(s.packets));
+    report.suggestions.truncate(options.top);
+    if report.uncounted > 0 {
+        report.notes.push(format!(
+            "{} rules have no counter and are not measured; add `counter` to them (firewall.toml: counter = true)",
+            report.uncounted
+        ));
+    }
+    report
+}
+
+pub fn print_report(report: &Report) {
+    match (&report.from, &report.to) {
+        (Some(from), Some(to)) => println!(
+            "Rule hits over the last {} days: {} samples from {from} to {to}, {} counted rules",
+            report.days, report.samples, report.rules
+        ),
+        _ => println!("Rule hits: no samples"),
+    }
+    let place = |s: &RuleStats| format!("{} {} {} #{}", s.family, s.table, s.chain, s.handle);
+    if !report.hottest.is_empty() {
+        println!("\nHottest rules:");
+        for s in &report.hottest {
+            println!(
+                "  {:>12} pkts {:>10}  {}  {}",
+                s.packets,
+                human_bytes(s.bytes),
+                place(s),
+                s.text
+            );
+        }
+    }
+    if !report.zero_hit.is_empty() {
+        println!(
+            "\nNo hits in {} days (candidates for removal):",
+            report.days
+        );
+        for s in &report.zero_hit {
+            println!("  {}  {}", place(s), s.text);
+        }
+    }
+    if !report.suggestions.is_empty() {
+        println!("\nOrdering suggestions:");
+        for s in &report.suggestions {
+            println!(
+                "  move {} {} {} #{} above #{} ({} packets skip {} colder rules)",
+                s.family, s.table, s.chain, s.handle, s.before_handle, s.packets, s.passes
+            );
+            println!("      {}", s.text);
+        }
+    }
+    for note in &report.notes {
+        println!("note: {note}");
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counters in the Prometheus text exposition format.
+pub fn prometheus(sample: &Sample) -> String {
+    let mut out = String::new();
+    for (metric, help, value) in [
+        (
+            "ghostctl_nft_rule_packets_total",
+            "Packets counted by an nftables rule.",
+            (|r: &RuleCounter| r.packets) as fn(&RuleCounter) -> u64,
+        ),
+        (
+            "ghostctl_nft_rule_bytes_total",
+            "Bytes counted by an nftables rule.",
+            |r: &RuleCounter| r.bytes,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {metric} {help}");
+        let _ = writeln!(out, "# TYPE {metric} counter");
+        for r in &sample.rules {
+            let _ = writeln!(
+                out,
+                "{metric}{{family=\"{}\",table=\"{}\",chain=\"{}\",handle=\"{}\",rule=\"{}\"}} {}",
+                label(&r.family),
+                label(&r.table),
+                label(&r.chain),
+                r.handle,
+                label(&r.text),
+                value(r)
+            );
+        }
+    }
+    let _ = writeln!(
+        out,
+        "# HELP ghostctl_nft_rules_uncounted Rules without a counter statement."
+    );
+    let _ = writeln!(out, "# TYPE ghostctl_nft_rules_uncounted gauge");
+    let _ = writeln!(out, "ghostctl_nft_rules_uncounted {}", sample.uncounted);
+    if let Some(at) = sample.time() {
+        let _ = writeln!(
+            out,
+            "# HELP ghostctl_nft_hits_sample_timestamp_seconds When the counters were read."
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE ghostctl_nft_hits_sample_timestamp_seconds gauge"
+        );
+        let _ = writeln!(
+            out,
+            "ghostctl_nft_hits_sample_timestamp_seconds {}",
+            at.timestamp()
+        );
+    }
+    out
+}
+
+/// Replace a textfile-collector file atomically, so node_exporter never
+/// reads a half-written file.
+pub fn write_textfile(path: &Path, content: &str) -> Result<()> {
+    if path.extension().is_none_or(|e| e != "prom") {
+        bail!("{}: node_exporter only reads *.prom files", path.display());
+    }
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".ghostctl-nft-")
+        .tempfile_in(dir)
+        .with_context(|| format!("cannot write in {}", dir.display()))?;
+    tmp.write_all(content.as_bytes())?;
+    tmp.flush()?;
+    tmp.persist(path)
+        .with_context(|| format!("failed to replace {}", path.display()))?;
+    // tempfile creates 0600; node_exporter usually runs as its own user.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o644))?;
+    }
+    Ok(())
+}
+
+/// systemd service and timer running `firewall hits sample` as root.
+pub fn timer_units(exe: &Path, interval: &str, textfile: Option<&Path>) -> (String, String) {
+    let mut exec = format!(
+        "{} firewall hits sample --log {SYSTEM_LOG}",
+        crate::utils::shell_quote(&exe.to_string_lossy())
+    );
+    if let Some(textfile) = textfile {
+        exec.push_str(&format!(
+            " --textfile {}",
+            crate::utils::shell_quote(&textfile.to_string_lossy())
+        ));
+    }
+    let service = format!(
+        "[Unit]\nDescription=Sample nftables rule counters for ghostctl\n\n\
+         [Service]\nType=oneshot\nExecStart={exec}\n"
+    );
+    let timer = format!(
+        "[Unit]\nDescription=Sample nftables rule counters every {interval}\n\n\
+         [Timer]\nOnBootSec=5min\nOnUnitActiveSec={interval}\nAccuracySec=1min\n\n\
+         [Install]\nWantedBy=timers.target\n"
+    );
+    (service, timer)
+}
+
+pub fn install_timer(
+    runner: &dyn CommandRunner,
+    interval: &str,
+    textfile: Option<&Path>,
+) -> Result<()> {
+    if interval.is_empty()
+        || !interval
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == ' ')
+    {
+        bail!("invalid interval '{interval}' (e.g. 15min, 1h)");
+    }
+    let exe = std::env::current_exe().context("cannot locate the ghostctl binary")?;
+    let (service, timer) = timer_units(&exe, interval, textfile);
+    for (ext, content) in [("service", &service), ("timer", &timer)] {
+        let path = format!("/etc/systemd/system/{TIMER_UNIT}.{ext}");
+        crate::utils::sudo_write_file(&path, content)
+            .with_context(|| format!("failed to write {path}"))?;
+    }
+    for args in [
+        &["daemon-reload"][..],
+        &["enable", "--now", &format!("{TIMER_UNIT}.timer")][..],
+    ] {
+        let out = runner.run_sudo("systemctl", args)?;
+        if !out.success {
+            bail!("systemctl {} failed: {}", args.join(" "), out.stderr.trim());
+        }
+    }
+    Ok(())
+}
+
+pub fn remove_timer(runner: &dyn CommandRunner) -> Result<()> {
+    let timer = format!("{TIMER_UNIT}.timer");
+    // Already-disabled units are fine.
+    let _ = runner.run_sudo("systemctl", &["disable", "--now", &timer])?;
+    let service = format!("/etc/systemd/system/{TIMER_UNIT}.service");
+    let timer_path = format!("/etc/systemd/system/{timer}");
+    let out = runner.run_sudo("rm", &["-f", &service, &timer_path])?;
+    if !out.success {
+        bail!("failed to remove the units: {}", out.stderr.trim());
+    }
+    runner.run_sudo("systemctl", &["daemon-reload"])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    fn rule(handle: u64, expr: &str) -> String {
+        format!(
+            r#"{{"rule": {{"family": "inet", "table": "ghostctl", "chain": "input", "handle": {handle}, "expr": [{expr}]}}}}"#
+        )
+    }
+
+    fn port(p: u16) -> String {
+        format!(
+            r#"{{"match": {{"op": "==", "left": {{"payload": {{"protocol": "tcp", "field": "dport"}}}}, "right": {p}}}}}"#
+        )
+    }
+
+    fn counter(n: u64) -> String {
+        format!(r#"{{"counter": {{"packets": {n}, "bytes": {}}}}}"#, n * 100)
+    }
+
+    fn nftables(rules: &[String]) -> String {
+        format!(
+            r#"{{"nftables": [{{"table": {{"family": "inet", "name": "ghostctl"}}}}, {}]}}"#,
+            rules.join(", ")
+        )
+    }
+
+    /// A ruleset with `ssh` and `web` accepts, a logged drop and an uncounted rule.
+    fn ruleset(ssh: u64, web: u64, dns: u64) -> String {
+        let rules = [
+            rule(
+                4,
+                &format!(r#"{},{},{{"accept": null}}"#, port(22), counter(ssh)),
+            ),
+            rule(
+                5,
+                &format!(r#"{},{},{{"accept": null}}"#, port(53), counter(dns)),
+            ),
+            rule(
+                6,
+                &format!(r#"{},{},{{"accept": null}}"#, port(443), counter(web)),
+            ),
+            rule(
+                7,
+                &format!(
+                    r#"{},{{"log": {{"prefix": "x "}}}},{{"drop": null}}"#,
+                    counter(0)
+                ),
+            ),
+            rule(8, r#"{"accept": null}"#),
+        ];
+        nftables(&rules)
+    }
+
+    fn at(day: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+            + Duration::days(day)
+    }
+
+    #[test]
+    fn parses_counters_and_plain_verdicts() {
+        let sample = Sample::parse(&ruleset(10, 20, 0), at(0)).unwrap();
+        assert_eq!(sample.rules.len(), 4);
+        assert_eq!(sample.uncounted, 1);
+        assert_eq!(sample.rules[0].text, "tcp dport 22 counter accept");
+        assert_eq!(sample.rules[0].packets, 10);
+        assert_eq!(sample.rules[0].bytes, 1000);
+        assert_eq!(sample.rules[0].plain_verdict.as_deref(), Some("accept"));
+        assert_eq!(sample.rules[3].plain_verdict, None);
+    }
+
+    #[test]
+    fn report_finds_dead_hot_and_misordered_rules() {
+        let samples = vec![
+            Sample::parse(&ruleset(0, 0, 0), at(0)).unwrap(),
+            Sample::parse(&ruleset(5, 1_000, 0), at(4)).unwrap(),
+            // Table reloaded: counters restart.
+            Sample::parse(&ruleset(3, 500, 0), at(8)).unwrap(),
+        ];
+        let report = report(&samples, at(8), &ReportOptions { days: 7, top: 5 });
+        assert_eq!(report.samples, 3);
+        let hottest: Vec<(u64, u64)> = report
+            .hottest
+            .iter()
+            .map(|s| (s.handle, s.packets))
+            .collect();
+        assert_eq!(hottest, [(6, 1_500), (4, 8)]);
+        let dead: Vec<u64> = report.zero_hit.iter().map(|s| s.handle).collect();
+        assert_eq!(dead, [5, 7]);
+        assert_eq!(report.suggestions.len(), 1);
+        let s = &report.suggestions[0];
+        assert_eq!((s.handle, s.before_handle, s.passes), (6, 4, 2));
+        assert!(report.notes.iter().any(|n| n.contains("no counter")));
+    }
+
+    #[test]
+    fn hot_rules_do_not_jump_uncounted_rules() {
+        let ruleset = |hot: u64| {
+            nftables(&[
+                rule(
+                    4,
+                    &format!(r#"{},{},{{"accept": null}}"#, port(22), counter(1)),
+                ),
+                rule(5, &format!(r#"{},{{"drop": null}}"#, port(443))),
+                rule(
+                    6,
+                    &format!(r#"{},{},{{"accept": null}}"#, port(443), counter(hot)),
+                ),
+            ])
+        };
+        let samples = vec![
+            Sample::parse(&ruleset(0), at(0)).unwrap(),
+            Sample::parse(&ruleset(1_000), at(7)).unwrap(),
+        ];
+        assert_eq!(samples[1].rules[1].position, Some(2));
+        let report = report(&samples, at(7), &ReportOptions { days: 7, top: 5 });
+        assert_eq!(report.hottest[0].handle, 6);
+        assert!(report.suggestions.is_empty(), "{:?}", report.suggestions);
+    }
+
+    #[test]
+    fn short_history_reports_no_dead_rules() {
+        let samples = vec![
+            Sample::parse(&ruleset(0, 0, 0), at(0)).unwrap(),
+            Sample::parse(&ruleset(0, 0, 0), at(2)).unwrap(),
+        ];
+        let report = report(&samples, at(2), &ReportOptions { days: 7, top: 5 });
+        assert!(report.zero_hit.is_empty());
+        assert!(
+            report
+                .notes
+                .iter()
+                .any(|n| n.contains("history covers 2.0 of 7 days"))
+        );
+    }
+
+    #[test]
+    fn log_appends_loads_and_prunes() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = HitLog::new(dir.path().join("hits.jsonl"));
+        assert!(log.load().unwrap().is_empty());
+        for day in [0, 5, 10] {
+            log.append(&Sample::parse(&ruleset(day as u64, 0, 0), at(day)).unwrap())
+                .unwrap();
+        }
+        assert_eq!(log.load().unwrap().len(), 3);
+        assert_eq!(log.prune(Duration::days(7), at(10)).unwrap(), 1);
+        let kept = log.load().unwrap();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].rules[0].packets, 5);
+    }
+
+    #[test]
+    fn prometheus_textfile_format() {
+        let mut sample = Sample::parse(&ruleset(10, 20, 0), at(0)).unwrap();
+        sample.rules[0].text = "tcp dport 22 counter accept comment \"a\\b\"".to_string();
+        let text = prometheus(&sample);
+        assert!(text.contains("# TYPE ghostctl_nft_rule_packets_total counter\n"));
+        assert!(text.contains(
+            "ghostctl_nft_rule_packets_total{family=\"inet\",table=\"ghostctl\",chain=\"input\",handle=\"4\",rule=\"tcp dport 22 counter accept comment \\\"a\\\\b\\\"\"} 10\n"
+        ));
+        assert!(text.contains("ghostctl_nft_rule_bytes_total{family=\"inet\",table=\"ghostctl\",chain=\"input\",handle=\"6\",rule=\"tcp dport 443 counter accept\"} 2000\n"));
+        assert!(text.contains("ghostctl_nft_rules_uncounted 1\n"));
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(write_textfile(&dir.path().join("nft.txt"), &text).is_err());
+        let path = dir.path().join("ghostctl_nft.prom");
+        write_textfile(&path, &text).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+    }
+
+    #[test]
+    fn sample_reads_nft_json() {
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "nft",
+            &["-j", "list", "ruleset"],
+            CommandResult::ok(ruleset(1, 2, 3)),
+        );
+        let sample = sample(&mock).unwrap();
+        assert_eq!(sample.rules.len(), 4);
+
+        let (service, timer) = timer_units(
+            Path::new("/usr/bin/ghostctl"),
+            "15min",
+            Some(Path::new("/var/lib/node_exporter/ghostctl_nft.prom")),
+        );
+        assert!(service.contains(
+            "ExecStart=/usr/bin/ghostctl firewall hits sample --log /var/lib/ghostctl/firewall-hits.jsonl --textfile /var/lib/node_exporter/ghostctl_nft.prom\n"
+        ));
+        assert!(timer.contains("OnUnitActiveSec=15min\n"));
+    }
+}
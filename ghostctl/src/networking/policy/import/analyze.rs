@@ -0,0 +1,655 @@
+//! Duplicate, shadowed and unreachable rule detection on imported tables.
+//!
+//! Rules are compared through a [`Selector`]: the set of packets a rule's
+//! matches select, kept per dimension (family, protocol, addresses, ports,
+//! interfaces, conntrack state). A rule is shadowed when an earlier terminal
+//! rule in the same chain selects a superset of its packets. Matches the
+//! model cannot reason about (custom expressions, sets, marks) only compare
+//! equal to themselves, so the analysis errs towards silence rather than
+//! reporting rules that can still be hit.
+
+use super::Imported;
+use crate::networking::nftables_enterprise::{
+    AddressMatch, InterfaceMatch, Match, NftChain, NftRule, NftTable, PortMatch, PortSpec,
+    Protocol, RuleVerdict, Statement, TableFamily,
+};
+use crate::networking::policy::render;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// Same rule text as an earlier rule in the chain.
+    Duplicate,
+    /// An earlier terminal rule matches every packet this one would.
+    Shadowed,
+    /// Nothing reaches the rule or chain at all.
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// `family name`, e.g. `ip filter`.
+    pub table: String,
+    pub chain: String,
+    /// 1-based position in the chain; `None` for whole-chain findings.
+    pub rule: Option<usize>,
+    pub text: Option<String>,
+    pub origin: Option<String>,
+    pub detail: String,
+}
+
+/// Rules that cannot be rendered are moved to the untranslated list with
+/// the render error, and the rest of the chain is still analyzed.
+pub fn analyze(imported: &mut Imported) -> Vec<Finding> {
+    set_aside_unrenderable(imported);
+    let mut findings = Vec::new();
+    for (t, table) in imported.tables.iter().enumerate() {
+        let table_name = format!("{} {}", render::family_name(&table.family), table.name);
+        for (c, chain) in table.chains.iter().enumerate() {
+            let texts: Vec<String> = chain
+                .rules
+                .iter()
+                .map(|r| render::render_rule(table, r).unwrap_or_default())
+                .collect();
+            let selectors: Vec<Selector> =
+                chain.rules.iter().map(|r| Selector::of(table, r)).collect();
+            for i in 0..chain.rules.len() {
+                let found =
+                    check_rule(chain, &texts, &selectors, i).map(|(kind, detail)| Finding {
+                        kind,
+                        table: table_name.clone(),
+                        chain: chain.name.clone(),
+                        rule: Some(i + 1),
+                        text: Some(texts[i].clone()),
+                        origin: imported.origin(t, c, i).map(str::to_string),
+                        detail,
+                    });
+                findings.extend(found);
+            }
+        }
+        for chain in unreachable_chains(table) {
+            findings.push(Finding {
+                kind: FindingKind::Unreachable,
+                table: table_name.clone(),
+                chain: chain.name.clone(),
+                rule: None,
+                text: None,
+                origin: None,
+                detail: "no base chain jumps to this chain".to_string(),
+            });
+        }
+    }
+    findings
+}
+
+fn set_aside_unrenderable(imported: &mut Imported) {
+    for t in 0..imported.tables.len() {
+        for c in 0..imported.tables[t].chains.len() {
+            let mut r = 0;
+            while r < imported.tables[t].chains[c].rules.len() {
+                let table = &imported.tables[t];
+                let Err(e) = render::render_rule(table, &table.chains[c].rules[r]) else {
+                    r += 1;
+                    continue;
+                };
+                let place = format!(
+                    "{} {} {} rule {}",
+                    render::family_name(&table.family),
+                    table.name,
+                    table.chains[c].name,
+                    r + 1
+                );
+                let origin = imported.remove(t, c, r);
+                imported.untranslated(
+                    origin.unwrap_or_else(|| "generated".to_string()),
+                    &place,
+                    format!("cannot be rendered as nft: {e:#}"),
+                );
+            }
+        }
+    }
+}
+
+pub fn print_finding(finding: &Finding) {
+    let kind = match finding.kind {
+        FindingKind::Duplicate => "duplicate",
+        FindingKind::Shadowed => "shadowed",
+        FindingKind::Unreachable => "unreachable",
+    };
+    let place = match finding.rule {
+        Some(n) => format!("{} {} rule {n}", finding.table, finding.chain),
+        None => format!("{} chain {}", finding.table, finding.chain),
+    };
+    println!("  {kind}: {place}");
+    if let Some(text) = &finding.text {
+        println!("      {text}");
+    }
+    match &finding.origin {
+        Some(origin) => println!("      {} (from {origin})", finding.detail),
+        None => println!("      {}", finding.detail),
+    }
+}
+
+fn check_rule(
+    chain: &NftChain,
+    texts: &[String],
+    selectors: &[Selector],
+    i: usize,
+) -> Option<(FindingKind, String)> {
+    for j in 0..i {
+        let earlier = &chain.rules[j];
+        if is_terminal(earlier) && earlier.expression.matches.is_empty() {
+            return Some((
+                FindingKind::Unreachable,
+                format!("rule {} ends the chain unconditionally", j + 1),
+            ));
+        }
+    }
+    if let Some(j) = texts[..i].iter().position(|t| *t == texts[i]) {
+        return Some((FindingKind::Duplicate, format!("same as rule {}", j + 1)));
+    }
+    let j = (0..i).find(|&j| is_terminal(&chain.rules[j]) && selectors[j].covers(&selectors[i]))?;
+    let mut detail = format!("rule {} matches every packet first: {}", j + 1, texts[j]);
+    let (earlier, later) = (verdict_name(&chain.rules[j]), verdict_name(&chain.rules[i]));
+    if earlier != later && is_terminal(&chain.rules[i]) {
+        detail.push_str(&format!(" ({earlier} wins over {later})"));
+    }
+    Some((FindingKind::Shadowed, detail))
+}
+
+/// Whether a matching packet leaves the chain at this rule.
+fn is_terminal(rule: &NftRule) -> bool {
+    let nat = rule.expression.statements.iter().any(|s| {
+        matches!(
+            s,
+            Statement::Masquerade { .. }
+                | Statement::Snat { .. }
+                | Statement::Dnat { .. }
+                | Statement::Redirect { .. }
+                | Statement::Queue { .. }
+        )
+    });
+    nat || !matches!(
+        rule.verdict,
+        RuleVerdict::Continue | RuleVerdict::Jump { .. }
+    )
+}
+
+fn verdict_name(rule: &NftRule) -> String {
+    match &rule.verdict {
+        RuleVerdict::Accept => "accept".to_string(),
+        RuleVerdict::Drop => "drop".to_string(),
+        RuleVerdict::Reject { .. } => "reject".to_string(),
+        RuleVerdict::Queue { .. } => "queue".to_string(),
+        RuleVerdict::Continue => "nat".to_string(),
+        RuleVerdict::Return => "return".to_string(),
+        RuleVerdict::Jump { target } => format!("jump {target}"),
+        RuleVerdict::Goto { target } => format!("goto {target}"),
+    }
+}
+
+/// Regular chains no base chain reaches through jump or goto.
+fn unreachable_chains(table: &NftTable) -> Vec<&NftChain> {
+    let mut reached: HashSet<&str> = HashSet::new();
+    let mut queue: Vec<&NftChain> = table.chains.iter().filter(|c| c.hook.is_some()).collect();
+    while let Some(chain) = queue.pop() {
+        if !reached.insert(chain.name.as_str()) {
+            continue;
+        }
+        for rule in &chain.rules {
+            if let RuleVerdict::Jump { target } | RuleVerdict::Goto { target } = &rule.verdict
+                && let Some(next) = table.chains.iter().find(|c| c.name == *target)
+            {
+                queue.push(next);
+            }
+        }
+    }
+    table
+        .chains
+        .iter()
+        .filter(|c| !reached.contains(c.name.as_str()))
+        .collect()
+}
+
+/// Inclusive ranges of a dimension, optionally negated.
+#[derive(Debug, Clone, PartialEq)]
+struct Ranges {
+    ranges: Vec<(u128, u128)>,
+    negated: bool,
+}
+
+impl Ranges {
+    fn contains_range(&self, (lo, hi): (u128, u128)) -> bool {
+        self.ranges.iter().any(|&(a, b)| a <= lo && hi <= b)
+    }
+
+    fn overlaps(&self, (lo, hi): (u128, u128)) -> bool {
+        self.ranges.iter().any(|&(a, b)| a <= hi && lo <= b)
+    }
+
+    /// Every value `other` selects is selected by `self`.
+    fn covers(&self, other: &Ranges) -> bool {
+        match (self.negated, other.negated) {
+            (false, false) => other.ranges.iter().all(|&r| self.contains_range(r)),
+            (true, false) => other.ranges.iter().all(|&r| !self.overlaps(r)),
+            // Excluding less than `other` excludes selects more.
+            (true, true) => self.ranges.iter().all(|&r| other.contains_range(r)),
+            (false, true) => false,
+        }
+    }
+}
+
+/// Interface names with nft's trailing `*` wildcard.
+#[derive(Debug, Clone, PartialEq)]
+struct Interfaces {
+    names: Vec<String>,
+    negated: bool,
+}
+
+impl Interfaces {
+    fn matches(pattern: &str, name: &str) -> bool {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => pattern == name,
+        }
+    }
+
+    fn covers(&self, other: &Interfaces) -> bool {
+        match (self.negated, other.negated) {
+            (false, false) => other
+                .names
+                .iter()
+                .all(|n| self.names.iter().any(|p| Self::matches(p, n))),
+            (true, false) => other
+                .names
+                .iter()
+                .all(|n| !n.ends_with('*') && !self.names.iter().any(|p| Self::matches(p, n))),
+            (true, true) => self
+                .names
+                .iter()
+                .all(|n| other.names.iter().any(|p| Self::matches(p, n))),
+            (false, true) => false,
+        }
+    }
+}
+
+/// The packets a rule selects, one constraint per dimension.
+#[derive(Debug, Clone, Default)]
+struct Selector {
+    /// Address families (4, 6) the rule can match.
+    families: BTreeSet<u8>,
+    protocols: Option<BTreeSet<String>>,
+    /// Keyed by field, with the address family for addresses (`saddr4`).
+    ranges: BTreeMap<String, Ranges>,
+    iif: Option<Interfaces>,
+    states: Option<BTreeSet<&'static str>>,
+    icmp: Option<u8>,
+    /// Matches compared only for equality.
+    opaque: BTreeSet<String>,
+}
+
+impl Selector {
+    fn of(table: &NftTable, rule: &NftRule) -> Self {
+        let mut selector = Selector {
+            families: match table.family {
+                TableFamily::Ip => [4].into(),
+                TableFamily::Ip6 => [6].into(),
+                _ => [4, 6].into(),
+            },
+            ..Default::default()
+        };
+        for m in &rule.expression.matches {
+            selector.add(m);
+        }
+        selector
+    }
+
+    fn restrict_family(&mut self, family: u8) {
+        self.families.retain(|f| *f == family);
+    }
+
+    fn add(&mut self, m: &Match) {
+        match m {
+            Match::Protocol { protocol } => match render::protocol_name(protocol) {
+                Some(name) => {
+                    match protocol {
+                        Protocol::Icmp => self.restrict_family(4),
+                        Protocol::Icmpv6 => self.restrict_family(6),
+                        _ => {}
+                    }
+                    let set = BTreeSet::from([name.to_string()]);
+                    self.protocols = Some(match self.protocols.take() {
+                        Some(existing) => existing.intersection(&set).cloned().collect(),
+                        None => set,
+                    });
+                }
+                None if matches!(protocol, Protocol::Any) => {}
+                None => {
+                    self.opaque.insert(format!("{m:?}"));
+                }
+            },
+            Match::SourceAddress { address } => self.address("saddr", address, m),
+            Match::DestinationAddress { address } => self.address("daddr", address, m),
+            Match::SourcePort { port } => self.port("sport", port, m),
+            Match::DestinationPort { port } => self.port("dport", port, m),
+            Match::Interface { interface } => self.interface(interface, m),
+            Match::ConnectionState { states } => {
+                let set: BTreeSet<&'static str> =
+                    states.iter().map(render::ct_state_name).collect();
+                self.states = Some(match self.states.take() {
+                    Some(existing) => existing.intersection(&set).copied().collect(),
+                    None => set,
+                });
+            }
+            Match::IcmpType { icmp_type } if self.icmp.is_none() => self.icmp = Some(*icmp_type),
+            Match::Custom { expression } => {
+                match expression.as_str() {
+                    "meta nfproto ipv4" => return self.restrict_family(4),
+                    "meta nfproto ipv6" => return self.restrict_family(6),
+                    e if e.starts_with("icmp ") => self.restrict_family(4),
+                    e if e.starts_with("icmpv6 ") => self.restrict_family(6),
+                    _ => {}
+                }
+                self.opaque.insert(expression.clone());
+            }
+            other => {
+                self.opaque.insert(format!("{other:?}"));
+            }
+        }
+    }
+
+    fn address(&mut self, key: &str, address: &AddressMatch, m: &Match) {
+        let parsed: Option<Vec<(u8, (u128, u128))>> =
+            address.addresses.iter().map(|a| address_range(a)).collect();
+        let Some(parsed) = parsed.filter(|p| !p.is_empty()) else {
+            self.opaque.insert(format!("{m:?}"));
+            return;
+        };
+        let families: BTreeSet<u8> = parsed.iter().map(|(f, _)| *f).collect();
+        if families.len() > 1 {
+            self.opaque.insert(format!("{m:?}"));
+            return;
+        }
+        let family = *families.iter().next().unwrap_or(&4);
+        if !address.negated {
+            self.restrict_family(family);
+        }
+        self.insert_ranges(
+            &format!("{key}{family}"),
+            parsed.into_iter().map(|(_, r)| r).collect(),
+            address.negated,
+            m,
+        );
+    }
+
+    fn port(&mut self, key: &str, port: &PortMatch, m: &Match) {
+        let parsed: Option<Vec<(u128, u128)>> = port
+            .ports
+            .iter()
+            .map(|p| match p {
+                PortSpec::Single(n) => Some((*n as u128, *n as u128)),
+                PortSpec::Range(lo, hi) => Some((*lo as u128, *hi as u128)),
+                PortSpec::Set(_) => None,
+            })
+            .collect();
+        match parsed {
+            Some(ranges) => self.insert_ranges(key, ranges, port.negated, m),
+            None => {
+                self.opaque.insert(format!("{m:?}"));
+            }
+        }
+    }
+
+    fn insert_ranges(&mut self, key: &str, ranges: Vec<(u128, u128)>, negated: bool, m: &Match) {
+        if self.ranges.contains_key(key) {
+            // A second match on the same field; compare it as-is.
+            self.opaque.insert(format!("{m:?}"));
+            return;
+        }
+        self.ranges
+            .insert(key.to_string(), Ranges { ranges, negated });
+    }
+
+    fn interface(&mut self, interface: &InterfaceMatch, m: &Match) {
+        if self.iif.is_some() {
+            self.opaque.insert(format!("{m:?}"));
+            return;
+        }
+        self.iif = Some(Interfaces {
+            names: interface.interfaces.clone(),
+            negated: interface.negated,
+        });
+    }
+
+    /// Whether every packet `other` selects is also selected by `self`.
+    fn covers(&self, other: &Selector) -> bool {
+        if !other.families.is_subset(&self.families) {
+            return false;
+        }
+        within(&self.protocols, &other.protocols, |a, b| b.is_subset(a))
+            && within(&self.states, &other.states, |a, b| b.is_subset(a))
+            && within(&self.iif, &other.iif, Interfaces::covers)
+            && within(&self.icmp, &other.icmp, |a, b| a == b)
+            && self.ranges.iter().all(|(key, mine)| {
+                other
+                    .ranges
+                    .get(key)
+                    .is_some_and(|theirs| mine.covers(theirs))
+            })
+            && self.opaque.is_subset(&other.opaque)
+    }
+}
+
+/// An unconstrained dimension covers anything; a constrained one never
+/// covers an unconstrained one.
+fn within<T>(mine: &Option<T>, theirs: &Option<T>, covers: impl Fn(&T, &T) -> bool) -> bool {
+    match (mine, theirs) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(a), Some(b)) => covers(a, b),
+    }
+}
+
+/// `(family, (first, last))` of an address, CIDR or `a-b` range.
+fn address_range(text: &str) -> Option<(u8, (u128, u128))> {
+    let value = |ip: IpAddr| match ip {
+        IpAddr::V4(v4) => (4, u32::from(v4) as u128),
+        IpAddr::V6(v6) => (6, u128::from(v6)),
+    };
+    if let Some((lo, hi)) = text.split_once('-') {
+        let (f1, lo) = value(lo.trim().parse().ok()?);
+        let (f2, hi) = value(hi.trim().parse().ok()?);
+        return (f1 == f2 && lo <= hi).then_some((f1, (lo, hi)));
+    }
+    let net: ipnet::IpNet = match text.parse() {
+        Ok(net) => net,
+        Err(_) => ipnet::IpNet::from(text.parse::<IpAddr>().ok()?),
+    };
+    let (family, lo) = value(net.network());
+    let (_, hi) = value(net.broadcast());
+    Some((family, (lo, hi)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::nftables_enterprise::{ChainType, ConntrackState, Hook};
+    use crate::networking::policy::import::Source;
+    use crate::networking::policy::spec;
+
+    fn tcp_port(port: u16) -> Vec<Match> {
+        vec![
+            Match::Protocol {
+                protocol: Protocol::Tcp,
+            },
+            Match::DestinationPort {
+                port: PortMatch {
+                    ports: vec![PortSpec::Single(port)],
+                    negated: false,
+                },
+            },
+        ]
+    }
+
+    fn saddr(addr: &str, mut rest: Vec<Match>) -> Vec<Match> {
+        rest.insert(
+            0,
+            Match::SourceAddress {
+                address: AddressMatch {
+                    addresses: vec![addr.to_string()],
+                    negated: false,
+                },
+            },
+        );
+        rest
+    }
+
+    fn findings(rules: Vec<NftRule>, extra_chain: bool) -> Vec<Finding> {
+        let mut imported = Imported::new(Source::Iptables);
+        let t = imported.table(TableFamily::Ip, "filter");
+        let c = imported.chain(
+            t,
+            spec::base_chain("input", ChainType::Filter, Hook::Input, 0),
+        );
+        for (i, rule) in rules.into_iter().enumerate() {
+            imported.push(t, c, rule, Some(format!("line {}", i + 1)));
+        }
+        if extra_chain {
+            imported.chain(t, spec::regular_chain("orphan"));
+        }
+        analyze(&mut imported)
+    }
+
+    #[test]
+    fn finds_duplicates_and_shadowed_rules() {
+        let found = findings(
+            vec![
+                spec::rule(saddr("10.0.0.0/8", tcp_port(22)), RuleVerdict::Accept),
+                spec::rule(saddr("10.1.0.0/16", tcp_port(22)), RuleVerdict::Drop),
+                spec::rule(saddr("192.168.0.0/16", tcp_port(22)), RuleVerdict::Accept),
+                spec::rule(saddr("10.0.0.0/8", tcp_port(22)), RuleVerdict::Accept),
+                spec::rule(tcp_port(22), RuleVerdict::Accept),
+            ],
+            false,
+        );
+        let summary: Vec<(FindingKind, Option<usize>)> =
+            found.iter().map(|f| (f.kind, f.rule)).collect();
+        assert_eq!(
+            summary,
+            [
+                (FindingKind::Shadowed, Some(2)),
+                (FindingKind::Duplicate, Some(4))
+            ]
+        );
+        assert!(found[0].detail.contains("accept wins over drop"));
+        assert_eq!(found[0].origin.as_deref(), Some("line 2"));
+    }
+
+    #[test]
+    fn conntrack_and_interfaces_are_compared_as_sets() {
+        let state = |states: Vec<ConntrackState>| Match::ConnectionState { states };
+        let iif = |name: &str| Match::Interface {
+            interface: InterfaceMatch {
+                interfaces: vec![name.to_string()],
+                negated: false,
+            },
+        };
+        let found = findings(
+            vec![
+                spec::rule(
+                    vec![state(vec![
+                        ConntrackState::Established,
+                        ConntrackState::Related,
+                    ])],
+                    RuleVerdict::Accept,
+                ),
+                spec::rule(
+                    vec![state(vec![ConntrackState::Established])],
+                    RuleVerdict::Accept,
+                ),
+                spec::rule(vec![state(vec![ConntrackState::New])], RuleVerdict::Accept),
+                spec::rule(vec![iif("eth*")], RuleVerdict::Drop),
+                spec::rule(
+                    [vec![iif("eth1")], tcp_port(80)].concat(),
+                    RuleVerdict::Accept,
+                ),
+                spec::rule(vec![iif("wg0")], RuleVerdict::Accept),
+            ],
+            false,
+        );
+        let shadowed: Vec<Option<usize>> = found.iter().map(|f| f.rule).collect();
+        assert_eq!(shadowed, [Some(2), Some(5)]);
+    }
+
+    #[test]
+    fn unconditional_verdicts_and_orphan_chains_are_unreachable() {
+        let found = findings(
+            vec![
+                spec::rule(tcp_port(22), RuleVerdict::Accept),
+                spec::rule(Vec::new(), RuleVerdict::Drop),
+                spec::rule(tcp_port(80), RuleVerdict::Accept),
+            ],
+            true,
+        );
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].kind, FindingKind::Unreachable);
+        assert_eq!(found[0].rule, Some(3));
+        assert_eq!(found[1].chain, "orphan");
+        assert_eq!(found[1].rule, None);
+    }
+
+    #[test]
+    fn unrenderable_rules_become_untranslated() {
+        let mut bad = spec::rule(tcp_port(80), RuleVerdict::Accept);
+        bad.comment = Some("say \"hi\"".to_string());
+        let mut imported = Imported::new(Source::Iptables);
+        let t = imported.table(TableFamily::Ip, "filter");
+        let c = imported.chain(
+            t,
+            spec::base_chain("input", ChainType::Filter, Hook::Input, 0),
+        );
+        imported.push(
+            t,
+            c,
+            spec::rule(tcp_port(22), RuleVerdict::Accept),
+            Some("line 1".into()),
+        );
+        imported.push(t, c, bad, Some("line 2".into()));
+        imported.push(
+            t,
+            c,
+            spec::rule(tcp_port(22), RuleVerdict::Accept),
+            Some("line 3".into()),
+        );
+        let found = analyze(&mut imported);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, FindingKind::Duplicate);
+        assert_eq!(found[0].origin.as_deref(), Some("line 3"));
+        assert_eq!(imported.translated(), 2);
+        assert_eq!(imported.untranslated.len(), 1);
+        assert_eq!(imported.untranslated[0].origin, "line 2");
+        assert!(imported.untranslated[0].reason.contains("rule comment"));
+    }
+
+    #[test]
+    fn address_ranges_parse() {
+        assert_eq!(
+            address_range("10.0.0.0/8"),
+            Some((4, (0x0a00_0000, 0x0aff_ffff)))
+        );
+        assert_eq!(
+            address_range("10.0.0.1"),
+            Some((4, (0x0a00_0001, 0x0a00_0001)))
+        );
+        assert_eq!(
+            address_range("10.0.0.1-10.0.0.9"),
+            Some((4, (0x0a00_0001, 0x0a00_0009)))
+        );
+        assert_eq!(address_range("::1").map(|(f, _)| f), Some(6));
+        assert_eq!(address_range("@blocklist"), None);
+    }
+}
@@ -0,0 +1,1031 @@
+//! firewalld zone XML into the model.
+//!
+//! Each active zone (bound to an interface or source, or the default zone)
+//! becomes a `zone_<name>` chain in one `inet zones` table. The input chain
+//! dispatches to it the way firewalld does: source bindings first, then
+//! interfaces, then the default zone. Inside a zone chain the order follows
+//! firewalld's: rich rules with a negative priority, log, deny (including
+//! ICMP blocks), allow (rich accept rules, services, ports), rich rules with
+//! a positive priority, and finally the zone target.
+
+use super::{Imported, Source, iptables};
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, ConntrackState, Hook, InterfaceMatch, LogLevel, Match,
+    NftRule, PortMatch, PortSpec, Protocol, RejectType, RuleVerdict, Statement, TableFamily,
+};
+use crate::networking::policy::{render, spec};
+use crate::networking::xml::{self, Element};
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What a firewalld service opens.
+#[derive(Debug, Clone, Default)]
+pub struct Service {
+    pub ports: Vec<(Protocol, PortSpec)>,
+    pub protocols: Vec<Protocol>,
+    pub source_ports: Vec<(Protocol, PortSpec)>,
+    pub destination_v4: Option<String>,
+    pub destination_v6: Option<String>,
+}
+
+/// Service definitions by name: a built-in table of common services,
+/// overridden by the XML files found on the host.
+#[derive(Debug, Clone)]
+pub struct ServiceCatalog {
+    services: BTreeMap<String, Service>,
+}
+
+const BUILTIN_SERVICES: &[(&str, &[(&str, &str)])] = &[
+    ("ssh", &[("tcp", "22")]),
+    ("http", &[("tcp", "80")]),
+    ("https", &[("tcp", "443")]),
+    ("http3", &[("udp", "443")]),
+    ("dns", &[("tcp", "53"), ("udp", "53")]),
+    ("dhcp", &[("udp", "67")]),
+    ("dhcpv6", &[("udp", "547")]),
+    ("dhcpv6-client", &[("udp", "546")]),
+    ("ntp", &[("udp", "123")]),
+    ("mdns", &[("udp", "5353")]),
+    ("cockpit", &[("tcp", "9090")]),
+    ("smtp", &[("tcp", "25")]),
+    ("smtps", &[("tcp", "465")]),
+    ("smtp-submission", &[("tcp", "587")]),
+    ("imaps", &[("tcp", "993")]),
+    ("pop3s", &[("tcp", "995")]),
+    ("ftp", &[("tcp", "21")]),
+    ("mysql", &[("tcp", "3306")]),
+    ("postgresql", &[("tcp", "5432")]),
+    ("redis", &[("tcp", "6379")]),
+    ("nfs", &[("tcp", "2049")]),
+    ("samba-client", &[("udp", "137"), ("udp", "138")]),
+    (
+        "samba",
+        &[
+            ("udp", "137"),
+            ("udp", "138"),
+            ("tcp", "139"),
+            ("tcp", "445"),
+        ],
+    ),
+    ("ldap", &[("tcp", "389")]),
+    ("ldaps", &[("tcp", "636")]),
+    ("kerberos", &[("tcp", "88"), ("udp", "88")]),
+    ("openvpn", &[("udp", "1194")]),
+    ("wireguard", &[("udp", "51820")]),
+    ("rdp", &[("tcp", "3389")]),
+    ("vnc-server", &[("tcp", "5900-5903")]),
+    ("syslog", &[("udp", "514")]),
+    ("snmp", &[("udp", "161")]),
+    ("prometheus", &[("tcp", "9090")]),
+    ("kube-apiserver", &[("tcp", "6443")]),
+];
+
+impl ServiceCatalog {
+    pub fn builtin() -> Self {
+        let mut services = BTreeMap::new();
+        for (name, ports) in BUILTIN_SERVICES {
+            let mut service = Service::default();
+            for (proto, port) in *ports {
+                if let (Ok(proto), Ok(port)) = (port_protocol(proto), port_spec(port)) {
+                    service.ports.push((proto, port));
+                }
+            }
+            services.insert(name.to_string(), service);
+        }
+        if let Some(mdns) = services.get_mut("mdns") {
+            mdns.destination_v4 = Some("224.0.0.251".to_string());
+            mdns.destination_v6 = Some("ff02::fb".to_string());
+        }
+        Self { services }
+    }
+
+    /// Built-ins plus every readable `<service>` XML in `dirs` (later wins).
+    pub fn load(dirs: &[&Path]) -> Self {
+        let mut catalog = Self::builtin();
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_none_or(|e| e != "xml") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                    continue;
+                };
+                if let Ok(service) = std::fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|xml| parse_service(&xml))
+                {
+                    catalog.services.insert(name, service);
+                }
+            }
+        }
+        catalog
+    }
+
+    pub fn insert(&mut self, name: &str, service: Service) {
+        self.services.insert(name.to_string(), service);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Service> {
+        self.services.get(name)
+    }
+}
+
+pub fn parse_service(content: &str) -> Result<Service> {
+    let root = xml::parse(content)?;
+    if root.name != "service" {
+        bail!("not a firewalld service (<{}>)", root.name);
+    }
+    let mut service = Service::default();
+    for el in root.elements() {
+        match el.name.as_str() {
+            "port" => service.ports.push(port_element(el)?),
+            "source-port" => service.source_ports.push(port_element(el)?),
+            "protocol" => service.protocols.push(protocol(
+                el.attr("value").context("<protocol> without value")?,
+            )?),
+            "destination" => {
+                service.destination_v4 = el.attr("ipv4").map(str::to_string);
+                service.destination_v6 = el.attr("ipv6").map(str::to_string);
+            }
+            _ => {}
+        }
+    }
+    Ok(service)
+}
+
+/// Zone files by name; files in later directories replace earlier ones, the
+/// way `/etc/firewalld/zones` overrides `/usr/lib/firewalld/zones`.
+pub fn read_zone_dir(dirs: &[&Path]) -> Result<Vec<(String, String)>> {
+    let mut zones = BTreeMap::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != "xml") {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            zones.insert(name, content);
+        }
+    }
+    if zones.is_empty() {
+        bail!("no zone files found");
+    }
+    Ok(zones.into_iter().collect())
+}
+
+/// `DefaultZone=` from firewalld.conf, or firewalld's own default.
+pub fn configured_default_zone() -> String {
+    std::fs::read_to_string("/etc/firewalld/firewalld.conf")
+        .ok()
+        .and_then(|conf| {
+            conf.lines()
+                .find_map(|l| l.trim().strip_prefix("DefaultZone=").map(str::to_string))
+        })
+        .unwrap_or_else(|| "public".to_string())
+}
+
+struct Zone {
+    name: String,
+    file: String,
+    chain: String,
+    root: Element,
+    interfaces: Vec<String>,
+    sources: Vec<String>,
+}
+
+pub fn import(
+    zone_files: &[(String, String)],
+    services: &ServiceCatalog,
+    default_zone: &str,
+) -> Result<Imported> {
+    let mut imported = Imported::new(Source::Firewalld);
+    let mut zones = Vec::new();
+    for (name, content) in zone_files {
+        let file = format!("{name}.xml");
+        let root = xml::parse(content).with_context(|| format!("failed to parse {file}"))?;
+        if root.name != "zone" {
+            bail!("{file} is not a firewalld zone (<{}>)", root.name);
+        }
+        let interfaces: Vec<String> = root
+            .children_named("interface")
+            .filter_map(|e| e.attr("name").map(str::to_string))
+            .collect();
+        let sources: Vec<String> = root
+            .children_named("source")
+            .filter_map(|e| e.attr("address").map(str::to_string))
+            .collect();
+        let active = name == default_zone || !interfaces.is_empty() || !sources.is_empty();
+        if !active {
+            imported.notes.push(format!(
+                "zone {name} has no interfaces or sources and is not the default zone; skipped"
+            ));
+            continue;
+        }
+        zones.push(Zone {
+            name: name.clone(),
+            chain: format!(
+                "zone_{}",
+                name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+            ),
+            file,
+            root,
+            interfaces,
+            sources,
+        });
+    }
+    if !zones.iter().any(|z| z.name == default_zone) {
+        bail!("default zone '{default_zone}' not found");
+    }
+
+    let t = imported.table(TableFamily::Inet, "zones");
+    let mut input = spec::base_chain("input", ChainType::Filter, Hook::Input, 10);
+    input.policy = Some(ChainPolicy::Accept);
+    let input = imported.chain(t, input);
+    let mut preamble = vec![
+        spec::rule(
+            vec![Match::ConnectionState {
+                states: vec![ConntrackState::Established, ConntrackState::Related],
+            }],
+            RuleVerdict::Accept,
+        ),
+        spec::rule(
+            vec![Match::Interface {
+                interface: InterfaceMatch {
+                    interfaces: vec!["lo".to_string()],
+                    negated: false,
+                },
+            }],
+            RuleVerdict::Accept,
+        ),
+        spec::rule(
+            vec![Match::ConnectionState {
+                states: vec![ConntrackState::Invalid],
+            }],
+            RuleVerdict::Drop,
+        ),
+    ];
+    for rule in &mut preamble {
+        rule.comment = Some("firewalld".to_string());
+    }
+    imported.prepend(t, input, preamble);
+
+    for zone in &zones {
+        imported.chain(t, spec::regular_chain(&zone.chain));
+    }
+    // Source bindings win over interface bindings.
+    for zone in &zones {
+        for source in &zone.root.children_named("source").collect::<Vec<_>>() {
+            let origin = format!("{} source", zone.file);
+            let goto = RuleVerdict::Goto {
+                target: zone.chain.clone(),
+            };
+            let matched = if let Some(addr) = source.attr("address") {
+                render::canonical_address(addr).map(|a| {
+                    vec![Match::SourceAddress {
+                        address: AddressMatch {
+                            addresses: vec![a],
+                            negated: false,
+                        },
+                    }]
+                })
+            } else if let Some(mac) = source.attr("mac") {
+                Ok(vec![Match::Custom {
+                    expression: format!("ether saddr {}", mac.to_ascii_lowercase()),
+                }])
+            } else {
+                Err(anyhow::anyhow!("ipset sources have no translation"))
+            };
+            match matched {
+                Ok(matches) => imported.push(t, input, spec::rule(matches, goto), Some(origin)),
+                Err(e) => imported.untranslated(origin, &element_text(source), format!("{e:#}")),
+            }
+        }
+    }
+    for zone in &zones {
+        for iface in &zone.interfaces {
+            let rule = spec::rule(
+                vec![Match::Interface {
+                    interface: InterfaceMatch {
+                        interfaces: vec![iface.clone()],
+                        negated: false,
+                    },
+                }],
+                RuleVerdict::Goto {
+                    target: zone.chain.clone(),
+                },
+            );
+            imported.push(
+                t,
+                input,
+                rule,
+                Some(format!("{} interface {iface}", zone.file)),
+            );
+        }
+    }
+    let default = zones
+        .iter()
+        .find(|z| z.name == default_zone)
+        .unwrap_or_else(|| unreachable!());
+    imported.push(
+        t,
+        input,
+        spec::rule(
+            Vec::new(),
+            RuleVerdict::Goto {
+                target: default.chain.clone(),
+            },
+        ),
+        Some(format!("default zone {default_zone}")),
+    );
+
+    for zone in &zones {
+        import_zone(&mut imported, t, zone, services, zone.name == default_zone)?;
+    }
+    imported.notes.push(
+        "forwarding between zones (policies, <forward/>) is not imported; \
+         add forward rules before enabling routing"
+            .to_string(),
+    );
+    Ok(imported)
+}
+
+/// Which part of the zone chain a rich rule lands in.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Early,
+    Log,
+    Deny,
+    Allow,
+    Late,
+}
+
+/// Rules bound for a zone chain: (stage, priority, rules, origin).
+type Staged = (Stage, i32, Vec<NftRule>, String);
+
+fn import_zone(
+    imported: &mut Imported,
+    t: usize,
+    zone: &Zone,
+    services: &ServiceCatalog,
+    is_default: bool,
+) -> Result<()> {
+    let c = imported
+        .find_chain(t, &zone.chain)
+        .unwrap_or_else(|| unreachable!());
+    let mut staged: Vec<Staged> = Vec::new();
+    let mut rich_index = 0;
+    for el in zone.root.elements() {
+        let origin = |what: &str| format!("{} {what}", zone.file);
+        let result: Result<Option<Staged>> = match el.name.as_str() {
+            "short" | "description" | "interface" | "source" => Ok(None),
+            "service" => {
+                let name = el.attr("name").unwrap_or_default();
+                service_rules(services, name, RuleVerdict::Accept, &[], Some(name))
+                    .map(|rules| Some((Stage::Allow, 0, rules, origin(&format!("service {name}")))))
+            }
+            "port" => port_element(el).map(|(proto, port)| {
+                let rule = port_rule(&proto, &port, false, Vec::new(), RuleVerdict::Accept);
+                Some((
+                    Stage::Allow,
+                    0,
+                    vec![rule],
+                    origin(&format!("port {}", describe(el))),
+                ))
+            }),
+            "source-port" => port_element(el).map(|(proto, port)| {
+                let rule = port_rule(&proto, &port, true, Vec::new(), RuleVerdict::Accept);
+                Some((
+                    Stage::Allow,
+                    0,
+                    vec![rule],
+                    origin(&format!("source-port {}", describe(el))),
+                ))
+            }),
+            "protocol" => el
+                .attr("value")
+                .context("<protocol> without value")
+                .and_then(protocol)
+                .map(|protocol| {
+                    let rule = spec::rule(vec![Match::Protocol { protocol }], RuleVerdict::Accept);
+                    Some((
+                        Stage::Allow,
+                        0,
+                        vec![rule],
+                        origin(&format!("protocol {}", describe(el))),
+                    ))
+                }),
+            "icmp-block" => {
+                let name = el.attr("name").unwrap_or_default();
+                icmp_rules(name, &[], icmp_reject()).map(|rules| {
+                    Some((Stage::Deny, 0, rules, origin(&format!("icmp-block {name}"))))
+                })
+            }
+            "icmp-block-inversion" => {
+                Err(anyhow::anyhow!("icmp-block-inversion has no translation"))
+            }
+            "masquerade" => nat_masquerade(imported, t, zone, is_default).map(|()| None),
+            "forward-port" => match forward_port(imported, t, zone, is_default, el) {
+                Ok(()) => Ok(None),
+                Err(e) => Err(e),
+            },
+            "rule" => {
+                rich_index += 1;
+                rich_rule(el, services).map(|(stage, priority, rules)| {
+                    Some((
+                        stage,
+                        priority,
+                        rules,
+                        origin(&format!("rule {rich_index}")),
+                    ))
+                })
+            }
+            other => Err(anyhow::anyhow!("<{other}> has no translation")),
+        };
+        match result {
+            Ok(Some(entry)) => staged.push(entry),
+            Ok(None) => {}
+            Err(e) => {
+                let what = if el.name == "rule" {
+                    format!("{} rule {rich_index}", zone.file)
+                } else {
+                    format!("{} <{}>", zone.file, el.name)
+                };
+                imported.untranslated(what, &element_text(el), format!("{e:#}"));
+            }
+        }
+    }
+    // Stable: file order is kept within a stage and priority.
+    staged.sort_by_key(|(stage, priority, _, _)| (*stage, *priority));
+    let target = zone.root.attr("target").unwrap_or("default");
+    let verdict = match target {
+        "ACCEPT" => RuleVerdict::Accept,
+        "DROP" => RuleVerdict::Drop,
+        "default" | "%%REJECT%%" => icmp_reject(),
+        other => bail!("{}: unknown zone target '{other}'", zone.file),
+    };
+    let late = staged
+        .iter()
+        .position(|(stage, ..)| *stage == Stage::Late)
+        .unwrap_or(staged.len());
+    let late_rules = staged.split_off(late);
+    for (_, _, rules, origin) in staged {
+        for rule in rules {
+            imported.push(t, c, rule, Some(origin.clone()));
+        }
+    }
+    // Rejecting zones still answer ICMP that no icmp-block denied.
+    if matches!(target, "default" | "%%REJECT%%") {
+        let mut icmp = spec::rule(
+            vec![Match::Custom {
+                expression: "meta l4proto { icmp, ipv6-icmp }".to_string(),
+            }],
+            RuleVerdict::Accept,
+        );
+        icmp.comment = Some("firewalld allows ICMP".to_string());
+        imported.push(t, c, icmp, None);
+    }
+    for (_, _, rules, origin) in late_rules {
+        for rule in rules {
+            imported.push(t, c, rule, Some(origin.clone()));
+        }
+    }
+    let mut last = spec::rule(Vec::new(), verdict);
+    last.comment = Some(format!("zone {} target {target}", zone.name));
+    imported.push(t, c, last, Some(format!("{} target", zone.file)));
+    Ok(())
+}
+
+fn icmp_reject() -> RuleVerdict {
+    RuleVerdict::Reject {
+        reject_type: Some(RejectType::IcmpAdminProhibited),
+    }
+}
+
+fn describe(el: &Element) -> String {
+    match (el.attr("port"), el.attr("protocol")) {
+        (Some(port), Some(proto)) => format!("{port}/{proto}"),
+        _ => el.attr("value").unwrap_or_default().to_string(),
+    }
+}
+
+/// Compact one-line XML of an element, for untranslated reports.
+fn element_text(el: &Element) -> String {
+    let mut out = format!("<{}", el.name);
+    for (k, v) in &el.attrs {
+        out.push_str(&format!(" {k}=\"{v}\""));
+    }
+    let children: Vec<&Element> = el.elements().collect();
+    if children.is_empty() {
+        out.push_str("/>");
+    } else {
+        out.push('>');
+        for child in children {
+            out.push_str(&element_text(child));
+        }
+        out.push_str(&format!("</{}>", el.name));
+    }
+    out
+}
+
+fn port_protocol(name: &str) -> Result<Protocol> {
+    Ok(match name {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        "sctp" => Protocol::Sctp,
+        other => bail!("ports over {other} have no translation"),
+    })
+}
+
+fn port_spec(port: &str) -> Result<PortSpec> {
+    Ok(match port.split_once('-') {
+        Some((lo, hi)) => PortSpec::Range(lo.parse()?, hi.parse()?),
+        None => PortSpec::Single(port.parse().with_context(|| format!("bad port '{port}'"))?),
+    })
+}
+
+fn port_element(el: &Element) -> Result<(Protocol, PortSpec)> {
+    let proto = el.attr("protocol").context("port without protocol")?;
+    let port = el.attr("port").context("port without number")?;
+    Ok((port_protocol(proto)?, port_spec(port)?))
+}
+
+fn protocol(name: &str) -> Result<Protocol> {
+    Ok(match name {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        "sctp" => Protocol::Sctp,
+        "icmp" => Protocol::Icmp,
+        "ipv6-icmp" | "icmpv6" => Protocol::Icmpv6,
+        "esp" => Protocol::Esp,
+        "ah" => Protocol::Ah,
+        "gre" => Protocol::Gre,
+        "igmp" => Protocol::Number(2),
+        "vrrp" => Protocol::Number(112),
+        other => match other.parse() {
+            Ok(n) => Protocol::Number(n),
+            Err(_) => bail!("protocol {other} has no translation"),
+        },
+    })
+}
+
+fn port_rule(
+    proto: &Protocol,
+    port: &PortSpec,
+    source: bool,
+    mut matches: Vec<Match>,
+    verdict: RuleVerdict,
+) -> NftRule {
+    matches.push(Match::Protocol {
+        protocol: proto.clone(),
+    });
+    let port = PortMatch {
+        ports: vec![port.clone()],
+        negated: false,
+    };
+    matches.push(if source {
+        Match::SourcePort { port }
+    } else {
+        Match::DestinationPort { port }
+    });
+    spec::rule(matches, verdict)
+}
+
+/// Rules opening a service; `base` carries rich-rule address matches.
+fn service_rules(
+    services: &ServiceCatalog,
+    name: &str,
+    verdict: RuleVerdict,
+    base: &[Match],
+    comment: Option<&str>,
+) -> Result<Vec<NftRule>> {
+    let service = services
+        .get(name)
+        .with_context(|| format!("service '{name}' is not defined (pass its XML directory)"))?;
+    // A service destination restricts it per family.
+    let mut families: Vec<Vec<Match>> = Vec::new();
+    match (&service.destination_v4, &service.destination_v6) {
+        (None, None) => families.push(base.to_vec()),
+        (v4, v6) => {
+            for addr in [v4, v6].into_iter().flatten() {
+                let mut m = base.to_vec();
+                m.push(Match::DestinationAddress {
+                    address: AddressMatch {
+                        addresses: vec![render::canonical_address(addr)?],
+                        negated: false,
+                    },
+                });
+                families.push(m);
+            }
+        }
+    }
+    let mut rules = Vec::new();
+    for base in families {
+        for (proto, port) in &service.ports {
+            rules.push(port_rule(proto, port, false, base.clone(), verdict.clone()));
+        }
+        for (proto, port) in &service.source_ports {
+            rules.push(port_rule(proto, port, true, base.clone(), verdict.clone()));
+        }
+        for protocol in &service.protocols {
+            let mut m = base.clone();
+            m.push(Match::Protocol {
+                protocol: protocol.clone(),
+            });
+            rules.push(spec::rule(m, verdict.clone()));
+        }
+    }
+    if rules.is_empty() {
+        bail!("service '{name}' opens no ports or protocols (helpers only)");
+    }
+    if let Some(comment) = comment {
+        for rule in &mut rules {
+            rule.comment = Some(comment.to_string());
+        }
+    }
+    Ok(rules)
+}
+
+/// firewalld ICMP type names into per-family nft type matches.
+fn icmp_rules(name: &str, base: &[Match], verdict: RuleVerdict) -> Result<Vec<NftRule>> {
+    let nft_name = |v6: bool| match name {
+        "neighbour-solicitation" if v6 => "nd-neighbor-solicit",
+        "neighbour-advertisement" if v6 => "nd-neighbor-advert",
+        "router-solicitation" if v6 => "nd-router-solicit",
+        "router-advertisement" if v6 => "nd-router-advert",
+        "redirect" if v6 => "nd-redirect",
+        "timestamp-request" if !v6 => "timestamp-request",
+        other => other,
+    };
+    let mut rules = Vec::new();
+    for (v6, protocol) in [(false, Protocol::Icmp), (true, Protocol::Icmpv6)] {
+        if let Some(icmp_type) = render::icmp_type_value(v6, nft_name(v6)) {
+            let mut m = base.to_vec();
+            m.push(Match::Protocol { protocol });
+            m.push(Match::IcmpType { icmp_type });
+            rules.push(spec::rule(m, verdict.clone()));
+        }
+    }
+    if rules.is_empty() {
+        bail!("ICMP type '{name}' has no translation");
+    }
+    Ok(rules)
+}
+
+fn nat_chain(imported: &mut Imported, t: usize, hook: Hook) -> usize {
+    let (name, priority) = match hook {
+        Hook::Prerouting => ("prerouting", -100),
+        _ => ("postrouting", 100),
+    };
+    imported.chain(t, spec::base_chain(name, ChainType::Nat, hook, priority))
+}
+
+/// Interface restriction for zone-scoped NAT; the default zone catches all.
+fn zone_interfaces(zone: &Zone, is_default: bool, output: bool) -> Result<Option<Match>> {
+    if is_default || zone.interfaces.is_empty() {
+        return Ok(None);
+    }
+    let interface = InterfaceMatch {
+        interfaces: zone.interfaces.clone(),
+        negated: false,
+    };
+    Ok(Some(if output {
+        iptables::oif_match(&interface)?
+    } else {
+        Match::Interface { interface }
+    }))
+}
+
+fn nat_masquerade(imported: &mut Imported, t: usize, zone: &Zone, is_default: bool) -> Result<()> {
+    let matches = zone_interfaces(zone, is_default, true)?;
+    let c = nat_chain(imported, t, Hook::Postrouting);
+    let mut rule = spec::rule(matches.into_iter().collect(), RuleVerdict::Continue);
+    rule.expression
+        .statements
+        .push(Statement::Masquerade { port_range: None });
+    rule.comment = Some(format!("zone {} masquerade", zone.name));
+    imported.push(t, c, rule, Some(format!("{} masquerade", zone.file)));
+    Ok(())
+}
+
+fn forward_port(
+    imported: &mut Imported,
+    t: usize,
+    zone: &Zone,
+    is_default: bool,
+    el: &Element,
+) -> Result<()> {
+    let (proto, port) = port_element(el)?;
+    let to_port = el
+        .attr("to-port")
+        .map(|p| {
+            p.parse::<u16>()
+                .with_context(|| format!("bad to-port '{p}'"))
+        })
+        .transpose()?;
+    let statement = match el.attr("to-addr") {
+        Some(addr) => Statement::Dnat {
+            address: render::canonical_address(addr)?,
+            port: to_port,
+        },
+        None => Statement::Redirect { port: to_port },
+    };
+    let c = nat_chain(imported, t, Hook::Prerouting);
+    let mut rule = port_rule(
+        &proto,
+        &port,
+        false,
+        zone_interfaces(zone, is_default, false)?
+            .into_iter()
+            .collect(),
+        RuleVerdict::Continue,
+    );
+    rule.expression.statements.push(statement);
+    rule.comment = Some(format!("zone {} forward-port", zone.name));
+    imported.push(
+        t,
+        c,
+        rule,
+        Some(format!("{} forward-port {}", zone.file, describe(el))),
+    );
+    Ok(())
+}
+
+/// A `<rule>` element: (stage, priority, rules).
+fn rich_rule(el: &Element, services: &ServiceCatalog) -> Result<(Stage, i32, Vec<NftRule>)> {
+    let priority: i32 = el
+        .attr("priority")
+        .map(|p| p.parse().with_context(|| format!("bad priority '{p}'")))
+        .transpose()?
+        .unwrap_or(0);
+    let family = el.attr("family");
+    let mut base = Vec::new();
+    let mut v6_addr = None;
+    for (tag, is_source) in [("source", true), ("destination", false)] {
+        let Some(side) = el.child(tag) else {
+            continue;
+        };
+        let negated = side
+            .attr("invert")
+            .is_some_and(|v| v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("yes"));
+        if let Some(addr) = side.attr("address") {
+            let addr = render::canonical_address(addr)?;
+            v6_addr = Some(addr.contains(':'));
+            let address = AddressMatch {
+                addresses: vec![addr],
+                negated,
+            };
+            base.push(if is_source {
+                Match::SourceAddress { address }
+            } else {
+                Match::DestinationAddress { address }
+            });
+        } else if let (true, Some(mac)) = (is_source, side.attr("mac")) {
+            let op = if negated { "!= " } else { "" };
+            base.push(Match::Custom {
+                expression: format!("ether saddr {op}{}", mac.to_ascii_lowercase()),
+            });
+        } else {
+            bail!("<{tag}> with an ipset has no translation");
+        }
+    }
+    match (family, v6_addr) {
+        (Some("ipv4"), Some(true)) | (Some("ipv6"), Some(false)) => {
+            bail!(
+                "address does not match family {}",
+                family.unwrap_or_default()
+            )
+        }
+        (Some(f @ ("ipv4" | "ipv6")), None) => base.insert(
+            0,
+            Match::Custom {
+                expression: format!("meta nfproto {f}"),
+            },
+        ),
+        (Some(f), _) if f != "ipv4" && f != "ipv6" => bail!("unknown family '{f}'"),
+        _ => {}
+    }
+
+    let mut statements = Vec::new();
+    let mut verdict = RuleVerdict::Continue;
+    let mut stage = Stage::Log;
+    let mut element: Option<&Element> = None;
+    for child in el.elements() {
+        if child.child("limit").is_some() {
+            bail!("<limit> has no translation");
+        }
+        match child.name.as_str() {
+            "source" | "destination" => {}
+            "service" | "port" | "protocol" | "icmp-block" | "icmp-type" | "source-port" => {
+                element = Some(child)
+            }
+            "log" => statements.push(Statement::Log {
+                prefix: child.attr("prefix").map(str::to_string),
+                level: match child.attr("level").unwrap_or("warning") {
+                    "emerg" => LogLevel::Emergency,
+                    "alert" => LogLevel::Alert,
+                    "crit" => LogLevel::Critical,
+                    "error" | "err" => LogLevel::Error,
+                    "notice" => LogLevel::Notice,
+                    "info" => LogLevel::Info,
+                    "debug" => LogLevel::Debug,
+                    _ => LogLevel::Warning,
+                },
+                group: None,
+            }),
+            "accept" => {
+                verdict = RuleVerdict::Accept;
+                stage = Stage::Allow;
+            }
+            "drop" => {
+                verdict = RuleVerdict::Drop;
+                stage = Stage::Deny;
+            }
+            "reject" => {
+                verdict = RuleVerdict::Reject {
+                    reject_type: Some(match child.attr("type") {
+                        None => RejectType::IcmpAdminProhibited,
+                        Some("tcp-reset") => RejectType::TcpReset,
+                        Some("icmp-host-prohibited") => RejectType::IcmpHostProhibited,
+                        Some("icmp-net-prohibited") => RejectType::IcmpNetProhibited,
+                        Some("icmp-port-unreachable" | "icmp6-port-unreachable") => {
+                            RejectType::IcmpPortUnreach
+                        }
+                        Some("icmp-host-unreachable" | "icmp6-addr-unreachable") => {
+                            RejectType::IcmpHostUnreach
+                        }
+                        Some("icmp-admin-prohibited" | "icmp6-adm-prohibited") => {
+                            RejectType::IcmpAdminProhibited
+                        }
+                        Some(other) => bail!("reject type {other} has no translation"),
+                    }),
+                };
+                stage = Stage::Deny;
+            }
+            other => bail!("<{other}> in a rich rule has no translation"),
+        }
+    }
+
+    let rules = match element {
+        None => vec![spec::rule(base, verdict.clone())],
+        Some(e) => match e.name.as_str() {
+            "service" => service_rules(
+                services,
+                e.attr("name").unwrap_or_default(),
+                verdict.clone(),
+                &base,
+                None,
+            )?,
+            "port" | "source-port" => {
+                let (proto, port) = port_element(e)?;
+                vec![port_rule(
+                    &proto,
+                    &port,
+                    e.name == "source-port",
+                    base,
+                    verdict.clone(),
+                )]
+            }
+            "protocol" => {
+                let mut m = base;
+                m.push(Match::Protocol {
+                    protocol: protocol(e.attr("value").context("<protocol> without value")?)?,
+                });
+                vec![spec::rule(m, verdict.clone())]
+            }
+            _ => {
+                // icmp-block carries its own reject; icmp-type uses the action.
+                let block = e.name == "icmp-block";
+                if block {
+                    stage = Stage::Deny;
+                }
+                let v = if block {
+                    icmp_reject()
+                } else {
+                    verdict.clone()
+                };
+                icmp_rules(e.attr("name").unwrap_or_default(), &base, v)?
+            }
+        },
+    };
+    let mut rules = rules;
+    for rule in &mut rules {
+        rule.expression.statements = statements.clone();
+    }
+    if rules
+        .iter()
+        .all(|r| r.expression.statements.is_empty() && matches!(r.verdict, RuleVerdict::Continue))
+    {
+        bail!("rule has no action");
+    }
+    let stage = match priority {
+        p if p < 0 => Stage::Early,
+        p if p > 0 => Stage::Late,
+        _ => stage,
+    };
+    Ok((stage, priority, rules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC: &str = include_str!("../testdata/import/public.xml");
+
+    fn zone_rules(imported: &Imported, chain: &str) -> Vec<String> {
+        let table = &imported.tables[0];
+        let chain = table.chains.iter().find(|c| c.name == chain).unwrap();
+        chain
+            .rules
+            .iter()
+            .map(|r| render::render_rule(table, r).unwrap())
+            .collect()
+    }
+
+    fn import_public() -> Imported {
+        let zones = vec![
+            ("public".to_string(), PUBLIC.to_string()),
+            (
+                "trusted".to_string(),
+                "<zone target=\"ACCEPT\"><interface name=\"wg0\"/></zone>".to_string(),
+            ),
+            (
+                "work".to_string(),
+                "<zone><service name=\"ssh\"/></zone>".to_string(),
+            ),
+        ];
+        import(&zones, &ServiceCatalog::builtin(), "public").unwrap()
+    }
+
+    #[test]
+    fn input_dispatches_sources_then_interfaces_then_default() {
+        let imported = import_public();
+        assert_eq!(
+            zone_rules(&imported, "input")[3..],
+            [
+                "ip saddr 10.20.0.0/16 goto zone_public",
+                "iifname \"eth0\" goto zone_public",
+                "iifname \"wg0\" goto zone_trusted",
+                "goto zone_public",
+            ]
+        );
+        assert!(imported.notes.iter().any(|n| n.contains("zone work")));
+    }
+
+    #[test]
+    fn zone_chain_follows_firewalld_order() {
+        let imported = import_public();
+        assert_eq!(
+            zone_rules(&imported, "zone_public"),
+            [
+                "icmp type echo-request reject with icmpx type admin-prohibited",
+                "icmpv6 type echo-request reject with icmpx type admin-prohibited",
+                "ip saddr 192.0.2.66 log prefix \"blocked \" drop",
+                "tcp dport 22 accept comment \"ssh\"",
+                "udp dport 546 accept comment \"dhcpv6-client\"",
+                "tcp dport 8080 accept",
+                "udp dport 6000-6010 accept",
+                "ip saddr 192.168.1.0/24 tcp dport 80 accept",
+                "meta l4proto { icmp, ipv6-icmp } accept comment \"firewalld allows ICMP\"",
+                "tcp dport 2222 accept",
+                "reject with icmpx type admin-prohibited comment \"zone public target default\"",
+            ]
+        );
+        assert_eq!(
+            zone_rules(&imported, "prerouting"),
+            ["tcp dport 80 dnat ip to 10.0.0.2:8080 comment \"zone public forward-port\""]
+        );
+        assert_eq!(imported.untranslated.len(), 1);
+        assert!(imported.untranslated[0].reason.contains("<limit>"));
+    }
+
+    #[test]
+    fn service_xml_is_read() {
+        let service = parse_service(
+            r#"<?xml version="1.0"?><service><short>X</short>
+            <port protocol="tcp" port="9100"/><port protocol="udp" port="9100-9101"/>
+            <protocol value="gre"/></service>"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            service.ports[..],
+            [
+                (Protocol::Tcp, PortSpec::Single(9100)),
+                (Protocol::Udp, PortSpec::Range(9100, 9101))
+            ]
+        ));
+        assert!(matches!(service.protocols[..], [Protocol::Gre]));
+    }
+}
@@ -0,0 +1,901 @@
+//! `iptables-save` / `ip6tables-save` (and ufw's `user.rules`, which uses
+//! the same format) into the model.
+//!
+//! Each `*table` block becomes an nft table of the same name in the ip or
+//! ip6 family - the layout `iptables-restore-translate` produces - with the
+//! built-in chains as base chains at iptables' priorities. A rule using a
+//! match module or target the model cannot express is reported as
+//! untranslated as a whole; it is never imported partially.
+
+use super::{Imported, Source};
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, ConntrackState, Hook, InterfaceMatch, LogLevel, Match,
+    NftRule, PortMatch, PortSpec, Protocol, RejectType, RuleVerdict, Statement, TableFamily,
+    TcpFlags,
+};
+use crate::networking::policy::{render, spec};
+use anyhow::{Context, Result, bail};
+
+/// Match modules whose options are understood below.
+const MODULES: &[&str] = &[
+    "tcp",
+    "udp",
+    "sctp",
+    "icmp",
+    "icmp6",
+    "icmpv6",
+    "multiport",
+    "conntrack",
+    "state",
+    "comment",
+    "mark",
+];
+
+pub fn parse(text: &str) -> Result<Imported> {
+    let mut imported = Imported::new(Source::Iptables);
+    let mut header_family: Option<bool> = None;
+    let mut block: Option<Block> = None;
+
+    for (n, raw) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let line = raw.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if comment.contains("ip6tables-save") {
+                header_family = Some(true);
+            } else if comment.contains("iptables-save") {
+                header_family = Some(false);
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('*') {
+            if block.is_some() {
+                bail!("line {line_no}: *{name} starts before COMMIT");
+            }
+            block = Some(Block {
+                table: name.trim().to_string(),
+                chains: Vec::new(),
+                rules: Vec::new(),
+            });
+            continue;
+        }
+        let Some(current) = block.as_mut() else {
+            bail!("line {line_no}: '{line}' outside a *table block");
+        };
+        if line == "COMMIT" {
+            let done = block.take().unwrap_or_else(|| unreachable!());
+            let v6 = header_family.unwrap_or_else(|| done.looks_v6());
+            import_block(&mut imported, done, v6)?;
+        } else if let Some(decl) = line.strip_prefix(':') {
+            let mut parts = decl.split_whitespace();
+            let name = parts.next().context("empty chain declaration")?;
+            let policy = parts.next().unwrap_or("-");
+            current
+                .chains
+                .push((name.to_string(), policy.to_string(), line_no));
+        } else {
+            current.rules.push((line.to_string(), line_no));
+        }
+    }
+    if let Some(open) = block {
+        bail!("*{} is missing its COMMIT", open.table);
+    }
+    Ok(imported)
+}
+
+struct Block {
+    table: String,
+    /// (name, policy or "-", line)
+    chains: Vec<(String, String, usize)>,
+    /// (line text, line number)
+    rules: Vec<(String, usize)>,
+}
+
+impl Block {
+    /// Without an `ip6tables-save` header, addresses decide the family.
+    fn looks_v6(&self) -> bool {
+        self.rules.iter().any(|(line, _)| {
+            let tokens = tokenize(line).unwrap_or_default();
+            tokens.windows(2).any(|w| {
+                matches!(w[0].as_str(), "-s" | "-d" | "--source" | "--destination")
+                    && w[1].contains(':')
+            })
+        })
+    }
+}
+
+/// (hook, chain type, priority) of a built-in chain.
+fn builtin(table: &str, chain: &str) -> Option<(Hook, ChainType, i32)> {
+    let hook = match chain {
+        "PREROUTING" => Hook::Prerouting,
+        "INPUT" => Hook::Input,
+        "FORWARD" => Hook::Forward,
+        "OUTPUT" => Hook::Output,
+        "POSTROUTING" => Hook::Postrouting,
+        _ => return None,
+    };
+    Some(match (table, &hook) {
+        ("filter", Hook::Input | Hook::Forward | Hook::Output) => (hook, ChainType::Filter, 0),
+        ("nat", Hook::Prerouting) => (hook, ChainType::Nat, -100),
+        ("nat", Hook::Input) => (hook, ChainType::Nat, 100),
+        ("nat", Hook::Output) => (hook, ChainType::Nat, -100),
+        ("nat", Hook::Postrouting) => (hook, ChainType::Nat, 100),
+        ("mangle", Hook::Output) => (hook, ChainType::Route, -150),
+        ("mangle", _) => (hook, ChainType::Filter, -150),
+        ("raw", Hook::Prerouting | Hook::Output) => (hook, ChainType::Filter, -300),
+        _ => return None,
+    })
+}
+
+fn import_block(imported: &mut Imported, block: Block, v6: bool) -> Result<()> {
+    if !matches!(block.table.as_str(), "filter" | "nat" | "mangle" | "raw") {
+        for (line, line_no) in &block.rules {
+            imported.untranslated(
+                format!("line {line_no}"),
+                line,
+                format!("the {} table has no nft equivalent here", block.table),
+            );
+        }
+        return Ok(());
+    }
+    let family = if v6 {
+        TableFamily::Ip6
+    } else {
+        TableFamily::Ip
+    };
+    let t = imported.table(family, &block.table);
+    for (name, policy, line_no) in &block.chains {
+        let chain = match builtin(&block.table, name) {
+            Some((hook, chain_type, priority)) => {
+                let mut chain = spec::base_chain(name, chain_type, hook, priority);
+                chain.policy = match policy.as_str() {
+                    "ACCEPT" => Some(ChainPolicy::Accept),
+                    "DROP" => Some(ChainPolicy::Drop),
+                    other => bail!("line {line_no}: unsupported chain policy '{other}'"),
+                };
+                chain
+            }
+            None => spec::regular_chain(name),
+        };
+        imported.chain(t, chain);
+    }
+
+    let ctx = BlockCtx {
+        table: &block.table,
+        v6,
+        chains: block.chains.iter().map(|(n, _, _)| n.as_str()).collect(),
+    };
+    for (line, line_no) in &block.rules {
+        let origin = format!("line {line_no}");
+        match translate(&ctx, line) {
+            Ok((chain_name, rule)) => {
+                let Some(c) = imported.find_chain(t, &chain_name) else {
+                    imported.untranslated(
+                        origin,
+                        line,
+                        format!("chain {chain_name} is not declared"),
+                    );
+                    continue;
+                };
+                imported.push(t, c, rule, Some(origin));
+            }
+            Err(e) => imported.untranslated(origin, line, format!("{e:#}")),
+        }
+    }
+    Ok(())
+}
+
+struct BlockCtx<'a> {
+    table: &'a str,
+    v6: bool,
+    chains: Vec<&'a str>,
+}
+
+/// Split a rule line the way the shell would for `iptables-restore`.
+pub fn tokenize(line: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                in_token = true;
+                loop {
+                    match chars.next() {
+                        Some('\\') if c == '"' => match chars.next() {
+                            Some(escaped) => current.push(escaped),
+                            None => bail!("dangling escape"),
+                        },
+                        Some(q) if q == c => break,
+                        Some(other) => current.push(other),
+                        None => bail!("unterminated quote"),
+                    }
+                }
+            }
+            '\\' => {
+                in_token = true;
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                in_token = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+#[derive(Default)]
+struct Parsed {
+    protocol: Option<Protocol>,
+    saddr: Option<AddressMatch>,
+    daddr: Option<AddressMatch>,
+    sport: Option<PortMatch>,
+    dport: Option<PortMatch>,
+    iif: Option<InterfaceMatch>,
+    oif: Option<InterfaceMatch>,
+    states: Option<Vec<ConntrackState>>,
+    icmp_type: Option<u8>,
+    tcp_flags: Option<TcpFlags>,
+    mark: Option<(u32, Option<u32>)>,
+    comment: Option<String>,
+    target: Option<String>,
+    goto: bool,
+    target_opts: Vec<(String, String)>,
+}
+
+fn translate(ctx: &BlockCtx, line: &str) -> Result<(String, NftRule)> {
+    let mut tokens = tokenize(line)?;
+    // `iptables-save -c` prefixes rules with [packets:bytes].
+    if tokens.first().is_some_and(|t| t.starts_with('[')) {
+        tokens.remove(0);
+    }
+    let mut it = tokens.into_iter().peekable();
+    let chain = match it.next().as_deref() {
+        Some("-A") | Some("--append") => it.next().context("-A without a chain")?,
+        other => bail!("expected -A <chain>, found {}", other.unwrap_or("nothing")),
+    };
+
+    let mut p = Parsed::default();
+    let mut negate = false;
+    while let Some(token) = it.next() {
+        if token == "!" {
+            negate = true;
+            continue;
+        }
+        let neg = std::mem::take(&mut negate);
+        let mut value = || it.next().with_context(|| format!("{token} needs a value"));
+        match token.as_str() {
+            "-p" | "--protocol" => {
+                if neg {
+                    bail!("negated protocol matches are not supported");
+                }
+                p.protocol = protocol(&value()?, ctx.v6)?;
+            }
+            "-s" | "--source" | "--src" => p.saddr = Some(addresses(&value()?, neg)?),
+            "-d" | "--destination" | "--dst" => p.daddr = Some(addresses(&value()?, neg)?),
+            "-i" | "--in-interface" => p.iif = Some(interface(&value()?, neg)),
+            "-o" | "--out-interface" => p.oif = Some(interface(&value()?, neg)),
+            "-m" | "--match" => {
+                let module = value()?;
+                if !MODULES.contains(&module.as_str()) {
+                    bail!("match module '-m {module}' has no translation");
+                }
+            }
+            "--sport" | "--source-port" | "--sports" | "--source-ports" => {
+                p.sport = Some(ports(&value()?, neg)?)
+            }
+            "--dport" | "--destination-port" | "--dports" | "--destination-ports" => {
+                p.dport = Some(ports(&value()?, neg)?)
+            }
+            "--state" | "--ctstate" => {
+                if neg {
+                    bail!("negated state matches are not supported");
+                }
+                p.states = Some(states(&value()?)?);
+            }
+            "--icmp-type" | "--icmpv6-type" => {
+                if neg {
+                    bail!("negated ICMP type matches are not supported");
+                }
+                p.icmp_type = icmp_type(&value()?, ctx.v6)?;
+            }
+            "--tcp-flags" => {
+                let mask = tcp_flags(&value()?)?;
+                let flags = tcp_flags(&value()?)?;
+                if neg {
+                    bail!("negated --tcp-flags is not supported");
+                }
+                p.tcp_flags = Some(TcpFlags { flags, mask });
+            }
+            "--syn" => {
+                if neg {
+                    bail!("! --syn is not supported");
+                }
+                p.tcp_flags = Some(TcpFlags {
+                    flags: 0x02,
+                    mask: 0x17,
+                });
+            }
+            "--mark" => {
+                if neg {
+                    bail!("negated mark matches are not supported");
+                }
+                p.mark = Some(mark(&value()?)?);
+            }
+            "--comment" => p.comment = Some(value()?),
+            "-j" | "--jump" => p.target = Some(value()?),
+            "-g" | "--goto" => {
+                p.target = Some(value()?);
+                p.goto = true;
+            }
+            opt if opt.starts_with("--") && p.target.is_some() => {
+                let v = match it.peek() {
+                    Some(next) if !next.starts_with("--") => it.next().unwrap_or_default(),
+                    _ => String::new(),
+                };
+                p.target_opts.push((opt.to_string(), v));
+            }
+            other => bail!("option '{other}' has no translation"),
+        }
+    }
+    if negate {
+        bail!("dangling '!'");
+    }
+    Ok((chain, build(ctx, p)?))
+}
+
+fn build(ctx: &BlockCtx, p: Parsed) -> Result<NftRule> {
+    let mut matches = Vec::new();
+    if let Some(interface) = p.iif {
+        matches.push(Match::Interface { interface });
+    }
+    if let Some(oif) = p.oif {
+        matches.push(oif_match(&oif)?);
+    }
+    if (p.sport.is_some() || p.dport.is_some())
+        && !matches!(
+            p.protocol,
+            Some(Protocol::Tcp | Protocol::Udp | Protocol::Sctp)
+        )
+    {
+        bail!("ports need -p tcp, udp or sctp");
+    }
+    if let Some(protocol) = &p.protocol {
+        matches.push(Match::Protocol {
+            protocol: protocol.clone(),
+        });
+    }
+    for (addr, is_source) in [(&p.saddr, true), (&p.daddr, false)] {
+        if let Some(address) = addr {
+            if address.addresses.iter().any(|a| a.contains(':') != ctx.v6) {
+                bail!("address family does not match the table");
+            }
+            matches.push(if is_source {
+                Match::SourceAddress {
+                    address: address.clone(),
+                }
+            } else {
+                Match::DestinationAddress {
+                    address: address.clone(),
+                }
+            });
+        }
+    }
+    if let Some(port) = p.sport {
+        matches.push(Match::SourcePort { port });
+    }
+    if let Some(port) = p.dport {
+        matches.push(Match::DestinationPort { port });
+    }
+    if let Some(icmp_type) = p.icmp_type {
+        matches.push(Match::IcmpType { icmp_type });
+    }
+    if let Some(flags) = p.tcp_flags {
+        matches.push(Match::TcpFlags { flags });
+    }
+    if let Some(states) = p.states {
+        matches.push(Match::ConnectionState { states });
+    }
+    if let Some((mark, mask)) = p.mark {
+        matches.push(Match::Mark { mark, mask });
+    }
+
+    let mut rule = spec::rule(matches, RuleVerdict::Continue);
+    rule.comment = p.comment.map(|c| c.replace('"', "'"));
+    let opt = |name: &str| {
+        p.target_opts
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let known_opts: &[&str] = match p.target.as_deref() {
+        None | Some("ACCEPT" | "DROP" | "RETURN") => &[],
+        Some("REJECT") => &["--reject-with"],
+        Some("LOG") => &["--log-prefix", "--log-level"],
+        Some("NFLOG") => &["--nflog-prefix", "--nflog-group"],
+        Some("MASQUERADE") => &["--to-ports"],
+        Some("SNAT") => &["--to-source"],
+        Some("DNAT") => &["--to-destination"],
+        Some("REDIRECT") => &["--to-ports"],
+        Some("MARK") => &["--set-mark", "--set-xmark"],
+        Some("NFQUEUE") => &["--queue-num"],
+        Some(_) => &[],
+    };
+    if let Some((unknown, _)) = p
+        .target_opts
+        .iter()
+        .find(|(k, _)| !known_opts.contains(&k.as_str()))
+    {
+        bail!(
+            "target option {unknown} of -j {} has no translation",
+            p.target.as_deref().unwrap_or("?")
+        );
+    }
+
+    match p.target.as_deref() {
+        None => {}
+        Some("ACCEPT") => rule.verdict = RuleVerdict::Accept,
+        Some("DROP") => rule.verdict = RuleVerdict::Drop,
+        Some("RETURN") => rule.verdict = RuleVerdict::Return,
+        Some("REJECT") => {
+            rule.verdict = RuleVerdict::Reject {
+                reject_type: reject_type(opt("--reject-with"))?,
+            }
+        }
+        Some("LOG") => rule.expression.statements.push(Statement::Log {
+            prefix: opt("--log-prefix").map(str::to_string),
+            level: log_level(opt("--log-level").unwrap_or("4"))?,
+            group: None,
+        }),
+        Some("NFLOG") => rule.expression.statements.push(Statement::Log {
+            prefix: opt("--nflog-prefix").map(str::to_string),
+            level: LogLevel::Warning,
+            group: Some(
+                opt("--nflog-group")
+                    .unwrap_or("0")
+                    .parse()
+                    .context("invalid --nflog-group")?,
+            ),
+        }),
+        Some("MASQUERADE") => rule.expression.statements.push(Statement::Masquerade {
+            port_range: opt("--to-ports").map(port_range).transpose()?,
+        }),
+        Some("SNAT") => {
+            let to = opt("--to-source").context("SNAT needs --to-source")?;
+            let (address, ports) = nat_target(to)?;
+            rule.expression.statements.push(Statement::Snat {
+                address,
+                port_range: ports,
+            });
+        }
+        Some("DNAT") => {
+            let to = opt("--to-destination").context("DNAT needs --to-destination")?;
+            let (address, ports) = nat_target(to)?;
+            let port = match ports {
+                Some((lo, hi)) if lo == hi => Some(lo),
+                Some(_) => bail!("DNAT to a port range has no translation"),
+                None => None,
+            };
+            rule.expression
+                .statements
+                .push(Statement::Dnat { address, port });
+        }
+        Some("REDIRECT") => {
+            let port = match opt("--to-ports").map(port_range).transpose()? {
+                Some((lo, hi)) if lo == hi => Some(lo),
+                Some(_) => bail!("REDIRECT to a port range has no translation"),
+                None => None,
+            };
+            rule.expression
+                .statements
+                .push(Statement::Redirect { port });
+        }
+        Some("MARK") => {
+            let (value, xor) = match (opt("--set-mark"), opt("--set-xmark")) {
+                (Some(v), _) => (v, false),
+                (None, Some(v)) => (v, true),
+                (None, None) => bail!("MARK needs --set-mark or --set-xmark"),
+            };
+            let (mark, mask) = mark(value)?;
+            let mask = mask.unwrap_or(u32::MAX);
+            // set-xmark XORs; that equals OR only when the value stays inside the mask.
+            if xor && mark & !mask != 0 {
+                bail!("--set-xmark {value} has no translation");
+            }
+            rule.expression.statements.push(Statement::Mark {
+                mark,
+                mask: (mask != u32::MAX).then_some(!mask),
+            });
+        }
+        Some("NFQUEUE") => {
+            rule.verdict = RuleVerdict::Queue {
+                queue_num: opt("--queue-num")
+                    .unwrap_or("0")
+                    .parse()
+                    .context("invalid --queue-num")?,
+            }
+        }
+        Some(target) if ctx.chains.contains(&target) => {
+            rule.verdict = if p.goto {
+                RuleVerdict::Goto {
+                    target: target.to_string(),
+                }
+            } else {
+                RuleVerdict::Jump {
+                    target: target.to_string(),
+                }
+            }
+        }
+        Some(target) => bail!("target -j {target} has no translation"),
+    }
+    let nat_target = matches!(
+        p.target.as_deref(),
+        Some("MASQUERADE" | "SNAT" | "DNAT" | "REDIRECT")
+    );
+    if nat_target && ctx.table != "nat" {
+        bail!(
+            "-j {} in the {} table has no translation",
+            p.target.as_deref().unwrap_or("?"),
+            ctx.table
+        );
+    }
+    if rule.expression.matches.is_empty()
+        && rule.expression.statements.is_empty()
+        && matches!(rule.verdict, RuleVerdict::Continue)
+    {
+        // A bare `-A CHAIN` only counts packets.
+        rule.expression.statements.push(Statement::Counter {
+            packets: 0,
+            bytes: 0,
+        });
+    }
+    Ok(rule)
+}
+
+/// The model's interface match is input-only, as in `firewall.toml`.
+pub fn oif_match(oif: &InterfaceMatch) -> Result<Match> {
+    let names: Vec<String> = oif
+        .interfaces
+        .iter()
+        .map(|i| render::interface(i))
+        .collect::<Result<_>>()?;
+    let op = if oif.negated { "!= " } else { "" };
+    Ok(Match::Custom {
+        expression: format!("oifname {op}{}", render::anonymous_set(names)),
+    })
+}
+
+fn protocol(name: &str, v6: bool) -> Result<Option<Protocol>> {
+    Ok(Some(match name.to_ascii_lowercase().as_str() {
+        "all" | "0" => return Ok(None),
+        "tcp" | "6" => Protocol::Tcp,
+        "udp" | "17" => Protocol::Udp,
+        "icmp" | "1" if !v6 => Protocol::Icmp,
+        "icmpv6" | "ipv6-icmp" | "icmp6" | "58" if v6 => Protocol::Icmpv6,
+        "esp" | "50" => Protocol::Esp,
+        "ah" | "51" => Protocol::Ah,
+        "sctp" | "132" => Protocol::Sctp,
+        "gre" | "47" => Protocol::Gre,
+        other => match other.parse::<u8>() {
+            Ok(n) => Protocol::Number(n),
+            Err(_) => bail!("protocol '{name}' has no translation here"),
+        },
+    }))
+}
+
+fn addresses(value: &str, negated: bool) -> Result<AddressMatch> {
+    let addresses = value
+        .split(',')
+        .map(render::canonical_address)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(AddressMatch { addresses, negated })
+}
+
+/// iptables' `eth+` wildcard is nft's `eth*`.
+fn interface(value: &str, negated: bool) -> InterfaceMatch {
+    let name = match value.strip_suffix('+') {
+        Some(prefix) => format!("{prefix}*"),
+        None => value.to_string(),
+    };
+    InterfaceMatch {
+        interfaces: vec![name],
+        negated,
+    }
+}
+
+fn ports(value: &str, negated: bool) -> Result<PortMatch> {
+    let mut ports = Vec::new();
+    for part in value.split(',') {
+        ports.push(match part.split_once(':') {
+            Some((lo, hi)) => {
+                let lo = if lo.is_empty() { 0 } else { lo.parse()? };
+                let hi = if hi.is_empty() { 65535 } else { hi.parse()? };
+                PortSpec::Range(lo, hi)
+            }
+            None => PortSpec::Single(
+                part.parse()
+                    .with_context(|| format!("port '{part}' is not a number"))?,
+            ),
+        });
+    }
+    Ok(PortMatch { ports, negated })
+}
+
+fn port_range(value: &str) -> Result<(u16, u16)> {
+    Ok(match value.split_once('-') {
+        Some((lo, hi)) => (lo.parse()?, hi.parse()?),
+        None => {
+            let p = value.parse()?;
+            (p, p)
+        }
+    })
+}
+
+/// `addr[:port[-port]]` or `[v6addr]:port`.
+fn nat_target(value: &str) -> Result<(String, Option<(u16, u16)>)> {
+    let (addr, ports) = if let Some(rest) = value.strip_prefix('[') {
+        let (addr, after) = rest.split_once(']').context("unterminated [address]")?;
+        (addr, after.strip_prefix(':'))
+    } else if value.matches(':').count() == 1 {
+        let (addr, ports) = value.split_once(':').unwrap_or((value, ""));
+        (addr, Some(ports))
+    } else {
+        (value, None)
+    };
+    if addr.contains('-') {
+        bail!("NAT to an address range has no translation");
+    }
+    let address = render::canonical_address(addr)?;
+    Ok((address, ports.map(port_range).transpose()?))
+}
+
+fn states(value: &str) -> Result<Vec<ConntrackState>> {
+    value
+        .split(',')
+        .map(|s| {
+            Ok(match s {
+                "NEW" => ConntrackState::New,
+                "ESTABLISHED" => ConntrackState::Established,
+                "RELATED" => ConntrackState::Related,
+                "INVALID" => ConntrackState::Invalid,
+                "UNTRACKED" => ConntrackState::Untracked,
+                other => bail!("conntrack state {other} has no translation"),
+            })
+        })
+        .collect()
+}
+
+fn icmp_type(value: &str, v6: bool) -> Result<Option<u8>> {
+    if value == "any" {
+        return Ok(None);
+    }
+    if value.contains('/') {
+        bail!("ICMP type/code matches have no translation");
+    }
+    if let Ok(n) = value.parse() {
+        return Ok(Some(n));
+    }
+    // iptables spells a few types differently from nft.
+    let name = match value {
+        "echo-request" | "ping" => "echo-request",
+        "echo-reply" | "pong" => "echo-reply",
+        "router-solicitation" if v6 => "nd-router-solicit",
+        "router-advertisement" if v6 => "nd-router-advert",
+        "neighbour-solicitation" | "neighbor-solicitation" => "nd-neighbor-solicit",
+        "neighbour-advertisement" | "neighbor-advertisement" => "nd-neighbor-advert",
+        other => other,
+    };
+    render::icmp_type_value(v6, name)
+        .map(Some)
+        .with_context(|| format!("ICMP type '{value}' has no translation"))
+}
+
+fn tcp_flags(value: &str) -> Result<u8> {
+    let mut bits = 0u8;
+    for flag in value.split(',') {
+        bits |= match flag {
+            "FIN" => 0x01,
+            "SYN" => 0x02,
+            "RST" => 0x04,
+            "PSH" => 0x08,
+            "ACK" => 0x10,
+            "URG" => 0x20,
+            "ECE" => 0x40,
+            "CWR" => 0x80,
+            "ALL" => 0xff,
+            "NONE" => 0x00,
+            other => bail!("unknown TCP flag {other}"),
+        };
+    }
+    Ok(bits)
+}
+
+fn mark(value: &str) -> Result<(u32, Option<u32>)> {
+    let number = |s: &str| -> Result<u32> {
+        match s.strip_prefix("0x") {
+            Some(hex) => Ok(u32::from_str_radix(hex, 16)?),
+            None => Ok(s.parse()?),
+        }
+    };
+    Ok(match value.split_once('/') {
+        Some((v, m)) => {
+            let mask = number(m)?;
+            (number(v)?, (mask != u32::MAX).then_some(mask))
+        }
+        None => (number(value)?, None),
+    })
+}
+
+fn reject_type(with: Option<&str>) -> Result<Option<RejectType>> {
+    Ok(match with {
+        None | Some("icmp-port-unreachable" | "icmp6-port-unreachable" | "port-unreach") => None,
+        Some("tcp-reset" | "tcp-rst") => Some(RejectType::TcpReset),
+        Some("icmp-net-unreachable" | "net-unreach") => Some(RejectType::IcmpNetUnreach),
+        Some("icmp6-no-route" | "no-route") => Some(RejectType::IcmpUnreach),
+        Some(
+            "icmp-host-unreachable" | "host-unreach" | "icmp6-addr-unreachable" | "addr-unreach",
+        ) => Some(RejectType::IcmpHostUnreach),
+        Some("icmp-proto-unreachable" | "proto-unreach") => Some(RejectType::IcmpProtoUnreach),
+        Some(
+            "icmp-admin-prohibited" | "admin-prohib" | "icmp6-adm-prohibited" | "adm-prohibited",
+        ) => Some(RejectType::IcmpAdminProhibited),
+        Some("icmp-host-prohibited" | "host-prohib") => Some(RejectType::IcmpHostProhibited),
+        Some("icmp-net-prohibited" | "net-prohib") => Some(RejectType::IcmpNetProhibited),
+        Some(other) => bail!("--reject-with {other} has no translation"),
+    })
+}
+
+fn log_level(value: &str) -> Result<LogLevel> {
+    Ok(match value {
+        "0" | "emerg" => LogLevel::Emergency,
+        "1" | "alert" => LogLevel::Alert,
+        "2" | "crit" => LogLevel::Critical,
+        "3" | "err" | "error" => LogLevel::Error,
+        "4" | "warn" | "warning" => LogLevel::Warning,
+        "5" | "notice" => LogLevel::Notice,
+        "6" | "info" => LogLevel::Info,
+        "7" | "debug" => LogLevel::Debug,
+        other => bail!("unknown log level {other}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str) -> Vec<String> {
+        let imported = parse(text).unwrap();
+        assert!(
+            imported.untranslated.is_empty(),
+            "{:?}",
+            imported.untranslated
+        );
+        let table = &imported.tables[0];
+        table
+            .chains
+            .iter()
+            .flat_map(|c| c.rules.iter())
+            .map(|r| render::render_rule(table, r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn tokenizer_handles_quoted_comments() {
+        assert_eq!(
+            tokenize(r#"-A INPUT -m comment --comment "say \"hi\" now" -j ACCEPT"#).unwrap(),
+            [
+                "-A",
+                "INPUT",
+                "-m",
+                "comment",
+                "--comment",
+                "say \"hi\" now",
+                "-j",
+                "ACCEPT"
+            ]
+        );
+    }
+
+    #[test]
+    fn translates_common_rule_shapes() {
+        let got = rules(
+            "*filter\n:INPUT DROP [0:0]\n:LOGDROP - [0:0]\n\
+             -A INPUT -i eth+ ! -s 10.0.0.0/8 -p tcp -m multiport --dports 80,443,8000:8010 -j ACCEPT\n\
+             -A INPUT -p tcp --syn -m conntrack --ctstate NEW -g LOGDROP\n\
+             -A INPUT -p icmp --icmp-type ping -j REJECT --reject-with icmp-host-prohibited\n\
+             -A LOGDROP -j LOG --log-prefix \"drop: \" --log-level 6\n\
+             -A LOGDROP -j DROP\nCOMMIT\n",
+        );
+        assert_eq!(
+            got,
+            [
+                "iifname \"eth*\" ip saddr != 10.0.0.0/8 tcp dport { 80, 443, 8000-8010 } accept",
+                "meta l4proto tcp tcp flags & 0x17 == 0x02 ct state new goto LOGDROP",
+                "icmp type echo-request reject with icmp type host-prohibited",
+                "log prefix \"drop: \" level info",
+                "drop",
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_rules_are_reported_whole() {
+        let imported = parse(
+            "*filter\n:INPUT ACCEPT [0:0]\n\
+             -A INPUT -p tcp --dport 22 -m recent --update --seconds 60 -j DROP\n\
+             -A INPUT -j CUSTOM_MISSING\nCOMMIT\n",
+        )
+        .unwrap();
+        assert_eq!(imported.translated(), 0);
+        let reasons: Vec<&str> = imported
+            .untranslated
+            .iter()
+            .map(|u| u.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                "match module '-m recent' has no translation",
+                "target -j CUSTOM_MISSING has no translation"
+            ]
+        );
+        assert_eq!(imported.untranslated[0].origin, "line 3");
+    }
+
+    #[test]
+    fn ip6tables_uses_the_ip6_family() {
+        let imported = parse(
+            "# Generated by ip6tables-save v1.8.10\n*filter\n:INPUT DROP [0:0]\n\
+             -A INPUT -s fd00::/8 -p ipv6-icmp --icmpv6-type neighbour-solicitation -j ACCEPT\n\
+             -A INPUT -p tcp -j REJECT --reject-with icmp6-adm-prohibited\nCOMMIT\n",
+        )
+        .unwrap();
+        let table = &imported.tables[0];
+        assert_eq!(render::family_name(&table.family), "ip6");
+        let text: Vec<String> = table.chains[0]
+            .rules
+            .iter()
+            .map(|r| render::render_rule(table, r).unwrap())
+            .collect();
+        assert_eq!(
+            text,
+            [
+                "ip6 saddr fd00::/8 icmpv6 type nd-neighbor-solicit accept",
+                "meta l4proto tcp reject with icmpv6 type admin-prohibited",
+            ]
+        );
+    }
+
+    #[test]
+    fn nat_targets() {
+        let got = rules(
+            "*nat\n:PREROUTING ACCEPT [0:0]\n:POSTROUTING ACCEPT [0:0]\n\
+             -A PREROUTING -i wan0 -p tcp --dport 8443 -j DNAT --to-destination 192.168.10.20:443\n\
+             -A POSTROUTING -o wan0 -j MASQUERADE\n\
+             -A POSTROUTING -s 10.0.0.0/24 -j SNAT --to-source 203.0.113.5:1024-2048\nCOMMIT\n",
+        );
+        assert_eq!(
+            got,
+            [
+                "iifname \"wan0\" tcp dport 8443 dnat ip to 192.168.10.20:443",
+                "oifname \"wan0\" masquerade",
+                "ip saddr 10.0.0.0/24 snat ip to 203.0.113.5:1024-2048",
+            ]
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! `ghostctl firewall import` - read an existing iptables, ufw or firewalld
+//! configuration into the `nftables_enterprise` model.
+//!
+//! Every source rule either becomes an [`NftRule`] or is listed as
+//! [`Untranslated`] with the reason, so a migration can prove nothing was
+//! dropped on the floor. The imported tables are checked for duplicate,
+//! shadowed and unreachable rules ([`analyze`]) and can be exported as one
+//! `nft -f` script.
+
+pub mod analyze;
+pub mod firewalld;
+pub mod iptables;
+pub mod ufw;
+
+use super::render;
+use crate::command::CommandRunner;
+use crate::networking::nftables_enterprise::{NftChain, NftRule, NftTable, TableFamily};
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Iptables,
+    Ufw,
+    Firewalld,
+}
+
+impl Source {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "iptables" | "iptables-save" => Ok(Source::Iptables),
+            "ufw" => Ok(Source::Ufw),
+            "firewalld" => Ok(Source::Firewalld),
+            other => bail!("unknown source '{other}' (iptables, ufw or firewalld)"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Iptables => "iptables",
+            Source::Ufw => "ufw",
+            Source::Firewalld => "firewalld",
+        }
+    }
+}
+
+/// A source rule that has no equivalent in the model, kept verbatim.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Untranslated {
+    /// Where it came from, e.g. `line 14` or `public.xml rule 2`.
+    pub origin: String,
+    pub text: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub source: Source,
+    pub tables: Vec<NftTable>,
+    /// Source location of each rule, indexed like `tables[t].chains[c].rules[r]`;
+    /// `None` for rules the importer adds to reproduce implicit behaviour.
+    origins: Vec<Vec<Vec<Option<String>>>>,
+    pub untranslated: Vec<Untranslated>,
+    pub notes: Vec<String>,
+}
+
+impl Imported {
+    pub fn new(source: Source) -> Self {
+        Self {
+            source,
+            tables: Vec::new(),
+            origins: Vec::new(),
+            untranslated: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Index of the table, created empty if missing.
+    pub fn table(&mut self, family: TableFamily, name: &str) -> usize {
+        let family_name = render::family_name(&family);
+        if let Some(i) = self
+            .tables
+            .iter()
+            .position(|t| render::family_name(&t.family) == family_name && t.name == name)
+        {
+            return i;
+        }
+        self.tables.push(NftTable {
+            name: name.to_string(),
+            family,
+            chains: Vec::new(),
+            sets: Vec::new(),
+            maps: Vec::new(),
+            flowtables: Vec::new(),
+            counters: Vec::new(),
+            quotas: Vec::new(),
+            limits: Vec::new(),
+        });
+        self.origins.push(Vec::new());
+        self.tables.len() - 1
+    }
+
+    /// Index of the chain in table `t`, added if missing.
+    pub fn chain(&mut self, t: usize, chain: NftChain) -> usize {
+        if let Some(i) = self.tables[t]
+            .chains
+            .iter()
+            .position(|c| c.name == chain.name)
+        {
+            return i;
+        }
+        self.tables[t].chains.push(chain);
+        self.origins[t].push(Vec::new());
+        self.tables[t].chains.len() - 1
+    }
+
+    pub fn find_chain(&self, t: usize, name: &str) -> Option<usize> {
+        self.tables[t].chains.iter().position(|c| c.name == name)
+    }
+
+    pub fn push(&mut self, t: usize, c: usize, rule: NftRule, origin: Option<String>) {
+        self.tables[t].chains[c].rules.push(rule);
+        self.origins[t][c].push(origin);
+    }
+
+    /// Take a rule back out, returning its source location.
+    pub fn remove(&mut self, t: usize, c: usize, r: usize) -> Option<String> {
+        self.tables[t].chains[c].rules.remove(r);
+        self.origins[t][c].remove(r)
+    }
+
+    /// Insert importer-generated rules at the top of a chain.
+    pub fn prepend(&mut self, t: usize, c: usize, rules: Vec<NftRule>) {
+        let n = rules.len();
+        self.tables[t].chains[c].rules.splice(0..0, rules);
+        self.origins[t][c].splice(0..0, std::iter::repeat_n(None, n));
+    }
+
+    pub fn untranslated(
+        &mut self,
+        origin: impl Into<String>,
+        text: &str,
+        reason: impl Into<String>,
+    ) {
+        self.untranslated.push(Untranslated {
+            origin: origin.into(),
+            text: text.trim().to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn origin(&self, t: usize, c: usize, r: usize) -> Option<&str> {
+        self.origins[t][c][r].as_deref()
+    }
+
+    /// Rules translated from the source (generated rules excluded).
+    pub fn translated(&self) -> usize {
+        self.origins
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|o| o.is_some())
+            .count()
+    }
+
+    /// Append another import of the same source (e.g. ip6tables after iptables).
+    pub fn merge(&mut self, other: Imported) {
+        for (table, origins) in other.tables.into_iter().zip(other.origins) {
+            self.tables.push(table);
+            self.origins.push(origins);
+        }
+        self.untranslated.extend(other.untranslated);
+        self.notes.extend(other.notes);
+    }
+}
+
+/// Guess the source format from file content.
+pub fn detect(content: &str) -> Option<Source> {
+    let trimmed = content.trim_start();
+    if trimmed.starts_with('<') {
+        return Some(Source::Firewalld);
+    }
+    if content.contains(":ufw-user-") || content.lines().any(|l| l.starts_with("Status: ")) {
+        return Some(Source::Ufw);
+    }
+    if content
+        .lines()
+        .any(|l| l.starts_with('*') || l.starts_with("-A ") || l.starts_with("COMMIT"))
+    {
+        return Some(Source::Iptables);
+    }
+    None
+}
+
+pub struct ImportOptions<'a> {
+    pub source: Option<Source>,
+    /// firewalld zone used for traffic not bound to another zone.
+    pub default_zone: Option<&'a str>,
+}
+
+/// Import from a file (or, for firewalld, a directory of zone XML files).
+pub fn from_path(path: &Path, options: &ImportOptions) -> Result<Imported> {
+    if path.is_dir() {
+        if matches!(options.source, Some(s) if s != Source::Firewalld) {
+            bail!(
+                "{} is a directory; only firewalld zones are read from directories",
+                path.display()
+            );
+        }
+        let zones = firewalld::read_zone_dir(&[path])?;
+        let services = firewalld::ServiceCatalog::load(&[
+            &path.with_file_name("services"),
+            Path::new("/usr/lib/firewalld/services"),
+        ]);
+        return firewalld::import(&zones, &services, options.default_zone.unwrap_or("public"));
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let source = match options.source {
+        Some(source) => source,
+        None => detect(&content).with_context(|| {
+            format!("cannot tell the format of {}; pass --from", path.display())
+        })?,
+    };
+    match source {
+        Source::Iptables => iptables::parse(&content),
+        Source::Ufw => ufw::parse(&content),
+        Source::Firewalld => {
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "public".to_string());
+            let services =
+                firewalld::ServiceCatalog::load(&[Path::new("/usr/lib/firewalld/services")]);
+            let default_zone = options.default_zone.unwrap_or(&name);
+            firewalld::import(&[(name.clone(), content)], &services, default_zone)
+        }
+    }
+}
+
+/// Import the configuration this host is running.
+pub fn from_host(runner: &dyn CommandRunner, options: &ImportOptions) -> Result<Imported> {
+    let Some(source) = options.source else {
+        bail!("pass --from iptables|ufw|firewalld, or a file to import");
+    };
+    let capture = |cmd: &str, args: &[&str]| -> Result<String> {
+        let out = runner
+            .run_sudo(cmd, args)
+            .with_context(|| format!("failed to run {cmd}"))?;
+        if !out.success {
+            bail!("{cmd} {} failed: {}", args.join(" "), out.stderr.trim());
+        }
+        Ok(out.stdout)
+    };
+    match source {
+        Source::Iptables => {
+            let mut imported = iptables::parse(&capture("iptables-save", &[])?)?;
+            if runner.command_exists("ip6tables-save") {
+                imported.merge(iptables::parse(&capture("ip6tables-save", &[])?)?);
+            }
+            Ok(imported)
+        }
+        Source::Ufw => ufw::parse(&capture("ufw", &["status", "verbose"])?),
+        Source::Firewalld => {
+            let zones = firewalld::read_zone_dir(&[
+                Path::new("/usr/lib/firewalld/zones"),
+                Path::new("/etc/firewalld/zones"),
+            ])?;
+            let services = firewalld::ServiceCatalog::load(&[
+                Path::new("/usr/lib/firewalld/services"),
+                Path::new("/etc/firewalld/services"),
+            ]);
+            let default_zone = match options.default_zone {
+                Some(zone) => zone.to_string(),
+                None => capture("firewall-cmd", &["--get-default-zone"])
+                    .map(|z| z.trim().to_string())
+                    .unwrap_or_else(|_| firewalld::configured_default_zone()),
+            };
+            firewalld::import(&zones, &services, &default_zone)
+        }
+    }
+}
+
+/// One `nft -f` script replacing every imported table.
+pub fn export(imported: &Imported) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "#!/usr/sbin/nft -f")?;
+    writeln!(
+        out,
+        "# Imported by ghostctl from {} - review before loading",
+        imported.source.name()
+    )?;
+    for table in &imported.tables {
+        let family = render::family_name(&table.family);
+        writeln!(out)?;
+        writeln!(out, "table {family} {}", table.name)?;
+        writeln!(out, "delete table {family} {}", table.name)?;
+        writeln!(out)?;
+        out.push_str(&render::render_table(table)?);
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub source: Source,
+    pub tables: Vec<TableSummary>,
+    pub translated: usize,
+    pub untranslated: Vec<Untranslated>,
+    pub findings: Vec<analyze::Finding>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSummary {
+    pub family: String,
+    pub name: String,
+    pub chains: Vec<ChainSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainSummary {
+    pub name: String,
+    pub definition: Option<String>,
+    pub rules: usize,
+}
+
+/// Summarise an import. Rules that cannot be rendered as nft are moved to
+/// `untranslated` first, so an export afterwards leaves them out.
+pub fn report(imported: &mut Imported) -> Report {
+    let findings = analyze::analyze(imported);
+    Report {
+        source: imported.source,
+        tables: imported
+            .tables
+            .iter()
+            .map(|t| TableSummary {
+                family: render::family_name(&t.family).to_string(),
+                name: t.name.clone(),
+                chains: t
+                    .chains
+                    .iter()
+                    .map(|c| ChainSummary {
+                        name: c.name.clone(),
+                        definition: render::chain_definition(c),
+                        rules: c.rules.len(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        translated: imported.translated(),
+        untranslated: imported.untranslated.clone(),
+        findings,
+        notes: imported.notes.clone(),
+    }
+}
+
+pub fn print_report(report: &Report) {
+    println!(
+        "Imported from {}: {} rules translated, {} untranslated",
+        report.source.name(),
+        report.translated,
+        report.untranslated.len()
+    );
+    for table in &report.tables {
+        println!("table {} {}", table.family, table.name);
+        for chain in &table.chains {
+            match &chain.definition {
+                Some(def) => println!("  chain {} {{ {def} }}: {} rules", chain.name, chain.rules),
+                None => println!("  chain {}: {} rules", chain.name, chain.rules),
+            }
+        }
+    }
+    if !report.untranslated.is_empty() {
+        println!("\nUntranslated (not in the export):");
+        for item in &report.untranslated {
+            println!("  {}: {}", item.origin, item.text);
+            println!("      {}", item.reason);
+        }
+    }
+    if !report.findings.is_empty() {
+        println!("\nFindings:");
+        for finding in &report.findings {
+            analyze::print_finding(finding);
+        }
+    }
+    for note in &report.notes {
+        println!("note: {note}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPTABLES: &str = include_str!("../testdata/import/iptables-save.txt");
+    const IPTABLES_NFT: &str = include_str!("../testdata/import/iptables.nft");
+    const UFW: &str = include_str!("../testdata/import/ufw-status.txt");
+    const ZONE: &str = include_str!("../testdata/import/public.xml");
+
+    #[test]
+    fn detects_source_formats() {
+        assert_eq!(detect(IPTABLES), Some(Source::Iptables));
+        assert_eq!(detect(UFW), Some(Source::Ufw));
+        assert_eq!(detect(ZONE), Some(Source::Firewalld));
+        assert_eq!(detect("hello"), None);
+    }
+
+    #[test]
+    fn golden_iptables_export() {
+        let imported = iptables::parse(IPTABLES).unwrap();
+        assert_eq!(export(&imported).unwrap(), IPTABLES_NFT);
+    }
+
+    #[test]
+    fn report_accounts_for_every_source_rule() {
+        let mut imported = iptables::parse(IPTABLES).unwrap();
+        let rules = IPTABLES.lines().filter(|l| l.starts_with("-A ")).count();
+        let report = report(&mut imported);
+        assert_eq!(report.translated + report.untranslated.len(), rules);
+        assert!(!report.untranslated.is_empty());
+        assert!(!report.findings.is_empty());
+    }
+
+    #[test]
+    fn host_import_reads_both_address_families() {
+        use crate::command::{CommandResult, MockRunner};
+        let mock = MockRunner::as_root();
+        mock.mock_command("iptables-save", &[], CommandResult::ok(IPTABLES));
+        mock.mock_command(
+            "ip6tables-save",
+            &[],
+            CommandResult::ok(
+                "# Generated by ip6tables-save v1.8.10\n*filter\n:INPUT DROP [0:0]\n\
+                 -A INPUT -p ipv6-icmp -j ACCEPT\nCOMMIT\n",
+            ),
+        );
+        let imported = from_host(
+            &mock,
+            &ImportOptions {
+                source: Some(Source::Iptables),
+                default_zone: None,
+            },
+        )
+        .unwrap();
+        let names: Vec<String> = imported
+            .tables
+            .iter()
+            .map(|t| format!("{} {}", render::family_name(&t.family), t.name))
+            .collect();
+        assert_eq!(names, ["ip filter", "ip nat", "ip6 filter"]);
+    }
+}
@@ -0,0 +1,450 @@
+//! ufw into the model, from `ufw status verbose|numbered` or from the
+//! `user.rules` / `user6.rules` files.
+//!
+//! The status listing becomes one `inet ufw` table. ufw's `before.rules`
+//! (established traffic, loopback, essential ICMP) never appear in the
+//! listing but every ufw host has them, so the importer adds their
+//! equivalent at the top of each chain; without them a migrated host would
+//! drop its own reply traffic.
+
+use super::{Imported, Source, iptables};
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, ConntrackState, Hook, InterfaceMatch, Match, NftRule,
+    PortMatch, PortSpec, Protocol, RuleVerdict, TableFamily,
+};
+use crate::networking::policy::{render, spec};
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[\s*\d+\]\s*").expect("valid regex"));
+static RULE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?P<to>.*?)\s+(?P<action>ALLOW|DENY|REJECT|LIMIT)(?:\s+(?P<dir>IN|OUT|FWD))?\s+(?P<from>.*?)$")
+        .expect("valid regex")
+});
+static PORTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?P<ports>[0-9][0-9,:]*)(?:/(?P<proto>[a-z]+))?$").expect("valid regex")
+});
+
+pub fn parse(text: &str) -> Result<Imported> {
+    if text.contains(":ufw-user-") {
+        return parse_user_rules(text);
+    }
+    let mut imported = Imported::new(Source::Ufw);
+    let t = imported.table(TableFamily::Inet, "ufw");
+    let mut chains = Vec::new();
+    for (name, hook) in [
+        ("input", Hook::Input),
+        ("forward", Hook::Forward),
+        ("output", Hook::Output),
+    ] {
+        chains.push(imported.chain(t, spec::base_chain(name, ChainType::Filter, hook, 0)));
+    }
+
+    let mut in_rules = false;
+    let mut defaults_seen = false;
+    let mut rejects = Vec::new();
+    for (n, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        let origin = format!("line {}", n + 1);
+        if line.is_empty() || line.starts_with("--") {
+            continue;
+        }
+        if let Some(status) = line.strip_prefix("Status:") {
+            if status.trim() != "active" {
+                imported.notes.push(format!(
+                    "ufw is {}; these rules are not enforced",
+                    status.trim()
+                ));
+            }
+            continue;
+        }
+        if let Some(defaults) = line.strip_prefix("Default:") {
+            defaults_seen = true;
+            for part in defaults.split(',') {
+                let mut words = part.split_whitespace();
+                let (Some(policy), Some(direction)) = (words.next(), words.next()) else {
+                    continue;
+                };
+                let c = match direction {
+                    "(incoming)" => chains[0],
+                    "(routed)" => chains[1],
+                    "(outgoing)" => chains[2],
+                    _ => continue,
+                };
+                imported.tables[t].chains[c].policy = Some(match policy {
+                    "allow" => ChainPolicy::Accept,
+                    "reject" => {
+                        rejects.push(c);
+                        ChainPolicy::Drop
+                    }
+                    _ => ChainPolicy::Drop,
+                });
+            }
+            continue;
+        }
+        if line.starts_with("To ") && line.contains("Action") {
+            in_rules = true;
+            continue;
+        }
+        if !in_rules {
+            continue;
+        }
+        match translate(line) {
+            Ok(rules) => {
+                for (direction, rule) in rules {
+                    let c = chains[direction];
+                    imported.push(t, c, rule, Some(origin.clone()));
+                }
+            }
+            Err(e) => imported.untranslated(origin, line, format!("{e:#}")),
+        }
+    }
+
+    for c in rejects {
+        let mut rule = spec::rule(Vec::new(), RuleVerdict::Reject { reject_type: None });
+        rule.comment = Some("ufw default reject".to_string());
+        imported.push(t, c, rule, None);
+    }
+    for (i, c) in chains.iter().enumerate() {
+        imported.prepend(t, *c, before_rules(i));
+    }
+    if !defaults_seen {
+        imported.notes.push(
+            "no 'Default:' line (use `ufw status verbose`); chain policies are left at accept"
+                .to_string(),
+        );
+    }
+    imported.notes.push(
+        "rules marked \"ufw before.rules\" reproduce ufw's built-in rules; \
+         DHCP and multicast allowances from before.rules are not included"
+            .to_string(),
+    );
+    Ok(imported)
+}
+
+/// `user.rules` is iptables-restore input whose `ufw-user-*` chains are
+/// normally reached from ufw's own chains; here they are hooked directly.
+fn parse_user_rules(text: &str) -> Result<Imported> {
+    let mut imported = iptables::parse(text)?;
+    imported.source = Source::Ufw;
+    for table in &mut imported.tables {
+        for chain in &mut table.chains {
+            let hook = match chain.name.as_str() {
+                "ufw-user-input" | "ufw6-user-input" => Hook::Input,
+                "ufw-user-forward" | "ufw6-user-forward" => Hook::Forward,
+                "ufw-user-output" | "ufw6-user-output" => Hook::Output,
+                _ => continue,
+            };
+            chain.hook = Some(hook);
+            chain.priority = Some(0);
+        }
+    }
+    imported.notes.push(
+        "user.rules has no default policies or before.rules; \
+         import `ufw status verbose` for the complete picture"
+            .to_string(),
+    );
+    Ok(imported)
+}
+
+fn state_rule(states: Vec<ConntrackState>, verdict: RuleVerdict) -> NftRule {
+    spec::rule(vec![Match::ConnectionState { states }], verdict)
+}
+
+/// ufw's `before.rules` equivalent for chain 0 (input), 1 (forward) or 2 (output).
+fn before_rules(chain: usize) -> Vec<NftRule> {
+    let lo = InterfaceMatch {
+        interfaces: vec!["lo".to_string()],
+        negated: false,
+    };
+    let mut rules = vec![state_rule(
+        vec![ConntrackState::Established, ConntrackState::Related],
+        RuleVerdict::Accept,
+    )];
+    match chain {
+        0 => {
+            rules.push(spec::rule(
+                vec![Match::Interface { interface: lo }],
+                RuleVerdict::Accept,
+            ));
+            rules.push(state_rule(vec![ConntrackState::Invalid], RuleVerdict::Drop));
+            rules.push(spec::rule(
+                vec![Match::Custom {
+                    expression: "icmp type { destination-unreachable, echo-request, \
+                                 time-exceeded, parameter-problem }"
+                        .to_string(),
+                }],
+                RuleVerdict::Accept,
+            ));
+            rules.push(spec::rule(
+                vec![Match::Custom {
+                    expression: "icmpv6 type { destination-unreachable, packet-too-big, \
+                                 time-exceeded, parameter-problem, echo-request, \
+                                 nd-router-advert, nd-neighbor-solicit, nd-neighbor-advert }"
+                        .to_string(),
+                }],
+                RuleVerdict::Accept,
+            ));
+        }
+        1 => rules.push(state_rule(vec![ConntrackState::Invalid], RuleVerdict::Drop)),
+        _ => rules.push(spec::rule(
+            vec![iptables::oif_match(&lo).expect("lo is a valid interface name")],
+            RuleVerdict::Accept,
+        )),
+    }
+    for rule in &mut rules {
+        rule.comment = Some("ufw before.rules".to_string());
+    }
+    rules
+}
+
+#[derive(Debug, Default)]
+struct Endpoint {
+    address: Option<String>,
+    ports: Option<Vec<PortSpec>>,
+    protocol: Option<String>,
+    interface: Option<String>,
+    v6: bool,
+}
+
+fn endpoint(text: &str) -> Result<Endpoint> {
+    let mut ep = Endpoint::default();
+    let mut text = text.trim().to_string();
+    if text.contains("(v6)") {
+        ep.v6 = true;
+        text = text.replace("(v6)", " ");
+    }
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    if let Some(i) = words.iter().position(|w| *w == "on") {
+        let iface = words.get(i + 1).context("'on' without an interface")?;
+        ep.interface = Some(iface.to_string());
+        words.drain(i..i + 2);
+    }
+    let mut words = words.into_iter().peekable();
+    match words.peek() {
+        Some(&"Anywhere") => {
+            words.next();
+        }
+        Some(w) if w.parse::<ipnet::IpNet>().is_ok() || w.parse::<std::net::IpAddr>().is_ok() => {
+            ep.address = Some(render::canonical_address(w)?);
+            ep.v6 |= w.contains(':');
+            words.next();
+        }
+        _ => {}
+    }
+    if let Some(w) = words.peek()
+        && let Some(caps) = PORTS.captures(w)
+    {
+        let mut ports = Vec::new();
+        for part in caps["ports"].split(',') {
+            ports.push(match part.split_once(':') {
+                Some((lo, hi)) => PortSpec::Range(lo.parse()?, hi.parse()?),
+                None => PortSpec::Single(part.parse()?),
+            });
+        }
+        ep.ports = Some(ports);
+        ep.protocol = caps.name("proto").map(|p| p.as_str().to_string());
+        words.next();
+    }
+    let rest: Vec<&str> = words.collect();
+    if !rest.is_empty() {
+        bail!(
+            "application profile '{}' has no translation; expand it with `ufw app info`",
+            rest.join(" ")
+        );
+    }
+    Ok(ep)
+}
+
+/// One status line into rules tagged with their chain (0 input, 1 forward, 2 output).
+fn translate(line: &str) -> Result<Vec<(usize, NftRule)>> {
+    let line = NUMBER.replace(line, "");
+    let (line, comment) = match line.split_once(" # ") {
+        Some((rule, comment)) => (
+            rule.trim_end().to_string(),
+            Some(comment.trim().to_string()),
+        ),
+        None => (line.trim_end().to_string(), None),
+    };
+    let Some(caps) = RULE.captures(&line) else {
+        bail!("not a ufw rule line");
+    };
+    let from_text = caps["from"]
+        .trim_end()
+        .trim_end_matches("(out)")
+        .trim_end_matches("(route)")
+        .to_string();
+    let to = endpoint(&caps["to"])?;
+    let from = endpoint(&from_text)?;
+    let verdict = match &caps["action"] {
+        "ALLOW" => RuleVerdict::Accept,
+        "DENY" => RuleVerdict::Drop,
+        "REJECT" => RuleVerdict::Reject { reject_type: None },
+        _ => bail!("ufw LIMIT (rate-limited allow) has no translation"),
+    };
+    let direction = caps.name("dir").map_or("IN", |d| d.as_str());
+    let (chain, iif, oif) = match direction {
+        "IN" => (0, to.interface.clone().or(from.interface.clone()), None),
+        "OUT" => (2, None, to.interface.clone().or(from.interface.clone())),
+        _ => (1, from.interface.clone(), to.interface.clone()),
+    };
+
+    let protocol = match (&to.protocol, &from.protocol) {
+        (Some(a), Some(b)) if a != b => bail!("conflicting protocols {a} and {b}"),
+        (Some(p), _) | (None, Some(p)) => Some(p.clone()),
+        (None, None) => None,
+    };
+    let has_ports = to.ports.is_some() || from.ports.is_some();
+    let protocols: Vec<Option<Protocol>> = match protocol.as_deref() {
+        Some("tcp") => vec![Some(Protocol::Tcp)],
+        Some("udp") => vec![Some(Protocol::Udp)],
+        Some(other) if has_ports => bail!("ports with protocol {other} have no translation"),
+        Some("esp") => vec![Some(Protocol::Esp)],
+        Some("ah") => vec![Some(Protocol::Ah)],
+        Some("gre") => vec![Some(Protocol::Gre)],
+        Some(other) => bail!("protocol {other} has no translation"),
+        // A bare port means tcp and udp.
+        None if has_ports => vec![Some(Protocol::Tcp), Some(Protocol::Udp)],
+        None => vec![None],
+    };
+
+    let v6 = to.v6 || from.v6;
+    let mut rules = Vec::new();
+    for protocol in protocols {
+        let mut matches = Vec::new();
+        if let Some(name) = &iif {
+            matches.push(Match::Interface {
+                interface: InterfaceMatch {
+                    interfaces: vec![name.clone()],
+                    negated: false,
+                },
+            });
+        }
+        if let Some(name) = &oif {
+            matches.push(iptables::oif_match(&InterfaceMatch {
+                interfaces: vec![name.clone()],
+                negated: false,
+            })?);
+        }
+        if from.address.is_none() && to.address.is_none() {
+            // ufw keeps separate v4 and v6 rules; pin each to its family.
+            matches.push(Match::Custom {
+                expression: format!("meta nfproto {}", if v6 { "ipv6" } else { "ipv4" }),
+            });
+        }
+        if let Some(protocol) = protocol {
+            matches.push(Match::Protocol { protocol });
+        }
+        if let Some(addr) = &from.address {
+            matches.push(Match::SourceAddress {
+                address: AddressMatch {
+                    addresses: vec![addr.clone()],
+                    negated: false,
+                },
+            });
+        }
+        if let Some(addr) = &to.address {
+            matches.push(Match::DestinationAddress {
+                address: AddressMatch {
+                    addresses: vec![addr.clone()],
+                    negated: false,
+                },
+            });
+        }
+        if let Some(ports) = &from.ports {
+            matches.push(Match::SourcePort {
+                port: PortMatch {
+                    ports: ports.clone(),
+                    negated: false,
+                },
+            });
+        }
+        if let Some(ports) = &to.ports {
+            matches.push(Match::DestinationPort {
+                port: PortMatch {
+                    ports: ports.clone(),
+                    negated: false,
+                },
+            });
+        }
+        let mut rule = spec::rule(matches, verdict.clone());
+        rule.comment = comment.as_ref().map(|c| c.replace('"', "'"));
+        rules.push((chain, rule));
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = include_str!("../testdata/import/ufw-status.txt");
+
+    fn rendered(imported: &Imported, chain: &str) -> Vec<String> {
+        let table = &imported.tables[0];
+        let chain = table.chains.iter().find(|c| c.name == chain).unwrap();
+        chain
+            .rules
+            .iter()
+            .filter(|r| r.comment.as_deref() != Some("ufw before.rules"))
+            .map(|r| render::render_rule(table, r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn status_verbose_becomes_an_inet_table() {
+        let imported = parse(STATUS).unwrap();
+        let table = &imported.tables[0];
+        assert_eq!(table.name, "ufw");
+        let input = &table.chains[0];
+        assert_eq!(
+            render::chain_definition(input).unwrap(),
+            "type filter hook input priority 0; policy drop;"
+        );
+        assert_eq!(
+            rendered(&imported, "input"),
+            [
+                "meta nfproto ipv4 tcp dport 22 accept",
+                "ip saddr 192.168.1.0/24 tcp dport { 80, 443 } accept comment \"web\"",
+                "iifname \"eth1\" meta nfproto ipv4 drop",
+                "meta nfproto ipv4 tcp dport 3000-3010 reject",
+                "meta nfproto ipv4 tcp dport 22 accept",
+                "meta nfproto ipv6 tcp dport 22 accept",
+            ]
+        );
+        assert_eq!(
+            rendered(&imported, "output"),
+            [
+                "meta nfproto ipv4 tcp dport 53 accept",
+                "meta nfproto ipv4 udp dport 53 accept",
+            ]
+        );
+        let reasons: Vec<&str> = imported
+            .untranslated
+            .iter()
+            .map(|u| u.reason.as_str())
+            .collect();
+        assert_eq!(reasons.len(), 2, "{reasons:?}");
+        assert!(reasons[0].contains("'OpenSSH'"));
+        assert!(reasons[1].contains("LIMIT"));
+    }
+
+    #[test]
+    fn user_rules_hook_the_ufw_chains() {
+        let imported = parse(
+            "*filter\n:ufw-user-input - [0:0]\n:ufw-user-output - [0:0]\n\
+             ### tuple ### allow tcp 22 0.0.0.0/0 any 0.0.0.0/0 in\n\
+             -A ufw-user-input -p tcp --dport 22 -j ACCEPT\nCOMMIT\n",
+        )
+        .unwrap();
+        assert_eq!(imported.source, Source::Ufw);
+        let chain = &imported.tables[0].chains[0];
+        assert_eq!(
+            render::chain_definition(chain).unwrap(),
+            "type filter hook input priority 0;"
+        );
+        assert_eq!(imported.translated(), 1);
+    }
+}
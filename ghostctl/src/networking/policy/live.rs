@@ -0,0 +1,889 @@
+//! The ruleset as `nft -j list ruleset` reports it, normalised to the
+//! renderer's syntax.
+//!
+//! Rules are printed expression by expression in the canonical form
+//! [`super::render::render_rule`] produces, so a rule loaded from our own
+//! script reads back as the identical string. Counter values and handles are
+//! kept out of the text; expressions the normaliser does not know are printed
+//! as `?<json>` and will always show up as a difference, never silently
+//! compare equal.
+//!
+//! [`model`] reads the same JSON into the `nftables_enterprise` model for
+//! the packet simulator. Matches the model has no variant for become
+//! [`Match::Custom`] with their normalised text.
+
+use super::render::{self, anonymous_set, ct_state_rank, quote};
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, ConntrackState, Hook, InterfaceMatch, LogLevel, Match,
+    NftChain, NftRule, NftSet, NftTable, PortMatch, PortSpec, Protocol, RejectType, RuleExpression,
+    RuleVerdict, SetFlag, SetType, Statement, TableFamily,
+};
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default)]
+pub struct Ruleset {
+    pub tables: Vec<Table>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub family: String,
+    pub name: String,
+    pub chains: Vec<Chain>,
+    pub sets: Vec<Set>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chain {
+    pub name: String,
+    /// Base-chain header (`type filter hook input priority 0; policy drop;`).
+    pub definition: Option<String>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rule {
+    pub handle: Option<u64>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Set {
+    pub name: String,
+    /// `type ipv4_addr; flags interval;` - everything except the elements.
+    pub definition: String,
+    pub elements: Vec<String>,
+}
+
+impl Ruleset {
+    pub fn parse(json: &str) -> Result<Self> {
+        let root: Value = serde_json::from_str(json).context("invalid nft JSON")?;
+        let items = root
+            .get("nftables")
+            .and_then(Value::as_array)
+            .context("nft JSON has no 'nftables' array")?;
+
+        let mut ruleset = Ruleset::default();
+        for item in items {
+            if let Some(t) = item.get("table") {
+                ruleset.tables.push(Table {
+                    family: str_field(t, "family"),
+                    name: str_field(t, "name"),
+                    ..Table::default()
+                });
+            } else if let Some(c) = item.get("chain") {
+                let definition = c.get("hook").and_then(Value::as_str).map(|hook| {
+                    let mut def = format!("type {} hook {hook}", str_field(c, "type"));
+                    if let Some(dev) = c.get("dev").and_then(Value::as_str) {
+                        def.push_str(&format!(" device {}", quote(dev)));
+                    }
+                    def.push_str(&format!(
+                        " priority {};",
+                        c.get("prio").and_then(Value::as_i64).unwrap_or(0)
+                    ));
+                    if let Some(policy) = c.get("policy").and_then(Value::as_str) {
+                        def.push_str(&format!(" policy {policy};"));
+                    }
+                    def
+                });
+                if let Some(table) = ruleset.table_mut(c) {
+                    table.chains.push(Chain {
+                        name: str_field(c, "name"),
+                        definition,
+                        rules: Vec::new(),
+                    });
+                }
+            } else if let Some(s) = item.get("set") {
+                let set_type = match s.get("type") {
+                    Some(Value::Array(parts)) => parts
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" . "),
+                    Some(Value::String(t)) => t.clone(),
+                    _ => String::new(),
+                };
+                let flags = string_list(s.get("flags"));
+                let definition =
+                    set_definition(&set_type, &flags, s.get("timeout").and_then(Value::as_u64));
+                let elements = s
+                    .get("elem")
+                    .and_then(Value::as_array)
+                    .map(|elems| {
+                        elems
+                            .iter()
+                            .map(|e| set_element(&set_type, e))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                if let Some(table) = ruleset.table_mut(s) {
+                    table.sets.push(Set {
+                        name: str_field(s, "name"),
+                        definition,
+                        elements: sorted(elements),
+                    });
+                }
+            } else if let Some(r) = item.get("rule") {
+                let text = rule_text(r);
+                let handle = r.get("handle").and_then(Value::as_u64);
+                let chain = str_field(r, "chain");
+                if let Some(table) = ruleset.table_mut(r)
+                    && let Some(chain) = table.chains.iter_mut().find(|c| c.name == chain)
+                {
+                    chain.rules.push(Rule { handle, text });
+                }
+            }
+        }
+        Ok(ruleset)
+    }
+
+    pub fn table(&self, family: &str, name: &str) -> Option<&Table> {
+        self.tables
+            .iter()
+            .find(|t| t.family == family && t.name == name)
+    }
+
+    fn table_mut(&mut self, item: &Value) -> Option<&mut Table> {
+        let family = str_field(item, "family");
+        let name = str_field(item, "table");
+        self.tables
+            .iter_mut()
+            .find(|t| t.family == family && t.name == name)
+    }
+}
+
+impl Table {
+    /// The compiled policy in the same shape as a parsed live table.
+    pub fn from_model(model: &NftTable) -> Result<Self> {
+        let mut table = Table {
+            family: render::family_name(&model.family).to_string(),
+            name: model.name.clone(),
+            ..Table::default()
+        };
+        for set in &model.sets {
+            let set_type = render::set_type_name(&set.set_type)?;
+            let flags: Vec<String> = set
+                .flags
+                .iter()
+                .map(|f| render::set_flag_name(f).to_string())
+                .collect();
+            let elements = set
+                .elements
+                .iter()
+                .map(|e| match set.set_type {
+                    SetType::Ipv4Address | SetType::Ipv6Address => render::canonical_address(e),
+                    SetType::IfName => Ok(quote(e)),
+                    _ => Ok(e.trim().to_string()),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            table.sets.push(Set {
+                name: set.name.clone(),
+                definition: set_definition(&set_type, &flags, set.timeout.map(u64::from)),
+                elements: sorted(elements),
+            });
+        }
+        for chain in &model.chains {
+            let rules = chain
+                .rules
+                .iter()
+                .map(|r| {
+                    Ok(Rule {
+                        handle: None,
+                        text: render::render_rule(model, r)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            table.chains.push(Chain {
+                name: chain.name.clone(),
+                definition: render::chain_definition(chain),
+                rules,
+            });
+        }
+        Ok(table)
+    }
+}
+
+/// `nft -j list ruleset` as model tables, rules in chain order with handles.
+pub fn model(json: &str) -> Result<Vec<NftTable>> {
+    let root: Value = serde_json::from_str(json).context("invalid nft JSON")?;
+    let items = root
+        .get("nftables")
+        .and_then(Value::as_array)
+        .context("nft JSON has no 'nftables' array")?;
+    let mut tables: Vec<NftTable> = Vec::new();
+    let find = |tables: &mut Vec<NftTable>, item: &Value| -> Option<usize> {
+        let family = str_field(item, "family");
+        let name = str_field(item, "table");
+        tables
+            .iter()
+            .position(|t| render::family_name(&t.family) == family && t.name == name)
+    };
+    for item in items {
+        if let Some(t) = item.get("table") {
+            tables.push(NftTable {
+                name: str_field(t, "name"),
+                family: table_family(&str_field(t, "family"))?,
+                chains: Vec::new(),
+                sets: Vec::new(),
+                maps: Vec::new(),
+                flowtables: Vec::new(),
+                counters: Vec::new(),
+                quotas: Vec::new(),
+                limits: Vec::new(),
+            });
+        } else if let Some(c) = item.get("chain") {
+            let Some(t) = find(&mut tables, c) else {
+                continue;
+            };
+            tables[t].chains.push(NftChain {
+                name: str_field(c, "name"),
+                chain_type: match c.get("type").and_then(Value::as_str) {
+                    Some("nat") => ChainType::Nat,
+                    Some("route") => ChainType::Route,
+                    _ => ChainType::Filter,
+                },
+                hook: c.get("hook").and_then(Value::as_str).and_then(hook),
+                priority: c.get("prio").and_then(Value::as_i64).map(|p| p as i32),
+                policy: c.get("policy").and_then(Value::as_str).map(|p| match p {
+                    "drop" => ChainPolicy::Drop,
+                    _ => ChainPolicy::Accept,
+                }),
+                rules: Vec::new(),
+                device: c.get("dev").and_then(Value::as_str).map(str::to_string),
+            });
+        } else if let Some(s) = item.get("set") {
+            let Some(t) = find(&mut tables, s) else {
+                continue;
+            };
+            let type_name = match s.get("type") {
+                Some(Value::String(t)) => t.clone(),
+                Some(other) => other.to_string(),
+                None => String::new(),
+            };
+            tables[t].sets.push(NftSet {
+                name: str_field(s, "name"),
+                set_type: match type_name.as_str() {
+                    "ipv4_addr" => SetType::Ipv4Address,
+                    "ipv6_addr" => SetType::Ipv6Address,
+                    "ether_addr" => SetType::EthernetAddress,
+                    "inet_proto" => SetType::InetProtocol,
+                    "inet_service" => SetType::InetService,
+                    "mark" => SetType::Mark,
+                    "ifname" => SetType::IfName,
+                    _ => SetType::Composite(Vec::new()),
+                },
+                elements: s
+                    .get("elem")
+                    .and_then(Value::as_array)
+                    .map(|elems| elems.iter().map(|e| set_element("", e)).collect())
+                    .unwrap_or_default(),
+                flags: string_list(s.get("flags"))
+                    .iter()
+                    .filter_map(|f| match f.as_str() {
+                        "constant" => Some(SetFlag::Constant),
+                        "interval" => Some(SetFlag::Interval),
+                        "timeout" => Some(SetFlag::Timeout),
+                        "dynamic" => Some(SetFlag::Dynamic),
+                        _ => None,
+                    })
+                    .collect(),
+                timeout: s.get("timeout").and_then(Value::as_u64).map(|t| t as u32),
+                gc_interval: None,
+                size: s.get("size").and_then(Value::as_u64).map(|t| t as u32),
+                policy: None,
+            });
+        } else if let Some(r) = item.get("rule") {
+            let Some(t) = find(&mut tables, r) else {
+                continue;
+            };
+            let chain = str_field(r, "chain");
+            let rule = model_rule(r);
+            if let Some(chain) = tables[t].chains.iter_mut().find(|c| c.name == chain) {
+                chain.rules.push(rule);
+            }
+        }
+    }
+    Ok(tables)
+}
+
+fn table_family(name: &str) -> Result<TableFamily> {
+    Ok(match name {
+        "inet" => TableFamily::Inet,
+        "ip" => TableFamily::Ip,
+        "ip6" => TableFamily::Ip6,
+        "bridge" => TableFamily::Bridge,
+        "arp" => TableFamily::Arp,
+        "netdev" => TableFamily::Netdev,
+        other => bail!("unknown table family '{other}'"),
+    })
+}
+
+fn hook(name: &str) -> Option<Hook> {
+    Some(match name {
+        "prerouting" => Hook::Prerouting,
+        "input" => Hook::Input,
+        "forward" => Hook::Forward,
+        "output" => Hook::Output,
+        "postrouting" => Hook::Postrouting,
+        "ingress" => Hook::Ingress,
+        _ => return None,
+    })
+}
+
+fn model_rule(rule: &Value) -> NftRule {
+    let mut matches = Vec::new();
+    let mut statements = Vec::new();
+    let mut verdict = RuleVerdict::Continue;
+    for expr in rule
+        .get("expr")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    {
+        let Some((key, body)) = expr.as_object().and_then(|o| o.iter().next()) else {
+            continue;
+        };
+        match key.as_str() {
+            "match" => matches.extend(model_match(body).unwrap_or_else(|| {
+                vec![Match::Custom {
+                    expression: expr_text(expr).unwrap_or_else(|| unknown(expr)),
+                }]
+            })),
+            "counter" => match body.as_str() {
+                Some(name) => matches.push(Match::Counter {
+                    counter_name: name.to_string(),
+                }),
+                None => statements.push(Statement::Counter {
+                    packets: body.get("packets").and_then(Value::as_u64).unwrap_or(0),
+                    bytes: body.get("bytes").and_then(Value::as_u64).unwrap_or(0),
+                }),
+            },
+            "log" => statements.push(Statement::Log {
+                prefix: body
+                    .get("prefix")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                level: match body.get("level").and_then(Value::as_str) {
+                    Some("emerg") => LogLevel::Emergency,
+                    Some("alert") => LogLevel::Alert,
+                    Some("crit") => LogLevel::Critical,
+                    Some("err") => LogLevel::Error,
+                    Some("notice") => LogLevel::Notice,
+                    Some("info") => LogLevel::Info,
+                    Some("debug") => LogLevel::Debug,
+                    _ => LogLevel::Warning,
+                },
+                group: body.get("group").and_then(Value::as_u64).map(|g| g as u16),
+            }),
+            "mangle" if expr_text(expr).is_some_and(|t| t.starts_with("meta mark set ")) => {
+                if let Some(mark) = body.get("value").and_then(Value::as_u64) {
+                    statements.push(Statement::Mark {
+                        mark: mark as u32,
+                        mask: None,
+                    });
+                }
+            }
+            "masquerade" => statements.push(Statement::Masquerade { port_range: None }),
+            "snat" | "dnat" => {
+                let address = body
+                    .get("addr")
+                    .map(|a| value_text(a, false))
+                    .unwrap_or_default();
+                let port = body.get("port").and_then(Value::as_u64).map(|p| p as u16);
+                statements.push(if key == "snat" {
+                    Statement::Snat {
+                        address,
+                        port_range: port.map(|p| (p, p)),
+                    }
+                } else {
+                    Statement::Dnat { address, port }
+                });
+            }
+            "redirect" => statements.push(Statement::Redirect {
+                port: body.get("port").and_then(Value::as_u64).map(|p| p as u16),
+            }),
+            // Rate limits and quotas decide whether the rule matches.
+            "limit" | "quota" => matches.push(Match::Custom {
+                expression: expr_text(expr).unwrap_or_else(|| unknown(expr)),
+            }),
+            "accept" => verdict = RuleVerdict::Accept,
+            "drop" => verdict = RuleVerdict::Drop,
+            "return" => verdict = RuleVerdict::Return,
+            "reject" => {
+                verdict = RuleVerdict::Reject {
+                    reject_type: match (
+                        body.get("type").and_then(Value::as_str),
+                        body.get("expr").and_then(Value::as_str),
+                    ) {
+                        (Some("tcp reset"), _) => Some(RejectType::TcpReset),
+                        (_, Some("admin-prohibited")) => Some(RejectType::IcmpAdminProhibited),
+                        (_, Some("host-prohibited")) => Some(RejectType::IcmpHostProhibited),
+                        (_, Some("net-prohibited")) => Some(RejectType::IcmpNetProhibited),
+                        (_, Some("host-unreachable" | "addr-unreachable")) => {
+                            Some(RejectType::IcmpHostUnreach)
+                        }
+                        (_, Some("net-unreachable" | "no-route")) => {
+                            Some(RejectType::IcmpNetUnreach)
+                        }
+                        (_, Some("prot-unreachable")) => Some(RejectType::IcmpProtoUnreach),
+                        _ => None,
+                    },
+                }
+            }
+            "jump" | "goto" => {
+                let target = body
+                    .get("target")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                verdict = if key == "jump" {
+                    RuleVerdict::Jump { target }
+                } else {
+                    RuleVerdict::Goto { target }
+                };
+            }
+            "queue" => {
+                verdict = RuleVerdict::Queue {
+                    queue_num: body.get("num").and_then(Value::as_u64).unwrap_or(0) as u16,
+                }
+            }
+            // Verdict maps and other statements decide things the model
+            // cannot express; keep them visible as an unknown match.
+            "continue" => {}
+            _ if key == "vmap" || expr_text(expr).is_some_and(|t| t.starts_with('?')) => matches
+                .push(Match::Custom {
+                    expression: expr_text(expr).unwrap_or_else(|| unknown(expr)),
+                }),
+            _ => {}
+        }
+    }
+    NftRule {
+        handle: rule.get("handle").and_then(Value::as_u64),
+        position: None,
+        expression: RuleExpression {
+            matches,
+            statements,
+        },
+        verdict,
+        comment: rule
+            .get("comment")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        performance_hints: Vec::new(),
+    }
+}
+
+/// A JSON match as model matches; `None` when the model has no equivalent.
+fn model_match(m: &Value) -> Option<Vec<Match>> {
+    let op = m.get("op").and_then(Value::as_str).unwrap_or("==");
+    let negated = match op {
+        "==" | "in" => false,
+        "!=" => true,
+        _ => return None,
+    };
+    let left = m.get("left")?;
+    let right = m.get("right")?;
+    if left.get("&").is_some() {
+        let and = left.get("&")?.as_array()?;
+        if negated || selector(and.first()?)? != "meta mark" {
+            return None;
+        }
+        return Some(vec![Match::Mark {
+            mark: right.as_u64()? as u32,
+            mask: Some(and.get(1)?.as_u64()? as u32),
+        }]);
+    }
+    let sel = selector(left)?;
+    Some(match sel.as_str() {
+        "ip saddr" | "ip6 saddr" | "ip daddr" | "ip6 daddr" => {
+            let address = AddressMatch {
+                addresses: values(right)?,
+                negated,
+            };
+            if sel.ends_with("saddr") {
+                vec![Match::SourceAddress { address }]
+            } else {
+                vec![Match::DestinationAddress { address }]
+            }
+        }
+        "tcp sport" | "tcp dport" | "udp sport" | "udp dport" | "sctp sport" | "sctp dport"
+        | "th sport" | "th dport" => {
+            let ports = values(right)?
+                .iter()
+                .map(|v| {
+                    if let Some(set) = v.strip_prefix('@') {
+                        return Some(PortSpec::Set(set.to_string()));
+                    }
+                    match v.split_once('-') {
+                        Some((lo, hi)) => Some(PortSpec::Range(lo.parse().ok()?, hi.parse().ok()?)),
+                        None => Some(PortSpec::Single(v.parse().ok()?)),
+                    }
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let port = PortMatch { ports, negated };
+            let mut out = Vec::new();
+            match sel.split(' ').next() {
+                Some("tcp") => out.push(Match::Protocol {
+                    protocol: Protocol::Tcp,
+                }),
+                Some("udp") => out.push(Match::Protocol {
+                    protocol: Protocol::Udp,
+                }),
+                Some("sctp") => out.push(Match::Protocol {
+                    protocol: Protocol::Sctp,
+                }),
+                _ => {}
+            }
+            out.push(if sel.ends_with("sport") {
+                Match::SourcePort { port }
+            } else {
+                Match::DestinationPort { port }
+            });
+            out
+        }
+        "iifname" => vec![Match::Interface {
+            interface: InterfaceMatch {
+                interfaces: values(right)?,
+                negated,
+            },
+        }],
+        "ct state" if !negated => vec![Match::ConnectionState {
+            states: values(right)?
+                .iter()
+                .map(|s| match s.as_str() {
+                    "new" => Some(ConntrackState::New),
+                    "established" => Some(ConntrackState::Established),
+                    "related" => Some(ConntrackState::Related),
+                    "invalid" => Some(ConntrackState::Invalid),
+                    "untracked" => Some(ConntrackState::Untracked),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?,
+        }],
+        "meta l4proto" | "ip protocol" | "ip6 nexthdr" if !negated && right.is_string() => {
+            vec![Match::Protocol {
+                protocol: protocol(right.as_str()?)?,
+            }]
+        }
+        "icmp type" | "icmpv6 type" if !negated => {
+            let v6 = sel.starts_with("icmpv6");
+            let icmp_type = match right {
+                Value::String(name) => render::icmp_type_value(v6, name)?,
+                Value::Number(n) => n.as_u64()? as u8,
+                _ => return None,
+            };
+            vec![
+                Match::Protocol {
+                    protocol: if v6 { Protocol::Icmpv6 } else { Protocol::Icmp },
+                },
+                Match::IcmpType { icmp_type },
+            ]
+        }
+        "meta mark" if !negated => vec![Match::Mark {
+            mark: right.as_u64()? as u32,
+            mask: None,
+        }],
+        _ => return None,
+    })
+}
+
+/// Right-hand side values as strings: `@set`, `a/len`, `lo-hi` or scalars.
+fn values(v: &Value) -> Option<Vec<String>> {
+    match v {
+        Value::Array(items) => items
+            .iter()
+            .map(|i| values(i).map(|mut v| v.remove(0)))
+            .collect(),
+        Value::Object(o) if o.contains_key("set") => {
+            let mut out = Vec::new();
+            for item in o["set"].as_array()? {
+                out.extend(values(item)?);
+            }
+            Some(out)
+        }
+        Value::String(_) | Value::Number(_) | Value::Object(_) => {
+            let text = value_text(v, false);
+            (!text.starts_with('?')).then(|| vec![text])
+        }
+        _ => None,
+    }
+}
+
+fn protocol(name: &str) -> Option<Protocol> {
+    Some(match name {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        "icmp" => Protocol::Icmp,
+        "ipv6-icmp" | "icmpv6" => Protocol::Icmpv6,
+        "esp" => Protocol::Esp,
+        "ah" => Protocol::Ah,
+        "sctp" => Protocol::Sctp,
+        "gre" => Protocol::Gre,
+        other => Protocol::Number(other.parse().ok()?),
+    })
+}
+
+fn set_definition(set_type: &str, flags: &[String], timeout: Option<u64>) -> String {
+    let mut flags = flags.to_vec();
+    flags.sort();
+    let mut def = format!("type {set_type};");
+    if !flags.is_empty() {
+        def.push_str(&format!(" flags {};", flags.join(",")));
+    }
+    if let Some(timeout) = timeout {
+        def.push_str(&format!(" timeout {timeout}s;"));
+    }
+    def
+}
+
+fn sorted(mut values: Vec<String>) -> Vec<String> {
+    values.sort();
+    values.dedup();
+    values
+}
+
+fn str_field(v: &Value, key: &str) -> String {
+    v.get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// nft writes a single flag as a bare string and several as an array.
+fn string_list(v: Option<&Value>) -> Vec<String> {
+    match v {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn set_element(set_type: &str, elem: &Value) -> String {
+    // Elements with timeouts or counters come wrapped: {"elem": {"val": ..}}.
+    let elem = elem.get("elem").and_then(|e| e.get("val")).unwrap_or(elem);
+    match elem {
+        Value::String(s) if set_type == "ifname" => quote(s),
+        _ => value_text(elem, false),
+    }
+}
+
+pub(super) fn rule_text(rule: &Value) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(exprs) = rule.get("expr").and_then(Value::as_array) {
+        for expr in exprs {
+            if let Some(text) = expr_text(expr) {
+                parts.push(text);
+            }
+        }
+    }
+    if let Some(comment) = rule.get("comment").and_then(Value::as_str) {
+        parts.push(format!("comment {}", quote(comment)));
+    }
+    parts.join(" ")
+}
+
+/// One statement/expression; `None` for ones the renderer never writes
+/// (`continue`).
+fn expr_text(expr: &Value) -> Option<String> {
+    let Some((key, body)) = expr.as_object().and_then(|o| o.iter().next()) else {
+        return Some(unknown(expr));
+    };
+    Some(match key.as_str() {
+        "match" => match_text(body).unwrap_or_else(|| unknown(expr)),
+        "counter" => match body.as_str() {
+            Some(name) => format!("counter name {}", quote(name)),
+            None => "counter".to_string(),
+        },
+        "quota" if body.is_string() => format!("quota name {}", quote(body.as_str()?)),
+        "limit" if body.is_string() => format!("limit name {}", quote(body.as_str()?)),
+        "accept" | "drop" | "return" => key.clone(),
+        "continue" => return None,
+        "jump" | "goto" => format!("{key} {}", body.get("target")?.as_str()?),
+        "reject" => match body.get("type").and_then(Value::as_str) {
+            None => "reject".to_string(),
+            Some("tcp reset") => "reject with tcp reset".to_string(),
+            Some(kind) => match body.get("expr").and_then(Value::as_str) {
+                Some(code) => format!("reject with {kind} type {code}"),
+                None => format!("reject with {kind}"),
+            },
+        },
+        "log" => {
+            let mut s = String::from("log");
+            if let Some(prefix) = body.get("prefix").and_then(Value::as_str) {
+                s.push_str(&format!(" prefix {}", quote(prefix)));
+            }
+            if let Some(group) = body.get("group").and_then(Value::as_u64) {
+                s.push_str(&format!(" group {group}"));
+            } else if let Some(level) = body.get("level").and_then(Value::as_str)
+                && level != "warn"
+            {
+                s.push_str(&format!(" level {level}"));
+            }
+            s
+        }
+        "masquerade" => match body.get("port") {
+            Some(port) => format!("masquerade to :{}", value_text(port, false)),
+            None => "masquerade".to_string(),
+        },
+        "redirect" => match body.get("port") {
+            Some(port) => format!("redirect to :{}", value_text(port, false)),
+            None => "redirect".to_string(),
+        },
+        "snat" | "dnat" => {
+            let addr = body.get("addr").map(|a| value_text(a, false))?;
+            let family = body
+                .get("family")
+                .and_then(Value::as_str)
+                .unwrap_or(if addr.contains(':') { "ip6" } else { "ip" });
+            match body.get("port") {
+                Some(port) => {
+                    let host = if addr.contains(':') {
+                        format!("[{addr}]")
+                    } else {
+                        addr
+                    };
+                    format!("{key} {family} to {host}:{}", value_text(port, false))
+                }
+                None => format!("{key} {family} to {addr}"),
+            }
+        }
+        "mangle" => {
+            let target = selector(body.get("key")?)?;
+            let mark = target == "meta mark";
+            format!("{target} set {}", value_text(body.get("value")?, mark))
+        }
+        "queue" => format!("queue num {}", value_text(body.get("num")?, false)),
+        "dup" | "fwd" => format!("{key} to {}", quote(body.get("dev")?.as_str()?)),
+        "set" => {
+            let op = body.get("op")?.as_str()?;
+            let set = body.get("set")?.as_str()?;
+            format!("{op} {set} {{ {} }}", value_text(body.get("elem")?, false))
+        }
+        _ => unknown(expr),
+    })
+}
+
+fn match_text(m: &Value) -> Option<String> {
+    let op = m.get("op").and_then(Value::as_str).unwrap_or("==");
+    let left = m.get("left")?;
+    let right = m.get("right")?;
+
+    // `meta mark & 0xff == 0x1` keeps the explicit operator.
+    if let Some(and) = left.get("&").and_then(Value::as_array) {
+        let sel = selector(and.first()?)?;
+        let hex = sel == "meta mark";
+        let mask = value_text(and.get(1)?, hex);
+        let op = if op == "in" { "==" } else { op };
+        return Some(format!("{sel} & {mask} {op} {}", value_text(right, hex)));
+    }
+
+    let sel = selector(left)?;
+    let value = match sel.as_str() {
+        "iifname" | "oifname" => quoted_value(right),
+        "meta mark" => value_text(right, true),
+        "meta hour" => quoted_value(right),
+        "meta day" => quoted_value(right),
+        "ct state" => match right {
+            Value::Array(states) => {
+                let mut names: Vec<&str> = states.iter().filter_map(Value::as_str).collect();
+                names.sort_by_key(|n| ct_state_rank(n));
+                names.join(",")
+            }
+            _ => value_text(right, false),
+        },
+        _ => value_text(right, false),
+    };
+    let op = match op {
+        "==" | "in" => String::new(),
+        other => format!("{other} "),
+    };
+    Some(format!("{sel} {op}{value}"))
+}
+
+fn selector(v: &Value) -> Option<String> {
+    if let Some(meta) = v.get("meta") {
+        let key = meta.get("key")?.as_str()?;
+        return Some(match key {
+            "iifname" | "oifname" => key.to_string(),
+            _ => format!("meta {key}"),
+        });
+    }
+    if let Some(payload) = v.get("payload") {
+        return Some(format!(
+            "{} {}",
+            payload.get("protocol")?.as_str()?,
+            payload.get("field")?.as_str()?
+        ));
+    }
+    if let Some(ct) = v.get("ct") {
+        return Some(format!("ct {}", ct.get("key")?.as_str()?));
+    }
+    None
+}
+
+/// Interface names and time strings are quoted in nft syntax.
+fn quoted_value(v: &Value) -> String {
+    match v {
+        Value::String(s) if s.starts_with('@') => s.clone(),
+        Value::String(s) => quote(s),
+        Value::Object(o) if o.contains_key("set") => {
+            let items = o["set"]
+                .as_array()
+                .map(|items| items.iter().map(quoted_value).collect())
+                .unwrap_or_default();
+            anonymous_set(items)
+        }
+        Value::Object(o) if o.contains_key("range") => match o["range"].as_array() {
+            Some(r) if r.len() == 2 => format!("{}-{}", quoted_value(&r[0]), quoted_value(&r[1])),
+            _ => value_text(v, false),
+        },
+        _ => value_text(v, false),
+    }
+}
+
+fn value_text(v: &Value, hex: bool) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => match (hex, n.as_u64()) {
+            (true, Some(n)) => format!("0x{n:08x}"),
+            _ => n.to_string(),
+        },
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(|i| value_text(i, hex))
+            .collect::<Vec<_>>()
+            .join(","),
+        Value::Object(o) => {
+            if let Some(prefix) = o.get("prefix") {
+                let addr = prefix.get("addr").map(|a| value_text(a, false));
+                let len = prefix.get("len").and_then(Value::as_u64);
+                if let (Some(addr), Some(len)) = (addr, len) {
+                    return format!("{addr}/{len}");
+                }
+            }
+            if let Some(Value::Array(r)) = o.get("range")
+                && r.len() == 2
+            {
+                return format!("{}-{}", value_text(&r[0], hex), value_text(&r[1], hex));
+            }
+            if let Some(Value::Array(items)) = o.get("set") {
+                return anonymous_set(items.iter().map(|i| value_text(i, hex)).collect());
+            }
+            if let Some(elem) = o.get("elem").and_then(|e| e.get("val")) {
+                return value_text(elem, hex);
+            }
+            unknown(v)
+        }
+    }
+}
+
+fn unknown(v: &Value) -> String {
+    format!("?{v}")
+}
@@ -0,0 +1,867 @@
+//! `ghostctl firewall` - declarative host firewall.
+//!
+//! `firewall.toml` ([`spec`]) compiles into the `nftables_enterprise` model,
+//! which [`render`] turns into one `nft -f` script that atomically replaces
+//! the ghostctl table. `plan` compares that table against
+//! `nft -j list ruleset` ([`live`], [`diff`]); `apply` checks the script with
+//! `nft -c` and loads it under the confirm-or-revert
+//! [`guard`](crate::networking::guard). `import` translates an existing
+//! iptables, ufw or firewalld setup into the same model ([`import`]),
+//! `hits` tracks rule counters over time ([`hits`]), and `trace` walks a
+//! synthetic packet through the live ruleset ([`trace`]).
+
+pub mod diff;
+pub mod hits;
+pub mod import;
+pub mod live;
+pub mod render;
+pub mod spec;
+pub mod trace;
+
+use crate::command::CommandRunner;
+use crate::networking::guard::{self, Backend, Guard, GuardOptions};
+use crate::networking::nftables_enterprise::ConntrackState;
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use diff::Plan;
+use live::{Ruleset, Table};
+use spec::Policy;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub fn command() -> Command {
+    let file = Arg::new("file")
+        .long("file")
+        .short('f')
+        .value_name("PATH")
+        .help("Policy file (default: ~/.config/ghostctl/firewall.toml)");
+    Command::new("firewall")
+        .about("Declarative nftables firewall from firewall.toml")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("plan")
+                .about("Show what apply would change in the live ruleset")
+                .arg(file.clone())
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Load the policy as a single atomic nft transaction")
+                .arg(file.clone())
+                .arg(
+                    Arg::new("revert-after")
+                        .long("revert-after")
+                        .value_name("SECS")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("90")
+                        .help("Restore the previous ruleset unless confirmed within SECS"),
+                )
+                .arg(
+                    Arg::new("no-revert")
+                        .long("no-revert")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["revert-after", "detach"])
+                        .help("Apply without the automatic rollback timer"),
+                )
+                .arg(
+                    Arg::new("detach")
+                        .long("detach")
+                        .action(ArgAction::SetTrue)
+                        .help("Arm the rollback timer and return without waiting"),
+                ),
+        )
+        .subcommand(
+            Command::new("confirm").about("Keep a guarded change (run from a new connection)"),
+        )
+        .subcommand(
+            Command::new("revert").about("Restore the firewall saved before a guarded change"),
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Print the generated nft script")
+                .arg(file),
+        )
+        .subcommand(
+            Command::new("hits")
+                .about("Rule hit counters over time: dead rules, hot rules, ordering")
+                .subcommand_required(true)
+                .arg(
+                    Arg::new("log")
+                        .long("log")
+                        .value_name("PATH")
+                        .global(true)
+                        .help("Sample series (default: ~/.local/state/ghostctl/firewall/hits.jsonl)"),
+                )
+                .subcommand(
+                    Command::new("sample")
+                        .about("Record the current rule counters")
+                        .arg(
+                            Arg::new("textfile")
+                                .long("textfile")
+                                .value_name("FILE.prom")
+                                .help("Also write Prometheus metrics for node_exporter's textfile collector"),
+                        )
+                        .arg(
+                            Arg::new("keep-days")
+                                .long("keep-days")
+                                .value_name("DAYS")
+                                .value_parser(clap::value_parser!(u32))
+                                .default_value("90")
+                                .help("Drop samples older than DAYS"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("report")
+                        .about("Unused rules, hottest rules and reorder suggestions")
+                        .arg(
+                            Arg::new("days")
+                                .long("days")
+                                .value_name("DAYS")
+                                .value_parser(clap::value_parser!(u32).range(1..))
+                                .default_value("30")
+                                .help("Window; rules without hits for this long are reported"),
+                        )
+                        .arg(
+                            Arg::new("top")
+                                .long("top")
+                                .value_name("N")
+                                .value_parser(clap::value_parser!(usize))
+                                .default_value("10")
+                                .help("Number of hot rules and suggestions to show"),
+                        )
+                        .arg(
+                            Arg::new("json")
+                                .long("json")
+                                .action(ArgAction::SetTrue)
+                                .help("Output as JSON"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("Print the current counters as Prometheus metrics"),
+                )
+                .subcommand(
+                    Command::new("timer")
+                        .about("Install a systemd timer that samples as root")
+                        .arg(
+                            Arg::new("interval")
+                                .long("interval")
+                                .value_name("SPAN")
+                                .default_value("15min")
+                                .help("Sampling interval (systemd time span)"),
+                        )
+                        .arg(
+                            Arg::new("textfile")
+                                .long("textfile")
+                                .value_name("FILE.prom")
+                                .help("Refresh this node_exporter textfile on every sample"),
+                        )
+                        .arg(
+                            Arg::new("remove")
+                                .long("remove")
+                                .action(ArgAction::SetTrue)
+                                .conflicts_with_all(["interval", "textfile"])
+                                .help("Disable and remove the timer"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Translate an iptables, ufw or firewalld configuration to nftables")
+                .arg(
+                    Arg::new("path")
+                        .value_name("PATH")
+                        .help("iptables-save output, ufw status/user.rules, or firewalld zone XML (file or directory); default: this host"),
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .value_name("SOURCE")
+                        .value_parser(["iptables", "ufw", "firewalld"])
+                        .help("Source format (detected from PATH when omitted)"),
+                )
+                .arg(
+                    Arg::new("default-zone")
+                        .long("default-zone")
+                        .value_name("ZONE")
+                        .help("firewalld zone for traffic not bound to another zone"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("FILE")
+                        .help("Write the nft script to FILE"),
+                )
+                .arg(
+                    Arg::new("nft")
+                        .long("nft")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["output", "json"])
+                        .help("Print the nft script instead of the report"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output the report as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("trace")
+                .about("Simulate a packet against the live ruleset and show the rules it hits")
+                .arg(
+                    Arg::new("src")
+                        .long("src")
+                        .value_name("ADDR")
+                        .required(true)
+                        .help("Source address"),
+                )
+                .arg(
+                    Arg::new("dst")
+                        .long("dst")
+                        .value_name("ADDR")
+                        .required(true)
+                        .help("Destination address"),
+                )
+                .arg(
+                    Arg::new("proto")
+                        .long("proto")
+                        .value_name("PROTO")
+                        .default_value("tcp")
+                        .help("tcp, udp, icmp, icmpv6, sctp or a protocol number"),
+                )
+                .arg(
+                    Arg::new("sport")
+                        .long("sport")
+                        .value_name("PORT")
+                        .value_parser(clap::value_parser!(u16))
+                        .help("Source port"),
+                )
+                .arg(
+                    Arg::new("dport")
+                        .long("dport")
+                        .value_name("PORT")
+                        .value_parser(clap::value_parser!(u16))
+                        .help("Destination port"),
+                )
+                .arg(
+                    Arg::new("iif")
+                        .long("iif")
+                        .value_name("IFACE")
+                        .help("Interface the packet arrives on"),
+                )
+                .arg(
+                    Arg::new("oif")
+                        .long("oif")
+                        .value_name("IFACE")
+                        .help("Interface the packet leaves on"),
+                )
+                .arg(
+                    Arg::new("state")
+                        .long("state")
+                        .value_name("STATE")
+                        .value_parser(["new", "established", "related", "invalid", "untracked"])
+                        .default_value("new")
+                        .help("Assumed conntrack state"),
+                )
+                .arg(
+                    Arg::new("icmp-type")
+                        .long("icmp-type")
+                        .value_name("TYPE")
+                        .help("ICMP type name or number, e.g. echo-request"),
+                )
+                .arg(
+                    Arg::new("mark")
+                        .long("mark")
+                        .value_name("MARK")
+                        .value_parser(clap::value_parser!(u32))
+                        .help("Packet mark"),
+                )
+                .arg(
+                    Arg::new("direction")
+                        .long("direction")
+                        .value_name("DIR")
+                        .value_parser(["input", "forward", "output"])
+                        .help("Path through the host (detected from local addresses when omitted)"),
+                )
+                .arg(
+                    Arg::new("ruleset")
+                        .long("ruleset")
+                        .value_name("FILE")
+                        .help("Read nft -j list ruleset output from FILE instead of this host"),
+                )
+                .arg(
+                    Arg::new("live")
+                        .long("live")
+                        .value_name("SECS")
+                        .value_parser(clap::value_parser!(u64).range(1..=600))
+                        .conflicts_with("ruleset")
+                        .help("Also trace matching packets in the kernel for SECS seconds (nft monitor trace)"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output the trace as JSON"),
+                ),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let runner = crate::command::runner();
+    let path = |m: &ArgMatches| {
+        m.get_one::<String>("file")
+            .map(PathBuf::from)
+            .unwrap_or_else(spec::default_path)
+    };
+    match matches.subcommand() {
+        Some(("plan", m)) => {
+            let plan = plan(runner.as_ref(), &path(m))?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else {
+                diff::print_plan(&plan);
+            }
+            Ok(())
+        }
+        Some(("apply", m)) => {
+            let options = (!m.get_flag("no-revert")).then(|| GuardOptions {
+                timeout: Duration::from_secs(
+                    m.get_one::<u64>("revert-after")
+                        .copied()
+                        .unwrap_or(guard::DEFAULT_TIMEOUT_SECS),
+                ),
+                detach: m.get_flag("detach"),
+            });
+            apply(runner.as_ref(), &path(m), options.as_ref())?;
+            Ok(())
+        }
+        Some(("confirm", _)) => {
+            let pending = Guard::new(runner.as_ref()).confirm()?;
+            tui::success(&format!(
+                "Kept the {} change armed at {}",
+                pending.backend.name(),
+                pending.armed_at
+            ));
+            Ok(())
+        }
+        Some(("revert", _)) => {
+            let pending = Guard::new(runner.as_ref()).revert()?;
+            tui::success(&format!(
+                "Restored the {} configuration saved in {}",
+                pending.backend.name(),
+                pending.dir.display()
+            ));
+            Ok(())
+        }
+        Some(("render", m)) => {
+            let (_, script) = compile(&path(m))?;
+            print!("{script}");
+            Ok(())
+        }
+        Some(("hits", m)) => handle_hits(runner.as_ref(), m),
+        Some(("import", m)) => {
+            let options = import::ImportOptions {
+                source: m
+                    .get_one::<String>("from")
+                    .map(|s| import::Source::parse(s))
+                    .transpose()?,
+                default_zone: m.get_one::<String>("default-zone").map(String::as_str),
+            };
+            let mut imported = match m.get_one::<String>("path") {
+                Some(path) => import::from_path(Path::new(path), &options)?,
+                None => import::from_host(runner.as_ref(), &options)?,
+            };
+            let report = import::report(&mut imported);
+            if m.get_flag("nft") {
+                print!("{}", import::export(&imported)?);
+                return Ok(());
+            }
+            if let Some(output) = m.get_one::<String>("output") {
+                std::fs::write(output, import::export(&imported)?)
+                    .with_context(|| format!("failed to write {output}"))?;
+            }
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                import::print_report(&report);
+                if let Some(output) = m.get_one::<String>("output") {
+                    tui::success(&format!("Wrote {output}; check it with nft -c -f {output}"));
+                }
+            }
+            Ok(())
+        }
+        Some(("trace", m)) => handle_trace(runner.as_ref(), m),
+        _ => unreachable!("subcommand_required"),
+    }
+}
+
+fn handle_trace(runner: &dyn CommandRunner, matches: &ArgMatches) -> Result<()> {
+    let address = |name: &str| -> Result<std::net::IpAddr> {
+        let value = matches
+            .get_one::<String>(name)
+            .map(String::as_str)
+            .unwrap_or_default();
+        value
+            .parse()
+            .with_context(|| format!("--{name} '{value}' is not an IP address"))
+    };
+    let (src, dst) = (address("src")?, address("dst")?);
+    if src.is_ipv4() != dst.is_ipv4() {
+        bail!("--src and --dst must be the same address family");
+    }
+    let proto = matches
+        .get_one::<String>("proto")
+        .map(String::as_str)
+        .unwrap_or("tcp");
+    let mut packet = trace::Packet::new(src, dst, trace::protocol_number(proto)?);
+    packet.sport = matches.get_one::<u16>("sport").copied();
+    packet.dport = matches.get_one::<u16>("dport").copied();
+    packet.iif = matches.get_one::<String>("iif").cloned();
+    packet.oif = matches.get_one::<String>("oif").cloned();
+    packet.mark = matches.get_one::<u32>("mark").copied().unwrap_or(0);
+    packet.state = match matches.get_one::<String>("state").map(String::as_str) {
+        Some("established") => ConntrackState::Established,
+        Some("related") => ConntrackState::Related,
+        Some("invalid") => ConntrackState::Invalid,
+        Some("untracked") => ConntrackState::Untracked,
+        _ => ConntrackState::New,
+    };
+    if let Some(icmp) = matches.get_one::<String>("icmp-type") {
+        let v6 = packet.proto == 58;
+        packet.icmp_type = Some(match icmp.parse() {
+            Ok(n) => n,
+            Err(_) => render::icmp_type_value(v6, icmp)
+                .with_context(|| format!("unknown ICMP type '{icmp}'"))?,
+        });
+    }
+    let tables = match matches.get_one::<String>("ruleset") {
+        Some(file) => live::model(
+            &std::fs::read_to_string(file).with_context(|| format!("failed to read {file}"))?,
+        )?,
+        None => {
+            let out = runner
+                .run_sudo("nft", &["-j", "list", "ruleset"])
+                .context("failed to run nft")?;
+            if !out.success {
+                bail!("nft -j list ruleset failed: {}", out.stderr.trim());
+            }
+            live::model(&out.stdout)?
+        }
+    };
+    let local = runner
+        .run("ip", &["-j", "addr"])
+        .ok()
+        .filter(|out| out.success)
+        .map(|out| trace::local_addresses(&out.stdout))
+        .unwrap_or_default();
+    let forced = matches
+        .get_one::<String>("direction")
+        .map(|d| trace::Direction::parse(d))
+        .transpose()?;
+    let simulated = trace::simulate(&tables, &packet, &local, forced);
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&simulated)?);
+    } else {
+        trace::print_trace(&simulated);
+    }
+    let Some(secs) = matches.get_one::<u64>("live") else {
+        return Ok(());
+    };
+    let script = trace::confirm_script(&packet);
+    if is_dry_run() {
+        tui::info("[DRY RUN] Would load this trace table and run nft monitor trace:");
+        print!("{script}");
+        return Ok(());
+    }
+    nft_file(runner, &script, &[])?;
+    tui::info(&format!(
+        "Tracing matching packets for {secs}s - send one now (e.g. connect from {src})"
+    ));
+    let monitored = runner.run_sudo("timeout", &[&secs.to_string(), "nft", "monitor", "trace"]);
+    let removed = runner.run_sudo("nft", &["delete", "table", "inet", trace::TRACE_TABLE]);
+    if !removed.is_ok_and(|out| out.success) {
+        tui::warn(&format!(
+            "Could not remove the trace table; run: nft delete table inet {}",
+            trace::TRACE_TABLE
+        ));
+    }
+    let observed =
+        trace::summarize_monitor(&monitored.context("failed to run nft monitor")?.stdout);
+    if observed.is_empty() {
+        tui::warn("No matching packet was traced; the simulation is unconfirmed");
+        return Ok(());
+    }
+    for packet in &observed {
+        println!(
+            "Kernel trace {}: {} in {} ({})",
+            packet.id, packet.verdict, packet.chain, packet.rule
+        );
+    }
+    if observed
+        .iter()
+        .all(|o| o.verdict == simulated.verdict.name())
+    {
+        tui::success("The kernel agrees with the simulation");
+    } else {
+        tui::warn("The kernel reached a different verdict than the simulation");
+    }
+    Ok(())
+}
+
+fn handle_hits(runner: &dyn CommandRunner, matches: &ArgMatches) -> Result<()> {
+    let log = match matches.get_one::<String>("log") {
+        Some(path) => hits::HitLog::new(path),
+        None if matches.subcommand_name() == Some("report") => hits::HitLog::locate(),
+        None => hits::HitLog::new(hits::HitLog::default_path()),
+    };
+    match matches.subcommand() {
+        Some(("sample", m)) => {
+            let sample = hits::sample(runner)?;
+            log.append(&sample)?;
+            let keep = i64::from(m.get_one::<u32>("keep-days").copied().unwrap_or(90));
+            log.prune(chrono::Duration::days(keep), chrono::Utc::now())?;
+            if let Some(textfile) = m.get_one::<String>("textfile") {
+                hits::write_textfile(Path::new(textfile), &hits::prometheus(&sample))?;
+            }
+            println!(
+                "Recorded {} rule counters in {}",
+                sample.rules.len(),
+                log.path.display()
+            );
+            Ok(())
+        }
+        Some(("report", m)) => {
+            let options = hits::ReportOptions {
+                days: i64::from(m.get_one::<u32>("days").copied().unwrap_or(30)),
+                top: m.get_one::<usize>("top").copied().unwrap_or(10),
+            };
+            let report = hits::report(&log.load()?, chrono::Utc::now(), &options);
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                hits::print_report(&report);
+            }
+            Ok(())
+        }
+        Some(("export", _)) => {
+            print!("{}", hits::prometheus(&hits::sample(runner)?));
+            Ok(())
+        }
+        Some(("timer", m)) => {
+            if m.get_flag("remove") {
+                hits::remove_timer(runner)?;
+                tui::success("Removed the rule counter sampling timer");
+                return Ok(());
+            }
+            let interval = m
+                .get_one::<String>("interval")
+                .map(String::as_str)
+                .unwrap_or("15min");
+            let textfile = m.get_one::<String>("textfile").map(Path::new);
+            if is_dry_run() {
+                println!(
+                    "[DRY RUN] Would install {}.timer every {interval}",
+                    hits::TIMER_UNIT
+                );
+                return Ok(());
+            }
+            hits::install_timer(runner, interval, textfile)?;
+            tui::success(&format!(
+                "Sampling every {interval} into {}; see `ghostctl firewall hits report`",
+                hits::SYSTEM_LOG
+            ));
+            Ok(())
+        }
+        _ => unreachable!("subcommand_required"),
+    }
+}
+
+/// Load, compile and render a policy file.
+pub fn compile(path: &Path) -> Result<(crate::networking::nftables_enterprise::NftTable, String)> {
+    let policy = Policy::load(path)?;
+    let table = policy.compile()?;
+    let script = render::script(&table)?;
+    Ok((table, script))
+}
+
+pub fn live_ruleset(runner: &dyn CommandRunner) -> Result<Ruleset> {
+    let out = runner
+        .run_sudo("nft", &["-j", "list", "ruleset"])
+        .context("failed to run nft")?;
+    if !out.success {
+        bail!("nft -j list ruleset failed: {}", out.stderr.trim());
+    }
+    Ruleset::parse(&out.stdout)
+}
+
+pub fn plan(runner: &dyn CommandRunner, path: &Path) -> Result<Plan> {
+    let (table, _) = compile(path)?;
+    let desired = Table::from_model(&table)?;
+    Ok(diff::plan(&desired, &live_ruleset(runner)?))
+}
+
+/// Run `nft <args> -f <script>` with the script in a temporary file.
+pub fn nft_file(runner: &dyn CommandRunner, script: &str, args: &[&str]) -> Result<()> {
+    let mut file = tempfile::Builder::new()
+        .prefix("ghostctl-firewall-")
+        .suffix(".nft")
+        .tempfile()?;
+    file.write_all(script.as_bytes())?;
+    file.flush()?;
+    let path = file.path().to_string_lossy().to_string();
+    let mut full: Vec<&str> = args.to_vec();
+    full.extend(["-f", &path]);
+    let out = runner.run_sudo("nft", &full).context("failed to run nft")?;
+    if !out.success {
+        bail!("nft {} failed:\n{}", full.join(" "), out.stderr.trim());
+    }
+    Ok(())
+}
+
+/// Returns whether the ruleset was loaded (and, when guarded, kept).
+pub fn apply(
+    runner: &dyn CommandRunner,
+    path: &Path,
+    rollback: Option<&GuardOptions>,
+) -> Result<bool> {
+    let (table, script) = compile(path)?;
+    let desired = Table::from_model(&table)?;
+    let plan = diff::plan(&desired, &live_ruleset(runner)?);
+    diff::print_plan(&plan);
+    if !plan.has_changes() {
+        tui::success("Firewall is up to date");
+        return Ok(false);
+    }
+
+    nft_file(runner, &script, &["-c"]).context("policy rejected by nft --check")?;
+    if is_dry_run() {
+        println!("[DRY RUN] Would load the ruleset with nft -f");
+        return Ok(false);
+    }
+    if !tui::confirm("Apply this firewall policy?", true) {
+        return Ok(false);
+    }
+    let loaded = format!(
+        "Loaded table {} {}",
+        render::family_name(&table.family),
+        table.name
+    );
+    let Some(options) = rollback else {
+        nft_file(runner, &script, &[])?;
+        tui::success(&loaded);
+        return Ok(true);
+    };
+    let outcome = Guard::new(runner).protect(Backend::Nftables, options, || {
+        nft_file(runner, &script, &[])?;
+        tui::success(&loaded);
+        Ok(())
+    })?;
+    guard::report(Backend::Nftables, outcome);
+    Ok(outcome != guard::Outcome::Reverted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+    use crate::networking::nftables_enterprise::Statement;
+
+    const HOST_POLICY: &str = include_str!("testdata/host.toml");
+    const HOST_NFT: &str = include_str!("testdata/host.nft");
+    const HOST_LIVE: &str = include_str!("testdata/host-live.json");
+    const ROUTER_POLICY: &str = include_str!("testdata/router.toml");
+    const ROUTER_NFT: &str = include_str!("testdata/router.nft");
+
+    fn rendered(policy: &str) -> String {
+        let table = Policy::parse(policy).unwrap().compile().unwrap();
+        render::script(&table).unwrap()
+    }
+
+    #[test]
+    fn golden_host_policy() {
+        assert_eq!(rendered(HOST_POLICY), HOST_NFT);
+    }
+
+    #[test]
+    fn golden_router_policy() {
+        assert_eq!(rendered(ROUTER_POLICY), ROUTER_NFT);
+    }
+
+    #[test]
+    fn live_ruleset_of_applied_policy_has_no_changes() {
+        let table = Policy::parse(HOST_POLICY).unwrap().compile().unwrap();
+        let desired = Table::from_model(&table).unwrap();
+        let live = Ruleset::parse(HOST_LIVE).unwrap();
+        let plan = diff::plan(&desired, &live);
+        assert_eq!(plan.changes, Vec::new());
+        assert!(plan.exists);
+        assert_eq!(plan.notes.len(), 1, "docker's filter chain is reported");
+    }
+
+    #[test]
+    fn diff_reports_rule_and_element_changes() {
+        let edited = HOST_POLICY
+            .replace("\"192.168.1.0/24\"", "\"192.168.2.0/24\"")
+            .replace("dport = [80, 443]", "dport = 443");
+        let table = Policy::parse(&edited).unwrap().compile().unwrap();
+        let desired = Table::from_model(&table).unwrap();
+        let plan = diff::plan(&desired, &Ruleset::parse(HOST_LIVE).unwrap());
+        assert!(plan.changes.contains(&diff::Change::AddElements {
+            set: "admin".to_string(),
+            elements: vec!["192.168.2.0/24".to_string()],
+        }));
+        assert!(plan.changes.contains(&diff::Change::RemoveElements {
+            set: "admin".to_string(),
+            elements: vec!["192.168.1.0/24".to_string()],
+        }));
+        assert!(plan.changes.contains(&diff::Change::DeleteRule {
+            chain: "input".to_string(),
+            handle: Some(12),
+            rule: "tcp dport { 80, 443 } accept comment \"web\"".to_string(),
+        }));
+        assert!(plan.changes.contains(&diff::Change::AddRule {
+            chain: "input".to_string(),
+            rule: "tcp dport 443 accept comment \"web\"".to_string(),
+        }));
+        assert_eq!(plan.changes.len(), 4);
+    }
+
+    #[test]
+    fn empty_ruleset_creates_everything() {
+        let table = Policy::parse(HOST_POLICY).unwrap().compile().unwrap();
+        let desired = Table::from_model(&table).unwrap();
+        let plan = diff::plan(&desired, &Ruleset::parse(r#"{"nftables":[]}"#).unwrap());
+        assert_eq!(plan.changes[0], diff::Change::CreateTable);
+        let rules = plan
+            .changes
+            .iter()
+            .filter(|c| matches!(c, diff::Change::AddRule { .. }))
+            .count();
+        assert_eq!(
+            rules,
+            desired.chains.iter().map(|c| c.rules.len()).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn compile_rejects_bad_policies() {
+        for (policy, needle) in [
+            (
+                "[[rules]]\ndport = 22\naction = \"accept\"\n",
+                "ports need proto",
+            ),
+            (
+                "[[rules]]\nsaddr = \"@nope\"\naction = \"accept\"\n",
+                "unknown set @nope",
+            ),
+            (
+                "[[rules]]\naction = \"jump missing\"\n",
+                "jumps to 'missing'",
+            ),
+            (
+                "[[rules]]\nchain = \"postrouting\"\naction = \"accept\"\n",
+                "reserved for [[nat]]",
+            ),
+            (
+                "[[rules]]\nsaddr = \"10.0.0.0/8\"\ndaddr = \"fd00::/8\"\naction = \"accept\"\n",
+                "no address family in common",
+            ),
+            ("[defaults]\ninput = \"reject\"\n", "accept' or 'drop"),
+        ] {
+            let err = Policy::parse(policy)
+                .and_then(|p| p.compile())
+                .expect_err(policy);
+            assert!(format!("{err:#}").contains(needle), "{policy}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn compile_rejects_text_nft_cannot_quote() {
+        for (policy, needle) in [
+            (
+                r#"[[rules]]
+log = "x\" accept #"
+action = "drop"
+"#,
+                "invalid log prefix",
+            ),
+            (
+                r#"[[rules]]
+iif = "eth0\\"
+action = "accept"
+"#,
+                "invalid interface",
+            ),
+            (
+                r#"[[nat]]
+type = "masquerade"
+oif = "wan\n0"
+"#,
+                "invalid interface",
+            ),
+            (
+                r#"[[rules]]
+comment = "tab\there"
+action = "accept"
+"#,
+                "invalid comment",
+            ),
+            (
+                r#"[sets.lan]
+type = "ifname"
+elements = ["br\"0"]
+"#,
+                "invalid element",
+            ),
+        ] {
+            let err = Policy::parse(policy)
+                .and_then(|p| p.compile())
+                .expect_err(policy);
+            assert!(format!("{err:#}").contains(needle), "{policy}: {err:#}");
+        }
+
+        // Model fields that never pass through firewall.toml are checked at render.
+        let mut table = Policy::parse("").unwrap().compile().unwrap();
+        let rule = &mut table.chains[0].rules[0];
+        rule.expression.statements.push(Statement::Duplicate {
+            device: "eth0\" drop".to_string(),
+        });
+        let err = render::script(&table).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("nft strings cannot carry"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn apply_checks_then_loads_the_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("firewall.toml");
+        std::fs::write(&path, HOST_POLICY).unwrap();
+
+        let mock = MockRunner::as_root();
+        mock.mock_command(
+            "nft",
+            &["-j", "list", "ruleset"],
+            CommandResult::ok(r#"{"nftables":[]}"#),
+        );
+        assert!(apply(&mock, &path, None).unwrap());
+        let history = mock.get_history();
+        let check = history.iter().position(|c| c.starts_with("nft -c -f "));
+        let load = history.iter().position(|c| c.starts_with("nft -f "));
+        assert!(
+            check.is_some() && load.is_some() && check < load,
+            "{history:?}"
+        );
+    }
+}
@@ -0,0 +1,893 @@
+//! Render the `nftables_enterprise` model as nft(8) script syntax.
+//!
+//! The output is canonical: the same model always renders to byte-identical
+//! text, anonymous sets are sorted and de-duplicated, and prefixes are
+//! truncated to their network address the way the kernel stores them. The
+//! live-ruleset normaliser in [`super::live`] prints `nft -j` expressions in
+//! this exact form so both sides of a plan can be compared as strings.
+
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, FlowtableFlag, Hook, InterfaceMatch, LimitPer, LogLevel,
+    Match, NftChain, NftRule, NftTable, PortMatch, PortSpec, Protocol, RateUnit, RejectType,
+    RuleVerdict, SetFlag, SetOperation, SetPolicy, SetType, Statement, TableFamily,
+};
+use anyhow::{Context, Result, bail};
+use ipnet::IpNet;
+use std::fmt::Write as _;
+use std::net::IpAddr;
+
+/// Full `nft -f` script that atomically replaces `table`.
+///
+/// Declaring the table before deleting it makes the delete valid on a host
+/// where the table does not exist yet; nft runs the whole file as a single
+/// transaction, so traffic never sees a half-loaded ruleset.
+pub fn script(table: &NftTable) -> Result<String> {
+    let family = family_name(&table.family);
+    let mut out = String::new();
+    writeln!(out, "#!/usr/sbin/nft -f")?;
+    writeln!(
+        out,
+        "# Generated by ghostctl - do not edit, change firewall.toml instead"
+    )?;
+    writeln!(out)?;
+    writeln!(out, "table {family} {}", table.name)?;
+    writeln!(out, "delete table {family} {}", table.name)?;
+    writeln!(out)?;
+    out.push_str(&render_table(table)?);
+    Ok(out)
+}
+
+pub fn render_table(table: &NftTable) -> Result<String> {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "table {} {} {{",
+        family_name(&table.family),
+        table.name
+    )?;
+    let mut first = true;
+    let mut section = |out: &mut String| {
+        if !first {
+            out.push('\n');
+        }
+        first = false;
+    };
+
+    for set in &table.sets {
+        section(&mut out);
+        writeln!(out, "\tset {} {{", set.name)?;
+        writeln!(out, "\t\ttype {}", set_type_name(&set.set_type)?)?;
+        if !set.flags.is_empty() {
+            let flags: Vec<&str> = set.flags.iter().map(set_flag_name).collect();
+            writeln!(out, "\t\tflags {}", flags.join(","))?;
+        }
+        if let Some(timeout) = set.timeout {
+            writeln!(out, "\t\ttimeout {timeout}s")?;
+        }
+        if let Some(gc) = set.gc_interval {
+            writeln!(out, "\t\tgc-interval {gc}s")?;
+        }
+        if let Some(size) = set.size {
+            writeln!(out, "\t\tsize {size}")?;
+        }
+        if let Some(policy) = &set.policy {
+            writeln!(out, "\t\tpolicy {}", set_policy_name(policy))?;
+        }
+        let elements = set_elements(&set.set_type, &set.elements)?;
+        if !elements.is_empty() {
+            writeln!(out, "\t\telements = {{ {} }}", elements.join(", "))?;
+        }
+        writeln!(out, "\t}}")?;
+    }
+
+    for map in &table.maps {
+        section(&mut out);
+        writeln!(out, "\tmap {} {{", map.name)?;
+        writeln!(
+            out,
+            "\t\ttype {} : {}",
+            set_type_name(&map.key_type)?,
+            set_type_name(&map.value_type)?
+        )?;
+        if !map.flags.is_empty() {
+            let flags: Vec<&str> = map.flags.iter().map(set_flag_name).collect();
+            writeln!(out, "\t\tflags {}", flags.join(","))?;
+        }
+        if let Some(timeout) = map.timeout {
+            writeln!(out, "\t\ttimeout {timeout}s")?;
+        }
+        if let Some(size) = map.size {
+            writeln!(out, "\t\tsize {size}")?;
+        }
+        if !map.elements.is_empty() {
+            let mut elements: Vec<String> = map
+                .elements
+                .iter()
+                .map(|(k, v)| format!("{k} : {v}"))
+                .collect();
+            elements.sort();
+            writeln!(out, "\t\telements = {{ {} }}", elements.join(", "))?;
+        }
+        writeln!(out, "\t}}")?;
+    }
+
+    for counter in &table.counters {
+        section(&mut out);
+        writeln!(
+            out,
+            "\tcounter {} {{\n\t\tpackets {} bytes {}\n\t}}",
+            counter.name, counter.packets, counter.bytes
+        )?;
+    }
+
+    for quota in &table.quotas {
+        section(&mut out);
+        let over = if quota.over { "over " } else { "" };
+        writeln!(
+            out,
+            "\tquota {} {{\n\t\t{over}{} bytes used {} bytes\n\t}}",
+            quota.name, quota.bytes, quota.used
+        )?;
+    }
+
+    for limit in &table.limits {
+        if let Some(per) = &limit.per {
+            bail!(
+                "limit '{}': per-{} limits need a meter and cannot be a named object",
+                limit.name,
+                match per {
+                    LimitPer::SourceIp => "source",
+                    LimitPer::DestinationIp => "destination",
+                    LimitPer::SourcePort => "source-port",
+                    LimitPer::DestinationPort => "destination-port",
+                }
+            );
+        }
+        section(&mut out);
+        let burst = match (limit.burst, &limit.unit) {
+            (Some(b), RateUnit::PacketsPerSecond)
+            | (Some(b), RateUnit::PacketsPerMinute)
+            | (Some(b), RateUnit::PacketsPerHour) => format!(" burst {b} packets"),
+            (Some(b), _) => format!(" burst {b} bytes"),
+            (None, _) => String::new(),
+        };
+        writeln!(
+            out,
+            "\tlimit {} {{\n\t\trate {}{burst}\n\t}}",
+            limit.name,
+            rate(limit.rate, &limit.unit)
+        )?;
+    }
+
+    for flowtable in &table.flowtables {
+        section(&mut out);
+        writeln!(out, "\tflowtable {} {{", flowtable.name)?;
+        writeln!(
+            out,
+            "\t\thook {} priority {}",
+            hook_name(&flowtable.hook),
+            flowtable.priority
+        )?;
+        let devices = flowtable
+            .devices
+            .iter()
+            .map(|d| quote_checked(d))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("flowtable {} devices", flowtable.name))?;
+        writeln!(out, "\t\tdevices = {{ {} }}", devices.join(", "))?;
+        for flag in &flowtable.flags {
+            match flag {
+                FlowtableFlag::Offload => writeln!(out, "\t\tflags offload")?,
+            }
+        }
+        if flowtable.counter {
+            writeln!(out, "\t\tcounter")?;
+        }
+        writeln!(out, "\t}}")?;
+    }
+
+    for chain in &table.chains {
+        section(&mut out);
+        writeln!(out, "\tchain {} {{", chain.name)?;
+        if let Some(device) = &chain.device {
+            check_string(device).with_context(|| format!("chain {} device", chain.name))?;
+        }
+        if let Some(definition) = chain_definition(chain) {
+            writeln!(out, "\t\t{definition}")?;
+        }
+        for rule in &chain.rules {
+            writeln!(out, "\t\t{}", render_rule(table, rule)?)?;
+        }
+        writeln!(out, "\t}}")?;
+    }
+
+    writeln!(out, "}}")?;
+    Ok(out)
+}
+
+/// `type filter hook input priority 0; policy drop;` for base chains,
+/// `None` for regular (jump-target) chains.
+pub fn chain_definition(chain: &NftChain) -> Option<String> {
+    let hook = chain.hook.as_ref()?;
+    let mut def = format!(
+        "type {} hook {}",
+        chain_type_name(&chain.chain_type),
+        hook_name(hook)
+    );
+    if let Some(device) = &chain.device {
+        write!(def, " device {}", quote(device)).ok();
+    }
+    write!(def, " priority {};", chain.priority.unwrap_or(0)).ok();
+    if let Some(policy) = &chain.policy {
+        write!(def, " policy {};", policy_name(policy)).ok();
+    }
+    Some(def)
+}
+
+/// One rule as a single line of nft syntax: matches, statements, verdict and
+/// comment, in that order.
+pub fn render_rule(table: &NftTable, rule: &NftRule) -> Result<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let protocol = rule.expression.matches.iter().find_map(|m| match m {
+        Match::Protocol { protocol } => Some(protocol),
+        _ => None,
+    });
+    let has_ports = rule
+        .expression
+        .matches
+        .iter()
+        .any(|m| matches!(m, Match::SourcePort { .. } | Match::DestinationPort { .. }));
+    let has_icmp_type = rule
+        .expression
+        .matches
+        .iter()
+        .any(|m| matches!(m, Match::IcmpType { .. }));
+
+    for m in &rule.expression.matches {
+        match m {
+            Match::Protocol { protocol } => {
+                // `tcp dport 22` and `icmp type ...` imply the protocol, and
+                // nft drops the redundant l4proto match when listing.
+                if (has_ports && is_port_protocol(protocol))
+                    || (has_icmp_type && matches!(protocol, Protocol::Icmp | Protocol::Icmpv6))
+                {
+                    continue;
+                }
+                if let Some(name) = protocol_name(protocol) {
+                    parts.push(format!("meta l4proto {name}"));
+                }
+            }
+            Match::SourceAddress { address } => {
+                parts.push(address_match(table, "saddr", address)?);
+            }
+            Match::DestinationAddress { address } => {
+                parts.push(address_match(table, "daddr", address)?);
+            }
+            Match::SourcePort { port } => parts.push(port_match(protocol, "sport", port)?),
+            Match::DestinationPort { port } => parts.push(port_match(protocol, "dport", port)?),
+            Match::Interface { interface } => parts.push(interface_match("iifname", interface)?),
+            Match::ConnectionState { states } => {
+                let mut names: Vec<&str> = states.iter().map(ct_state_name).collect();
+                names.sort_by_key(|n| ct_state_rank(n));
+                names.dedup();
+                parts.push(format!("ct state {}", names.join(",")));
+            }
+            Match::Mark { mark, mask } => match mask {
+                Some(mask) => parts.push(format!("meta mark & 0x{mask:08x} == 0x{mark:08x}")),
+                None => parts.push(format!("meta mark 0x{mark:08x}")),
+            },
+            Match::TcpFlags { flags } => parts.push(format!(
+                "tcp flags & 0x{:02x} == 0x{:02x}",
+                flags.mask, flags.flags
+            )),
+            Match::IcmpType { icmp_type } => {
+                let v6 = matches!(protocol, Some(Protocol::Icmpv6));
+                let name = icmp_type_name(v6, *icmp_type)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| icmp_type.to_string());
+                parts.push(format!(
+                    "{} type {name}",
+                    if v6 { "icmpv6" } else { "icmp" }
+                ));
+            }
+            Match::Length { length } => match (length.min, length.max) {
+                (Some(min), Some(max)) => parts.push(format!("meta length {min}-{max}")),
+                (Some(min), None) => parts.push(format!("meta length >= {min}")),
+                (None, Some(max)) => parts.push(format!("meta length <= {max}")),
+                (None, None) => {}
+            },
+            Match::Dscp { dscp } => parts.push(format!("ip dscp {dscp}")),
+            Match::Set { set_name, .. } => bail!(
+                "a bare set match on '@{set_name}' has no selector; \
+                 reference the set from an address or port match instead"
+            ),
+            Match::Map { map_name, .. } => {
+                bail!("map lookups ('@{map_name}') cannot be rendered as a match")
+            }
+            Match::Counter { counter_name } => {
+                parts.push(format!("counter name {}", quote_checked(counter_name)?))
+            }
+            Match::Quota { quota_name } => {
+                parts.push(format!("quota name {}", quote_checked(quota_name)?))
+            }
+            Match::Limit { limit_name } => {
+                parts.push(format!("limit name {}", quote_checked(limit_name)?))
+            }
+            Match::Time { time_range } => {
+                if let (Some(start), Some(end)) = (&time_range.start_time, &time_range.end_time) {
+                    parts.push(format!(
+                        "meta hour {}-{}",
+                        quote_checked(start)?,
+                        quote_checked(end)?
+                    ));
+                }
+                if !time_range.days_of_week.is_empty() {
+                    let mut days = time_range.days_of_week.clone();
+                    days.sort_unstable();
+                    days.dedup();
+                    let names: Vec<String> = days
+                        .iter()
+                        .map(|d| quote(day_name(*d).unwrap_or("Sunday")))
+                        .collect();
+                    parts.push(if names.len() == 1 {
+                        format!("meta day {}", names[0])
+                    } else {
+                        format!("meta day {{ {} }}", names.join(", "))
+                    });
+                }
+            }
+            Match::Custom { expression } => parts.push(expression.trim().to_string()),
+        }
+    }
+
+    for statement in &rule.expression.statements {
+        parts.push(render_statement(statement)?);
+    }
+    if let Some(verdict) = render_verdict(&table.family, &rule.verdict)? {
+        parts.push(verdict);
+    }
+    if let Some(comment) = &rule.comment {
+        parts.push(format!(
+            "comment {}",
+            quote_checked(comment).context("rule comment")?
+        ));
+    }
+    if parts.is_empty() {
+        bail!("rule renders to nothing (no matches, statements or verdict)");
+    }
+    Ok(parts.join(" "))
+}
+
+fn render_statement(statement: &Statement) -> Result<String> {
+    Ok(match statement {
+        Statement::Log {
+            prefix,
+            level,
+            group,
+        } => {
+            let mut s = String::from("log");
+            if let Some(prefix) = prefix {
+                write!(
+                    s,
+                    " prefix {}",
+                    quote_checked(prefix).context("log prefix")?
+                )?;
+            }
+            if let Some(group) = group {
+                write!(s, " group {group}")?;
+            } else if !matches!(level, LogLevel::Warning) {
+                write!(s, " level {}", log_level_name(level))?;
+            }
+            s
+        }
+        // Packet/byte values are runtime state, never part of the policy.
+        Statement::Counter { .. } => "counter".to_string(),
+        Statement::Mark { mark, mask } => match mask {
+            Some(mask) => format!("meta mark set meta mark & 0x{mask:08x} | 0x{mark:08x}"),
+            None => format!("meta mark set 0x{mark:08x}"),
+        },
+        Statement::Dscp { dscp } => format!("ip dscp set {dscp}"),
+        Statement::Redirect { port } => match port {
+            Some(port) => format!("redirect to :{port}"),
+            None => "redirect".to_string(),
+        },
+        Statement::Masquerade { port_range } => match port_range {
+            Some((lo, hi)) => format!("masquerade to :{lo}-{hi}"),
+            None => "masquerade".to_string(),
+        },
+        Statement::Snat {
+            address,
+            port_range,
+        } => {
+            let family = nat_family(address)?;
+            match port_range {
+                Some((lo, hi)) => {
+                    format!("snat {family} to {}:{lo}-{hi}", nat_address(address))
+                }
+                None => format!("snat {family} to {address}"),
+            }
+        }
+        Statement::Dnat { address, port } => {
+            let family = nat_family(address)?;
+            match port {
+                Some(port) => format!("dnat {family} to {}:{port}", nat_address(address)),
+                None => format!("dnat {family} to {address}"),
+            }
+        }
+        Statement::Queue {
+            queue_num,
+            queue_total,
+        } => match queue_total {
+            Some(total) if *total > 1 => {
+                format!("queue num {queue_num}-{}", queue_num + total - 1)
+            }
+            _ => format!("queue num {queue_num}"),
+        },
+        Statement::Duplicate { device } => format!("dup to {}", quote_checked(device)?),
+        Statement::Fwd { device } => format!("fwd to {}", quote_checked(device)?),
+        Statement::Set {
+            set_name,
+            operation,
+            element,
+        } => {
+            let op = match operation {
+                SetOperation::Add => "add",
+                SetOperation::Update => "update",
+                SetOperation::Delete => "delete",
+                SetOperation::Lookup => {
+                    bail!("'lookup' is a match, not a statement (set @{set_name})")
+                }
+            };
+            format!("{op} @{set_name} {{ {element} }}")
+        }
+        Statement::Map { map_name, .. } => {
+            bail!("map statements ('@{map_name}') are not supported")
+        }
+    })
+}
+
+/// `None` for [`RuleVerdict::Continue`]: falling through is the implicit
+/// verdict, so statement-only rules (counters, NAT) use it.
+fn render_verdict(family: &TableFamily, verdict: &RuleVerdict) -> Result<Option<String>> {
+    Ok(Some(match verdict {
+        RuleVerdict::Accept => "accept".to_string(),
+        RuleVerdict::Drop => "drop".to_string(),
+        RuleVerdict::Reject { reject_type } => match reject_type {
+            None => "reject".to_string(),
+            Some(t) => format!("reject with {}", reject_type_name(family, t)?),
+        },
+        RuleVerdict::Queue { queue_num } => format!("queue num {queue_num}"),
+        RuleVerdict::Continue => return Ok(None),
+        RuleVerdict::Return => "return".to_string(),
+        RuleVerdict::Jump { target } => format!("jump {target}"),
+        RuleVerdict::Goto { target } => format!("goto {target}"),
+    }))
+}
+
+fn address_match(table: &NftTable, field: &str, address: &AddressMatch) -> Result<String> {
+    let Some(first) = address.addresses.first() else {
+        bail!("empty {field} match");
+    };
+    let v6 = if let Some(set) = first.strip_prefix('@') {
+        let Some(def) = table.sets.iter().find(|s| s.name == set) else {
+            bail!("{field} references unknown set @{set}");
+        };
+        match def.set_type {
+            SetType::Ipv4Address => false,
+            SetType::Ipv6Address => true,
+            _ => bail!("set @{set} is not an address set"),
+        }
+    } else {
+        first.contains(':')
+    };
+    let mut values = Vec::new();
+    for addr in &address.addresses {
+        if addr.starts_with('@') {
+            if address.addresses.len() > 1 {
+                bail!("a set reference cannot be combined with other addresses");
+            }
+            values.push(addr.clone());
+        } else {
+            let canonical = canonical_address(addr)?;
+            if canonical.contains(':') != v6 {
+                bail!("{field} mixes IPv4 and IPv6 addresses: {addr}");
+            }
+            values.push(canonical);
+        }
+    }
+    let op = if address.negated { "!= " } else { "" };
+    Ok(format!(
+        "{} {field} {op}{}",
+        if v6 { "ip6" } else { "ip" },
+        anonymous_set(values)
+    ))
+}
+
+fn port_match(protocol: Option<&Protocol>, field: &str, port: &PortMatch) -> Result<String> {
+    let proto = match protocol {
+        Some(p) if is_port_protocol(p) => protocol_name(p).unwrap_or("th"),
+        _ => "th",
+    };
+    let mut values = Vec::new();
+    for spec in &port.ports {
+        values.push(match spec {
+            PortSpec::Single(p) => p.to_string(),
+            PortSpec::Range(lo, hi) if lo == hi => lo.to_string(),
+            PortSpec::Range(lo, hi) if lo < hi => format!("{lo}-{hi}"),
+            PortSpec::Range(lo, hi) => bail!("invalid port range {lo}-{hi}"),
+            PortSpec::Set(name) => {
+                if port.ports.len() > 1 {
+                    bail!("a set reference cannot be combined with other ports");
+                }
+                format!("@{}", name.trim_start_matches('@'))
+            }
+        });
+    }
+    if values.is_empty() {
+        bail!("empty {field} match");
+    }
+    let op = if port.negated { "!= " } else { "" };
+    Ok(format!("{proto} {field} {op}{}", anonymous_set(values)))
+}
+
+fn interface_match(selector: &str, interface: &InterfaceMatch) -> Result<String> {
+    if interface.interfaces.is_empty() {
+        bail!("empty {selector} match");
+    }
+    let values = interface
+        .interfaces
+        .iter()
+        .map(|i| self::interface(i))
+        .collect::<Result<_>>()?;
+    let op = if interface.negated { "!= " } else { "" };
+    Ok(format!("{selector} {op}{}", anonymous_set(values)))
+}
+
+/// `value` or `{ a, b }`, sorted the way nft lists anonymous sets.
+pub fn anonymous_set(mut values: Vec<String>) -> String {
+    values.sort_by(|a, b| element_order(a).cmp(&element_order(b)).then(a.cmp(b)));
+    values.dedup();
+    if values.len() == 1 {
+        values.remove(0)
+    } else {
+        format!("{{ {} }}", values.join(", "))
+    }
+}
+
+/// Sort key for set elements: numbers and port ranges by their start value,
+/// everything else after them, lexically.
+fn element_order(value: &str) -> (u8, u64) {
+    let start = value.split('-').next().unwrap_or(value);
+    match start.parse::<u64>() {
+        Ok(n) => (0, n),
+        Err(_) => (1, 0),
+    }
+}
+
+/// Truncate a prefix to its network address and drop host-length prefixes,
+/// matching how nft lists them (`10.1.2.3/8` -> `10.0.0.0/8`, `1.2.3.4/32`
+/// -> `1.2.3.4`). Ranges (`a-b`) are passed through.
+pub fn canonical_address(addr: &str) -> Result<String> {
+    let addr = addr.trim();
+    if let Some((lo, hi)) = addr.split_once('-') {
+        let lo: IpAddr = lo.trim().parse()?;
+        let hi: IpAddr = hi.trim().parse()?;
+        return Ok(format!("{lo}-{hi}"));
+    }
+    if addr.contains('/') {
+        let net: IpNet = addr
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid prefix '{addr}'"))?;
+        let net = net.trunc();
+        if net.prefix_len() == net.max_prefix_len() {
+            return Ok(net.addr().to_string());
+        }
+        return Ok(net.to_string());
+    }
+    let ip: IpAddr = addr
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid address '{addr}'"))?;
+    Ok(ip.to_string())
+}
+
+fn set_elements(set_type: &SetType, elements: &[String]) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    for element in elements {
+        out.push(match set_type {
+            SetType::Ipv4Address | SetType::Ipv6Address => canonical_address(element)?,
+            SetType::IfName => quote_checked(element)?,
+            _ => element.trim().to_string(),
+        });
+    }
+    out.sort_by(|a, b| element_order(a).cmp(&element_order(b)).then(a.cmp(b)));
+    out.dedup();
+    Ok(out)
+}
+
+fn nat_family(address: &str) -> Result<&'static str> {
+    let host = nat_address(address);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => Ok("ip"),
+        Ok(IpAddr::V6(_)) => Ok("ip6"),
+        Err(_) => bail!("NAT target '{address}' is not an IP address"),
+    }
+}
+
+/// NAT targets are written `[v6]:port`, so bracket IPv6 hosts.
+fn nat_address(address: &str) -> String {
+    if address.contains(':') && !address.starts_with('[') {
+        format!("[{address}]")
+    } else {
+        address.to_string()
+    }
+}
+
+fn rate(rate: u32, unit: &RateUnit) -> String {
+    match unit {
+        RateUnit::PacketsPerSecond => format!("{rate}/second"),
+        RateUnit::PacketsPerMinute => format!("{rate}/minute"),
+        RateUnit::PacketsPerHour => format!("{rate}/hour"),
+        RateUnit::BytesPerSecond => format!("{rate} bytes/second"),
+        RateUnit::KilobytesPerSecond => format!("{rate} kbytes/second"),
+        RateUnit::MegabytesPerSecond => format!("{rate} mbytes/second"),
+    }
+}
+
+/// An interface name, or an `@set` of them.
+pub fn interface(name: &str) -> Result<String> {
+    if name.starts_with('@') {
+        Ok(name.to_string())
+    } else {
+        quote_checked(name)
+    }
+}
+
+/// Quote text as nft printed it (see `live`); anything headed into a
+/// ruleset goes through [`quote_checked`].
+pub fn quote(s: &str) -> String {
+    format!("\"{s}\"")
+}
+
+/// Quote a string for the ruleset after [`check_string`].
+pub fn quote_checked(s: &str) -> Result<String> {
+    check_string(s)?;
+    Ok(quote(s))
+}
+
+/// nft strings have no escapes: a `"` or `\` ends or corrupts the string and
+/// a control character breaks the ruleset line, so all of them are refused.
+pub fn check_string(s: &str) -> Result<()> {
+    if let Some(c) = s
+        .chars()
+        .find(|c| *c == '"' || *c == '\\' || c.is_control())
+    {
+        bail!("{s:?} contains {c:?}, which nft strings cannot carry");
+    }
+    Ok(())
+}
+
+pub fn family_name(family: &TableFamily) -> &'static str {
+    match family {
+        TableFamily::Inet => "inet",
+        TableFamily::Ip => "ip",
+        TableFamily::Ip6 => "ip6",
+        TableFamily::Bridge => "bridge",
+        TableFamily::Arp => "arp",
+        TableFamily::Netdev => "netdev",
+    }
+}
+
+pub fn chain_type_name(chain_type: &ChainType) -> &'static str {
+    match chain_type {
+        ChainType::Filter => "filter",
+        ChainType::Route => "route",
+        ChainType::Nat => "nat",
+    }
+}
+
+pub fn hook_name(hook: &Hook) -> &'static str {
+    match hook {
+        Hook::Prerouting => "prerouting",
+        Hook::Input => "input",
+        Hook::Forward => "forward",
+        Hook::Output => "output",
+        Hook::Postrouting => "postrouting",
+        Hook::Ingress => "ingress",
+        Hook::Egress => "egress",
+    }
+}
+
+pub fn policy_name(policy: &ChainPolicy) -> &'static str {
+    match policy {
+        ChainPolicy::Accept => "accept",
+        ChainPolicy::Drop => "drop",
+    }
+}
+
+fn is_port_protocol(protocol: &Protocol) -> bool {
+    matches!(protocol, Protocol::Tcp | Protocol::Udp | Protocol::Sctp)
+}
+
+/// Protocol names as nft prints them; `None` for [`Protocol::Any`].
+pub fn protocol_name(protocol: &Protocol) -> Option<&'static str> {
+    Some(match protocol {
+        Protocol::Tcp => "tcp",
+        Protocol::Udp => "udp",
+        Protocol::Icmp => "icmp",
+        Protocol::Icmpv6 => "ipv6-icmp",
+        Protocol::Esp => "esp",
+        Protocol::Ah => "ah",
+        Protocol::Sctp => "sctp",
+        Protocol::Gre => "gre",
+        Protocol::Any => return None,
+        Protocol::Number(n) => protocol_number_name(*n)?,
+    })
+}
+
+/// nft prints well-known protocol numbers by name, so `Number(6)` must render
+/// as `tcp` to compare equal with the live ruleset.
+fn protocol_number_name(n: u8) -> Option<&'static str> {
+    Some(match n {
+        1 => "icmp",
+        6 => "tcp",
+        17 => "udp",
+        47 => "gre",
+        50 => "esp",
+        51 => "ah",
+        58 => "ipv6-icmp",
+        132 => "sctp",
+        _ => return None,
+    })
+}
+
+pub fn ct_state_name(
+    state: &crate::networking::nftables_enterprise::ConntrackState,
+) -> &'static str {
+    use crate::networking::nftables_enterprise::ConntrackState;
+    match state {
+        ConntrackState::New => "new",
+        ConntrackState::Established => "established",
+        ConntrackState::Related => "related",
+        ConntrackState::Invalid => "invalid",
+        ConntrackState::Untracked => "untracked",
+    }
+}
+
+/// nft lists ct states in bit order: invalid, established, related, new,
+/// untracked.
+pub fn ct_state_rank(name: &str) -> u8 {
+    match name {
+        "invalid" => 0,
+        "established" => 1,
+        "related" => 2,
+        "new" => 3,
+        "untracked" => 6,
+        _ => 9,
+    }
+}
+
+const ICMP_TYPES: &[(u8, &str)] = &[
+    (0, "echo-reply"),
+    (3, "destination-unreachable"),
+    (5, "redirect"),
+    (8, "echo-request"),
+    (9, "router-advertisement"),
+    (10, "router-solicitation"),
+    (11, "time-exceeded"),
+    (12, "parameter-problem"),
+    (13, "timestamp-request"),
+    (14, "timestamp-reply"),
+];
+
+const ICMPV6_TYPES: &[(u8, &str)] = &[
+    (1, "destination-unreachable"),
+    (2, "packet-too-big"),
+    (3, "time-exceeded"),
+    (4, "parameter-problem"),
+    (128, "echo-request"),
+    (129, "echo-reply"),
+    (133, "nd-router-solicit"),
+    (134, "nd-router-advert"),
+    (135, "nd-neighbor-solicit"),
+    (136, "nd-neighbor-advert"),
+    (137, "nd-redirect"),
+];
+
+pub fn icmp_type_name(v6: bool, value: u8) -> Option<&'static str> {
+    let table = if v6 { ICMPV6_TYPES } else { ICMP_TYPES };
+    table.iter().find(|(n, _)| *n == value).map(|(_, s)| *s)
+}
+
+pub fn icmp_type_value(v6: bool, name: &str) -> Option<u8> {
+    let table = if v6 { ICMPV6_TYPES } else { ICMP_TYPES };
+    table.iter().find(|(_, s)| *s == name).map(|(n, _)| *n)
+}
+
+fn day_name(day: u8) -> Option<&'static str> {
+    [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ]
+    .get(day as usize)
+    .copied()
+}
+
+fn log_level_name(level: &LogLevel) -> &'static str {
+    match level {
+        LogLevel::Emergency => "emerg",
+        LogLevel::Alert => "alert",
+        LogLevel::Critical => "crit",
+        LogLevel::Error => "err",
+        LogLevel::Warning => "warn",
+        LogLevel::Notice => "notice",
+        LogLevel::Info => "info",
+        LogLevel::Debug => "debug",
+    }
+}
+
+/// icmpx codes only exist in the inet family; ip and ip6 tables need the
+/// family's own ICMP type.
+fn reject_type_name(family: &TableFamily, reject: &RejectType) -> Result<&'static str> {
+    Ok(match (family, reject) {
+        (_, RejectType::TcpReset) => "tcp reset",
+        (TableFamily::Ip, RejectType::IcmpUnreach) => "icmp type net-unreachable",
+        (TableFamily::Ip, RejectType::IcmpHostUnreach) => "icmp type host-unreachable",
+        (TableFamily::Ip, RejectType::IcmpPortUnreach) => "icmp type port-unreachable",
+        (TableFamily::Ip, RejectType::IcmpAdminProhibited) => "icmp type admin-prohibited",
+        (TableFamily::Ip6, RejectType::IcmpUnreach) => "icmpv6 type no-route",
+        (TableFamily::Ip6, RejectType::IcmpHostUnreach) => "icmpv6 type addr-unreachable",
+        (TableFamily::Ip6, RejectType::IcmpPortUnreach) => "icmpv6 type port-unreachable",
+        (TableFamily::Ip6, RejectType::IcmpAdminProhibited) => "icmpv6 type admin-prohibited",
+        (TableFamily::Ip6, other) => bail!("{other:?} has no ICMPv6 equivalent"),
+        (_, RejectType::IcmpUnreach) => "icmpx type no-route",
+        (_, RejectType::IcmpHostUnreach) => "icmpx type host-unreachable",
+        (_, RejectType::IcmpPortUnreach) => "icmpx type port-unreachable",
+        (_, RejectType::IcmpAdminProhibited) => "icmpx type admin-prohibited",
+        (_, RejectType::IcmpProtoUnreach) => "icmp type prot-unreachable",
+        (_, RejectType::IcmpNetUnreach) => "icmp type net-unreachable",
+        (_, RejectType::IcmpHostProhibited) => "icmp type host-prohibited",
+        (_, RejectType::IcmpNetProhibited) => "icmp type net-prohibited",
+    })
+}
+
+pub fn set_type_name(set_type: &SetType) -> Result<String> {
+    Ok(match set_type {
+        SetType::Ipv4Address => "ipv4_addr".to_string(),
+        SetType::Ipv6Address => "ipv6_addr".to_string(),
+        SetType::EthernetAddress => "ether_addr".to_string(),
+        SetType::InetProtocol => "inet_proto".to_string(),
+        SetType::InetService => "inet_service".to_string(),
+        SetType::Mark => "mark".to_string(),
+        SetType::IfName => "ifname".to_string(),
+        SetType::Verdict => "verdict".to_string(),
+        SetType::Counter | SetType::Quota => {
+            bail!("counter and quota are object types, not set key types")
+        }
+        SetType::Composite(parts) => {
+            let names: Result<Vec<String>> = parts.iter().map(set_type_name).collect();
+            names?.join(" . ")
+        }
+    })
+}
+
+pub fn set_flag_name(flag: &SetFlag) -> &'static str {
+    match flag {
+        SetFlag::Constant => "constant",
+        SetFlag::Interval => "interval",
+        SetFlag::Timeout => "timeout",
+        SetFlag::Dynamic => "dynamic",
+    }
+}
+
+fn set_policy_name(policy: &SetPolicy) -> &'static str {
+    match policy {
+        SetPolicy::Performance => "performance",
+        SetPolicy::Memory => "memory",
+    }
+}
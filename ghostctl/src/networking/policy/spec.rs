@@ -0,0 +1,886 @@
+//! `firewall.toml`: the declarative host firewall policy.
+//!
+//! ```toml
+//! table = "ghostctl"
+//!
+//! [defaults]
+//! input = "drop"
+//! forward = "drop"
+//! output = "accept"
+//!
+//! [sets.admin]
+//! type = "ipv4_addr"
+//! elements = ["192.168.1.0/24", "10.8.0.5"]
+//!
+//! [[rules]]
+//! chain = "input"
+//! proto = "tcp"
+//! dport = 22
+//! saddr = "@admin"
+//! action = "accept"
+//! comment = "ssh from admin hosts"
+//!
+//! [[nat]]
+//! type = "masquerade"
+//! oif = "wan0"
+//! ```
+//!
+//! The policy compiles into a single inet [`NftTable`] owned by ghostctl;
+//! tables created by docker, libvirt or iptables-nft are left alone.
+
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, ConntrackState, Hook, InterfaceMatch, LogLevel, Match,
+    NftChain, NftRule, NftSet, NftTable, PortMatch, PortSpec, Protocol, RuleExpression,
+    RuleVerdict, SetFlag, SetType, Statement, TableFamily,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const BASE_CHAINS: [&str; 3] = ["input", "forward", "output"];
+const NAT_CHAINS: [&str; 2] = ["prerouting", "postrouting"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// nft table the policy owns (always in the inet family).
+    #[serde(default = "default_table")]
+    pub table: String,
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub sets: BTreeMap<String, SetSpec>,
+    #[serde(default)]
+    pub rules: Vec<RuleSpec>,
+    #[serde(default)]
+    pub nat: Vec<NatSpec>,
+}
+
+fn default_table() -> String {
+    "ghostctl".to_string()
+}
+
+/// Chain policies and the boilerplate rules every host wants first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    pub input: String,
+    pub forward: String,
+    pub output: String,
+    /// Accept established/related traffic in input and forward.
+    pub established: bool,
+    /// Drop conntrack-invalid packets in input and forward.
+    pub drop_invalid: bool,
+    /// Accept everything arriving on `lo`.
+    pub loopback: bool,
+    /// Accept ICMP and ICMPv6 in input (IPv6 breaks without neighbour
+    /// discovery).
+    pub icmp: bool,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            input: "drop".to_string(),
+            forward: "drop".to_string(),
+            output: "accept".to_string(),
+            established: true,
+            drop_invalid: true,
+            loopback: true,
+            icmp: true,
+        }
+    }
+}
+
+/// A named set (`[sets.<name>]`), referenced from rules as `@<name>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetSpec {
+    /// `ipv4_addr`, `ipv6_addr`, `inet_service`, `ifname` or `mark`.
+    #[serde(rename = "type")]
+    pub set_type: String,
+    #[serde(default)]
+    pub elements: Vec<Scalar>,
+    /// Element timeout in seconds (adds the `timeout` flag).
+    #[serde(default)]
+    pub timeout: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSpec {
+    /// `input`, `forward`, `output`, or the name of a regular chain reached
+    /// with `action = "jump <name>"`.
+    #[serde(default = "default_chain")]
+    pub chain: String,
+    #[serde(default)]
+    pub proto: OneOrMany<String>,
+    #[serde(default)]
+    pub saddr: OneOrMany<String>,
+    #[serde(default)]
+    pub daddr: OneOrMany<String>,
+    #[serde(default)]
+    pub sport: OneOrMany<Scalar>,
+    #[serde(default)]
+    pub dport: OneOrMany<Scalar>,
+    #[serde(default)]
+    pub iif: OneOrMany<String>,
+    #[serde(default)]
+    pub oif: OneOrMany<String>,
+    #[serde(default)]
+    pub ct_state: OneOrMany<String>,
+    #[serde(default)]
+    pub icmp_type: Option<String>,
+    /// Log prefix; logs the packet before the verdict.
+    #[serde(default)]
+    pub log: Option<String>,
+    #[serde(default)]
+    pub counter: bool,
+    /// `accept`, `drop`, `reject`, `return`, `jump <chain>` or `goto <chain>`.
+    pub action: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+fn default_chain() -> String {
+    "input".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NatSpec {
+    /// `masquerade`, `snat`, `dnat` or `redirect`.
+    #[serde(rename = "type")]
+    pub nat_type: String,
+    #[serde(default)]
+    pub proto: OneOrMany<String>,
+    #[serde(default)]
+    pub saddr: OneOrMany<String>,
+    #[serde(default)]
+    pub daddr: OneOrMany<String>,
+    #[serde(default)]
+    pub dport: OneOrMany<Scalar>,
+    #[serde(default)]
+    pub iif: OneOrMany<String>,
+    #[serde(default)]
+    pub oif: OneOrMany<String>,
+    /// `addr`, `addr:port` or (for redirect) `port`.
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// TOML lets a field be a bare value or a list; both mean the same.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        OneOrMany::Many(Vec::new())
+    }
+}
+
+impl<T: Clone> OneOrMany<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        match self {
+            OneOrMany::One(v) => vec![v.clone()],
+            OneOrMany::Many(v) => v.clone(),
+        }
+    }
+}
+
+/// A port or set element: `22`, `"8000-8100"`, `"@web"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+    Number(u64),
+    Text(String),
+}
+
+impl std::fmt::Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::Number(n) => write!(f, "{n}"),
+            Scalar::Text(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// `~/.config/ghostctl/firewall.toml`, next to config.toml.
+pub fn default_path() -> PathBuf {
+    crate::config::GhostConfig::config_path().with_file_name("firewall.toml")
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("invalid policy {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let policy: Policy = toml::from_str(content)?;
+        Ok(policy)
+    }
+
+    /// Compile into the enterprise nftables model.
+    pub fn compile(&self) -> Result<NftTable> {
+        if !is_identifier(&self.table) {
+            bail!("invalid table name '{}'", self.table);
+        }
+
+        let mut sets = Vec::new();
+        for (name, spec) in &self.sets {
+            sets.push(compile_set(name, spec).with_context(|| format!("set '{name}'"))?);
+        }
+
+        let mut chains: Vec<NftChain> = Vec::new();
+        for (name, hook, policy) in [
+            ("input", Hook::Input, &self.defaults.input),
+            ("forward", Hook::Forward, &self.defaults.forward),
+            ("output", Hook::Output, &self.defaults.output),
+        ] {
+            let mut chain = base_chain(name, ChainType::Filter, hook, 0);
+            chain.policy = Some(chain_policy(policy).with_context(|| format!("defaults.{name}"))?);
+            chain.rules = self.default_rules(name);
+            chains.push(chain);
+        }
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            let context = || format!("rules[{i}] ({} {})", rule.chain, rule.action);
+            if NAT_CHAINS.contains(&rule.chain.as_str()) {
+                bail!(
+                    "{}: chain '{}' is reserved for [[nat]]",
+                    context(),
+                    rule.chain
+                );
+            }
+            if !is_identifier(&rule.chain) {
+                bail!("{}: invalid chain name", context());
+            }
+            let compiled = compile_rule(rule, &sets).with_context(context)?;
+            let index = match chains.iter().position(|c| c.name == rule.chain) {
+                Some(index) => index,
+                None => {
+                    chains.push(regular_chain(&rule.chain));
+                    chains.len() - 1
+                }
+            };
+            chains[index].rules.extend(compiled);
+        }
+
+        for chain in &chains {
+            for rule in &chain.rules {
+                if let RuleVerdict::Jump { target } | RuleVerdict::Goto { target } = &rule.verdict
+                    && !chains.iter().any(|c| &c.name == target && c.hook.is_none())
+                {
+                    bail!(
+                        "chain '{}' jumps to '{target}', which has no rules",
+                        chain.name
+                    );
+                }
+            }
+        }
+
+        if !self.nat.is_empty() {
+            let mut prerouting = base_chain("prerouting", ChainType::Nat, Hook::Prerouting, -100);
+            let mut postrouting = base_chain("postrouting", ChainType::Nat, Hook::Postrouting, 100);
+            for (i, nat) in self.nat.iter().enumerate() {
+                let rules = compile_nat(nat, &sets)
+                    .with_context(|| format!("nat[{i}] ({})", nat.nat_type))?;
+                match nat.nat_type.as_str() {
+                    "dnat" | "redirect" => prerouting.rules.extend(rules),
+                    _ => postrouting.rules.extend(rules),
+                }
+            }
+            for chain in [prerouting, postrouting] {
+                if !chain.rules.is_empty() {
+                    chains.push(chain);
+                }
+            }
+        }
+
+        Ok(NftTable {
+            name: self.table.clone(),
+            family: TableFamily::Inet,
+            chains,
+            sets,
+            maps: Vec::new(),
+            flowtables: Vec::new(),
+            counters: Vec::new(),
+            quotas: Vec::new(),
+            limits: Vec::new(),
+        })
+    }
+
+    fn default_rules(&self, chain: &str) -> Vec<NftRule> {
+        let d = &self.defaults;
+        let mut rules = Vec::new();
+        if chain == "output" {
+            return rules;
+        }
+        if d.established {
+            rules.push(rule(
+                vec![Match::ConnectionState {
+                    states: vec![ConntrackState::Established, ConntrackState::Related],
+                }],
+                RuleVerdict::Accept,
+            ));
+        }
+        if d.drop_invalid {
+            rules.push(rule(
+                vec![Match::ConnectionState {
+                    states: vec![ConntrackState::Invalid],
+                }],
+                RuleVerdict::Drop,
+            ));
+        }
+        if chain == "input" {
+            if d.loopback {
+                rules.push(rule(
+                    vec![Match::Interface {
+                        interface: InterfaceMatch {
+                            interfaces: vec!["lo".to_string()],
+                            negated: false,
+                        },
+                    }],
+                    RuleVerdict::Accept,
+                ));
+            }
+            if d.icmp {
+                for protocol in [Protocol::Icmp, Protocol::Icmpv6] {
+                    rules.push(rule(
+                        vec![Match::Protocol { protocol }],
+                        RuleVerdict::Accept,
+                    ));
+                }
+            }
+        }
+        rules
+    }
+}
+
+pub(super) fn rule(matches: Vec<Match>, verdict: RuleVerdict) -> NftRule {
+    NftRule {
+        handle: None,
+        position: None,
+        expression: RuleExpression {
+            matches,
+            statements: Vec::new(),
+        },
+        verdict,
+        comment: None,
+        performance_hints: Vec::new(),
+    }
+}
+
+pub(super) fn base_chain(name: &str, chain_type: ChainType, hook: Hook, priority: i32) -> NftChain {
+    NftChain {
+        name: name.to_string(),
+        chain_type,
+        hook: Some(hook),
+        priority: Some(priority),
+        policy: None,
+        rules: Vec::new(),
+        device: None,
+    }
+}
+
+pub(super) fn regular_chain(name: &str) -> NftChain {
+    NftChain {
+        name: name.to_string(),
+        chain_type: ChainType::Filter,
+        hook: None,
+        priority: None,
+        policy: None,
+        rules: Vec::new(),
+        device: None,
+    }
+}
+
+fn chain_policy(value: &str) -> Result<ChainPolicy> {
+    match value {
+        "accept" => Ok(ChainPolicy::Accept),
+        "drop" => Ok(ChainPolicy::Drop),
+        other => bail!("chain policy must be 'accept' or 'drop', not '{other}'"),
+    }
+}
+
+fn compile_set(name: &str, spec: &SetSpec) -> Result<NftSet> {
+    if !is_identifier(name) {
+        bail!("invalid set name");
+    }
+    let set_type = match spec.set_type.as_str() {
+        "ipv4_addr" => SetType::Ipv4Address,
+        "ipv6_addr" => SetType::Ipv6Address,
+        "inet_service" => SetType::InetService,
+        "ifname" => SetType::IfName,
+        "mark" => SetType::Mark,
+        other => bail!("unsupported set type '{other}'"),
+    };
+    let elements: Vec<String> = spec.elements.iter().map(|e| e.to_string()).collect();
+    for element in &elements {
+        match set_type {
+            SetType::Ipv4Address | SetType::Ipv6Address => {
+                let canonical = super::render::canonical_address(element)?;
+                if canonical.contains(':') != matches!(set_type, SetType::Ipv6Address) {
+                    bail!(
+                        "element {element} does not match set type {}",
+                        spec.set_type
+                    );
+                }
+            }
+            SetType::InetService => {
+                parse_port(element)?;
+            }
+            SetType::IfName => check_text("element", element)?,
+            _ => {}
+        }
+    }
+    let mut flags = Vec::new();
+    let interval = match set_type {
+        SetType::Ipv4Address | SetType::Ipv6Address => {
+            elements.iter().any(|e| e.contains('/') || e.contains('-'))
+        }
+        SetType::InetService => elements.iter().any(|e| e.contains('-')),
+        _ => false,
+    };
+    if interval {
+        flags.push(SetFlag::Interval);
+    }
+    if spec.timeout.is_some() {
+        flags.push(SetFlag::Timeout);
+    }
+    Ok(NftSet {
+        name: name.to_string(),
+        set_type,
+        elements,
+        flags,
+        timeout: spec.timeout,
+        gc_interval: None,
+        size: None,
+        policy: None,
+    })
+}
+
+/// One policy rule can expand to several nft rules: one per protocol in
+/// `proto`, and one per address family when `saddr`/`daddr` mix IPv4 and
+/// IPv6 (an inet rule matches `ip saddr` or `ip6 saddr`, never both).
+fn compile_rule(spec: &RuleSpec, sets: &[NftSet]) -> Result<Vec<NftRule>> {
+    let verdict = verdict(&spec.action)?;
+    if let Some(comment) = &spec.comment {
+        check_text("comment", comment)?;
+    }
+    let mut statements = Vec::new();
+    if let Some(prefix) = &spec.log {
+        check_text("log prefix", prefix)?;
+        statements.push(Statement::Log {
+            prefix: Some(prefix.clone()),
+            level: LogLevel::Warning,
+            group: None,
+        });
+    }
+    if spec.counter {
+        statements.push(Statement::Counter {
+            packets: 0,
+            bytes: 0,
+        });
+    }
+
+    let mut extra = Vec::new();
+    let ct_states = spec.ct_state.to_vec();
+    if !ct_states.is_empty() {
+        let states = ct_states
+            .iter()
+            .map(|s| ct_state(s))
+            .collect::<Result<Vec<_>>>()?;
+        extra.push(Match::ConnectionState { states });
+    }
+    if let Some(icmp_type) = &spec.icmp_type {
+        let protocols = spec.proto.to_vec();
+        let v6 = protocols.iter().any(|p| p == "icmpv6" || p == "ipv6-icmp");
+        let value = super::render::icmp_type_value(v6, icmp_type)
+            .or_else(|| icmp_type.parse().ok())
+            .with_context(|| format!("unknown icmp type '{icmp_type}'"))?;
+        if protocols.is_empty() {
+            bail!("icmp_type needs proto = \"icmp\" or \"icmpv6\"");
+        }
+        extra.push(Match::IcmpType { icmp_type: value });
+    }
+
+    expand(
+        &Selectors {
+            proto: spec.proto.to_vec(),
+            saddr: spec.saddr.to_vec(),
+            daddr: spec.daddr.to_vec(),
+            sport: spec.sport.to_vec(),
+            dport: spec.dport.to_vec(),
+            iif: spec.iif.to_vec(),
+            oif: spec.oif.to_vec(),
+        },
+        sets,
+        extra,
+        statements,
+        verdict,
+        spec.comment.clone(),
+    )
+}
+
+fn compile_nat(spec: &NatSpec, sets: &[NftSet]) -> Result<Vec<NftRule>> {
+    if let Some(comment) = &spec.comment {
+        check_text("comment", comment)?;
+    }
+    let to = spec.to.as_deref();
+    let statement = match spec.nat_type.as_str() {
+        "masquerade" => {
+            if to.is_some() {
+                bail!("masquerade takes no 'to'");
+            }
+            Statement::Masquerade { port_range: None }
+        }
+        "snat" => Statement::Snat {
+            address: to.context("snat needs 'to'")?.to_string(),
+            port_range: None,
+        },
+        "dnat" => {
+            let to = to.context("dnat needs 'to'")?;
+            let (address, port) = split_host_port(to)?;
+            Statement::Dnat { address, port }
+        }
+        "redirect" => {
+            let port = to
+                .context("redirect needs 'to' (a port)")?
+                .trim_start_matches(':')
+                .parse::<u16>()
+                .context("redirect 'to' must be a port")?;
+            Statement::Redirect { port: Some(port) }
+        }
+        other => bail!("unknown nat type '{other}'"),
+    };
+    if matches!(spec.nat_type.as_str(), "masquerade" | "snat") && !spec.iif.to_vec().is_empty() {
+        bail!(
+            "{} rules run in postrouting; match 'oif', not 'iif'",
+            spec.nat_type
+        );
+    }
+    if matches!(spec.nat_type.as_str(), "dnat" | "redirect") && !spec.oif.to_vec().is_empty() {
+        bail!(
+            "{} rules run in prerouting; match 'iif', not 'oif'",
+            spec.nat_type
+        );
+    }
+    expand(
+        &Selectors {
+            proto: spec.proto.to_vec(),
+            saddr: spec.saddr.to_vec(),
+            daddr: spec.daddr.to_vec(),
+            sport: Vec::new(),
+            dport: spec.dport.to_vec(),
+            iif: spec.iif.to_vec(),
+            oif: spec.oif.to_vec(),
+        },
+        sets,
+        Vec::new(),
+        vec![statement],
+        RuleVerdict::Continue,
+        spec.comment.clone(),
+    )
+}
+
+struct Selectors {
+    proto: Vec<String>,
+    saddr: Vec<String>,
+    daddr: Vec<String>,
+    sport: Vec<Scalar>,
+    dport: Vec<Scalar>,
+    iif: Vec<String>,
+    oif: Vec<String>,
+}
+
+fn expand(
+    sel: &Selectors,
+    sets: &[NftSet],
+    extra: Vec<Match>,
+    statements: Vec<Statement>,
+    verdict: RuleVerdict,
+    comment: Option<String>,
+) -> Result<Vec<NftRule>> {
+    let protocols: Vec<Option<Protocol>> = if sel.proto.is_empty() {
+        vec![None]
+    } else {
+        sel.proto
+            .iter()
+            .map(|p| protocol(p).map(Some))
+            .collect::<Result<_>>()?
+    };
+    let ports_used = !sel.sport.is_empty() || !sel.dport.is_empty();
+    if ports_used
+        && !protocols
+            .iter()
+            .all(|p| matches!(p, Some(Protocol::Tcp | Protocol::Udp | Protocol::Sctp)))
+    {
+        bail!("ports need proto = \"tcp\", \"udp\" or \"sctp\"");
+    }
+    for name in sel.iif.iter().chain(&sel.oif) {
+        check_text("interface", name)?;
+        if let Some(set) = name.strip_prefix('@') {
+            let def = sets
+                .iter()
+                .find(|s| s.name == set)
+                .with_context(|| format!("unknown set @{set}"))?;
+            if !matches!(def.set_type, SetType::IfName) {
+                bail!("@{set} is not an ifname set");
+            }
+            if sel.iif.len() > 1 || sel.oif.len() > 1 {
+                bail!("an interface set cannot be combined with other interfaces");
+            }
+        }
+    }
+    let sport = ports(&sel.sport, sets)?;
+    let dport = ports(&sel.dport, sets)?;
+
+    let saddr = split_families(&sel.saddr, sets)?;
+    let daddr = split_families(&sel.daddr, sets)?;
+    let mut families: Vec<Option<bool>> = Vec::new();
+    for v6 in [false, true] {
+        let s = saddr.as_ref().map(|(v4, v6s)| if v6 { v6s } else { v4 });
+        let d = daddr.as_ref().map(|(v4, v6s)| if v6 { v6s } else { v4 });
+        let s_ok = s.is_none_or(|list| !list.is_empty());
+        let d_ok = d.is_none_or(|list| !list.is_empty());
+        if (saddr.is_some() || daddr.is_some()) && s_ok && d_ok {
+            families.push(Some(v6));
+        }
+    }
+    if saddr.is_none() && daddr.is_none() {
+        families.push(None);
+    } else if families.is_empty() {
+        bail!("saddr and daddr have no address family in common");
+    }
+
+    let mut rules = Vec::new();
+    for protocol in &protocols {
+        for family in &families {
+            let mut matches = Vec::new();
+            if !sel.iif.is_empty() {
+                matches.push(Match::Interface {
+                    interface: InterfaceMatch {
+                        interfaces: sel.iif.clone(),
+                        negated: false,
+                    },
+                });
+            }
+            if !sel.oif.is_empty() {
+                // The model's interface match is the input side only.
+                let names: Vec<String> = sel
+                    .oif
+                    .iter()
+                    .map(|i| super::render::interface(i))
+                    .collect::<Result<_>>()?;
+                matches.push(Match::Custom {
+                    expression: format!("oifname {}", super::render::anonymous_set(names)),
+                });
+            }
+            if let Some(protocol) = protocol {
+                matches.push(Match::Protocol {
+                    protocol: protocol.clone(),
+                });
+            }
+            for (field, split) in [("saddr", &saddr), ("daddr", &daddr)] {
+                let (Some((v4, v6)), Some(family)) = (split, family) else {
+                    continue;
+                };
+                let address = AddressMatch {
+                    addresses: if *family { v6.clone() } else { v4.clone() },
+                    negated: false,
+                };
+                matches.push(if field == "saddr" {
+                    Match::SourceAddress { address }
+                } else {
+                    Match::DestinationAddress { address }
+                });
+            }
+            if let Some(port) = &sport {
+                matches.push(Match::SourcePort { port: port.clone() });
+            }
+            if let Some(port) = &dport {
+                matches.push(Match::DestinationPort { port: port.clone() });
+            }
+            matches.extend(extra.iter().cloned());
+            rules.push(NftRule {
+                handle: None,
+                position: None,
+                expression: RuleExpression {
+                    matches,
+                    statements: statements.clone(),
+                },
+                verdict: verdict.clone(),
+                comment: comment.clone(),
+                performance_hints: Vec::new(),
+            });
+        }
+    }
+    Ok(rules)
+}
+
+/// Split an address list into (IPv4, IPv6) halves; `None` when empty.
+fn split_families(addrs: &[String], sets: &[NftSet]) -> Result<Option<(Vec<String>, Vec<String>)>> {
+    if addrs.is_empty() {
+        return Ok(None);
+    }
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for addr in addrs {
+        let is_v6 = if let Some(name) = addr.strip_prefix('@') {
+            let set = sets
+                .iter()
+                .find(|s| s.name == name)
+                .with_context(|| format!("unknown set @{name}"))?;
+            match set.set_type {
+                SetType::Ipv4Address => false,
+                SetType::Ipv6Address => true,
+                _ => bail!("@{name} is not an address set"),
+            }
+        } else {
+            super::render::canonical_address(addr)?.contains(':')
+        };
+        if is_v6 {
+            v6.push(addr.clone());
+        } else {
+            v4.push(addr.clone());
+        }
+    }
+    Ok(Some((v4, v6)))
+}
+
+fn ports(values: &[Scalar], sets: &[NftSet]) -> Result<Option<PortMatch>> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+    let mut ports = Vec::new();
+    for value in values {
+        let text = value.to_string();
+        if let Some(name) = text.strip_prefix('@') {
+            let set = sets
+                .iter()
+                .find(|s| s.name == name)
+                .with_context(|| format!("unknown set @{name}"))?;
+            if !matches!(set.set_type, SetType::InetService) {
+                bail!("@{name} is not an inet_service set");
+            }
+            ports.push(PortSpec::Set(name.to_string()));
+        } else {
+            ports.push(parse_port(&text)?);
+        }
+    }
+    Ok(Some(PortMatch {
+        ports,
+        negated: false,
+    }))
+}
+
+fn parse_port(text: &str) -> Result<PortSpec> {
+    let port = |s: &str| {
+        s.trim()
+            .parse::<u16>()
+            .with_context(|| format!("invalid port '{s}'"))
+    };
+    Ok(match text.split_once('-') {
+        Some((lo, hi)) => {
+            let (lo, hi) = (port(lo)?, port(hi)?);
+            if lo > hi {
+                bail!("invalid port range '{text}'");
+            }
+            PortSpec::Range(lo, hi)
+        }
+        None => PortSpec::Single(port(text)?),
+    })
+}
+
+fn split_host_port(to: &str) -> Result<(String, Option<u16>)> {
+    if let Some(rest) = to.strip_prefix('[') {
+        let (host, port) = rest.split_once(']').context("unterminated [ in 'to'")?;
+        let port = match port.strip_prefix(':') {
+            Some(p) => Some(p.parse().context("invalid port in 'to'")?),
+            None => None,
+        };
+        return Ok((host.to_string(), port));
+    }
+    if to.matches(':').count() == 1 {
+        let (host, port) = to.split_once(':').unwrap_or((to, ""));
+        return Ok((
+            host.to_string(),
+            Some(port.parse().context("invalid port in 'to'")?),
+        ));
+    }
+    Ok((to.to_string(), None))
+}
+
+fn protocol(name: &str) -> Result<Protocol> {
+    Ok(match name {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        "icmp" => Protocol::Icmp,
+        "icmpv6" | "ipv6-icmp" => Protocol::Icmpv6,
+        "esp" => Protocol::Esp,
+        "ah" => Protocol::Ah,
+        "sctp" => Protocol::Sctp,
+        "gre" => Protocol::Gre,
+        other => match other.parse::<u8>() {
+            Ok(n) => Protocol::Number(n),
+            Err(_) => bail!("unknown protocol '{other}'"),
+        },
+    })
+}
+
+fn ct_state(name: &str) -> Result<ConntrackState> {
+    Ok(match name {
+        "new" => ConntrackState::New,
+        "established" => ConntrackState::Established,
+        "related" => ConntrackState::Related,
+        "invalid" => ConntrackState::Invalid,
+        "untracked" => ConntrackState::Untracked,
+        other => bail!("unknown ct state '{other}'"),
+    })
+}
+
+fn verdict(action: &str) -> Result<RuleVerdict> {
+    let mut words = action.split_whitespace();
+    let verdict = match (words.next(), words.next()) {
+        (Some("accept"), None) => RuleVerdict::Accept,
+        (Some("drop"), None) => RuleVerdict::Drop,
+        (Some("reject"), None) => RuleVerdict::Reject { reject_type: None },
+        (Some("return"), None) => RuleVerdict::Return,
+        (Some("jump"), Some(target)) if is_identifier(target) => RuleVerdict::Jump {
+            target: target.to_string(),
+        },
+        (Some("goto"), Some(target)) if is_identifier(target) => RuleVerdict::Goto {
+            target: target.to_string(),
+        },
+        _ => bail!("unknown action '{action}'"),
+    };
+    if words.next().is_some() {
+        bail!("unknown action '{action}'");
+    }
+    Ok(verdict)
+}
+
+/// Text that ends up quoted in the ruleset (interfaces, log prefixes,
+/// comments) must be something nft can quote.
+fn check_text(field: &str, value: &str) -> Result<()> {
+    super::render::check_string(value).with_context(|| format!("invalid {field}"))
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
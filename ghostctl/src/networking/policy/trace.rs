@@ -0,0 +1,1172 @@
+//! Packet-path simulation: `ghostctl firewall trace`.
+//!
+//! A synthetic packet is walked through the ruleset as the kernel would: the
+//! hooks for its direction in order, the base chains on each hook by
+//! priority, and jumps, gotos and returns between chains. Every rule that
+//! matches (or that could not be evaluated) is recorded together with the
+//! chain policy that finally applied, so the output reads as the path the
+//! packet took. Connection tracking is not simulated; the packet carries an
+//! assumed `ct state` (new unless told otherwise).
+//!
+//! Matches the simulator cannot decide from the packet - rate limits, packet
+//! length, an input interface that was not given - are reported as
+//! "not evaluated" and assumed not to match. [`confirm_script`] and
+//! [`summarize_monitor`] support checking the answer against the kernel with
+//! `meta nftrace` and `nft monitor trace`.
+
+use super::render;
+use crate::networking::nftables_enterprise::{
+    AddressMatch, ChainPolicy, ChainType, ConntrackState, Hook, InterfaceMatch, Match, NftChain,
+    NftRule, NftTable, PortMatch, PortSpec, Protocol, RuleVerdict, SetType, Statement, TableFamily,
+};
+use anyhow::{Result, bail};
+use ipnet::IpNet;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::IpAddr;
+
+/// Table holding the temporary `meta nftrace` rules for `--live`.
+pub const TRACE_TABLE: &str = "ghostctl_trace";
+
+/// Chains may jump this deep before the walk gives up; nft itself refuses
+/// loops when a ruleset is loaded.
+const MAX_DEPTH: usize = 32;
+
+/// Rules one base chain may evaluate before the walk gives up; a `goto`
+/// cycle never grows the jump stack, so only this bounds it.
+const MAX_STEPS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// To this host: prerouting, input.
+    Input,
+    /// Routed through this host: prerouting, forward, postrouting.
+    Forward,
+    /// From this host: output, postrouting.
+    Output,
+}
+
+impl Direction {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "input" => Direction::Input,
+            "forward" => Direction::Forward,
+            "output" => Direction::Output,
+            other => bail!("unknown direction '{other}' (expected input, forward or output)"),
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Input => "input",
+            Direction::Forward => "forward",
+            Direction::Output => "output",
+        }
+    }
+
+    fn hooks(self) -> &'static [Hook] {
+        match self {
+            Direction::Input => &[Hook::Prerouting, Hook::Input],
+            Direction::Forward => &[Hook::Prerouting, Hook::Forward, Hook::Postrouting],
+            Direction::Output => &[Hook::Output, Hook::Postrouting],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Packet {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    /// IP protocol number.
+    pub proto: u8,
+    pub sport: Option<u16>,
+    pub dport: Option<u16>,
+    pub iif: Option<String>,
+    pub oif: Option<String>,
+    /// The conntrack state the packet is assumed to have.
+    pub state: ConntrackState,
+    pub icmp_type: Option<u8>,
+    pub mark: u32,
+}
+
+impl Packet {
+    /// A new-connection packet with no ports or interfaces set.
+    pub fn new(src: IpAddr, dst: IpAddr, proto: u8) -> Self {
+        Packet {
+            src,
+            dst,
+            proto,
+            sport: None,
+            dport: None,
+            iif: None,
+            oif: None,
+            state: ConntrackState::New,
+            icmp_type: None,
+            mark: 0,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let endpoint = |addr: &IpAddr, port: Option<u16>| match (addr, port) {
+            (IpAddr::V6(a), Some(p)) => format!("[{a}]:{p}"),
+            (a, Some(p)) => format!("{a}:{p}"),
+            (a, None) => a.to_string(),
+        };
+        let mut out = format!(
+            "{} {} -> {}",
+            protocol_label(self.proto),
+            endpoint(&self.src, self.sport),
+            endpoint(&self.dst, self.dport)
+        );
+        if let Some(iif) = &self.iif {
+            let _ = write!(out, " iif {iif}");
+        }
+        if let Some(oif) = &self.oif {
+            let _ = write!(out, " oif {oif}");
+        }
+        let _ = write!(out, " ct state {}", render::ct_state_name(&self.state));
+        out
+    }
+}
+
+/// The IP protocol number for a name such as `tcp`, `icmpv6` or `47`.
+pub fn protocol_number(name: &str) -> Result<u8> {
+    Ok(match name {
+        "tcp" => 6,
+        "udp" => 17,
+        "icmp" => 1,
+        "icmpv6" | "ipv6-icmp" => 58,
+        "esp" => 50,
+        "ah" => 51,
+        "sctp" => 132,
+        "gre" => 47,
+        other => match other.parse() {
+            Ok(n) => n,
+            Err(_) => bail!("unknown protocol '{other}'"),
+        },
+    })
+}
+
+fn protocol_label(proto: u8) -> String {
+    match proto {
+        6 => "tcp".to_string(),
+        17 => "udp".to_string(),
+        1 => "icmp".to_string(),
+        58 => "icmpv6".to_string(),
+        132 => "sctp".to_string(),
+        n => format!("proto {n}"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Accept,
+    Drop,
+    Reject,
+    Queue,
+}
+
+impl Verdict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::Accept => "accept",
+            Verdict::Drop => "drop",
+            Verdict::Reject => "reject",
+            Verdict::Queue => "queue",
+        }
+    }
+}
+
+/// One line of the path: a rule that matched or could not be evaluated, or
+/// the policy of a base chain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Step {
+    pub hook: String,
+    pub family: String,
+    pub table: String,
+    pub chain: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handle: Option<u64>,
+    pub rule: String,
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Trace {
+    pub packet: Packet,
+    pub direction: Direction,
+    pub steps: Vec<Step>,
+    pub verdict: Verdict,
+    /// The step that decided the verdict, an index into `steps`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decided_by: Option<usize>,
+    /// True when a rule on the path could not be evaluated, so a different
+    /// verdict is possible.
+    pub uncertain: bool,
+    pub notes: Vec<String>,
+}
+
+/// Where an address is: this host's addresses are local, as is loopback.
+pub fn direction(packet: &Packet, local: &[IpAddr]) -> Direction {
+    let is_local = |a: &IpAddr| a.is_loopback() || local.contains(a);
+    if is_local(&packet.src) {
+        Direction::Output
+    } else if is_local(&packet.dst) {
+        Direction::Input
+    } else {
+        Direction::Forward
+    }
+}
+
+/// Addresses from `ip -j addr` output.
+pub fn local_addresses(json: &str) -> Vec<IpAddr> {
+    let Ok(Value::Array(links)) = serde_json::from_str::<Value>(json) else {
+        return Vec::new();
+    };
+    links
+        .iter()
+        .filter_map(|link| link.get("addr_info").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|a| a.get("local").and_then(Value::as_str)?.parse().ok())
+        .collect()
+}
+
+/// Walk `packet` through `tables`. `forced` overrides the direction that is
+/// otherwise worked out from `local` (after any DNAT in prerouting).
+pub fn simulate(
+    tables: &[NftTable],
+    packet: &Packet,
+    local: &[IpAddr],
+    forced: Option<Direction>,
+) -> Trace {
+    let mut walk = Walk {
+        packet: packet.clone(),
+        steps: Vec::new(),
+        uncertain: false,
+        notes: Vec::new(),
+        redirected: false,
+    };
+    let original = packet.clone();
+    let mut direction = forced.unwrap_or_else(|| self::direction(packet, local));
+    let mut verdict = None;
+    let mut hook_index = 0;
+    while hook_index < direction.hooks().len() {
+        let hook = &direction.hooks()[hook_index];
+        hook_index += 1;
+        for (table, chain) in base_chains(tables, hook, &walk.packet) {
+            if matches!(chain.chain_type, ChainType::Nat)
+                && !matches!(walk.packet.state, ConntrackState::New)
+            {
+                continue;
+            }
+            if let Some(v) = walk.run(table, chain, hook) {
+                verdict = Some(v);
+                break;
+            }
+        }
+        if verdict.is_some() {
+            break;
+        }
+        // DNAT or a redirect in prerouting can turn a forwarded packet into
+        // one for this host, or the other way round.
+        if matches!(hook, Hook::Prerouting) && forced.is_none() {
+            let routed = if walk.redirected {
+                Direction::Input
+            } else {
+                self::direction(&walk.packet, local)
+            };
+            if routed != direction {
+                walk.notes.push(format!(
+                    "NAT changed the destination; the packet now takes the {} path",
+                    routed.name()
+                ));
+                direction = routed;
+                hook_index = 1;
+            }
+        }
+    }
+    if matches!(original.state, ConntrackState::New) {
+        walk.notes.push(
+            "Assumed ct state new; pass --state established to check reply traffic".to_string(),
+        );
+    }
+    let verdict = verdict.unwrap_or(Verdict::Accept);
+    let decided_by = walk.steps.iter().rposition(|s| {
+        s.action == verdict.name() || s.action == format!("policy {}", verdict.name())
+    });
+    Trace {
+        packet: original,
+        direction,
+        steps: walk.steps,
+        verdict,
+        decided_by,
+        uncertain: walk.uncertain,
+        notes: walk.notes,
+    }
+}
+
+/// Base chains on `hook` for the packet's family, lowest priority first.
+fn base_chains<'a>(
+    tables: &'a [NftTable],
+    hook: &Hook,
+    packet: &Packet,
+) -> Vec<(&'a NftTable, &'a NftChain)> {
+    let mut chains: Vec<_> = tables
+        .iter()
+        .filter(|t| match t.family {
+            TableFamily::Inet => true,
+            TableFamily::Ip => packet.src.is_ipv4(),
+            TableFamily::Ip6 => packet.src.is_ipv6(),
+            _ => false,
+        })
+        .flat_map(|t| t.chains.iter().map(move |c| (t, c)))
+        .filter(|(_, c)| {
+            c.hook
+                .as_ref()
+                .is_some_and(|h| render::hook_name(h) == render::hook_name(hook))
+        })
+        .collect();
+    chains.sort_by_key(|(_, c)| c.priority.unwrap_or(0));
+    chains
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Eval {
+    Yes,
+    No,
+    Unknown,
+}
+
+impl Eval {
+    fn from(b: bool) -> Self {
+        if b { Eval::Yes } else { Eval::No }
+    }
+
+    fn negate(self, negated: bool) -> Self {
+        match (self, negated) {
+            (Eval::Yes, true) => Eval::No,
+            (Eval::No, true) => Eval::Yes,
+            (e, _) => e,
+        }
+    }
+}
+
+struct Walk {
+    packet: Packet,
+    steps: Vec<Step>,
+    uncertain: bool,
+    notes: Vec<String>,
+    redirected: bool,
+}
+
+impl Walk {
+    /// Run one base chain; `None` means it let the packet through.
+    fn run(&mut self, table: &NftTable, base: &NftChain, hook: &Hook) -> Option<Verdict> {
+        let mut stack: Vec<(&NftChain, usize)> = Vec::new();
+        let (mut chain, mut next) = (base, 0);
+        let mut evaluated = 0;
+        loop {
+            let Some(rule) = chain.rules.get(next) else {
+                if let Some((caller, resume)) = stack.pop() {
+                    (chain, next) = (caller, resume);
+                    continue;
+                }
+                let policy = base.policy.as_ref().unwrap_or(&ChainPolicy::Accept);
+                let name = render::policy_name(policy);
+                self.step(
+                    hook,
+                    table,
+                    base,
+                    None,
+                    "-".to_string(),
+                    format!("policy {name}"),
+                );
+                return matches!(policy, ChainPolicy::Drop).then_some(Verdict::Drop);
+            };
+            next += 1;
+            evaluated += 1;
+            if evaluated > MAX_STEPS {
+                self.notes.push(format!(
+                    "Gave up after {MAX_STEPS} rules in {}; the chains loop",
+                    base.name
+                ));
+                return Some(Verdict::Drop);
+            }
+            let text = render::render_rule(table, rule).unwrap_or_else(|_| "?".to_string());
+            match self.eval_rule(table, rule) {
+                Eval::No => continue,
+                Eval::Unknown => {
+                    self.uncertain = true;
+                    self.step(
+                        hook,
+                        table,
+                        chain,
+                        rule.handle,
+                        text,
+                        "not evaluated, assumed no match".to_string(),
+                    );
+                    continue;
+                }
+                Eval::Yes => {}
+            }
+            self.apply_statements(rule, hook);
+            let action = match &rule.verdict {
+                RuleVerdict::Continue => "continue".to_string(),
+                RuleVerdict::Accept => "accept".to_string(),
+                RuleVerdict::Drop => "drop".to_string(),
+                RuleVerdict::Reject { .. } => "reject".to_string(),
+                RuleVerdict::Queue { .. } => "queue".to_string(),
+                RuleVerdict::Return => "return".to_string(),
+                RuleVerdict::Jump { target } => format!("jump {target}"),
+                RuleVerdict::Goto { target } => format!("goto {target}"),
+            };
+            self.step(hook, table, chain, rule.handle, text, action);
+            match &rule.verdict {
+                RuleVerdict::Continue => {}
+                RuleVerdict::Accept => return None,
+                RuleVerdict::Drop => return Some(Verdict::Drop),
+                RuleVerdict::Reject { .. } => return Some(Verdict::Reject),
+                RuleVerdict::Queue { .. } => return Some(Verdict::Queue),
+                RuleVerdict::Return => match stack.pop() {
+                    Some((caller, resume)) => (chain, next) = (caller, resume),
+                    // A return from the base chain ends it with its policy.
+                    None => next = chain.rules.len(),
+                },
+                RuleVerdict::Jump { target } | RuleVerdict::Goto { target } => {
+                    let Some(callee) = table.chains.iter().find(|c| &c.name == target) else {
+                        self.notes
+                            .push(format!("Chain {target} does not exist in {}", table.name));
+                        continue;
+                    };
+                    if stack.len() >= MAX_DEPTH {
+                        self.notes
+                            .push(format!("Gave up after {MAX_DEPTH} nested jumps"));
+                        return Some(Verdict::Drop);
+                    }
+                    if matches!(rule.verdict, RuleVerdict::Jump { .. }) {
+                        stack.push((chain, next));
+                    }
+                    (chain, next) = (callee, 0);
+                }
+            }
+        }
+    }
+
+    fn step(
+        &mut self,
+        hook: &Hook,
+        table: &NftTable,
+        chain: &NftChain,
+        handle: Option<u64>,
+        rule: String,
+        action: String,
+    ) {
+        self.steps.push(Step {
+            hook: render::hook_name(hook).to_string(),
+            family: render::family_name(&table.family).to_string(),
+            table: table.name.clone(),
+            chain: chain.name.clone(),
+            handle,
+            rule,
+            action,
+        });
+    }
+
+    fn apply_statements(&mut self, rule: &NftRule, hook: &Hook) {
+        for statement in &rule.expression.statements {
+            match statement {
+                Statement::Mark { mark, .. } => self.packet.mark = *mark,
+                Statement::Dnat { address, port } => {
+                    let host = address.split('/').next().unwrap_or(address);
+                    match host.parse() {
+                        Ok(addr) => self.packet.dst = addr,
+                        Err(_) => self.notes.push(format!(
+                            "Could not follow dnat to {address}; destination left unchanged"
+                        )),
+                    }
+                    if port.is_some() {
+                        self.packet.dport = *port;
+                    }
+                    self.notes.push(format!("dnat to {}", self.endpoint()));
+                }
+                Statement::Redirect { port } => {
+                    self.redirected = true;
+                    if port.is_some() {
+                        self.packet.dport = *port;
+                    }
+                    self.notes.push("redirected to this host".to_string());
+                }
+                Statement::Snat { address, .. } => {
+                    self.notes.push(format!("snat to {address}"));
+                }
+                Statement::Masquerade { .. } if matches!(hook, Hook::Postrouting) => {
+                    self.notes.push("masqueraded".to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn endpoint(&self) -> String {
+        match self.packet.dport {
+            Some(port) => format!("{} port {port}", self.packet.dst),
+            None => self.packet.dst.to_string(),
+        }
+    }
+
+    fn eval_rule(&self, table: &NftTable, rule: &NftRule) -> Eval {
+        let mut result = Eval::Yes;
+        for m in &rule.expression.matches {
+            match self.eval(table, m) {
+                Eval::No => return Eval::No,
+                Eval::Unknown => result = Eval::Unknown,
+                Eval::Yes => {}
+            }
+        }
+        result
+    }
+
+    fn eval(&self, table: &NftTable, m: &Match) -> Eval {
+        let p = &self.packet;
+        match m {
+            Match::Protocol { protocol } => match protocol {
+                Protocol::Any => Eval::Yes,
+                other => Eval::from(protocol_value(other) == p.proto),
+            },
+            Match::SourceAddress { address } => self.address(table, address, p.src),
+            Match::DestinationAddress { address } => self.address(table, address, p.dst),
+            Match::SourcePort { port } => self.port(table, port, p.sport),
+            Match::DestinationPort { port } => self.port(table, port, p.dport),
+            Match::Interface { interface } => self.interface(table, interface),
+            Match::ConnectionState { states } => Eval::from(
+                states
+                    .iter()
+                    .any(|s| render::ct_state_name(s) == render::ct_state_name(&p.state)),
+            ),
+            Match::Mark { mark, mask } => Eval::from(p.mark & mask.unwrap_or(u32::MAX) == *mark),
+            Match::IcmpType { icmp_type } => match p.icmp_type {
+                Some(t) => Eval::from(t == *icmp_type),
+                None => Eval::Unknown,
+            },
+            // The first packet of a TCP connection is a bare SYN.
+            Match::TcpFlags { flags } if p.proto == 6 => {
+                if matches!(p.state, ConntrackState::New) {
+                    Eval::from(0x02 & flags.mask == flags.flags)
+                } else {
+                    Eval::Unknown
+                }
+            }
+            Match::TcpFlags { .. } => Eval::No,
+            Match::Counter { .. } => Eval::Yes,
+            Match::Custom { expression } => self.custom(expression),
+            _ => Eval::Unknown,
+        }
+    }
+
+    fn address(&self, table: &NftTable, address: &AddressMatch, addr: IpAddr) -> Eval {
+        let mut result = Eval::No;
+        for spec in &address.addresses {
+            let candidates: Vec<String> = match spec.strip_prefix('@') {
+                Some(name) => match table.sets.iter().find(|s| s.name == name) {
+                    Some(set) => {
+                        // `ip saddr @v6set` cannot load, but a family mismatch
+                        // never matches either way.
+                        let v4 = matches!(set.set_type, SetType::Ipv4Address);
+                        let v6 = matches!(set.set_type, SetType::Ipv6Address);
+                        if (v4 && addr.is_ipv6()) || (v6 && addr.is_ipv4()) {
+                            return Eval::No;
+                        }
+                        set.elements.clone()
+                    }
+                    None => return Eval::Unknown,
+                },
+                None => vec![spec.clone()],
+            };
+            for candidate in &candidates {
+                match address_contains(candidate, addr) {
+                    // `ip saddr` also checks the family, so negation cannot
+                    // turn a family mismatch into a match.
+                    Some(Contains::OtherFamily) if spec.starts_with('@') => {}
+                    Some(Contains::OtherFamily) => return Eval::No,
+                    Some(Contains::Yes) => result = Eval::Yes,
+                    Some(Contains::No) => {}
+                    None => {
+                        if result == Eval::No {
+                            result = Eval::Unknown;
+                        }
+                    }
+                }
+            }
+        }
+        result.negate(address.negated)
+    }
+
+    fn port(&self, table: &NftTable, port: &PortMatch, value: Option<u16>) -> Eval {
+        let Some(value) = value else {
+            // A protocol match before this one has already failed for
+            // portless packets; without a port there is nothing to compare.
+            return if matches!(self.packet.proto, 6 | 17 | 132) {
+                Eval::Unknown
+            } else {
+                Eval::No
+            };
+        };
+        let mut result = Eval::No;
+        for spec in &port.ports {
+            let hit = match spec {
+                PortSpec::Single(p) => Some(*p == value),
+                PortSpec::Range(lo, hi) => Some((*lo..=*hi).contains(&value)),
+                PortSpec::Set(name) => table
+                    .sets
+                    .iter()
+                    .find(|s| s.name == *name)
+                    .map(|set| set.elements.iter().any(|e| port_contains(e, value))),
+            };
+            match hit {
+                Some(true) => result = Eval::Yes,
+                Some(false) => {}
+                None if result == Eval::No => result = Eval::Unknown,
+                None => {}
+            }
+        }
+        result.negate(port.negated)
+    }
+
+    fn interface(&self, table: &NftTable, interface: &InterfaceMatch) -> Eval {
+        let names: Vec<&String> = interface
+            .interfaces
+            .iter()
+            .flat_map(|i| match i.strip_prefix('@') {
+                Some(name) => table
+                    .sets
+                    .iter()
+                    .find(|s| s.name == name)
+                    .map(|s| s.elements.iter().collect())
+                    .unwrap_or_default(),
+                None => vec![i],
+            })
+            .collect();
+        let Some(iif) = &self.packet.iif else {
+            // Only loopback traffic arrives on lo.
+            let loopback = self.packet.src.is_loopback();
+            if !loopback && names.iter().all(|n| n.as_str() == "lo") {
+                return Eval::from(interface.negated);
+            }
+            return Eval::Unknown;
+        };
+        Eval::from(names.iter().any(|n| interface_matches(n, iif))).negate(interface.negated)
+    }
+
+    fn custom(&self, expression: &str) -> Eval {
+        let p = &self.packet;
+        if let Some(family) = expression.strip_prefix("meta nfproto ") {
+            return match family {
+                "ipv4" => Eval::from(p.src.is_ipv4()),
+                "ipv6" => Eval::from(p.src.is_ipv6()),
+                _ => Eval::Unknown,
+            };
+        }
+        if let Some(set) = expression.strip_prefix("meta l4proto ") {
+            let names = set.trim_matches(|c| c == '{' || c == '}' || c == ' ');
+            let numbers: Option<Vec<u8>> = names
+                .split(',')
+                .map(|n| protocol_number(n.trim()).ok())
+                .collect();
+            return match numbers {
+                Some(numbers) => Eval::from(numbers.contains(&p.proto)),
+                None => Eval::Unknown,
+            };
+        }
+        if let Some(rest) = expression.strip_prefix("oifname ") {
+            let (negated, names) = match rest.strip_prefix("!= ") {
+                Some(names) => (true, names),
+                None => (false, rest),
+            };
+            let Some(oif) = &p.oif else {
+                return Eval::Unknown;
+            };
+            if names.starts_with('@') {
+                return Eval::Unknown;
+            }
+            let names = names.trim_matches(|c| c == '{' || c == '}' || c == ' ');
+            let hit = names
+                .split(',')
+                .any(|n| interface_matches(n.trim().trim_matches('"'), oif));
+            return Eval::from(hit).negate(negated);
+        }
+        Eval::Unknown
+    }
+}
+
+fn protocol_value(protocol: &Protocol) -> u8 {
+    match protocol {
+        Protocol::Tcp => 6,
+        Protocol::Udp => 17,
+        Protocol::Icmp => 1,
+        Protocol::Icmpv6 => 58,
+        Protocol::Esp => 50,
+        Protocol::Ah => 51,
+        Protocol::Sctp => 132,
+        Protocol::Gre => 47,
+        Protocol::Number(n) => *n,
+        Protocol::Any => 0,
+    }
+}
+
+enum Contains {
+    Yes,
+    No,
+    OtherFamily,
+}
+
+/// Whether `addr` is `spec`: an address, a prefix or an `a-b` range.
+fn address_contains(spec: &str, addr: IpAddr) -> Option<Contains> {
+    let same = |other: &IpAddr| other.is_ipv4() == addr.is_ipv4();
+    let result = |family: bool, hit: bool| match (family, hit) {
+        (false, _) => Contains::OtherFamily,
+        (true, true) => Contains::Yes,
+        (true, false) => Contains::No,
+    };
+    if let Ok(single) = spec.parse::<IpAddr>() {
+        return Some(result(same(&single), single == addr));
+    }
+    if let Ok(net) = spec.parse::<IpNet>() {
+        return Some(result(same(&net.addr()), net.contains(&addr)));
+    }
+    let (lo, hi) = spec.split_once('-')?;
+    let (lo, hi): (IpAddr, IpAddr) = (lo.trim().parse().ok()?, hi.trim().parse().ok()?);
+    Some(result(same(&lo), lo <= addr && addr <= hi))
+}
+
+fn port_contains(spec: &str, port: u16) -> bool {
+    match spec.split_once('-') {
+        Some((lo, hi)) => match (lo.parse::<u16>(), hi.parse::<u16>()) {
+            (Ok(lo), Ok(hi)) => (lo..=hi).contains(&port),
+            _ => false,
+        },
+        None => spec.parse() == Ok(port),
+    }
+}
+
+/// nft interface names match exactly or, ending in `*`, by prefix.
+fn interface_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+pub fn print_trace(trace: &Trace) {
+    println!(
+        "Packet: {} ({} path)",
+        trace.packet.describe(),
+        trace.direction.name()
+    );
+    println!();
+    for (i, step) in trace.steps.iter().enumerate() {
+        let marker = if Some(i) == trace.decided_by {
+            "=>"
+        } else {
+            "  "
+        };
+        let handle = step.handle.map(|h| format!("#{h} ")).unwrap_or_default();
+        println!(
+            "{marker} {:<11} {} {}/{:<12} {handle}{}  -> {}",
+            step.hook, step.family, step.table, step.chain, step.rule, step.action
+        );
+    }
+    if trace.steps.is_empty() {
+        println!("   No chain on this path; the packet is accepted.");
+    }
+    println!();
+    for note in &trace.notes {
+        println!("Note: {note}");
+    }
+    let verdict = format!("Verdict: {}", trace.verdict.name().to_uppercase());
+    match trace.verdict {
+        Verdict::Accept => crate::tui::success(&verdict),
+        _ => crate::tui::error(&verdict),
+    }
+    if trace.uncertain {
+        crate::tui::warn(
+            "Some rules could not be evaluated for this packet; check them or confirm with --live",
+        );
+    }
+}
+
+/// A temporary table that sets `meta nftrace` on packets like `packet`, ahead
+/// of every other chain.
+pub fn confirm_script(packet: &Packet) -> String {
+    let family = if packet.src.is_ipv4() { "ip" } else { "ip6" };
+    let mut selector = format!(
+        "{family} saddr {} {family} daddr {}",
+        packet.src, packet.dst
+    );
+    let l4 = match packet.proto {
+        6 => Some("tcp"),
+        17 => Some("udp"),
+        132 => Some("sctp"),
+        _ => None,
+    };
+    match (l4, packet.dport) {
+        (Some(l4), Some(port)) => {
+            let _ = write!(selector, " {l4} dport {port}");
+        }
+        _ => {
+            let _ = write!(selector, " meta l4proto {}", packet.proto);
+        }
+    }
+    format!(
+        "table inet {TRACE_TABLE}\n\
+         delete table inet {TRACE_TABLE}\n\
+         \n\
+         table inet {TRACE_TABLE} {{\n\
+         \tchain prerouting {{\n\
+         \t\ttype filter hook prerouting priority -350; policy accept;\n\
+         \t\t{selector} meta nftrace set 1\n\
+         \t}}\n\
+         \n\
+         \tchain output {{\n\
+         \t\ttype filter hook output priority -350; policy accept;\n\
+         \t\t{selector} meta nftrace set 1\n\
+         \t}}\n\
+         }}\n"
+    )
+}
+
+/// What the kernel decided for one traced packet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Observed {
+    pub id: String,
+    pub verdict: String,
+    /// `family table chain` of the deciding rule or policy.
+    pub chain: String,
+    pub rule: String,
+}
+
+/// Verdicts from `nft monitor trace` output, one per trace id in the order
+/// first seen. Lines from the trace table itself are ignored.
+pub fn summarize_monitor(output: &str) -> Vec<Observed> {
+    let mut order = Vec::new();
+    let mut decided: BTreeMap<String, Observed> = BTreeMap::new();
+    for line in output.lines() {
+        let Some(rest) = line.trim().strip_prefix("trace id ") else {
+            continue;
+        };
+        let mut words = rest.splitn(5, ' ');
+        let (Some(id), Some(family), Some(table), Some(chain), Some(event)) = (
+            words.next(),
+            words.next(),
+            words.next(),
+            words.next(),
+            words.next(),
+        ) else {
+            continue;
+        };
+        if table == TRACE_TABLE {
+            continue;
+        }
+        let (rule, verdict) = if let Some(policy) = event.strip_prefix("policy ") {
+            ("-".to_string(), policy.trim().to_string())
+        } else if let Some(rule) = event.strip_prefix("rule ") {
+            match rule.rsplit_once(" (verdict ") {
+                Some((text, verdict)) => {
+                    (text.to_string(), verdict.trim_end_matches(')').to_string())
+                }
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+        let verdict = verdict.split(' ').next().unwrap_or_default().to_string();
+        if !matches!(verdict.as_str(), "accept" | "drop" | "reject" | "queue") {
+            continue;
+        }
+        if !order.iter().any(|o| o == id) {
+            order.push(id.to_string());
+        }
+        // The first drop is final; otherwise the last accept stands.
+        if decided.get(id).is_some_and(|o| o.verdict != "accept") {
+            continue;
+        }
+        decided.insert(
+            id.to_string(),
+            Observed {
+                id: id.to_string(),
+                verdict,
+                chain: format!("{family} {table} {chain}"),
+                rule,
+            },
+        );
+    }
+    order.iter().filter_map(|id| decided.remove(id)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::nftables_enterprise::ChainType;
+    use crate::networking::policy::{live, spec};
+
+    const HOST_LIVE: &str = include_str!("testdata/host-live.json");
+
+    fn host() -> Vec<NftTable> {
+        live::model(HOST_LIVE).unwrap()
+    }
+
+    fn tcp(src: &str, dst: &str, dport: u16) -> Packet {
+        let mut packet = Packet::new(src.parse().unwrap(), dst.parse().unwrap(), 6);
+        packet.sport = Some(40000);
+        packet.dport = Some(dport);
+        packet
+    }
+
+    fn local() -> Vec<IpAddr> {
+        vec!["192.168.1.1".parse().unwrap()]
+    }
+
+    #[test]
+    fn live_json_converts_to_model() {
+        let tables = host();
+        let ghostctl = tables.iter().find(|t| t.name == "ghostctl").unwrap();
+        let admin = &ghostctl.sets[0];
+        assert_eq!(admin.elements, vec!["10.8.0.5", "192.168.1.0/24"]);
+        let input = ghostctl.chains.iter().find(|c| c.name == "input").unwrap();
+        assert!(matches!(input.policy, Some(ChainPolicy::Drop)));
+        assert_eq!(input.rules.len(), 11);
+        // Re-rendering the converted rules gives the script they came from.
+        let script = render::render_table(ghostctl).unwrap();
+        assert!(script.contains("ip saddr @admin tcp dport 22 accept comment \"ssh\""));
+        assert!(script.contains("tcp dport 9000-9010 jump admin_ui"));
+    }
+
+    #[test]
+    fn admin_ssh_is_accepted_by_its_rule() {
+        let trace = simulate(&host(), &tcp("10.8.0.5", "192.168.1.1", 22), &local(), None);
+        assert_eq!(trace.direction, Direction::Input);
+        assert_eq!(trace.verdict, Verdict::Accept);
+        let decided = &trace.steps[trace.decided_by.unwrap()];
+        assert_eq!(decided.handle, Some(11));
+        assert_eq!(decided.chain, "input");
+    }
+
+    #[test]
+    fn ssh_from_elsewhere_falls_to_the_drop_policy() {
+        let trace = simulate(
+            &host(),
+            &tcp("203.0.113.9", "192.168.1.1", 22),
+            &local(),
+            None,
+        );
+        assert_eq!(trace.verdict, Verdict::Drop);
+        let decided = &trace.steps[trace.decided_by.unwrap()];
+        assert_eq!(decided.action, "policy drop");
+        assert!(!trace.uncertain, "lo cannot match a remote source");
+    }
+
+    #[test]
+    fn established_traffic_is_accepted_first() {
+        let mut packet = tcp("203.0.113.9", "192.168.1.1", 22);
+        packet.state = ConntrackState::Established;
+        let trace = simulate(&host(), &packet, &local(), None);
+        assert_eq!(trace.verdict, Verdict::Accept);
+        assert_eq!(trace.steps[0].rule, "ct state established,related accept");
+    }
+
+    #[test]
+    fn jumps_are_followed_and_rejects_are_final() {
+        let tables = host();
+        let trace = simulate(
+            &tables,
+            &tcp("10.8.0.5", "192.168.1.1", 9001),
+            &local(),
+            None,
+        );
+        assert_eq!(trace.verdict, Verdict::Accept);
+        let actions: Vec<_> = trace.steps.iter().map(|s| s.action.as_str()).collect();
+        assert_eq!(actions, vec!["jump admin_ui", "accept"]);
+        assert_eq!(trace.steps[1].chain, "admin_ui");
+
+        let trace = simulate(
+            &tables,
+            &tcp("203.0.113.9", "192.168.1.1", 9001),
+            &local(),
+            None,
+        );
+        assert_eq!(trace.verdict, Verdict::Reject);
+        assert_eq!(trace.steps.last().unwrap().chain, "admin_ui");
+    }
+
+    #[test]
+    fn goto_cycles_give_up_instead_of_looping() {
+        let chain = |name: &str, target: &str| NftChain {
+            name: name.to_string(),
+            chain_type: ChainType::Filter,
+            hook: None,
+            priority: None,
+            policy: None,
+            rules: vec![spec::rule(
+                Vec::new(),
+                RuleVerdict::Goto {
+                    target: target.to_string(),
+                },
+            )],
+            device: None,
+        };
+        let mut input = spec::base_chain("input", ChainType::Filter, Hook::Input, 0);
+        input.policy = Some(ChainPolicy::Accept);
+        input.rules.push(spec::rule(
+            Vec::new(),
+            RuleVerdict::Goto {
+                target: "a".to_string(),
+            },
+        ));
+        let table = NftTable {
+            chains: vec![input, chain("a", "b"), chain("b", "a")],
+            ..live::model(r#"{"nftables":[{"table":{"family":"inet","name":"loop"}}]}"#)
+                .unwrap()
+                .remove(0)
+        };
+        let trace = simulate(
+            &[table],
+            &tcp("203.0.113.9", "192.168.1.1", 22),
+            &local(),
+            None,
+        );
+        assert_eq!(trace.verdict, Verdict::Drop);
+        assert!(trace.notes.iter().any(|n| n.starts_with("Gave up after")));
+    }
+
+    #[test]
+    fn unknown_interfaces_are_reported_not_guessed() {
+        let mut packet = Packet::new(
+            "192.168.1.20".parse().unwrap(),
+            "192.168.1.1".parse().unwrap(),
+            17,
+        );
+        packet.dport = Some(53);
+        let trace = simulate(&host(), &packet, &local(), None);
+        assert_eq!(trace.verdict, Verdict::Drop);
+        assert!(trace.uncertain);
+        packet.iif = Some("br-lan".to_string());
+        let trace = simulate(&host(), &packet, &local(), None);
+        assert_eq!(trace.verdict, Verdict::Accept);
+        assert!(!trace.uncertain);
+    }
+
+    #[test]
+    fn forwarded_traffic_walks_both_families_tables() {
+        let trace = simulate(&host(), &tcp("10.0.0.2", "10.0.1.2", 443), &local(), None);
+        assert_eq!(trace.direction, Direction::Forward);
+        assert_eq!(trace.verdict, Verdict::Drop);
+        let tables: Vec<_> = trace.steps.iter().map(|s| s.table.as_str()).collect();
+        assert!(tables.contains(&"filter"));
+        // The ip filter table is not consulted for IPv6 packets.
+        let trace = simulate(&host(), &tcp("fd00::2", "fd01::2", 443), &local(), None);
+        assert!(trace.steps.iter().all(|s| s.table == "ghostctl"));
+    }
+
+    #[test]
+    fn dnat_in_prerouting_reroutes_the_packet() {
+        let mut nat = spec::base_chain("prerouting", ChainType::Nat, Hook::Prerouting, -100);
+        let mut rule = spec::rule(
+            vec![
+                Match::Protocol {
+                    protocol: Protocol::Tcp,
+                },
+                Match::DestinationPort {
+                    port: PortMatch {
+                        ports: vec![PortSpec::Single(8080)],
+                        negated: false,
+                    },
+                },
+            ],
+            RuleVerdict::Continue,
+        );
+        rule.expression.statements.push(Statement::Dnat {
+            address: "10.0.0.20".to_string(),
+            port: Some(80),
+        });
+        nat.rules.push(rule);
+        let mut forward = spec::base_chain("forward", ChainType::Filter, Hook::Forward, 0);
+        forward.policy = Some(ChainPolicy::Drop);
+        forward.rules.push(spec::rule(
+            vec![Match::DestinationPort {
+                port: PortMatch {
+                    ports: vec![PortSpec::Single(80)],
+                    negated: false,
+                },
+            }],
+            RuleVerdict::Accept,
+        ));
+        let mut input = spec::base_chain("input", ChainType::Filter, Hook::Input, 0);
+        input.policy = Some(ChainPolicy::Drop);
+        let table = NftTable {
+            chains: vec![nat, forward, input],
+            ..live::model(r#"{"nftables":[{"table":{"family":"ip","name":"nat"}}]}"#)
+                .unwrap()
+                .remove(0)
+        };
+        let trace = simulate(
+            &[table],
+            &tcp("203.0.113.9", "192.168.1.1", 8080),
+            &local(),
+            None,
+        );
+        assert_eq!(trace.direction, Direction::Forward);
+        assert_eq!(trace.verdict, Verdict::Accept);
+        assert!(trace.notes.iter().any(|n| n == "dnat to 10.0.0.20 port 80"));
+        assert_eq!(trace.steps.last().unwrap().chain, "forward");
+    }
+
+    #[test]
+    fn monitor_output_is_summarised_per_trace() {
+        let output = "\
+trace id 3a7c1e2f inet ghostctl_trace prerouting packet: iif \"eth0\" ip saddr 203.0.113.9 ip daddr 192.168.1.1 tcp dport 22
+trace id 3a7c1e2f inet ghostctl_trace prerouting rule ip saddr 203.0.113.9 ip daddr 192.168.1.1 tcp dport 22 meta nftrace set 1 (verdict continue)
+trace id 3a7c1e2f inet ghostctl_trace prerouting policy accept
+trace id 3a7c1e2f inet ghostctl input packet: iif \"eth0\" ip saddr 203.0.113.9
+trace id 3a7c1e2f inet ghostctl input policy drop
+trace id 51b0d4aa inet ghostctl input rule ip saddr @admin tcp dport 22 accept comment \"ssh\" (verdict accept)
+";
+        let observed = summarize_monitor(output);
+        assert_eq!(
+            observed,
+            vec![
+                Observed {
+                    id: "3a7c1e2f".to_string(),
+                    verdict: "drop".to_string(),
+                    chain: "inet ghostctl input".to_string(),
+                    rule: "-".to_string(),
+                },
+                Observed {
+                    id: "51b0d4aa".to_string(),
+                    verdict: "accept".to_string(),
+                    chain: "inet ghostctl input".to_string(),
+                    rule: "ip saddr @admin tcp dport 22 accept comment \"ssh\"".to_string(),
+                },
+            ]
+        );
+        let script = confirm_script(&tcp("203.0.113.9", "192.168.1.1", 22));
+        assert!(
+            script.contains(
+                "ip saddr 203.0.113.9 ip daddr 192.168.1.1 tcp dport 22 meta nftrace set 1"
+            )
+        );
+    }
+
+    #[test]
+    fn local_addresses_come_from_ip_json() {
+        let json = r#"[{"ifname":"lo","addr_info":[{"family":"inet","local":"127.0.0.1"}]},
+            {"ifname":"eth0","addr_info":[{"family":"inet","local":"192.168.1.1"},
+            {"family":"inet6","local":"fe80::1"}]}]"#;
+        let addrs = local_addresses(json);
+        assert_eq!(addrs.len(), 3);
+        let packet = tcp("192.168.1.1", "1.1.1.1", 443);
+        assert_eq!(direction(&packet, &addrs), Direction::Output);
+    }
+}
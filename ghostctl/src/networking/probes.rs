@@ -0,0 +1,466 @@
+//! Service probe definitions for the scanner.
+//!
+//! The built-in probes come from [`super::services::SERVICES`]. Extra probes
+//! are read from `probes.toml` and `probes.d/*.toml` next to `config.toml`
+//! and take precedence, so internal services can be identified without a
+//! rebuild:
+//!
+//! ```toml
+//! [[probe]]
+//! name = "billing-api"
+//! ports = [9440, 9441]
+//! payload = "STATUS\r\n"          # or payload_hex = "0001ff"
+//! match = '^BILLING (?<ver>[\d.]+)'
+//! version = "ver"                 # capture group name or number
+//! tls = true                      # wrap the exchange in TLS
+//! ```
+//!
+//! A probe without `ports` is tried against the response of every port, so
+//! a match-only probe can recognise a banner wherever it shows up.
+
+use super::services::SERVICES;
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeFile {
+    #[serde(default)]
+    probe: Vec<ProbeDef>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeDef {
+    name: String,
+    #[serde(default)]
+    ports: Vec<u16>,
+    payload: Option<String>,
+    payload_hex: Option<String>,
+    #[serde(rename = "match")]
+    pattern: Option<String>,
+    service: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    tls: bool,
+}
+
+/// Which capture group of `match` holds the version.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub name: String,
+    pub ports: Vec<u16>,
+    /// Sent after connecting; empty for services that speak first.
+    pub payload: Vec<u8>,
+    pub pattern: Option<Regex>,
+    /// Service name reported on a match (defaults to `name`).
+    pub service: String,
+    pub version: Option<Capture>,
+    pub tls: bool,
+    /// File the probe came from, or `built-in`.
+    pub source: String,
+}
+
+/// What a probe recognised in a response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identified {
+    pub probe: String,
+    pub service: String,
+    pub version: Option<String>,
+}
+
+impl Probe {
+    fn from_def(def: ProbeDef, source: &str) -> Result<Self> {
+        let name = def.name.trim().to_string();
+        if name.is_empty() {
+            bail!("probe without a name");
+        }
+        let payload = match (def.payload, def.payload_hex) {
+            (Some(_), Some(_)) => bail!("probe '{name}': set payload or payload_hex, not both"),
+            (Some(text), None) => text.into_bytes(),
+            (None, Some(hex)) => decode_hex(&hex).with_context(|| format!("probe '{name}'"))?,
+            (None, None) => Vec::new(),
+        };
+        let pattern = def
+            .pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(|| format!("probe '{name}': invalid match regex"))?;
+        let version = match def.version {
+            None => None,
+            Some(capture) => {
+                let Some(re) = &pattern else {
+                    bail!("probe '{name}': version needs a match regex");
+                };
+                let capture = match capture.parse::<usize>() {
+                    Ok(index) if index < re.captures_len() => Capture::Index(index),
+                    Ok(index) => bail!("probe '{name}': match regex has no group {index}"),
+                    Err(_) if re.capture_names().flatten().any(|n| n == capture) => {
+                        Capture::Name(capture)
+                    }
+                    Err(_) => bail!("probe '{name}': match regex has no group '{capture}'"),
+                };
+                Some(capture)
+            }
+        };
+        if pattern.is_none() && def.ports.is_empty() {
+            bail!("probe '{name}': needs ports, a match regex or both");
+        }
+        Ok(Self {
+            service: def.service.unwrap_or_else(|| name.clone()),
+            name,
+            ports: def.ports,
+            payload,
+            pattern,
+            version,
+            tls: def.tls,
+            source: source.to_string(),
+        })
+    }
+
+    fn applies_to(&self, port: u16) -> bool {
+        self.ports.is_empty() || self.ports.contains(&port)
+    }
+
+    /// Match `response` against this probe. Probes without a regex match
+    /// nothing; the scanner falls back to the port's well-known service.
+    pub fn identify(&self, response: &str) -> Option<Identified> {
+        let caps = self.pattern.as_ref()?.captures(response)?;
+        let version = match &self.version {
+            Some(Capture::Index(i)) => caps.get(*i),
+            Some(Capture::Name(n)) => caps.name(n),
+            None => None,
+        }
+        .map(|m| m.as_str().trim().to_string())
+        .filter(|v| !v.is_empty());
+        Some(Identified {
+            probe: self.name.clone(),
+            service: self.service.clone(),
+            version,
+        })
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        bail!("payload_hex has an odd number of digits");
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .with_context(|| format!("invalid hex byte '{}'", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+/// Parse one probe file.
+pub fn parse(text: &str, source: &str) -> Result<Vec<Probe>> {
+    let file: ProbeFile = toml::from_str(text).with_context(|| format!("invalid {source}"))?;
+    file.probe
+        .into_iter()
+        .map(|def| Probe::from_def(def, source))
+        .collect::<Result<_>>()
+        .with_context(|| format!("invalid {source}"))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProbeSet {
+    /// Configured probes first, then the built-in ones.
+    pub probes: Vec<Probe>,
+}
+
+impl ProbeSet {
+    pub fn builtin() -> Self {
+        let mut ports: Vec<_> = SERVICES.iter().collect();
+        ports.sort_by_key(|(port, _)| **port);
+        let probes = ports
+            .into_iter()
+            .filter(|(_, info)| info.probe.is_some() || info.version_pattern.is_some() || info.tls)
+            .map(|(port, info)| Probe {
+                name: info.name.to_string(),
+                ports: vec![*port],
+                payload: info.probe.unwrap_or_default().as_bytes().to_vec(),
+                pattern: info.version_pattern.and_then(|p| Regex::new(p).ok()),
+                service: info.name.to_string(),
+                version: info.version_pattern.map(|_| Capture::Index(1)),
+                tls: info.tls,
+                source: "built-in".to_string(),
+            })
+            .collect();
+        Self { probes }
+    }
+
+    /// The configured files (in order) ahead of the built-in probes.
+    pub fn load(files: &[PathBuf]) -> Result<Self> {
+        let mut probes = Vec::new();
+        for file in files {
+            let text = std::fs::read_to_string(file)
+                .with_context(|| format!("cannot read {}", file.display()))?;
+            probes.extend(parse(&text, &file.display().to_string())?);
+        }
+        probes.extend(Self::builtin().probes);
+        Ok(Self { probes })
+    }
+
+    /// The probe whose payload and TLS setting are used on `port`.
+    pub fn for_port(&self, port: u16) -> Option<&Probe> {
+        self.probes.iter().find(|p| p.ports.contains(&port))
+    }
+
+    /// First probe for `port` whose regex matches `response`.
+    pub fn identify(&self, port: u16, response: &str) -> Option<Identified> {
+        self.probes
+            .iter()
+            .filter(|p| p.applies_to(port))
+            .find_map(|p| p.identify(response))
+    }
+}
+
+/// `probes.toml` and `probes.d/*.toml` beside the main config, if present.
+pub fn default_files() -> Vec<PathBuf> {
+    let config = crate::config::GhostConfig::config_path();
+    let mut files = Vec::new();
+    let single = config.with_file_name("probes.toml");
+    if single.is_file() {
+        files.push(single);
+    }
+    files.extend(dir_files(&config.with_file_name("probes.d")));
+    files
+}
+
+fn dir_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+static ACTIVE: LazyLock<ProbeSet> = LazyLock::new(|| {
+    ProbeSet::load(&default_files()).unwrap_or_else(|e| {
+        crate::tui::warn(&format!("Ignoring custom probes: {e:#}"));
+        ProbeSet::builtin()
+    })
+});
+
+/// The probe set used by scans: configured plus built-in probes.
+pub fn active() -> &'static ProbeSet {
+    &ACTIVE
+}
+
+#[derive(Serialize)]
+struct ProbeSummary<'a> {
+    name: &'a str,
+    service: &'a str,
+    ports: &'a [u16],
+    tls: bool,
+    payload_bytes: usize,
+    matches: Option<&'a str>,
+    source: &'a str,
+}
+
+pub fn command() -> clap::Command {
+    use clap::{Arg, ArgAction, Command};
+    Command::new("probes")
+        .about("List service probes, or check a probe file")
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .value_name("FILE")
+                .help("Validate a probe file instead of listing the active probes"),
+        )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Include the built-in probes"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+pub fn handle(matches: &clap::ArgMatches) -> Result<()> {
+    let set = match matches.get_one::<String>("check") {
+        Some(file) => {
+            let set = ProbeSet::load(&[PathBuf::from(file)])?;
+            crate::tui::success(&format!(
+                "{file}: {} probe(s) OK",
+                set.probes.iter().filter(|p| p.source != "built-in").count()
+            ));
+            set
+        }
+        None => ProbeSet::load(&default_files())?,
+    };
+    let shown: Vec<&Probe> = set
+        .probes
+        .iter()
+        .filter(|p| matches.get_flag("all") || p.source != "built-in")
+        .collect();
+    if matches.get_flag("json") {
+        let summary: Vec<ProbeSummary> = shown
+            .iter()
+            .map(|p| ProbeSummary {
+                name: &p.name,
+                service: &p.service,
+                ports: &p.ports,
+                tls: p.tls,
+                payload_bytes: p.payload.len(),
+                matches: p.pattern.as_ref().map(Regex::as_str),
+                source: &p.source,
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+    if shown.is_empty() {
+        println!("No custom probes (add them to probes.toml or probes.d/ beside config.toml)");
+        return Ok(());
+    }
+    println!(
+        "{:<20} {:<16} {:<18} {:<4} SOURCE",
+        "PROBE", "SERVICE", "PORTS", "TLS"
+    );
+    for p in shown {
+        let ports = if p.ports.is_empty() {
+            "any".to_string()
+        } else {
+            p.ports
+                .iter()
+                .map(u16::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        println!(
+            "{:<20} {:<16} {:<18} {:<4} {}",
+            p.name,
+            p.service,
+            ports,
+            if p.tls { "yes" } else { "" },
+            p.source
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+[[probe]]
+name = "billing-api"
+ports = [9440, 9441]
+payload = "STATUS\r\n"
+match = '^BILLING (?<ver>[\d.]+)'
+version = "ver"
+tls = true
+
+[[probe]]
+name = "legacy-bin"
+ports = [7000]
+payload_hex = "00 01 ff"
+match = 'LEG/(\d+)'
+version = "1"
+service = "legacy"
+
+[[probe]]
+name = "acme-agent"
+match = 'acme-agent v(\S+)'
+version = "1"
+"#;
+
+    #[test]
+    fn probe_file_parses() {
+        let probes = parse(FILE, "probes.toml").unwrap();
+        assert_eq!(probes.len(), 3);
+        assert_eq!(probes[0].payload, b"STATUS\r\n");
+        assert!(probes[0].tls);
+        assert_eq!(probes[0].version, Some(Capture::Name("ver".into())));
+        assert_eq!(probes[1].payload, vec![0x00, 0x01, 0xff]);
+        assert_eq!(probes[1].service, "legacy");
+        assert_eq!(probes[2].service, "acme-agent");
+        assert!(probes[2].ports.is_empty());
+    }
+
+    #[test]
+    fn invalid_probes_are_rejected() {
+        let err = |text: &str| format!("{:#}", parse(text, "p.toml").unwrap_err());
+        assert!(err("[[probe]]\nname = \"x\"\nports = [1]\nmatch = '('").contains("invalid match"));
+        assert!(
+            err("[[probe]]\nname = \"x\"\nmatch = 'a'\nversion = \"2\"").contains("no group 2")
+        );
+        assert!(
+            err("[[probe]]\nname = \"x\"\nports = [1]\nversion = \"1\"").contains("needs a match")
+        );
+        assert!(err("[[probe]]\nname = \"x\"").contains("needs ports"));
+        assert!(err("[[probe]]\nname = \"x\"\nports = [1]\npayload_hex = \"0g\"").contains("hex"));
+        assert!(err("[[probe]]\nname = \"x\"\nports = [1]\nbogus = 1").contains("bogus"));
+    }
+
+    #[test]
+    fn configured_probes_win_over_builtin() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("probes.toml");
+        std::fs::write(
+            &file,
+            "[[probe]]\nname = \"internal-web\"\nports = [8080]\npayload = \"HEAD / HTTP/1.0\\r\\n\\r\\n\"\nmatch = 'Server: intweb/(\\S+)'\nversion = \"1\"\n",
+        )
+        .unwrap();
+        let set = ProbeSet::load(&[file]).unwrap();
+        assert_eq!(set.for_port(8080).unwrap().name, "internal-web");
+        assert_eq!(set.for_port(22).unwrap().source, "built-in");
+        assert!(set.for_port(443).unwrap().tls);
+
+        let id = set
+            .identify(8080, "HTTP/1.0 200 OK\r\nServer: intweb/4.2\r\n")
+            .unwrap();
+        assert_eq!(id.service, "internal-web");
+        assert_eq!(id.version.as_deref(), Some("4.2"));
+        // Built-in patterns still apply when no configured probe matches.
+        let ssh = set.identify(22, "SSH-2.0-OpenSSH_9.6p1\r\n").unwrap();
+        assert_eq!(ssh.service, "ssh");
+        assert!(ssh.version.is_some());
+    }
+
+    #[test]
+    fn portless_probes_match_anywhere() {
+        let set = ProbeSet {
+            probes: parse(FILE, "probes.toml").unwrap(),
+        };
+        let id = set
+            .identify(12345, "hello from acme-agent v3.1.4\n")
+            .unwrap();
+        assert_eq!(id.probe, "acme-agent");
+        assert_eq!(id.version.as_deref(), Some("3.1.4"));
+        assert_eq!(
+            set.identify(9441, "BILLING 2.7 ready")
+                .unwrap()
+                .version
+                .as_deref(),
+            Some("2.7")
+        );
+        assert!(set.identify(9441, "nope").is_none());
+    }
+}
@@ -27,6 +27,10 @@ pub struct ScanResult {
     pub status: PortStatus,
     pub service: Option<String>,
     pub banner: Option<String>,
+    /// Version captured by the matching service probe.
+    pub version: Option<String>,
+    /// Certificate and protocol details for TLS services.
+    pub tls: Option<super::advanced_scanner::SslInfo>,
     pub response_time: Duration,
 }
 
@@ -520,6 +524,8 @@ async fn scan_port(target: &str, port: u16, config: &ScanConfig) -> ScanResult {
                         status: PortStatus::Unknown,
                         service: None,
                         banner: None,
+                        version: None,
+                        tls: None,
                         response_time: start_time.elapsed(),
                     };
                 }
@@ -531,6 +537,8 @@ async fn scan_port(target: &str, port: u16, config: &ScanConfig) -> ScanResult {
                     status: PortStatus::Unknown,
                     service: None,
                     banner: None,
+                    version: None,
+                    tls: None,
                     response_time: start_time.elapsed(),
                 };
             }
@@ -551,19 +559,51 @@ async fn scan_port(target: &str, port: u16, config: &ScanConfig) -> ScanResult {
 
     let mut service = None;
     let mut banner = None;
+    let mut version = None;
+    let mut tls = None;
 
     // Service detection for open ports
     if status == PortStatus::Open && config.service_detection {
         service = detect_service(port);
-
-        // Try to grab banner
-        if let Ok(Ok(mut stream)) = tokio::time::timeout(
+        let probes = super::probes::active();
+        let probe = probes.for_port(port);
+        let payload = probe.map(|p| p.payload.clone()).unwrap_or_default();
+
+        let response = if probe.map_or_else(|| super::services::is_tls_port(port), |p| p.tls) {
+            // rustls is blocking; keep it off the scan workers.
+            let host = target.to_string();
+            let timeout = config.timeout.max(Duration::from_secs(2));
+            let (response, info) = tokio::task::spawn_blocking(move || {
+                (
+                    tls_exchange(&host, port, &payload, timeout),
+                    super::tls::inspect(&host, port, timeout).ok(),
+                )
+            })
+            .await
+            .unwrap_or((None, None));
+            tls = info;
+            response
+        } else if let Ok(Ok(mut stream)) = tokio::time::timeout(
             Duration::from_millis(500),
             tokio::net::TcpStream::connect(socket_addr),
         )
         .await
         {
-            banner = grab_banner(&mut stream, port).await;
+            grab_banner(&mut stream, &payload).await
+        } else {
+            None
+        };
+
+        if let Some(response) = response {
+            if let Some(found) = probes.identify(port, &response) {
+                service = Some(found.service);
+                version = found.version;
+            }
+            banner = response
+                .lines()
+                .next()
+                .map(str::to_string)
+                .filter(|b| !b.trim().is_empty());
         }
     }
 
@@ -573,6 +613,8 @@ async fn scan_port(target: &str, port: u16, config: &ScanConfig) -> ScanResult {
         status,
         service,
         banner,
+        version,
+        tls,
         response_time: start_time.elapsed(),
     }
 }
@@ -582,31 +624,33 @@ fn detect_service(port: u16) -> Option<String> {
     super::services::get_service_name(port).map(|s| s.to_string())
 }
 
-async fn grab_banner(stream: &mut tokio::net::TcpStream, port: u16) -> Option<String> {
+async fn grab_banner(stream: &mut tokio::net::TcpStream, payload: &[u8]) -> Option<String> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-    // Get probe from services database, fallback to empty for banner-on-connect services
-    let probe = super::services::get_probe(port).unwrap_or("");
-
-    if !probe.is_empty() {
-        let _ = stream.write_all(probe.as_bytes()).await;
+    // Services that speak first get no payload
+    if !payload.is_empty() {
+        let _ = stream.write_all(payload).await;
     }
 
     let mut buffer = [0; 2048];
     match tokio::time::timeout(Duration::from_millis(1500), stream.read(&mut buffer)).await {
-        Ok(Ok(n)) if n > 0 => {
-            let banner = String::from_utf8_lossy(&buffer[..n]);
-            // Try to extract version info
-            if let Some(version) = super::services::extract_version(port, &banner) {
-                Some(format!(
-                    "{} ({})",
-                    banner.lines().next().unwrap_or(""),
-                    version
-                ))
-            } else {
-                Some(banner.lines().next().unwrap_or("").to_string())
-            }
-        }
+        Ok(Ok(n)) if n > 0 => Some(String::from_utf8_lossy(&buffer[..n]).into_owned()),
+        _ => None,
+    }
+}
+
+/// [`grab_banner`] over TLS.
+fn tls_exchange(host: &str, port: u16, payload: &[u8], timeout: Duration) -> Option<String> {
+    use std::io::{Read, Write};
+
+    let mut stream = super::tls::connect(host, port, timeout).ok()?;
+    if !payload.is_empty() {
+        stream.write_all(payload).ok()?;
+        stream.flush().ok()?;
+    }
+    let mut buffer = [0; 2048];
+    match stream.read(&mut buffer) {
+        Ok(n) if n > 0 => Some(String::from_utf8_lossy(&buffer[..n]).into_owned()),
         _ => None,
     }
 }
@@ -616,24 +660,77 @@ pub fn scan_cli(
     targets: Vec<String>,
     ports: Option<String>,
     threads: Option<usize>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Vec<ScanResult>, Box<dyn std::error::Error>> {
     let rt = tokio::runtime::Runtime::new()?;
 
     rt.block_on(async {
-        let ports = parse_ports(ports.as_deref().unwrap_or("1-1000"))?;
-        let config = ScanConfig {
-            targets,
-            ports,
-            threads: threads.unwrap_or(100),
-            timeout: Duration::from_millis(1000),
-            scan_type: ScanType::Connect,
-            service_detection: true,
-            os_detection: false,
-            vulnerability_scan: false,
-        };
-
+        let config = cli_config(targets, ports.as_deref(), threads)?;
         let mut app = ScannerApp::new(config);
-        app.run_scan_with_tui().await
+        app.run_scan_with_tui().await?;
+        let results = app.results.lock().map(|r| r.clone()).unwrap_or_default();
+        Ok(results)
+    })
+}
+
+/// Same scan as [`scan_cli`] without the TUI, for cron jobs and `--json`.
+pub fn scan_headless(
+    targets: Vec<String>,
+    ports: Option<String>,
+    threads: Option<usize>,
+) -> Result<Vec<ScanResult>, Box<dyn std::error::Error>> {
+    let rt = tokio::runtime::Runtime::new()?;
+
+    rt.block_on(async {
+        let config = cli_config(targets, ports.as_deref(), threads)?;
+        let app = ScannerApp::new(config);
+        perform_scan(
+            app.config.clone(),
+            Arc::clone(&app.results),
+            Arc::clone(&app.stats),
+        )
+        .await;
+        let results = app.results.lock().map(|r| r.clone()).unwrap_or_default();
+        Ok(results)
+    })
+}
+
+fn cli_config(
+    targets: Vec<String>,
+    ports: Option<&str>,
+    threads: Option<usize>,
+) -> Result<ScanConfig, Box<dyn std::error::Error>> {
+    Ok(ScanConfig {
+        targets,
+        ports: parse_ports(ports.unwrap_or("1-1000"))?,
+        threads: threads.unwrap_or(100),
+        timeout: Duration::from_millis(1000),
+        scan_type: ScanType::Connect,
+        service_detection: true,
+        os_detection: false,
+        vulnerability_scan: false,
+    })
+}
+
+/// OS guesses for `hosts` as `"<guess> (<confidence>%)"`, skipping hosts that
+/// could not be fingerprinted.
+pub fn os_guesses(hosts: &[String]) -> Vec<(String, String)> {
+    let Ok(rt) = tokio::runtime::Runtime::new() else {
+        return Vec::new();
+    };
+    rt.block_on(async {
+        let mut guesses = Vec::new();
+        for host in hosts {
+            let Ok(ip) = host.parse::<IpAddr>() else {
+                continue;
+            };
+            if let Some(fp) = fingerprint_os_enhanced(ip).await {
+                guesses.push((
+                    host.clone(),
+                    format!("{} ({:.0}%)", fp.os_guess, fp.confidence * 100.0),
+                ));
+            }
+        }
+        guesses
     })
 }
 
@@ -1137,6 +1234,8 @@ pub async fn scan_port_extended(target: &str, port: u16, config: &ScanConfig) ->
                             status: PortStatus::Unknown,
                             service: None,
                             banner: None,
+                            version: None,
+                            tls: None,
                             response_time: start_time.elapsed(),
                         };
                     }
@@ -1148,6 +1247,8 @@ pub async fn scan_port_extended(target: &str, port: u16, config: &ScanConfig) ->
                         status: PortStatus::Unknown,
                         service: None,
                         banner: None,
+                        version: None,
+                        tls: None,
                         response_time: start_time.elapsed(),
                     };
                 }
@@ -1186,6 +1287,8 @@ pub async fn scan_port_extended(target: &str, port: u16, config: &ScanConfig) ->
         status,
         service,
         banner: None,
+        version: None,
+        tls: None,
         response_time: start_time.elapsed(),
     }
 }
@@ -1508,6 +1611,8 @@ mod tests {
             status: PortStatus::Open,
             service: Some("http".to_string()),
             banner: Some("Apache/2.4".to_string()),
+            version: None,
+            tls: None,
             response_time: Duration::from_millis(50),
         };
         assert_eq!(result.target, "192.168.1.1");
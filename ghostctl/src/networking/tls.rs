@@ -0,0 +1,808 @@
+//! TLS inspection for the scanner: `ghostctl scan tls`.
+//!
+//! A full handshake through rustls (certificate checks disabled - we want to
+//! see the certificate, not trust it) gives the negotiated protocol and
+//! cipher and the peer certificate, which x509-parser turns into a
+//! [`CertificateInfo`]. rustls only speaks TLS 1.2 and 1.3 with modern
+//! ciphers, so protocol support from SSL 3.0 to TLS 1.2 and weak cipher
+//! support are tested with hand-built ClientHellos: the server either answers
+//! with a ServerHello naming the version and cipher it picked, or refuses
+//! with an alert. Nothing past the ServerHello is negotiated.
+//!
+//! Everything here is blocking; the async scanner calls it through
+//! `spawn_blocking`.
+
+use super::advanced_scanner::{CertificateInfo, SslInfo};
+use anyhow::{Context, Result, anyhow, bail};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned,
+    SupportedProtocolVersion,
+};
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const SSL3: u16 = 0x0300;
+pub const TLS10: u16 = 0x0301;
+pub const TLS11: u16 = 0x0302;
+pub const TLS12: u16 = 0x0303;
+pub const TLS13: u16 = 0x0304;
+
+/// Cipher suites by IANA code with their OpenSSL names; `true` marks the ones
+/// reported as weak (NULL, export, anonymous, RC4, DES and 3DES).
+const SUITES: &[(u16, &str, bool)] = &[
+    (0x1301, "TLS_AES_128_GCM_SHA256", false),
+    (0x1302, "TLS_AES_256_GCM_SHA384", false),
+    (0x1303, "TLS_CHACHA20_POLY1305_SHA256", false),
+    (0xc02b, "ECDHE-ECDSA-AES128-GCM-SHA256", false),
+    (0xc02f, "ECDHE-RSA-AES128-GCM-SHA256", false),
+    (0xc02c, "ECDHE-ECDSA-AES256-GCM-SHA384", false),
+    (0xc030, "ECDHE-RSA-AES256-GCM-SHA384", false),
+    (0xcca9, "ECDHE-ECDSA-CHACHA20-POLY1305", false),
+    (0xcca8, "ECDHE-RSA-CHACHA20-POLY1305", false),
+    (0x009e, "DHE-RSA-AES128-GCM-SHA256", false),
+    (0x009f, "DHE-RSA-AES256-GCM-SHA384", false),
+    (0xc023, "ECDHE-ECDSA-AES128-SHA256", false),
+    (0xc027, "ECDHE-RSA-AES128-SHA256", false),
+    (0xc009, "ECDHE-ECDSA-AES128-SHA", false),
+    (0xc013, "ECDHE-RSA-AES128-SHA", false),
+    (0xc00a, "ECDHE-ECDSA-AES256-SHA", false),
+    (0xc014, "ECDHE-RSA-AES256-SHA", false),
+    (0x0033, "DHE-RSA-AES128-SHA", false),
+    (0x0039, "DHE-RSA-AES256-SHA", false),
+    (0x009c, "AES128-GCM-SHA256", false),
+    (0x009d, "AES256-GCM-SHA384", false),
+    (0x003c, "AES128-SHA256", false),
+    (0x003d, "AES256-SHA256", false),
+    (0x002f, "AES128-SHA", false),
+    (0x0035, "AES256-SHA", false),
+    (0xc012, "ECDHE-RSA-DES-CBC3-SHA", true),
+    (0xc008, "ECDHE-ECDSA-DES-CBC3-SHA", true),
+    (0x0016, "EDH-RSA-DES-CBC3-SHA", true),
+    (0x000a, "DES-CBC3-SHA", true),
+    (0xc011, "ECDHE-RSA-RC4-SHA", true),
+    (0xc007, "ECDHE-ECDSA-RC4-SHA", true),
+    (0x0005, "RC4-SHA", true),
+    (0x0004, "RC4-MD5", true),
+    (0x0015, "EDH-RSA-DES-CBC-SHA", true),
+    (0x0009, "DES-CBC-SHA", true),
+    (0x0014, "EXP-EDH-RSA-DES-CBC-SHA", true),
+    (0x0008, "EXP-DES-CBC-SHA", true),
+    (0x0006, "EXP-RC2-CBC-MD5", true),
+    (0x0003, "EXP-RC4-MD5", true),
+    (0x0034, "ADH-AES128-SHA", true),
+    (0x003a, "ADH-AES256-SHA", true),
+    (0x001b, "ADH-DES-CBC3-SHA", true),
+    (0x0018, "ADH-RC4-MD5", true),
+    (0xc018, "AECDH-AES128-SHA", true),
+    (0xc016, "AECDH-RC4-SHA", true),
+    (0x003b, "NULL-SHA256", true),
+    (0x0002, "NULL-SHA", true),
+    (0x0001, "NULL-MD5", true),
+];
+
+pub fn cipher_name(code: u16) -> String {
+    SUITES
+        .iter()
+        .find(|(c, _, _)| *c == code)
+        .map(|(_, name, _)| name.to_string())
+        .unwrap_or_else(|| format!("0x{code:04x}"))
+}
+
+pub fn version_name(version: u16) -> &'static str {
+    match version {
+        SSL3 => "SSLv3",
+        TLS10 => "TLSv1.0",
+        TLS11 => "TLSv1.1",
+        TLS12 => "TLSv1.2",
+        TLS13 => "TLSv1.3",
+        _ => "unknown",
+    }
+}
+
+/// Protocol versions that should no longer be offered.
+pub fn is_legacy_version(name: &str) -> bool {
+    matches!(name, "SSLv3" | "TLSv1.0" | "TLSv1.1")
+}
+
+/// A ClientHello record offering `suites` at `version` (SSL 3.0 - TLS 1.2).
+pub fn client_hello(version: u16, suites: &[u16], server_name: Option<&str>) -> Vec<u8> {
+    let mut random = [0u8; 32];
+    if getrandom::fill(&mut random).is_err() {
+        random = [0x5a; 32];
+    }
+    let mut body = Vec::new();
+    body.extend(version.to_be_bytes());
+    body.extend(random);
+    body.push(0); // no session id
+    let mut offered: Vec<u8> = suites.iter().flat_map(|s| s.to_be_bytes()).collect();
+    offered.extend([0x00, 0xff]); // TLS_EMPTY_RENEGOTIATION_INFO_SCSV
+    body.extend((offered.len() as u16).to_be_bytes());
+    body.extend(offered);
+    body.extend([1, 0]); // null compression
+
+    let mut extensions = Vec::new();
+    let mut extension = |kind: u16, data: &[u8]| {
+        extensions.extend(kind.to_be_bytes());
+        extensions.extend((data.len() as u16).to_be_bytes());
+        extensions.extend(data);
+    };
+    if let Some(name) = server_name.filter(|n| n.parse::<IpAddr>().is_err()) {
+        let name = name.as_bytes();
+        let mut sni = Vec::new();
+        sni.extend(((name.len() + 3) as u16).to_be_bytes());
+        sni.push(0); // host_name
+        sni.extend((name.len() as u16).to_be_bytes());
+        sni.extend(name);
+        extension(0x0000, &sni);
+    }
+    // x25519, secp256r1, secp384r1, secp521r1
+    extension(
+        0x000a,
+        &[0, 8, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18, 0x00, 0x19],
+    );
+    extension(0x000b, &[1, 0]); // uncompressed points
+    if version >= TLS12 {
+        let schemes: [u16; 11] = [
+            0x0403, 0x0503, 0x0603, 0x0804, 0x0805, 0x0806, 0x0401, 0x0501, 0x0601, 0x0203, 0x0201,
+        ];
+        let mut data = Vec::new();
+        data.extend(((schemes.len() * 2) as u16).to_be_bytes());
+        data.extend(schemes.iter().flat_map(|s| s.to_be_bytes()));
+        extension(0x000d, &data);
+    }
+    extension(0x0017, &[]); // extended master secret
+    if version > SSL3 {
+        body.extend((extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+    }
+
+    let mut handshake = vec![1]; // client_hello
+    handshake.extend(&(body.len() as u32).to_be_bytes()[1..]);
+    handshake.extend(body);
+    let mut record = vec![22]; // handshake
+    record.extend(version.min(TLS10).to_be_bytes());
+    record.extend((handshake.len() as u16).to_be_bytes());
+    record.extend(handshake);
+    record
+}
+
+/// How a server answered a ClientHello.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerReply {
+    Hello {
+        version: u16,
+        cipher: u16,
+        /// DER certificates, leaf first, when the server sent them in the
+        /// clear (TLS 1.2 and older).
+        certificates: Vec<Vec<u8>>,
+    },
+    Alert {
+        description: u8,
+    },
+    /// Not TLS, or the connection closed before a ServerHello.
+    None,
+}
+
+/// The server's records up to ServerHelloDone (or the first alert).
+pub fn parse_reply(data: &[u8]) -> ServerReply {
+    let mut handshake = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 5 {
+        let kind = rest[0];
+        let len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        let Some(fragment) = rest.get(5..5 + len) else {
+            break;
+        };
+        match kind {
+            21 if fragment.len() >= 2 => {
+                return ServerReply::Alert {
+                    description: fragment[1],
+                };
+            }
+            22 => handshake.extend_from_slice(fragment),
+            _ => {}
+        }
+        rest = &rest[5 + len..];
+    }
+
+    let mut reply = ServerReply::None;
+    let mut messages = handshake.as_slice();
+    while messages.len() >= 4 {
+        let kind = messages[0];
+        let len = u32::from_be_bytes([0, messages[1], messages[2], messages[3]]) as usize;
+        let Some(body) = messages.get(4..4 + len) else {
+            break;
+        };
+        match kind {
+            2 if body.len() >= 35 => {
+                let session = body[34] as usize;
+                if let Some(cipher) = body.get(35 + session..37 + session) {
+                    reply = ServerReply::Hello {
+                        version: u16::from_be_bytes([body[0], body[1]]),
+                        cipher: u16::from_be_bytes([cipher[0], cipher[1]]),
+                        certificates: Vec::new(),
+                    };
+                }
+            }
+            11 => {
+                if let ServerReply::Hello { certificates, .. } = &mut reply {
+                    let mut list = body.get(3..).unwrap_or_default();
+                    while list.len() >= 3 {
+                        let len = u32::from_be_bytes([0, list[0], list[1], list[2]]) as usize;
+                        let Some(der) = list.get(3..3 + len) else {
+                            break;
+                        };
+                        certificates.push(der.to_vec());
+                        list = &list[3 + len..];
+                    }
+                }
+            }
+            _ => {}
+        }
+        messages = &messages[4 + len..];
+    }
+    reply
+}
+
+/// Whether `data` holds a complete answer: an alert or ServerHelloDone.
+fn reply_complete(data: &[u8]) -> bool {
+    let mut rest = data;
+    while rest.len() >= 5 {
+        let len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        let Some(fragment) = rest.get(5..5 + len) else {
+            return false;
+        };
+        if rest[0] == 21 {
+            return true;
+        }
+        // ServerHelloDone is an empty message, usually the last in a record.
+        if rest[0] == 22 && fragment.ends_with(&[14, 0, 0, 0]) {
+            return true;
+        }
+        rest = &rest[5 + len..];
+    }
+    false
+}
+
+fn tcp(host: &str, port: u16, timeout: Duration) -> Result<TcpStream> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("cannot resolve {host}"))?
+        .next()
+        .with_context(|| format!("no address for {host}"))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)
+        .with_context(|| format!("cannot connect to {host}:{port}"))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// Send one ClientHello and read the answer.
+pub fn hello(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    version: u16,
+    suites: &[u16],
+) -> Result<ServerReply> {
+    let mut stream = tcp(host, port, timeout)?;
+    stream.write_all(&client_hello(version, suites, Some(host)))?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 8192];
+    while !reply_complete(&data) && data.len() < 256 * 1024 {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+        }
+    }
+    Ok(parse_reply(&data))
+}
+
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// A TLS session that accepts any certificate, for probing and inspection.
+pub fn connect(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<StreamOwned<ClientConnection, TcpStream>> {
+    connect_with(host, port, timeout, rustls::ALL_VERSIONS)
+}
+
+fn connect_with(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    versions: &[&'static SupportedProtocolVersion],
+) -> Result<StreamOwned<ClientConnection, TcpStream>> {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(versions)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|_| anyhow!("'{host}' is not a valid TLS server name"))?;
+    let mut conn = ClientConnection::new(Arc::new(config), name)?;
+    let mut sock = tcp(host, port, timeout)?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut sock)
+            .with_context(|| format!("TLS handshake with {host}:{port} failed"))?;
+    }
+    Ok(StreamOwned::new(conn, sock))
+}
+
+/// Certificate details from DER.
+pub fn certificate_info(der: &[u8]) -> Result<CertificateInfo> {
+    use x509_parser::extensions::GeneralName;
+    let (_, cert) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| anyhow!("invalid certificate: {e}"))?;
+    let time = |t: &x509_parser::time::ASN1Time| {
+        chrono::DateTime::from_timestamp(t.timestamp(), 0)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_default()
+    };
+    let registry = x509_parser::objects::oid_registry();
+    let signature = &cert.signature_algorithm.algorithm;
+    let subject_alt_names = match cert.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(ip) => match ip.len() {
+                    4 => Some(IpAddr::from(<[u8; 4]>::try_from(*ip).ok()?).to_string()),
+                    16 => Some(IpAddr::from(<[u8; 16]>::try_from(*ip).ok()?).to_string()),
+                    _ => None,
+                },
+                GeneralName::RFC822Name(mail) => Some(format!("email:{mail}")),
+                GeneralName::URI(uri) => Some(format!("uri:{uri}")),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let public_key = match cert.public_key().parsed() {
+        Ok(x509_parser::public_key::PublicKey::RSA(rsa)) => format!("RSA {}", rsa.key_size()),
+        Ok(x509_parser::public_key::PublicKey::EC(ec)) => format!("EC {}", ec.key_size()),
+        Ok(other) => format!("{} {}", key_kind(&other), other.key_size()),
+        Err(_) => "unknown".to_string(),
+    };
+    Ok(CertificateInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        validity_start: time(&cert.validity().not_before),
+        validity_end: time(&cert.validity().not_after),
+        serial_number: cert.raw_serial_as_string(),
+        signature_algorithm: x509_parser::objects::oid2sn(signature, registry)
+            .map(str::to_string)
+            .unwrap_or_else(|_| signature.to_id_string()),
+        subject_alt_names,
+        public_key,
+        self_signed: cert.subject().as_raw() == cert.issuer().as_raw(),
+    })
+}
+
+fn key_kind(key: &x509_parser::public_key::PublicKey) -> &'static str {
+    use x509_parser::public_key::PublicKey;
+    match key {
+        PublicKey::RSA(_) => "RSA",
+        PublicKey::EC(_) => "EC",
+        PublicKey::DSA(_) => "DSA",
+        PublicKey::GostR3410(_) | PublicKey::GostR3410_2012(_) => "GOST",
+        _ => "unknown",
+    }
+}
+
+/// Handshake with `host:port` and test which protocol versions and weak
+/// ciphers it accepts.
+pub fn inspect(host: &str, port: u16, timeout: Duration) -> Result<SslInfo> {
+    let all: Vec<u16> = SUITES
+        .iter()
+        .map(|(code, _, _)| *code)
+        .filter(|code| *code >> 8 != 0x13)
+        .collect();
+    let weak: Vec<u16> = SUITES
+        .iter()
+        .filter(|(_, _, weak)| *weak)
+        .map(|(code, _, _)| *code)
+        .collect();
+
+    let mut supported_versions = Vec::new();
+    let mut legacy = Vec::new();
+    let mut best: Option<(u16, u16, Vec<Vec<u8>>)> = None;
+    for version in [SSL3, TLS10, TLS11, TLS12] {
+        if let Ok(ServerReply::Hello {
+            version: chosen,
+            cipher,
+            certificates,
+        }) = hello(host, port, timeout, version, &all)
+            && chosen == version
+        {
+            supported_versions.push(version_name(version).to_string());
+            legacy.push(version);
+            best = Some((version, cipher, certificates));
+        }
+    }
+    let modern = connect_with(host, port, timeout, &[&rustls::version::TLS13]).is_ok();
+    if modern {
+        supported_versions.push(version_name(TLS13).to_string());
+    }
+
+    let mut weak_ciphers: Vec<String> = Vec::new();
+    for version in &legacy {
+        let mut offered = weak.clone();
+        while !offered.is_empty() {
+            match hello(host, port, timeout, *version, &offered) {
+                Ok(ServerReply::Hello { cipher, .. }) if offered.contains(&cipher) => {
+                    let name = cipher_name(cipher);
+                    if !weak_ciphers.contains(&name) {
+                        weak_ciphers.push(name);
+                    }
+                    offered.retain(|c| *c != cipher);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    match connect(host, port, timeout) {
+        Ok(stream) => {
+            let conn = &stream.conn;
+            let certificate = conn
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|der| certificate_info(der.as_ref()))
+                .transpose()?;
+            Ok(SslInfo {
+                version: conn
+                    .protocol_version()
+                    .map(|v| version_name(u16::from(v)).to_string())
+                    .unwrap_or_default(),
+                cipher: conn
+                    .negotiated_cipher_suite()
+                    .map(|s| cipher_name(u16::from(s.suite())))
+                    .unwrap_or_default(),
+                certificate,
+                supported_versions,
+                weak_ciphers,
+            })
+        }
+        // Servers stuck on old protocols or ciphers rustls does not offer.
+        Err(e) => {
+            let Some((version, cipher, certificates)) = best else {
+                return Err(e);
+            };
+            Ok(SslInfo {
+                version: version_name(version).to_string(),
+                cipher: cipher_name(cipher),
+                certificate: certificates
+                    .first()
+                    .map(|der| certificate_info(der))
+                    .transpose()?,
+                supported_versions,
+                weak_ciphers,
+            })
+        }
+    }
+}
+
+/// Days until `info`'s certificate expires (negative once expired).
+pub fn days_left(info: &CertificateInfo, now: chrono::DateTime<chrono::Utc>) -> Option<i64> {
+    let end = chrono::DateTime::parse_from_rfc3339(&info.validity_end).ok()?;
+    Some((end.with_timezone(&chrono::Utc) - now).num_days())
+}
+
+/// Problems worth flagging in an inspection result.
+pub fn findings(info: &SslInfo, now: chrono::DateTime<chrono::Utc>) -> Vec<String> {
+    let mut out = Vec::new();
+    for version in info
+        .supported_versions
+        .iter()
+        .filter(|v| is_legacy_version(v))
+    {
+        out.push(format!("{version} is accepted"));
+    }
+    for cipher in &info.weak_ciphers {
+        out.push(format!("weak cipher {cipher} is accepted"));
+    }
+    if let Some(cert) = &info.certificate {
+        match days_left(cert, now) {
+            Some(days) if days < 0 => out.push(format!("certificate expired {} days ago", -days)),
+            Some(days) if days < 30 => out.push(format!("certificate expires in {days} days")),
+            _ => {}
+        }
+        if cert.self_signed {
+            out.push("certificate is self-signed".to_string());
+        }
+        if cert.public_key.starts_with("RSA ")
+            && cert.public_key[4..]
+                .parse::<usize>()
+                .is_ok_and(|bits| bits < 2048)
+        {
+            out.push(format!("weak key ({})", cert.public_key));
+        }
+        if cert.signature_algorithm.contains("sha1") || cert.signature_algorithm.contains("md5") {
+            out.push(format!("weak signature ({})", cert.signature_algorithm));
+        }
+    }
+    out
+}
+
+pub fn print_info(host: &str, port: u16, info: &SslInfo) {
+    println!("TLS on {host}:{port}");
+    println!("  Negotiated:  {} {}", info.version, info.cipher);
+    println!("  Protocols:   {}", info.supported_versions.join(", "));
+    if info.weak_ciphers.is_empty() {
+        println!("  Weak ciphers: none accepted");
+    } else {
+        println!("  Weak ciphers: {}", info.weak_ciphers.join(", "));
+    }
+    if let Some(cert) = &info.certificate {
+        println!("  Subject:     {}", cert.subject);
+        if !cert.subject_alt_names.is_empty() {
+            println!("  SANs:        {}", cert.subject_alt_names.join(", "));
+        }
+        println!("  Issuer:      {}", cert.issuer);
+        println!(
+            "  Valid:       {} to {}",
+            cert.validity_start, cert.validity_end
+        );
+        println!(
+            "  Key:         {} ({})",
+            cert.public_key, cert.signature_algorithm
+        );
+        println!("  Serial:      {}", cert.serial_number);
+    }
+    let findings = findings(info, chrono::Utc::now());
+    if findings.is_empty() {
+        crate::tui::success("No TLS issues found");
+    }
+    for finding in findings {
+        crate::tui::warn(&finding);
+    }
+}
+
+pub fn command() -> clap::Command {
+    use clap::{Arg, ArgAction, Command};
+    Command::new("tls")
+        .about("Inspect a TLS service: certificate, protocol versions and weak ciphers")
+        .arg(
+            Arg::new("target")
+                .required(true)
+                .value_name("HOST[:PORT]")
+                .help("Host to inspect (port 443 unless given)"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("5")
+                .help("Connect and read timeout per handshake"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+pub fn handle(matches: &clap::ArgMatches) -> Result<()> {
+    let target = matches
+        .get_one::<String>("target")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let (host, port) = split_target(target)?;
+    let timeout = Duration::from_secs(matches.get_one::<u64>("timeout").copied().unwrap_or(5));
+    let info = inspect(&host, port, timeout)?;
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        print_info(&host, port, &info);
+    }
+    Ok(())
+}
+
+/// `host`, `host:port`, `[v6]:port` or a bare IPv6 address.
+fn split_target(target: &str) -> Result<(String, u16)> {
+    if let Some(rest) = target.strip_prefix('[') {
+        let (host, port) = rest.split_once(']').context("unterminated '[' in target")?;
+        let port = match port.strip_prefix(':') {
+            Some(port) => port.parse().context("invalid port")?,
+            None => 443,
+        };
+        return Ok((host.to_string(), port));
+    }
+    if target.parse::<IpAddr>().is_ok() {
+        return Ok((target.to_string(), 443));
+    }
+    match target.rsplit_once(':') {
+        Some((host, port)) => Ok((host.to_string(), port.parse().context("invalid port")?)),
+        None if target.is_empty() => bail!("no target given"),
+        None => Ok((target.to_string(), 443)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{cert_der, tls_config};
+    use std::net::TcpListener;
+
+    /// A rustls server that answers every connection, replying to any
+    /// request with `reply`.
+    fn server(reply: &'static [u8]) -> u16 {
+        let config = tls_config();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let config = Arc::clone(&config);
+                std::thread::spawn(move || {
+                    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+                    let conn = rustls::ServerConnection::new(config).unwrap();
+                    let mut tls = StreamOwned::new(conn, stream);
+                    let mut buf = [0u8; 512];
+                    if tls.read(&mut buf).is_ok_and(|n| n > 0) {
+                        let _ = tls.write_all(reply);
+                        let _ = tls.flush();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn certificate_details_are_extracted() {
+        let info = certificate_info(&cert_der()).unwrap();
+        assert_eq!(info.subject, "CN=lab.internal, O=Ghost Lab");
+        assert_eq!(info.issuer, info.subject);
+        assert!(info.self_signed);
+        assert_eq!(
+            info.subject_alt_names,
+            vec!["lab.internal", "*.lab.internal", "10.0.0.5"]
+        );
+        assert_eq!(info.serial_number, "1a:2b:3c");
+        assert_eq!(info.public_key, "EC 256");
+        assert_eq!(info.signature_algorithm, "ecdsa-with-SHA256");
+        assert!(info.validity_end.starts_with("2126-"));
+    }
+
+    #[test]
+    fn client_hello_is_well_formed_and_replies_parse() {
+        let hello = client_hello(TLS12, &[0xc02f, 0x000a], Some("lab.internal"));
+        assert_eq!(&hello[..3], &[22, 0x03, 0x01]);
+        assert_eq!(
+            u16::from_be_bytes([hello[3], hello[4]]) as usize,
+            hello.len() - 5
+        );
+        assert_eq!(hello[5], 1);
+        assert_eq!(&hello[9..11], &[0x03, 0x03]);
+        assert!(hello.windows(12).any(|w| w == b"lab.internal"));
+        assert!(
+            !client_hello(TLS12, &[0xc02f], Some("10.0.0.5"))
+                .windows(8)
+                .any(|w| w == b"10.0.0.5")
+        );
+
+        // ServerHello (TLS 1.0, RC4-SHA) + Certificate + ServerHelloDone.
+        let der = cert_der();
+        let mut body = vec![0x03, 0x01];
+        body.extend([0u8; 32]);
+        body.extend([0, 0x00, 0x05, 0]);
+        let mut msgs = vec![2];
+        msgs.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        msgs.extend(&body);
+        let mut certs = Vec::new();
+        certs.extend(&(der.len() as u32).to_be_bytes()[1..]);
+        certs.extend(&der);
+        msgs.push(11);
+        msgs.extend(&((certs.len() + 3) as u32).to_be_bytes()[1..]);
+        msgs.extend(&(certs.len() as u32).to_be_bytes()[1..]);
+        msgs.extend(&certs);
+        msgs.extend([14, 0, 0, 0]);
+        let mut records = vec![22, 0x03, 0x01];
+        records.extend((msgs.len() as u16).to_be_bytes());
+        records.extend(&msgs);
+        assert!(reply_complete(&records));
+        assert_eq!(
+            parse_reply(&records),
+            ServerReply::Hello {
+                version: TLS10,
+                cipher: 0x0005,
+                certificates: vec![der],
+            }
+        );
+        assert!(!reply_complete(&records[..40]));
+        assert_eq!(
+            parse_reply(&[21, 0x03, 0x03, 0, 2, 2, 40]),
+            ServerReply::Alert { description: 40 }
+        );
+        assert_eq!(
+            parse_reply(b"HTTP/1.1 400 Bad Request\r\n"),
+            ServerReply::None
+        );
+    }
+
+    #[test]
+    fn inspect_reads_a_live_server() {
+        let port = server(b"pong\n");
+        let info = inspect("127.0.0.1", port, Duration::from_secs(5)).unwrap();
+        assert_eq!(info.version, "TLSv1.3");
+        assert_eq!(info.supported_versions, vec!["TLSv1.2", "TLSv1.3"]);
+        assert!(info.weak_ciphers.is_empty());
+        let cert = info.certificate.as_ref().unwrap();
+        assert_eq!(cert.subject, "CN=lab.internal, O=Ghost Lab");
+        let now = chrono::Utc::now();
+        assert_eq!(findings(&info, now), vec!["certificate is self-signed"]);
+
+        let mut stream = connect("127.0.0.1", port, Duration::from_secs(5)).unwrap();
+        stream.write_all(b"ping\n").unwrap();
+        let mut reply = String::new();
+        let _ = stream.read_to_string(&mut reply);
+        assert_eq!(reply, "pong\n");
+    }
+
+    #[test]
+    fn targets_split_into_host_and_port() {
+        assert_eq!(split_target("lab:8443").unwrap(), ("lab".to_string(), 8443));
+        assert_eq!(split_target("lab").unwrap(), ("lab".to_string(), 443));
+        assert_eq!(split_target("::1").unwrap(), ("::1".to_string(), 443));
+        assert_eq!(split_target("[::1]:993").unwrap(), ("::1".to_string(), 993));
+    }
+}
@@ -68,193 +68,21 @@ fn general_network_troubleshooting() {
 }
 
 fn complete_network_diagnosis() {
-    println!("🔍 Complete Network Diagnosis");
-    println!("==============================\n");
-
-    // 1. Network Interfaces
-    println!("1️⃣ Network Interfaces Status:");
-    Command::new("ip").args(&["addr", "show"]).status().ok();
-
-    println!("\n📊 Interface Statistics:");
-    Command::new("cat").args(&["/proc/net/dev"]).status().ok();
-
-    // 2. Routing Table
-    println!("\n2️⃣ Routing Table:");
-    Command::new("ip").args(&["route", "show"]).status().ok();
-
-    println!("\n📋 Route Cache:");
-    Command::new("ip")
-        .args(&["route", "show", "cache"])
-        .status()
-        .ok();
-
-    // 3. DNS Configuration
-    println!("\n3️⃣ DNS Configuration:");
-    Command::new("cat")
-        .args(&["/etc/resolv.conf"])
-        .status()
-        .ok();
-
-    println!("\n🔍 systemd-resolved status:");
-    Command::new("systemctl")
-        .args(&["status", "systemd-resolved", "--no-pager"])
-        .status()
-        .ok();
-
-    // 4. ARP Table
-    println!("\n4️⃣ ARP Table:");
-    Command::new("ip").args(&["neigh", "show"]).status().ok();
-
-    // 5. Active Connections
-    println!("\n5️⃣ Active Network Connections:");
-    Command::new("ss").args(&["-tuln"]).status().ok();
-
-    // 6. Network Services
-    println!("\n6️⃣ Network Services:");
-    Command::new("systemctl")
-        .args(&[
-            "list-units",
-            "--type=service",
-            "--state=active",
-            "|",
-            "grep",
-            "network",
-        ])
-        .status()
-        .ok();
-
-    // 7. Firewall Status
-    println!("\n7️⃣ Firewall Status:");
-    Command::new("sudo")
-        .args(&["iptables", "-L", "-n"])
-        .status()
-        .ok();
-
-    // 8. Network Performance
-    println!("\n8️⃣ Network Interface Performance:");
-    if let Ok(entries) = std::fs::read_dir("/sys/class/net") {
-        for entry in entries.flatten() {
-            if let Some(iface) = entry.file_name().to_str() {
-                println!("=== {} ===", iface);
-                let output = Command::new("ethtool").arg(iface).output();
-                match output {
-                    Ok(out) if out.status.success() => {
-                        let text = String::from_utf8_lossy(&out.stdout);
-                        for line in text.lines() {
-                            if line.contains("Speed")
-                                || line.contains("Duplex")
-                                || line.contains("Link")
-                            {
-                                println!("{}", line);
-                            }
-                        }
-                    }
-                    _ => println!("ethtool not available"),
-                }
-            }
-        }
-    }
-
-    println!("\n✅ Diagnosis complete. Check above for any issues.");
+    run_diagnosis(false);
 }
 
+/// Every layer, even above a broken one, for a full picture of the uplink.
 fn internet_connectivity_test() {
-    println!("🌐 Internet Connectivity Test");
-    println!("==============================\n");
-
-    let test_hosts = vec![
-        ("8.8.8.8", "Google DNS"),
-        ("1.1.1.1", "Cloudflare DNS"),
-        ("google.com", "Google"),
-        ("github.com", "GitHub"),
-        ("archlinux.org", "Arch Linux"),
-    ];
-
-    for (host, name) in &test_hosts {
-        println!("🔍 Testing {}...", name);
-
-        // Ping test
-        let ping = Command::new("ping")
-            .args(&["-c", "3", "-W", "2", host])
-            .output();
-
-        match ping {
-            Ok(out) if out.status.success() => {
-                let output_str = String::from_utf8_lossy(&out.stdout);
-                for line in output_str.lines() {
-                    if line.contains("packet loss") {
-                        println!("  📊 {}", line.trim());
-                    }
-                    if line.contains("min/avg/max") {
-                        println!("  ⏱️ {}", line.trim());
-                    }
-                }
-            }
-            _ => println!("  ❌ Ping failed to {}", host),
-        }
-
-        // DNS resolution test (for domain names)
-        if !host.chars().all(|c| c.is_numeric() || c == '.') {
-            let nslookup = Command::new("nslookup").arg(host).output();
-
-            match nslookup {
-                Ok(out) if out.status.success() => println!("  ✅ DNS resolution successful"),
-                _ => println!("  ❌ DNS resolution failed"),
-            }
-        }
-
-        println!();
-    }
-
-    // Test different protocols
-    println!("🔍 Protocol Tests:");
-
-    println!("\n📡 HTTP Test:");
-    let http = Command::new("curl")
-        .args(&["-I", "-m", "10", "http://httpbin.org/status/200"])
-        .output();
-
-    match http {
-        Ok(out) if out.status.success() => println!("  ✅ HTTP working"),
-        _ => println!("  ❌ HTTP failed"),
-    }
-
-    println!("\n🔐 HTTPS Test:");
-    let https = Command::new("curl")
-        .args(&["-I", "-m", "10", "https://httpbin.org/status/200"])
-        .output();
-
-    match https {
-        Ok(out) if out.status.success() => println!("  ✅ HTTPS working"),
-        _ => println!("  ❌ HTTPS failed"),
-    }
-
-    // Test specific ports
-    println!("\n🔌 Port Connectivity Tests:");
-    let port_tests = vec![
-        ("google.com", "80", "HTTP"),
-        ("google.com", "443", "HTTPS"),
-        ("github.com", "22", "SSH"),
-        ("8.8.8.8", "53", "DNS"),
-    ];
-
-    for (host, port, service) in port_tests {
-        let nc = Command::new("nc")
-            .args(&["-zv", "-w", "2", host, port])
-            .output();
+    run_diagnosis(true);
+}
 
-        match nc {
-            Ok(out) => {
-                let stderr = String::from_utf8_lossy(&out.stderr);
-                if stderr.contains("succeeded") || out.status.success() {
-                    println!("  ✅ {} ({}:{}) - OPEN", service, host, port);
-                } else {
-                    println!("  ❌ {} ({}:{}) - BLOCKED", service, host, port);
-                }
-            }
-            _ => println!("  ⚠️ {} ({}:{}) - TEST FAILED", service, host, port),
-        }
-    }
+fn run_diagnosis(keep_going: bool) {
+    let options = super::diagnose::Options {
+        keep_going,
+        ..super::diagnose::Options::from_env()
+    };
+    let runner = crate::command::runner();
+    super::diagnose::print(&super::diagnose::diagnose(runner.as_ref(), &options));
 }
 
 fn network_interface_analysis() {
@@ -0,0 +1,294 @@
+//! Minimal XML reader for the tool output and config files ghostctl imports
+//! (firewalld zones and services, nmap reports).
+//!
+//! Elements, attributes, text and the predefined/numeric entities are
+//! supported; the prolog, comments, processing instructions, doctypes and
+//! CDATA markers are skipped. Namespaces are kept as part of the name.
+
+use anyhow::{Result, bail};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|n| match n {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |e| e.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    /// Concatenated text content, trimmed.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for node in &self.children {
+            match node {
+                Node::Text(t) => out.push_str(t),
+                Node::Element(e) => out.push_str(&e.text()),
+            }
+        }
+        out.trim().to_string()
+    }
+}
+
+/// Parse a document and return its root element.
+pub fn parse(input: &str) -> Result<Element> {
+    let mut parser = Parser { input, pos: 0 };
+    parser.skip_misc()?;
+    if !parser.rest().starts_with('<') {
+        bail!("no root element");
+    }
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if !parser.rest().trim().is_empty() {
+        bail!("content after the root element at byte {}", parser.pos);
+    }
+    Ok(root)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            }
+            None => bail!("unterminated markup, expected '{end}'"),
+        }
+    }
+
+    /// Skip whitespace, `<?...?>`, `<!--...-->` and `<!DOCTYPE ...>`.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_ws();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") && !rest.starts_with("<![CDATA[") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            bail!("expected a name at byte {}", self.pos);
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn element(&mut self) -> Result<Element> {
+        self.pos += 1; // '<'
+        let name = self.name()?;
+        let mut attrs = Vec::new();
+        loop {
+            self.skip_ws();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children: Vec::new(),
+                });
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            if rest.is_empty() {
+                bail!("unterminated <{name}>");
+            }
+            let key = self.name()?;
+            self.skip_ws();
+            if !self.rest().starts_with('=') {
+                bail!("attribute '{key}' of <{name}> has no value");
+            }
+            self.pos += 1;
+            self.skip_ws();
+            let Some(quote) = self
+                .rest()
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '"' | '\''))
+            else {
+                bail!("attribute '{key}' of <{name}> is not quoted");
+            };
+            self.pos += 1;
+            let Some(end) = self.rest().find(quote) else {
+                bail!("unterminated attribute '{key}' of <{name}>");
+            };
+            let value = unescape(&self.rest()[..end]);
+            self.pos += end + 1;
+            attrs.push((key, value));
+        }
+
+        let mut children = Vec::new();
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                bail!("unterminated <{name}>");
+            }
+            if let Some(after) = rest.strip_prefix("<![CDATA[") {
+                let Some(end) = after.find("]]>") else {
+                    bail!("unterminated CDATA in <{name}>");
+                };
+                children.push(Node::Text(after[..end].to_string()));
+                self.pos += "<![CDATA[".len() + end + 3;
+            } else if rest.starts_with("</") {
+                self.pos += 2;
+                let close = self.name()?;
+                if close != name {
+                    bail!("<{name}> closed by </{close}>");
+                }
+                self.skip_ws();
+                if !self.rest().starts_with('>') {
+                    bail!("malformed </{close}>");
+                }
+                self.pos += 1;
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children,
+                });
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                children.push(Node::Element(self.element()?));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = unescape(&rest[..end]);
+                if !text.trim().is_empty() {
+                    children.push(Node::Text(text));
+                }
+                self.pos += end;
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_elements_attributes_and_text() {
+        let doc = parse(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<!-- comment -->
+<zone target="DROP">
+  <short>Work &amp; play</short>
+  <port protocol='tcp' port="22"/>
+  <rule><source address="10.0.0.0/8" invert="True"/><accept/></rule>
+</zone>"#,
+        )
+        .unwrap();
+        assert_eq!(doc.name, "zone");
+        assert_eq!(doc.attr("target"), Some("DROP"));
+        assert_eq!(doc.child("short").unwrap().text(), "Work & play");
+        assert_eq!(doc.child("port").unwrap().attr("protocol"), Some("tcp"));
+        let rule = doc.child("rule").unwrap();
+        assert_eq!(rule.elements().count(), 2);
+        assert_eq!(rule.child("source").unwrap().attr("invert"), Some("True"));
+    }
+
+    #[test]
+    fn rejects_mismatched_tags() {
+        assert!(parse("<a><b></a></b>").is_err());
+        assert!(parse("<a x=1/>").is_err());
+        assert!(parse("<a>").is_err());
+    }
+
+    #[test]
+    fn decodes_numeric_entities() {
+        assert_eq!(unescape("a&#65;&#x42;&unknown;"), "aAB&unknown;");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::btrfs::transaction;
 use dialoguer::{Confirm, Select, theme::ColorfulTheme};
 use std::fs;
 use std::process::Command;
@@ -226,22 +227,24 @@ pub fn install_proprietary_drivers() {
 
     println!("📦 Installing packages: {}", packages.join(", "));
 
-    // Remove conflicting packages first
-    remove_conflicting_packages();
+    transaction::run("nvidia: install proprietary drivers", || {
+        // Remove conflicting packages first
+        remove_conflicting_packages();
 
-    // Install packages
-    let mut cmd = Command::new("sudo");
-    cmd.args(&["pacman", "-S", "--noconfirm"]);
-    cmd.args(&packages);
+        // Install packages
+        let mut cmd = Command::new("sudo");
+        cmd.args(&["pacman", "-S", "--noconfirm"]);
+        cmd.args(&packages);
 
-    let status = cmd.status();
-    match status {
-        Ok(status) if status.success() => {
-            println!("✅ NVIDIA proprietary drivers installed successfully");
-            post_install_setup();
+        let status = cmd.status();
+        match status {
+            Ok(status) if status.success() => {
+                println!("✅ NVIDIA proprietary drivers installed successfully");
+                post_install_setup();
+            }
+            _ => println!("❌ Failed to install NVIDIA proprietary drivers"),
         }
-        _ => println!("❌ Failed to install NVIDIA proprietary drivers"),
-    }
+    });
 }
 
 #[allow(dead_code)]
@@ -271,21 +274,23 @@ pub fn install_open_drivers() {
 
     println!("📦 Installing packages: {}", packages.join(", "));
 
-    // Remove conflicting packages
-    remove_conflicting_packages();
+    transaction::run("nvidia: install open drivers", || {
+        // Remove conflicting packages
+        remove_conflicting_packages();
 
-    let mut cmd = Command::new("sudo");
-    cmd.args(&["pacman", "-S", "--noconfirm"]);
-    cmd.args(&packages);
+        let mut cmd = Command::new("sudo");
+        cmd.args(&["pacman", "-S", "--noconfirm"]);
+        cmd.args(&packages);
 
-    let status = cmd.status();
-    match status {
-        Ok(status) if status.success() => {
-            println!("✅ NVIDIA open-source drivers installed successfully");
-            post_install_setup();
+        let status = cmd.status();
+        match status {
+            Ok(status) if status.success() => {
+                println!("✅ NVIDIA open-source drivers installed successfully");
+                post_install_setup();
+            }
+            _ => println!("❌ Failed to install NVIDIA open-source drivers"),
         }
-        _ => println!("❌ Failed to install NVIDIA open-source drivers"),
-    }
+    });
 }
 
 #[allow(dead_code)]
@@ -309,21 +314,23 @@ pub fn install_open_beta_drivers() {
     println!("📦 Installing AUR packages: {}", packages.join(", "));
     println!("🔧 Using AUR helper: {}", helper);
 
-    // Remove conflicting packages
-    remove_conflicting_packages();
+    transaction::run("nvidia: install open beta drivers", || {
+        // Remove conflicting packages
+        remove_conflicting_packages();
 
-    let mut cmd = Command::new(&helper);
-    cmd.args(&["-S", "--noconfirm"]);
-    cmd.args(&packages);
+        let mut cmd = Command::new(&helper);
+        cmd.args(&["-S", "--noconfirm"]);
+        cmd.args(&packages);
 
-    let status = cmd.status();
-    match status {
-        Ok(status) if status.success() => {
-            println!("✅ NVIDIA open-source beta drivers installed successfully");
-            post_install_setup();
+        let status = cmd.status();
+        match status {
+            Ok(status) if status.success() => {
+                println!("✅ NVIDIA open-source beta drivers installed successfully");
+                post_install_setup();
+            }
+            _ => println!("❌ Failed to install NVIDIA open-source beta drivers"),
         }
-        _ => println!("❌ Failed to install NVIDIA open-source beta drivers"),
-    }
+    });
 }
 
 fn detect_kernel() -> String {
@@ -483,6 +490,11 @@ pub fn remove_all_drivers() {
         return;
     }
 
+    transaction::run("nvidia: remove all drivers", remove_driver_packages);
+}
+
+/// Remove every NVIDIA driver package and its modprobe configuration.
+fn remove_driver_packages() {
     // List of possible NVIDIA packages
     let nvidia_packages = [
         "nvidia",
@@ -551,16 +563,18 @@ pub fn fix_driver_issues() {
 fn rebuild_dkms() {
     println!("🔨 Rebuilding DKMS modules...");
 
-    // Remove existing modules
-    let _ = Command::new("sudo")
-        .args(&["dkms", "remove", "-m", "nvidia", "--all"])
-        .status();
+    transaction::run("nvidia: rebuild dkms", || {
+        // Remove existing modules
+        let _ = Command::new("sudo")
+            .args(&["dkms", "remove", "-m", "nvidia", "--all"])
+            .status();
 
-    // Add and build
-    let _ = Command::new("sudo").args(&["dkms", "autoinstall"]).status();
+        // Add and build
+        let _ = Command::new("sudo").args(&["dkms", "autoinstall"]).status();
 
-    // Rebuild initramfs
-    let _ = Command::new("sudo").args(&["mkinitcpio", "-P"]).status();
+        // Rebuild initramfs
+        let _ = Command::new("sudo").args(&["mkinitcpio", "-P"]).status();
+    });
 
     println!("✅ DKMS rebuild complete");
 }
@@ -587,24 +601,26 @@ fn fix_kernel_modules() {
 fn reset_nvidia_config() {
     println!("♻️  Resetting NVIDIA configuration...");
 
-    // Backup and remove configs
-    let config_files = [
-        "/etc/X11/xorg.conf",
-        "/etc/X11/xorg.conf.d/20-nvidia.conf",
-        "/etc/modprobe.d/nvidia.conf",
-    ];
-
-    for config in &config_files {
-        if std::path::Path::new(config).exists() {
-            let backup = format!("{}.backup", config);
-            let _ = Command::new("sudo").args(&["cp", config, &backup]).status();
-            let _ = Command::new("sudo").args(&["rm", config]).status();
-            println!("  Backed up and removed: {}", config);
+    transaction::run("nvidia: reset configuration", || {
+        // Backup and remove configs
+        let config_files = [
+            "/etc/X11/xorg.conf",
+            "/etc/X11/xorg.conf.d/20-nvidia.conf",
+            "/etc/modprobe.d/nvidia.conf",
+        ];
+
+        for config in &config_files {
+            if std::path::Path::new(config).exists() {
+                let backup = format!("{}.backup", config);
+                let _ = Command::new("sudo").args(&["cp", config, &backup]).status();
+                let _ = Command::new("sudo").args(&["rm", config]).status();
+                println!("  Backed up and removed: {}", config);
+            }
         }
-    }
 
-    // Regenerate basic config
-    post_install_setup();
+        // Regenerate basic config
+        post_install_setup();
+    });
 
     println!("✅ NVIDIA configuration reset");
 }
@@ -616,9 +632,11 @@ fn fix_wayland_issues() {
     let nvidia_conf =
         "options nvidia-drm modeset=1\noptions nvidia NVreg_PreserveVideoMemoryAllocations=1\n";
     let _ = fs::write("/tmp/nvidia.conf", nvidia_conf);
-    let _ = Command::new("sudo")
-        .args(&["mv", "/tmp/nvidia.conf", "/etc/modprobe.d/"])
-        .status();
+    let _ = transaction::run("nvidia: fix wayland modeset", || {
+        Command::new("sudo")
+            .args(&["mv", "/tmp/nvidia.conf", "/etc/modprobe.d/"])
+            .status()
+    });
 
     // Set environment variables
     println!("💡 Add these environment variables:");
@@ -633,7 +651,9 @@ fn fix_x11_config() {
     println!("🖥️  Fixing X11 configuration...");
 
     // Generate basic xorg.conf
-    let _ = Command::new("sudo").args(&["nvidia-xconfig"]).status();
+    let _ = transaction::run("nvidia: regenerate xorg.conf", || {
+        Command::new("sudo").args(&["nvidia-xconfig"]).status()
+    });
 
     println!("✅ X11 configuration regenerated");
 }
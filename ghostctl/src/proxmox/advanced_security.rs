@@ -1,6 +1,6 @@
 use dialoguer::{Confirm, Input, MultiSelect, Select, theme::ColorfulTheme};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::process::Command;
 
@@ -40,17 +40,71 @@ pub enum SecurityLevel {
     Enterprise, // Maximum security with compliance
 }
 
+/// A policy for one scope. The firewall parts (options through
+/// `firewall_rules`, plus `rules` and `exceptions`) are what
+/// `proxmox::firewall` renders into that scope's .fw file.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityPolicy {
     pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub rules: Vec<SecurityRule>,
     pub scope: PolicyScope,
+    #[serde(default)]
     pub enforcement_level: EnforcementLevel,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub exceptions: Vec<PolicyException>,
+    /// `[OPTIONS]`, such as `enable`, `policy_in` or `log_level_in`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<Alias>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ipsets: Vec<IpSet>,
+    /// Security groups, cluster scope only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<FirewallTemplate>,
+    /// `[RULES]`, in order, after the exceptions and `rules`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub firewall_rules: Vec<FirewallRule>,
+}
+
+/// A named address (`[ALIASES]`), usable in rules and IPSets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alias {
+    pub name: String,
+    pub cidr: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub comment: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// `[IPSET name]`, referenced from rules as `+name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpSet {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub comment: String,
+    #[serde(default)]
+    pub entries: Vec<IpSetEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpSetEntry {
+    /// Address, CIDR or alias name.
+    pub cidr: String,
+    /// Written `!cidr`: excluded from the set.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub nomatch: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub comment: String,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PolicyScope {
     Cluster,
     Datacenter,
@@ -59,11 +113,12 @@ pub enum PolicyScope {
     Container(u32),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub enum EnforcementLevel {
-    Advisory,   // Log only
-    Warn,       // Warn but allow
-    Block,      // Block action
+    Advisory, // Log only
+    Warn,     // Warn but allow
+    #[default]
+    Block, // Block action
     Quarantine, // Isolate resource
 }
 
@@ -86,13 +141,19 @@ pub enum SecurityRuleType {
     Compliance,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RuleCondition {
+    #[serde(default)]
     pub source: Option<String>,
+    #[serde(default)]
     pub destination: Option<String>,
+    #[serde(default)]
     pub port_range: Option<String>,
+    #[serde(default)]
     pub protocol: Option<String>,
+    #[serde(default)]
     pub time_window: Option<TimeWindow>,
+    #[serde(default)]
     pub resource_threshold: Option<ResourceThreshold>,
 }
 
@@ -127,6 +188,8 @@ pub struct PolicyException {
     pub name: String,
     pub condition: RuleCondition,
     pub justification: String,
+    /// `YYYY-MM-DD`; the exception is dropped from rendered files after it.
+    #[serde(default)]
     pub expiry_date: Option<String>,
     pub approved_by: String,
 }
@@ -134,14 +197,24 @@ pub struct PolicyException {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FirewallTemplate {
     pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
+    #[serde(default = "security_group_target")]
     pub target_type: TargetType,
+    #[serde(default)]
     pub rules: Vec<FirewallRule>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub variables: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub prerequisites: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Target of templates read from a `[group name]` section.
+pub fn security_group_target() -> TargetType {
+    TargetType::Custom("security-group".to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TargetType {
     WebServer,
     DatabaseServer,
@@ -154,68 +227,110 @@ pub enum TargetType {
     Custom(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One firewall rule. `name` is the rule's comment in a .fw file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FirewallRule {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub name: String,
     pub action: RuleAction,
+    #[serde(default)]
     pub direction: Direction,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub protocol: Protocol,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub source: AddressSpec,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub destination: AddressSpec,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub ports: PortSpec,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub logging: bool,
+    #[serde(default)]
     pub rate_limit: Option<RateLimit>,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub connection_tracking: bool,
+    /// Disabled rules stay in the file, prefixed with `|`.
+    #[serde(default = "enabled", skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
+    /// Interface (`-i net0`) the rule applies to.
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub icmp_type: Option<String>,
+    /// Syslog level (`-log nolog`, `-log info`); `logging` alone means info.
+    #[serde(default)]
+    pub log_level: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn enabled() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RuleAction {
     Accept,
     Reject,
     Drop,
     Queue(u16),
     Redirect(String),
+    /// Jump to a security group (`GROUP name`); direction does not apply.
+    Group(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
+    #[default]
     Input,
     Output,
     Forward,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub enum Protocol {
     TCP,
     UDP,
     ICMP,
     ESP,
     AH,
+    #[default]
     All,
     Custom(u8),
+    /// Any other name from /etc/protocols, such as `icmpv6` or `gre`.
+    Other(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct AddressSpec {
     pub address: String, // IP, CIDR, or alias
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub exclude: Vec<String>,
+    #[serde(default)]
     pub geo_restriction: Option<GeoRestriction>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GeoRestriction {
     pub allowed_countries: Vec<String>,
     pub blocked_countries: Vec<String>,
     pub vpn_detection: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PortSpec {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub ports: String, // "80", "80,443", "1-1000", etc.
+    /// PVE macros such as `SSH` or `HTTPS`; a rule takes at most one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub named_services: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub source_ports: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RateLimit {
     pub rate: String, // "10/minute", "100/second", etc.
     pub burst: Option<u32>,
@@ -0,0 +1,846 @@
+//! Blocking client for the Proxmox `/api2/json` REST API.
+//!
+//! Authenticates with an API token and can pin the server certificate by
+//! SHA-256 fingerprint, the way the web UI and `pvecm` trust self-signed
+//! node certificates. A cluster lists several endpoints; requests go to the
+//! first that answers, so any reachable node will do. Long-running actions
+//! return a task UPID which [`ApiClient::wait_task`] follows to completion.
+
+use super::config::PveCluster;
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::Method;
+use reqwest::blocking::Client;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Lowercase hex without separators, if `fingerprint` is a SHA-256 digest
+/// in any of the usual spellings (`AB:CD:..`, `abcd..`).
+pub fn normalize_fingerprint(fingerprint: &str) -> Option<String> {
+    let hex: String = fingerprint
+        .chars()
+        .filter(|c| !matches!(c, ':' | ' ' | '-'))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(hex)
+}
+
+/// SHA-256 fingerprint of a DER certificate as Proxmox prints it.
+pub fn fingerprint(der: &[u8]) -> String {
+    let digest = Sha256::digest(der);
+    let mut out = String::new();
+    for (i, byte) in digest.iter().enumerate() {
+        if i > 0 {
+            out.push(':');
+        }
+        let _ = write!(out, "{byte:02X}");
+    }
+    out
+}
+
+/// Accepts exactly the pinned certificates, whatever their issuer or name.
+#[derive(Debug)]
+struct PinnedCertificate {
+    pins: Vec<String>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let seen = fingerprint(end_entity);
+        if normalize_fingerprint(&seen).is_some_and(|hex| self.pins.contains(&hex)) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "certificate fingerprint {seen} does not match the pinned fingerprint"
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// How to trust the server certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trust {
+    /// System CA store.
+    CaStore,
+    /// Only these SHA-256 fingerprints (normalized).
+    Pinned(Vec<String>),
+    /// Anything (`verify_tls = false`).
+    Insecure,
+}
+
+impl Trust {
+    pub fn for_cluster(cluster: &PveCluster) -> Result<Self> {
+        if !cluster.fingerprint.is_empty() {
+            let pins = cluster
+                .fingerprint
+                .iter()
+                .map(|f| {
+                    normalize_fingerprint(f)
+                        .with_context(|| format!("'{f}' is not a SHA-256 fingerprint"))
+                })
+                .collect::<Result<_>>()?;
+            return Ok(Trust::Pinned(pins));
+        }
+        Ok(if cluster.verify_tls {
+            Trust::CaStore
+        } else {
+            Trust::Insecure
+        })
+    }
+}
+
+/// Token-authenticated client for one PVE or PBS cluster.
+pub struct ApiClient {
+    client: Client,
+    endpoints: Vec<String>,
+    authorization: String,
+    /// Index of the endpoint that answered last.
+    current: Cell<usize>,
+}
+
+impl ApiClient {
+    /// Client for a `[[pve.clusters]]` entry.
+    pub fn for_cluster(cluster: &PveCluster) -> Result<Self> {
+        let secret = cluster
+            .token_secret
+            .resolve(&format!("PVE cluster '{}' token secret", cluster.name))?;
+        Self::new(
+            &cluster.endpoints,
+            8006,
+            &format!("PVEAPIToken={}={secret}", cluster.token_id),
+            &Trust::for_cluster(cluster)?,
+            cluster.timeout_secs,
+        )
+    }
+
+    /// Client for a `[[pve.backup_servers]]` entry.
+    pub fn for_backup_server(server: &PveCluster) -> Result<Self> {
+        let secret = server
+            .token_secret
+            .resolve(&format!("backup server '{}' token secret", server.name))?;
+        Self::new(
+            &server.endpoints,
+            8007,
+            &format!("PBSAPIToken={}:{secret}", server.token_id),
+            &Trust::for_cluster(server)?,
+            server.timeout_secs,
+        )
+    }
+
+    /// `authorization` is the full header value, e.g.
+    /// `PVEAPIToken=root@pam!cli=SECRET` or `PBSAPIToken=root@pam!cli:SECRET`.
+    pub fn new(
+        endpoints: &[String],
+        default_port: u16,
+        authorization: &str,
+        trust: &Trust,
+        timeout_secs: u64,
+    ) -> Result<Self> {
+        if endpoints.is_empty() {
+            bail!("no API endpoints configured");
+        }
+        let endpoints = endpoints
+            .iter()
+            .map(|e| with_default_port(e, default_port))
+            .collect::<Result<Vec<_>>>()?;
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .connect_timeout(Duration::from_secs(timeout_secs.min(10)))
+            .user_agent("ghostctl");
+        builder = match trust {
+            Trust::CaStore => builder,
+            Trust::Insecure => builder.tls_danger_accept_invalid_certs(true),
+            Trust::Pinned(pins) => {
+                let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+                let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+                    .with_safe_default_protocol_versions()?
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(PinnedCertificate {
+                        pins: pins.clone(),
+                        provider,
+                    }))
+                    .with_no_client_auth();
+                builder.tls_backend_preconfigured(config)
+            }
+        };
+        let client = builder.build().context("failed to build API client")?;
+        Ok(Self {
+            client,
+            endpoints,
+            authorization: authorization.to_string(),
+            current: Cell::new(0),
+        })
+    }
+
+    /// The endpoint that answered last.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoints[self.current.get()]
+    }
+
+    /// Send a request, moving to the next endpoint when one cannot be
+    /// reached. Returns the response's `data`.
+    ///
+    /// Only a GET that timed out is retried elsewhere: a POST/PUT/DELETE may
+    /// already have run on a node that answered too slowly.
+    fn call(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value> {
+        let count = self.endpoints.len();
+        let start = self.current.get();
+        let mut unreachable = Vec::new();
+        for offset in 0..count {
+            let index = (start + offset) % count;
+            let url = format!("{}/api2/json{path}", self.endpoints[index]);
+            let mut request = self
+                .client
+                .request(method.clone(), &url)
+                .header(reqwest::header::AUTHORIZATION, &self.authorization);
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            match request.send() {
+                Ok(resp) => {
+                    self.current.set(index);
+                    return response_data(resp, path);
+                }
+                Err(e) if e.is_connect() || (e.is_timeout() && method == Method::GET) => {
+                    unreachable.push(format!("{}: {}", self.endpoints[index], error_chain(&e)));
+                }
+                Err(e) if e.is_timeout() => {
+                    return Err(anyhow!(error_chain(&e))).context(format!(
+                        "{method} {url} timed out; it may still have run, check before retrying"
+                    ));
+                }
+                Err(e) => return Err(anyhow!(error_chain(&e))).context(format!("{method} {url}")),
+            }
+        }
+        bail!("no endpoint answered:\n  {}", unreachable.join("\n  "))
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let data = self.call(Method::GET, path, None)?;
+        serde_json::from_value(data).with_context(|| format!("unexpected response from {path}"))
+    }
+
+    pub fn post<T: DeserializeOwned>(&self, path: &str, body: &Value) -> Result<T> {
+        let data = self.call(Method::POST, path, Some(body))?;
+        serde_json::from_value(data).with_context(|| format!("unexpected response from {path}"))
+    }
+
+    pub fn put<T: DeserializeOwned>(&self, path: &str, body: &Value) -> Result<T> {
+        let data = self.call(Method::PUT, path, Some(body))?;
+        serde_json::from_value(data).with_context(|| format!("unexpected response from {path}"))
+    }
+
+    pub fn delete<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let data = self.call(Method::DELETE, path, None)?;
+        serde_json::from_value(data).with_context(|| format!("unexpected response from {path}"))
+    }
+
+    pub fn version(&self) -> Result<Version> {
+        self.get("/version")
+    }
+
+    pub fn nodes(&self) -> Result<Vec<Node>> {
+        let mut nodes: Vec<Node> = self.get("/nodes")?;
+        nodes.sort_by(|a, b| a.node.cmp(&b.node));
+        Ok(nodes)
+    }
+
+    /// Every VM and container in the cluster, by VMID.
+    pub fn guests(&self) -> Result<Vec<Guest>> {
+        let mut guests: Vec<Guest> = self.get("/cluster/resources?type=vm")?;
+        guests.sort_by_key(|g| g.vmid);
+        Ok(guests)
+    }
+
+    pub fn guest(&self, vmid: u32) -> Result<Guest> {
+        self.guests()?
+            .into_iter()
+            .find(|g| g.vmid == vmid)
+            .with_context(|| format!("no guest {vmid} in the cluster"))
+    }
+
+    /// Start, stop, shutdown or reboot a guest; returns the task.
+    pub fn guest_action(&self, guest: &Guest, action: &str) -> Result<Upid> {
+        let path = format!(
+            "/nodes/{}/{}/{}/status/{action}",
+            guest.node,
+            guest.kind.path(),
+            guest.vmid
+        );
+        let upid: String = self.post(&path, &serde_json::json!({}))?;
+        upid.parse()
+    }
+
+    pub fn task_status(&self, upid: &Upid) -> Result<TaskStatus> {
+        self.get(&format!(
+            "/nodes/{}/tasks/{}/status",
+            upid.node,
+            encode_segment(&upid.raw)
+        ))
+    }
+
+    pub fn task_log(&self, upid: &Upid, start: usize) -> Result<Vec<LogLine>> {
+        self.get(&format!(
+            "/nodes/{}/tasks/{}/log?start={start}&limit=500",
+            upid.node,
+            encode_segment(&upid.raw)
+        ))
+    }
+
+    /// Poll a task until it stops, passing new log lines to `on_line`.
+    /// Fails when the task ends with anything but `OK` (warnings count as
+    /// success) or outlives `timeout`.
+    pub fn wait_task(
+        &self,
+        upid: &Upid,
+        poll: Duration,
+        timeout: Duration,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<TaskStatus> {
+        let started = Instant::now();
+        let mut seen = 0;
+        loop {
+            let status = self.task_status(upid)?;
+            for line in self.task_log(upid, seen).unwrap_or_default() {
+                if line.n > seen {
+                    seen = line.n;
+                    on_line(&line.t);
+                }
+            }
+            if status.status == "stopped" {
+                if status.succeeded() {
+                    return Ok(status);
+                }
+                bail!(
+                    "task {} on {} failed: {}",
+                    upid.task_type,
+                    upid.node,
+                    status.exitstatus.as_deref().unwrap_or("unknown")
+                );
+            }
+            if started.elapsed() > timeout {
+                bail!(
+                    "task {} still running after {}s; follow it in the web UI ({})",
+                    upid.task_type,
+                    timeout.as_secs(),
+                    upid.raw
+                );
+            }
+            std::thread::sleep(poll);
+        }
+    }
+}
+
+fn with_default_port(endpoint: &str, port: u16) -> Result<String> {
+    let mut url = reqwest::Url::parse(endpoint)
+        .with_context(|| format!("invalid API endpoint '{endpoint}'"))?;
+    if url.port().is_none() {
+        let _ = url.set_port(Some(port));
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// The error and its causes on one line; the TLS reason is buried in them.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        if !message.contains(&text) {
+            message.push_str(": ");
+            message.push_str(&text);
+        }
+        source = cause.source();
+    }
+    message
+}
+
+fn response_data(resp: reqwest::blocking::Response, path: &str) -> Result<Value> {
+    let status = resp.status().as_u16();
+    let body = resp.text().unwrap_or_default();
+    let parsed: Option<Value> = serde_json::from_str(&body).ok();
+    match status {
+        200..=299 => {}
+        401 => bail!("HTTP 401 for {path}: API token rejected; check token_id and token_secret"),
+        _ => {
+            // PVE reports parameter errors as {"errors": {"param": "reason"}}
+            // and puts other reasons in the body's "message".
+            let detail = parsed
+                .as_ref()
+                .and_then(|v| {
+                    v["errors"]
+                        .as_object()
+                        .map(|errors| {
+                            errors
+                                .iter()
+                                .map(|(k, v)| {
+                                    format!("{k}: {}", v.as_str().unwrap_or_default().trim())
+                                })
+                                .collect::<Vec<_>>()
+                                .join("; ")
+                        })
+                        .or_else(|| v["message"].as_str().map(|m| m.trim().to_string()))
+                })
+                .filter(|d| !d.is_empty())
+                .unwrap_or_else(|| body.trim().to_string());
+            if status == 403 {
+                bail!(
+                    "HTTP 403 for {path}: permission denied ({detail}); grant the token the needed privileges"
+                );
+            }
+            bail!("HTTP {status} for {path}: {detail}");
+        }
+    }
+    Ok(parsed.map(|mut v| v["data"].take()).unwrap_or(Value::Null))
+}
+
+pub(crate) fn encode_segment(segment: &str) -> String {
+    let mut out = String::new();
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "%{byte:02X}");
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Version {
+    pub version: String,
+    #[serde(default)]
+    pub release: String,
+    #[serde(default)]
+    pub repoid: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Node {
+    pub node: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub cpu: f64,
+    #[serde(default)]
+    pub maxcpu: u32,
+    #[serde(default)]
+    pub mem: u64,
+    #[serde(default)]
+    pub maxmem: u64,
+    #[serde(default)]
+    pub uptime: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuestKind {
+    Qemu,
+    Lxc,
+}
+
+impl GuestKind {
+    pub fn path(self) -> &'static str {
+        match self {
+            GuestKind::Qemu => "qemu",
+            GuestKind::Lxc => "lxc",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GuestKind::Qemu => "VM",
+            GuestKind::Lxc => "container",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Guest {
+    pub vmid: u32,
+    #[serde(default)]
+    pub name: String,
+    pub node: String,
+    #[serde(rename = "type")]
+    pub kind: GuestKind,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub cpu: f64,
+    #[serde(default)]
+    pub maxcpu: f64,
+    #[serde(default)]
+    pub mem: u64,
+    #[serde(default)]
+    pub maxmem: u64,
+    #[serde(default)]
+    pub uptime: u64,
+    #[serde(default)]
+    pub template: u8,
+    #[serde(default)]
+    pub tags: Option<String>,
+}
+
+impl Guest {
+    /// A guest known only by id and location, for calls that skip the
+    /// `/cluster/resources` lookup.
+    pub fn new(vmid: u32, node: &str, kind: GuestKind) -> Self {
+        Guest {
+            vmid,
+            name: String::new(),
+            node: node.to_string(),
+            kind,
+            status: String::new(),
+            cpu: 0.0,
+            maxcpu: 0.0,
+            mem: 0,
+            maxmem: 0,
+            uptime: 0,
+            template: 0,
+            tags: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TaskStatus {
+    pub status: String,
+    #[serde(default)]
+    pub exitstatus: Option<String>,
+}
+
+impl TaskStatus {
+    /// A stopped task ended with `OK`, or with `WARNINGS: <n>` (finished,
+    /// but something was logged along the way).
+    pub fn succeeded(&self) -> bool {
+        self.exitstatus
+            .as_deref()
+            .is_some_and(|exit| exit == "OK" || exit.starts_with("WARNINGS"))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogLine {
+    pub n: usize,
+    pub t: String,
+}
+
+/// A task id, `UPID:node:pid:pstart:starttime:type:id:user:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upid {
+    pub node: String,
+    pub task_type: String,
+    pub id: String,
+    pub user: String,
+    pub raw: String,
+}
+
+impl std::str::FromStr for Upid {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        let fields: Vec<&str> = raw.split(':').collect();
+        // PBS UPIDs carry an extra task-id field after pstart.
+        let (node, task_type, id, user) = match fields.as_slice() {
+            ["UPID", node, _pid, _pstart, _start, task_type, id, user, ..] if fields.len() == 9 => {
+                (node, task_type, id, user)
+            }
+            [
+                "UPID",
+                node,
+                _pid,
+                _pstart,
+                _task,
+                _start,
+                task_type,
+                id,
+                user,
+                ..,
+            ] => (node, task_type, id, user),
+            _ => bail!("'{raw}' is not a task UPID"),
+        };
+        Ok(Upid {
+            node: node.to_string(),
+            task_type: task_type.to_string(),
+            id: id.to_string(),
+            user: user.to_string(),
+            raw: raw.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Request, cert_der, serve};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const UPID: &str = "UPID:pve2:0000A1B2:0012C3D4:66F0A0B0:qmstart:101:root@pam!ghostctl:";
+    const TOKEN: &str = "PVEAPIToken=root@pam!ghostctl=s3cret";
+
+    fn pinned() -> Trust {
+        Trust::Pinned(vec![
+            normalize_fingerprint(&fingerprint(&cert_der())).unwrap(),
+        ])
+    }
+
+    fn pve() -> (String, Arc<Mutex<Vec<Request>>>) {
+        let polls = AtomicUsize::new(0);
+        serve(move |req: &Request| {
+            if req.authorization != TOKEN {
+                return (401, r#"{"data":null}"#.to_string());
+            }
+            let encoded = encode_segment(UPID);
+            match (req.method.as_str(), req.path.as_str()) {
+                ("GET", "/api2/json/version") => (
+                    200,
+                    r#"{"data":{"version":"9.0.3","release":"9.0","repoid":"abc"}}"#.to_string(),
+                ),
+                ("GET", "/api2/json/cluster/resources?type=vm") => (
+                    200,
+                    r#"{"data":[
+                      {"id":"lxc/200","type":"lxc","vmid":200,"name":"dns","node":"pve1","status":"running","maxmem":536870912,"mem":104857600,"cpu":0.01,"maxcpu":1,"uptime":3600},
+                      {"id":"qemu/101","type":"qemu","vmid":101,"name":"web","node":"pve2","status":"stopped","maxmem":4294967296,"template":0}
+                    ]}"#
+                    .to_string(),
+                ),
+                ("POST", "/api2/json/nodes/pve2/qemu/101/status/start") => {
+                    (200, format!(r#"{{"data":"{UPID}"}}"#))
+                }
+                ("GET", path) if path == format!("/api2/json/nodes/pve2/tasks/{encoded}/status") => {
+                    if polls.fetch_add(1, Ordering::SeqCst) == 0 {
+                        (200, r#"{"data":{"status":"running"}}"#.to_string())
+                    } else {
+                        (200, r#"{"data":{"status":"stopped","exitstatus":"OK"}}"#.to_string())
+                    }
+                }
+                ("GET", path) if path.starts_with(&format!("/api2/json/nodes/pve2/tasks/{encoded}/log")) => (
+                    200,
+                    r#"{"data":[{"n":1,"t":"starting VM 101"},{"n":2,"t":"TASK OK"}]}"#.to_string(),
+                ),
+                ("POST", "/api2/json/nodes/pve1/lxc/200/status/stop") => (
+                    500,
+                    r#"{"data":null,"message":"CT 200 is locked (backup)\n"}"#.to_string(),
+                ),
+                _ => (501, r#"{"data":null}"#.to_string()),
+            }
+        })
+    }
+
+    #[test]
+    fn fingerprints_normalize() {
+        let hex = "ab".repeat(32);
+        let colons = vec!["AB"; 32].join(":");
+        assert_eq!(
+            normalize_fingerprint(&colons).as_deref(),
+            Some(hex.as_str())
+        );
+        assert_eq!(normalize_fingerprint(&hex).as_deref(), Some(hex.as_str()));
+        assert!(normalize_fingerprint("ab:cd").is_none());
+        assert!(normalize_fingerprint(&"zz".repeat(32)).is_none());
+        assert_eq!(fingerprint(&cert_der()).len(), 95);
+    }
+
+    #[test]
+    fn upids_parse() {
+        let upid: Upid = UPID.parse().unwrap();
+        assert_eq!(upid.node, "pve2");
+        assert_eq!(upid.task_type, "qmstart");
+        assert_eq!(upid.id, "101");
+        assert_eq!(upid.user, "root@pam!ghostctl");
+        let pbs: Upid =
+            "UPID:pbs1:000004E5:0000218C:00000001:66F0A0B0:garbage_collection:store1:root@pam:"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            (pbs.node.as_str(), pbs.task_type.as_str(), pbs.id.as_str()),
+            ("pbs1", "garbage_collection", "store1")
+        );
+        assert!("not-a-upid".parse::<Upid>().is_err());
+        assert_eq!(encode_segment("UPID:a@b!c:"), "UPID%3Aa%40b%21c%3A");
+    }
+
+    #[test]
+    fn pinned_client_lists_guests_and_follows_tasks() {
+        let (base, seen) = pve();
+        let client = ApiClient::new(&[base], 8006, TOKEN, &pinned(), 5).unwrap();
+        assert_eq!(client.version().unwrap().version, "9.0.3");
+        let guests = client.guests().unwrap();
+        assert_eq!(
+            guests.iter().map(|g| g.vmid).collect::<Vec<_>>(),
+            vec![101, 200]
+        );
+        assert_eq!(guests[1].kind, GuestKind::Lxc);
+
+        let web = client.guest(101).unwrap();
+        let upid = client.guest_action(&web, "start").unwrap();
+        assert_eq!(upid.node, "pve2");
+        let mut lines = Vec::new();
+        let status = client
+            .wait_task(
+                &upid,
+                Duration::from_millis(10),
+                Duration::from_secs(5),
+                |l| lines.push(l.to_string()),
+            )
+            .unwrap();
+        assert_eq!(status.exitstatus.as_deref(), Some("OK"));
+        assert_eq!(lines, vec!["starting VM 101", "TASK OK"]);
+        assert!(
+            seen.lock()
+                .unwrap()
+                .iter()
+                .all(|r| r.authorization == TOKEN)
+        );
+
+        let dns = client.guest(200).unwrap();
+        let err = client.guest_action(&dns, "stop").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "HTTP 500 for /nodes/pve1/lxc/200/status/stop: CT 200 is locked (backup)"
+        );
+        assert!(client.guest(999).is_err());
+    }
+
+    #[test]
+    fn wrong_pin_and_token_are_refused() {
+        let (base, _) = pve();
+        let wrong = Trust::Pinned(vec!["00".repeat(32)]);
+        let client = ApiClient::new(std::slice::from_ref(&base), 8006, TOKEN, &wrong, 5).unwrap();
+        let err = client.version().unwrap_err().to_string();
+        assert!(
+            err.contains("does not match the pinned fingerprint"),
+            "{err}"
+        );
+
+        // The lab certificate is self-signed, so the CA store rejects it.
+        let ca =
+            ApiClient::new(std::slice::from_ref(&base), 8006, TOKEN, &Trust::CaStore, 5).unwrap();
+        assert!(ca.version().is_err());
+
+        let bad = ApiClient::new(&[base], 8006, "PVEAPIToken=root@pam!x=no", &pinned(), 5).unwrap();
+        assert!(
+            bad.version()
+                .unwrap_err()
+                .to_string()
+                .contains("API token rejected")
+        );
+    }
+
+    #[test]
+    fn unreachable_endpoints_fail_over() {
+        let (base, _) = pve();
+        // A port that was just free: nothing listens there.
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!(
+                "https://127.0.0.1:{}",
+                listener.local_addr().unwrap().port()
+            )
+        };
+        let client =
+            ApiClient::new(&[closed.clone(), base.clone()], 8006, TOKEN, &pinned(), 5).unwrap();
+        assert_eq!(client.version().unwrap().version, "9.0.3");
+        assert_eq!(client.endpoint(), base);
+
+        let dead =
+            ApiClient::new(std::slice::from_ref(&closed), 8006, TOKEN, &pinned(), 5).unwrap();
+        let err = dead.version().unwrap_err().to_string();
+        assert!(err.starts_with("no endpoint answered"), "{err}");
+        assert!(err.contains(&closed));
+    }
+
+    #[test]
+    fn timed_out_writes_are_not_sent_twice() {
+        let slow = |req: &Request| {
+            std::thread::sleep(Duration::from_millis(1500));
+            if req.method == "GET" {
+                (
+                    200,
+                    r#"{"data":{"version":"9.0.3","release":"9.0","repoid":"abc"}}"#.to_string(),
+                )
+            } else {
+                (200, format!(r#"{{"data":"{UPID}"}}"#))
+            }
+        };
+        let (slow, _) = serve(slow);
+        let (base, seen) = pve();
+        let client =
+            ApiClient::new(&[slow.clone(), base.clone()], 8006, TOKEN, &pinned(), 1).unwrap();
+
+        let err = client
+            .post::<String>("/nodes/pve2/qemu/101/status/start", &serde_json::json!({}))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("may still have run"), "{err:#}");
+        assert!(seen.lock().unwrap().is_empty(), "retried on another node");
+
+        // Reads are safe to repeat.
+        assert_eq!(client.version().unwrap().version, "9.0.3");
+        assert_eq!(client.endpoint(), base);
+    }
+
+    #[test]
+    fn endpoints_get_the_default_port() {
+        assert_eq!(
+            with_default_port("https://pve1.lab", 8006).unwrap(),
+            "https://pve1.lab:8006"
+        );
+        assert_eq!(
+            with_default_port("https://pve1.lab:8443/", 8006).unwrap(),
+            "https://pve1.lab:8443"
+        );
+        assert!(with_default_port("pve1.lab", 8006).is_err());
+    }
+}
@@ -0,0 +1,1003 @@
+//! `ghostctl pbs`: Proxmox Backup Server health over its REST API.
+//!
+//! Servers are `[[pve.backup_servers]]` entries and share the PVE client
+//! (token auth with `PBSAPIToken`, fingerprint pinning, endpoint failover).
+//! Everything here is read-only: prune is always a dry run on the server, so
+//! the listing is exactly what a real prune with the same options would
+//! remove.
+
+use super::api::ApiClient;
+use super::api::encode_segment;
+use super::config::{PveCluster, PveConfig};
+use crate::tui;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+/// Datastores at or above this usage are reported as a problem.
+const FULL_PERCENT: f64 = 90.0;
+
+/// A datastore's disk usage, from `/status/datastore-usage`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct DatastoreUsage {
+    pub store: String,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub used: Option<u64>,
+    #[serde(default)]
+    pub avail: Option<u64>,
+    /// Unix time the datastore is projected to fill up, from its history.
+    #[serde(default)]
+    pub estimated_full_date: Option<i64>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl DatastoreUsage {
+    pub fn used_percent(&self) -> Option<f64> {
+        match (self.used, self.total) {
+            (Some(used), Some(total)) if total > 0 => Some(used as f64 * 100.0 / total as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Garbage collection job state, from `/admin/gc`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct GcStatus {
+    pub store: String,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub next_run: Option<i64>,
+    #[serde(default)]
+    pub last_run_state: Option<String>,
+    #[serde(default)]
+    pub last_run_endtime: Option<i64>,
+    #[serde(default)]
+    pub removed_bytes: Option<u64>,
+    #[serde(default)]
+    pub pending_bytes: Option<u64>,
+}
+
+/// A sync, verify or prune job and how its last run went.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct JobStatus {
+    pub id: String,
+    pub store: String,
+    /// Sync jobs: the remote pulled from and its datastore.
+    #[serde(default)]
+    pub remote: Option<String>,
+    #[serde(default)]
+    pub remote_store: Option<String>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub next_run: Option<i64>,
+    /// `ok`, or the error the last run ended with.
+    #[serde(default)]
+    pub last_run_state: Option<String>,
+    #[serde(default)]
+    pub last_run_endtime: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct Verification {
+    pub state: String,
+}
+
+/// A namespace, from `/admin/datastore/{store}/namespace`; `""` is the root.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Namespace {
+    #[serde(default)]
+    pub ns: String,
+}
+
+/// A backup snapshot, from `/admin/datastore/{store}/snapshots`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct Snapshot {
+    /// Namespace the snapshot was listed in; not part of the API answer.
+    #[serde(default)]
+    pub ns: String,
+    pub backup_type: String,
+    pub backup_id: String,
+    pub backup_time: i64,
+    #[serde(default)]
+    pub verification: Option<Verification>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub protected: bool,
+}
+
+/// One backup group (`vm/101`) with its verify state.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GroupReport {
+    pub ns: String,
+    pub backup_type: String,
+    pub backup_id: String,
+    pub snapshots: usize,
+    pub last_backup: i64,
+    /// State of the newest verified snapshot (`ok`/`failed`).
+    pub last_verify_state: Option<String>,
+    /// Backup time of the newest snapshot that verified `ok`.
+    pub last_verified_ok: Option<i64>,
+    pub unverified: usize,
+    pub failed: usize,
+    pub size: u64,
+}
+
+impl GroupReport {
+    pub fn name(&self) -> String {
+        group_name(&self.ns, &self.backup_type, &self.backup_id)
+    }
+}
+
+/// Group `snapshots` by backup group, sorted by name.
+pub fn groups(snapshots: &[Snapshot]) -> Vec<GroupReport> {
+    let mut by_group: BTreeMap<(&str, &str, &str), Vec<&Snapshot>> = BTreeMap::new();
+    for s in snapshots {
+        by_group
+            .entry((&s.ns, &s.backup_type, &s.backup_id))
+            .or_default()
+            .push(s);
+    }
+    by_group
+        .into_iter()
+        .map(|((ns, backup_type, backup_id), mut list)| {
+            list.sort_by_key(|s| std::cmp::Reverse(s.backup_time));
+            let state = |s: &&Snapshot| s.verification.as_ref().map(|v| v.state.clone());
+            GroupReport {
+                ns: ns.to_string(),
+                backup_type: backup_type.to_string(),
+                backup_id: backup_id.to_string(),
+                snapshots: list.len(),
+                last_backup: list[0].backup_time,
+                last_verify_state: list.iter().find_map(state),
+                last_verified_ok: list
+                    .iter()
+                    .find(|s| state(s).as_deref() == Some("ok"))
+                    .map(|s| s.backup_time),
+                unverified: list.iter().filter(|s| s.verification.is_none()).count(),
+                failed: list
+                    .iter()
+                    .filter(|s| state(s).as_deref() == Some("failed"))
+                    .count(),
+                size: list.iter().filter_map(|s| s.size).sum(),
+            }
+        })
+        .collect()
+}
+
+/// `keep-*` retention options, as for `proxmox-backup-client prune`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct KeepOptions {
+    pub last: Option<u64>,
+    pub hourly: Option<u64>,
+    pub daily: Option<u64>,
+    pub weekly: Option<u64>,
+    pub monthly: Option<u64>,
+    pub yearly: Option<u64>,
+}
+
+impl KeepOptions {
+    /// Argument and API parameter names, in `values()` order.
+    const NAMES: [&str; 6] = [
+        "keep-last",
+        "keep-hourly",
+        "keep-daily",
+        "keep-weekly",
+        "keep-monthly",
+        "keep-yearly",
+    ];
+
+    fn values(&self) -> [Option<u64>; 6] {
+        [
+            self.last,
+            self.hourly,
+            self.daily,
+            self.weekly,
+            self.monthly,
+            self.yearly,
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values().iter().all(Option::is_none)
+    }
+
+    fn into_params(self, params: &mut Map<String, Value>) {
+        for (name, value) in Self::NAMES.iter().zip(self.values()) {
+            if let Some(n) = value {
+                params.insert(name.to_string(), json!(n));
+            }
+        }
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let [last, hourly, daily, weekly, monthly, yearly] =
+            Self::NAMES.map(|name| matches.get_one::<u64>(name).copied());
+        Self {
+            last,
+            hourly,
+            daily,
+            weekly,
+            monthly,
+            yearly,
+        }
+    }
+}
+
+/// One snapshot in a prune simulation.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct PruneEntry {
+    /// Namespace the prune ran in; not part of the API answer.
+    #[serde(default)]
+    pub ns: String,
+    pub backup_type: String,
+    pub backup_id: String,
+    pub backup_time: i64,
+    pub keep: bool,
+    #[serde(default)]
+    pub protected: bool,
+}
+
+/// Read-only view of one backup server.
+pub struct BackupServer {
+    client: ApiClient,
+}
+
+impl BackupServer {
+    pub fn new(client: ApiClient) -> Self {
+        Self { client }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        self.client.endpoint()
+    }
+
+    pub fn version(&self) -> Result<String> {
+        Ok(self.client.version()?.version)
+    }
+
+    pub fn usage(&self) -> Result<Vec<DatastoreUsage>> {
+        let mut usage: Vec<DatastoreUsage> = self.client.get("/status/datastore-usage")?;
+        usage.sort_by(|a, b| a.store.cmp(&b.store));
+        Ok(usage)
+    }
+
+    pub fn gc(&self) -> Result<Vec<GcStatus>> {
+        self.client.get("/admin/gc")
+    }
+
+    /// Every namespace of a datastore, the root (`""`) first.
+    pub fn namespaces(&self, store: &str) -> Result<Vec<String>> {
+        let listed: Vec<Namespace> = self.client.get(&format!(
+            "/admin/datastore/{}/namespace",
+            encode_segment(store)
+        ))?;
+        let mut namespaces = vec![String::new()];
+        for n in listed {
+            if !namespaces.contains(&n.ns) {
+                namespaces.push(n.ns);
+            }
+        }
+        Ok(namespaces)
+    }
+
+    /// Snapshots in every namespace of a datastore.
+    pub fn snapshots(&self, store: &str) -> Result<Vec<Snapshot>> {
+        let mut all = Vec::new();
+        for ns in self.namespaces(store)? {
+            let mut snapshots: Vec<Snapshot> = self.client.get(&format!(
+                "/admin/datastore/{}/snapshots{}",
+                encode_segment(store),
+                ns_query(&ns)
+            ))?;
+            for s in &mut snapshots {
+                s.ns = ns.clone();
+            }
+            all.extend(snapshots);
+        }
+        Ok(all)
+    }
+
+    fn jobs(&self, kind: &str) -> Result<Vec<JobStatus>> {
+        let mut jobs: Vec<JobStatus> = self.client.get(&format!("/admin/{kind}"))?;
+        jobs.sort_by(|a, b| (&a.store, &a.id).cmp(&(&b.store, &b.id)));
+        Ok(jobs)
+    }
+
+    pub fn sync_jobs(&self) -> Result<Vec<JobStatus>> {
+        self.jobs("sync")
+    }
+
+    pub fn verify_jobs(&self) -> Result<Vec<JobStatus>> {
+        self.jobs("verify")
+    }
+
+    pub fn prune_jobs(&self) -> Result<Vec<JobStatus>> {
+        self.jobs("prune")
+    }
+
+    /// Ask the server what a prune of one group would keep and remove.
+    pub fn simulate_prune(
+        &self,
+        store: &str,
+        ns: &str,
+        backup_type: &str,
+        backup_id: &str,
+        keep: &KeepOptions,
+    ) -> Result<Vec<PruneEntry>> {
+        let mut params = Map::new();
+        if !ns.is_empty() {
+            params.insert("ns".to_string(), json!(ns));
+        }
+        params.insert("backup-type".to_string(), json!(backup_type));
+        params.insert("backup-id".to_string(), json!(backup_id));
+        params.insert("dry-run".to_string(), json!(true));
+        keep.clone().into_params(&mut params);
+        let mut entries: Vec<PruneEntry> = self.client.post(
+            &format!("/admin/datastore/{}/prune", encode_segment(store)),
+            &Value::Object(params),
+        )?;
+        for e in &mut entries {
+            e.ns = ns.to_string();
+        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.backup_time));
+        Ok(entries)
+    }
+
+    /// Everything `pbs report` shows, with the problems found.
+    pub fn report(&self, name: &str) -> Result<Report> {
+        let mut problems = Vec::new();
+        let usage = self.usage()?;
+        let gc = self.gc().unwrap_or_else(|e| {
+            problems.push(format!("garbage collection status unavailable: {e:#}"));
+            Vec::new()
+        });
+        let mut datastores = Vec::new();
+        for usage in usage {
+            let store = usage.store.clone();
+            let groups = match self.snapshots(&store) {
+                Ok(snapshots) => groups(&snapshots),
+                Err(e) => {
+                    problems.push(format!("{store}: cannot list snapshots: {e:#}"));
+                    Vec::new()
+                }
+            };
+            let used_percent = usage.used_percent();
+            datastores.push(DatastoreReport {
+                gc: gc.iter().find(|g| g.store == store).cloned(),
+                used_percent,
+                usage,
+                groups,
+            });
+        }
+        let mut jobs = |kind: &str, list: Result<Vec<JobStatus>>| {
+            list.unwrap_or_else(|e| {
+                problems.push(format!("{kind} jobs unavailable: {e:#}"));
+                Vec::new()
+            })
+        };
+        let sync_jobs = jobs("sync", self.sync_jobs());
+        let verify_jobs = jobs("verify", self.verify_jobs());
+        let prune_jobs = jobs("prune", self.prune_jobs());
+        let mut report = Report {
+            server: name.to_string(),
+            endpoint: self.endpoint().to_string(),
+            version: self.version()?,
+            datastores,
+            sync_jobs,
+            verify_jobs,
+            prune_jobs,
+            problems,
+        };
+        report.problems.extend(report.find_problems());
+        Ok(report)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatastoreReport {
+    #[serde(flatten)]
+    pub usage: DatastoreUsage,
+    pub used_percent: Option<f64>,
+    pub gc: Option<GcStatus>,
+    pub groups: Vec<GroupReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub server: String,
+    pub endpoint: String,
+    pub version: String,
+    pub datastores: Vec<DatastoreReport>,
+    pub sync_jobs: Vec<JobStatus>,
+    pub verify_jobs: Vec<JobStatus>,
+    pub prune_jobs: Vec<JobStatus>,
+    pub problems: Vec<String>,
+}
+
+fn failed_run(state: Option<&str>) -> Option<&str> {
+    state.filter(|s| !s.eq_ignore_ascii_case("ok"))
+}
+
+impl Report {
+    fn find_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for ds in &self.datastores {
+            let store = &ds.usage.store;
+            if let Some(error) = &ds.usage.error {
+                problems.push(format!("{store}: {error}"));
+            }
+            if let Some(percent) = ds.used_percent
+                && percent >= FULL_PERCENT
+            {
+                problems.push(format!("{store}: {percent:.0}% used"));
+            }
+            if let Some(gc) = &ds.gc
+                && let Some(state) = failed_run(gc.last_run_state.as_deref())
+            {
+                problems.push(format!("{store}: garbage collection failed: {state}"));
+            }
+            for group in ds.groups.iter().filter(|g| g.failed > 0) {
+                problems.push(format!(
+                    "{store} {}: {} snapshot(s) failed verification",
+                    group.name(),
+                    group.failed
+                ));
+            }
+        }
+        for (kind, jobs) in [
+            ("sync", &self.sync_jobs),
+            ("verify", &self.verify_jobs),
+            ("prune", &self.prune_jobs),
+        ] {
+            for job in jobs {
+                if let Some(state) = failed_run(job.last_run_state.as_deref()) {
+                    problems.push(format!("{kind} job {}: {state}", job.id));
+                }
+            }
+        }
+        problems
+    }
+}
+
+fn when(time: Option<i64>) -> String {
+    time.and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// PBS snapshot name, `vm/101/2026-10-19T02:00:00Z`.
+/// `?ns=...` for a namespace, nothing for the root.
+fn ns_query(ns: &str) -> String {
+    if ns.is_empty() {
+        String::new()
+    } else {
+        format!("?ns={}", encode_segment(ns))
+    }
+}
+
+/// `vm/101`, or `prod/vm/101` outside the root namespace.
+fn group_name(ns: &str, backup_type: &str, backup_id: &str) -> String {
+    if ns.is_empty() {
+        format!("{backup_type}/{backup_id}")
+    } else {
+        format!("{ns}/{backup_type}/{backup_id}")
+    }
+}
+
+fn snapshot_name(backup_type: &str, backup_id: &str, time: i64) -> String {
+    let stamp = chrono::DateTime::from_timestamp(time, 0)
+        .map(|t| t.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_else(|| time.to_string());
+    format!("{backup_type}/{backup_id}/{stamp}")
+}
+
+fn bytes(n: Option<u64>) -> String {
+    match n {
+        Some(n) if n >= 1 << 40 => format!("{:.2}T", n as f64 / (1u64 << 40) as f64),
+        Some(n) => format!("{:.1}G", n as f64 / (1u64 << 30) as f64),
+        None => "-".to_string(),
+    }
+}
+
+fn state(state: Option<&str>) -> &str {
+    state.unwrap_or("never run")
+}
+
+pub fn command() -> Command {
+    let json_arg = || {
+        Arg::new("json")
+            .long("json")
+            .action(ArgAction::SetTrue)
+            .help("Output as JSON")
+    };
+    let store_arg = || {
+        Arg::new("store")
+            .required(true)
+            .value_name("STORE")
+            .help("Datastore name")
+    };
+    let mut prune = Command::new("prune")
+        .about("Show what a prune with these keep options would remove (dry run)")
+        .arg(store_arg())
+        .arg(
+            Arg::new("group")
+                .long("group")
+                .value_name("[NS/]TYPE/ID")
+                .help("Only this backup group, e.g. vm/101 or prod/vm/101 (default: all)"),
+        )
+        .arg(json_arg());
+    for name in KeepOptions::NAMES {
+        prune = prune.arg(
+            Arg::new(name)
+                .long(name)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64)),
+        );
+    }
+    Command::new("pbs")
+        .about("Proxmox Backup Server health over the API")
+        .subcommand_required(true)
+        .arg(
+            Arg::new("server")
+                .long("server")
+                .global(true)
+                .value_name("NAME")
+                .help("[[pve.backup_servers]] entry (default: default_backup_server)"),
+        )
+        .subcommand(
+            Command::new("datastores")
+                .about("Datastore usage and garbage collection status")
+                .arg(json_arg()),
+        )
+        .subcommand(
+            Command::new("groups")
+                .about("Backup groups with their last verify result")
+                .arg(store_arg())
+                .arg(json_arg()),
+        )
+        .subcommand(prune)
+        .subcommand(
+            Command::new("jobs")
+                .about("Sync jobs by remote, verify and prune jobs")
+                .arg(json_arg()),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Full health report; exits non-zero when problems are found")
+                .arg(json_arg()),
+        )
+}
+
+fn server_entry(matches: &ArgMatches) -> Result<PveCluster> {
+    let cfg = PveConfig::load();
+    let problems = cfg.validate();
+    if !problems.is_empty() {
+        bail!("invalid [pve] config:\n  {}", problems.join("\n  "));
+    }
+    let name = match (
+        matches.get_one::<String>("server"),
+        &cfg.default_backup_server,
+        cfg.backup_servers.as_slice(),
+    ) {
+        (Some(name), _, _) | (None, Some(name), _) => name.clone(),
+        (None, None, [only]) => only.name.clone(),
+        (None, None, []) => bail!("no backup servers: add [[pve.backup_servers]] to config.toml"),
+        (None, None, _) => bail!("several backup servers configured: pass --server"),
+    };
+    Ok(cfg.backup_server(&name)?.clone())
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let entry = server_entry(matches)?;
+    let server = BackupServer::new(ApiClient::for_backup_server(&entry)?);
+    match matches.subcommand() {
+        Some(("datastores", m)) => datastores(&server, m.get_flag("json")),
+        Some(("groups", m)) => {
+            let store = m.get_one::<String>("store").context("store required")?;
+            let groups = groups(&server.snapshots(store)?);
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&groups)?);
+            } else {
+                print_groups(&groups);
+            }
+            Ok(())
+        }
+        Some(("prune", m)) => prune(&server, m),
+        Some(("jobs", m)) => jobs(&server, m.get_flag("json")),
+        Some(("report", m)) => {
+            let report = server.report(&entry.name)?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_report(&report);
+            }
+            if !report.problems.is_empty() {
+                bail!("{} problem(s) on {}", report.problems.len(), entry.name);
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn datastores(server: &BackupServer, json: bool) -> Result<()> {
+    let usage = server.usage()?;
+    let gc = server.gc().unwrap_or_default();
+    if json {
+        let rows: Vec<Value> = usage
+            .iter()
+            .map(|u| {
+                json!({
+                    "usage": u,
+                    "used_percent": u.used_percent(),
+                    "gc": gc.iter().find(|g| g.store == u.store),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    println!(
+        "{:<16} {:>9} {:>9} {:>6}  {:<16} {:<12} {:<16}",
+        "STORE", "USED", "TOTAL", "USE%", "FULL BY", "LAST GC", "GC ENDED"
+    );
+    for u in &usage {
+        let g = gc.iter().find(|g| g.store == u.store);
+        println!(
+            "{:<16} {:>9} {:>9} {:>6}  {:<16} {:<12} {:<16}",
+            u.store,
+            bytes(u.used),
+            bytes(u.total),
+            u.used_percent()
+                .map_or_else(|| "-".to_string(), |p| format!("{p:.0}%")),
+            when(u.estimated_full_date),
+            state(g.and_then(|g| g.last_run_state.as_deref())),
+            when(g.and_then(|g| g.last_run_endtime)),
+        );
+    }
+    Ok(())
+}
+
+fn print_groups(groups: &[GroupReport]) {
+    println!(
+        "{:<24} {:>5} {:<16} {:<8} {:<16} {:>6} {:>6}",
+        "GROUP", "SNAPS", "LAST BACKUP", "VERIFY", "LAST VERIFIED", "UNVER", "FAILED"
+    );
+    for g in groups {
+        println!(
+            "{:<24} {:>5} {:<16} {:<8} {:<16} {:>6} {:>6}",
+            g.name(),
+            g.snapshots,
+            when(Some(g.last_backup)),
+            g.last_verify_state.as_deref().unwrap_or("none"),
+            when(g.last_verified_ok),
+            g.unverified,
+            g.failed
+        );
+    }
+}
+
+fn prune(server: &BackupServer, matches: &ArgMatches) -> Result<()> {
+    let store = matches
+        .get_one::<String>("store")
+        .context("store required")?;
+    let keep = KeepOptions::from_matches(matches);
+    if keep.is_empty() {
+        bail!("give at least one --keep-* option (without any, prune keeps everything)");
+    }
+    let targets: Vec<(String, String, String)> = match matches.get_one::<String>("group") {
+        Some(group) => {
+            let mut parts = group.rsplitn(3, '/');
+            let (Some(id), Some(t)) = (parts.next(), parts.next()) else {
+                bail!("'{group}' is not [NS/]TYPE/ID, e.g. vm/101");
+            };
+            let ns = parts.next().unwrap_or_default();
+            vec![(ns.to_string(), t.to_string(), id.to_string())]
+        }
+        None => groups(&server.snapshots(store)?)
+            .into_iter()
+            .map(|g| (g.ns, g.backup_type, g.backup_id))
+            .collect(),
+    };
+    let mut all = Vec::new();
+    for (ns, backup_type, backup_id) in &targets {
+        all.extend(server.simulate_prune(store, ns, backup_type, backup_id, &keep)?);
+    }
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&all)?);
+        return Ok(());
+    }
+    let removed = all.iter().filter(|e| !e.keep).count();
+    for (ns, backup_type, backup_id) in &targets {
+        let entries: Vec<&PruneEntry> = all
+            .iter()
+            .filter(|e| e.ns == *ns && e.backup_type == *backup_type && e.backup_id == *backup_id)
+            .collect();
+        println!(
+            "{}: keep {}, remove {}",
+            group_name(ns, backup_type, backup_id),
+            entries.iter().filter(|e| e.keep).count(),
+            entries.iter().filter(|e| !e.keep).count()
+        );
+        for e in entries {
+            let mark = match (e.keep, e.protected) {
+                (_, true) => "protected",
+                (true, false) => "keep",
+                (false, false) => "remove",
+            };
+            println!(
+                "  {mark:<9} {}",
+                snapshot_name(&e.backup_type, &e.backup_id, e.backup_time)
+            );
+        }
+    }
+    println!(
+        "\nDry run: {removed} of {} snapshot(s) in {store} would be removed.",
+        all.len()
+    );
+    Ok(())
+}
+
+fn print_jobs(kind: &str, jobs: &[JobStatus]) {
+    println!("{kind} jobs:");
+    if jobs.is_empty() {
+        println!("  (none)");
+    }
+    for job in jobs {
+        println!(
+            "  {:<20} {:<12} {:<16} {:<16} {}",
+            job.id,
+            job.store,
+            when(job.last_run_endtime),
+            when(job.next_run),
+            state(job.last_run_state.as_deref())
+        );
+    }
+}
+
+fn jobs(server: &BackupServer, json: bool) -> Result<()> {
+    let sync = server.sync_jobs()?;
+    let verify = server.verify_jobs()?;
+    let prune = server.prune_jobs()?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "sync_jobs": sync,
+                "verify_jobs": verify,
+                "prune_jobs": prune,
+            }))?
+        );
+        return Ok(());
+    }
+    let mut by_remote: BTreeMap<&str, Vec<&JobStatus>> = BTreeMap::new();
+    for job in &sync {
+        by_remote
+            .entry(job.remote.as_deref().unwrap_or("(local)"))
+            .or_default()
+            .push(job);
+    }
+    println!("sync jobs:");
+    if sync.is_empty() {
+        println!("  (none)");
+    }
+    for (remote, jobs) in by_remote {
+        println!("  {remote}");
+        for job in jobs {
+            println!(
+                "    {:<18} {} -> {:<12} {:<16} {}",
+                job.id,
+                job.remote_store.as_deref().unwrap_or("-"),
+                job.store,
+                when(job.last_run_endtime),
+                state(job.last_run_state.as_deref())
+            );
+        }
+    }
+    print_jobs("verify", &verify);
+    print_jobs("prune", &prune);
+    Ok(())
+}
+
+fn print_report(report: &Report) {
+    println!(
+        "{} ({}, PBS {})",
+        report.server, report.endpoint, report.version
+    );
+    for ds in &report.datastores {
+        let u = &ds.usage;
+        println!(
+            "\n{}: {} of {} used ({}), last GC {} {}",
+            u.store,
+            bytes(u.used),
+            bytes(u.total),
+            ds.used_percent
+                .map_or_else(|| "-".to_string(), |p| format!("{p:.0}%")),
+            state(ds.gc.as_ref().and_then(|g| g.last_run_state.as_deref())),
+            when(ds.gc.as_ref().and_then(|g| g.last_run_endtime)),
+        );
+        print_groups(&ds.groups);
+    }
+    println!();
+    print_jobs("sync", &report.sync_jobs);
+    print_jobs("verify", &report.verify_jobs);
+    print_jobs("prune", &report.prune_jobs);
+    if report.problems.is_empty() {
+        tui::success("No problems found");
+    }
+    for problem in &report.problems {
+        tui::warn(problem);
+    }
+}
+
+/// Menu entry: the report for the default backup server.
+pub fn report_menu() {
+    let matches = command().get_matches_from(["pbs", "report"]);
+    if let Err(e) = handle(&matches) {
+        tui::error(&format!("{e:#}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::api::{Trust, fingerprint, normalize_fingerprint};
+    use super::*;
+    use crate::test_server::{Request, serve};
+    use std::sync::Arc;
+
+    const TOKEN: &str = "PBSAPIToken=monitor@pbs!ghostctl:s3cret";
+
+    fn pbs() -> (BackupServer, Arc<std::sync::Mutex<Vec<Request>>>) {
+        let (base, seen) = serve(|req: &Request| {
+            if req.authorization != TOKEN {
+                return (401, r#"{"data":null}"#.to_string());
+            }
+            let body = match (req.method.as_str(), req.path.as_str()) {
+                ("GET", "/api2/json/version") => {
+                    r#"{"version":"3.4.1","release":"3.4","repoid":"x"}"#.to_string()
+                }
+                ("GET", "/api2/json/status/datastore-usage") => r#"[
+                    {"store":"tank","total":1000,"used":950,"avail":50,"estimated-full-date":1793000000},
+                    {"store":"fast","total":1000,"used":100,"avail":900}
+                ]"#
+                .to_string(),
+                ("GET", "/api2/json/admin/gc") => r#"[
+                    {"store":"tank","last-run-state":"ok","last-run-endtime":1760000000,"removed-bytes":10,"pending-bytes":0,"schedule":"daily"},
+                    {"store":"fast","last-run-state":"TASK ERROR: chunk store locked"}
+                ]"#
+                .to_string(),
+                ("GET", "/api2/json/admin/datastore/tank/snapshots") => r#"[
+                    {"backup-type":"vm","backup-id":"101","backup-time":1760000000,"size":5,"verification":{"state":"ok","upid":"U"}},
+                    {"backup-type":"vm","backup-id":"101","backup-time":1760086400,"size":6},
+                    {"backup-type":"ct","backup-id":"200","backup-time":1760000000,"verification":{"state":"failed","upid":"U"}},
+                    {"backup-type":"ct","backup-id":"200","backup-time":1759900000,"verification":{"state":"ok","upid":"U"}}
+                ]"#
+                .to_string(),
+                ("GET", "/api2/json/admin/datastore/tank/namespace") => {
+                    r#"[{"ns":""},{"ns":"prod"}]"#.to_string()
+                }
+                ("GET", "/api2/json/admin/datastore/tank/snapshots?ns=prod") => r#"[
+                    {"backup-type":"vm","backup-id":"101","backup-time":1760050000,"verification":{"state":"failed","upid":"U"}}
+                ]"#
+                .to_string(),
+                ("GET", "/api2/json/admin/datastore/fast/namespace") => r#"[{"ns":""}]"#.to_string(),
+                ("GET", "/api2/json/admin/datastore/fast/snapshots") => "[]".to_string(),
+                ("GET", "/api2/json/admin/sync") => r#"[
+                    {"id":"offsite","store":"tank","remote":"colo","remote-store":"store1","last-run-state":"ok","last-run-endtime":1760000000},
+                    {"id":"lab","store":"fast","remote":"lab","remote-store":"s","last-run-state":"remote unreachable"}
+                ]"#
+                .to_string(),
+                ("GET", "/api2/json/admin/verify") => {
+                    r#"[{"id":"v-tank","store":"tank","schedule":"weekly"}]"#.to_string()
+                }
+                ("GET", "/api2/json/admin/prune") => "[]".to_string(),
+                ("POST", "/api2/json/admin/datastore/tank/prune") => r#"[
+                    {"backup-type":"vm","backup-id":"101","backup-time":1760086400,"keep":true,"protected":false},
+                    {"backup-type":"vm","backup-id":"101","backup-time":1760000000,"keep":false,"protected":false}
+                ]"#
+                .to_string(),
+                _ => return (501, r#"{"data":null}"#.to_string()),
+            };
+            (200, format!(r#"{{"data":{body}}}"#))
+        });
+        let pin = normalize_fingerprint(&fingerprint(&crate::test_server::cert_der())).unwrap();
+        let client = ApiClient::new(&[base], 8007, TOKEN, &Trust::Pinned(vec![pin]), 5).unwrap();
+        (BackupServer::new(client), seen)
+    }
+
+    #[test]
+    fn report_collects_usage_verify_and_job_problems() {
+        let (server, seen) = pbs();
+        let report = server.report("pbs").unwrap();
+        assert_eq!(report.version, "3.4.1");
+        assert_eq!(
+            report
+                .datastores
+                .iter()
+                .map(|d| d.usage.store.as_str())
+                .collect::<Vec<_>>(),
+            ["fast", "tank"]
+        );
+        let tank = &report.datastores[1];
+        assert_eq!(tank.used_percent, Some(95.0));
+        assert_eq!(tank.gc.as_ref().unwrap().removed_bytes, Some(10));
+        let names: Vec<String> = tank.groups.iter().map(GroupReport::name).collect();
+        assert_eq!(names, ["ct/200", "vm/101", "prod/vm/101"]);
+        let vm = &tank.groups[1];
+        assert_eq!(
+            (vm.snapshots, vm.unverified, vm.last_verify_state.as_deref()),
+            (2, 1, Some("ok"))
+        );
+        assert_eq!(vm.last_backup, 1760086400);
+        assert_eq!(vm.size, 11);
+        let ct = &tank.groups[0];
+        assert_eq!(
+            (
+                ct.failed,
+                ct.last_verify_state.as_deref(),
+                ct.last_verified_ok
+            ),
+            (1, Some("failed"), Some(1759900000))
+        );
+
+        assert_eq!(
+            report.problems,
+            [
+                "fast: garbage collection failed: TASK ERROR: chunk store locked",
+                "tank: 95% used",
+                "tank ct/200: 1 snapshot(s) failed verification",
+                "tank prod/vm/101: 1 snapshot(s) failed verification",
+                "sync job lab: remote unreachable",
+            ]
+        );
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["datastores"][1]["estimated_full_date"], 1793000000);
+        assert_eq!(json["sync_jobs"][1]["remote_store"], "store1");
+        assert!(seen.lock().unwrap().iter().all(|r| r.method == "GET"));
+    }
+
+    #[test]
+    fn prune_is_a_server_side_dry_run() {
+        let (server, seen) = pbs();
+        let keep = KeepOptions {
+            last: Some(1),
+            daily: Some(7),
+            ..KeepOptions::default()
+        };
+        let entries = server
+            .simulate_prune("tank", "prod", "vm", "101", &keep)
+            .unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.keep).collect::<Vec<_>>(),
+            [true, false]
+        );
+        let seen = seen.lock().unwrap();
+        let body: Value = serde_json::from_str(&seen[0].body).unwrap();
+        assert_eq!(
+            body,
+            json!({"ns":"prod","backup-type":"vm","backup-id":"101","dry-run":true,"keep-last":1,"keep-daily":7})
+        );
+        assert_eq!(
+            snapshot_name("vm", "101", 1760000000),
+            "vm/101/2025-10-09T08:53:20Z"
+        );
+    }
+}
@@ -0,0 +1,1045 @@
+//! `pve template list|refresh`: cloud images turned into versioned template
+//! VMs.
+//!
+//! Each `[[pve.images]]` entry names an upstream image and how to verify it.
+//! A refresh downloads it into a cache (skipped when the cached copy still
+//! matches the published checksum), checks the checksum file's signature,
+//! optionally customizes a copy with `virt-customize`, and imports the
+//! result as a template named `<image>-<date>-<version>`. The version is a
+//! hash of the image digest and the customization, so the templates on the
+//! cluster themselves record what has been built and a refresh only builds
+//! when upstream or the recipe changed.
+
+use super::api::Guest;
+use super::script_safety::{self, HashAlgorithm, Hasher};
+use crate::command::CommandRunner;
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Tag put on every template this pipeline builds.
+pub const TEMPLATE_TAG: &str = "ghostctl-image";
+
+/// A cloud image in the `[[pve.images]]` catalog.
+///
+/// ```toml
+/// [[pve.images]]
+/// name = "debian-12"
+/// url = "https://cloud.debian.org/images/cloud/bookworm/latest/debian-12-genericcloud-amd64.qcow2"
+/// checksum_url = "https://cloud.debian.org/images/cloud/bookworm/latest/SHA512SUMS"
+/// customize = { agent = true, packages = ["htop"] }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CloudImage {
+    pub name: String,
+    pub url: String,
+
+    /// Checksum file published next to the image (`SHA256SUMS`, ...).
+    #[serde(default)]
+    pub checksum_url: Option<String>,
+
+    /// Pinned digest, `sha256:HEX` or `sha512:HEX`, instead of a checksum file.
+    #[serde(default)]
+    pub checksum: Option<String>,
+
+    /// Detached OpenPGP signature over the checksum file.
+    #[serde(default)]
+    pub signature_url: Option<String>,
+
+    /// Keyring holding the signing key; gpgv's `trustedkeys.kbx` otherwise.
+    #[serde(default)]
+    pub keyring: Option<String>,
+
+    /// First VMID to try for new templates.
+    #[serde(default = "default_vmid_start")]
+    pub vmid_start: u32,
+
+    #[serde(default = "default_storage")]
+    pub storage: String,
+
+    #[serde(default = "default_bridge")]
+    pub bridge: String,
+
+    /// MiB.
+    #[serde(default = "default_memory")]
+    pub memory: u64,
+
+    #[serde(default = "default_cores")]
+    pub cores: u32,
+
+    /// Grow the imported disk to this size, e.g. `8G`.
+    #[serde(default)]
+    pub disk_size: Option<String>,
+
+    /// Template versions kept per image; older ones are destroyed.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+
+    #[serde(default)]
+    pub customize: Customize,
+}
+
+/// Changes baked into the image before it becomes a template.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Customize {
+    #[serde(default)]
+    pub packages: Vec<String>,
+
+    /// Install and enable qemu-guest-agent, and turn the agent on in PVE.
+    #[serde(default)]
+    pub agent: bool,
+
+    #[serde(default)]
+    pub ssh_keys_file: Option<String>,
+
+    #[serde(default = "default_ssh_user")]
+    pub ssh_user: String,
+
+    /// Commands run inside the image, in order.
+    #[serde(default)]
+    pub run: Vec<String>,
+}
+
+fn default_vmid_start() -> u32 {
+    9000
+}
+
+fn default_storage() -> String {
+    "local-lvm".to_string()
+}
+
+fn default_bridge() -> String {
+    "vmbr0".to_string()
+}
+
+fn default_memory() -> u64 {
+    2048
+}
+
+fn default_cores() -> u32 {
+    2
+}
+
+fn default_keep() -> usize {
+    2
+}
+
+fn default_ssh_user() -> String {
+    "root".to_string()
+}
+
+impl Customize {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+            && !self.agent
+            && self.ssh_keys_file.is_none()
+            && self.run.is_empty()
+    }
+
+    /// `virt-customize` arguments after `-a IMAGE`.
+    fn args(&self) -> Vec<String> {
+        let mut packages = self.packages.clone();
+        if self.agent && !packages.iter().any(|p| p == "qemu-guest-agent") {
+            packages.push("qemu-guest-agent".to_string());
+        }
+        let mut args = Vec::new();
+        if !packages.is_empty() {
+            args.extend(["--install".to_string(), packages.join(",")]);
+        }
+        if self.agent {
+            args.extend([
+                "--run-command".to_string(),
+                "systemctl enable qemu-guest-agent".to_string(),
+            ]);
+        }
+        if let Some(keys) = &self.ssh_keys_file {
+            args.extend([
+                "--ssh-inject".to_string(),
+                format!("{}:file:{}", self.ssh_user, expand_home(keys).display()),
+            ]);
+        }
+        for command in &self.run {
+            args.extend(["--run-command".to_string(), command.clone()]);
+        }
+        // Clones must not share a machine-id (DHCP leases, journald).
+        args.extend(["--truncate".to_string(), "/etc/machine-id".to_string()]);
+        args
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl CloudImage {
+    pub fn validate(&self) -> Vec<String> {
+        let name = &self.name;
+        let mut problems = Vec::new();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            problems.push(format!(
+                "image '{name}': name must be letters, digits and '-'"
+            ));
+        }
+        match (&self.checksum_url, &self.checksum) {
+            (None, None) => problems.push(format!(
+                "image '{name}': set checksum_url or checksum, downloads are never used unverified"
+            )),
+            (Some(_), Some(_)) => problems.push(format!(
+                "image '{name}': set checksum_url or checksum, not both"
+            )),
+            (None, Some(pinned)) => {
+                if parse_pinned(pinned).is_none() {
+                    problems.push(format!(
+                        "image '{name}': checksum must be sha256:HEX or sha512:HEX"
+                    ));
+                }
+            }
+            (Some(_), None) => {}
+        }
+        if self.signature_url.is_some() && self.checksum_url.is_none() {
+            problems.push(format!(
+                "image '{name}': signature_url signs the checksum file, so it needs checksum_url"
+            ));
+        }
+        if self.vmid_start < 100 {
+            problems.push(format!("image '{name}': vmid_start must be at least 100"));
+        }
+        if self.keep == 0 {
+            problems.push(format!("image '{name}': keep must be at least 1"));
+        }
+        problems
+    }
+}
+
+fn parse_pinned(pinned: &str) -> Option<(HashAlgorithm, String)> {
+    let (name, hex) = pinned.split_once(':')?;
+    let algorithm = HashAlgorithm::for_digest(hex)?;
+    (algorithm.name() == name.to_ascii_lowercase()).then(|| (algorithm, hex.to_ascii_lowercase()))
+}
+
+/// A template built from an image, as found on the cluster.
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateVersion {
+    pub vmid: u32,
+    pub name: String,
+    pub node: String,
+    /// `<date>-<version>` part of the name.
+    pub version: String,
+}
+
+/// Templates built for `image`, oldest first.
+pub fn versions(guests: &[Guest], image: &str) -> Vec<TemplateVersion> {
+    let prefix = format!("{image}-");
+    let mut found: Vec<TemplateVersion> = guests
+        .iter()
+        .filter(|g| g.template == 1)
+        .filter(|g| {
+            g.tags
+                .as_deref()
+                .is_some_and(|t| t.split([';', ',', ' ']).any(|t| t == TEMPLATE_TAG))
+        })
+        .filter_map(|g| {
+            let version = g.name.strip_prefix(&prefix)?;
+            // Another image's name may extend this one (`debian-12-arm`).
+            let (date, hash) = version.split_once('-')?;
+            (date.len() == 8
+                && date.bytes().all(|b| b.is_ascii_digit())
+                && hash.len() == 8
+                && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| TemplateVersion {
+                vmid: g.vmid,
+                name: g.name.clone(),
+                node: g.node.clone(),
+                version: version.to_string(),
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| a.version.cmp(&b.version).then(a.vmid.cmp(&b.vmid)));
+    found
+}
+
+/// What a refresh did for one image.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    UpToDate {
+        vmid: u32,
+        name: String,
+    },
+    Built {
+        vmid: u32,
+        name: String,
+        removed: Vec<u32>,
+    },
+    WouldBuild {
+        name: String,
+    },
+}
+
+/// Downloads, verifies and imports images on this node.
+pub struct Pipeline<'a> {
+    runner: &'a dyn CommandRunner,
+    cache: PathBuf,
+    http: reqwest::blocking::Client,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Result<Self> {
+        let cache = dirs::cache_dir()
+            .or_else(|| dirs::home_dir().map(|h| h.join(".cache")))
+            .context("Cannot determine cache directory - HOME not set")?
+            .join("ghostctl")
+            .join("images");
+        Self::with_cache(runner, cache)
+    }
+
+    pub fn with_cache(runner: &'a dyn CommandRunner, cache: PathBuf) -> Result<Self> {
+        // No overall timeout: images are hundreds of MB.
+        let http = reqwest::blocking::Client::builder()
+            .connect_timeout(Duration::from_secs(30))
+            .timeout(None)
+            .user_agent(concat!("ghostctl/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("failed to build HTTP client")?;
+        Ok(Self {
+            runner,
+            cache,
+            http,
+        })
+    }
+
+    fn open(&self, url: &str) -> Result<Box<dyn Read>> {
+        if let Some(path) = url.strip_prefix("file://") {
+            let file =
+                std::fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+            return Ok(Box::new(file));
+        }
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .with_context(|| format!("failed to fetch {url}"))?;
+        if !response.status().is_success() {
+            bail!("HTTP {} for {url}", response.status());
+        }
+        Ok(Box::new(response))
+    }
+
+    fn fetch_to(&self, url: &str, dest: &Path) -> Result<()> {
+        let mut reader = self.open(url)?;
+        let mut file = std::fs::File::create(dest)
+            .with_context(|| format!("failed to create {}", dest.display()))?;
+        std::io::copy(&mut reader, &mut file)
+            .with_context(|| format!("failed to download {url}"))?;
+        Ok(())
+    }
+
+    fn image_dir(&self, image: &CloudImage) -> Result<PathBuf> {
+        let dir = self.cache.join(&image.name);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(dir)
+    }
+
+    /// The digest the image must have, from the pinned value or the
+    /// (signature-checked) checksum file.
+    pub fn expected_digest(&self, image: &CloudImage) -> Result<(HashAlgorithm, String)> {
+        if let Some(pinned) = &image.checksum {
+            return parse_pinned(pinned).context("checksum must be sha256:HEX or sha512:HEX");
+        }
+        let url = image
+            .checksum_url
+            .as_deref()
+            .context("no checksum_url or checksum")?;
+        let dir = self.image_dir(image)?;
+        let sums_path = dir.join(file_name(url));
+        self.fetch_to(url, &sums_path)?;
+        if let Some(signature_url) = &image.signature_url {
+            let signature_path = dir.join(file_name(signature_url));
+            self.fetch_to(signature_url, &signature_path)?;
+            let keyring = image.keyring.as_deref().map(expand_home);
+            script_safety::verify_signature(
+                self.runner,
+                keyring.as_deref(),
+                &signature_path,
+                &sums_path,
+            )
+            .with_context(|| format!("{} is not signed by a trusted key", file_name(url)))?;
+        }
+        let sums = std::fs::read_to_string(&sums_path)?;
+        let digest = script_safety::checksum_for(&sums, file_name(&image.url))
+            .with_context(|| format!("{} is not listed in {url}", file_name(&image.url)))?;
+        let algorithm = HashAlgorithm::for_digest(&digest).context("unknown digest length")?;
+        Ok((algorithm, digest))
+    }
+
+    /// The verified image in the cache, downloading it unless the cached
+    /// copy already has the expected digest.
+    pub fn download(
+        &self,
+        image: &CloudImage,
+        algorithm: HashAlgorithm,
+        digest: &str,
+    ) -> Result<PathBuf> {
+        let path = self.image_dir(image)?.join(file_name(&image.url));
+        if path.exists() && script_safety::hash_file(algorithm, &path)? == digest {
+            tui::info(&format!("Using cached {}", path.display()));
+            return Ok(path);
+        }
+        tui::info(&format!("Downloading {}", image.url));
+        let partial = path.with_extension("part");
+        let mut reader = self.open(&image.url)?;
+        let mut file = std::fs::File::create(&partial)
+            .with_context(|| format!("failed to create {}", partial.display()))?;
+        let mut hasher = Hasher::new(algorithm);
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            let n = reader
+                .read(&mut buf)
+                .with_context(|| format!("failed to download {}", image.url))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])?;
+        }
+        drop(file);
+        let got = hasher.finish();
+        if got != digest {
+            let _ = std::fs::remove_file(&partial);
+            bail!(
+                "{} has {} {got}, expected {digest}",
+                file_name(&image.url),
+                algorithm.name()
+            );
+        }
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// A customized copy of `source`, or `source` itself when there is
+    /// nothing to change.
+    fn customize(&self, image: &CloudImage, source: &Path) -> Result<(PathBuf, bool)> {
+        if image.customize.is_empty() {
+            return Ok((source.to_path_buf(), false));
+        }
+        if !self.runner.command_exists("virt-customize") {
+            bail!("customizing images needs virt-customize (apt install libguestfs-tools)");
+        }
+        let work = source.with_file_name(format!("{}-build.qcow2", image.name));
+        std::fs::copy(source, &work)
+            .with_context(|| format!("failed to copy {}", source.display()))?;
+        let work_arg = work.to_string_lossy().to_string();
+        let mut args = vec!["-a".to_string(), work_arg];
+        args.extend(image.customize.args());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        tui::info(&format!("Customizing {}", image.name));
+        let out = self
+            .runner
+            .run("virt-customize", &args)
+            .context("failed to run virt-customize")?;
+        if !out.success {
+            let _ = std::fs::remove_file(&work);
+            bail!("virt-customize failed: {}", out.stderr.trim());
+        }
+        Ok((work, true))
+    }
+
+    fn qm(&self, args: &[&str]) -> Result<()> {
+        let out = self
+            .runner
+            .run("qm", args)
+            .with_context(|| "failed to run qm".to_string())?;
+        if !out.success {
+            bail!(
+                "qm {} failed: {}",
+                args.first().unwrap_or(&""),
+                out.stderr.trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Every guest in the cluster, templates included.
+    pub fn guests(&self) -> Result<Vec<Guest>> {
+        let out = self
+            .runner
+            .run(
+                "pvesh",
+                &[
+                    "get",
+                    "/cluster/resources",
+                    "--type",
+                    "vm",
+                    "--output-format",
+                    "json",
+                ],
+            )
+            .context("failed to run pvesh")?;
+        if !out.success {
+            bail!("pvesh get /cluster/resources failed: {}", out.stderr.trim());
+        }
+        serde_json::from_str(&out.stdout).context("unexpected pvesh output")
+    }
+
+    fn import(
+        &self,
+        image: &CloudImage,
+        disk: &Path,
+        vmid: u32,
+        name: &str,
+        description: &str,
+    ) -> Result<()> {
+        let vmid = vmid.to_string();
+        let memory = image.memory.to_string();
+        let cores = image.cores.to_string();
+        let net = format!("virtio,bridge={}", image.bridge);
+        let scsi0 = format!(
+            "{}:0,import-from={},discard=on",
+            image.storage,
+            disk.display()
+        );
+        let cloudinit = format!("{}:cloudinit", image.storage);
+        let mut args = vec![
+            "create",
+            &vmid,
+            "--name",
+            name,
+            "--memory",
+            &memory,
+            "--cores",
+            &cores,
+            "--net0",
+            &net,
+            "--scsihw",
+            "virtio-scsi-pci",
+            "--scsi0",
+            &scsi0,
+            "--ide2",
+            &cloudinit,
+            "--boot",
+            "order=scsi0",
+            "--serial0",
+            "socket",
+            "--vga",
+            "serial0",
+            "--ostype",
+            "l26",
+            "--tags",
+            TEMPLATE_TAG,
+            "--description",
+            description,
+        ];
+        if image.customize.agent {
+            args.extend(["--agent", "enabled=1"]);
+        }
+        self.qm(&args)?;
+        let built = (|| {
+            if let Some(size) = &image.disk_size {
+                self.qm(&["resize", &vmid, "scsi0", size])?;
+            }
+            self.qm(&["template", &vmid])
+        })();
+        if let Err(e) = built {
+            // Don't leave a half-built VM holding the VMID.
+            let _ = self.qm(&["destroy", &vmid, "--purge"]);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Build a new template for `image` unless the newest one already
+    /// matches upstream and the customization.
+    pub fn refresh(&self, image: &CloudImage, force: bool, today: &str) -> Result<Outcome> {
+        let problems = image.validate();
+        if !problems.is_empty() {
+            bail!("{}", problems.join("; "));
+        }
+        let (algorithm, digest) = self.expected_digest(image)?;
+        let version = version_hash(image, algorithm, &digest)?;
+        let guests = self.guests()?;
+        let existing = versions(&guests, &image.name);
+        if !force
+            && let Some(current) = existing
+                .iter()
+                .rev()
+                .find(|v| v.version.ends_with(&version))
+        {
+            return Ok(Outcome::UpToDate {
+                vmid: current.vmid,
+                name: current.name.clone(),
+            });
+        }
+        let name = format!("{}-{today}-{version}", image.name);
+        if is_dry_run() {
+            return Ok(Outcome::WouldBuild { name });
+        }
+
+        let cached = self.download(image, algorithm, &digest)?;
+        let (disk, scratch) = self.customize(image, &cached)?;
+        let vmid = next_free_vmid(&guests, image.vmid_start)?;
+        let description = format!(
+            "Built by ghostctl from {}\n{}:{digest}",
+            image.url,
+            algorithm.name()
+        );
+        tui::info(&format!("Importing {name} as template {vmid}"));
+        let imported = self.import(image, &disk, vmid, &name, &description);
+        if scratch {
+            let _ = std::fs::remove_file(&disk);
+        }
+        imported?;
+
+        let mut removed = Vec::new();
+        let mut remaining = existing.len() + 1;
+        for old in &existing {
+            if remaining <= image.keep {
+                break;
+            }
+            match self.qm(&["destroy", &old.vmid.to_string()]) {
+                Ok(()) => {
+                    removed.push(old.vmid);
+                    remaining -= 1;
+                }
+                // Linked clones keep their base template alive.
+                Err(e) => tui::warn(&format!("Kept {} ({}): {e:#}", old.name, old.vmid)),
+            }
+        }
+        Ok(Outcome::Built {
+            vmid,
+            name,
+            removed,
+        })
+    }
+}
+
+/// First 8 hex digits of a hash over the image digest and the recipe.
+fn version_hash(image: &CloudImage, algorithm: HashAlgorithm, digest: &str) -> Result<String> {
+    let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+    hasher.update(format!("{}:{digest}\n", algorithm.name()).as_bytes());
+    if !image.customize.is_empty() {
+        hasher.update(serde_json::to_string(&image.customize)?.as_bytes());
+        if let Some(keys) = &image.customize.ssh_keys_file {
+            let path = expand_home(keys);
+            let keys = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            hasher.update(keys.as_bytes());
+        }
+    }
+    hasher.update(
+        format!(
+            "{}:{}:{}",
+            image.storage,
+            image.disk_size.as_deref().unwrap_or_default(),
+            image.bridge
+        )
+        .as_bytes(),
+    );
+    Ok(hasher.finish()[..8].to_string())
+}
+
+fn next_free_vmid(guests: &[Guest], start: u32) -> Result<u32> {
+    (start..=999_999_999)
+        .find(|id| !guests.iter().any(|g| g.vmid == *id))
+        .context("no free VMID")
+}
+
+pub fn command() -> Command {
+    Command::new("template")
+        .about("Cloud image templates from the [[pve.images]] catalog")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("Show catalog images and the templates built from them")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("refresh")
+                .about("Build new templates for images that changed upstream")
+                .arg(
+                    Arg::new("image")
+                        .num_args(0..)
+                        .value_name("IMAGE")
+                        .help("Images to refresh (default: all)"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Rebuild even when the newest template is current"),
+                ),
+        )
+}
+
+fn catalog() -> Result<Vec<CloudImage>> {
+    let images = super::config::PveConfig::load().images;
+    if images.is_empty() {
+        bail!("no cloud images configured: add [[pve.images]] to config.toml");
+    }
+    Ok(images)
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let runner = crate::command::runner();
+    match matches.subcommand() {
+        Some(("list", m)) => list(runner.as_ref(), m.get_flag("json")),
+        Some(("refresh", m)) => {
+            let wanted: Vec<String> = m
+                .get_many::<String>("image")
+                .map(|v| v.cloned().collect())
+                .unwrap_or_default();
+            refresh(runner.as_ref(), &wanted, m.get_flag("force"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn list(runner: &dyn CommandRunner, json: bool) -> Result<()> {
+    let images = catalog()?;
+    let guests = Pipeline::new(runner)?.guests()?;
+    let report: Vec<serde_json::Value> = images
+        .iter()
+        .map(|image| {
+            serde_json::json!({
+                "name": image.name,
+                "url": image.url,
+                "templates": versions(&guests, &image.name),
+            })
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    for image in &images {
+        println!("{} ({})", image.name, file_name(&image.url));
+        let found = versions(&guests, &image.name);
+        if found.is_empty() {
+            println!(
+                "  (no templates yet: run `ghostctl pve template refresh {}`)",
+                image.name
+            );
+        }
+        for (i, v) in found.iter().rev().enumerate() {
+            let marker = if i == 0 { "*" } else { " " };
+            println!("  {marker} {:>6} {:<40} {}", v.vmid, v.name, v.node);
+        }
+    }
+    Ok(())
+}
+
+fn refresh(runner: &dyn CommandRunner, wanted: &[String], force: bool) -> Result<()> {
+    let images = catalog()?;
+    for name in wanted {
+        if !images.iter().any(|i| i.name == *name) {
+            bail!("no image '{name}' in [[pve.images]]");
+        }
+    }
+    if !runner.command_exists("qm") {
+        bail!("qm not found: templates are built on a PVE node");
+    }
+    let pipeline = Pipeline::new(runner)?;
+    let today = chrono::Local::now().format("%Y%m%d").to_string();
+    let mut failed = 0;
+    for image in images
+        .iter()
+        .filter(|i| wanted.is_empty() || wanted.contains(&i.name))
+    {
+        match pipeline.refresh(image, force, &today) {
+            Ok(Outcome::UpToDate { vmid, name }) => {
+                tui::success(&format!("{}: up to date ({name}, {vmid})", image.name))
+            }
+            Ok(Outcome::WouldBuild { name }) => {
+                tui::info(&format!("[DRY RUN] {}: would build {name}", image.name))
+            }
+            Ok(Outcome::Built {
+                vmid,
+                name,
+                removed,
+            }) => {
+                tui::success(&format!("{}: built {name} as {vmid}", image.name));
+                if !removed.is_empty() {
+                    let removed: Vec<String> = removed.iter().map(u32::to_string).collect();
+                    tui::info(&format!("Removed old templates {}", removed.join(", ")));
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                tui::error(&format!("{}: {e:#}", image.name));
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} image(s) failed to refresh");
+    }
+    Ok(())
+}
+
+/// Menu entry: show the catalog and offer a refresh.
+pub fn refresh_menu() {
+    let runner = crate::command::runner();
+    if let Err(e) = list(runner.as_ref(), false) {
+        tui::error(&format!("{e:#}"));
+        return;
+    }
+    if tui::confirm("Refresh all images now?", false)
+        && let Err(e) = refresh(runner.as_ref(), &[], false)
+    {
+        tui::error(&format!("{e:#}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    fn guests_json(extra: &str) -> String {
+        format!(
+            r#"[{{"type":"qemu","vmid":9000,"name":"debian-12-20260901-0badcafe","node":"pve1","template":1,"tags":"ghostctl-image"}},
+               {{"type":"qemu","vmid":9001,"name":"debian-12-arm-20260901-0badcafe","node":"pve1","template":1,"tags":"ghostctl-image"}},
+               {{"type":"qemu","vmid":9002,"name":"debian-12-20260801-00c0ffee","node":"pve1","template":1,"tags":"ghostctl-image"}},
+               {{"type":"qemu","vmid":101,"name":"web","node":"pve1","status":"running"}}{extra}]"#
+        )
+    }
+
+    /// An image, its checksum file and signature served from a directory.
+    fn upstream(dir: &Path, content: &[u8]) -> CloudImage {
+        std::fs::write(dir.join("debian-12-genericcloud-amd64.qcow2"), content).unwrap();
+        let digest = hash_reader_sha512(content);
+        std::fs::write(
+            dir.join("SHA512SUMS"),
+            format!(
+                "{digest}  debian-12-genericcloud-amd64.qcow2\n{}  other.raw\n",
+                "0".repeat(128)
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.join("SHA512SUMS.sign"), "signature").unwrap();
+        let url = |f: &str| format!("file://{}/{f}", dir.display());
+        CloudImage {
+            name: "debian-12".to_string(),
+            url: url("debian-12-genericcloud-amd64.qcow2"),
+            checksum_url: Some(url("SHA512SUMS")),
+            checksum: None,
+            signature_url: Some(url("SHA512SUMS.sign")),
+            keyring: Some("/usr/share/keyrings/debian-archive-keyring.gpg".to_string()),
+            vmid_start: 9000,
+            storage: default_storage(),
+            bridge: default_bridge(),
+            memory: 1024,
+            cores: 1,
+            disk_size: Some("8G".to_string()),
+            keep: 2,
+            customize: Customize {
+                agent: true,
+                ..Customize::default()
+            },
+        }
+    }
+
+    fn hash_reader_sha512(content: &[u8]) -> String {
+        script_safety::hash_reader(HashAlgorithm::Sha512, content).unwrap()
+    }
+
+    fn node() -> MockRunner {
+        let runner = MockRunner::new();
+        runner.mock_command(
+            "gpgv",
+            &["--version"],
+            CommandResult::ok("gpgv (GnuPG) 2.2.40"),
+        );
+        runner.mock_command("virt-customize", &["--version"], CommandResult::ok("1.48"));
+        runner.mock_command("pvesh", &[], CommandResult::ok(&guests_json("")));
+        runner
+    }
+
+    #[test]
+    fn catalog_entries_are_validated() {
+        let image: CloudImage = toml::from_str(
+            r#"
+name = "debian-12"
+url = "https://cloud.debian.org/images/cloud/bookworm/latest/debian-12-genericcloud-amd64.qcow2"
+checksum_url = "https://cloud.debian.org/images/cloud/bookworm/latest/SHA512SUMS"
+customize = { agent = true, packages = ["htop"] }
+"#,
+        )
+        .unwrap();
+        assert!(image.validate().is_empty(), "{:?}", image.validate());
+        assert_eq!((image.vmid_start, image.keep), (9000, 2));
+        assert_eq!(
+            image.customize.args(),
+            [
+                "--install",
+                "htop,qemu-guest-agent",
+                "--run-command",
+                "systemctl enable qemu-guest-agent",
+                "--truncate",
+                "/etc/machine-id"
+            ]
+        );
+
+        let unverified = CloudImage {
+            checksum_url: None,
+            signature_url: Some("https://example.net/SUMS.gpg".to_string()),
+            ..image.clone()
+        };
+        let problems = unverified.validate().join("\n");
+        assert!(problems.contains("never used unverified"));
+        assert!(problems.contains("needs checksum_url"));
+        let pinned = CloudImage {
+            checksum_url: None,
+            checksum: Some(format!("sha256:{}", "a".repeat(64))),
+            ..image
+        };
+        assert!(pinned.validate().is_empty());
+        assert!(
+            CloudImage {
+                checksum: Some("md5:abc".to_string()),
+                ..pinned
+            }
+            .validate()[0]
+                .contains("sha256:HEX")
+        );
+    }
+
+    #[test]
+    fn versions_belong_to_one_image() {
+        let guests: Vec<Guest> = serde_json::from_str(&guests_json("")).unwrap();
+        let found = versions(&guests, "debian-12");
+        let ids: Vec<u32> = found.iter().map(|v| v.vmid).collect();
+        assert_eq!(ids, [9002, 9000], "oldest first, the arm image excluded");
+        assert_eq!(next_free_vmid(&guests, 9000).unwrap(), 9003);
+    }
+
+    #[test]
+    fn refresh_verifies_builds_and_prunes() {
+        let upstream_dir = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let image = upstream(upstream_dir.path(), b"qcow2 image bytes");
+        let runner = node();
+        let pipeline = Pipeline::with_cache(&runner, cache.path().to_path_buf()).unwrap();
+
+        let outcome = pipeline.refresh(&image, false, "20261019").unwrap();
+        let Outcome::Built {
+            vmid,
+            name,
+            removed,
+        } = outcome
+        else {
+            panic!("expected a build, got {outcome:?}");
+        };
+        assert_eq!(vmid, 9003);
+        assert!(name.starts_with("debian-12-20261019-"), "{name}");
+        assert_eq!(removed, [9002], "keeps 2 versions");
+
+        let history = runner.get_history();
+        let cached = cache
+            .path()
+            .join("debian-12/debian-12-genericcloud-amd64.qcow2");
+        assert_eq!(std::fs::read(&cached).unwrap(), b"qcow2 image bytes");
+        let gpgv = history
+            .iter()
+            .find(|h| h.starts_with("gpgv --keyring"))
+            .unwrap();
+        assert!(gpgv.contains("SHA512SUMS.sign"), "{gpgv}");
+        let customize = history
+            .iter()
+            .find(|h| h.starts_with("virt-customize -a"))
+            .unwrap();
+        assert!(customize.contains("debian-12-build.qcow2 --install qemu-guest-agent"));
+        let create = history
+            .iter()
+            .find(|h| h.starts_with("qm create 9003"))
+            .unwrap();
+        assert!(create.contains(&format!("--name {name}")));
+        assert!(create.contains("--scsi0 local-lvm:0,import-from="));
+        assert!(create.contains("--tags ghostctl-image"));
+        assert!(create.contains("--agent enabled=1"));
+        let order: Vec<&String> = history.iter().filter(|h| h.starts_with("qm ")).collect();
+        assert_eq!(order[1], "qm resize 9003 scsi0 8G");
+        assert_eq!(order[2], "qm template 9003");
+        assert_eq!(order[3], "qm destroy 9002");
+        assert!(
+            !cache
+                .path()
+                .join("debian-12/debian-12-build.qcow2")
+                .exists(),
+            "scratch copy removed"
+        );
+
+        // The new template is now current, and the cached image is reused.
+        let runner = node();
+        runner.mock_command(
+            "pvesh",
+            &[],
+            CommandResult::ok(&guests_json(&format!(
+                r#",{{"type":"qemu","vmid":9003,"name":"{name}","node":"pve1","template":1,"tags":"ghostctl-image"}}"#
+            ))),
+        );
+        let pipeline = Pipeline::with_cache(&runner, cache.path().to_path_buf()).unwrap();
+        assert_eq!(
+            pipeline.refresh(&image, false, "20261020").unwrap(),
+            Outcome::UpToDate {
+                vmid: 9003,
+                name: name.clone()
+            }
+        );
+        assert!(!runner.get_history().iter().any(|h| h.starts_with("qm ")));
+    }
+
+    #[test]
+    fn tampered_downloads_and_bad_signatures_are_refused() {
+        let upstream_dir = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let image = upstream(upstream_dir.path(), b"original");
+        std::fs::write(
+            upstream_dir
+                .path()
+                .join("debian-12-genericcloud-amd64.qcow2"),
+            b"tampered",
+        )
+        .unwrap();
+        let runner = node();
+        let pipeline = Pipeline::with_cache(&runner, cache.path().to_path_buf()).unwrap();
+        let err = pipeline.refresh(&image, true, "20261019").unwrap_err();
+        assert!(format!("{err:#}").contains("expected"), "{err:#}");
+        assert!(!runner.get_history().iter().any(|h| h.starts_with("qm ")));
+        assert!(
+            !cache
+                .path()
+                .join("debian-12/debian-12-genericcloud-amd64.qcow2")
+                .exists()
+        );
+
+        let runner = node();
+        runner.mock_command(
+            "gpgv --keyring",
+            &[],
+            CommandResult::err("gpgv: BAD signature from \"Debian Cloud Images\"", 1),
+        );
+        let pipeline = Pipeline::with_cache(&runner, cache.path().to_path_buf()).unwrap();
+        let err = pipeline.refresh(&image, true, "20261019").unwrap_err();
+        assert!(format!("{err:#}").contains("BAD signature"), "{err:#}");
+    }
+}
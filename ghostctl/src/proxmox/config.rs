@@ -0,0 +1,308 @@
+use super::cloud_images::CloudImage;
+use crate::support::SecretSource;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Remote Proxmox VE clusters and Backup Servers stored in config.toml
+/// under [pve].
+///
+/// ```toml
+/// [pve]
+/// default_cluster = "home"
+///
+/// [[pve.clusters]]
+/// name = "home"
+/// endpoints = ["https://pve1.lab:8006", "https://pve2.lab:8006"]
+/// token_id = "root@pam!ghostctl"
+/// token_secret = { credential = "pve-home" }
+/// fingerprint = "AB:CD:..."   # `pvenode cert info`, SHA-256
+///
+/// [[pve.backup_servers]]
+/// name = "pbs"
+/// endpoints = ["https://pbs.lab:8007"]
+/// token_id = "monitor@pbs!ghostctl"
+/// token_secret = { credential = "pbs" }
+///
+/// [[pve.images]]
+/// name = "debian-12"
+/// url = "https://cloud.debian.org/.../debian-12-genericcloud-amd64.qcow2"
+/// checksum_url = "https://cloud.debian.org/.../SHA512SUMS"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PveConfig {
+    /// Cluster used without `--cluster` when this host is not a PVE node.
+    #[serde(default)]
+    pub default_cluster: Option<String>,
+
+    #[serde(default)]
+    pub clusters: Vec<PveCluster>,
+
+    /// Backup server used by `ghostctl pbs` without `--server`.
+    #[serde(default)]
+    pub default_backup_server: Option<String>,
+
+    /// Proxmox Backup Servers, described like clusters (port 8007).
+    #[serde(default)]
+    pub backup_servers: Vec<PveCluster>,
+
+    /// Cloud images built into templates by `pve template refresh`.
+    #[serde(default)]
+    pub images: Vec<CloudImage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PveCluster {
+    pub name: String,
+
+    /// API URLs of the cluster's nodes, tried in order until one answers.
+    /// Port 8006 (8007 for backup servers) is assumed when missing.
+    pub endpoints: Vec<String>,
+
+    /// API token id, `USER@REALM!TOKENNAME`.
+    pub token_id: String,
+
+    pub token_secret: SecretSource,
+
+    /// SHA-256 fingerprint of the node certificate(s) to trust instead of
+    /// the CA store. Several can be given for clusters with per-node certs.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub fingerprint: Vec<String>,
+
+    /// Verify against the system CA store when no fingerprint is pinned.
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+
+    /// HTTP request timeout in seconds.
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+impl PveConfig {
+    pub fn load() -> Self {
+        crate::config::GhostConfig::load().pve.unwrap_or_default()
+    }
+
+    pub fn cluster(&self, name: &str) -> Result<&PveCluster> {
+        self.clusters
+            .iter()
+            .find(|c| c.name == name)
+            .with_context(|| {
+                let known: Vec<&str> = self.clusters.iter().map(|c| c.name.as_str()).collect();
+                if known.is_empty() {
+                    format!("no PVE cluster '{name}': add [[pve.clusters]] to config.toml")
+                } else {
+                    format!("no PVE cluster '{name}' (have: {})", known.join(", "))
+                }
+            })
+    }
+
+    pub fn backup_server(&self, name: &str) -> Result<&PveCluster> {
+        self.backup_servers
+            .iter()
+            .find(|c| c.name == name)
+            .with_context(|| {
+                let known: Vec<&str> = self
+                    .backup_servers
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect();
+                if known.is_empty() {
+                    format!("no backup server '{name}': add [[pve.backup_servers]] to config.toml")
+                } else {
+                    format!("no backup server '{name}' (have: {})", known.join(", "))
+                }
+            })
+    }
+
+    /// Problems that would keep a cluster or backup server from connecting.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        validate_entries("cluster", &self.clusters, &mut problems);
+        validate_entries("backup server", &self.backup_servers, &mut problems);
+        if let Some(default) = &self.default_cluster
+            && !self.clusters.iter().any(|c| c.name == *default)
+        {
+            problems.push(format!("default_cluster '{default}' is not defined"));
+        }
+        if let Some(default) = &self.default_backup_server
+            && !self.backup_servers.iter().any(|c| c.name == *default)
+        {
+            problems.push(format!("default_backup_server '{default}' is not defined"));
+        }
+        problems
+    }
+}
+
+fn validate_entries(label: &str, entries: &[PveCluster], problems: &mut Vec<String>) {
+    for (i, cluster) in entries.iter().enumerate() {
+        let name = &cluster.name;
+        if entries[..i].iter().any(|c| c.name == *name) {
+            problems.push(format!("{label} '{name}' is defined twice"));
+        }
+        if cluster.endpoints.is_empty() {
+            problems.push(format!("{label} '{name}' has no endpoints"));
+        }
+        for endpoint in &cluster.endpoints {
+            if !endpoint.starts_with("https://") {
+                problems.push(format!(
+                    "{label} '{name}': endpoint '{endpoint}' must be https://"
+                ));
+            }
+        }
+        let Some((user, token)) = cluster.token_id.split_once('!') else {
+            problems.push(format!(
+                "{label} '{name}': token_id must look like USER@REALM!TOKEN"
+            ));
+            continue;
+        };
+        if !user.contains('@') || token.is_empty() {
+            problems.push(format!(
+                "{label} '{name}': token_id must look like USER@REALM!TOKEN"
+            ));
+        }
+        for fingerprint in &cluster.fingerprint {
+            if crate::proxmox::api::normalize_fingerprint(fingerprint).is_none() {
+                problems.push(format!(
+                    "{label} '{name}': '{fingerprint}' is not a SHA-256 fingerprint"
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_parse_and_validate() {
+        let cfg: PveConfig = toml::from_str(
+            r#"
+default_cluster = "home"
+
+[[clusters]]
+name = "home"
+endpoints = ["https://pve1.lab:8006", "https://pve2.lab"]
+token_id = "root@pam!ghostctl"
+token_secret = { env = "PVE_HOME_TOKEN" }
+fingerprint = "ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89"
+
+[[clusters]]
+name = "colo"
+endpoints = ["https://colo.example.net:8006"]
+token_id = "ops@pve!cli"
+token_secret = { credential = "pve-colo" }
+"#,
+        )
+        .unwrap();
+        assert!(cfg.validate().is_empty(), "{:?}", cfg.validate());
+        let home = cfg.cluster("home").unwrap();
+        assert_eq!(home.fingerprint.len(), 1);
+        assert!(home.verify_tls);
+        assert_eq!(home.timeout_secs, 30);
+        assert!(cfg.cluster("colo").unwrap().fingerprint.is_empty());
+        assert!(
+            cfg.cluster("lab")
+                .unwrap_err()
+                .to_string()
+                .contains("have: home, colo")
+        );
+    }
+
+    #[test]
+    fn bad_clusters_are_reported() {
+        let cfg: PveConfig = toml::from_str(
+            r#"
+default_cluster = "gone"
+[[clusters]]
+name = "a"
+endpoints = ["http://pve1:8006"]
+token_id = "root@pam"
+token_secret = { env = "X" }
+[[clusters]]
+name = "a"
+endpoints = []
+token_id = "root!t"
+token_secret = { env = "X" }
+fingerprint = ["12:34"]
+"#,
+        )
+        .unwrap();
+        let problems = cfg.validate();
+        for expected in [
+            "must be https://",
+            "token_id must look like USER@REALM!TOKEN",
+            "cluster 'a' is defined twice",
+            "has no endpoints",
+            "'12:34' is not a SHA-256 fingerprint",
+            "default_cluster 'gone' is not defined",
+        ] {
+            assert!(
+                problems.iter().any(|p| p.contains(expected)),
+                "missing '{expected}' in {problems:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn backup_servers_parse_and_validate() {
+        let cfg: PveConfig = toml::from_str(
+            r#"
+default_backup_server = "offsite"
+
+[[backup_servers]]
+name = "pbs"
+endpoints = ["https://pbs.lab"]
+token_id = "monitor@pbs!ghostctl"
+token_secret = { env = "PBS_TOKEN" }
+
+[[backup_servers]]
+name = "pbs"
+endpoints = ["https://pbs2.lab:8007"]
+token_id = "monitor@pbs"
+token_secret = { env = "PBS_TOKEN" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.backup_server("pbs").unwrap().endpoints,
+            ["https://pbs.lab"]
+        );
+        assert!(cfg.clusters.is_empty());
+        let problems = cfg.validate();
+        for expected in [
+            "backup server 'pbs' is defined twice",
+            "backup server 'pbs': token_id must look like USER@REALM!TOKEN",
+            "default_backup_server 'offsite' is not defined",
+        ] {
+            assert!(
+                problems.iter().any(|p| p.contains(expected)),
+                "missing '{expected}' in {problems:?}"
+            );
+        }
+    }
+}
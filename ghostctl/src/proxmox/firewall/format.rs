@@ -0,0 +1,792 @@
+//! Reading and writing Proxmox VE firewall files.
+//!
+//! Files are rendered the way pve-firewall saves them itself: sections in a
+//! fixed order, options, aliases, IPSets (and their entries) and security
+//! groups sorted by name, rules in file order. Comment lines are dropped, as
+//! pve-firewall does, so a file it wrote parses and renders back unchanged.
+
+use crate::proxmox::advanced_security::{
+    Alias, Direction, FirewallRule, FirewallTemplate, IpSet, IpSetEntry, PolicyException,
+    PolicyScope, Protocol, RuleAction, RuleCondition, SecurityAction, SecurityPolicy,
+    SecurityRuleType, security_group_target,
+};
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeSet;
+
+/// The three kinds of firewall file and what each may contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// `/etc/pve/firewall/cluster.fw`: everything, including security groups.
+    Cluster,
+    /// `/etc/pve/nodes/<node>/host.fw`: options and rules only.
+    Host,
+    /// `/etc/pve/firewall/<vmid>.fw`: everything but security groups.
+    Guest,
+}
+
+impl FileKind {
+    pub fn of(scope: &PolicyScope) -> Self {
+        match scope {
+            PolicyScope::Cluster | PolicyScope::Datacenter => FileKind::Cluster,
+            PolicyScope::Node(_) => FileKind::Host,
+            PolicyScope::VM(_) | PolicyScope::Container(_) => FileKind::Guest,
+        }
+    }
+}
+
+/// Path of the file a scope is written to.
+pub fn path(scope: &PolicyScope) -> String {
+    match scope {
+        PolicyScope::Cluster | PolicyScope::Datacenter => "/etc/pve/firewall/cluster.fw".into(),
+        PolicyScope::Node(node) => format!("/etc/pve/nodes/{node}/host.fw"),
+        PolicyScope::VM(vmid) | PolicyScope::Container(vmid) => {
+            format!("/etc/pve/firewall/{vmid}.fw")
+        }
+    }
+}
+
+pub fn scope_label(scope: &PolicyScope) -> String {
+    match scope {
+        PolicyScope::Cluster | PolicyScope::Datacenter => "cluster".into(),
+        PolicyScope::Node(node) => format!("node {node}"),
+        PolicyScope::VM(vmid) => format!("VM {vmid}"),
+        PolicyScope::Container(vmid) => format!("CT {vmid}"),
+    }
+}
+
+/// A policy with nothing in it, named after its scope.
+pub fn empty_policy(scope: PolicyScope) -> SecurityPolicy {
+    SecurityPolicy {
+        name: scope_label(&scope),
+        description: String::new(),
+        rules: Vec::new(),
+        scope,
+        enforcement_level: Default::default(),
+        exceptions: Vec::new(),
+        options: Default::default(),
+        aliases: Vec::new(),
+        ipsets: Vec::new(),
+        groups: Vec::new(),
+        firewall_rules: Vec::new(),
+    }
+}
+
+fn rule(direction: Direction, action: RuleAction) -> FirewallRule {
+    FirewallRule {
+        name: String::new(),
+        action,
+        direction,
+        protocol: Protocol::All,
+        source: Default::default(),
+        destination: Default::default(),
+        ports: Default::default(),
+        logging: false,
+        rate_limit: None,
+        connection_tracking: false,
+        enabled: true,
+        interface: None,
+        icmp_type: None,
+        log_level: None,
+    }
+}
+
+enum Section {
+    None,
+    Options,
+    Aliases,
+    Rules,
+    IpSet(usize),
+    Group(usize),
+}
+
+/// `body # comment`, both trimmed.
+fn split_comment(line: &str) -> (&str, &str) {
+    match line.split_once('#') {
+        Some((body, comment)) => (body.trim(), comment.trim()),
+        None => (line.trim(), ""),
+    }
+}
+
+/// Parse a firewall file into a policy for `scope`.
+pub fn parse(text: &str, scope: PolicyScope) -> Result<SecurityPolicy> {
+    let mut policy = empty_policy(scope);
+    let mut section = Section::None;
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at = || format!("line {}: {line}", i + 1);
+        if line.starts_with('[') {
+            section = parse_header(line, &mut policy).with_context(at)?;
+            continue;
+        }
+        let (body, comment) = split_comment(line);
+        match section {
+            Section::None => bail!("{}: not inside a section", at()),
+            Section::Options => {
+                let (key, value) = body
+                    .split_once(':')
+                    .with_context(|| format!("{}: expected `key: value`", at()))?;
+                policy
+                    .options
+                    .insert(key.trim().to_string(), value.trim().to_string());
+            }
+            Section::Aliases => {
+                let mut words = body.split_whitespace();
+                let (Some(name), Some(cidr), None) = (words.next(), words.next(), words.next())
+                else {
+                    bail!("{}: expected `name cidr`", at());
+                };
+                policy.aliases.push(Alias {
+                    name: name.to_string(),
+                    cidr: cidr.to_string(),
+                    comment: comment.to_string(),
+                });
+            }
+            Section::IpSet(n) => {
+                let (nomatch, cidr) = match body.strip_prefix('!') {
+                    Some(cidr) => (true, cidr.trim()),
+                    None => (false, body),
+                };
+                if cidr.is_empty() || cidr.contains(char::is_whitespace) {
+                    bail!("{}: expected one address per line", at());
+                }
+                policy.ipsets[n].entries.push(IpSetEntry {
+                    cidr: cidr.to_string(),
+                    nomatch,
+                    comment: comment.to_string(),
+                });
+            }
+            Section::Rules => policy
+                .firewall_rules
+                .push(parse_rule(body, comment).with_context(at)?),
+            Section::Group(n) => policy.groups[n]
+                .rules
+                .push(parse_rule(body, comment).with_context(at)?),
+        }
+    }
+    normalize(&mut policy);
+    Ok(policy)
+}
+
+fn parse_header(line: &str, policy: &mut SecurityPolicy) -> Result<Section> {
+    let (header, comment) = split_comment(line);
+    let inner = header
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .context("unterminated section header")?;
+    let mut words = inner.split_whitespace();
+    let kind = words.next().unwrap_or_default().to_ascii_uppercase();
+    let name = words.next();
+    if words.next().is_some() {
+        bail!("unexpected text in section header");
+    }
+    Ok(match (kind.as_str(), name) {
+        ("OPTIONS", None) => Section::Options,
+        ("ALIASES", None) => Section::Aliases,
+        ("RULES", None) => Section::Rules,
+        ("IPSET", Some(name)) => {
+            policy.ipsets.push(IpSet {
+                name: name.to_string(),
+                comment: comment.to_string(),
+                entries: Vec::new(),
+            });
+            Section::IpSet(policy.ipsets.len() - 1)
+        }
+        ("GROUP", Some(name)) => {
+            policy.groups.push(FirewallTemplate {
+                name: name.to_string(),
+                description: comment.to_string(),
+                target_type: security_group_target(),
+                rules: Vec::new(),
+                variables: Default::default(),
+                prerequisites: Vec::new(),
+            });
+            Section::Group(policy.groups.len() - 1)
+        }
+        _ => bail!("unknown section"),
+    })
+}
+
+fn parse_protocol(proto: &str) -> Protocol {
+    match proto.to_ascii_lowercase().as_str() {
+        "tcp" => Protocol::TCP,
+        "udp" => Protocol::UDP,
+        "icmp" => Protocol::ICMP,
+        "esp" => Protocol::ESP,
+        "ah" => Protocol::AH,
+        other => other
+            .parse()
+            .map(Protocol::Custom)
+            .unwrap_or_else(|_| Protocol::Other(other.to_string())),
+    }
+}
+
+fn parse_verdict(verdict: &str) -> Result<RuleAction> {
+    Ok(match verdict.to_ascii_uppercase().as_str() {
+        "ACCEPT" => RuleAction::Accept,
+        "DROP" => RuleAction::Drop,
+        "REJECT" => RuleAction::Reject,
+        _ => bail!("unknown action {verdict:?}"),
+    })
+}
+
+/// `[|]IN|OUT|FORWARD ACTION|MACRO(ACTION) [-option value]...` or
+/// `[|]GROUP name [-i iface]`.
+fn parse_rule(body: &str, comment: &str) -> Result<FirewallRule> {
+    let (enabled, body) = match body.strip_prefix('|') {
+        Some(rest) => (false, rest.trim_start()),
+        None => (true, body),
+    };
+    let mut words = body.split_whitespace();
+    let kind = words.next().context("empty rule")?.to_ascii_uppercase();
+    let target = words
+        .next()
+        .with_context(|| format!("{kind} without an action"))?;
+    let mut rule = match kind.as_str() {
+        "GROUP" => rule(Direction::Input, RuleAction::Group(target.to_string())),
+        "IN" | "OUT" | "FORWARD" => {
+            let direction = match kind.as_str() {
+                "IN" => Direction::Input,
+                "OUT" => Direction::Output,
+                _ => Direction::Forward,
+            };
+            match target.split_once('(') {
+                Some((name, verdict)) => {
+                    let verdict = verdict
+                        .strip_suffix(')')
+                        .with_context(|| format!("unterminated macro {target:?}"))?;
+                    let mut rule = rule(direction, parse_verdict(verdict)?);
+                    rule.ports.named_services.push(name.to_string());
+                    rule
+                }
+                None => rule(direction, parse_verdict(target)?),
+            }
+        }
+        _ => bail!("unknown rule type {kind:?}"),
+    };
+    rule.enabled = enabled;
+    rule.name = comment.to_string();
+    while let Some(option) = words.next() {
+        let value = words
+            .next()
+            .with_context(|| format!("{option} needs a value"))?
+            .to_string();
+        if kind == "GROUP" && option != "-i" {
+            bail!("GROUP only takes -i, not {option}");
+        }
+        match option {
+            "-i" => rule.interface = Some(value),
+            "-source" => rule.source.address = value,
+            "-dest" => rule.destination.address = value,
+            "-p" => rule.protocol = parse_protocol(&value),
+            "-dport" => rule.ports.ports = value,
+            "-sport" => rule.ports.source_ports = value,
+            "-icmp-type" => rule.icmp_type = Some(value),
+            "-log" => {
+                rule.logging = value != "nolog";
+                rule.log_level = Some(value);
+            }
+            _ => bail!("unknown rule option {option}"),
+        }
+    }
+    Ok(rule)
+}
+
+/// Sort what pve-firewall sorts and drop duplicate IPSet entries, keeping
+/// the last, as it does.
+fn normalize(policy: &mut SecurityPolicy) {
+    policy.aliases.sort_by(|a, b| a.name.cmp(&b.name));
+    policy.ipsets.sort_by(|a, b| a.name.cmp(&b.name));
+    for ipset in &mut policy.ipsets {
+        ipset.entries.reverse();
+        let mut seen = BTreeSet::new();
+        ipset.entries.retain(|e| seen.insert(e.cidr.clone()));
+        ipset.entries.sort_by(|a, b| a.cidr.cmp(&b.cidr));
+    }
+    policy.groups.sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+/// Names of the policy's exceptions that expired before `today`.
+pub fn expired<'p>(policy: &'p SecurityPolicy, today: &str) -> Vec<&'p str> {
+    policy
+        .exceptions
+        .iter()
+        .filter(|e| e.expiry_date.as_deref().is_some_and(|d| d < today))
+        .map(|e| e.name.as_str())
+        .collect()
+}
+
+/// A network condition as an accepting or dropping rule.
+fn condition_rule(condition: &RuleCondition, action: RuleAction) -> Result<FirewallRule> {
+    if condition.time_window.is_some() || condition.resource_threshold.is_some() {
+        bail!("time windows and resource thresholds cannot be written to a .fw file");
+    }
+    let mut rule = rule(Direction::Input, action);
+    if let Some(source) = &condition.source {
+        rule.source.address = source.clone();
+    }
+    if let Some(destination) = &condition.destination {
+        rule.destination.address = destination.clone();
+    }
+    if let Some(protocol) = &condition.protocol {
+        rule.protocol = parse_protocol(protocol);
+    }
+    if let Some(ports) = &condition.port_range {
+        rule.ports.ports = ports.clone();
+    }
+    Ok(rule)
+}
+
+fn exception_rule(exception: &PolicyException) -> Result<FirewallRule> {
+    let mut rule = condition_rule(&exception.condition, RuleAction::Accept)
+        .with_context(|| format!("exception {}", exception.name))?;
+    rule.name = format!(
+        "exception {}: {} (approved by {}{})",
+        exception.name,
+        exception.justification,
+        exception.approved_by,
+        exception
+            .expiry_date
+            .as_deref()
+            .map(|d| format!(", until {d}"))
+            .unwrap_or_default()
+    );
+    Ok(rule)
+}
+
+/// Everything that goes in `[RULES]`: unexpired exceptions first, then the
+/// network-access `rules` by descending priority, then `firewall_rules`.
+pub fn rules(policy: &SecurityPolicy, today: &str) -> Result<Vec<FirewallRule>> {
+    let expired = expired(policy, today);
+    let mut out = Vec::new();
+    for exception in &policy.exceptions {
+        if let Some(date) = &exception.expiry_date
+            && chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err()
+        {
+            bail!(
+                "exception {}: expiry_date {date:?} is not YYYY-MM-DD",
+                exception.name
+            );
+        }
+        if !expired.contains(&exception.name.as_str()) {
+            out.push(exception_rule(exception)?);
+        }
+    }
+    let mut access: Vec<_> = policy.rules.iter().collect();
+    access.sort_by_key(|r| std::cmp::Reverse(r.priority));
+    for security_rule in access {
+        if !matches!(security_rule.rule_type, SecurityRuleType::NetworkAccess) {
+            bail!(
+                "{:?} rules cannot be written to a .fw file",
+                security_rule.rule_type
+            );
+        }
+        let action = match security_rule.action {
+            SecurityAction::Allow => RuleAction::Accept,
+            SecurityAction::Deny => RuleAction::Drop,
+            ref other => bail!("{other:?} cannot be written to a .fw file"),
+        };
+        let mut rule = condition_rule(&security_rule.condition, action)?;
+        rule.enabled = security_rule.enabled;
+        out.push(rule);
+    }
+    out.extend(policy.firewall_rules.iter().cloned());
+    Ok(out)
+}
+
+/// PVE's rule for alias, IPSet and group names.
+fn check_name(what: &str, name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.len() >= 2
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("{what} name {name:?} must start with a letter and use letters, digits, - and _");
+    }
+    Ok(())
+}
+
+/// A value written as one word on a line.
+fn check_word(what: &str, value: &str) -> Result<()> {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '#') {
+        bail!("{what} {value:?} must be one word without #");
+    }
+    Ok(())
+}
+
+fn check_comment(comment: &str) -> Result<()> {
+    if comment.contains(['\n', '\r']) {
+        bail!("comment {comment:?} must be one line");
+    }
+    Ok(())
+}
+
+fn check_contents(policy: &SecurityPolicy) -> Result<()> {
+    let kind = FileKind::of(&policy.scope);
+    if kind == FileKind::Host && !(policy.aliases.is_empty() && policy.ipsets.is_empty()) {
+        bail!("host.fw only holds options and rules; define aliases and IPSets at cluster scope");
+    }
+    if kind != FileKind::Cluster && !policy.groups.is_empty() {
+        bail!("security groups can only be defined at cluster scope");
+    }
+    for (key, value) in &policy.options {
+        check_word("option", key)?;
+        check_word("option value", value)?;
+    }
+    let mut names = BTreeSet::new();
+    for alias in &policy.aliases {
+        check_name("alias", &alias.name)?;
+        check_word("alias address", &alias.cidr)?;
+        check_comment(&alias.comment)?;
+        if !names.insert(alias.name.to_ascii_lowercase()) {
+            bail!("alias {} is defined twice", alias.name);
+        }
+    }
+    names.clear();
+    for ipset in &policy.ipsets {
+        check_name("IPSet", &ipset.name)?;
+        check_comment(&ipset.comment)?;
+        if !names.insert(ipset.name.to_ascii_lowercase()) {
+            bail!("IPSet {} is defined twice", ipset.name);
+        }
+        for entry in &ipset.entries {
+            check_word("IPSet entry", &entry.cidr)?;
+            check_comment(&entry.comment)?;
+        }
+    }
+    names.clear();
+    for group in &policy.groups {
+        check_name("security group", &group.name)?;
+        check_comment(&group.description)?;
+        if !names.insert(group.name.to_ascii_lowercase()) {
+            bail!("security group {} is defined twice", group.name);
+        }
+        if !group.variables.is_empty() || !group.prerequisites.is_empty() {
+            bail!(
+                "security group {}: template variables and prerequisites cannot be written to a .fw file",
+                group.name
+            );
+        }
+    }
+    Ok(())
+}
+
+fn verdict(action: &RuleAction) -> Result<&'static str> {
+    Ok(match action {
+        RuleAction::Accept => "ACCEPT",
+        RuleAction::Drop => "DROP",
+        RuleAction::Reject => "REJECT",
+        other => bail!("{other:?} is not a PVE firewall action"),
+    })
+}
+
+fn protocol(protocol: &Protocol) -> Option<String> {
+    Some(match protocol {
+        Protocol::All => return None,
+        Protocol::TCP => "tcp".into(),
+        Protocol::UDP => "udp".into(),
+        Protocol::ICMP => "icmp".into(),
+        Protocol::ESP => "esp".into(),
+        Protocol::AH => "ah".into(),
+        Protocol::Custom(number) => number.to_string(),
+        Protocol::Other(name) => name.clone(),
+    })
+}
+
+/// One rule line, options in the order pve-firewall writes them.
+fn render_rule(rule: &FirewallRule, in_group: bool) -> Result<String> {
+    if rule.rate_limit.is_some() {
+        bail!("the PVE firewall has no per-rule rate limits");
+    }
+    if rule.connection_tracking {
+        bail!("the PVE firewall has no per-rule connection tracking switch");
+    }
+    for address in [&rule.source, &rule.destination] {
+        if !address.exclude.is_empty() || address.geo_restriction.is_some() {
+            bail!("the PVE firewall cannot exclude addresses or countries in a rule; use an IPSet");
+        }
+    }
+    check_comment(&rule.name)?;
+    let mut line = String::new();
+    if !rule.enabled {
+        line.push('|');
+    }
+    if let RuleAction::Group(group) = &rule.action {
+        if in_group {
+            bail!("security groups cannot include other groups");
+        }
+        check_word("group", group)?;
+        line.push_str(&format!("GROUP {group}"));
+        if let Some(interface) = &rule.interface {
+            check_word("interface", interface)?;
+            line.push_str(&format!(" -i {interface}"));
+        }
+    } else {
+        line.push_str(match rule.direction {
+            Direction::Input => "IN",
+            Direction::Output => "OUT",
+            Direction::Forward => "FORWARD",
+        });
+        let verdict = verdict(&rule.action)?;
+        match rule.ports.named_services.as_slice() {
+            [] => line.push_str(&format!(" {verdict}")),
+            [name] => {
+                check_word("macro", name)?;
+                line.push_str(&format!(" {name}({verdict})"));
+            }
+            _ => bail!("a rule takes at most one macro"),
+        }
+        let log_level = rule
+            .log_level
+            .clone()
+            .or_else(|| rule.logging.then(|| "info".to_string()));
+        let options = [
+            ("-i", rule.interface.clone()),
+            ("-source", Some(rule.source.address.clone())),
+            ("-dest", Some(rule.destination.address.clone())),
+            ("-p", protocol(&rule.protocol)),
+            ("-dport", Some(rule.ports.ports.clone())),
+            ("-sport", Some(rule.ports.source_ports.clone())),
+            ("-log", log_level),
+            ("-icmp-type", rule.icmp_type.clone()),
+        ];
+        for (option, value) in options {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                check_word(option, &value)?;
+                line.push_str(&format!(" {option} {value}"));
+            }
+        }
+    }
+    if !rule.name.trim().is_empty() {
+        line.push_str(&format!(" # {}", rule.name.trim()));
+    }
+    line.push('\n');
+    Ok(line)
+}
+
+fn with_comment(text: &str, comment: &str) -> String {
+    if comment.trim().is_empty() {
+        text.to_string()
+    } else {
+        format!("{text} # {}", comment.trim())
+    }
+}
+
+/// Render a policy as its firewall file. Exceptions that expired before
+/// `today` (`YYYY-MM-DD`) are left out.
+pub fn render(policy: &SecurityPolicy, today: &str) -> Result<String> {
+    check_contents(policy)?;
+    let mut policy = policy.clone();
+    normalize(&mut policy);
+    let mut out = String::new();
+    if !policy.options.is_empty() {
+        out.push_str("[OPTIONS]\n\n");
+        for (key, value) in &policy.options {
+            out.push_str(&format!("{key}: {value}\n"));
+        }
+        out.push('\n');
+    }
+    if !policy.aliases.is_empty() {
+        out.push_str("[ALIASES]\n\n");
+        for alias in &policy.aliases {
+            let line = format!("{} {}", alias.name, alias.cidr);
+            out.push_str(&with_comment(&line, &alias.comment));
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    for ipset in &policy.ipsets {
+        let header = format!("[IPSET {}]", ipset.name);
+        out.push_str(&with_comment(&header, &ipset.comment));
+        out.push_str("\n\n");
+        for entry in &ipset.entries {
+            let cidr = format!("{}{}", if entry.nomatch { "!" } else { "" }, entry.cidr);
+            out.push_str(&with_comment(&cidr, &entry.comment));
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    let rules = rules(&policy, today)?;
+    if !rules.is_empty() {
+        out.push_str("[RULES]\n\n");
+        for rule in &rules {
+            out.push_str(&render_rule(rule, false)?);
+        }
+        out.push('\n');
+    }
+    for group in &policy.groups {
+        let header = format!("[group {}]", group.name);
+        out.push_str(&with_comment(&header, &group.description));
+        out.push_str("\n\n");
+        for rule in &group.rules {
+            out.push_str(
+                &render_rule(rule, true).with_context(|| format!("group {}", group.name))?,
+            );
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLUSTER: &str = include_str!("../testdata/firewall/cluster.fw");
+    const HOST: &str = include_str!("../testdata/firewall/host.fw");
+    const GUEST: &str = include_str!("../testdata/firewall/101.fw");
+    const TODAY: &str = "2026-10-19";
+
+    #[test]
+    fn real_files_round_trip() {
+        for (text, scope) in [
+            (CLUSTER, PolicyScope::Cluster),
+            (HOST, PolicyScope::Node("pve1".into())),
+            (GUEST, PolicyScope::VM(101)),
+        ] {
+            let policy = parse(text, scope).unwrap();
+            assert_eq!(render(&policy, TODAY).unwrap(), text);
+
+            // And through the desired-state representation.
+            let toml = toml::to_string(&policy).unwrap();
+            let back: SecurityPolicy = toml::from_str(&toml).unwrap();
+            assert_eq!(render(&back, TODAY).unwrap(), text, "{toml}");
+        }
+    }
+
+    #[test]
+    fn parses_sections_into_policy_types() {
+        let policy = parse(CLUSTER, PolicyScope::Cluster).unwrap();
+        assert_eq!(policy.options["policy_in"], "DROP");
+        assert_eq!(policy.aliases[1].name, "mgmt_net");
+        assert_eq!(policy.aliases[1].comment, "Management VLAN");
+
+        let management = &policy.ipsets[1];
+        assert_eq!(management.name, "management");
+        assert!(management.entries[1].nomatch);
+        assert_eq!(management.entries[1].cidr, "10.10.0.99");
+        assert_eq!(policy.ipsets[0].comment, "abusive scanners");
+
+        let rules = &policy.firewall_rules;
+        assert_eq!(rules[0].action, RuleAction::Group("proxmox".into()));
+        assert_eq!(rules[0].interface.as_deref(), Some("vmbr0"));
+        assert_eq!(rules[1].action, RuleAction::Drop);
+        assert!(rules[1].logging);
+        assert_eq!(rules[3].ports.named_services, ["Ping"]);
+        assert!(!rules[3].logging);
+        assert!(!rules[4].enabled);
+        assert_eq!(rules[4].name, "emergency only");
+        assert_eq!(rules[5].direction, Direction::Output);
+        assert_eq!(rules[5].action, RuleAction::Reject);
+        assert_eq!(rules[6].protocol, Protocol::Other("ipv6-icmp".into()));
+
+        let proxmox = &policy.groups[0];
+        assert_eq!(proxmox.description, "web UI, SSH and migration");
+        assert_eq!(proxmox.target_type, security_group_target());
+        assert_eq!(proxmox.rules[3].ports.source_ports, "1024:65535");
+
+        let host = parse(HOST, PolicyScope::Node("pve1".into())).unwrap();
+        assert_eq!(host.firewall_rules[1].protocol, Protocol::Custom(112));
+    }
+
+    #[test]
+    fn comment_lines_and_case_are_normalized() {
+        let text =
+            "# managed by hand\n[options]\nenable:1\n\n[rules]\nin accept -p tcp -dport 22\n";
+        let policy = parse(text, PolicyScope::VM(100)).unwrap();
+        assert_eq!(
+            render(&policy, TODAY).unwrap(),
+            "[OPTIONS]\n\nenable: 1\n\n[RULES]\n\nIN ACCEPT -p tcp -dport 22\n\n"
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let err = parse("[RULES]\nIN ACCEPT -port 22\n", PolicyScope::Cluster).unwrap_err();
+        assert!(format!("{err:#}").contains("line 2"), "{err:#}");
+        assert!(format!("{err:#}").contains("-port"), "{err:#}");
+
+        let err = parse("enable: 1\n", PolicyScope::Cluster).unwrap_err();
+        assert!(format!("{err:#}").contains("not inside a section"));
+
+        let err = parse("[RULES]\nGROUP web -p tcp\n", PolicyScope::Cluster).unwrap_err();
+        assert!(format!("{err:#}").contains("GROUP only takes -i"));
+    }
+
+    #[test]
+    fn render_rejects_what_pve_cannot_express() {
+        let host = parse(CLUSTER, PolicyScope::Cluster).map(|mut p| {
+            p.scope = PolicyScope::Node("pve1".into());
+            p
+        });
+        let err = render(&host.unwrap(), TODAY).unwrap_err();
+        assert!(err.to_string().contains("host.fw"));
+
+        let mut guest = parse(GUEST, PolicyScope::VM(101)).unwrap();
+        guest.firewall_rules[1].action = RuleAction::Queue(1);
+        assert!(render(&guest, TODAY).is_err());
+
+        let mut guest = parse(GUEST, PolicyScope::VM(101)).unwrap();
+        guest.firewall_rules[1].source.address = "10.0.0.1 -dport 1".into();
+        assert!(render(&guest, TODAY).is_err());
+
+        let mut guest = parse(GUEST, PolicyScope::VM(101)).unwrap();
+        guest.ipsets[0].name = "]bad".into();
+        assert!(render(&guest, TODAY).is_err());
+
+        let mut guest = parse(GUEST, PolicyScope::VM(101)).unwrap();
+        guest.firewall_rules[1].connection_tracking = true;
+        let err = render(&guest, TODAY).unwrap_err();
+        assert!(err.to_string().contains("connection tracking"), "{err}");
+
+        let mut cluster = parse(CLUSTER, PolicyScope::Cluster).unwrap();
+        cluster.groups[0]
+            .variables
+            .insert("port".into(), "8006".into());
+        let err = render(&cluster, TODAY).unwrap_err();
+        assert!(err.to_string().contains("template variables"), "{err}");
+
+        let mut cluster = parse(CLUSTER, PolicyScope::Cluster).unwrap();
+        cluster.groups[0].prerequisites.push("ceph".into());
+        assert!(render(&cluster, TODAY).is_err());
+    }
+
+    #[test]
+    fn exceptions_render_first_until_they_expire() {
+        let mut policy = empty_policy(PolicyScope::Cluster);
+        policy.firewall_rules = parse("[RULES]\nIN DROP\n", PolicyScope::Cluster)
+            .unwrap()
+            .firewall_rules;
+        for (name, expiry) in [("vendor", "2026-11-30"), ("audit", "2026-01-31")] {
+            policy.exceptions.push(PolicyException {
+                name: name.into(),
+                condition: RuleCondition {
+                    source: Some("203.0.113.7".into()),
+                    protocol: Some("tcp".into()),
+                    port_range: Some("22".into()),
+                    ..Default::default()
+                },
+                justification: "support".into(),
+                expiry_date: Some(expiry.into()),
+                approved_by: "ops".into(),
+            });
+        }
+        assert_eq!(expired(&policy, TODAY), ["audit"]);
+        assert_eq!(
+            render(&policy, TODAY).unwrap(),
+            "[RULES]\n\n\
+             IN ACCEPT -source 203.0.113.7 -p tcp -dport 22 \
+             # exception vendor: support (approved by ops, until 2026-11-30)\n\
+             IN DROP\n\n"
+        );
+        assert_eq!(
+            render(&policy, "2026-12-01").unwrap(),
+            "[RULES]\n\nIN DROP\n\n"
+        );
+
+        policy.exceptions[0].expiry_date = Some("30.11.2026".into());
+        assert!(render(&policy, TODAY).is_err());
+    }
+}
@@ -0,0 +1,761 @@
+//! `pve firewall`: the Proxmox VE firewall as code.
+//!
+//! A desired-state file lists `SecurityPolicy`s, one per scope: the cluster
+//! (`/etc/pve/firewall/cluster.fw`), a node (`<node>/host.fw`) or a guest
+//! (`<vmid>.fw`). Plan renders each policy and compares it with the live file
+//! after rendering that the same way, so only real changes show. Apply copies
+//! every file it is about to replace to `<state dir>/pve-firewall/backups/`
+//! first; `restore` puts a backup back. Files for scopes the desired state
+//! does not list are left alone.
+
+pub mod format;
+
+use crate::command::CommandRunner;
+use crate::proxmox::advanced_security::{EnforcementLevel, PolicyScope, SecurityPolicy};
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+const FIREWALL_DIR: &str = "/etc/pve/firewall";
+const NODES_DIR: &str = "/etc/pve/nodes";
+/// Cluster-wide guest list, used to tell containers from VMs on export.
+const VMLIST: &str = "/etc/pve/.vmlist";
+
+/// The desired-state file (`firewall.toml`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FirewallFile {
+    #[serde(default)]
+    pub policies: Vec<SecurityPolicy>,
+}
+
+impl FirewallFile {
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: FirewallFile = toml::from_str(text)?;
+        let mut paths = BTreeSet::new();
+        for policy in &file.policies {
+            if let PolicyScope::Node(node) = &policy.scope
+                && (node.is_empty() || node.contains(['/', '.']))
+            {
+                bail!("policy {}: invalid node name {node:?}", policy.name);
+            }
+            if !paths.insert(format::path(&policy.scope)) {
+                bail!(
+                    "policy {}: another policy already covers {}",
+                    policy.name,
+                    format::path(&policy.scope)
+                );
+            }
+        }
+        Ok(file)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    Create,
+    Update,
+    Unchanged,
+}
+
+/// What apply would do to one file.
+#[derive(Debug, Serialize)]
+pub struct FilePlan {
+    pub policy: String,
+    pub path: String,
+    pub action: FileAction,
+    /// Advisory policies are planned but never written.
+    pub advisory: bool,
+    /// Exceptions left out because they expired.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expired: Vec<String>,
+    /// The live file already has these rules; only comments, order or
+    /// formatting differ.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub normalization_only: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub diff: String,
+    #[serde(skip)]
+    content: String,
+}
+
+impl FilePlan {
+    fn writes(&self) -> bool {
+        self.action != FileAction::Unchanged && !self.advisory
+    }
+}
+
+/// One file as it was before apply or restore replaced it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Saved {
+    path: String,
+    existed: bool,
+}
+
+pub struct Firewall<'a> {
+    runner: &'a dyn CommandRunner,
+    dir: PathBuf,
+    /// `YYYY-MM-DD`, for exception expiry.
+    today: String,
+}
+
+impl<'a> Firewall<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self::with_dir(
+            runner,
+            crate::support::state_dir().join("pve-firewall"),
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        )
+    }
+
+    pub fn with_dir(runner: &'a dyn CommandRunner, dir: PathBuf, today: &str) -> Self {
+        Self {
+            runner,
+            dir,
+            today: today.to_string(),
+        }
+    }
+
+    fn backups_dir(&self) -> PathBuf {
+        self.dir.join("backups")
+    }
+
+    fn live(&self, path: &str) -> Result<Option<String>> {
+        if !self.runner.file_exists(path) {
+            return Ok(None);
+        }
+        self.runner
+            .read_file(path)
+            .map(Some)
+            .with_context(|| format!("failed to read {path}"))
+    }
+
+    pub fn plan(&self, file: &FirewallFile) -> Result<Vec<FilePlan>> {
+        file.policies
+            .iter()
+            .map(|policy| {
+                self.plan_policy(policy)
+                    .with_context(|| format!("policy {}", policy.name))
+            })
+            .collect()
+    }
+
+    fn plan_policy(&self, policy: &SecurityPolicy) -> Result<FilePlan> {
+        let path = format::path(&policy.scope);
+        let content = format::render(policy, &self.today)?;
+        // The diff is against the file as it is, so comments and manual
+        // formatting that apply would drop show up too.
+        let current = self.live(&path)?;
+        let normalization_only = match &current {
+            Some(text) if *text != content => {
+                let live = format::parse(text, policy.scope.clone())
+                    .with_context(|| format!("failed to parse {path}"))?;
+                format::render(&live, &self.today)? == content
+            }
+            _ => false,
+        };
+        let action = match &current {
+            None if content.is_empty() => FileAction::Unchanged,
+            None => FileAction::Create,
+            Some(current) if *current == content => FileAction::Unchanged,
+            Some(_) => FileAction::Update,
+        };
+        let diff = if action == FileAction::Unchanged {
+            String::new()
+        } else {
+            super::script_registry::unified_diff(
+                current.as_deref().unwrap_or_default(),
+                &content,
+                &path,
+                &path,
+            )
+        };
+        Ok(FilePlan {
+            policy: policy.name.clone(),
+            path,
+            action,
+            advisory: policy.enforcement_level == EnforcementLevel::Advisory,
+            expired: format::expired(policy, &self.today)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            normalization_only,
+            diff,
+            content,
+        })
+    }
+
+    /// Write every planned change, backing the old files up first. Returns
+    /// the backup's directory, or `None` when there was nothing to write.
+    pub fn apply(&self, plans: &[FilePlan]) -> Result<Option<PathBuf>> {
+        let writes: Vec<(String, String)> = plans
+            .iter()
+            .filter(|p| p.writes())
+            .map(|p| (p.path.clone(), p.content.clone()))
+            .collect();
+        if writes.is_empty() {
+            return Ok(None);
+        }
+        self.write_with_backup(&writes).map(Some)
+    }
+
+    fn write_with_backup(&self, writes: &[(String, String)]) -> Result<PathBuf> {
+        let backup = self.new_backup_dir()?;
+        let mut saved = Vec::new();
+        for (path, _) in writes {
+            let existed = match self.live(path)? {
+                Some(text) => {
+                    let copy = backup.join(path.trim_start_matches('/'));
+                    if let Some(parent) = copy.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&copy, text)
+                        .with_context(|| format!("failed to write {}", copy.display()))?;
+                    true
+                }
+                None => false,
+            };
+            saved.push(Saved {
+                path: path.clone(),
+                existed,
+            });
+        }
+        std::fs::write(
+            backup.join("manifest.json"),
+            serde_json::to_string_pretty(&saved)?,
+        )?;
+        for (path, content) in writes {
+            self.runner
+                .write_file(path, content)
+                .with_context(|| format!("failed to write {path}"))?;
+        }
+        Ok(backup)
+    }
+
+    fn new_backup_dir(&self) -> Result<PathBuf> {
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let root = self.backups_dir();
+        let mut dir = root.join(&stamp);
+        let mut n = 1;
+        while dir.exists() {
+            n += 1;
+            dir = root.join(format!("{stamp}-{n}"));
+        }
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(dir)
+    }
+
+    /// Backup names, oldest first, with the files each holds.
+    pub fn backups(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let root = self.backups_dir();
+        let entries = match std::fs::read_dir(&root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", root.display())),
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().join("manifest.json").exists())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                let files = self.manifest(&name)?.into_iter().map(|s| s.path).collect();
+                Ok((name, files))
+            })
+            .collect()
+    }
+
+    fn manifest(&self, name: &str) -> Result<Vec<Saved>> {
+        let path = self.backups_dir().join(name).join("manifest.json");
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("no backup named {name} ({})", path.display()))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Put the files of a backup back, emptying those that did not exist
+    /// then. The files replaced are themselves backed up first.
+    pub fn restore(&self, name: &str) -> Result<PathBuf> {
+        if name.contains(['/', '\\']) || name.starts_with('.') {
+            bail!("invalid backup name {name:?}");
+        }
+        let dir = self.backups_dir().join(name);
+        let mut writes = Vec::new();
+        for saved in self.manifest(name)? {
+            let content = if saved.existed {
+                let copy = dir.join(saved.path.trim_start_matches('/'));
+                std::fs::read_to_string(&copy)
+                    .with_context(|| format!("failed to read {}", copy.display()))?
+            } else {
+                String::new()
+            };
+            writes.push((saved.path, content));
+        }
+        self.write_with_backup(&writes)
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        self.runner
+            .run("ls", &["-1", dir])
+            .ok()
+            .filter(|r| r.success)
+            .map(|r| r.stdout.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// The live firewall as a desired-state file: the cluster, every node
+    /// with a host.fw and every guest with a .fw file.
+    pub fn export(&self) -> Result<FirewallFile> {
+        let containers: BTreeSet<u32> = self
+            .live(VMLIST)?
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|list| list.get("ids").and_then(|ids| ids.as_object()).cloned())
+            .map(|ids| {
+                ids.iter()
+                    .filter(|(_, v)| v.get("type").and_then(|t| t.as_str()) == Some("lxc"))
+                    .filter_map(|(id, _)| id.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut scopes = vec![PolicyScope::Cluster];
+        scopes.extend(self.list(NODES_DIR).into_iter().map(PolicyScope::Node));
+        let mut vmids: Vec<u32> = self
+            .list(FIREWALL_DIR)
+            .iter()
+            .filter_map(|f| f.strip_suffix(".fw")?.parse().ok())
+            .collect();
+        vmids.sort_unstable();
+        scopes.extend(vmids.into_iter().map(|vmid| {
+            if containers.contains(&vmid) {
+                PolicyScope::Container(vmid)
+            } else {
+                PolicyScope::VM(vmid)
+            }
+        }));
+
+        let mut file = FirewallFile::default();
+        for scope in scopes {
+            let path = format::path(&scope);
+            if let Some(text) = self.live(&path)? {
+                let policy = format::parse(&text, scope)
+                    .with_context(|| format!("failed to parse {path}"))?;
+                file.policies.push(policy);
+            }
+        }
+        Ok(file)
+    }
+}
+
+pub fn print_plan(plans: &[FilePlan]) {
+    for plan in plans {
+        let (symbol, what) = match plan.action {
+            FileAction::Create => ("+", "create"),
+            FileAction::Update => ("~", "update"),
+            FileAction::Unchanged => ("=", "unchanged"),
+        };
+        let advisory = if plan.advisory && plan.action != FileAction::Unchanged {
+            " (advisory, not applied)"
+        } else {
+            ""
+        };
+        println!("{symbol} {} [{}]: {what}{advisory}", plan.path, plan.policy);
+        for name in &plan.expired {
+            tui::warn(&format!("  exception {name} has expired and is left out"));
+        }
+        if plan.normalization_only {
+            tui::info("  same rules; only comments and formatting would change");
+        }
+        if !plan.diff.is_empty() {
+            for line in plan.diff.lines() {
+                println!("    {line}");
+            }
+        }
+    }
+    let writes = plans.iter().filter(|p| p.writes()).count();
+    println!(
+        "\n{writes} to write, {} unchanged",
+        plans
+            .iter()
+            .filter(|p| p.action == FileAction::Unchanged)
+            .count()
+    );
+}
+
+fn apply_file(firewall: &Firewall, file: &FirewallFile) -> Result<()> {
+    let plans = firewall.plan(file)?;
+    print_plan(&plans);
+    let writes = plans.iter().filter(|p| p.writes()).count();
+    if writes == 0 {
+        tui::success("Firewall files already match");
+        return Ok(());
+    }
+    if is_dry_run() {
+        tui::info("Dry run: nothing written");
+        return Ok(());
+    }
+    if !tui::confirm_dangerous(&format!("Write {writes} firewall file(s)?")) {
+        bail!("aborted");
+    }
+    if let Some(backup) = firewall.apply(&plans)? {
+        tui::success(&format!(
+            "Wrote {writes} file(s); previous versions saved in {}",
+            backup.display()
+        ));
+        let name = backup.file_name().unwrap_or_default().to_string_lossy();
+        tui::info(&format!("Undo with: ghostctl pve firewall restore {name}"));
+    }
+    Ok(())
+}
+
+fn restore(firewall: &Firewall, name: Option<&str>) -> Result<()> {
+    let backups = firewall.backups()?;
+    let Some((name, files)) = (match name {
+        Some(name) => backups.into_iter().find(|(n, _)| n == name),
+        None => backups.into_iter().last(),
+    }) else {
+        bail!("no such firewall backup");
+    };
+    for path in &files {
+        println!("  {path}");
+    }
+    if is_dry_run() {
+        tui::info("Dry run: nothing restored");
+        return Ok(());
+    }
+    if !tui::confirm_dangerous(&format!("Restore {} file(s) from {name}?", files.len())) {
+        bail!("aborted");
+    }
+    let backup = firewall.restore(&name)?;
+    tui::success(&format!(
+        "Restored {name}; replaced files saved in {}",
+        backup.display()
+    ));
+    Ok(())
+}
+
+fn file_arg() -> Arg {
+    Arg::new("file")
+        .value_name("FILE")
+        .value_parser(clap::value_parser!(PathBuf))
+        .default_value("firewall.toml")
+        .help("Desired firewall state")
+}
+
+fn file_path(matches: &ArgMatches) -> PathBuf {
+    matches
+        .get_one::<PathBuf>("file")
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("firewall.toml"))
+}
+
+pub fn command() -> Command {
+    Command::new("firewall")
+        .about("PVE firewall as code: cluster.fw, host.fw and <vmid>.fw")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("plan")
+                .about("Show what apply would change in the firewall files")
+                .arg(file_arg())
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Write the changed firewall files, backing up the old ones")
+                .arg(file_arg()),
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Print the .fw files the desired state produces")
+                .arg(file_arg()),
+        )
+        .subcommand(Command::new("export").about("Print the live firewall as a desired-state file"))
+        .subcommand(Command::new("backups").about("List backups taken by apply and restore"))
+        .subcommand(
+            Command::new("restore")
+                .about("Put a backup's files back (default: the latest)")
+                .arg(Arg::new("backup").value_name("BACKUP").help("Backup name")),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let runner = crate::command::runner();
+    let firewall = Firewall::new(runner.as_ref());
+    match matches.subcommand() {
+        Some(("plan", m)) => {
+            let plans = firewall.plan(&FirewallFile::load(&file_path(m))?)?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&plans)?);
+            } else {
+                print_plan(&plans);
+            }
+        }
+        Some(("apply", m)) => apply_file(&firewall, &FirewallFile::load(&file_path(m))?)?,
+        Some(("render", m)) => {
+            for plan in firewall.plan(&FirewallFile::load(&file_path(m))?)? {
+                println!("# {}\n{}", plan.path, plan.content);
+            }
+        }
+        Some(("export", _)) => print!("{}", toml::to_string_pretty(&firewall.export()?)?),
+        Some(("backups", _)) => {
+            let backups = firewall.backups()?;
+            if backups.is_empty() {
+                println!("No firewall backups");
+            }
+            for (name, files) in backups {
+                println!("{name}  {}", files.join(", "));
+            }
+        }
+        Some(("restore", m)) => {
+            restore(&firewall, m.get_one::<String>("backup").map(String::as_str))?
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+pub fn menu() {
+    let runner = crate::command::runner();
+    let firewall = Firewall::new(runner.as_ref());
+    let Some(choice) = tui::select(
+        "PVE firewall as code",
+        &["Plan", "Apply", "Restore a backup", "Back"],
+        0,
+    ) else {
+        return;
+    };
+    let result = match choice {
+        0 | 1 => {
+            let Some(path) = tui::input("Desired-state file", Some("firewall.toml")) else {
+                return;
+            };
+            FirewallFile::load(Path::new(&path)).and_then(|file| {
+                if choice == 0 {
+                    firewall.plan(&file).map(|plans| print_plan(&plans))
+                } else {
+                    apply_file(&firewall, &file)
+                }
+            })
+        }
+        2 => restore_menu_with(&firewall),
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        tui::error(&format!("{e:#}"));
+    }
+}
+
+pub fn restore_menu() {
+    let runner = crate::command::runner();
+    if let Err(e) = restore_menu_with(&Firewall::new(runner.as_ref())) {
+        tui::error(&format!("{e:#}"));
+    }
+}
+
+fn restore_menu_with(firewall: &Firewall) -> Result<()> {
+    let backups = firewall.backups()?;
+    if backups.is_empty() {
+        tui::info("No firewall backups yet; apply takes one before writing");
+        return Ok(());
+    }
+    let items: Vec<String> = backups
+        .iter()
+        .rev()
+        .map(|(name, files)| format!("{name}  ({} file(s))", files.len()))
+        .collect();
+    match tui::select("Backup to restore", &items, 0) {
+        Some(i) => restore(firewall, Some(&backups[backups.len() - 1 - i].0)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+
+    const DESIRED: &str = include_str!("../testdata/firewall/firewall.toml");
+    const CLUSTER: &str = include_str!("../testdata/firewall/cluster.fw");
+    const GUEST: &str = include_str!("../testdata/firewall/101.fw");
+    const CLUSTER_FW: &str = "/etc/pve/firewall/cluster.fw";
+    const TODAY: &str = "2026-10-19";
+
+    const RENDERED: &str = "[OPTIONS]
+
+enable: 1
+policy_in: DROP
+policy_out: ACCEPT
+
+[ALIASES]
+
+mgmt_net 10.10.0.0/24 # Management VLAN
+
+[IPSET management]
+
+10.10.0.0/24
+!10.10.0.99 # kiosk
+
+[RULES]
+
+IN ACCEPT -source 203.0.113.7 -p tcp -dport 22 # exception vendor: support session (approved by ops, until 2026-11-30)
+GROUP proxmox -i vmbr0
+|IN SSH(ACCEPT) # emergency only
+
+[group proxmox] # web UI and SSH
+
+IN ACCEPT -source +management -p tcp -dport 8006 # web UI
+IN SSH(ACCEPT) -source +management
+
+";
+
+    #[test]
+    fn desired_state_renders_each_scope() {
+        let file = FirewallFile::parse(DESIRED).unwrap();
+        assert_eq!(file.policies.len(), 3);
+        assert_eq!(format::render(&file.policies[0], TODAY).unwrap(), RENDERED);
+        assert_eq!(
+            format::path(&file.policies[1].scope),
+            "/etc/pve/firewall/101.fw"
+        );
+        assert_eq!(
+            format::path(&file.policies[2].scope),
+            "/etc/pve/nodes/pve2/host.fw"
+        );
+
+        let twice = format!("{DESIRED}\n[[policies]]\nname = \"dc\"\nscope = \"Datacenter\"\n");
+        let err = FirewallFile::parse(&twice).unwrap_err();
+        assert!(err.to_string().contains("cluster.fw"), "{err}");
+    }
+
+    #[test]
+    fn apply_backs_up_and_restore_undoes() {
+        let runner = MockRunner::new();
+        runner.mock_file(CLUSTER_FW, CLUSTER);
+        runner.mock_file("/etc/pve/nodes/pve2/host.fw", "[OPTIONS]\n\nenable: 1\n\n");
+        let dir = tempfile::tempdir().unwrap();
+        let firewall = Firewall::with_dir(&runner, dir.path().to_path_buf(), TODAY);
+        let file = FirewallFile::parse(DESIRED).unwrap();
+
+        let plans = firewall.plan(&file).unwrap();
+        assert_eq!(plans[0].action, FileAction::Update);
+        assert!(
+            plans[0].diff.contains("-[group webserver]"),
+            "{}",
+            plans[0].diff
+        );
+        assert_eq!(plans[0].expired, ["audit"]);
+        assert_eq!(plans[1].action, FileAction::Create);
+        assert!(plans[2].advisory);
+        assert_eq!(plans[2].action, FileAction::Update);
+
+        let backup = firewall.apply(&plans).unwrap().unwrap();
+        assert_eq!(runner.read_file(CLUSTER_FW).unwrap(), RENDERED);
+        assert!(
+            runner
+                .read_file("/etc/pve/firewall/101.fw")
+                .unwrap()
+                .contains("IN ACCEPT -i net0 -p tcp -dport 80,443")
+        );
+        // Advisory policies are never written.
+        assert_eq!(
+            runner.read_file("/etc/pve/nodes/pve2/host.fw").unwrap(),
+            "[OPTIONS]\n\nenable: 1\n\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(backup.join("etc/pve/firewall/cluster.fw")).unwrap(),
+            CLUSTER
+        );
+
+        let again = firewall.plan(&file).unwrap();
+        assert!(again.iter().all(|p| !p.writes()));
+        assert!(firewall.apply(&again).unwrap().is_none());
+
+        let name = backup.file_name().unwrap().to_str().unwrap().to_string();
+        let backups = firewall.backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            backups[0].1,
+            ["/etc/pve/firewall/cluster.fw", "/etc/pve/firewall/101.fw"]
+        );
+        firewall.restore(&name).unwrap();
+        assert_eq!(runner.read_file(CLUSTER_FW).unwrap(), CLUSTER);
+        assert_eq!(runner.read_file("/etc/pve/firewall/101.fw").unwrap(), "");
+        assert_eq!(firewall.backups().unwrap().len(), 2);
+        assert!(firewall.restore("../elsewhere").is_err());
+    }
+
+    #[test]
+    fn export_reads_every_scope() {
+        let runner = MockRunner::new();
+        runner.mock_file(CLUSTER_FW, CLUSTER);
+        runner.mock_file("/etc/pve/firewall/101.fw", GUEST);
+        runner.mock_file("/etc/pve/firewall/200.fw", "[OPTIONS]\n\nenable: 1\n\n");
+        runner.mock_file(
+            VMLIST,
+            r#"{"version": 7, "ids": {"101": {"node": "pve1", "type": "qemu", "version": 3}, "200": {"node": "pve2", "type": "lxc", "version": 4}}}"#,
+        );
+        runner.mock_command(
+            "ls",
+            &["-1", FIREWALL_DIR],
+            CommandResult::ok("101.fw\n200.fw\ncluster.fw\n"),
+        );
+        runner.mock_command("ls", &["-1", NODES_DIR], CommandResult::ok("pve1\npve2\n"));
+        let dir = tempfile::tempdir().unwrap();
+        let firewall = Firewall::with_dir(&runner, dir.path().to_path_buf(), TODAY);
+
+        let exported = firewall.export().unwrap();
+        let scopes: Vec<_> = exported.policies.iter().map(|p| p.scope.clone()).collect();
+        assert_eq!(
+            scopes,
+            [
+                PolicyScope::Cluster,
+                PolicyScope::VM(101),
+                PolicyScope::Container(200)
+            ]
+        );
+
+        // The export is itself a desired state that plans no changes.
+        let text = toml::to_string_pretty(&exported).unwrap();
+        let plans = firewall.plan(&FirewallFile::parse(&text).unwrap()).unwrap();
+        assert!(plans.iter().all(|p| p.action == FileAction::Unchanged));
+    }
+
+    #[test]
+    fn comment_only_differences_are_planned_against_the_raw_file() {
+        let runner = MockRunner::new();
+        let commented = RENDERED.replace("[RULES]\n", "[RULES]\n# keep the vendor rule first\n");
+        runner.mock_file(CLUSTER_FW, &commented);
+        let dir = tempfile::tempdir().unwrap();
+        let firewall = Firewall::with_dir(&runner, dir.path().to_path_buf(), TODAY);
+
+        let plans = firewall
+            .plan(&FirewallFile::parse(DESIRED).unwrap())
+            .unwrap();
+        assert_eq!(plans[0].action, FileAction::Update);
+        assert!(plans[0].normalization_only);
+        assert!(
+            plans[0].diff.contains("-# keep the vendor rule first"),
+            "{}",
+            plans[0].diff
+        );
+        assert!(!plans[1].normalization_only);
+    }
+}
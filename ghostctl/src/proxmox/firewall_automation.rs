@@ -56,10 +56,10 @@ pub fn firewall_automation_menu() {
             "🔥 Firewall Rule Management",
             "📋 Firewall Profiles & Templates",
             "🔍 Network Security Scanning",
-            "🛡️  Security Policy Enforcement",
+            "🛡️  Firewall as Code (plan/apply)",
             "📊 Firewall Monitoring & Analytics",
             "🚨 Threat Detection & Response",
-            "⚙️  Firewall Configuration Backup",
+            "⚙️  Restore Firewall File Backup",
             "🔧 Advanced Firewall Tools",
             "📈 Security Compliance Checks",
             "⬅️  Back",
@@ -79,10 +79,10 @@ pub fn firewall_automation_menu() {
             0 => firewall_rule_management(),
             1 => firewall_profiles_templates(),
             2 => crate::network::scan::network_security_scanning(),
-            3 => security_policy_enforcement(),
+            3 => super::firewall::menu(),
             4 => firewall_monitoring_analytics(),
             5 => threat_detection_response(),
-            6 => firewall_configuration_backup(),
+            6 => super::firewall::restore_menu(),
             7 => advanced_firewall_tools(),
             8 => security_compliance_checks(),
             _ => break,
@@ -1483,10 +1483,6 @@ fn scan_configuration() {
     println!("💡 Scan settings implementation pending");
 }
 
-fn security_policy_enforcement() {
-    println!("🛡️  Security Policy Enforcement - Implementation coming in next update!");
-}
-
 fn firewall_monitoring_analytics() {
     println!("📊 Firewall Monitoring & Analytics - Implementation coming in next update!");
 }
@@ -1495,10 +1491,6 @@ fn threat_detection_response() {
     println!("🚨 Threat Detection & Response - Implementation coming in next update!");
 }
 
-fn firewall_configuration_backup() {
-    println!("⚙️  Firewall Configuration Backup - Implementation coming in next update!");
-}
-
 fn advanced_firewall_tools() {
     println!("🔧 Advanced Firewall Tools - Implementation coming in next update!");
 }
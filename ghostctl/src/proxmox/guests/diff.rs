@@ -0,0 +1,481 @@
+//! What it takes to turn a guest's live config into the declared one.
+//!
+//! Only keys the file declares are compared, so settings PVE adds on its own
+//! (`vmgenid`, `smbios1`, `digest`, unused disks) never show up as changes.
+
+use super::spec::{Desired, Field, format_size, is_net_key, parse_size};
+use crate::proxmox::api::GuestKind;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A guest's config as `key -> value`, from `qm config`/`pct config` or the API.
+pub type Config = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub target: String,
+    pub guests: Vec<GuestPlan>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GuestPlan {
+    pub vmid: u32,
+    pub kind: GuestKind,
+    pub name: String,
+    pub node: String,
+    pub action: Action,
+    pub running: bool,
+    /// Template cloned before the changes are made.
+    pub clone: Option<u32>,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Update,
+    InSync,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    Set {
+        key: String,
+        from: Option<String>,
+        to: String,
+        /// Pending until the running guest restarts.
+        restart: bool,
+    },
+    Delete {
+        key: String,
+        from: String,
+    },
+    Resize {
+        disk: String,
+        from: String,
+        to: String,
+    },
+    /// A difference ghostctl will not resolve by itself.
+    Blocked {
+        key: String,
+        reason: String,
+    },
+}
+
+impl Plan {
+    pub fn has_changes(&self) -> bool {
+        self.guests.iter().any(GuestPlan::has_changes)
+    }
+}
+
+impl GuestPlan {
+    pub fn label(&self) -> String {
+        format!("{} {} {}", kind_label(self.kind), self.vmid, self.name)
+    }
+
+    /// Changes apply can make; blocked ones only get reported.
+    pub fn has_changes(&self) -> bool {
+        self.action == Action::Create
+            || self
+                .changes
+                .iter()
+                .any(|c| !matches!(c, Change::Blocked { .. }))
+    }
+
+    /// Keys that stay pending until the guest restarts.
+    pub fn restart_keys(&self) -> Vec<&str> {
+        self.changes
+            .iter()
+            .filter_map(|c| match c {
+                Change::Set {
+                    key, restart: true, ..
+                } => Some(key.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub fn kind_label(kind: GuestKind) -> &'static str {
+    match kind {
+        GuestKind::Qemu => "vm",
+        GuestKind::Lxc => "ct",
+    }
+}
+
+/// Whether changing `key` on a running guest only takes effect after a
+/// restart. NICs and new disks hotplug; CPU and memory hotplug in
+/// containers but not in VMs without NUMA hotplug set up.
+fn needs_restart(kind: GuestKind, key: &str) -> bool {
+    const LIVE: &[&str] = &["name", "tags", "onboot", "description", "protection"];
+    const CT_LIVE: &[&str] = &["cores", "memory", "swap", "cpulimit", "cpuunits"];
+    if LIVE.contains(&key) || is_net_key(key) {
+        return false;
+    }
+    !(kind == GuestKind::Lxc && CT_LIVE.contains(&key))
+}
+
+/// The settings a new guest is created with.
+pub fn create(desired: &Desired) -> Vec<Change> {
+    desired
+        .fields
+        .iter()
+        .map(|(key, field)| (key.clone(), field.render(None)))
+        .chain(desired.create_only.iter().cloned())
+        .map(|(key, to)| Change::Set {
+            key,
+            from: None,
+            to,
+            restart: false,
+        })
+        .collect()
+}
+
+pub fn diff(desired: &Desired, live: &Config, running: bool) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (key, field) in &desired.fields {
+        let current = live.get(key).map(String::as_str);
+        if let Field::Disk { .. } = field {
+            disk_changes(desired.kind, key, field, current, running, &mut changes);
+            continue;
+        }
+        if !field.matches(current) {
+            changes.push(Change::Set {
+                key: key.clone(),
+                from: current.map(str::to_string),
+                to: field.render(current),
+                restart: running && needs_restart(desired.kind, key),
+            });
+        }
+    }
+    if desired.manage_nets {
+        for (key, value) in live {
+            if is_net_key(key) && !desired.fields.contains_key(key) {
+                changes.push(Change::Delete {
+                    key: key.clone(),
+                    from: value.clone(),
+                });
+            }
+        }
+    }
+    if desired.kind == GuestKind::Lxc
+        && let Some((_, want)) = desired
+            .create_only
+            .iter()
+            .find(|(k, _)| k == "unprivileged")
+    {
+        let have = live.get("unprivileged").map(String::as_str).unwrap_or("0");
+        if have != want {
+            changes.push(Change::Blocked {
+                key: "unprivileged".to_string(),
+                reason: format!("is {have}, want {want}; only set when the container is created"),
+            });
+        }
+    }
+    changes
+}
+
+/// A live disk is `storage:volume,opt=val,...,size=32G`.
+fn disk_changes(
+    kind: GuestKind,
+    key: &str,
+    field: &Field,
+    live: Option<&str>,
+    running: bool,
+    changes: &mut Vec<Change>,
+) {
+    let Field::Disk {
+        storage,
+        size,
+        options,
+    } = field
+    else {
+        return;
+    };
+    let Some(live) = live else {
+        // New disks hotplug into VMs; a container always has its rootfs.
+        changes.push(Change::Set {
+            key: key.to_string(),
+            from: None,
+            to: field.render(None),
+            restart: false,
+        });
+        return;
+    };
+    let mut parts = live.split(',');
+    let volume = parts.next().unwrap_or_default();
+    let live_opts: Vec<(&str, &str)> = parts.filter_map(|p| p.split_once('=')).collect();
+    let live_storage = volume.split(':').next().unwrap_or_default();
+    if live_storage != storage {
+        let how = match kind {
+            GuestKind::Qemu => "qm disk move",
+            GuestKind::Lxc => "pct move-volume",
+        };
+        changes.push(Change::Blocked {
+            key: key.to_string(),
+            reason: format!("is on {live_storage}, want {storage}; move it with `{how}`"),
+        });
+        return;
+    }
+
+    let missing: Vec<&(String, String)> = options
+        .iter()
+        .filter(|(k, v)| !live_opts.contains(&(k.as_str(), v.as_str())))
+        .collect();
+    if !missing.is_empty() {
+        let mut value = volume.to_string();
+        for (k, v) in &live_opts {
+            if !options.iter().any(|(dk, _)| dk == k) {
+                value.push_str(&format!(",{k}={v}"));
+            }
+        }
+        for (k, v) in options {
+            value.push_str(&format!(",{k}={v}"));
+        }
+        changes.push(Change::Set {
+            key: key.to_string(),
+            from: Some(live.to_string()),
+            to: value,
+            restart: running && needs_restart(kind, key),
+        });
+    }
+
+    let live_size = live_opts
+        .iter()
+        .find(|(k, _)| *k == "size")
+        .and_then(|(_, v)| parse_size(v).ok())
+        .unwrap_or(0);
+    if *size > live_size {
+        changes.push(Change::Resize {
+            disk: key.to_string(),
+            from: format_size(live_size),
+            to: format_size(*size),
+        });
+    } else if *size < live_size {
+        changes.push(Change::Blocked {
+            key: key.to_string(),
+            reason: format!(
+                "is {}, want {}; disks cannot shrink",
+                format_size(live_size),
+                format_size(*size)
+            ),
+        });
+    }
+}
+
+pub fn print_plan(plan: &Plan) {
+    println!("guests on {}", plan.target);
+    let mut counts = [0; 3];
+    for guest in &plan.guests {
+        let (mark, index) = match guest.action {
+            Action::Create => ("+", 0),
+            Action::Update => ("~", 1),
+            Action::InSync => ("=", 2),
+        };
+        counts[index] += 1;
+        let mut header = format!("  {mark} {} on {}", guest.label(), guest.node);
+        if let Some(template) = guest.clone {
+            header.push_str(&format!(" (clone of {template})"));
+        }
+        if guest.running {
+            header.push_str(" (running)");
+        }
+        println!("{header}");
+        for change in &guest.changes {
+            match change {
+                Change::Set {
+                    key,
+                    from: None,
+                    to,
+                    restart,
+                } => {
+                    println!(
+                        "      {key} = {to}{}",
+                        if *restart { "  [restart]" } else { "" }
+                    )
+                }
+                Change::Set {
+                    key,
+                    from: Some(from),
+                    to,
+                    restart,
+                } => println!(
+                    "      {key}: {from} -> {to}{}",
+                    if *restart { "  [restart]" } else { "" }
+                ),
+                Change::Delete { key, from } => println!("      - {key} ({from})"),
+                Change::Resize { disk, from, to } => {
+                    println!("      resize {disk}: {from} -> {to}")
+                }
+                Change::Blocked { key, reason } => println!("      ! {key} {reason}"),
+            }
+        }
+    }
+    println!(
+        "Plan: {} to create, {} to change, {} unchanged.",
+        counts[0], counts[1], counts[2]
+    );
+    let restarts: Vec<String> = plan
+        .guests
+        .iter()
+        .filter(|g| !g.restart_keys().is_empty())
+        .map(GuestPlan::label)
+        .collect();
+    if !restarts.is_empty() {
+        println!("Restart needed afterwards: {}", restarts.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::spec::GuestFile;
+    use super::*;
+
+    const GUESTS: &str = include_str!("../testdata/guests.toml");
+
+    fn config(text: &str) -> Config {
+        super::super::parse_config(text)
+    }
+
+    fn desired(vmid: u32) -> Desired {
+        GuestFile::parse(GUESTS)
+            .unwrap()
+            .desired()
+            .unwrap()
+            .into_iter()
+            .find(|d| d.vmid == vmid)
+            .unwrap()
+    }
+
+    const DB_LIVE: &str = "\
+boot: order=scsi0
+cores: 2
+memory: 8192
+meta: creation-qemu=9.0.2,ctime=1727000000
+name: db
+net0: virtio=BC:24:11:5E:10:02,bridge=vmbr0,tag=30
+net1: virtio=BC:24:11:5E:10:03,bridge=vmbr1
+ostype: l26
+scsi0: local-lvm:vm-102-disk-0,iothread=1,size=32G
+scsi1: local-lvm:vm-102-disk-1,size=200G
+scsihw: virtio-scsi-single
+smbios1: uuid=6f4f3c9e-0f5e-4b8e-9a53-3b1d6d7c1a2b
+vmgenid: 0d8e2f7a-1c34-4f6b-8a9e-2b7c5d4e3f10
+";
+
+    #[test]
+    fn changes_against_a_running_vm() {
+        let changes = diff(&desired(102), &config(DB_LIVE), true);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Set {
+                    key: "cores".to_string(),
+                    from: Some("2".to_string()),
+                    to: "4".to_string(),
+                    restart: true,
+                },
+                Change::Resize {
+                    disk: "scsi0".to_string(),
+                    from: "32G".to_string(),
+                    to: "64G".to_string(),
+                },
+                Change::Blocked {
+                    key: "scsi1".to_string(),
+                    reason: "is on local-lvm, want tank; move it with `qm disk move`".to_string(),
+                },
+                Change::Delete {
+                    key: "net1".to_string(),
+                    from: "virtio=BC:24:11:5E:10:03,bridge=vmbr1".to_string(),
+                },
+            ]
+        );
+        // Stopped, nothing waits for a restart.
+        let stopped = diff(&desired(102), &config(DB_LIVE), false);
+        assert!(
+            stopped
+                .iter()
+                .all(|c| !matches!(c, Change::Set { restart: true, .. }))
+        );
+    }
+
+    #[test]
+    fn applied_container_is_in_sync_and_shrink_is_blocked() {
+        let live = "\
+arch: amd64
+cores: 1
+features: nesting=1
+hostname: dns
+memory: 512
+net0: name=eth0,bridge=vmbr0,hwaddr=BC:24:11:AA:00:01,ip=dhcp,type=veth
+onboot: 1
+ostype: debian
+rootfs: local-lvm:vm-200-disk-0,size=8G
+swap: 512
+unprivileged: 1
+";
+        assert_eq!(diff(&desired(200), &config(live), true), Vec::new());
+
+        let grown = live
+            .replace("size=8G", "size=16G")
+            .replace("bridge=vmbr0", "bridge=vmbr9")
+            .replace("unprivileged: 1\n", "");
+        let changes = diff(&desired(200), &config(&grown), true);
+        assert_eq!(
+            changes[0],
+            Change::Set {
+                key: "net0".to_string(),
+                from: Some(
+                    "name=eth0,bridge=vmbr9,hwaddr=BC:24:11:AA:00:01,ip=dhcp,type=veth".to_string()
+                ),
+                to: "hwaddr=BC:24:11:AA:00:01,name=eth0,bridge=vmbr0,ip=dhcp,type=veth".to_string(),
+                restart: false,
+            }
+        );
+        assert!(
+            matches!(&changes[1], Change::Blocked { key, reason } if key == "rootfs" && reason.contains("cannot shrink"))
+        );
+        assert!(matches!(&changes[2], Change::Blocked { key, .. } if key == "unprivileged"));
+    }
+
+    #[test]
+    fn disk_options_keep_the_volume() {
+        let live = config("scsi0: local-lvm:vm-102-disk-0,size=64G\n");
+        let changes = diff(&desired(102), &live, false);
+        assert!(changes.contains(&Change::Set {
+            key: "scsi0".to_string(),
+            from: Some("local-lvm:vm-102-disk-0,size=64G".to_string()),
+            to: "local-lvm:vm-102-disk-0,size=64G,iothread=1".to_string(),
+            restart: false,
+        }));
+    }
+
+    #[test]
+    fn create_lists_every_setting() {
+        let changes = create(&desired(200));
+        let keys: Vec<&str> = changes
+            .iter()
+            .map(|c| match c {
+                Change::Set { key, .. } => key.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "cores",
+                "features",
+                "hostname",
+                "memory",
+                "net0",
+                "onboot",
+                "rootfs",
+                "swap",
+                "ostemplate",
+                "unprivileged"
+            ]
+        );
+    }
+}
@@ -0,0 +1,933 @@
+//! `pve plan|apply guests.toml`: VMs and containers as code.
+//!
+//! The file is compared with each guest's live config, from `qm config` /
+//! `pct config` on a node or from the API with `--cluster`, and apply makes
+//! only the changes the plan shows, so running it twice changes nothing.
+
+pub mod diff;
+pub mod spec;
+
+use super::api::{ApiClient, Guest, GuestKind, Upid, encode_segment};
+use crate::command::CommandRunner;
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use diff::{Action, Change, Config, GuestPlan, Plan};
+use serde_json::Value;
+use spec::{Desired, GuestFile};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where guests are read from and changed.
+pub trait Backend {
+    fn describe(&self) -> String;
+
+    /// Node used for new guests without one in the file.
+    fn default_node(&self) -> Option<&str> {
+        None
+    }
+
+    /// Fail early for guests this backend cannot manage.
+    fn check_node(&self, _node: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn guests(&self) -> Result<Vec<Guest>>;
+    fn config(&self, guest: &Guest) -> Result<Config>;
+    fn create(
+        &self,
+        kind: GuestKind,
+        node: &str,
+        vmid: u32,
+        options: &[(String, String)],
+    ) -> Result<()>;
+    fn clone_template(&self, template: &Guest, vmid: u32, name: &str, node: &str) -> Result<()>;
+    fn set(&self, guest: &Guest, set: &[(String, String)], delete: &[String]) -> Result<()>;
+    fn resize(&self, guest: &Guest, disk: &str, size: &str) -> Result<()>;
+    fn reboot(&self, guest: &Guest) -> Result<()>;
+}
+
+/// `key: value` lines as printed by `qm config`/`pct config`, up to the
+/// first snapshot section.
+pub fn parse_config(text: &str) -> Config {
+    text.lines()
+        .take_while(|l| !l.starts_with('['))
+        .filter_map(|l| l.split_once(": "))
+        .filter(|(k, _)| *k != "digest")
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect()
+}
+
+fn tool(kind: GuestKind) -> &'static str {
+    match kind {
+        GuestKind::Qemu => "qm",
+        GuestKind::Lxc => "pct",
+    }
+}
+
+/// `qm`/`pct` on the node ghostctl runs on.
+pub struct LocalBackend<'a> {
+    runner: &'a dyn CommandRunner,
+    node: String,
+}
+
+impl<'a> LocalBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner, node: &str) -> Self {
+        Self {
+            runner,
+            node: node.to_string(),
+        }
+    }
+
+    fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+        let out = self
+            .runner
+            .run(cmd, args)
+            .with_context(|| format!("failed to run {cmd}"))?;
+        if !out.success {
+            bail!(
+                "{cmd} {} failed: {}",
+                args.first().unwrap_or(&""),
+                out.stderr.trim()
+            );
+        }
+        Ok(out.stdout)
+    }
+
+    /// `--key value` pairs; keys go through a temporary file because `qm`
+    /// and `pct` take a path for them.
+    fn run_with_options(
+        &self,
+        cmd: &str,
+        mut args: Vec<String>,
+        options: &[(String, String)],
+    ) -> Result<()> {
+        let mut files = Vec::new();
+        for (key, value) in options {
+            args.push(format!("--{key}"));
+            if key == "sshkeys" || key == "ssh-public-keys" {
+                let mut file = tempfile::Builder::new()
+                    .prefix("ghostctl-keys-")
+                    .tempfile()?;
+                writeln!(file, "{value}")?;
+                args.push(file.path().to_string_lossy().to_string());
+                files.push(file);
+            } else {
+                args.push(value.clone());
+            }
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.run(cmd, &args)?;
+        Ok(())
+    }
+}
+
+impl Backend for LocalBackend<'_> {
+    fn describe(&self) -> String {
+        format!("node {}", self.node)
+    }
+
+    fn default_node(&self) -> Option<&str> {
+        Some(&self.node)
+    }
+
+    fn check_node(&self, node: &str) -> Result<()> {
+        if node != self.node {
+            bail!(
+                "guests on {node} cannot be managed from {}: run there or pass --cluster",
+                self.node
+            );
+        }
+        Ok(())
+    }
+
+    fn guests(&self) -> Result<Vec<Guest>> {
+        let mut guests = Vec::new();
+        // VMID NAME STATUS MEM(MB) BOOTDISK(GB) PID
+        for line in self.run("qm", &["list"])?.lines().skip(1) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if let [vmid, name, status, ..] = cols.as_slice()
+                && let Ok(vmid) = vmid.parse()
+            {
+                let mut guest = Guest::new(vmid, &self.node, GuestKind::Qemu);
+                guest.name = name.to_string();
+                guest.status = status.to_string();
+                guests.push(guest);
+            }
+        }
+        // VMID Status Lock Name, with Lock often empty
+        for line in self.run("pct", &["list"])?.lines().skip(1) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if let [vmid, status, .., name] = cols.as_slice()
+                && let Ok(vmid) = vmid.parse()
+            {
+                let mut guest = Guest::new(vmid, &self.node, GuestKind::Lxc);
+                guest.name = name.to_string();
+                guest.status = status.to_string();
+                guests.push(guest);
+            }
+        }
+        Ok(guests)
+    }
+
+    fn config(&self, guest: &Guest) -> Result<Config> {
+        let vmid = guest.vmid.to_string();
+        Ok(parse_config(
+            &self.run(tool(guest.kind), &["config", &vmid])?,
+        ))
+    }
+
+    fn create(
+        &self,
+        kind: GuestKind,
+        node: &str,
+        vmid: u32,
+        options: &[(String, String)],
+    ) -> Result<()> {
+        self.check_node(node)?;
+        let mut args = vec!["create".to_string(), vmid.to_string()];
+        let mut rest = Vec::new();
+        for (key, value) in options {
+            if key == "ostemplate" {
+                args.push(value.clone());
+            } else {
+                rest.push((key.clone(), value.clone()));
+            }
+        }
+        self.run_with_options(tool(kind), args, &rest)
+    }
+
+    fn clone_template(&self, template: &Guest, vmid: u32, name: &str, node: &str) -> Result<()> {
+        self.check_node(node)?;
+        let (template, vmid) = (template.vmid.to_string(), vmid.to_string());
+        self.run("qm", &["clone", &template, &vmid, "--name", name, "--full"])?;
+        Ok(())
+    }
+
+    fn set(&self, guest: &Guest, set: &[(String, String)], delete: &[String]) -> Result<()> {
+        let mut options = set.to_vec();
+        if !delete.is_empty() {
+            options.push(("delete".to_string(), delete.join(",")));
+        }
+        let args = vec!["set".to_string(), guest.vmid.to_string()];
+        self.run_with_options(tool(guest.kind), args, &options)
+    }
+
+    fn resize(&self, guest: &Guest, disk: &str, size: &str) -> Result<()> {
+        self.run(
+            tool(guest.kind),
+            &["resize", &guest.vmid.to_string(), disk, size],
+        )?;
+        Ok(())
+    }
+
+    fn reboot(&self, guest: &Guest) -> Result<()> {
+        self.run(tool(guest.kind), &["reboot", &guest.vmid.to_string()])?;
+        Ok(())
+    }
+}
+
+/// A `[[pve.clusters]]` entry over the REST API.
+pub struct ApiBackend {
+    client: ApiClient,
+    cluster: String,
+    poll: Duration,
+}
+
+impl ApiBackend {
+    pub fn new(client: ApiClient, cluster: &str) -> Self {
+        Self {
+            client,
+            cluster: cluster.to_string(),
+            poll: Duration::from_secs(1),
+        }
+    }
+
+    fn wait(&self, upid: Value) -> Result<()> {
+        // Some endpoints answer synchronously with null.
+        let Some(upid) = upid.as_str() else {
+            return Ok(());
+        };
+        let upid: Upid = upid.parse()?;
+        self.client.wait_task(
+            &upid,
+            self.poll,
+            crate::proxmox::remote::TASK_TIMEOUT,
+            |line| println!("    {line}"),
+        )?;
+        Ok(())
+    }
+
+    fn body(options: &[(String, String)]) -> serde_json::Map<String, Value> {
+        options
+            .iter()
+            .map(|(k, v)| {
+                let v = if k == "sshkeys" {
+                    encode_segment(v)
+                } else {
+                    v.clone()
+                };
+                (k.clone(), Value::String(v))
+            })
+            .collect()
+    }
+
+    fn path(guest: &Guest) -> String {
+        format!("/nodes/{}/{}/{}", guest.node, guest.kind.path(), guest.vmid)
+    }
+}
+
+impl Backend for ApiBackend {
+    fn describe(&self) -> String {
+        format!("cluster {}", self.cluster)
+    }
+
+    fn guests(&self) -> Result<Vec<Guest>> {
+        self.client.guests()
+    }
+
+    fn config(&self, guest: &Guest) -> Result<Config> {
+        let config: serde_json::Map<String, Value> =
+            self.client.get(&format!("{}/config", Self::path(guest)))?;
+        Ok(config
+            .into_iter()
+            .filter(|(k, _)| k != "digest")
+            .map(|(k, v)| match v {
+                Value::String(s) => (k, s),
+                other => (k, other.to_string()),
+            })
+            .collect())
+    }
+
+    fn create(
+        &self,
+        kind: GuestKind,
+        node: &str,
+        vmid: u32,
+        options: &[(String, String)],
+    ) -> Result<()> {
+        let mut body = Self::body(options);
+        body.insert("vmid".to_string(), vmid.into());
+        let upid = self.client.post(
+            &format!("/nodes/{node}/{}", kind.path()),
+            &Value::Object(body),
+        )?;
+        self.wait(upid)
+    }
+
+    fn clone_template(&self, template: &Guest, vmid: u32, name: &str, node: &str) -> Result<()> {
+        let mut body = serde_json::json!({ "newid": vmid, "name": name, "full": 1 });
+        if node != template.node {
+            body["target"] = node.into();
+        }
+        let upid = self
+            .client
+            .post(&format!("{}/clone", Self::path(template)), &body)?;
+        self.wait(upid)
+    }
+
+    fn set(&self, guest: &Guest, set: &[(String, String)], delete: &[String]) -> Result<()> {
+        let mut body = Self::body(set);
+        if !delete.is_empty() {
+            body.insert("delete".to_string(), delete.join(",").into());
+        }
+        let result = self.client.put(
+            &format!("{}/config", Self::path(guest)),
+            &Value::Object(body),
+        )?;
+        self.wait(result)
+    }
+
+    fn resize(&self, guest: &Guest, disk: &str, size: &str) -> Result<()> {
+        let result = self.client.put(
+            &format!("{}/resize", Self::path(guest)),
+            &serde_json::json!({ "disk": disk, "size": size }),
+        )?;
+        self.wait(result)
+    }
+
+    fn reboot(&self, guest: &Guest) -> Result<()> {
+        let upid = self.client.guest_action(guest, "reboot")?;
+        self.wait(Value::String(upid.raw))
+    }
+}
+
+fn plan_guest(backend: &dyn Backend, inventory: &[Guest], desired: &Desired) -> Result<GuestPlan> {
+    if let Some(node) = &desired.node {
+        backend.check_node(node)?;
+    }
+    let label = format!("{} {}", diff::kind_label(desired.kind), desired.vmid);
+    let Some(live) = inventory.iter().find(|g| g.vmid == desired.vmid) else {
+        let node = desired
+            .node
+            .as_deref()
+            .or(backend.default_node())
+            .with_context(|| {
+                format!("{label}: no node for the new guest (set node or [defaults] node)")
+            })?;
+        let changes = match desired.clone {
+            Some(template) => {
+                let template = find_template(inventory, template)?;
+                diff::diff(desired, &backend.config(template)?, false)
+            }
+            None => diff::create(desired),
+        };
+        return Ok(GuestPlan {
+            vmid: desired.vmid,
+            kind: desired.kind,
+            name: desired.name.clone(),
+            node: node.to_string(),
+            action: Action::Create,
+            running: false,
+            clone: desired.clone,
+            changes,
+        });
+    };
+    if live.kind != desired.kind {
+        bail!(
+            "{label}: VMID {} is a {} on {}",
+            live.vmid,
+            live.kind.label(),
+            live.node
+        );
+    }
+    let config = backend.config(live)?;
+    if config.get("template").is_some_and(|t| t == "1") || live.template == 1 {
+        bail!("{label}: VMID {} is a template", live.vmid);
+    }
+    let running = live.status == "running";
+    let mut changes = diff::diff(desired, &config, running);
+    if let Some(node) = &desired.node
+        && *node != live.node
+    {
+        changes.insert(
+            0,
+            Change::Blocked {
+                key: "node".to_string(),
+                reason: format!("is {}, want {node}; migrate the guest first", live.node),
+            },
+        );
+    }
+    Ok(GuestPlan {
+        vmid: desired.vmid,
+        kind: desired.kind,
+        name: desired.name.clone(),
+        node: live.node.clone(),
+        action: if changes.is_empty() {
+            Action::InSync
+        } else {
+            Action::Update
+        },
+        running,
+        clone: None,
+        changes,
+    })
+}
+
+fn find_template(inventory: &[Guest], vmid: u32) -> Result<&Guest> {
+    inventory
+        .iter()
+        .find(|g| g.vmid == vmid && g.kind == GuestKind::Qemu)
+        .with_context(|| format!("clone template {vmid} not found"))
+}
+
+pub fn plan(backend: &dyn Backend, file: &GuestFile) -> Result<Plan> {
+    let inventory = backend.guests()?;
+    let guests = file
+        .desired()?
+        .iter()
+        .map(|desired| plan_guest(backend, &inventory, desired))
+        .collect::<Result<_>>()?;
+    Ok(Plan {
+        target: backend.describe(),
+        guests,
+    })
+}
+
+fn apply_changes(backend: &dyn Backend, guest: &Guest, changes: &[Change]) -> Result<()> {
+    let mut set = Vec::new();
+    let mut delete = Vec::new();
+    for change in changes {
+        match change {
+            Change::Set { key, to, .. } => set.push((key.clone(), to.clone())),
+            Change::Delete { key, .. } => delete.push(key.clone()),
+            _ => {}
+        }
+    }
+    if !set.is_empty() || !delete.is_empty() {
+        backend.set(guest, &set, &delete)?;
+    }
+    for change in changes {
+        if let Change::Resize { disk, to, .. } = change {
+            backend.resize(guest, disk, to)?;
+        }
+    }
+    Ok(())
+}
+
+fn apply_guest(
+    backend: &dyn Backend,
+    desired: &Desired,
+    planned: &GuestPlan,
+    restart: bool,
+) -> Result<()> {
+    let guest = Guest::new(planned.vmid, &planned.node, planned.kind);
+    let label = planned.label();
+    match (planned.action, planned.clone) {
+        (Action::InSync, _) => return Ok(()),
+        (Action::Create, Some(template)) => {
+            let inventory = backend.guests()?;
+            let template = find_template(&inventory, template)?;
+            tui::info(&format!("Cloning {} into {label}", template.vmid));
+            backend.clone_template(template, planned.vmid, &planned.name, &planned.node)?;
+            // Volume names changed with the clone, so diff the copy itself.
+            let changes = diff::diff(desired, &backend.config(&guest)?, false);
+            apply_changes(backend, &guest, &changes)?;
+        }
+        (Action::Create, None) => {
+            let options: Vec<(String, String)> = planned
+                .changes
+                .iter()
+                .filter_map(|c| match c {
+                    Change::Set { key, to, .. } => Some((key.clone(), to.clone())),
+                    _ => None,
+                })
+                .collect();
+            backend.create(planned.kind, &planned.node, planned.vmid, &options)?;
+        }
+        (Action::Update, _) => apply_changes(backend, &guest, &planned.changes)?,
+    }
+    tui::success(&format!(
+        "{} {label} on {}",
+        if planned.action == Action::Create {
+            "Created"
+        } else {
+            "Updated"
+        },
+        planned.node
+    ));
+
+    let pending = planned.restart_keys();
+    if !pending.is_empty() {
+        if restart {
+            tui::info(&format!("Restarting {label}"));
+            backend.reboot(&guest)?;
+        } else {
+            tui::warn(&format!(
+                "{label} needs a restart for {} (pending until then; or use --restart)",
+                pending.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether anything was changed.
+pub fn apply(backend: &dyn Backend, file: &GuestFile, restart: bool) -> Result<bool> {
+    let plan = plan(backend, file)?;
+    diff::print_plan(&plan);
+    let blocked: Vec<String> = plan
+        .guests
+        .iter()
+        .flat_map(|g| {
+            g.changes.iter().filter_map(move |c| match c {
+                Change::Blocked { key, reason } => Some(format!("{}: {key} {reason}", g.label())),
+                _ => None,
+            })
+        })
+        .collect();
+    for message in &blocked {
+        tui::warn(message);
+    }
+    if !plan.has_changes() {
+        tui::success("Guests match the file");
+        return Ok(false);
+    }
+    if is_dry_run() {
+        println!("[DRY RUN] Would apply the plan above");
+        return Ok(false);
+    }
+    if !tui::confirm("Apply this plan?", true) {
+        return Ok(false);
+    }
+    let desired = file.desired()?;
+    for planned in plan.guests.iter().filter(|g| g.has_changes()) {
+        let wanted = desired
+            .iter()
+            .find(|d| d.vmid == planned.vmid)
+            .context("guest vanished from the file")?;
+        apply_guest(backend, wanted, planned, restart)
+            .with_context(|| format!("failed to apply {}", planned.label()))?;
+    }
+    Ok(true)
+}
+
+fn file_arg() -> Arg {
+    Arg::new("file")
+        .value_name("FILE")
+        .value_parser(clap::value_parser!(PathBuf))
+        .default_value("guests.toml")
+        .help("Guest definitions")
+}
+
+pub fn plan_command() -> Command {
+    Command::new("plan")
+        .about("Show what apply would change to match guests.toml")
+        .arg(file_arg())
+        .arg(super::remote::cluster_arg())
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+pub fn apply_command() -> Command {
+    Command::new("apply")
+        .about("Create and update VMs and containers from guests.toml")
+        .arg(file_arg())
+        .arg(super::remote::cluster_arg())
+        .arg(
+            Arg::new("restart")
+                .long("restart")
+                .action(ArgAction::SetTrue)
+                .help("Reboot running guests whose changes need it"),
+        )
+}
+
+fn with_backend<T>(matches: &ArgMatches, f: impl FnOnce(&dyn Backend) -> Result<T>) -> Result<T> {
+    if let Some(cluster) = super::remote::remote_cluster(matches) {
+        let (_, client) = super::remote::connect(&cluster)?;
+        return f(&ApiBackend::new(client, &cluster));
+    }
+    let runner = crate::command::runner();
+    if !runner.command_exists("qm") {
+        bail!("qm not found: run on a PVE node or pass --cluster");
+    }
+    let node = runner
+        .run("hostname", &["-s"])
+        .ok()
+        .filter(|r| r.success)
+        .map(|r| r.stdout.trim().to_string())
+        .context("cannot tell this node's name")?;
+    f(&LocalBackend::new(runner.as_ref(), &node))
+}
+
+fn load(matches: &ArgMatches) -> Result<GuestFile> {
+    let path = matches
+        .get_one::<PathBuf>("file")
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("guests.toml"));
+    GuestFile::load(&path)
+}
+
+pub fn handle_plan(matches: &ArgMatches) -> Result<()> {
+    let file = load(matches)?;
+    let plan = with_backend(matches, |backend| plan(backend, &file))?;
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+    } else {
+        diff::print_plan(&plan);
+    }
+    Ok(())
+}
+
+pub fn handle_apply(matches: &ArgMatches) -> Result<()> {
+    let file = load(matches)?;
+    with_backend(matches, |backend| {
+        apply(backend, &file, matches.get_flag("restart"))
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResult, MockRunner};
+    use crate::proxmox::api::Trust;
+    use crate::test_server::{Request, serve};
+
+    const GUESTS: &str = include_str!("../testdata/guests.toml");
+
+    const QM_LIST: &str = "      VMID NAME                 STATUS     MEM(MB)    BOOTDISK(GB) PID
+       102 db                   running    8192              32.00 4242
+      9000 debian-12-tmpl       stopped    2048               2.00 0
+";
+    const PCT_LIST: &str = "VMID       Status     Lock         Name
+200        stopped                 dns
+";
+    const TEMPLATE: &str = "boot: order=scsi0
+cores: 1
+ide2: local-lvm:vm-9000-cloudinit,media=cdrom
+memory: 2048
+name: debian-12-tmpl
+net0: virtio=BC:24:11:00:90:00,bridge=vmbr0
+scsi0: local-lvm:base-9000-disk-0,size=2G
+scsihw: virtio-scsi-pci
+template: 1
+";
+    const CLONED: &str = "boot: order=scsi0
+cores: 1
+ide2: local-lvm:vm-101-cloudinit,media=cdrom
+memory: 2048
+name: web
+net0: virtio=BC:24:11:00:10:10,bridge=vmbr0
+scsi0: local-lvm:vm-101-disk-0,size=2G
+scsihw: virtio-scsi-pci
+";
+    const DB: &str = "cores: 4
+memory: 4096
+name: db
+net0: virtio=BC:24:11:5E:10:02,bridge=vmbr0,tag=30
+ostype: l26
+scsi0: local-lvm:vm-102-disk-0,iothread=1,size=64G
+scsi1: tank:vm-102-disk-1,size=200G
+scsihw: virtio-scsi-single
+";
+    const DNS: &str = "cores: 1
+features: nesting=1
+hostname: dns
+memory: 512
+net0: name=eth0,bridge=vmbr0,hwaddr=BC:24:11:AA:00:01,ip=dhcp,type=veth
+onboot: 1
+rootfs: local-lvm:vm-200-disk-0,size=8G
+swap: 512
+unprivileged: 1
+";
+
+    const CLUSTER_GUESTS: &str = r#"
+[defaults]
+node = "pve1"
+storage = "local-lvm"
+
+[[vm]]
+vmid = 102
+name = "db"
+node = "pve2"
+cores = 4
+memory = 8192
+disks.scsi0 = { size = "64G", options = "iothread=1" }
+disks.scsi1 = { storage = "tank", size = "200G" }
+
+[[ct]]
+vmid = 200
+hostname = "dns"
+ostemplate = "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst"
+rootfs = { size = "8G" }
+"#;
+
+    fn local() -> MockRunner {
+        let runner = MockRunner::new();
+        runner.mock_command("qm", &["list"], CommandResult::ok(QM_LIST));
+        runner.mock_command("pct", &["list"], CommandResult::ok(PCT_LIST));
+        runner.mock_command("qm", &["config", "9000"], CommandResult::ok(TEMPLATE));
+        runner.mock_command("qm", &["config", "102"], CommandResult::ok(DB));
+        runner.mock_command("pct", &["config", "200"], CommandResult::ok(DNS));
+        runner
+    }
+
+    #[test]
+    fn local_plan_clones_updates_and_keeps() {
+        let runner = local();
+        let file = GuestFile::parse(GUESTS).unwrap();
+        let plan = plan(&LocalBackend::new(&runner, "pve1"), &file).unwrap();
+        let actions: Vec<(u32, Action)> = plan.guests.iter().map(|g| (g.vmid, g.action)).collect();
+        assert_eq!(
+            actions,
+            [
+                (101, Action::Create),
+                (102, Action::Update),
+                (200, Action::InSync)
+            ]
+        );
+        let web = &plan.guests[0];
+        assert_eq!(web.clone, Some(9000));
+        assert!(web.changes.contains(&Change::Resize {
+            disk: "scsi0".to_string(),
+            from: "2G".to_string(),
+            to: "32G".to_string(),
+        }));
+        let db = &plan.guests[1];
+        assert!(db.running);
+        assert_eq!(db.restart_keys(), ["memory"]);
+        assert!(plan.has_changes());
+    }
+
+    #[test]
+    fn local_apply_runs_qm_and_is_idempotent() {
+        let runner = local();
+        runner.mock_command("qm", &["config", "101"], CommandResult::ok(CLONED));
+        let file = GuestFile::parse(GUESTS).unwrap();
+        let backend = LocalBackend::new(&runner, "pve1");
+        assert!(apply(&backend, &file, true).unwrap());
+        let history = runner.get_history();
+        let position = |cmd: &str| {
+            history
+                .iter()
+                .position(|h| h.starts_with(cmd))
+                .unwrap_or_else(|| panic!("{cmd} not run: {history:?}"))
+        };
+        assert!(position("qm clone 9000 101 --name web --full") < position("qm set 101"));
+        assert!(position("qm set 101") < position("qm resize 101 scsi0 32G"));
+        let set_101 = &history[position("qm set 101")];
+        assert!(set_101.contains("--memory 4096"), "{set_101}");
+        assert!(set_101.contains("--net0 virtio=BC:24:11:00:10:10,bridge=vmbr0,tag=20,firewall=1"));
+        assert!(set_101.contains("--sshkeys /"));
+        assert!(set_101.contains("--tags lab;web"));
+        assert!(history.contains(&"qm set 102 --memory 8192".to_string()));
+        assert!(runner.was_called("qm reboot 102"));
+        assert!(!history.iter().any(|h| h.starts_with("pct set")));
+
+        // Once the live config matches, a second run has nothing to do.
+        let settled = local();
+        settled.mock_command(
+            "qm",
+            &["list"],
+            CommandResult::ok(&format!(
+                "{QM_LIST}       101 web                  running    4096              32.00 4343\n"
+            )),
+        );
+        settled.mock_command(
+            "qm",
+            &["config", "101"],
+            CommandResult::ok(
+                "agent: 1\nciuser: ops\ncores: 2\nide2: local-lvm:vm-101-cloudinit,media=cdrom\n\
+                 ipconfig0: ip=dhcp\nmemory: 4096\nname: web\n\
+                 net0: virtio=BC:24:11:00:10:10,bridge=vmbr0,firewall=1,tag=20\nonboot: 1\n\
+                 scsi0: local-lvm:vm-101-disk-0,discard=on,size=32G\n\
+                 sshkeys: ssh-ed25519%20AAAAC3NzaC1lZDI1NTE5AAAAIOps%20ops%40lab%0A\ntags: lab;web\n",
+            ),
+        );
+        settled.mock_command(
+            "qm",
+            &["config", "102"],
+            CommandResult::ok(&DB.replace("memory: 4096", "memory: 8192")),
+        );
+        let backend = LocalBackend::new(&settled, "pve1");
+        assert!(!apply(&backend, &file, true).unwrap());
+        assert!(
+            !settled
+                .get_history()
+                .iter()
+                .any(|h| h.starts_with("qm set") || h.starts_with("qm clone"))
+        );
+    }
+
+    #[test]
+    fn local_create_and_wrong_node() {
+        let runner = MockRunner::new();
+        runner.mock_command("qm", &["list"], CommandResult::ok(""));
+        runner.mock_command("pct", &["list"], CommandResult::ok(""));
+        let backend = LocalBackend::new(&runner, "pve1");
+
+        let elsewhere = GuestFile::parse(
+            &GUESTS
+                .replace("clone = 9000\n", "")
+                .replace("[[vm]]\nvmid = 102", "[[vm]]\nnode = \"pve2\"\nvmid = 102"),
+        )
+        .unwrap();
+        let err = plan(&backend, &elsewhere).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("guests on pve2 cannot be managed from pve1")
+        );
+
+        let only_ct = GuestFile::parse(&format!(
+            "{}{}",
+            &GUESTS[..GUESTS.find("[[vm]]").unwrap()],
+            &GUESTS[GUESTS.find("[[ct]]").unwrap()..]
+        ))
+        .unwrap();
+        assert!(apply(&backend, &only_ct, false).unwrap());
+        let create = runner
+            .get_history()
+            .into_iter()
+            .find(|h| h.starts_with("pct create"))
+            .unwrap();
+        assert!(
+            create.starts_with(
+                "pct create 200 local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst --cores 1"
+            ),
+            "{create}"
+        );
+        assert!(create.contains("--rootfs local-lvm:8"));
+        assert!(create.contains("--unprivileged 1"));
+    }
+
+    #[test]
+    fn api_apply_creates_over_rest_and_follows_tasks() {
+        let upid = "UPID:pve1:0000C0DE:0012C3D4:66F0A0B0:vzcreate:200:root@pam!ghostctl:";
+        let reply = upid.to_string();
+        let (base, seen) = serve(move |req: &Request| {
+            match (req.method.as_str(), req.path.as_str()) {
+                ("GET", "/api2/json/cluster/resources?type=vm") => (
+                    200,
+                    r#"{"data":[{"type":"qemu","vmid":102,"name":"db","node":"pve2","status":"stopped"}]}"#
+                        .to_string(),
+                ),
+                ("GET", "/api2/json/nodes/pve2/qemu/102/config") => (
+                    200,
+                    serde_json::json!({ "data": parse_config(DB) }).to_string(),
+                ),
+                ("POST", "/api2/json/nodes/pve1/lxc") => (200, format!(r#"{{"data":"{reply}"}}"#)),
+                ("PUT", "/api2/json/nodes/pve2/qemu/102/config") => {
+                    (200, r#"{"data":null}"#.to_string())
+                }
+                ("GET", path) if path.ends_with("/status") => (
+                    200,
+                    r#"{"data":{"status":"stopped","exitstatus":"OK"}}"#.to_string(),
+                ),
+                ("GET", path) if path.contains("/log") => (
+                    200,
+                    r#"{"data":[{"n":1,"t":"extracting archive"}]}"#.to_string(),
+                ),
+                _ => (404, r#"{"data":null}"#.to_string()),
+            }
+        });
+        let client = ApiClient::new(
+            &[base],
+            8006,
+            "PVEAPIToken=root@pam!ghostctl=s3cret",
+            &Trust::Insecure,
+            5,
+        )
+        .unwrap();
+        let backend = ApiBackend::new(client, "home");
+        let file = GuestFile::parse(CLUSTER_GUESTS).unwrap();
+        let plan = plan(&backend, &file).unwrap();
+        assert_eq!(plan.target, "cluster home");
+        assert_eq!(plan.guests[0].node, "pve2");
+        assert_eq!(plan.guests[1].node, "pve1");
+        assert!(apply(&backend, &file, false).unwrap());
+
+        let requests = seen.lock().unwrap();
+        let put = requests
+            .iter()
+            .find(|r| r.method == "PUT")
+            .expect("config update");
+        assert_eq!(
+            serde_json::from_str::<Value>(&put.body).unwrap(),
+            serde_json::json!({ "memory": "8192" })
+        );
+        let create = requests
+            .iter()
+            .find(|r| r.method == "POST")
+            .expect("container create");
+        let body: Value = serde_json::from_str(&create.body).unwrap();
+        assert_eq!(body["vmid"], 200);
+        assert_eq!(body["hostname"], "dns");
+        assert_eq!(body["rootfs"], "local-lvm:8");
+        assert_eq!(
+            body["ostemplate"],
+            "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst"
+        );
+        assert!(
+            requests
+                .iter()
+                .any(|r| r.path.contains("/tasks/") && r.path.ends_with("/status"))
+        );
+    }
+}
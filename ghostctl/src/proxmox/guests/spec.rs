@@ -0,0 +1,913 @@
+//! guests.toml: the VMs and containers a lab is made of, and the PVE config
+//! keys each one should end up with.
+//!
+//! ```toml
+//! [defaults]
+//! node = "pve1"
+//! storage = "local-lvm"
+//! bridge = "vmbr0"
+//!
+//! [[vm]]
+//! vmid = 101
+//! name = "web"
+//! clone = 9000
+//! cores = 2
+//! memory = 4096
+//! tags = ["web"]
+//! disks.scsi0 = { size = "32G", options = "discard=on" }
+//! nets.net0 = { tag = 20, firewall = true }
+//! cloudinit = { user = "ops", ssh_keys_file = "~/.ssh/id_ed25519.pub", ipconfig = ["ip=dhcp"] }
+//!
+//! [[ct]]
+//! vmid = 200
+//! hostname = "dns"
+//! ostemplate = "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst"
+//! memory = 512
+//! rootfs = { size = "8G" }
+//! nets.net0 = { ip = "dhcp" }
+//! ```
+
+use crate::proxmox::api::GuestKind;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const GIB: u64 = 1 << 30;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestFile {
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub vm: Vec<VmSpec>,
+    #[serde(default)]
+    pub ct: Vec<CtSpec>,
+    /// Directory relative `ssh_keys_file` paths are resolved against.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    pub node: Option<String>,
+    pub storage: Option<String>,
+    pub bridge: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VmSpec {
+    pub vmid: u32,
+    pub name: String,
+    pub node: Option<String>,
+    /// Template to full-clone instead of creating an empty VM.
+    pub clone: Option<u32>,
+    pub cores: Option<u32>,
+    pub sockets: Option<u32>,
+    /// MiB.
+    pub memory: Option<u64>,
+    pub cpu: Option<String>,
+    pub ostype: Option<String>,
+    pub scsihw: Option<String>,
+    pub agent: Option<bool>,
+    pub onboot: Option<bool>,
+    /// Omitted leaves tags alone; `[]` clears them.
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub disks: BTreeMap<String, DiskSpec>,
+    /// When any are given, `netN` devices not listed are removed.
+    #[serde(default)]
+    pub nets: BTreeMap<String, VmNet>,
+    pub cloudinit: Option<CloudInit>,
+    /// Other `qm set` options, compared and applied verbatim.
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiskSpec {
+    pub storage: Option<String>,
+    /// `32G`, `512M`; a bare number is GiB.
+    pub size: String,
+    /// Extra drive options such as `discard=on,ssd=1`.
+    pub options: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VmNet {
+    #[serde(default = "default_model")]
+    pub model: String,
+    pub bridge: Option<String>,
+    pub tag: Option<u16>,
+    #[serde(default)]
+    pub firewall: bool,
+    pub mac: Option<String>,
+}
+
+fn default_model() -> String {
+    "virtio".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloudInit {
+    #[serde(default = "default_cloudinit_drive")]
+    pub drive: String,
+    /// Defaults to `[defaults] storage`, then the first disk's storage.
+    pub storage: Option<String>,
+    pub user: Option<String>,
+    pub ssh_keys: Option<String>,
+    pub ssh_keys_file: Option<String>,
+    /// `ipconfig0`, `ipconfig1`, ... in order, e.g. `ip=dhcp`.
+    #[serde(default)]
+    pub ipconfig: Vec<String>,
+    pub nameserver: Option<String>,
+    pub searchdomain: Option<String>,
+}
+
+fn default_cloudinit_drive() -> String {
+    "ide2".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CtSpec {
+    pub vmid: u32,
+    pub hostname: String,
+    pub node: Option<String>,
+    pub ostemplate: String,
+    #[serde(default = "default_true")]
+    pub unprivileged: bool,
+    pub cores: Option<u32>,
+    /// MiB.
+    pub memory: Option<u64>,
+    /// MiB.
+    pub swap: Option<u64>,
+    /// e.g. `nesting=1,keyctl=1`.
+    pub features: Option<String>,
+    pub onboot: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    pub rootfs: DiskSpec,
+    #[serde(default)]
+    pub nets: BTreeMap<String, CtNet>,
+    /// Root's authorized keys; only used when the container is created.
+    pub ssh_keys: Option<String>,
+    pub ssh_keys_file: Option<String>,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CtNet {
+    /// Interface name inside the container (default `ethN` for `netN`).
+    pub name: Option<String>,
+    pub bridge: Option<String>,
+    pub ip: Option<String>,
+    pub gw: Option<String>,
+    pub ip6: Option<String>,
+    pub gw6: Option<String>,
+    pub tag: Option<u16>,
+    #[serde(default)]
+    pub firewall: bool,
+    pub hwaddr: Option<String>,
+}
+
+/// The state one guest should be in, as PVE config keys.
+#[derive(Debug, Clone)]
+pub struct Desired {
+    pub kind: GuestKind,
+    pub vmid: u32,
+    pub name: String,
+    pub node: Option<String>,
+    pub clone: Option<u32>,
+    pub fields: BTreeMap<String, Field>,
+    /// Settings that only exist at creation (`pct create` template, keys).
+    pub create_only: Vec<(String, String)>,
+    /// Whether `netN` keys missing from `fields` are removed.
+    pub manage_nets: bool,
+}
+
+/// A desired config value and how it compares to what PVE reports.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Text(String),
+    Flag(bool),
+    Tags(Vec<String>),
+    Disk {
+        storage: String,
+        size: u64,
+        options: Vec<(String, String)>,
+    },
+    /// A `netN` device. `model` is set for VMs (`virtio=MAC,...`); `keep`
+    /// is the address key carried over from the live value unless pinned.
+    Net {
+        model: Option<String>,
+        options: Vec<(String, String)>,
+        managed: &'static [&'static str],
+        keep: &'static str,
+    },
+    CloudDrive {
+        storage: String,
+    },
+    SshKeys(String),
+}
+
+const VM_NET_KEYS: &[&str] = &["bridge", "tag", "firewall"];
+const CT_NET_KEYS: &[&str] = &[
+    "name", "bridge", "ip", "gw", "ip6", "gw6", "tag", "firewall",
+];
+
+impl Field {
+    pub fn matches(&self, live: Option<&str>) -> bool {
+        match self {
+            Field::Text(want) => live == Some(want.as_str()),
+            Field::Flag(want) => {
+                let on = live
+                    .and_then(|v| v.split(',').next())
+                    .is_some_and(|v| v == "1" || v == "enabled=1");
+                on == *want
+            }
+            Field::Tags(want) => live.map(split_tags).unwrap_or_default() == *want,
+            Field::Disk { .. } => {
+                unreachable!("disks are compared by diff::disk_changes")
+            }
+            Field::Net {
+                model,
+                options,
+                managed,
+                keep,
+            } => {
+                let Some(live) = live else {
+                    return false;
+                };
+                let (live_model, live_opts) = parse_net(live, model.is_some());
+                if model.is_some() && live_model.as_deref() != model.as_deref() {
+                    return false;
+                }
+                let get = |opts: &[(String, String)], key: &str| {
+                    opts.iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, v)| v.clone())
+                        .filter(|v| !(key == "firewall" && v == "0"))
+                };
+                managed
+                    .iter()
+                    .all(|key| get(options, key) == get(&live_opts, key))
+                    && get(options, keep).is_none_or(|v| Some(v) == get(&live_opts, keep))
+            }
+            Field::CloudDrive { .. } => live.is_some_and(|v| v.contains("cloudinit")),
+            Field::SshKeys(want) => {
+                live.map(|v| normalize_keys(&percent_decode(v))) == Some(normalize_keys(want))
+            }
+        }
+    }
+
+    /// The value to set, keeping the live MAC address of a NIC so that
+    /// changing its bridge or VLAN does not give it a new one.
+    pub fn render(&self, live: Option<&str>) -> String {
+        match self {
+            Field::Text(value) => value.clone(),
+            Field::Flag(on) => if *on { "1" } else { "0" }.to_string(),
+            Field::Tags(tags) => tags.join(";"),
+            Field::Disk {
+                storage,
+                size,
+                options,
+            } => {
+                let mut value = format!("{storage}:{}", size / GIB);
+                for (k, v) in options {
+                    value.push_str(&format!(",{k}={v}"));
+                }
+                value
+            }
+            Field::Net {
+                model,
+                options,
+                keep,
+                ..
+            } => {
+                let (_, live_opts) = live
+                    .map(|l| parse_net(l, model.is_some()))
+                    .unwrap_or_default();
+                let address = options
+                    .iter()
+                    .chain(&live_opts)
+                    .find(|(k, _)| k == keep)
+                    .map(|(_, v)| v.clone());
+                let mut parts = Vec::new();
+                match (model, &address) {
+                    (Some(model), Some(mac)) => parts.push(format!("{model}={mac}")),
+                    (Some(model), None) => parts.push(model.clone()),
+                    (None, Some(hwaddr)) => parts.push(format!("{keep}={hwaddr}")),
+                    (None, None) => {}
+                }
+                for (k, v) in options.iter().filter(|(k, _)| k != keep) {
+                    parts.push(format!("{k}={v}"));
+                }
+                if model.is_none() {
+                    parts.push("type=veth".to_string());
+                }
+                parts.join(",")
+            }
+            Field::CloudDrive { storage } => format!("{storage}:cloudinit"),
+            Field::SshKeys(keys) => normalize_keys(keys),
+        }
+    }
+}
+
+/// `virtio=BC:24:11:00:00:01,bridge=vmbr0,tag=20` into the model and the
+/// options, with the MAC as `mac`.
+fn parse_net(value: &str, vm: bool) -> (Option<String>, Vec<(String, String)>) {
+    let mut model = None;
+    let mut options = Vec::new();
+    for (i, part) in value.split(',').enumerate() {
+        let (k, v) = part.split_once('=').unwrap_or((part, ""));
+        if vm && i == 0 {
+            model = Some(k.to_string());
+            if !v.is_empty() {
+                options.push(("mac".to_string(), v.to_string()));
+            }
+        } else if vm && k == "macaddr" {
+            options.push(("mac".to_string(), v.to_string()));
+        } else {
+            options.push((k.to_string(), v.to_string()));
+        }
+    }
+    (model, options)
+}
+
+fn split_tags(value: &str) -> Vec<String> {
+    let mut tags: Vec<String> = value
+        .split([';', ',', ' '])
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+fn normalize_keys(keys: &str) -> String {
+    keys.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// PVE stores `sshkeys` URL-encoded.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = value.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// `32G`, `512M`, `1T`, `1024K` to bytes; a bare number is GiB.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = size[digits.len()..].to_ascii_uppercase();
+    let number: f64 = digits
+        .parse()
+        .with_context(|| format!("'{size}' is not a disk size"))?;
+    let scale = match unit.trim_end_matches('B') {
+        "" | "G" => GIB,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "T" => 1 << 40,
+        _ => bail!("'{size}' is not a disk size (use K, M, G or T)"),
+    };
+    Ok((number * scale as f64) as u64)
+}
+
+/// Bytes in the largest unit that divides them, as PVE writes sizes.
+pub fn format_size(bytes: u64) -> String {
+    for (unit, scale) in [
+        ("T", 1u64 << 40),
+        ("G", GIB),
+        ("M", 1 << 20),
+        ("K", 1 << 10),
+    ] {
+        if bytes >= scale && bytes.is_multiple_of(scale) {
+            return format!("{}{unit}", bytes / scale);
+        }
+    }
+    bytes.to_string()
+}
+
+fn parse_options(options: Option<&str>) -> Vec<(String, String)> {
+    options
+        .unwrap_or_default()
+        .split(',')
+        .filter(|o| !o.is_empty())
+        .map(|o| {
+            let (k, v) = o.split_once('=').unwrap_or((o, "1"));
+            (k.trim().to_string(), v.trim().to_string())
+        })
+        .collect()
+}
+
+fn is_indexed(key: &str, prefixes: &[&str]) -> bool {
+    prefixes.iter().any(|p| {
+        key.strip_prefix(p)
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+pub fn is_net_key(key: &str) -> bool {
+    is_indexed(key, &["net"])
+}
+
+pub fn is_vm_disk_key(key: &str) -> bool {
+    is_indexed(key, &["scsi", "virtio", "sata", "ide"])
+}
+
+impl GuestFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut file = Self::parse(&text).with_context(|| format!("in {}", path.display()))?;
+        file.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(file)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: GuestFile = toml::from_str(text)?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut seen = std::collections::BTreeSet::new();
+        let ids = self
+            .vm
+            .iter()
+            .map(|v| (v.vmid, "vm"))
+            .chain(self.ct.iter().map(|c| (c.vmid, "ct")));
+        for (vmid, what) in ids {
+            if vmid < 100 {
+                bail!("{what} {vmid}: VMIDs start at 100");
+            }
+            if !seen.insert(vmid) {
+                bail!("VMID {vmid} is declared twice");
+            }
+        }
+        for vm in &self.vm {
+            let id = format!("vm {}", vm.vmid);
+            if vm.name.is_empty()
+                || !vm
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            {
+                bail!("{id}: name '{}' must be a DNS name", vm.name);
+            }
+            for (key, disk) in &vm.disks {
+                if !is_vm_disk_key(key) {
+                    bail!("{id}: '{key}' is not a disk slot (scsiN, virtioN, sataN, ideN)");
+                }
+                self.check_disk(&format!("{id} {key}"), disk)?;
+            }
+            for key in vm.nets.keys() {
+                if !is_net_key(key) {
+                    bail!("{id}: '{key}' is not a network device (netN)");
+                }
+            }
+            if let Some(ci) = &vm.cloudinit {
+                if !is_vm_disk_key(&ci.drive) || vm.disks.contains_key(&ci.drive) {
+                    bail!(
+                        "{id}: cloud-init drive '{}' must be a free disk slot",
+                        ci.drive
+                    );
+                }
+                if ci.ssh_keys.is_some() && ci.ssh_keys_file.is_some() {
+                    bail!("{id}: set ssh_keys or ssh_keys_file, not both");
+                }
+                if self.cloudinit_storage(vm, ci).is_none() {
+                    bail!("{id}: cloud-init needs a storage (or [defaults] storage)");
+                }
+            }
+        }
+        for ct in &self.ct {
+            let id = format!("ct {}", ct.vmid);
+            if ct.hostname.is_empty() {
+                bail!("{id}: hostname is empty");
+            }
+            if ct.ostemplate.is_empty() {
+                bail!("{id}: ostemplate is empty");
+            }
+            self.check_disk(&format!("{id} rootfs"), &ct.rootfs)?;
+            for key in ct.nets.keys() {
+                if !is_net_key(key) {
+                    bail!("{id}: '{key}' is not a network device (netN)");
+                }
+            }
+            if ct.ssh_keys.is_some() && ct.ssh_keys_file.is_some() {
+                bail!("{id}: set ssh_keys or ssh_keys_file, not both");
+            }
+        }
+        Ok(())
+    }
+
+    fn check_disk(&self, id: &str, disk: &DiskSpec) -> Result<()> {
+        if disk.storage.is_none() && self.defaults.storage.is_none() {
+            bail!("{id}: no storage (set it or [defaults] storage)");
+        }
+        let size = parse_size(&disk.size).with_context(|| id.to_string())?;
+        if size == 0 || !size.is_multiple_of(GIB) {
+            bail!("{id}: size must be a whole number of GiB");
+        }
+        Ok(())
+    }
+
+    fn cloudinit_storage(&self, vm: &VmSpec, ci: &CloudInit) -> Option<String> {
+        ci.storage
+            .clone()
+            .or_else(|| self.defaults.storage.clone())
+            .or_else(|| vm.disks.values().find_map(|d| d.storage.clone()))
+    }
+
+    fn disk(&self, disk: &DiskSpec) -> Result<Field> {
+        Ok(Field::Disk {
+            storage: disk
+                .storage
+                .clone()
+                .or_else(|| self.defaults.storage.clone())
+                .context("disk without storage")?,
+            size: parse_size(&disk.size)?,
+            options: parse_options(disk.options.as_deref()),
+        })
+    }
+
+    fn bridge(&self, bridge: &Option<String>) -> String {
+        bridge
+            .clone()
+            .or_else(|| self.defaults.bridge.clone())
+            .unwrap_or_else(|| "vmbr0".to_string())
+    }
+
+    fn keys(&self, literal: &Option<String>, file: &Option<String>) -> Result<Option<String>> {
+        if let Some(keys) = literal {
+            return Ok(Some(keys.clone()));
+        }
+        let Some(file) = file else {
+            return Ok(None);
+        };
+        let path = match file.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().context("no home directory")?.join(rest),
+            None => self.base_dir.join(file),
+        };
+        let keys = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(Some(keys))
+    }
+
+    fn tags(tags: &Option<Vec<String>>) -> Option<Field> {
+        tags.as_ref().map(|t| Field::Tags(split_tags(&t.join(";"))))
+    }
+
+    /// Every guest in the file, VMs first, each in VMID order.
+    pub fn desired(&self) -> Result<Vec<Desired>> {
+        let mut out = Vec::new();
+        for vm in &self.vm {
+            out.push(self.vm_desired(vm)?);
+        }
+        for ct in &self.ct {
+            out.push(self.ct_desired(ct)?);
+        }
+        out.sort_by_key(|d| (d.kind == GuestKind::Lxc, d.vmid));
+        Ok(out)
+    }
+
+    fn vm_desired(&self, vm: &VmSpec) -> Result<Desired> {
+        let mut fields = BTreeMap::new();
+        fields.insert("name".to_string(), Field::Text(vm.name.clone()));
+        let numbers = [
+            ("cores", vm.cores.map(u64::from)),
+            ("sockets", vm.sockets.map(u64::from)),
+            ("memory", vm.memory),
+        ];
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), Field::Text(value.to_string()));
+            }
+        }
+        for (key, value) in [
+            ("cpu", &vm.cpu),
+            ("ostype", &vm.ostype),
+            ("scsihw", &vm.scsihw),
+        ] {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), Field::Text(value.clone()));
+            }
+        }
+        for (key, value) in [("agent", vm.agent), ("onboot", vm.onboot)] {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), Field::Flag(value));
+            }
+        }
+        if let Some(tags) = Self::tags(&vm.tags) {
+            fields.insert("tags".to_string(), tags);
+        }
+        for (key, disk) in &vm.disks {
+            fields.insert(key.clone(), self.disk(disk)?);
+        }
+        for (key, net) in &vm.nets {
+            let mut options = vec![("bridge".to_string(), self.bridge(&net.bridge))];
+            if let Some(tag) = net.tag {
+                options.push(("tag".to_string(), tag.to_string()));
+            }
+            if net.firewall {
+                options.push(("firewall".to_string(), "1".to_string()));
+            }
+            if let Some(mac) = &net.mac {
+                options.push(("mac".to_string(), mac.to_ascii_uppercase()));
+            }
+            fields.insert(
+                key.clone(),
+                Field::Net {
+                    model: Some(net.model.clone()),
+                    options,
+                    managed: VM_NET_KEYS,
+                    keep: "mac",
+                },
+            );
+        }
+        if let Some(ci) = &vm.cloudinit {
+            let storage = self
+                .cloudinit_storage(vm, ci)
+                .context("cloud-init without storage")?;
+            fields.insert(ci.drive.clone(), Field::CloudDrive { storage });
+            let text = [
+                ("ciuser", &ci.user),
+                ("nameserver", &ci.nameserver),
+                ("searchdomain", &ci.searchdomain),
+            ];
+            for (key, value) in text {
+                if let Some(value) = value {
+                    fields.insert(key.to_string(), Field::Text(value.clone()));
+                }
+            }
+            for (i, ipconfig) in ci.ipconfig.iter().enumerate() {
+                fields.insert(format!("ipconfig{i}"), Field::Text(ipconfig.clone()));
+            }
+            if let Some(keys) = self.keys(&ci.ssh_keys, &ci.ssh_keys_file)? {
+                fields.insert("sshkeys".to_string(), Field::SshKeys(keys));
+            }
+        }
+        for (key, value) in &vm.options {
+            fields.insert(key.clone(), Field::Text(value.clone()));
+        }
+        Ok(Desired {
+            kind: GuestKind::Qemu,
+            vmid: vm.vmid,
+            name: vm.name.clone(),
+            node: vm.node.clone().or_else(|| self.defaults.node.clone()),
+            clone: vm.clone,
+            fields,
+            create_only: Vec::new(),
+            manage_nets: !vm.nets.is_empty(),
+        })
+    }
+
+    fn ct_desired(&self, ct: &CtSpec) -> Result<Desired> {
+        let mut fields = BTreeMap::new();
+        fields.insert("hostname".to_string(), Field::Text(ct.hostname.clone()));
+        let numbers = [
+            ("cores", ct.cores.map(u64::from)),
+            ("memory", ct.memory),
+            ("swap", ct.swap),
+        ];
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), Field::Text(value.to_string()));
+            }
+        }
+        if let Some(features) = &ct.features {
+            fields.insert("features".to_string(), Field::Text(features.clone()));
+        }
+        if let Some(onboot) = ct.onboot {
+            fields.insert("onboot".to_string(), Field::Flag(onboot));
+        }
+        if let Some(tags) = Self::tags(&ct.tags) {
+            fields.insert("tags".to_string(), tags);
+        }
+        fields.insert("rootfs".to_string(), self.disk(&ct.rootfs)?);
+        for (key, net) in &ct.nets {
+            let index = key.trim_start_matches("net");
+            let mut options = vec![
+                (
+                    "name".to_string(),
+                    net.name.clone().unwrap_or_else(|| format!("eth{index}")),
+                ),
+                ("bridge".to_string(), self.bridge(&net.bridge)),
+            ];
+            let text = [
+                ("ip", &net.ip),
+                ("gw", &net.gw),
+                ("ip6", &net.ip6),
+                ("gw6", &net.gw6),
+            ];
+            for (k, v) in text {
+                if let Some(v) = v {
+                    options.push((k.to_string(), v.clone()));
+                }
+            }
+            if let Some(tag) = net.tag {
+                options.push(("tag".to_string(), tag.to_string()));
+            }
+            if net.firewall {
+                options.push(("firewall".to_string(), "1".to_string()));
+            }
+            if let Some(hwaddr) = &net.hwaddr {
+                options.push(("hwaddr".to_string(), hwaddr.to_ascii_uppercase()));
+            }
+            fields.insert(
+                key.clone(),
+                Field::Net {
+                    model: None,
+                    options,
+                    managed: CT_NET_KEYS,
+                    keep: "hwaddr",
+                },
+            );
+        }
+        for (key, value) in &ct.options {
+            fields.insert(key.clone(), Field::Text(value.clone()));
+        }
+        let mut create_only = vec![
+            ("ostemplate".to_string(), ct.ostemplate.clone()),
+            (
+                "unprivileged".to_string(),
+                if ct.unprivileged { "1" } else { "0" }.to_string(),
+            ),
+        ];
+        if let Some(keys) = self.keys(&ct.ssh_keys, &ct.ssh_keys_file)? {
+            create_only.push(("ssh-public-keys".to_string(), normalize_keys(&keys)));
+        }
+        Ok(Desired {
+            kind: GuestKind::Lxc,
+            vmid: ct.vmid,
+            name: ct.hostname.clone(),
+            node: ct.node.clone().or_else(|| self.defaults.node.clone()),
+            clone: None,
+            fields,
+            create_only,
+            manage_nets: !ct.nets.is_empty(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUESTS: &str = include_str!("../testdata/guests.toml");
+
+    #[test]
+    fn example_file_parses_into_config_keys() {
+        let desired = GuestFile::parse(GUESTS).unwrap().desired().unwrap();
+        let ids: Vec<u32> = desired.iter().map(|d| d.vmid).collect();
+        assert_eq!(ids, [101, 102, 200]);
+
+        let web = &desired[0];
+        assert_eq!(web.clone, Some(9000));
+        assert_eq!(web.node.as_deref(), Some("pve1"));
+        assert_eq!(web.fields["memory"], Field::Text("4096".to_string()));
+        assert_eq!(
+            web.fields["tags"],
+            Field::Tags(vec!["lab".to_string(), "web".to_string()])
+        );
+        assert_eq!(web.fields["scsi0"].render(None), "local-lvm:32,discard=on");
+        assert_eq!(
+            web.fields["net0"].render(None),
+            "virtio,bridge=vmbr0,tag=20,firewall=1"
+        );
+        assert_eq!(web.fields["ide2"].render(None), "local-lvm:cloudinit");
+        assert_eq!(web.fields["ipconfig0"], Field::Text("ip=dhcp".to_string()));
+
+        let dns = &desired[2];
+        assert_eq!(dns.kind, GuestKind::Lxc);
+        assert_eq!(
+            dns.fields["net0"].render(None),
+            "name=eth0,bridge=vmbr0,ip=dhcp,type=veth"
+        );
+        assert_eq!(dns.fields["rootfs"].render(None), "local-lvm:8");
+        assert_eq!(
+            dns.create_only[1],
+            ("unprivileged".to_string(), "1".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let base = "[defaults]\nstorage = \"local-lvm\"\n";
+        for (body, expected) in [
+            (
+                "[[vm]]\nvmid = 101\nname = \"a\"\n[[ct]]\nvmid = 101\nhostname = \"b\"\nostemplate = \"t\"\nrootfs = { size = \"8G\" }\n",
+                "declared twice",
+            ),
+            ("[[vm]]\nvmid = 99\nname = \"a\"\n", "VMIDs start at 100"),
+            (
+                "[[vm]]\nvmid = 101\nname = \"a\"\ndisks.root = { size = \"8G\" }\n",
+                "not a disk slot",
+            ),
+            (
+                "[[vm]]\nvmid = 101\nname = \"a\"\ndisks.scsi0 = { size = \"1.5G\" }\n",
+                "whole number of GiB",
+            ),
+            (
+                "[[vm]]\nvmid = 101\nname = \"a\"\nnets.eth0 = {}\n",
+                "not a network device",
+            ),
+            ("[[vm]]\nvmid = 101\nname = \"a b\"\n", "must be a DNS name"),
+            (
+                "[[vm]]\nvmid = 101\nname = \"a\"\ndisks.ide2 = { size = \"8G\" }\ncloudinit = {}\n",
+                "free disk slot",
+            ),
+        ] {
+            let err = GuestFile::parse(&format!("{base}{body}")).unwrap_err();
+            assert!(format!("{err:#}").contains(expected), "{expected}: {err:#}");
+        }
+        let err =
+            GuestFile::parse("[[vm]]\nvmid = 101\nname = \"a\"\ndisks.scsi0 = { size = \"8G\" }\n")
+                .unwrap_err();
+        assert!(err.to_string().contains("no storage"));
+    }
+
+    #[test]
+    fn live_values_compare_semantically() {
+        let net = Field::Net {
+            model: Some("virtio".to_string()),
+            options: vec![
+                ("bridge".to_string(), "vmbr0".to_string()),
+                ("tag".to_string(), "20".to_string()),
+            ],
+            managed: VM_NET_KEYS,
+            keep: "mac",
+        };
+        assert!(net.matches(Some(
+            "virtio=BC:24:11:AA:BB:CC,bridge=vmbr0,firewall=0,tag=20"
+        )));
+        assert!(!net.matches(Some(
+            "virtio=BC:24:11:AA:BB:CC,bridge=vmbr0,firewall=1,tag=20"
+        )));
+        assert!(!net.matches(Some("e1000=BC:24:11:AA:BB:CC,bridge=vmbr0,tag=20")));
+        assert_eq!(
+            net.render(Some("virtio=BC:24:11:AA:BB:CC,bridge=vmbr1")),
+            "virtio=BC:24:11:AA:BB:CC,bridge=vmbr0,tag=20"
+        );
+
+        let tags = Field::Tags(vec!["a".to_string(), "b".to_string()]);
+        assert!(tags.matches(Some("b;a")));
+        assert!(!tags.matches(None));
+        assert!(Field::Tags(Vec::new()).matches(None));
+
+        assert!(Field::Flag(false).matches(None));
+        assert!(Field::Flag(true).matches(Some("enabled=1,fstrim_cloned_disks=1")));
+
+        let keys = Field::SshKeys("ssh-ed25519 AAAA ops@lab\n".to_string());
+        assert!(keys.matches(Some("ssh-ed25519%20AAAA%20ops%40lab%0A")));
+        assert!(
+            Field::CloudDrive {
+                storage: "local-lvm".to_string()
+            }
+            .matches(Some("local-lvm:vm-101-cloudinit,media=cdrom"))
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("32G").unwrap(), 32 * GIB);
+        assert_eq!(parse_size("32").unwrap(), 32 * GIB);
+        assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1T").unwrap(), 1 << 40);
+        assert!(parse_size("big").is_err());
+        assert_eq!(format_size(64 * GIB), "64G");
+        assert_eq!(format_size(1536 << 20), "1536M");
+        assert_eq!(format_size(1 << 40), "1T");
+    }
+}
@@ -2,11 +2,21 @@ use dialoguer::{Confirm, Input, Select, theme::ColorfulTheme};
 use std::process::Command;
 
 pub mod advanced_security;
+pub mod api;
 pub mod backup_rotation;
+pub mod backup_server;
+pub mod cloud_images;
+pub mod config;
 pub mod enhanced;
 pub mod errors;
+pub mod firewall;
 pub mod firewall_automation;
+pub mod guests;
 pub mod helper;
+pub mod remote;
+pub mod rolling_upgrade;
+pub mod script_lint;
+pub mod script_registry;
 pub mod script_safety;
 pub mod storage_migration;
 pub mod template_management;
@@ -173,10 +183,14 @@ pub fn browse_all_scripts() {
 }
 
 fn confirm_and_run_script(name: &str, url: &str) {
+    confirm_and_run_script_with(name, url, script_safety::ScriptSafetyConfig::default());
+}
+
+fn confirm_and_run_script_with(name: &str, url: &str, config: script_safety::ScriptSafetyConfig) {
     println!("\n📜 Proxmox Script Execution");
     println!("═══════════════════════════");
 
-    match script_safety::safe_run_script(name, url) {
+    match script_safety::safe_run_script_with_config(name, url, config) {
         Ok(true) => println!("✅ Script '{}' executed successfully.", name),
         Ok(false) => println!("⏭️  Script execution was cancelled or skipped."),
         Err(e) => println!("❌ Failed to run '{}': {}", name, e),
@@ -326,6 +340,7 @@ fn pve_upgrade_guide() {
         "📖 View upgrade guide (8→9)",
         "🌐 Open official upgrade docs",
         "🔧 Run community upgrade script",
+        "🌊 Rolling cluster upgrade (resumable)",
         "⬅️  Back",
     ];
 
@@ -444,9 +459,15 @@ fn pve_upgrade_guide() {
 
             if confirm {
                 let url = "https://raw.githubusercontent.com/community-scripts/ProxmoxVE/main/tools/pve/pve8-upgrade.sh";
-                confirm_and_run_script("PVE Upgrade Script", url);
+                // Snapshot only once the script is confirmed to run.
+                let config = script_safety::ScriptSafetyConfig {
+                    transaction: Some("proxmox upgrade".to_string()),
+                    ..Default::default()
+                };
+                confirm_and_run_script_with("PVE Upgrade Script", url, config);
             }
         }
+        5 => rolling_upgrade::menu(),
         _ => return,
     }
 }
@@ -463,6 +484,7 @@ fn pbs_management_guide() {
         "📖 View PBS setup guide",
         "🌐 Open PBS documentation",
         "🔧 Run community PBS script",
+        "🩺 Health report (API)",
         "⬅️  Back",
     ];
 
@@ -630,6 +652,7 @@ fn pbs_management_guide() {
                 confirm_and_run_script("PBS Install Script", url);
             }
         }
+        6 => backup_server::report_menu(),
         _ => return,
     }
 }
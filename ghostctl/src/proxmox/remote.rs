@@ -0,0 +1,302 @@
+//! `pve vm|ct --cluster` and `pve clusters`: guest management over the REST
+//! API, for running ghostctl somewhere other than a PVE node.
+
+use super::api::{ApiClient, Guest, GuestKind};
+use super::config::{PveCluster, PveConfig};
+use crate::tui;
+use crate::utils::is_dry_run;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::time::Duration;
+
+/// How long `start`/`stop` follow a task before giving up on it.
+pub(crate) const TASK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// `--cluster NAME`, read by [`remote_cluster`].
+pub fn cluster_arg() -> Arg {
+    Arg::new("cluster")
+        .long("cluster")
+        .global(true)
+        .value_name("NAME")
+        .help("Manage a [[pve.clusters]] entry over the API")
+}
+
+/// `--cluster` and `--node` for the `pve vm` and `pve ct` commands.
+pub fn target_args(command: Command) -> Command {
+    command.arg(cluster_arg()).arg(
+        Arg::new("node")
+            .long("node")
+            .global(true)
+            .value_name("NODE")
+            .help("Cluster node (default: wherever the guest runs)"),
+    )
+}
+
+/// `--json` for `list`.
+pub fn list_args(command: Command) -> Command {
+    command.arg(
+        Arg::new("json")
+            .long("json")
+            .action(ArgAction::SetTrue)
+            .help("Output as JSON (with --cluster)"),
+    )
+}
+
+/// `--no-wait` for `start`/`stop`.
+pub fn action_args(command: Command) -> Command {
+    command.arg(
+        Arg::new("no-wait")
+            .long("no-wait")
+            .action(ArgAction::SetTrue)
+            .help("Print the task UPID instead of following it (with --cluster)"),
+    )
+}
+
+/// The cluster to talk to over the API: `--cluster`, or `pve.default_cluster`
+/// when this machine is not a PVE node. `None` means use local `qm`/`pct`.
+pub fn remote_cluster(matches: &ArgMatches) -> Option<String> {
+    if let Some(name) = matches.get_one::<String>("cluster") {
+        return Some(name.clone());
+    }
+    if std::path::Path::new("/etc/pve").exists() {
+        return None;
+    }
+    PveConfig::load().default_cluster
+}
+
+pub(crate) fn connect(name: &str) -> Result<(PveCluster, ApiClient)> {
+    let cfg = PveConfig::load();
+    let problems = cfg.validate();
+    if !problems.is_empty() {
+        bail!("invalid [pve] config:\n  {}", problems.join("\n  "));
+    }
+    let cluster = cfg.cluster(name)?.clone();
+    let client = ApiClient::for_cluster(&cluster)?;
+    Ok((cluster, client))
+}
+
+fn gib(bytes: u64) -> String {
+    format!("{:.1}G", bytes as f64 / (1u64 << 30) as f64)
+}
+
+fn uptime(secs: u64) -> String {
+    match secs {
+        0 => "-".to_string(),
+        s if s >= 86_400 => format!("{}d{}h", s / 86_400, s % 86_400 / 3600),
+        s => format!("{}h{}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+pub fn list(cluster: &str, node: Option<&str>, kind: GuestKind, json: bool) -> Result<()> {
+    let (_, client) = connect(cluster)?;
+    let guests: Vec<Guest> = client
+        .guests()?
+        .into_iter()
+        .filter(|g| g.kind == kind && node.is_none_or(|n| g.node == n))
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&guests)?);
+        return Ok(());
+    }
+    if guests.is_empty() {
+        println!("No {}s on {cluster}.", kind.label());
+        return Ok(());
+    }
+    println!(
+        "{:>6} {:<24} {:<10} {:<9} {:>7} {:>8} {:>8}",
+        "VMID", "NAME", "NODE", "STATUS", "CPU", "MEM", "UPTIME"
+    );
+    for g in &guests {
+        let status = if g.template == 1 {
+            "template"
+        } else {
+            g.status.as_str()
+        };
+        println!(
+            "{:>6} {:<24} {:<10} {:<9} {:>6.0}% {:>8} {:>8}",
+            g.vmid,
+            g.name,
+            g.node,
+            status,
+            g.cpu * 100.0,
+            gib(g.maxmem),
+            uptime(g.uptime)
+        );
+    }
+    Ok(())
+}
+
+/// Run `action` on a guest and follow the task unless `wait` is false.
+pub fn action(
+    cluster: &str,
+    node: Option<&str>,
+    kind: GuestKind,
+    vmid: &str,
+    action: &str,
+    wait: bool,
+) -> Result<()> {
+    let vmid: u32 = vmid
+        .parse()
+        .with_context(|| format!("'{vmid}' is not a VMID"))?;
+    let (_, client) = connect(cluster)?;
+    let guest = match node {
+        // With --node the lookup is skipped, so tokens limited to one node work.
+        Some(node) => Guest::new(vmid, node, kind),
+        None => {
+            let guest = client.guest(vmid)?;
+            if guest.kind != kind {
+                bail!(
+                    "{vmid} is a {}; use `pve {}`",
+                    guest.kind.label(),
+                    if guest.kind == GuestKind::Qemu {
+                        "vm"
+                    } else {
+                        "ct"
+                    }
+                );
+            }
+            guest
+        }
+    };
+    let what = format!("{} {vmid} on {cluster}/{}", kind.label(), guest.node);
+    if is_dry_run() {
+        tui::info(&format!("[DRY RUN] Would {action} {what}"));
+        return Ok(());
+    }
+    let upid = client.guest_action(&guest, action)?;
+    if !wait {
+        println!("{}", upid.raw);
+        return Ok(());
+    }
+    tui::info(&format!("{action} {what} ({})", upid.task_type));
+    client.wait_task(&upid, Duration::from_secs(1), TASK_TIMEOUT, |line| {
+        println!("  {line}")
+    })?;
+    tui::success(&format!("{action} {what}: done"));
+    Ok(())
+}
+
+pub fn clusters_command() -> Command {
+    Command::new("clusters")
+        .about("List [[pve.clusters]] and check that each answers")
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+pub fn fingerprint_command() -> Command {
+    Command::new("fingerprint")
+        .about("Show a node's certificate fingerprint for pinning")
+        .arg(
+            Arg::new("host")
+                .required(true)
+                .value_name("HOST[:PORT]")
+                .help("PVE (8006) or PBS (8007) host"),
+        )
+}
+
+pub fn handle_clusters(matches: &ArgMatches) -> Result<()> {
+    let cfg = PveConfig::load();
+    for problem in cfg.validate() {
+        tui::warn(&problem);
+    }
+    let mut report = Vec::new();
+    for cluster in &cfg.clusters {
+        let checked = ApiClient::for_cluster(cluster).and_then(|client| {
+            let version = client.version()?;
+            let nodes = client.nodes()?;
+            Ok((client.endpoint().to_string(), version, nodes))
+        });
+        report.push(match checked {
+            Ok((endpoint, version, nodes)) => serde_json::json!({
+                "name": cluster.name,
+                "ok": true,
+                "endpoint": endpoint,
+                "version": version.version,
+                "nodes": nodes,
+            }),
+            Err(e) => serde_json::json!({
+                "name": cluster.name,
+                "ok": false,
+                "error": format!("{e:#}"),
+            }),
+        });
+    }
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    if cfg.clusters.is_empty() {
+        println!("No PVE clusters configured: add [[pve.clusters]] to config.toml");
+        return Ok(());
+    }
+    for entry in &report {
+        let name = entry["name"].as_str().unwrap_or_default();
+        let default = if cfg.default_cluster.as_deref() == Some(name) {
+            " (default)"
+        } else {
+            ""
+        };
+        if entry["ok"] == true {
+            let nodes: Vec<String> = entry["nodes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|n| {
+                    format!(
+                        "{}{}",
+                        n["node"].as_str().unwrap_or_default(),
+                        if n["status"] == "online" {
+                            ""
+                        } else {
+                            " (offline)"
+                        }
+                    )
+                })
+                .collect();
+            tui::success(&format!(
+                "{name}{default}: PVE {} via {} - nodes {}",
+                entry["version"].as_str().unwrap_or_default(),
+                entry["endpoint"].as_str().unwrap_or_default(),
+                nodes.join(", ")
+            ));
+        } else {
+            tui::error(&format!(
+                "{name}{default}: {}",
+                entry["error"].as_str().unwrap_or_default()
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_fingerprint(matches: &ArgMatches) -> Result<()> {
+    let target = matches
+        .get_one::<String>("host")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let (host, port) = match target.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.starts_with('[') => (
+            host.trim_matches(['[', ']']),
+            port.parse::<u16>()
+                .with_context(|| format!("invalid port in '{target}'"))?,
+        ),
+        _ => (target, 8006),
+    };
+    let stream = crate::networking::tls::connect(host, port, Duration::from_secs(10))?;
+    let cert = stream
+        .conn
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .context("server sent no certificate")?;
+    let info = crate::networking::tls::certificate_info(cert)?;
+    println!("{}", super::api::fingerprint(cert));
+    tui::info(&format!(
+        "{} (issuer {}, valid until {}). Compare with `pvenode cert info` on the node before pinning.",
+        info.subject, info.issuer, info.validity_end
+    ));
+    Ok(())
+}
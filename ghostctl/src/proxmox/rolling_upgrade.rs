@@ -0,0 +1,1570 @@
+//! Resumable rolling upgrade of a Proxmox VE cluster (`ghostctl pve upgrade`).
+//!
+//! Nodes are upgraded one at a time, each through the same steps: pre-checks,
+//! drain, upgrade, reboot, wait for the node, post-checks, migrate guests
+//! back. The run is saved to `<state dir>/pve-upgrade/state.json` after every
+//! step and every guest moved, so an interrupted upgrade (a dropped SSH
+//! session, this machine rebooting, a failed gate) continues where it stopped
+//! with `pve upgrade resume`. A failed gate stops the run and leaves the node
+//! on that step; every step is safe to repeat, so resuming runs it again.
+//!
+//! Cluster-wide queries (`pvesh`, `pvecm`, `ha-manager`, `ceph`) run here;
+//! commands for a node go over `ssh root@<node>`, the cluster's own SSH
+//! trust, or run directly when the node is this machine.
+
+use super::api::{GuestKind, TaskStatus};
+use crate::command::{CommandResult, CommandRunner};
+use crate::tui;
+use crate::utils::shell_quote;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Proxmox VE major version `start` upgrades to unless `--target` is given.
+pub const DEFAULT_TARGET: u32 = 9;
+
+const BOOT_ID: &str = "/proc/sys/kernel/random/boot_id";
+/// Prints every APT source file, each after a `==> path` line.
+const APT_SOURCES: &str = "for f in /etc/apt/sources.list /etc/apt/sources.list.d/*.list \
+    /etc/apt/sources.list.d/*.sources; do [ -f \"$f\" ] && echo \"==> $f\" && cat \"$f\"; done; true";
+const CEPH_CONF: &str = "/etc/pve/ceph.conf";
+
+/// HA service states that mean a resource is moving or being recovered.
+const HA_BUSY: [&str; 5] = ["migrate", "relocate", "fence", "recovery", "error"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    PreCheck,
+    Drain,
+    Upgrade,
+    Reboot,
+    WaitOnline,
+    PostCheck,
+    MigrateBack,
+    Done,
+}
+
+impl Step {
+    pub fn label(self) -> &'static str {
+        match self {
+            Step::PreCheck => "pre-checks",
+            Step::Drain => "drain",
+            Step::Upgrade => "upgrade",
+            Step::Reboot => "reboot",
+            Step::WaitOnline => "wait for node",
+            Step::PostCheck => "post-checks",
+            Step::MigrateBack => "migrate guests back",
+            Step::Done => "done",
+        }
+    }
+}
+
+/// A guest moved off a node while it was drained.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Migration {
+    pub vmid: u32,
+    pub kind: GuestKind,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeState {
+    pub name: String,
+    pub step: Step,
+    /// Guests to bring back after the upgrade, removed as they return.
+    #[serde(default)]
+    pub migrated: Vec<Migration>,
+    /// Boot id before the reboot, to tell when the node has rebooted.
+    #[serde(default)]
+    pub boot_id: Option<String>,
+    /// Whether this run set Ceph `noout` for the node's reboot.
+    #[serde(default)]
+    pub noout: bool,
+    /// Why the last attempt at `step` stopped.
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub finished: Option<String>,
+}
+
+/// A rolling upgrade, as saved in `state.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    /// Proxmox VE major version the nodes are upgraded to.
+    pub target: u32,
+    pub started: String,
+    pub nodes: Vec<NodeState>,
+}
+
+impl Run {
+    pub fn new(nodes: &[String], target: u32) -> Self {
+        Self {
+            target,
+            started: chrono::Local::now().to_rfc3339(),
+            nodes: nodes
+                .iter()
+                .map(|name| NodeState {
+                    name: name.clone(),
+                    step: Step::PreCheck,
+                    migrated: Vec::new(),
+                    boot_id: None,
+                    noout: false,
+                    error: None,
+                    finished: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.nodes.iter().all(|n| n.step == Step::Done)
+    }
+
+    fn upgraded(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|n| n.step == Step::Done)
+            .map(|n| n.name.clone())
+            .collect()
+    }
+}
+
+/// One gate condition and what was seen.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok,
+            detail: detail.into(),
+        }
+    }
+}
+
+fn print_checks(checks: &[Check]) {
+    for check in checks {
+        let line = format!("{}: {}", check.name, check.detail);
+        if check.ok {
+            tui::success(&line);
+        } else {
+            tui::error(&line);
+        }
+    }
+}
+
+/// Print `checks` and fail unless all of them passed.
+fn gate(checks: &[Check]) -> Result<()> {
+    print_checks(checks);
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|c| !c.ok)
+        .map(|c| c.name.as_str())
+        .collect();
+    if !failed.is_empty() {
+        bail!("gate failed: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// `Quorate: Yes` in `pvecm status`.
+pub fn quorate(pvecm_status: &str) -> bool {
+    pvecm_status.lines().any(|line| {
+        line.trim()
+            .strip_prefix("Quorate:")
+            .is_some_and(|v| v.trim() == "Yes")
+    })
+}
+
+/// HA services in `ha-manager status` that are migrating or recovering.
+pub fn ha_busy(ha_status: &str) -> Vec<String> {
+    ha_status
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("service "))
+        .filter(|line| {
+            line.rsplit_once(',')
+                .map(|(_, state)| state.trim().trim_end_matches(')'))
+                .is_some_and(|state| HA_BUSY.contains(&state))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Major version from the `proxmox-ve:` line of `pveversion --verbose`.
+pub fn pve_major(pveversion: &str) -> Option<u32> {
+    pveversion.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("proxmox-ve:")?
+            .trim()
+            .split('.')
+            .next()?
+            .parse()
+            .ok()
+    })
+}
+
+/// `FAIL:` and `WARN:` lines of a `pveXtoY` checker run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckerReport {
+    pub failures: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+pub fn parse_checker(output: &str) -> CheckerReport {
+    let mut report = CheckerReport::default();
+    for line in output.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("FAIL:") {
+            report.failures.push(rest.trim().to_string());
+        } else if let Some(rest) = line.strip_prefix("WARN:") {
+            report.warnings.push(rest.trim().to_string());
+        }
+    }
+    report
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ClusterNode {
+    node: String,
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Resource {
+    vmid: u32,
+    node: String,
+    #[serde(rename = "type")]
+    kind: GuestKind,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    template: u8,
+}
+
+pub struct Orchestrator<'a> {
+    runner: &'a dyn CommandRunner,
+    dir: PathBuf,
+    /// This machine's node name; its commands run without SSH.
+    local: String,
+    pub poll: Duration,
+    pub reboot_timeout: Duration,
+    pub ceph_timeout: Duration,
+    /// Longest a single migration task may run.
+    pub task_timeout: Duration,
+}
+
+impl<'a> Orchestrator<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Result<Self> {
+        let hostname = runner
+            .run("hostname", &["-s"])
+            .context("failed to run hostname")?;
+        Ok(Self::with_dir(
+            runner,
+            crate::support::state_dir().join("pve-upgrade"),
+            hostname.stdout.trim(),
+        ))
+    }
+
+    pub fn with_dir(runner: &'a dyn CommandRunner, dir: PathBuf, local: &str) -> Self {
+        Self {
+            runner,
+            dir,
+            local: local.to_string(),
+            poll: Duration::from_secs(10),
+            reboot_timeout: Duration::from_secs(15 * 60),
+            ceph_timeout: Duration::from_secs(30 * 60),
+            task_timeout: Duration::from_secs(60 * 60),
+        }
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+
+    pub fn load(&self) -> Result<Option<Run>> {
+        let path = self.state_path();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .with_context(|| format!("failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the state through a temporary file so a crash never leaves it
+    /// half written.
+    pub fn save(&self, run: &Run) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let path = self.state_path();
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(run)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn discard(&self) -> Result<()> {
+        match std::fs::remove_file(self.state_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn on_node(&self, node: &str, cmd: &str, args: &[&str]) -> Result<CommandResult> {
+        let result = if node == self.local {
+            self.runner.run(cmd, args)
+        } else {
+            let remote = std::iter::once(cmd)
+                .chain(args.iter().copied())
+                .map(shell_quote)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let host = format!("root@{node}");
+            self.runner.run(
+                "ssh",
+                &[
+                    "-o",
+                    "BatchMode=yes",
+                    "-o",
+                    "ConnectTimeout=10",
+                    &host,
+                    &remote,
+                ],
+            )
+        };
+        result.with_context(|| format!("failed to run {cmd} on {node}"))
+    }
+
+    fn node_output(&self, node: &str, cmd: &str, args: &[&str]) -> Result<String> {
+        let out = self.on_node(node, cmd, args)?;
+        if !out.success {
+            bail!("{cmd} on {node} failed: {}", failure(&out));
+        }
+        Ok(out.stdout)
+    }
+
+    fn output(&self, cmd: &str, args: &[&str]) -> Result<String> {
+        let out = self
+            .runner
+            .run(cmd, args)
+            .with_context(|| format!("failed to run {cmd}"))?;
+        if !out.success {
+            bail!("{cmd} {} failed: {}", args.join(" "), failure(&out));
+        }
+        Ok(out.stdout)
+    }
+
+    fn pvesh_get<T: serde::de::DeserializeOwned>(&self, path: &str, extra: &[&str]) -> Result<T> {
+        let mut args = vec!["get", path];
+        args.extend(extra);
+        args.extend(["--output-format", "json"]);
+        let out = self.output("pvesh", &args)?;
+        serde_json::from_str(&out).with_context(|| format!("unexpected pvesh output for {path}"))
+    }
+
+    fn nodes(&self) -> Result<Vec<ClusterNode>> {
+        self.pvesh_get("/nodes", &[])
+    }
+
+    fn resources(&self) -> Result<Vec<Resource>> {
+        self.pvesh_get("/cluster/resources", &["--type", "vm"])
+    }
+
+    fn running_on(&self, node: &str) -> Result<Vec<Resource>> {
+        let mut guests: Vec<Resource> = self
+            .resources()?
+            .into_iter()
+            .filter(|r| r.node == node && r.status == "running" && r.template == 0)
+            .collect();
+        guests.sort_by_key(|r| r.vmid);
+        Ok(guests)
+    }
+
+    fn uses_ceph(&self) -> bool {
+        self.runner.file_exists(CEPH_CONF)
+    }
+
+    /// Call `ready` until it is true or `timeout` has passed.
+    fn wait_for(&self, timeout: Duration, mut ready: impl FnMut() -> Result<bool>) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if ready()? {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            std::thread::sleep(self.poll);
+        }
+    }
+
+    /// Quorum, every node online, HA idle, and Ceph healthy within
+    /// `ceph_wait`.
+    fn cluster_checks(&self, ceph_wait: Duration) -> Result<Vec<Check>> {
+        let mut checks = Vec::new();
+        let pvecm = self.output("pvecm", &["status"])?;
+        checks.push(if quorate(&pvecm) {
+            Check::new("quorum", true, "cluster is quorate")
+        } else {
+            Check::new("quorum", false, "cluster is not quorate")
+        });
+
+        let offline: Vec<String> = self
+            .nodes()?
+            .into_iter()
+            .filter(|n| n.status != "online")
+            .map(|n| n.node)
+            .collect();
+        checks.push(if offline.is_empty() {
+            Check::new("nodes", true, "all nodes online")
+        } else {
+            Check::new("nodes", false, format!("offline: {}", offline.join(", ")))
+        });
+
+        // Without HA resources `ha-manager status` only reports the quorum.
+        let ha = self.output("ha-manager", &["status"]).unwrap_or_default();
+        let busy = ha_busy(&ha);
+        checks.push(if busy.is_empty() {
+            Check::new("ha", true, "no HA migrations or recoveries pending")
+        } else {
+            Check::new("ha", false, busy.join("; "))
+        });
+
+        if self.uses_ceph() {
+            let mut health = String::new();
+            let healthy = self.wait_for(ceph_wait, || {
+                health = self
+                    .output("ceph", &["health"])
+                    .unwrap_or_else(|e| format!("{e:#}"));
+                Ok(health.trim().starts_with("HEALTH_OK"))
+            })?;
+            checks.push(Check::new("ceph", healthy, health.trim()));
+        }
+        Ok(checks)
+    }
+
+    /// The release checker (`pve8to9 --full`) on the node; failures block,
+    /// warnings are shown.
+    fn checker(&self, node: &str, target: u32) -> Result<Check> {
+        let version = self.node_output(node, "pveversion", &["--verbose"])?;
+        let major = pve_major(&version).context("cannot read the Proxmox VE version")?;
+        let tool = format!("pve{}to{target}", target.saturating_sub(1));
+        if major >= target {
+            return Ok(Check::new(&tool, true, format!("already on {major}.x")));
+        }
+        if major + 1 != target {
+            return Ok(Check::new(
+                &tool,
+                false,
+                format!("on {major}.x; upgrade one major version at a time"),
+            ));
+        }
+        let out = self.on_node(node, &tool, &["--full"])?;
+        if !out.success && out.stdout.trim().is_empty() {
+            return Ok(Check::new(
+                &tool,
+                false,
+                format!("cannot run {tool}: {}", failure(&out)),
+            ));
+        }
+        let report = parse_checker(&out.stdout);
+        let mut detail = match report.failures.as_slice() {
+            [] => "no failures".to_string(),
+            failures => failures.join("; "),
+        };
+        if !report.warnings.is_empty() {
+            detail.push_str(&format!(
+                " ({} warning(s): {})",
+                report.warnings.len(),
+                report.warnings.join("; ")
+            ));
+        }
+        Ok(Check::new(&tool, report.failures.is_empty(), detail))
+    }
+
+    /// Every enabled APT source on the node must already name the target's
+    /// Debian release, or dist-upgrade stays on the current major version.
+    fn apt_sources(&self, node: &str, target: u32) -> Result<Check> {
+        let Some(codename) = debian_codename(target) else {
+            return Ok(Check::new(
+                "apt sources",
+                false,
+                format!("no known Debian release for Proxmox VE {target}"),
+            ));
+        };
+        let listing = self.node_output(node, "sh", &["-c", APT_SOURCES])?;
+        let stale = stale_sources(&listing, codename);
+        Ok(if stale.is_empty() {
+            Check::new(
+                "apt sources",
+                true,
+                format!("all enabled sources on {codename}"),
+            )
+        } else {
+            Check::new(
+                "apt sources",
+                false,
+                format!(
+                    "not on {codename}: {}; switch them before upgrading",
+                    stale.join(", ")
+                ),
+            )
+        })
+    }
+
+    pub fn pre_checks(&self, node: &str, target: u32) -> Result<Vec<Check>> {
+        let mut checks = self.cluster_checks(Duration::ZERO)?;
+        checks.push(self.checker(node, target)?);
+        checks.push(self.apt_sources(node, target)?);
+        Ok(checks)
+    }
+
+    /// Validate the node list and save a new run.
+    pub fn start(&self, nodes: &[String], target: u32) -> Result<Run> {
+        if let Some(run) = self.load()?
+            && !run.is_done()
+        {
+            bail!(
+                "an upgrade started {} is not finished: `pve upgrade resume` or `pve upgrade abort`",
+                run.started
+            );
+        }
+        if nodes.is_empty() {
+            bail!("no nodes given");
+        }
+        let known: Vec<String> = self.nodes()?.into_iter().map(|n| n.node).collect();
+        for (i, node) in nodes.iter().enumerate() {
+            if !known.contains(node) {
+                bail!(
+                    "'{node}' is not a cluster node (have: {})",
+                    known.join(", ")
+                );
+            }
+            if nodes[..i].contains(node) {
+                bail!("'{node}' is listed twice");
+            }
+        }
+        if let Some(pos) = nodes.iter().position(|n| *n == self.local)
+            && pos + 1 != nodes.len()
+        {
+            bail!(
+                "{} is this machine: list it last, or run from another node",
+                self.local
+            );
+        }
+        let run = Run::new(nodes, target);
+        self.save(&run)?;
+        Ok(run)
+    }
+
+    /// Advance the run until every node is done or a gate fails.
+    pub fn resume(&self, run: &mut Run) -> Result<()> {
+        while let Some(idx) = run.nodes.iter().position(|n| n.step != Step::Done) {
+            let node = run.nodes[idx].name.clone();
+            let step = run.nodes[idx].step;
+            tui::info(&format!("{node}: {}", step.label()));
+            match self.step(run, idx) {
+                Ok(next) => {
+                    let state = &mut run.nodes[idx];
+                    state.step = next;
+                    state.error = None;
+                    if next == Step::Done {
+                        state.finished = Some(chrono::Local::now().to_rfc3339());
+                        tui::success(&format!("{node} upgraded"));
+                    }
+                    self.save(run)?;
+                }
+                Err(e) => {
+                    run.nodes[idx].error = Some(format!("{e:#}"));
+                    self.save(run)?;
+                    return Err(e.context(format!(
+                        "{node}: {} stopped the upgrade; fix it and run `ghostctl pve upgrade resume`",
+                        step.label()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the node's current step; returns the step that follows.
+    fn step(&self, run: &mut Run, idx: usize) -> Result<Step> {
+        let node = run.nodes[idx].name.clone();
+        match run.nodes[idx].step {
+            Step::PreCheck => {
+                gate(&self.pre_checks(&node, run.target)?)?;
+                Ok(Step::Drain)
+            }
+            Step::Drain => {
+                self.drain(run, idx)?;
+                Ok(Step::Upgrade)
+            }
+            Step::Upgrade => {
+                self.upgrade(&node)?;
+                Ok(Step::Reboot)
+            }
+            Step::Reboot => {
+                self.reboot(run, idx)?;
+                Ok(Step::WaitOnline)
+            }
+            Step::WaitOnline => {
+                self.wait_online(&run.nodes[idx])?;
+                Ok(Step::PostCheck)
+            }
+            Step::PostCheck => {
+                self.post_checks(run, idx)?;
+                Ok(Step::MigrateBack)
+            }
+            Step::MigrateBack => {
+                self.migrate_back(run, idx)?;
+                Ok(Step::Done)
+            }
+            Step::Done => Ok(Step::Done),
+        }
+    }
+
+    fn drain(&self, run: &mut Run, idx: usize) -> Result<()> {
+        let node = run.nodes[idx].name.clone();
+        let online: Vec<String> = self
+            .nodes()?
+            .into_iter()
+            .filter(|n| n.node != node && n.status == "online")
+            .map(|n| n.node)
+            .collect();
+        // Guests can move to a newer version but not back, so nodes this
+        // run already upgraded take them first.
+        let upgraded = run.upgraded();
+        let preferred: Vec<&String> = online.iter().filter(|n| upgraded.contains(n)).collect();
+        let targets = if preferred.is_empty() {
+            online.iter().collect()
+        } else {
+            preferred
+        };
+        let guests = self.running_on(&node)?;
+        if !guests.is_empty() && targets.is_empty() {
+            bail!("no online node to move guests to");
+        }
+        for (i, guest) in guests.iter().enumerate() {
+            let target = targets[i % targets.len()];
+            self.migrate(guest.kind, guest.vmid, &node, target)?;
+            run.nodes[idx].migrated.push(Migration {
+                vmid: guest.vmid,
+                kind: guest.kind,
+                target: target.clone(),
+            });
+            self.save(run)?;
+        }
+        let left: Vec<String> = self
+            .running_on(&node)?
+            .iter()
+            .map(|r| r.vmid.to_string())
+            .collect();
+        if !left.is_empty() {
+            bail!("still running on {node}: {}", left.join(", "));
+        }
+        Ok(())
+    }
+
+    fn migrate(&self, kind: GuestKind, vmid: u32, from: &str, to: &str) -> Result<()> {
+        tui::info(&format!(
+            "moving {} {vmid} from {from} to {to}",
+            kind.label()
+        ));
+        let path = format!("/nodes/{from}/{}/{vmid}/migrate", kind.path());
+        let mut args = vec!["create", path.as_str(), "--target", to];
+        match kind {
+            GuestKind::Qemu => args.extend(["--online", "1", "--with-local-disks", "1"]),
+            GuestKind::Lxc => args.extend(["--restart", "1"]),
+        }
+        args.extend(["--output-format", "json"]);
+        let out = self.output("pvesh", &args)?;
+        let upid = out.trim().trim_matches('"');
+        self.wait_task(from, upid)
+            .with_context(|| format!("moving {} {vmid} to {to}", kind.label()))
+    }
+
+    fn wait_task(&self, node: &str, upid: &str) -> Result<()> {
+        let path = format!("/nodes/{node}/tasks/{upid}/status");
+        let deadline = Instant::now() + self.task_timeout;
+        loop {
+            let status: TaskStatus = self.pvesh_get(&path, &[])?;
+            if status.status == "stopped" {
+                if status.succeeded() {
+                    return Ok(());
+                }
+                bail!(
+                    "task {upid} ended with {}",
+                    status.exitstatus.as_deref().unwrap_or("no status")
+                );
+            }
+            if Instant::now() >= deadline {
+                bail!(
+                    "task {upid} still running after {} minute(s); check it on {node} before resuming",
+                    self.task_timeout.as_secs() / 60
+                );
+            }
+            std::thread::sleep(self.poll);
+        }
+    }
+
+    fn upgrade(&self, node: &str) -> Result<()> {
+        self.node_output(node, "apt-get", &["update"])?;
+        let out = self.on_node(
+            node,
+            "env",
+            &[
+                "DEBIAN_FRONTEND=noninteractive",
+                "apt-get",
+                "-y",
+                "-o",
+                "Dpkg::Options::=--force-confdef",
+                "-o",
+                "Dpkg::Options::=--force-confold",
+                "dist-upgrade",
+            ],
+        )?;
+        std::fs::create_dir_all(&self.dir)?;
+        let log = self.dir.join(format!("{node}-dist-upgrade.log"));
+        std::fs::write(&log, format!("{}{}", out.stdout, out.stderr))
+            .with_context(|| format!("failed to write {}", log.display()))?;
+        if !out.success {
+            bail!("dist-upgrade failed, see {}", log.display());
+        }
+        Ok(())
+    }
+
+    fn reboot(&self, run: &mut Run, idx: usize) -> Result<()> {
+        let node = run.nodes[idx].name.clone();
+        let boot_id = self.node_output(&node, "cat", &[BOOT_ID])?;
+        if self.uses_ceph() && !run.nodes[idx].noout {
+            self.output("ceph", &["osd", "set", "noout"])?;
+            run.nodes[idx].noout = true;
+        }
+        let state = &mut run.nodes[idx];
+        state.boot_id = Some(boot_id.trim().to_string());
+        // Saved before rebooting: when the node is this machine, nothing
+        // after the reboot gets to run.
+        state.step = Step::WaitOnline;
+        self.save(run)?;
+        // The SSH session dies with the node, so only a failure to start
+        // counts.
+        if let Err(e) = self.on_node(&node, "systemctl", &["reboot"]) {
+            run.nodes[idx].step = Step::Reboot;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn wait_online(&self, state: &NodeState) -> Result<()> {
+        let node = &state.name;
+        let back = self.wait_for(self.reboot_timeout, || {
+            let Ok(boot_id) = self.node_output(node, "cat", &[BOOT_ID]) else {
+                return Ok(false);
+            };
+            if state.boot_id.as_deref() == Some(boot_id.trim()) {
+                return Ok(false);
+            }
+            Ok(self.nodes().is_ok_and(|nodes| {
+                nodes
+                    .iter()
+                    .any(|n| n.node == *node && n.status == "online")
+            }))
+        })?;
+        if !back {
+            bail!(
+                "{node} has not rebooted and rejoined the cluster within {} minute(s)",
+                self.reboot_timeout.as_secs() / 60
+            );
+        }
+        Ok(())
+    }
+
+    fn post_checks(&self, run: &mut Run, idx: usize) -> Result<()> {
+        let node = run.nodes[idx].name.clone();
+        if run.nodes[idx].noout {
+            self.output("ceph", &["osd", "unset", "noout"])?;
+            run.nodes[idx].noout = false;
+            self.save(run)?;
+        }
+        let version = self.node_output(&node, "pveversion", &["--verbose"])?;
+        let mut checks = vec![match pve_major(&version) {
+            Some(major) if major >= run.target => {
+                Check::new("version", true, format!("running {major}.x"))
+            }
+            Some(major) => Check::new(
+                "version",
+                false,
+                format!("still on {major}.x after the upgrade"),
+            ),
+            None => Check::new("version", false, "cannot read the Proxmox VE version"),
+        }];
+        checks.extend(self.cluster_checks(self.ceph_timeout)?);
+        gate(&checks)
+    }
+
+    fn migrate_back(&self, run: &mut Run, idx: usize) -> Result<()> {
+        let node = run.nodes[idx].name.clone();
+        while let Some(moved) = run.nodes[idx].migrated.first().cloned() {
+            let resources = self.resources()?;
+            match resources.iter().find(|r| r.vmid == moved.vmid) {
+                Some(r) if r.node != node => self.migrate(r.kind, r.vmid, &r.node, &node)?,
+                Some(_) => {}
+                None => tui::warn(&format!(
+                    "guest {} no longer exists; not moving it back",
+                    moved.vmid
+                )),
+            }
+            run.nodes[idx].migrated.remove(0);
+            self.save(run)?;
+        }
+        Ok(())
+    }
+}
+
+/// Debian release a Proxmox VE major version is built on.
+fn debian_codename(major: u32) -> Option<&'static str> {
+    match major {
+        7 => Some("bullseye"),
+        8 => Some("bookworm"),
+        9 => Some("trixie"),
+        _ => None,
+    }
+}
+
+fn on_release(suite: &str, codename: &str) -> bool {
+    suite == codename
+        || suite
+            .strip_prefix(codename)
+            .is_some_and(|rest| rest.starts_with(['-', '/']))
+}
+
+/// `file: suite` for each enabled source in `APT_SOURCES` output whose
+/// suite is not `codename` (or one of its `-updates`/`-security` suites).
+fn stale_sources(listing: &str, codename: &str) -> Vec<String> {
+    let mut stale = Vec::new();
+    let mut file = "";
+    // deb822 stanza: (enabled, suites)
+    let mut stanza: (bool, Vec<String>) = (true, Vec::new());
+    let flush = |file: &str, stanza: &mut (bool, Vec<String>), stale: &mut Vec<String>| {
+        if stanza.0 {
+            for suite in &stanza.1 {
+                if !on_release(suite, codename) {
+                    stale.push(format!("{file}: {suite}"));
+                }
+            }
+        }
+        *stanza = (true, Vec::new());
+    };
+    for line in listing.lines() {
+        if let Some(path) = line.strip_prefix("==> ") {
+            flush(file, &mut stanza, &mut stale);
+            file = path.trim();
+            continue;
+        }
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if file.ends_with(".sources") {
+            if line.is_empty() {
+                flush(file, &mut stanza, &mut stale);
+            } else if let Some((key, value)) = line.split_once(':') {
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "enabled" => stanza.0 = value.trim() != "no",
+                    "suites" => stanza
+                        .1
+                        .extend(value.split_whitespace().map(str::to_string)),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        if !matches!(words.next(), Some("deb" | "deb-src")) {
+            continue;
+        }
+        let first = words.next();
+        if first.is_some_and(|w| w.starts_with('[')) {
+            let mut word = first;
+            while word.is_some_and(|w| !w.ends_with(']')) {
+                word = words.next();
+            }
+            words.next(); // the URI
+        }
+        if let Some(suite) = words.next()
+            && !on_release(suite, codename)
+        {
+            stale.push(format!("{file}: {suite}"));
+        }
+    }
+    flush(file, &mut stanza, &mut stale);
+    stale
+}
+
+fn failure(out: &CommandResult) -> String {
+    let text = if out.stderr.trim().is_empty() {
+        &out.stdout
+    } else {
+        &out.stderr
+    };
+    text.trim()
+        .lines()
+        .last()
+        .unwrap_or("no output")
+        .to_string()
+}
+
+pub fn command() -> Command {
+    let nodes = || {
+        Arg::new("nodes")
+            .required(true)
+            .num_args(1..)
+            .value_name("NODE")
+            .help("Nodes to upgrade, in order")
+    };
+    let target = || {
+        Arg::new("target")
+            .long("target")
+            .value_name("MAJOR")
+            .value_parser(clap::value_parser!(u32))
+            .default_value("9")
+            .help("Proxmox VE major version to upgrade to")
+    };
+    Command::new("upgrade")
+        .about("Resumable rolling upgrade of cluster nodes with health gates")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("check")
+                .about("Run the pre-upgrade gates for nodes")
+                .arg(nodes())
+                .arg(target()),
+        )
+        .subcommand(
+            Command::new("start")
+                .about("Upgrade nodes one at a time, in the order given")
+                .arg(nodes())
+                .arg(target()),
+        )
+        .subcommand(Command::new("resume").about("Continue a stopped or interrupted upgrade"))
+        .subcommand(
+            Command::new("status")
+                .about("Show where each node is in the upgrade")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output as JSON"),
+                ),
+        )
+        .subcommand(Command::new("abort").about("Forget the saved upgrade state"))
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let runner = crate::command::runner();
+    let orchestrator = Orchestrator::new(runner.as_ref())?;
+    let nodes = |m: &ArgMatches| -> Vec<String> {
+        m.get_many::<String>("nodes")
+            .map(|v| v.cloned().collect())
+            .unwrap_or_default()
+    };
+    let target = |m: &ArgMatches| {
+        m.get_one::<u32>("target")
+            .copied()
+            .unwrap_or(DEFAULT_TARGET)
+    };
+    match matches.subcommand() {
+        Some(("check", m)) => {
+            let mut failed = Vec::new();
+            for node in nodes(m) {
+                tui::info(&format!("{node}:"));
+                let checks = orchestrator.pre_checks(&node, target(m))?;
+                print_checks(&checks);
+                if checks.iter().any(|c| !c.ok) {
+                    failed.push(node);
+                }
+            }
+            if !failed.is_empty() {
+                bail!("not ready: {}", failed.join(", "));
+            }
+            Ok(())
+        }
+        Some(("start", m)) => start(&orchestrator, &nodes(m), target(m)),
+        Some(("resume", _)) => resume(&orchestrator),
+        Some(("status", m)) => {
+            let run = orchestrator.load()?;
+            if m.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&run)?);
+            } else if let Some(run) = run {
+                print_status(&run);
+            } else {
+                println!("No rolling upgrade recorded.");
+            }
+            Ok(())
+        }
+        Some(("abort", _)) => abort(&orchestrator),
+        _ => Ok(()),
+    }
+}
+
+fn start(orchestrator: &Orchestrator, nodes: &[String], target: u32) -> Result<()> {
+    println!("Rolling upgrade to Proxmox VE {target}.x, one node at a time:");
+    for (i, node) in nodes.iter().enumerate() {
+        println!("  {}. {node}", i + 1);
+    }
+    println!(
+        "Each node: pre-checks, drain, dist-upgrade, reboot, post-checks, migrate guests back."
+    );
+    if crate::utils::is_dry_run() {
+        println!("[DRY RUN] Nothing started.");
+        return Ok(());
+    }
+    if !tui::confirm_dangerous("Start the rolling upgrade?") {
+        return Ok(());
+    }
+    let mut run = orchestrator.start(nodes, target)?;
+    orchestrator.resume(&mut run)?;
+    tui::success("Rolling upgrade complete");
+    Ok(())
+}
+
+fn resume(orchestrator: &Orchestrator) -> Result<()> {
+    let Some(mut run) = orchestrator.load()? else {
+        bail!("no rolling upgrade to resume");
+    };
+    if run.is_done() {
+        println!("The rolling upgrade started {} is complete.", run.started);
+        return Ok(());
+    }
+    print_status(&run);
+    orchestrator.resume(&mut run)?;
+    tui::success("Rolling upgrade complete");
+    Ok(())
+}
+
+fn abort(orchestrator: &Orchestrator) -> Result<()> {
+    let Some(run) = orchestrator.load()? else {
+        println!("No rolling upgrade recorded.");
+        return Ok(());
+    };
+    print_status(&run);
+    for node in &run.nodes {
+        if node.noout {
+            tui::warn(&format!(
+                "Ceph noout is still set for {}: run `ceph osd unset noout`",
+                node.name
+            ));
+        }
+        for m in &node.migrated {
+            tui::warn(&format!(
+                "{} {} was moved from {} to {} and stays there",
+                m.kind.label(),
+                m.vmid,
+                node.name,
+                m.target
+            ));
+        }
+    }
+    if !run.is_done() && !tui::confirm_dangerous("Forget this upgrade's state?") {
+        return Ok(());
+    }
+    orchestrator.discard()?;
+    tui::success("Upgrade state removed");
+    Ok(())
+}
+
+fn print_status(run: &Run) {
+    println!(
+        "Rolling upgrade to {}.x, started {}",
+        run.target, run.started
+    );
+    println!("{:<16} {:<20} {:>6}  ERROR", "NODE", "STEP", "AWAY");
+    for node in &run.nodes {
+        println!(
+            "{:<16} {:<20} {:>6}  {}",
+            node.name,
+            node.step.label(),
+            node.migrated.len(),
+            node.error.as_deref().unwrap_or("")
+        );
+    }
+}
+
+/// Menu entry: resume or abort a saved run, or pick nodes for a new one.
+pub fn menu() {
+    let runner = crate::command::runner();
+    let orchestrator = match Orchestrator::new(runner.as_ref()) {
+        Ok(o) => o,
+        Err(e) => return tui::error(&format!("{e:#}")),
+    };
+    let result = match orchestrator.load() {
+        Ok(Some(run)) if !run.is_done() => {
+            print_status(&run);
+            match tui::select(
+                "Unfinished rolling upgrade",
+                &["Resume", "Abort", "Back"],
+                2,
+            ) {
+                Some(0) => resume(&orchestrator),
+                Some(1) => abort(&orchestrator),
+                _ => Ok(()),
+            }
+        }
+        Ok(_) => orchestrator.nodes().and_then(|nodes| {
+            let names: Vec<String> = nodes.into_iter().map(|n| n.node).collect();
+            let picked = tui::multi_select("Nodes to upgrade (in list order)", &names, None);
+            let chosen: Vec<String> = picked.into_iter().map(|i| names[i].clone()).collect();
+            if chosen.is_empty() {
+                return Ok(());
+            }
+            start(&orchestrator, &chosen, DEFAULT_TARGET)
+        }),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tui::error(&format!("{e:#}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::sync::Mutex;
+
+    const CHECKER: &str = "= CHECKING VERSION INFORMATION FOR PVE PACKAGES =
+PASS: proxmox-ve package has version >= 8.4-0
+WARN: 2 running guest(s) detected - consider migrating or stopping them.
+FAIL: systemd-boot meta-package installed
+= SUMMARY =
+TOTAL:    41
+FAILURES: 1
+";
+
+    #[test]
+    fn parses_gate_inputs() {
+        assert!(quorate("Votequorum information\nQuorate:          Yes\n"));
+        assert!(!quorate("Quorate:          No\n"));
+        assert_eq!(
+            ha_busy(
+                "quorum OK\nmaster pve1 (active, Mon Oct 19)\nservice vm:100 (pve1, started)\nservice vm:101 (pve2, migrate)\nservice ct:200 (pve3, recovery)\n"
+            ),
+            [
+                "service vm:101 (pve2, migrate)",
+                "service ct:200 (pve3, recovery)"
+            ]
+        );
+        assert_eq!(
+            pve_major("proxmox-ve: 8.4.0 (running kernel: 6.8.12-9-pve)\npve-manager: 8.4.1"),
+            Some(8)
+        );
+        let report = parse_checker(CHECKER);
+        assert_eq!(report.failures, ["systemd-boot meta-package installed"]);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[derive(Default)]
+    struct Node {
+        version: u32,
+        boot: u32,
+        online: bool,
+        /// Comes back from a reboot; `false` simulates a node stuck in boot.
+        boots: bool,
+        /// Debian suite its APT sources point at.
+        suite: &'static str,
+    }
+
+    /// A small simulated cluster behind `pvesh`, `ssh` and friends.
+    #[derive(Default)]
+    struct Fake {
+        nodes: Mutex<BTreeMap<String, Node>>,
+        /// vmid -> (kind, node, running)
+        guests: Mutex<BTreeMap<u32, (&'static str, String, bool)>>,
+        ceph_ok: Mutex<bool>,
+        /// Migration tasks never finish.
+        tasks_hang: Mutex<bool>,
+        /// Exit status of finished tasks.
+        task_exit: Mutex<&'static str>,
+        history: Mutex<Vec<String>>,
+    }
+
+    impl Fake {
+        fn cluster() -> Self {
+            let fake = Fake::default();
+            {
+                let mut nodes = fake.nodes.lock().unwrap();
+                for name in ["pve1", "pve2", "pve3"] {
+                    nodes.insert(
+                        name.to_string(),
+                        Node {
+                            version: 8,
+                            online: true,
+                            boots: true,
+                            suite: "trixie",
+                            ..Node::default()
+                        },
+                    );
+                }
+                let mut guests = fake.guests.lock().unwrap();
+                guests.insert(101, ("qemu", "pve1".to_string(), true));
+                guests.insert(102, ("qemu", "pve1".to_string(), false));
+                guests.insert(200, ("lxc", "pve1".to_string(), true));
+                guests.insert(300, ("qemu", "pve2".to_string(), true));
+            }
+            *fake.ceph_ok.lock().unwrap() = true;
+            *fake.task_exit.lock().unwrap() = "OK";
+            fake
+        }
+
+        fn ran(&self, needle: &str) -> usize {
+            let history = self.history.lock().unwrap();
+            history.iter().filter(|h| h.contains(needle)).count()
+        }
+
+        fn respond(&self, cmd: &str, args: &[&str]) -> CommandResult {
+            let mut nodes = self.nodes.lock().unwrap();
+            let mut guests = self.guests.lock().unwrap();
+            if cmd == "ssh" {
+                let node = args[4].trim_start_matches("root@");
+                let state = nodes.get_mut(node).unwrap();
+                let words: Vec<&str> = args[5].split(' ').collect();
+                return match words[0] {
+                    _ if !state.online => CommandResult::err("ssh: connect: No route", 255),
+                    "pveversion" => CommandResult::ok(format!(
+                        "proxmox-ve: {}.0.0 (running kernel: 6.8)\n",
+                        state.version
+                    )),
+                    "pve8to9" => CommandResult::ok("WARN: 1 running guest(s)\nPASS: ok\n"),
+                    "cat" => CommandResult::ok(format!("boot-{}\n", state.boot)),
+                    "sh" => CommandResult::ok(format!(
+                        "==> /etc/apt/sources.list\ndeb http://deb.debian.org/debian {0} main\n\
+                         ==> /etc/apt/sources.list.d/pve.sources\nTypes: deb\n\
+                         URIs: http://download.proxmox.com/debian/pve\nSuites: {0}\n",
+                        state.suite
+                    )),
+                    "systemctl" => {
+                        state.online = state.boots;
+                        if state.boots {
+                            state.boot += 1;
+                            state.version = 9;
+                        }
+                        CommandResult::err("Connection closed", 255)
+                    }
+                    _ => CommandResult::ok(""),
+                };
+            }
+            let key = format!("{cmd} {}", args.join(" "));
+            match (cmd, args) {
+                ("pvecm", _) => CommandResult::ok("Quorate:          Yes\n"),
+                ("ha-manager", _) => CommandResult::ok("quorum OK\n"),
+                ("ceph", ["osd", _, "noout"]) => CommandResult::ok(""),
+                ("ceph", ["health"]) => CommandResult::ok(if *self.ceph_ok.lock().unwrap() {
+                    "HEALTH_OK"
+                } else {
+                    "HEALTH_WARN 1 osds down"
+                }),
+                ("pvesh", ["get", "/nodes", ..]) => CommandResult::ok(
+                    serde_json::to_string(
+                        &nodes
+                            .iter()
+                            .map(|(n, s)| {
+                                serde_json::json!({"node": n, "status": if s.online {"online"} else {"offline"}})
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .unwrap(),
+                ),
+                ("pvesh", ["get", "/cluster/resources", ..]) => CommandResult::ok(
+                    serde_json::to_string(
+                        &guests
+                            .iter()
+                            .map(|(id, (kind, node, running))| {
+                                serde_json::json!({
+                                    "vmid": id, "type": kind, "node": node, "template": 0,
+                                    "status": if *running {"running"} else {"stopped"},
+                                })
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .unwrap(),
+                ),
+                ("pvesh", ["create", path, "--target", to, ..]) => {
+                    let vmid: u32 = path.split('/').nth(4).unwrap().parse().unwrap();
+                    guests.get_mut(&vmid).unwrap().1 = to.to_string();
+                    CommandResult::ok(format!("\"UPID:x:{vmid}:\""))
+                }
+                ("pvesh", ["get", path, ..]) if path.contains("/tasks/") => {
+                    CommandResult::ok(if *self.tasks_hang.lock().unwrap() {
+                        r#"{"status":"running"}"#.to_string()
+                    } else {
+                        format!(
+                            r#"{{"status":"stopped","exitstatus":"{}"}}"#,
+                            self.task_exit.lock().unwrap()
+                        )
+                    })
+                }
+                _ => CommandResult::err(format!("unexpected: {key}"), 1),
+            }
+        }
+    }
+
+    impl CommandRunner for Fake {
+        fn run(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult> {
+            self.history
+                .lock()
+                .unwrap()
+                .push(format!("{cmd} {}", args.join(" ")));
+            Ok(self.respond(cmd, args))
+        }
+        fn run_with_stdin(
+            &self,
+            cmd: &str,
+            args: &[&str],
+            _input: &str,
+        ) -> io::Result<CommandResult> {
+            self.run(cmd, args)
+        }
+        fn run_sudo(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult> {
+            self.run(cmd, args)
+        }
+        fn run_interactive(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult> {
+            self.run(cmd, args)
+        }
+        fn run_sudo_interactive(&self, cmd: &str, args: &[&str]) -> io::Result<CommandResult> {
+            self.run(cmd, args)
+        }
+        fn run_shell(&self, shell_cmd: &str) -> io::Result<CommandResult> {
+            self.run("bash", &["-c", shell_cmd])
+        }
+        fn run_sudo_shell(&self, shell_cmd: &str) -> io::Result<CommandResult> {
+            self.run_shell(shell_cmd)
+        }
+        fn command_exists(&self, _cmd: &str) -> bool {
+            true
+        }
+        fn is_root(&self) -> bool {
+            true
+        }
+        fn has_sudo(&self) -> bool {
+            true
+        }
+        fn read_file(&self, path: &str) -> io::Result<String> {
+            Err(io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+        }
+        fn write_file(&self, _path: &str, _content: &str) -> io::Result<()> {
+            Ok(())
+        }
+        fn file_exists(&self, path: &str) -> bool {
+            path == CEPH_CONF
+        }
+        fn remove_path(&self, _path: &str) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn orchestrator<'a>(fake: &'a Fake, dir: &tempfile::TempDir) -> Orchestrator<'a> {
+        let mut o = Orchestrator::with_dir(fake, dir.path().to_path_buf(), "admin");
+        o.poll = Duration::ZERO;
+        o.reboot_timeout = Duration::ZERO;
+        o.ceph_timeout = Duration::ZERO;
+        o.task_timeout = Duration::ZERO;
+        o
+    }
+
+    #[test]
+    fn stops_at_failed_gates_and_resumes_from_saved_state() {
+        let fake = Fake::cluster();
+        let dir = tempfile::tempdir().unwrap();
+        let o = orchestrator(&fake, &dir);
+        let nodes = ["pve1".to_string(), "pve2".to_string()];
+        let mut run = o.start(&nodes, 9).unwrap();
+        assert!(
+            o.start(&nodes, 9)
+                .unwrap_err()
+                .to_string()
+                .contains("not finished")
+        );
+
+        // pve1 hangs in its reboot: the wait times out with guests away and noout set.
+        fake.nodes.lock().unwrap().get_mut("pve1").unwrap().boots = false;
+        let err = o.resume(&mut run).unwrap_err();
+        assert!(format!("{err:#}").contains("wait for node"), "{err:#}");
+        let saved = o.load().unwrap().unwrap();
+        assert_eq!(saved, run);
+        let pve1 = &saved.nodes[0];
+        assert_eq!(pve1.step, Step::WaitOnline);
+        assert_eq!(pve1.boot_id.as_deref(), Some("boot-0"));
+        assert!(pve1.noout);
+        assert!(pve1.error.as_deref().unwrap().contains("has not rebooted"));
+        assert_eq!(
+            pve1.migrated
+                .iter()
+                .map(|m| (m.vmid, m.target.as_str()))
+                .collect::<Vec<_>>(),
+            [(101, "pve2"), (200, "pve3")]
+        );
+        assert_eq!(fake.guests.lock().unwrap()[&102].1, "pve1");
+        assert_eq!(fake.ran("systemctl reboot"), 1);
+
+        // The node comes up, but Ceph is still recovering: the post-check gate holds.
+        {
+            let mut nodes = fake.nodes.lock().unwrap();
+            let pve1 = nodes.get_mut("pve1").unwrap();
+            (pve1.online, pve1.boot, pve1.version) = (true, 1, 9);
+        }
+        *fake.ceph_ok.lock().unwrap() = false;
+        let mut run = o.load().unwrap().unwrap();
+        let err = o.resume(&mut run).unwrap_err();
+        assert!(format!("{err:#}").contains("gate failed: ceph"), "{err:#}");
+        assert_eq!(run.nodes[0].step, Step::PostCheck);
+        assert!(!run.nodes[0].noout);
+        assert_eq!(fake.ran("ceph osd unset noout"), 1);
+
+        // Ceph heals; the run finishes without repeating pve1's reboot.
+        *fake.ceph_ok.lock().unwrap() = true;
+        let mut run = o.load().unwrap().unwrap();
+        o.resume(&mut run).unwrap();
+        assert!(run.is_done());
+        assert!(
+            run.nodes
+                .iter()
+                .all(|n| n.migrated.is_empty() && n.error.is_none())
+        );
+        assert_eq!(fake.ran("systemctl reboot"), 2);
+        let homes: Vec<(u32, String)> = fake
+            .guests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, (_, node, _))| (*id, node.clone()))
+            .collect();
+        let pve = |n: &str| n.to_string();
+        assert_eq!(
+            homes,
+            [
+                (101, pve("pve1")),
+                (102, pve("pve1")),
+                (200, pve("pve1")),
+                (300, pve("pve2"))
+            ]
+        );
+        // pve2's guest went to the node already upgraded, then came home.
+        assert_eq!(
+            fake.ran("pvesh create /nodes/pve2/qemu/300/migrate --target pve1"),
+            1
+        );
+        assert!(o.start(&nodes, 9).is_ok());
+    }
+
+    #[test]
+    fn pre_checks_block_on_checker_failures_and_lost_quorum() {
+        let fake = Fake::cluster();
+        let dir = tempfile::tempdir().unwrap();
+        let o = orchestrator(&fake, &dir);
+        let checks = o.pre_checks("pve1", 9).unwrap();
+        assert!(checks.iter().all(|c| c.ok), "{checks:?}");
+        assert!(checks[4].detail.contains("1 warning(s)"));
+
+        fake.nodes.lock().unwrap().get_mut("pve3").unwrap().online = false;
+        *fake.ceph_ok.lock().unwrap() = false;
+        let failed: Vec<String> = o
+            .pre_checks("pve1", 9)
+            .unwrap()
+            .into_iter()
+            .filter(|c| !c.ok)
+            .map(|c| format!("{}: {}", c.name, c.detail))
+            .collect();
+        assert_eq!(
+            failed,
+            ["nodes: offline: pve3", "ceph: HEALTH_WARN 1 osds down"]
+        );
+
+        let err = o
+            .start(&["admin".to_string(), "pve1".to_string()], 9)
+            .unwrap_err();
+        assert!(err.to_string().contains("not a cluster node"));
+    }
+
+    #[test]
+    fn apt_sources_must_name_the_target_release() {
+        let listing = "==> /etc/apt/sources.list
+deb http://deb.debian.org/debian trixie main contrib
+deb [arch=amd64 signed-by=/usr/share/keyrings/x.gpg] http://security.debian.org/debian-security trixie-security main
+# deb http://deb.debian.org/debian bookworm main
+==> /etc/apt/sources.list.d/ceph.list
+deb http://download.proxmox.com/debian/ceph-squid bookworm no-subscription
+==> /etc/apt/sources.list.d/pve-enterprise.sources
+Types: deb
+URIs: https://enterprise.proxmox.com/debian/pve
+Suites: bookworm
+Components: pve-enterprise
+Enabled: no
+
+Types: deb
+URIs: http://download.proxmox.com/debian/pve
+Suites: trixie trixie-updates
+";
+        assert_eq!(
+            stale_sources(listing, "trixie"),
+            ["/etc/apt/sources.list.d/ceph.list: bookworm"]
+        );
+        assert_eq!(debian_codename(9), Some("trixie"));
+
+        let fake = Fake::cluster();
+        fake.nodes.lock().unwrap().get_mut("pve1").unwrap().suite = "bookworm";
+        let dir = tempfile::tempdir().unwrap();
+        let o = orchestrator(&fake, &dir);
+        let check = o.apt_sources("pve1", 9).unwrap();
+        assert!(!check.ok);
+        assert!(check.detail.contains("pve.sources: bookworm"), "{check:?}");
+
+        // The gate stops the run before anything is drained.
+        let mut run = o.start(&["pve1".to_string()], 9).unwrap();
+        assert!(o.resume(&mut run).is_err());
+        assert_eq!(run.nodes[0].step, Step::PreCheck);
+        assert_eq!(fake.ran("migrate"), 0);
+    }
+
+    #[test]
+    fn stuck_migration_times_out() {
+        let fake = Fake::cluster();
+        *fake.tasks_hang.lock().unwrap() = true;
+        let dir = tempfile::tempdir().unwrap();
+        let o = orchestrator(&fake, &dir);
+        let mut run = o.start(&["pve1".to_string()], 9).unwrap();
+        let err = o.resume(&mut run).unwrap_err();
+        assert!(format!("{err:#}").contains("still running"), "{err:#}");
+        assert_eq!(run.nodes[0].step, Step::Drain);
+    }
+
+    #[test]
+    fn migrations_finishing_with_warnings_count_as_done() {
+        let fake = Fake::cluster();
+        *fake.task_exit.lock().unwrap() = "WARNINGS: 1";
+        let dir = tempfile::tempdir().unwrap();
+        let o = orchestrator(&fake, &dir);
+        let mut run = o.start(&["pve1".to_string()], 9).unwrap();
+        o.resume(&mut run).unwrap();
+        assert!(run.is_done());
+        assert_eq!(fake.ran("pvesh create /nodes/pve1/qemu/101/migrate"), 1);
+
+        let fake = Fake::cluster();
+        *fake.task_exit.lock().unwrap() = "migration aborted";
+        let dir = tempfile::tempdir().unwrap();
+        let o = orchestrator(&fake, &dir);
+        let mut run = o.start(&["pve1".to_string()], 9).unwrap();
+        let err = o.resume(&mut run).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("ended with migration aborted"),
+            "{err:#}"
+        );
+        assert_eq!(run.nodes[0].step, Step::Drain);
+    }
+}
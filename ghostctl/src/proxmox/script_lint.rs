@@ -0,0 +1,813 @@
+//! Static checks for downloaded shell scripts.
+//!
+//! Scripts are split into simple commands by a small POSIX/bash tokenizer
+//! (quoting, escapes, comments, pipes, redirects, here-documents, command
+//! and process substitution), so the checks look at what a line runs rather
+//! than at substrings: `echo "sudo is not needed"` is not a sudo call, while
+//! `bash -c "$(curl -fsSL ...)"` is a nested download even though no `|`
+//! appears in it.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    Sudo,
+    RecursiveDelete,
+    /// Fetches code and runs it without it being reviewed here.
+    NestedDownload,
+    Eval,
+    Base64Blob,
+    /// Writes to an absolute path outside scratch and PVE data directories.
+    WriteOutside,
+}
+
+impl FindingKind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            FindingKind::Sudo => "runs commands with sudo",
+            FindingKind::RecursiveDelete => "deletes recursively",
+            FindingKind::NestedDownload => "downloads and runs more code",
+            FindingKind::Eval => "evaluates generated code",
+            FindingKind::Base64Blob => "decodes or embeds base64 data",
+            FindingKind::WriteOutside => "writes outside expected paths",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Finding {
+    pub line: usize,
+    pub kind: FindingKind,
+    pub detail: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: {}: {}",
+            self.line,
+            self.kind.describe(),
+            self.detail
+        )
+    }
+}
+
+/// Paths scripts may write to without a finding.
+const EXPECTED_WRITE_PREFIXES: &[&str] = &[
+    "/tmp/",
+    "/var/tmp/",
+    "/dev/null",
+    "/dev/stdout",
+    "/dev/stderr",
+    "/dev/tty",
+    "/dev/fd/",
+    "/opt/",
+    "/var/lib/vz/",
+    "/var/log/",
+];
+
+const DOWNLOADERS: &[&str] = &["curl", "wget"];
+
+const INTERPRETERS: &[&str] = &[
+    "bash", "sh", "dash", "zsh", "ksh", "python", "python3", "perl", "ruby", "node", "source", ".",
+];
+
+/// Base64 literals shorter than this are ordinary tokens (keys, hashes).
+const BASE64_BLOB_LEN: usize = 120;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub op: String,
+    pub target: String,
+}
+
+/// One simple command: words with quotes removed, and what was attached to
+/// them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleCommand {
+    pub line: usize,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+    /// Bodies of `$(...)`, backticks, `<(...)` and `>(...)` in the words.
+    pub substitutions: Vec<String>,
+    pub heredocs: Vec<String>,
+    /// Reads the previous command's output through `|`.
+    pub piped: bool,
+}
+
+impl SimpleCommand {
+    fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.redirects.is_empty()
+    }
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    commands: Vec<SimpleCommand>,
+    current: SimpleCommand,
+    word: Option<String>,
+    /// Here-document delimiters waiting for the end of the line, and
+    /// whether they were `<<-`.
+    pending_heredocs: Vec<(String, bool)>,
+}
+
+/// Split `script` into simple commands, in order.
+pub fn tokenize(script: &str) -> Vec<SimpleCommand> {
+    let mut lexer = Lexer {
+        chars: script.chars().collect(),
+        pos: 0,
+        line: 1,
+        commands: Vec::new(),
+        current: SimpleCommand {
+            line: 1,
+            ..SimpleCommand::default()
+        },
+        word: None,
+        pending_heredocs: Vec::new(),
+    };
+    lexer.run();
+    lexer.commands
+}
+
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn push_char(&mut self, c: char) {
+        if self.word.is_none() && self.current.words.is_empty() {
+            self.current.line = self.line;
+        }
+        self.word.get_or_insert_with(String::new).push(c);
+    }
+
+    fn push_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.push_char(c);
+        }
+    }
+
+    fn end_word(&mut self) {
+        if let Some(word) = self.word.take() {
+            self.current.words.push(word);
+        }
+    }
+
+    fn end_command(&mut self, piped_next: bool) {
+        self.end_word();
+        let done = std::mem::take(&mut self.current);
+        if !done.is_empty() {
+            self.commands.push(done);
+        }
+        self.current.line = self.line;
+        self.current.piped = piped_next;
+    }
+
+    fn run(&mut self) {
+        while let Some(c) = self.peek(0) {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.bump();
+                    self.end_word();
+                }
+                '\n' => {
+                    self.bump();
+                    self.end_command(false);
+                    self.read_heredocs();
+                }
+                '#' if self.word.is_none() => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                '\\' => {
+                    self.bump();
+                    match self.bump() {
+                        Some('\n') | None => {}
+                        Some(c) => self.push_char(c),
+                    }
+                }
+                '\'' => {
+                    self.bump();
+                    self.word.get_or_insert_with(String::new);
+                    while let Some(c) = self.bump() {
+                        if c == '\'' {
+                            break;
+                        }
+                        self.push_char(c);
+                    }
+                }
+                '"' => {
+                    self.bump();
+                    self.word.get_or_insert_with(String::new);
+                    self.read_double_quoted();
+                }
+                '`' => {
+                    self.bump();
+                    let body = self.read_backticks();
+                    self.push_str(&format!("`{body}`"));
+                    self.current.substitutions.push(body);
+                }
+                '$' if self.peek(1) == Some('(') => self.read_dollar_paren(),
+                '<' | '>' if self.peek(1) == Some('(') && self.word.is_none() => {
+                    let open = self.bump().unwrap_or('<');
+                    self.bump();
+                    let body = self.read_balanced();
+                    self.push_str(&format!("{open}({body})"));
+                    self.current.substitutions.push(body);
+                }
+                '|' => {
+                    self.bump();
+                    match self.peek(0) {
+                        Some('|') => {
+                            self.bump();
+                            self.end_command(false);
+                        }
+                        Some('&') => {
+                            self.bump();
+                            self.end_command(true);
+                        }
+                        _ => self.end_command(true),
+                    }
+                }
+                '&' if self.peek(1) == Some('>') => {
+                    self.end_word();
+                    self.bump();
+                    self.bump();
+                    let op = if self.peek(0) == Some('>') {
+                        self.bump();
+                        "&>>"
+                    } else {
+                        "&>"
+                    };
+                    self.read_redirect_target(op.to_string());
+                }
+                '&' => {
+                    self.bump();
+                    if self.peek(0) == Some('&') {
+                        self.bump();
+                    }
+                    self.end_command(false);
+                }
+                ';' | '(' | ')' => {
+                    self.bump();
+                    if c == ';' && self.peek(0) == Some(';') {
+                        self.bump();
+                    }
+                    self.end_command(false);
+                }
+                '<' | '>' => self.read_redirect(),
+                _ => {
+                    self.bump();
+                    self.push_char(c);
+                }
+            }
+        }
+        self.end_command(false);
+    }
+
+    fn read_double_quoted(&mut self) {
+        while let Some(c) = self.peek(0) {
+            match c {
+                '"' => {
+                    self.bump();
+                    return;
+                }
+                '\\' => {
+                    self.bump();
+                    match self.bump() {
+                        Some(c @ ('"' | '\\' | '$' | '`')) => self.push_char(c),
+                        Some('\n') | None => {}
+                        Some(c) => {
+                            self.push_char('\\');
+                            self.push_char(c);
+                        }
+                    }
+                }
+                '$' if self.peek(1) == Some('(') => self.read_dollar_paren(),
+                '`' => {
+                    self.bump();
+                    let body = self.read_backticks();
+                    self.push_str(&format!("`{body}`"));
+                    self.current.substitutions.push(body);
+                }
+                _ => {
+                    self.bump();
+                    self.push_char(c);
+                }
+            }
+        }
+    }
+
+    /// `$(cmd)` or `$((arith))`, at the `$`.
+    fn read_dollar_paren(&mut self) {
+        self.bump();
+        self.bump();
+        let arithmetic = self.peek(0) == Some('(');
+        let body = self.read_balanced();
+        self.push_str(&format!("$({body})"));
+        if !arithmetic {
+            self.current.substitutions.push(body);
+        }
+    }
+
+    /// Text up to the `)` closing an already consumed `(`.
+    fn read_balanced(&mut self) -> String {
+        let mut depth = 1;
+        let mut body = String::new();
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    body.push(c);
+                    if let Some(next) = self.bump() {
+                        body.push(next);
+                    }
+                    continue;
+                }
+                '\'' | '"' => {
+                    body.push(c);
+                    while let Some(q) = self.bump() {
+                        body.push(q);
+                        if q == '\\' && c == '"' {
+                            if let Some(next) = self.bump() {
+                                body.push(next);
+                            }
+                        } else if q == c {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(c);
+        }
+        body
+    }
+
+    fn read_backticks(&mut self) -> String {
+        let mut body = String::new();
+        while let Some(c) = self.bump() {
+            match c {
+                '`' => break,
+                '\\' => {
+                    if let Some(next) = self.bump() {
+                        if !matches!(next, '`' | '\\' | '$') {
+                            body.push('\\');
+                        }
+                        body.push(next);
+                    }
+                }
+                _ => body.push(c),
+            }
+        }
+        body
+    }
+
+    fn read_redirect(&mut self) {
+        // A bare fd number right before the operator (`2>`) is not a word.
+        if self
+            .word
+            .as_deref()
+            .is_some_and(|w| !w.is_empty() && w.bytes().all(|b| b.is_ascii_digit()))
+            && self.pos > 0
+            && self.chars[self.pos - 1].is_ascii_digit()
+        {
+            self.word = None;
+        } else {
+            self.end_word();
+        }
+        let mut op = String::new();
+        while let Some(c) = self.peek(0) {
+            if matches!(c, '<' | '>' | '&' | '|' | '-') && op.len() < 3 {
+                if c == '|' && op != ">" {
+                    break;
+                }
+                if c == '-' && op != "<<" {
+                    break;
+                }
+                op.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        self.read_redirect_target(op);
+    }
+
+    fn read_redirect_target(&mut self, op: String) {
+        while matches!(self.peek(0), Some(' ' | '\t')) {
+            self.bump();
+        }
+        let mut target = String::new();
+        while let Some(c) = self.peek(0) {
+            match c {
+                '\'' | '"' => {
+                    self.bump();
+                    while let Some(q) = self.bump() {
+                        if q == c {
+                            break;
+                        }
+                        target.push(q);
+                    }
+                }
+                '\\' => {
+                    self.bump();
+                    if let Some(next) = self.bump() {
+                        target.push(next);
+                    }
+                }
+                c if c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')' | '<' | '>') => {
+                    break;
+                }
+                _ => {
+                    self.bump();
+                    target.push(c);
+                }
+            }
+        }
+        if op == "<<" || op == "<<-" {
+            self.pending_heredocs.push((target.clone(), op == "<<-"));
+        }
+        if self.current.words.is_empty() && self.current.redirects.is_empty() {
+            self.current.line = self.line;
+        }
+        self.current.redirects.push(Redirect { op, target });
+    }
+
+    /// Here-document bodies start on the line after their operator.
+    fn read_heredocs(&mut self) {
+        let pending = std::mem::take(&mut self.pending_heredocs);
+        for (delimiter, strip_tabs) in pending {
+            let mut body = String::new();
+            loop {
+                if self.pos >= self.chars.len() {
+                    break;
+                }
+                let mut line = String::new();
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                    line.push(c);
+                }
+                let check = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line.as_str()
+                };
+                if check == delimiter {
+                    break;
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            // The command owning the here-document has already ended.
+            if let Some(owner) = self.commands.last_mut() {
+                owner.heredocs.push(body);
+            }
+        }
+    }
+}
+
+fn basename(word: &str) -> &str {
+    word.rsplit('/').next().unwrap_or(word)
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+    })
+}
+
+const RESERVED: &[&str] = &[
+    "!", "{", "}", "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "time",
+];
+
+/// The argv that actually runs, past keywords, assignments and wrappers,
+/// and whether `sudo` was one of the wrappers.
+fn effective_argv(words: &[String]) -> (Vec<&str>, bool) {
+    let mut rest: Vec<&str> = words.iter().map(String::as_str).collect();
+    let mut sudo = false;
+    while let Some(&first) = rest.first() {
+        if RESERVED.contains(&first) || is_assignment(first) {
+            rest.remove(0);
+            continue;
+        }
+        match basename(first) {
+            "sudo" | "doas" => {
+                sudo = true;
+                rest.remove(0);
+                while let Some(&flag) = rest.first() {
+                    if !flag.starts_with('-') {
+                        break;
+                    }
+                    rest.remove(0);
+                    if matches!(flag, "-u" | "-g" | "-C" | "-D" | "-h" | "-p" | "-U")
+                        && !rest.is_empty()
+                    {
+                        rest.remove(0);
+                    }
+                }
+            }
+            "env" | "command" | "exec" | "nohup" | "builtin" => {
+                rest.remove(0);
+                while rest
+                    .first()
+                    .is_some_and(|w| w.starts_with('-') || is_assignment(w))
+                {
+                    rest.remove(0);
+                }
+            }
+            _ => break,
+        }
+    }
+    (rest, sudo)
+}
+
+fn short_flags<'a>(args: &'a [&'a str]) -> impl Iterator<Item = char> + 'a {
+    args.iter()
+        .filter(|a| a.starts_with('-') && !a.starts_with("--"))
+        .flat_map(|a| a.chars().skip(1))
+}
+
+fn has_downloader(commands: &[SimpleCommand]) -> Option<String> {
+    commands.iter().find_map(|c| {
+        let (argv, _) = effective_argv(&c.words);
+        let program = basename(argv.first()?);
+        DOWNLOADERS.contains(&program).then(|| summarize(&argv))
+    })
+}
+
+fn summarize(argv: &[&str]) -> String {
+    let text = argv.join(" ");
+    if text.chars().count() > 80 {
+        format!("{}...", text.chars().take(77).collect::<String>())
+    } else {
+        text
+    }
+}
+
+fn is_base64_blob(text: &str) -> bool {
+    let trimmed = text.trim_end_matches('=');
+    trimmed.len() >= BASE64_BLOB_LEN
+        && trimmed
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+        && trimmed.bytes().any(|b| b.is_ascii_digit())
+        && trimmed.bytes().any(|b| b.is_ascii_uppercase())
+        && trimmed.bytes().any(|b| b.is_ascii_lowercase())
+}
+
+fn is_unexpected_write(target: &str) -> bool {
+    target.starts_with('/')
+        && !target.contains('$')
+        && !target.contains('`')
+        && !EXPECTED_WRITE_PREFIXES
+            .iter()
+            .any(|prefix| target.starts_with(prefix))
+}
+
+/// Everything worth a reviewer's attention in `script`.
+pub fn analyze(script: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    analyze_into(&tokenize(script), None, &mut findings);
+    let mut seen = std::collections::HashSet::new();
+    findings.retain(|f| seen.insert(f.clone()));
+    findings
+}
+
+/// `line` pins nested findings to the line of the outer command.
+fn analyze_into(commands: &[SimpleCommand], line: Option<usize>, findings: &mut Vec<Finding>) {
+    for (i, command) in commands.iter().enumerate() {
+        let line = line.unwrap_or(command.line);
+        let mut add = |kind, detail: String| findings.push(Finding { line, kind, detail });
+        let (argv, sudo) = effective_argv(&command.words);
+        let program = argv.first().map(|p| basename(p)).unwrap_or_default();
+        let args = argv.get(1..).unwrap_or_default();
+
+        if sudo {
+            add(FindingKind::Sudo, summarize(&argv));
+        }
+        if program == "rm" {
+            let recursive =
+                short_flags(args).any(|c| c == 'r' || c == 'R') || args.contains(&"--recursive");
+            let force = short_flags(args).any(|c| c == 'f') || args.contains(&"--force");
+            if recursive && force {
+                add(FindingKind::RecursiveDelete, summarize(&argv));
+            }
+        }
+        if program == "eval" {
+            add(FindingKind::Eval, summarize(&argv));
+        }
+        if (program == "base64" && args.iter().any(|a| matches!(*a, "-d" | "--decode" | "-D")))
+            || (program == "openssl"
+                && args
+                    .iter()
+                    .any(|a| *a == "base64" || *a == "-base64" || *a == "-a")
+                && args.contains(&"-d"))
+        {
+            add(FindingKind::Base64Blob, summarize(&argv));
+        }
+        let blobs = command
+            .words
+            .iter()
+            .chain(command.heredocs.iter())
+            .flat_map(|w| w.split_whitespace())
+            .filter(|w| is_base64_blob(w));
+        for blob in blobs {
+            add(
+                FindingKind::Base64Blob,
+                format!("{}-character literal {}...", blob.len(), &blob[..16]),
+            );
+        }
+
+        // Downloads piped into an interpreter: `curl ... | bash`.
+        if command.piped && INTERPRETERS.contains(&program) {
+            let start = commands[..i]
+                .iter()
+                .rposition(|c| !c.piped)
+                .unwrap_or_default();
+            if let Some(download) = has_downloader(&commands[start..i]) {
+                add(
+                    FindingKind::NestedDownload,
+                    format!("{download} | {}", summarize(&argv)),
+                );
+            }
+        }
+        // Downloads run through a substitution: `bash -c "$(curl ...)"`,
+        // `source <(curl ...)`, `eval "$(wget -qO- ...)"`.
+        if INTERPRETERS.contains(&program) || program == "eval" {
+            for body in &command.substitutions {
+                if let Some(download) = has_downloader(&tokenize(body)) {
+                    add(
+                        FindingKind::NestedDownload,
+                        format!("{program} runs the output of {download}"),
+                    );
+                }
+            }
+        }
+
+        for redirect in &command.redirects {
+            if matches!(redirect.op.as_str(), ">" | ">>" | ">|" | "&>" | "&>>")
+                && is_unexpected_write(&redirect.target)
+            {
+                add(
+                    FindingKind::WriteOutside,
+                    format!("{} {}", redirect.op, redirect.target),
+                );
+            }
+        }
+        let written: Vec<&str> = match program {
+            "tee" => args
+                .iter()
+                .copied()
+                .filter(|a| !a.starts_with('-'))
+                .collect(),
+            "dd" => args.iter().filter_map(|a| a.strip_prefix("of=")).collect(),
+            _ => Vec::new(),
+        };
+        for target in written {
+            if is_unexpected_write(target) {
+                add(FindingKind::WriteOutside, format!("{program} {target}"));
+            }
+        }
+
+        for body in &command.substitutions {
+            analyze_into(&tokenize(body), Some(line), findings);
+        }
+        // Here-documents fed to a shell are script too.
+        if INTERPRETERS.contains(&program) {
+            for body in &command.heredocs {
+                analyze_into(&tokenize(body), Some(line), findings);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(script: &str) -> Vec<(usize, FindingKind)> {
+        analyze(script).iter().map(|f| (f.line, f.kind)).collect()
+    }
+
+    #[test]
+    fn tokenizer_handles_quotes_pipes_and_heredocs() {
+        let commands = tokenize(
+            "echo 'a | b' \"c $(date +%F) d\" \\\n  e # comment\n\
+             cat <<'EOF' > /etc/motd\nhello $USER\nEOF\n\
+             grep -q x file 2>/dev/null || curl -fsSL https://x/y.sh | bash -s -- --opt\n",
+        );
+        assert_eq!(commands[0].words, ["echo", "a | b", "c $(date +%F) d", "e"]);
+        assert_eq!(commands[0].substitutions, ["date +%F"]);
+        assert_eq!(commands[1].line, 3);
+        assert_eq!(commands[1].words, ["cat"]);
+        assert_eq!(commands[1].heredocs, ["hello $USER\n"]);
+        assert_eq!(
+            commands[1].redirects[1],
+            Redirect {
+                op: ">".to_string(),
+                target: "/etc/motd".to_string()
+            }
+        );
+        assert_eq!(commands[2].line, 6);
+        assert_eq!(commands[2].redirects[0].op, ">");
+        assert_eq!(commands[2].words, ["grep", "-q", "x", "file"]);
+        assert!(!commands[3].piped);
+        assert!(commands[4].piped);
+        assert_eq!(commands[4].words, ["bash", "-s", "--", "--opt"]);
+    }
+
+    #[test]
+    fn flags_what_commands_run_not_what_they_say() {
+        assert!(analyze("echo \"sudo and rm -rf / are not used\"\n").is_empty());
+        assert_eq!(
+            kinds("sudo apt-get update\nFOO=1 sudo -u www rm -fr \"$DIR\"\n"),
+            [
+                (1, FindingKind::Sudo),
+                (2, FindingKind::Sudo),
+                (2, FindingKind::RecursiveDelete)
+            ]
+        );
+        assert_eq!(
+            kinds("rm -r -f /tmp/x\nrm --recursive build\n"),
+            [(1, FindingKind::RecursiveDelete)]
+        );
+    }
+
+    #[test]
+    fn flags_nested_downloads() {
+        let script = r#"#!/usr/bin/env bash
+source <(curl -fsSL https://raw.githubusercontent.com/community-scripts/ProxmoxVE/main/misc/build.func)
+bash -c "$(wget -qLO - https://example.net/install.sh)"
+curl -sL https://example.net/setup | sudo bash -
+wget -q https://example.net/data.tar.gz -O /tmp/data.tar.gz
+VERSION=$(curl -s https://api.example.net/latest)
+"#;
+        let findings = analyze(script);
+        let nested: Vec<usize> = findings
+            .iter()
+            .filter(|f| f.kind == FindingKind::NestedDownload)
+            .map(|f| f.line)
+            .collect();
+        assert_eq!(nested, [2, 3, 4], "{findings:#?}");
+        assert!(
+            findings[0]
+                .detail
+                .starts_with("source runs the output of curl")
+        );
+        assert!(
+            findings
+                .iter()
+                .any(|f| f.line == 4 && f.kind == FindingKind::Sudo)
+        );
+    }
+
+    #[test]
+    fn flags_eval_base64_and_unexpected_writes() {
+        let blob = "QmFzZTY0IGVuY29kZWQgcGF5bG9hZA".repeat(5);
+        let script = format!(
+            "eval \"$CMD\"\necho {blob} | base64 -d > /usr/local/bin/helper\n\
+             echo ok >> /var/log/install.log\n\
+             echo 'deb http://x y z' | tee /etc/apt/sources.list.d/x.list >/dev/null\n\
+             dd if=img of=/dev/sda bs=1M\necho done > \"$LOG\"\n\
+             bash <<EOF\ncurl -s https://x/y | sh\nEOF\n"
+        );
+        assert_eq!(
+            kinds(&script),
+            [
+                (1, FindingKind::Eval),
+                (2, FindingKind::Base64Blob),
+                (2, FindingKind::Base64Blob),
+                (2, FindingKind::WriteOutside),
+                (4, FindingKind::WriteOutside),
+                (5, FindingKind::WriteOutside),
+                (7, FindingKind::NestedDownload),
+            ]
+        );
+    }
+}
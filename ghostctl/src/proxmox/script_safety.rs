@@ -1,13 +1,136 @@
+use super::script_lint::{self, Finding, FindingKind};
+use super::script_registry::{self, Gate, Registry};
 use crate::http_client::RobustHttpClient;
 use crate::logging::GhostLogger;
 use anyhow::{Context, Result};
 use dialoguer::{Confirm, Select, theme::ColorfulTheme};
-use sha2::{Digest, Sha256};
+use sha2::{Digest, Sha256, Sha512};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// Digests published in upstream checksum files (`SHA256SUMS`, `SHA512SUMS`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Tell the algorithm from the length of a hex digest.
+    pub fn for_digest(hex: &str) -> Option<Self> {
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        match hex.len() {
+            64 => Some(Self::Sha256),
+            128 => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+}
+
+/// Incremental hashing, so large downloads are hashed while they are written.
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(bytes),
+            Self::Sha512(h) => h.update(bytes),
+        }
+    }
+
+    /// Lowercase hex digest.
+    pub fn finish(self) -> String {
+        match self {
+            Self::Sha256(h) => crate::utils::bytes_to_hex(h.finalize()),
+            Self::Sha512(h) => crate::utils::bytes_to_hex(h.finalize()),
+        }
+    }
+}
+
+pub fn hash_reader(algorithm: HashAlgorithm, mut reader: impl Read) -> Result<String> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+pub fn hash_file(algorithm: HashAlgorithm, path: &Path) -> Result<String> {
+    let file =
+        fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    hash_reader(algorithm, file)
+}
+
+/// The digest for `file` in a checksum file, in either the GNU
+/// (`HEX  name`, `HEX *name`) or the BSD (`SHA256 (name) = HEX`) format.
+pub fn checksum_for(sums: &str, file: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let line = line.trim();
+        if let Some((head, hex)) = line.split_once(") = ") {
+            let (_, name) = head.split_once(" (")?;
+            return (name == file).then(|| hex.trim().to_ascii_lowercase());
+        }
+        let (hex, name) = line.split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        let name = name.strip_prefix("./").unwrap_or(name);
+        (name == file && HashAlgorithm::for_digest(hex).is_some()).then(|| hex.to_ascii_lowercase())
+    })
+}
+
+/// Check a detached OpenPGP signature with `gpgv`, against `keyring` or
+/// gpgv's default `trustedkeys.kbx`.
+pub fn verify_signature(
+    runner: &dyn crate::command::CommandRunner,
+    keyring: Option<&Path>,
+    signature: &Path,
+    data: &Path,
+) -> Result<()> {
+    if !runner.command_exists("gpgv") {
+        anyhow::bail!("gpgv not found (install gnupg or gpgv) to check signatures");
+    }
+    let keyring = keyring.map(|k| k.to_string_lossy().to_string());
+    let (signature, data) = (
+        signature.to_string_lossy().to_string(),
+        data.to_string_lossy().to_string(),
+    );
+    let mut args = Vec::new();
+    if let Some(keyring) = &keyring {
+        args.extend(["--keyring", keyring.as_str()]);
+    }
+    args.extend([signature.as_str(), data.as_str()]);
+    let out = runner.run("gpgv", &args).context("failed to run gpgv")?;
+    if !out.success {
+        anyhow::bail!("bad signature: {}", out.stderr.trim());
+    }
+    Ok(())
+}
+
 /// Configuration for script execution safety
 #[derive(Debug, Clone)]
 pub struct ScriptSafetyConfig {
@@ -21,6 +144,8 @@ pub struct ScriptSafetyConfig {
     pub dry_run: bool,
     /// Number of preview lines to show
     pub preview_lines: usize,
+    /// Snapshot transaction label to wrap a confirmed run in
+    pub transaction: Option<String>,
 }
 
 impl Default for ScriptSafetyConfig {
@@ -31,6 +156,7 @@ impl Default for ScriptSafetyConfig {
             cache_scripts: true,
             dry_run: false,
             preview_lines: 15,
+            transaction: None,
         }
     }
 }
@@ -54,25 +180,13 @@ pub struct ScriptVerification {
     pub has_sudo: bool,
     pub has_rm_rf: bool,
     pub has_curl_pipe: bool,
+    pub findings: Vec<Finding>,
 }
 
 impl ScriptVerification {
     /// Returns a list of warnings based on script content analysis
     pub fn warnings(&self) -> Vec<String> {
-        let mut warnings = Vec::new();
-
-        if self.has_sudo {
-            warnings.push("Script contains sudo commands (requires root privileges)".to_string());
-        }
-        if self.has_rm_rf {
-            warnings.push("Script contains 'rm -rf' commands (destructive)".to_string());
-        }
-        if self.has_curl_pipe {
-            warnings
-                .push("Script downloads and executes additional scripts (curl|bash)".to_string());
-        }
-
-        warnings
+        self.findings.iter().map(ToString::to_string).collect()
     }
 }
 
@@ -172,9 +286,9 @@ impl SafeScriptExecutor {
 
     /// Compute SHA256 hash of script content
     pub fn compute_sha256(content: &str) -> String {
-        let mut hasher = Sha256::new();
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256);
         hasher.update(content.as_bytes());
-        crate::utils::bytes_to_hex(hasher.finalize())
+        hasher.finish()
     }
 
     /// Verify a script and return verification details
@@ -183,22 +297,18 @@ impl SafeScriptExecutor {
         let lines: Vec<&str> = content.lines().collect();
 
         // Analyze script for potentially dangerous patterns
-        let content_lower = content.to_lowercase();
-        let has_sudo = content_lower.contains("sudo ");
-        let has_rm_rf = content.contains("rm -rf") || content.contains("rm -fr");
-        let has_curl_pipe = content.contains("curl") && content.contains("| bash")
-            || content.contains("|bash")
-            || content.contains("| sh")
-            || content.contains("|sh");
+        let findings = script_lint::analyze(content);
+        let has = |kind| findings.iter().any(|f| f.kind == kind);
 
         ScriptVerification {
             url: url.to_string(),
             sha256,
             line_count: lines.len(),
             size_bytes: content.len(),
-            has_sudo,
-            has_rm_rf,
-            has_curl_pipe,
+            has_sudo: has(FindingKind::Sudo),
+            has_rm_rf: has(FindingKind::RecursiveDelete),
+            has_curl_pipe: has(FindingKind::NestedDownload),
+            findings,
         }
     }
 
@@ -258,6 +368,26 @@ impl SafeScriptExecutor {
             Some(&format!("name:{} url:{}", name, url)),
         );
 
+        // Approved scripts run from their pinned version; others need a
+        // person to review them.
+        let registry = Registry::open()?;
+        let fetch = |u: &str| self.client.fetch(u);
+        match registry.gate(url, &fetch)? {
+            Gate::Approved(entry, content) => return self.run_approved(name, &entry, &content),
+            Gate::Unlisted if crate::utils::is_headless() => {
+                GhostLogger::log_action(
+                    "script_refused",
+                    false,
+                    Some(&format!("name:{} url:{} reason:not_in_lockfile", name, url)),
+                );
+                anyhow::bail!(
+                    "{url} is not in {}; review and pin it with `ghostctl pve scripts approve {url}`",
+                    registry.path().display()
+                );
+            }
+            Gate::Unlisted => {}
+        }
+
         // Fetch the script
         let content = self.fetch_script(url)?;
 
@@ -291,6 +421,7 @@ impl SafeScriptExecutor {
                 "Execute script (with warnings)",
                 "View full script",
                 "Save script locally",
+                "Approve and pin in scripts.lock",
                 "Cancel",
             ]
         } else {
@@ -298,6 +429,7 @@ impl SafeScriptExecutor {
                 "Execute script",
                 "View full script",
                 "Save script locally",
+                "Approve and pin in scripts.lock",
                 "Cancel",
             ]
         };
@@ -305,7 +437,7 @@ impl SafeScriptExecutor {
         let Ok(choice) = Select::with_theme(&ColorfulTheme::default())
             .with_prompt("Choose action")
             .items(&options)
-            .default(if has_warnings { 3 } else { 0 })
+            .default(if has_warnings { 4 } else { 0 })
             .interact()
         else {
             return Ok(false);
@@ -411,6 +543,34 @@ impl SafeScriptExecutor {
                 );
                 Ok(false)
             }
+            3 => {
+                let mut registry = registry;
+                let candidate = script_registry::resolve(url, &fetch)?;
+                if candidate.sha256 != verification.sha256 {
+                    anyhow::bail!("{url} changed while it was being reviewed; try again");
+                }
+                let lock_name = script_registry::default_name(url);
+                let reviewer = script_registry::current_reviewer();
+                let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+                registry.approve(&lock_name, url, &candidate, &reviewer, &today)?;
+                println!(
+                    "  Pinned as '{}' in {}",
+                    lock_name,
+                    registry.path().display()
+                );
+                GhostLogger::log_action(
+                    "script_approved",
+                    true,
+                    Some(&format!(
+                        "name:{} sha256:{} reviewer:{}",
+                        lock_name, verification.sha256, reviewer
+                    )),
+                );
+                let Some(entry) = registry.find(&lock_name).cloned() else {
+                    return Ok(false);
+                };
+                self.run_approved(name, &entry, &content)
+            }
             _ => {
                 println!("  Execution cancelled");
                 GhostLogger::log_action(
@@ -426,9 +586,52 @@ impl SafeScriptExecutor {
         }
     }
 
+    /// Run a script approved in scripts.lock after a final confirmation.
+    fn run_approved(
+        &self,
+        name: &str,
+        entry: &script_registry::LockedScript,
+        content: &str,
+    ) -> Result<bool> {
+        println!(
+            "  Approved by {} on {} (sha256 {}{})",
+            entry.reviewer,
+            entry.approved,
+            &entry.sha256[..12],
+            entry
+                .commit
+                .as_deref()
+                .map(|c| format!(", commit {}", &c[..12.min(c.len())]))
+                .unwrap_or_default()
+        );
+        if !crate::tui::confirm(&format!("Run {name}?"), true) {
+            println!("  Execution cancelled");
+            return Ok(false);
+        }
+        if self.config.dry_run || crate::utils::is_dry_run() {
+            println!("  [DRY RUN] Would execute approved script: {}", name);
+            return Ok(true);
+        }
+        self.execute_script(content, name)
+    }
+
+    /// Execute a confirmed script, inside a snapshot transaction when the
+    /// config names one. The journal records whether the script succeeded.
+    fn execute_script(&self, content: &str, name: &str) -> Result<bool> {
+        let Some(label) = self.config.transaction.as_deref() else {
+            return self.run_script(content, name);
+        };
+        let mut result = Ok(false);
+        crate::btrfs::transaction::run(label, || {
+            result = self.run_script(content, name);
+            matches!(result, Ok(true))
+        });
+        result
+    }
+
     /// Execute a script by writing to a temp file and executing it directly
     /// This avoids shell injection issues that can occur with `bash -c` for complex scripts
-    fn execute_script(&self, content: &str, name: &str) -> Result<bool> {
+    fn run_script(&self, content: &str, name: &str) -> Result<bool> {
         let sha256 = Self::compute_sha256(content);
         println!("\n  Executing script: {}...", name);
 
@@ -586,6 +789,40 @@ mod tests {
         assert_eq!(hash.len(), 64); // SHA256 produces 64 hex chars
     }
 
+    #[test]
+    fn test_checksum_files() {
+        let sha256 = SafeScriptExecutor::compute_sha256("image");
+        let gnu = format!(
+            "{sha256}  noble-server-cloudimg-amd64.img\n{}  *noble-server-cloudimg-arm64.img\n",
+            "a".repeat(64)
+        );
+        assert_eq!(
+            checksum_for(&gnu, "noble-server-cloudimg-amd64.img"),
+            Some(sha256.clone())
+        );
+        assert_eq!(
+            checksum_for(&gnu, "noble-server-cloudimg-arm64.img"),
+            Some("a".repeat(64))
+        );
+        assert_eq!(checksum_for(&gnu, "missing.img"), None);
+        let bsd = format!(
+            "SHA512 (debian-12-genericcloud-amd64.qcow2) = {}\n",
+            "B".repeat(128)
+        );
+        assert_eq!(
+            checksum_for(&bsd, "debian-12-genericcloud-amd64.qcow2"),
+            Some("b".repeat(128))
+        );
+        assert_eq!(
+            HashAlgorithm::for_digest(&"b".repeat(128)),
+            Some(HashAlgorithm::Sha512)
+        );
+        assert_eq!(
+            hash_reader(HashAlgorithm::Sha256, "image".as_bytes()).unwrap(),
+            sha256
+        );
+    }
+
     #[test]
     fn test_verify_script_detects_sudo() {
         let content = "sudo apt-get update";
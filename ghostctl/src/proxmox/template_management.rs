@@ -15,6 +15,7 @@ pub fn template_management_menu() {
             "🏭 Template Customization",
             "🔧 Template Maintenance",
             "📊 Template Usage Statistics",
+            "☁️  Cloud Image Templates",
             "⬅️  Back",
         ];
 
@@ -37,6 +38,7 @@ pub fn template_management_menu() {
             6 => template_customization(),
             7 => template_maintenance(),
             8 => template_usage_statistics(),
+            9 => crate::proxmox::cloud_images::refresh_menu(),
             _ => break,
         }
     }
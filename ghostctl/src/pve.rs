@@ -109,6 +109,9 @@ pub fn list_vms() {
 pub fn create_vm_wizard() {
     println!("🆕 Create Virtual Machine Wizard");
     println!("=================================");
+    println!(
+        "💡 To keep a record of what you build, describe it in guests.toml and run `ghostctl pve apply`"
+    );
 
     let Ok(vm_id): Result<String, _> = Input::new()
         .with_prompt("VM ID (100-999999)")
@@ -603,6 +606,9 @@ pub fn list_containers() {
 pub fn create_container_wizard() {
     println!("🆕 Create Container Wizard");
     println!("==========================");
+    println!(
+        "💡 To keep a record of what you build, describe it in guests.toml and run `ghostctl pve apply`"
+    );
 
     let Ok(ct_id): Result<String, _> = Input::new()
         .with_prompt("Container ID (100-999999)")
@@ -1,8 +1,9 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use chrono::Utc;
 use flate2::Compression;
 use flate2::write::GzEncoder;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::fmt::Write as _;
 use std::io::Write;
@@ -19,6 +20,59 @@ pub fn state_dir() -> PathBuf {
     }
 }
 
+/// Where a secret in config.toml comes from (mesh auth keys, PVE API
+/// tokens, backup job passwords):
+///
+/// ```toml
+/// token_secret = { file = "/etc/ghostctl/pve-token" }
+/// token_secret = { command = "pass show pve/ghostctl" }
+/// token_secret = { env = "PVE_TOKEN_SECRET" }
+/// token_secret = { credential = "pve-prod" }   # ghostctl credential store
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSource {
+    File(String),
+    Command(String),
+    Env(String),
+    Credential(String),
+}
+
+impl SecretSource {
+    /// The secret, trimmed; `what` names it in errors.
+    pub fn resolve(&self, what: &str) -> Result<String> {
+        let value = match self {
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("{what}: cannot read {path}"))?,
+            SecretSource::Command(cmd) => {
+                let result = crate::command::runner()
+                    .run_shell(cmd)
+                    .with_context(|| format!("{what}: cannot run '{cmd}'"))?;
+                if !result.success {
+                    bail!("{what}: '{cmd}' failed: {}", result.stderr.trim());
+                }
+                result.stdout
+            }
+            SecretSource::Env(var) => {
+                std::env::var(var).with_context(|| format!("{what}: ${var} is not set"))?
+            }
+            SecretSource::Credential(key) => {
+                let preferred = crate::config::GhostConfig::load()
+                    .credentials
+                    .map(|c| c.backend);
+                crate::security::credential_backends::detect_backend(preferred.as_deref())
+                    .get(key)
+                    .with_context(|| format!("{what}: credential '{key}' not found"))?
+            }
+        };
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            bail!("{what} is empty");
+        }
+        Ok(value)
+    }
+}
+
 pub fn support_dir() -> PathBuf {
     state_dir().join("support")
 }
@@ -611,7 +665,7 @@ fn redact_text(text: &str, enabled: bool) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::redact_text;
+    use super::{SecretSource, redact_text};
 
     #[test]
     fn redaction_covers_common_identifiers() {
@@ -632,4 +686,21 @@ mod tests {
         let input = "192.168.1.10";
         assert_eq!(redact_text(input, false), input);
     }
+
+    #[test]
+    fn secrets_resolve_from_files_and_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key");
+        std::fs::write(&path, "tskey-auth-abc\n").unwrap();
+        let file = SecretSource::File(path.to_string_lossy().to_string());
+        assert_eq!(file.resolve("auth key").unwrap(), "tskey-auth-abc");
+        let missing = SecretSource::Env("GHOSTCTL_TEST_UNSET_MESH_KEY".into());
+        assert!(
+            missing
+                .resolve("auth key")
+                .unwrap_err()
+                .to_string()
+                .contains("$GHOSTCTL_TEST_UNSET_MESH_KEY is not set")
+        );
+    }
 }
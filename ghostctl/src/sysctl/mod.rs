@@ -5,6 +5,7 @@
 
 mod tui;
 
+use crate::btrfs::transaction;
 use crate::tui::{confirm, error, header, icons, info, input, select_with_back, success, warn};
 use crate::utils::sudo_write_file;
 use std::collections::HashMap;
@@ -318,10 +319,16 @@ fn set_parameter() {
     }
 
     // Write using sudo if needed
-    match sudo_write_file(&path, &format!("{}\n", new_value)) {
+    match transaction::run(&format!("sysctl set {}", name), || {
+        sudo_write_file(&path, &format!("{}\n", new_value))
+    }) {
         Ok(_) => {
             success(&format!("Set {} = {}", name, new_value));
             info("Note: This change is temporary. To persist, add to /etc/sysctl.d/");
+            info(&format!(
+                "Previous value was {}; restore with: sudo sysctl -w {}=\"{}\"",
+                current, name, current
+            ));
         }
         Err(e) => {
             error(&format!("Failed to set parameter: {}", e));
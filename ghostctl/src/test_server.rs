@@ -0,0 +1,182 @@
+//! HTTP(S) stand-ins for the API clients' tests (PVE/PBS nodes, Headscale
+//! and the like).
+//!
+//! HTTPS servers present the lab certificate from `networking/testdata`.
+//! Every server answers one request per connection from a handler and
+//! records what it received.
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+const CERT: &[u8] = include_bytes!("networking/testdata/lab-cert.pem");
+const KEY: &[u8] = include_bytes!("networking/testdata/lab-key.pem");
+
+pub fn cert_der() -> Vec<u8> {
+    CertificateDer::from_pem_slice(CERT).unwrap().to_vec()
+}
+
+/// rustls server configuration presenting the lab certificate.
+pub fn tls_config() -> Arc<rustls::ServerConfig> {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(cert_der())],
+            PrivateKeyDer::from_pem_slice(KEY).unwrap(),
+        )
+        .unwrap();
+    Arc::new(config)
+}
+
+/// One request as seen by the server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub authorization: String,
+    pub body: String,
+}
+
+/// A reply: status, extra headers and a JSON body. Handlers may also
+/// return a bare `(status, body)`.
+#[derive(Debug, Clone, Default)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            ..Self::default()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+impl From<(u16, String)> for Reply {
+    fn from((status, body): (u16, String)) -> Self {
+        Reply::new(status).body(body)
+    }
+}
+
+type Log = Arc<Mutex<Vec<Request>>>;
+
+/// Serve HTTPS with the lab certificate; returns the base URL and the
+/// requests received.
+pub fn serve<R: Into<Reply>>(
+    handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+) -> (String, Log) {
+    listen(
+        Some(tls_config()),
+        Arc::new(move |req: &Request| handler(req).into()),
+    )
+}
+
+/// Plain-HTTP variant of `serve`.
+pub fn serve_http<R: Into<Reply>>(
+    handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+) -> (String, Log) {
+    listen(None, Arc::new(move |req: &Request| handler(req).into()))
+}
+
+type Handler = dyn Fn(&Request) -> Reply + Send + Sync;
+
+fn listen(tls: Option<Arc<rustls::ServerConfig>>, handler: Arc<Handler>) -> (String, Log) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!(
+        "{}://127.0.0.1:{}",
+        if tls.is_some() { "https" } else { "http" },
+        listener.local_addr().unwrap().port()
+    );
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&seen);
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let tls = tls.clone();
+            let handler = Arc::clone(&handler);
+            let log = Arc::clone(&log);
+            std::thread::spawn(move || {
+                let Some(config) = tls else {
+                    answer(&mut stream, &*handler, &log);
+                    return;
+                };
+                let Ok(conn) = rustls::ServerConnection::new(config) else {
+                    return;
+                };
+                let mut tls = rustls::StreamOwned::new(conn, stream);
+                answer(&mut tls, &*handler, &log);
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+            });
+        }
+    });
+    (base, seen)
+}
+
+/// Read one request from `stream`, log it and write the handler's reply.
+fn answer<S: Read + Write>(stream: &mut S, handler: &Handler, log: &Log) {
+    let mut reader = BufReader::new(&mut *stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+        return;
+    }
+    let mut length = 0;
+    let mut authorization = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let lower = line.to_ascii_lowercase();
+        if let Some(v) = lower.strip_prefix("content-length:") {
+            length = v.trim().parse().unwrap_or(0);
+        }
+        if lower.starts_with("authorization:") {
+            authorization = line["authorization:".len()..].trim().to_string();
+        }
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    let _ = reader.read_exact(&mut body);
+    let mut parts = request_line.split_whitespace();
+    let request = Request {
+        method: parts.next().unwrap_or_default().to_string(),
+        path: parts.next().unwrap_or_default().to_string(),
+        authorization,
+        body: String::from_utf8_lossy(&body).to_string(),
+    };
+    let reply = handler(&request);
+    log.lock().unwrap().push(request);
+    let headers: String = reply
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}\r\n"))
+        .collect();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} X\r\nContent-Type: application/json\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.body.len(),
+        reply.body
+    );
+    let _ = stream.flush();
+}
@@ -507,6 +507,29 @@ pub fn run_bash_command(command: &str) -> io::Result<Output> {
     Command::new("bash").arg("-c").arg(command).output()
 }
 
+/// Quote a value for safe interpolation into a `bash -c` command line.
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@=+,".contains(c))
+    {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for ch in value.chars() {
+        if ch == '\'' {
+            quoted.push_str("'\\''");
+        } else {
+            quoted.push(ch);
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
 pub fn check_command_exists(command: &str) -> bool {
     Command::new("which")
         .arg(command)
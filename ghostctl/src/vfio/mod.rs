@@ -58,6 +58,7 @@ pub use single_gpu::{
     print_status as print_single_gpu_status, remove_hooks, write_hooks,
 };
 
+use crate::btrfs::transaction;
 use anyhow::Result;
 use dialoguer::{Input, Select, theme::ColorfulTheme};
 
@@ -353,10 +354,18 @@ fn bind_device_interactive() {
         Err(_) => return,
     };
 
-    match bind_device(&address) {
+    let previous_driver = get_current_driver(&address).ok().flatten();
+
+    match transaction::run(&format!("vfio bind {address}"), || bind_device(&address)) {
         Ok(_) => {
             println!("\nDevice {} successfully bound to vfio-pci", address);
             println!("Note: This binding is runtime only. Use configuration for persistence.");
+            if let Some(driver) = previous_driver.filter(|d| d != "vfio-pci") {
+                println!(
+                    "Previous driver was {}; use 'Unbind Device from VFIO' to hand the device back to it.",
+                    driver
+                );
+            }
         }
         Err(e) => {
             eprintln!("\nError binding device: {}", e);
@@ -400,7 +409,9 @@ fn unbind_device_interactive() {
         Err(_) => return,
     };
 
-    match unbind_device(&address) {
+    match transaction::run(&format!("vfio unbind {address}"), || {
+        unbind_device(&address)
+    }) {
         Ok(_) => {
             println!("\nDevice {} unbound from vfio-pci", address);
         }
@@ -630,17 +641,29 @@ pub fn vfio_setup_wizard() -> Result<()> {
     };
 
     if apply {
-        // Write modprobe config
-        match write_modprobe_config(&config) {
-            Ok(_) => println!("modprobe.d configuration written."),
-            Err(e) => eprintln!("Error writing modprobe config: {}", e),
-        }
-
-        // Write initramfs config
-        match write_initramfs_config(initramfs) {
-            Ok(_) => {}
-            Err(e) => eprintln!("Error writing initramfs config: {}", e),
-        }
+        transaction::run("vfio setup: write configuration", || {
+            // Write modprobe config
+            let modprobe_ok = match write_modprobe_config(&config) {
+                Ok(_) => {
+                    println!("modprobe.d configuration written.");
+                    true
+                }
+                Err(e) => {
+                    eprintln!("Error writing modprobe config: {}", e);
+                    false
+                }
+            };
+
+            // Write initramfs config
+            let initramfs_ok = match write_initramfs_config(initramfs) {
+                Ok(_) => true,
+                Err(e) => {
+                    eprintln!("Error writing initramfs config: {}", e);
+                    false
+                }
+            };
+            modprobe_ok && initramfs_ok
+        });
 
         // Offer to regenerate initramfs
         let regenerate = match Confirm::with_theme(&ColorfulTheme::default())
@@ -653,7 +676,9 @@ pub fn vfio_setup_wizard() -> Result<()> {
         };
 
         if regenerate {
-            match config::regenerate_initramfs(initramfs) {
+            match transaction::run("vfio setup: regenerate initramfs", || {
+                config::regenerate_initramfs(initramfs)
+            }) {
                 Ok(_) => println!("Initramfs regenerated."),
                 Err(e) => eprintln!("Error regenerating initramfs: {}", e),
             }